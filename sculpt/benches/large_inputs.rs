@@ -0,0 +1,63 @@
+//! Times reading and parsing generated sources of a few sizes, up to the tens of megabytes tools
+//! that generate sculpt produce. Run with `cargo bench --bench large_inputs`.
+
+use std::env;
+use std::fs;
+use std::process;
+use std::time::{Duration, Instant};
+
+use sculpt::run::parse;
+use sculpt::source::{FileSystem, SourceProvider};
+
+/// Sizes of the generated sources, in megabytes.
+const SIZES: [usize; 3] = [1, 4, 16];
+
+/// A program of about `bytes` bytes, made of many small functions like generated code has.
+fn generate(bytes: usize) -> String {
+    let mut source_code = String::with_capacity(bytes + 64);
+    let mut i = 0;
+    while source_code.len() < bytes {
+        source_code.push_str(&format!(
+            "fn f{i}(x: i64) -> i64 {{\n    let y = x * {i} + 1;\n    println!(\"{{}} {{}}\", y, \"s{i}\");\n    y\n}}\n"
+        ));
+        i += 1;
+    }
+    source_code.push_str("fn main() { println!(\"{}\", f0(1)); }\n");
+    source_code
+}
+
+fn megabytes_per_second(bytes: usize, elapsed: Duration) -> f64 {
+    bytes as f64 / 1e6 / elapsed.as_secs_f64().max(f64::EPSILON)
+}
+
+fn main() {
+    let dir = env::temp_dir().join(format!("sculpt-large-inputs-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    println!(
+        "{:>6} {:>12} {:>10} {:>12} {:>10}",
+        "size", "read", "MB/s", "parse", "MB/s"
+    );
+    for megabytes in SIZES {
+        let path = dir.join(format!("{}mb.sculpt", megabytes));
+        fs::write(&path, generate(megabytes * 1_000_000)).unwrap();
+
+        let start = Instant::now();
+        let source_code = FileSystem.read(&path).unwrap();
+        let read = start.elapsed();
+
+        let start = Instant::now();
+        let program = parse(&source_code).unwrap();
+        let parsed = start.elapsed();
+        assert!(!program.functions.is_empty());
+
+        println!(
+            "{:>4}MB {:>12} {:>10.1} {:>12} {:>10.1}",
+            megabytes,
+            format!("{:.3?}", read),
+            megabytes_per_second(source_code.len(), read),
+            format!("{:.3?}", parsed),
+            megabytes_per_second(source_code.len(), parsed),
+        );
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
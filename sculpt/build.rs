@@ -1,3 +1,16 @@
 fn main() {
+    println!("cargo:rerun-if-changed=src/grammar.lalrpop");
     lalrpop::process_root().unwrap();
+
+    // sculpt-rt is embedded in the compiler rather than linked into it, and written out for the
+    // linker whenever `sculpt build` makes an executable.
+    #[cfg(feature = "llvm")]
+    {
+        println!("cargo:rerun-if-changed=runtime");
+        cc::Build::new()
+            .file("runtime/sculpt_rt.c")
+            .warnings(true)
+            .cargo_metadata(false)
+            .compile("sculpt_rt");
+    }
 }
@@ -0,0 +1,9 @@
+//! Extracts the specs from arbitrary format strings: `cargo fuzz run extract_fmt`.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|input: &str| {
+    let _ = sculpt::fmt_str::extract_fmt_for_fuzzing(input);
+});
@@ -0,0 +1,9 @@
+//! Checks arbitrary bytes as a program: `cargo fuzz run parse`.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    sculpt::run::parse_for_fuzzing(data);
+});
@@ -0,0 +1,891 @@
+#include "sculpt_rt.h"
+
+#include <errno.h>
+#include <fcntl.h>
+#include <locale.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <time.h>
+#include <unistd.h>
+#include <wctype.h>
+
+struct sculpt_rt_stream {
+    int fd;
+};
+
+enum clock { CLOCK_REAL, CLOCK_VIRTUAL, CLOCK_DENIED };
+
+enum files { FILES_REAL, FILES_VIRTUAL, FILES_DENIED };
+
+/* A file written to a virtual file system. */
+struct virtual_file {
+    char *path;
+    uint8_t *contents;
+    uint64_t len;
+    struct virtual_file *next;
+};
+
+/* Executables reach the host directly, so all there is to keep is the random number generator, the
+ * clock and the file system, which are set up from the environment on first use, and the buffer
+ * the last file was read or string mapped into. */
+struct sculpt_rt_host {
+    int initialized;
+    uint64_t rng;
+    enum clock clock;
+    int64_t virtual_millis;
+    enum files files;
+    struct virtual_file *virtual_files;
+    uint8_t *buffer;
+    size_t capacity;
+};
+
+struct sculpt_rt_host sculpt_rt_host = {0, 0, CLOCK_REAL, 0, FILES_REAL, NULL, NULL, 0};
+
+struct sculpt_rt_stream sculpt_rt_stdin = {0};
+struct sculpt_rt_stream sculpt_rt_stdout = {1};
+struct sculpt_rt_stream sculpt_rt_stderr = {2};
+
+int32_t sculpt_rt_status = 0;
+
+/* Starts out empty, so the first step reads how many the program may take. */
+int64_t sculpt_rt_fuel = 0;
+
+/* Writes why the program was cut off to stderr and exits with status 101, like a panic. */
+static _Noreturn void abort_run(const char *message) {
+    static const char prefix[] = "program aborted: ";
+    (void)!write(2, prefix, sizeof prefix - 1);
+    (void)!write(2, message, strlen(message));
+    (void)!write(2, "\n", 1);
+    _exit(101);
+}
+
+/* Reads a limit from the environment variable `name`, which is unlimited if it isn't set. */
+static uint64_t limit(const char *name) {
+    const char *value = getenv(name);
+    return value != NULL ? strtoull(value, NULL, 10) : UINT64_MAX;
+}
+
+int64_t sculpt_rt_refuel(void) {
+    static int fueled = 0;
+    static uint64_t fuel;
+    if (!fueled) {
+        fueled = 1;
+        fuel = limit("SCULPT_FUEL");
+        sculpt_rt_fuel = fuel < INT64_MAX ? (int64_t)fuel : INT64_MAX;
+        /* The step that found the fuel empty takes one. */
+        sculpt_rt_fuel -= 1;
+        return 0;
+    }
+    if (fuel != UINT64_MAX) {
+        char message[64];
+        snprintf(message, sizeof message, "ran out of fuel after %llu steps",
+                 (unsigned long long)fuel);
+        abort_run(message);
+    }
+    sculpt_rt_fuel = INT64_MAX;
+    return 0;
+}
+
+static int64_t fail(void) {
+    sculpt_rt_status = 1;
+    return -1;
+}
+
+int64_t sculpt_rt_write(struct sculpt_rt_stream *stream, const uint8_t *buffer, uint64_t len) {
+    uint64_t written = 0;
+    while (written < len) {
+        ssize_t n = write(stream->fd, buffer + written, len - written);
+        if (n < 0) {
+            if (errno == EINTR) {
+                continue;
+            }
+            return fail();
+        }
+        written += (uint64_t)n;
+    }
+    return (int64_t)len;
+}
+
+int64_t sculpt_rt_read(struct sculpt_rt_stream *stream, uint64_t mode, const uint8_t **buffer) {
+    static uint8_t *data;
+    static size_t capacity;
+    size_t len = 0;
+    for (;;) {
+        if (len == capacity) {
+            capacity = capacity ? capacity * 2 : 64;
+            data = sculpt_rt_realloc(data, capacity);
+        }
+        /* Lines are read a byte at a time so input past the end of the line is left for the next
+         * read. */
+        size_t want = mode == SCULPT_RT_READ_LINE ? 1 : capacity - len;
+        ssize_t n = read(stream->fd, data + len, want);
+        if (n < 0) {
+            if (errno == EINTR) {
+                continue;
+            }
+            return fail();
+        }
+        if (n == 0) {
+            break;
+        }
+        if (mode == SCULPT_RT_READ_LINE && data[len] == '\n') {
+            if (len > 0 && data[len - 1] == '\r') {
+                len--;
+            }
+            break;
+        }
+        len += (size_t)n;
+    }
+    *buffer = data;
+    return (int64_t)len;
+}
+
+int64_t sculpt_rt_format_i64(int64_t value, uint8_t *buffer) {
+    /* Digits are generated from the end, negated so `INT64_MIN` doesn't overflow. */
+    uint8_t digits[SCULPT_RT_FORMAT_I64_LEN];
+    size_t start = sizeof digits;
+    int64_t rest = value < 0 ? value : -value;
+    do {
+        digits[--start] = (uint8_t)('0' - rest % 10);
+        rest /= 10;
+    } while (rest != 0);
+    if (value < 0) {
+        digits[--start] = '-';
+    }
+    size_t len = sizeof digits - start;
+    for (size_t i = 0; i < len; i++) {
+        buffer[i] = digits[start + i];
+    }
+    return (int64_t)len;
+}
+
+int64_t sculpt_rt_format_radix(int64_t value, uint64_t radix, uint64_t upper, uint8_t *buffer) {
+    const char *symbols = upper ? "0123456789ABCDEF" : "0123456789abcdef";
+    uint8_t digits[SCULPT_RT_FORMAT_RADIX_LEN];
+    size_t start = sizeof digits;
+    uint64_t rest = (uint64_t)value;
+    do {
+        digits[--start] = (uint8_t)symbols[rest % radix];
+        rest /= radix;
+    } while (rest != 0);
+    size_t len = sizeof digits - start;
+    for (size_t i = 0; i < len; i++) {
+        buffer[i] = digits[start + i];
+    }
+    return (int64_t)len;
+}
+
+/* Copies the `len` bytes of `s` into a NUL-terminated string, or returns NULL if they contain a
+ * NUL, which no name the C library takes can. */
+static char *terminate(const uint8_t *s, uint64_t len) {
+    if (memchr(s, '\0', len) != NULL) {
+        return NULL;
+    }
+    char *terminated = sculpt_rt_alloc(len + 1);
+    memcpy(terminated, s, len);
+    terminated[len] = '\0';
+    return terminated;
+}
+
+int64_t sculpt_rt_getenv(struct sculpt_rt_host *host, const uint8_t *name, uint64_t len,
+                         const uint8_t **value) {
+    (void)host;
+    char *terminated = terminate(name, len);
+    if (terminated == NULL) {
+        *value = NULL;
+        return 0;
+    }
+    const char *found = getenv(terminated);
+    sculpt_rt_free(terminated);
+    *value = (const uint8_t *)found;
+    return found != NULL ? (int64_t)strlen(found) : 0;
+}
+
+static struct sculpt_rt_host *init(struct sculpt_rt_host *host) {
+    if (host->initialized) {
+        return host;
+    }
+    const char *seed = getenv("SCULPT_SEED");
+    if (seed != NULL) {
+        host->rng = strtoull(seed, NULL, 10);
+    } else {
+        struct timespec now;
+        clock_gettime(CLOCK_REALTIME, &now);
+        host->rng = (uint64_t)now.tv_sec * 1000000000u + (uint64_t)now.tv_nsec;
+    }
+    const char *clock = getenv("SCULPT_CLOCK");
+    if (clock != NULL && strcmp(clock, "virtual") == 0) {
+        host->clock = CLOCK_VIRTUAL;
+    } else if (clock != NULL && strcmp(clock, "denied") == 0) {
+        host->clock = CLOCK_DENIED;
+    }
+    const char *files = getenv("SCULPT_FILES");
+    if (files != NULL && strcmp(files, "virtual") == 0) {
+        host->files = FILES_VIRTUAL;
+    } else if (files != NULL && strcmp(files, "denied") == 0) {
+        host->files = FILES_DENIED;
+    }
+    host->initialized = 1;
+    return host;
+}
+
+/* SplitMix64, as the JIT's runtime implements it, so both draw the same integers from a seed. */
+static uint64_t next_random(struct sculpt_rt_host *host) {
+    init(host);
+    uint64_t z = host->rng += 0x9e3779b97f4a7c15u;
+    z = (z ^ (z >> 30)) * 0xbf58476d1ce4e5b9u;
+    z = (z ^ (z >> 27)) * 0x94d049bb133111ebu;
+    return z ^ (z >> 31);
+}
+
+int64_t sculpt_rt_random_int(struct sculpt_rt_host *host, int64_t lo, int64_t hi) {
+    uint64_t span = (uint64_t)hi - (uint64_t)lo;
+    uint64_t offset = (uint64_t)(((unsigned __int128)next_random(host) * span) >> 64);
+    return (int64_t)((uint64_t)lo + offset);
+}
+
+static void deny_clock(struct sculpt_rt_host *host) {
+    if (init(host)->clock == CLOCK_DENIED) {
+        static const char message[] = "this program may not use the clock";
+        sculpt_rt_panic(message, sizeof message - 1);
+    }
+}
+
+int64_t sculpt_rt_now_millis(struct sculpt_rt_host *host) {
+    deny_clock(host);
+    if (host->clock == CLOCK_VIRTUAL) {
+        return host->virtual_millis;
+    }
+    struct timespec now;
+    if (clock_gettime(CLOCK_REALTIME, &now) != 0) {
+        return fail();
+    }
+    return (int64_t)now.tv_sec * 1000 + now.tv_nsec / 1000000;
+}
+
+int64_t sculpt_rt_sleep_millis(struct sculpt_rt_host *host, int64_t millis) {
+    deny_clock(host);
+    if (host->clock == CLOCK_VIRTUAL) {
+        /* Saturates rather than overflowing, like the JIT's runtime. */
+        host->virtual_millis =
+            millis > INT64_MAX - host->virtual_millis ? INT64_MAX : host->virtual_millis + millis;
+        return 0;
+    }
+    struct timespec duration = {millis / 1000, (millis % 1000) * 1000000};
+    while (nanosleep(&duration, &duration) != 0) {
+        if (errno != EINTR) {
+            return fail();
+        }
+    }
+    return 0;
+}
+
+static int is_char_boundary(const uint8_t *s, uint64_t len, uint64_t i) {
+    return i >= len || (int8_t)s[i] >= -0x40;
+}
+
+int64_t sculpt_rt_check_slice(const uint8_t *s, uint64_t len, int64_t begin, int64_t end,
+                              uint64_t start, uint64_t span_end) {
+    /* Shows at most 256 bytes of `s`, cut on a char boundary, like Rust. */
+    uint64_t shown = len < 256 ? len : 256;
+    while (!is_char_boundary(s, len, shown)) {
+        shown--;
+    }
+    const char *ellipsis = shown < len ? "[...]" : "";
+    char message[512];
+    int size;
+    if (begin < 0 || end < 0 || (uint64_t)begin > len || (uint64_t)end > len) {
+        int64_t index = begin < 0 || (uint64_t)begin > len ? begin : end;
+        size = snprintf(message, sizeof message, "byte index %lld is out of bounds of `%.*s`%s",
+                        (long long)index, (int)shown, s, ellipsis);
+    } else if (begin > end) {
+        size = snprintf(message, sizeof message,
+                        "begin <= end (%lld <= %lld) when slicing `%.*s`%s", (long long)begin,
+                        (long long)end, (int)shown, s, ellipsis);
+    } else {
+        uint64_t index =
+            is_char_boundary(s, len, (uint64_t)begin) ? (uint64_t)end : (uint64_t)begin;
+        if (is_char_boundary(s, len, index)) {
+            return 0;
+        }
+        uint64_t char_start = index - 1;
+        while (!is_char_boundary(s, len, char_start)) {
+            char_start--;
+        }
+        uint64_t char_end = index + 1;
+        while (!is_char_boundary(s, len, char_end)) {
+            char_end++;
+        }
+        size = snprintf(message, sizeof message,
+                        "byte index %llu is not a char boundary; it is inside '%.*s' (bytes "
+                        "%llu..%llu) of `%.*s`%s",
+                        (unsigned long long)index, (int)(char_end - char_start), s + char_start,
+                        (unsigned long long)char_start, (unsigned long long)char_end, (int)shown, s,
+                        ellipsis);
+    }
+    if (size >= (int)sizeof message) {
+        size = sizeof message - 1;
+    }
+    sculpt_rt_panic_at(message, (size_t)size, start, span_end);
+}
+
+/* Decodes the char `s` starts with, setting `len` to how many bytes it takes. An invalid byte
+ * decodes to itself. */
+static uint32_t decode_char(const uint8_t *s, uint64_t available, uint64_t *len) {
+    uint64_t n = s[0] < 0x80 ? 1 : s[0] >= 0xf0 ? 4 : s[0] >= 0xe0 ? 3 : s[0] >= 0xc0 ? 2 : 0;
+    if (n == 0 || n > available) {
+        *len = 1;
+        return s[0];
+    }
+    uint32_t c = n == 1 ? s[0] : s[0] & (0x7f >> n);
+    for (uint64_t i = 1; i < n; i++) {
+        if ((s[i] & 0xc0) != 0x80) {
+            *len = 1;
+            return s[0];
+        }
+        c = c << 6 | (s[i] & 0x3f);
+    }
+    *len = n;
+    return c;
+}
+
+/* Encodes `c` into `buffer`, which has room for 4 bytes, returning how many it took. */
+static uint64_t encode_char(uint32_t c, uint8_t *buffer) {
+    if (c < 0x80) {
+        buffer[0] = (uint8_t)c;
+        return 1;
+    }
+    if (c < 0x800) {
+        buffer[0] = (uint8_t)(0xc0 | c >> 6);
+        buffer[1] = (uint8_t)(0x80 | (c & 0x3f));
+        return 2;
+    }
+    if (c < 0x10000) {
+        buffer[0] = (uint8_t)(0xe0 | c >> 12);
+        buffer[1] = (uint8_t)(0x80 | (c >> 6 & 0x3f));
+        buffer[2] = (uint8_t)(0x80 | (c & 0x3f));
+        return 3;
+    }
+    buffer[0] = (uint8_t)(0xf0 | c >> 18);
+    buffer[1] = (uint8_t)(0x80 | (c >> 12 & 0x3f));
+    buffer[2] = (uint8_t)(0x80 | (c >> 6 & 0x3f));
+    buffer[3] = (uint8_t)(0x80 | (c & 0x3f));
+    return 4;
+}
+
+/* Maps each char of `s` with `map` into the host's buffer, in a UTF-8 locale if there is one. */
+static int64_t map_chars(struct sculpt_rt_host *host, const uint8_t *s, uint64_t len,
+                         const uint8_t **value, wint_t (*map)(wint_t, locale_t)) {
+    static locale_t utf8;
+    if (utf8 == (locale_t)0) {
+        utf8 = newlocale(LC_CTYPE_MASK, "C.UTF-8", (locale_t)0);
+    }
+    locale_t locale = utf8 != (locale_t)0 ? utf8 : LC_GLOBAL_LOCALE;
+    uint64_t mapped_len = 0;
+    for (uint64_t i = 0; i < len;) {
+        if (host->capacity - mapped_len < 4) {
+            host->capacity = host->capacity ? host->capacity * 2 : 4096;
+            host->buffer = sculpt_rt_realloc(host->buffer, host->capacity);
+        }
+        uint64_t char_len;
+        uint32_t c = decode_char(s + i, len - i, &char_len);
+        if (char_len == 1 && s[i] >= 0x80) {
+            host->buffer[mapped_len++] = s[i];
+        } else {
+            mapped_len += encode_char((uint32_t)map((wint_t)c, locale), host->buffer + mapped_len);
+        }
+        i += char_len;
+    }
+    *value = host->buffer;
+    return (int64_t)mapped_len;
+}
+
+int64_t sculpt_rt_to_uppercase(struct sculpt_rt_host *host, const uint8_t *s, uint64_t len,
+                               const uint8_t **value) {
+    return map_chars(host, s, len, value, towupper_l);
+}
+
+int64_t sculpt_rt_to_lowercase(struct sculpt_rt_host *host, const uint8_t *s, uint64_t len,
+                               const uint8_t **value) {
+    return map_chars(host, s, len, value, towlower_l);
+}
+
+/* Whether `c` has Unicode's White_Space property, like Rust's `char::is_whitespace`. */
+static int is_whitespace(uint32_t c) {
+    return (c >= 0x9 && c <= 0xd) || c == 0x20 || c == 0x85 || c == 0xa0 || c == 0x1680 ||
+           (c >= 0x2000 && c <= 0x200a) || c == 0x2028 || c == 0x2029 || c == 0x202f ||
+           c == 0x205f || c == 0x3000;
+}
+
+int64_t sculpt_rt_trim(const uint8_t *s, uint64_t len, const uint8_t **trimmed) {
+    uint64_t start = 0;
+    while (start < len) {
+        uint64_t char_len;
+        if (!is_whitespace(decode_char(s + start, len - start, &char_len))) {
+            break;
+        }
+        start += char_len;
+    }
+    uint64_t end = len;
+    while (end > start) {
+        uint64_t char_start = end - 1;
+        while (char_start > start && (s[char_start] & 0xc0) == 0x80) {
+            char_start--;
+        }
+        uint64_t char_len;
+        if (!is_whitespace(decode_char(s + char_start, end - char_start, &char_len)) ||
+            char_start + char_len != end) {
+            break;
+        }
+        end = char_start;
+    }
+    *trimmed = s + start;
+    return (int64_t)(end - start);
+}
+
+int64_t sculpt_rt_contains(const uint8_t *s, uint64_t len, const uint8_t *pattern,
+                           uint64_t pattern_len) {
+    for (uint64_t i = 0; i + pattern_len <= len; i++) {
+        if (memcmp(s + i, pattern, pattern_len) == 0) {
+            return 1;
+        }
+    }
+    return 0;
+}
+
+/* Panics with "could not <action> `<path>`: <reason>". */
+static _Noreturn void file_panic(const char *action, const uint8_t *path, uint64_t len,
+                                 const char *reason) {
+    int size = snprintf(NULL, 0, "could not %s `%.*s`: %s", action, (int)len, path, reason);
+    char *message = sculpt_rt_alloc((size_t)size + 1);
+    snprintf(message, (size_t)size + 1, "could not %s `%.*s`: %s", action, (int)len, path, reason);
+    sculpt_rt_panic(message, (size_t)size);
+}
+
+/* The path a program passed, terminated, panicking if the file system is denied or it can't name a
+ * file. */
+static char *file_path(struct sculpt_rt_host *host, const char *action, const uint8_t *path,
+                       uint64_t len) {
+    if (init(host)->files == FILES_DENIED) {
+        static const char message[] = "this program may not use files";
+        sculpt_rt_panic(message, sizeof message - 1);
+    }
+    char *terminated = terminate(path, len);
+    if (terminated == NULL) {
+        file_panic(action, path, len, "paths cannot contain NUL");
+    }
+    return terminated;
+}
+
+static struct virtual_file *find_virtual_file(struct sculpt_rt_host *host, const char *path) {
+    for (struct virtual_file *file = host->virtual_files; file != NULL; file = file->next) {
+        if (strcmp(file->path, path) == 0) {
+            return file;
+        }
+    }
+    return NULL;
+}
+
+int64_t sculpt_rt_read_file(struct sculpt_rt_host *host, const uint8_t *path, uint64_t len,
+                            const uint8_t **contents) {
+    char *terminated = file_path(host, "read", path, len);
+    if (host->files == FILES_VIRTUAL) {
+        struct virtual_file *file = find_virtual_file(host, terminated);
+        sculpt_rt_free(terminated);
+        if (file == NULL) {
+            file_panic("read", path, len, strerror(ENOENT));
+        }
+        *contents = file->contents;
+        return (int64_t)file->len;
+    }
+    int fd = open(terminated, O_RDONLY);
+    sculpt_rt_free(terminated);
+    if (fd < 0) {
+        file_panic("read", path, len, strerror(errno));
+    }
+    size_t read_len = 0;
+    for (;;) {
+        if (read_len == host->capacity) {
+            host->capacity = host->capacity ? host->capacity * 2 : 4096;
+            host->buffer = sculpt_rt_realloc(host->buffer, host->capacity);
+        }
+        ssize_t n = read(fd, host->buffer + read_len, host->capacity - read_len);
+        if (n < 0) {
+            if (errno == EINTR) {
+                continue;
+            }
+            file_panic("read", path, len, strerror(errno));
+        }
+        if (n == 0) {
+            break;
+        }
+        read_len += (size_t)n;
+    }
+    close(fd);
+    *contents = host->buffer;
+    return (int64_t)read_len;
+}
+
+int64_t sculpt_rt_write_file(struct sculpt_rt_host *host, const uint8_t *path, uint64_t path_len,
+                             const uint8_t *contents, uint64_t len) {
+    char *terminated = file_path(host, "write", path, path_len);
+    if (host->files == FILES_VIRTUAL) {
+        struct virtual_file *file = find_virtual_file(host, terminated);
+        if (file == NULL) {
+            file = sculpt_rt_alloc(sizeof *file);
+            file->path = terminated;
+            file->next = host->virtual_files;
+            host->virtual_files = file;
+        } else {
+            sculpt_rt_free(file->contents);
+            sculpt_rt_free(terminated);
+        }
+        file->contents = sculpt_rt_alloc(len);
+        memcpy(file->contents, contents, len);
+        file->len = len;
+        return 0;
+    }
+    int fd = open(terminated, O_WRONLY | O_CREAT | O_TRUNC, 0666);
+    sculpt_rt_free(terminated);
+    if (fd < 0) {
+        file_panic("write", path, path_len, strerror(errno));
+    }
+    uint64_t written = 0;
+    while (written < len) {
+        ssize_t n = write(fd, contents + written, len - written);
+        if (n < 0) {
+            if (errno == EINTR) {
+                continue;
+            }
+            file_panic("write", path, path_len, strerror(errno));
+        }
+        written += (uint64_t)n;
+    }
+    if (close(fd) != 0) {
+        file_panic("write", path, path_len, strerror(errno));
+    }
+    return 0;
+}
+
+int64_t sculpt_rt_exit(struct sculpt_rt_host *host, int64_t status) {
+    (void)host;
+    if (status < INT32_MIN || status > INT32_MAX) {
+        char message[64];
+        int len = snprintf(message, sizeof message, "exit status %lld out of range",
+                           (long long)status);
+        sculpt_rt_panic(message, (size_t)len);
+    }
+    exit((int)status);
+}
+
+void sculpt_rt_append(struct sculpt_rt_string *string, const uint8_t *s, uint64_t len) {
+    if (len == 0) {
+        return;
+    }
+    if (string->len + len > string->capacity) {
+        string->capacity = string->len + len > 2 * string->capacity ? string->len + len
+                                                                    : 2 * string->capacity;
+        string->buffer = sculpt_rt_realloc(string->buffer, string->capacity);
+    }
+    memcpy(string->buffer + string->len, s, len);
+    string->len += len;
+}
+
+void sculpt_rt_append_debug_str(struct sculpt_rt_string *string, const uint8_t *s, uint64_t len) {
+    sculpt_rt_append(string, (const uint8_t *)"\"", 1);
+    uint64_t start = 0;
+    for (uint64_t i = 0; i < len; i++) {
+        char escape[8];
+        int escape_len;
+        switch (s[i]) {
+        case '"':
+        case '\\':
+            escape_len = snprintf(escape, sizeof escape, "\\%c", s[i]);
+            break;
+        case '\n':
+            escape_len = snprintf(escape, sizeof escape, "\\n");
+            break;
+        case '\r':
+            escape_len = snprintf(escape, sizeof escape, "\\r");
+            break;
+        case '\t':
+            escape_len = snprintf(escape, sizeof escape, "\\t");
+            break;
+        case '\0':
+            escape_len = snprintf(escape, sizeof escape, "\\0");
+            break;
+        default:
+            if (s[i] >= 0x20 && s[i] != 0x7f) {
+                continue;
+            }
+            escape_len = snprintf(escape, sizeof escape, "\\u{%x}", s[i]);
+        }
+        sculpt_rt_append(string, s + start, i - start);
+        sculpt_rt_append(string, (const uint8_t *)escape, (uint64_t)escape_len);
+        start = i + 1;
+    }
+    sculpt_rt_append(string, s + start, len - start);
+    sculpt_rt_append(string, (const uint8_t *)"\"", 1);
+}
+
+/* Entries are found through an open addressing table of their indices, at least half of which
+ * is empty. */
+struct sculpt_rt_map {
+    uint64_t len;
+    uint64_t key_kind;
+    uint64_t entry_size;
+    uint64_t capacity;
+    uint8_t *entries;
+    uint64_t slot_count;
+    int64_t *slots;
+};
+
+/* The bytes of the key `key` points to. */
+static const uint8_t *key_bytes(const struct sculpt_rt_map *map, const uint8_t *key,
+                                uint64_t *len) {
+    if (map->key_kind == SCULPT_RT_KEY_STR) {
+        const uint8_t *bytes;
+        memcpy(&bytes, key, sizeof bytes);
+        memcpy(len, key + sizeof bytes, sizeof *len);
+        return bytes;
+    }
+    *len = sizeof(int64_t);
+    return key;
+}
+
+/* FNV-1a. */
+static uint64_t hash_key(const struct sculpt_rt_map *map, const uint8_t *key) {
+    uint64_t len;
+    const uint8_t *bytes = key_bytes(map, key, &len);
+    uint64_t hash = 0xcbf29ce484222325;
+    for (uint64_t i = 0; i < len; i++) {
+        hash = (hash ^ bytes[i]) * 0x100000001b3;
+    }
+    return hash;
+}
+
+static int keys_equal(const struct sculpt_rt_map *map, const uint8_t *a, const uint8_t *b) {
+    uint64_t a_len, b_len;
+    const uint8_t *a_bytes = key_bytes(map, a, &a_len);
+    const uint8_t *b_bytes = key_bytes(map, b, &b_len);
+    return a_len == b_len && (a_len == 0 || memcmp(a_bytes, b_bytes, a_len) == 0);
+}
+
+/* The slot holding the entry with the key `key` points to, or the empty one it would go in. */
+static uint64_t find_slot(const struct sculpt_rt_map *map, const uint8_t *key) {
+    uint64_t mask = map->slot_count - 1;
+    uint64_t slot = hash_key(map, key) & mask;
+    while (map->slots[slot] >= 0 &&
+           !keys_equal(map, sculpt_rt_map_entry((struct sculpt_rt_map *)map,
+                                                (uint64_t)map->slots[slot]),
+                       key)) {
+        slot = (slot + 1) & mask;
+    }
+    return slot;
+}
+
+struct sculpt_rt_map *sculpt_rt_map_new(uint64_t key_kind, uint64_t entry_size) {
+    struct sculpt_rt_map *map = sculpt_rt_alloc(sizeof *map);
+    *map = (struct sculpt_rt_map){0, key_kind, entry_size, 0, NULL, 0, NULL};
+    return map;
+}
+
+uint8_t *sculpt_rt_map_entry(struct sculpt_rt_map *map, uint64_t index) {
+    return map->entries + index * map->entry_size;
+}
+
+int64_t sculpt_rt_map_find(const struct sculpt_rt_map *map, const uint8_t *key) {
+    if (map->len == 0) {
+        return -1;
+    }
+    return map->slots[find_slot(map, key)];
+}
+
+void sculpt_rt_map_push(struct sculpt_rt_map *map, const uint8_t *entry) {
+    if (map->len == map->capacity) {
+        map->capacity = map->capacity ? map->capacity * 2 : 8;
+        map->entries = sculpt_rt_realloc(map->entries, map->capacity * map->entry_size);
+    }
+    memcpy(sculpt_rt_map_entry(map, map->len), entry, map->entry_size);
+    map->len++;
+    if (2 * map->len > map->slot_count) {
+        sculpt_rt_free(map->slots);
+        map->slot_count = map->slot_count ? map->slot_count * 2 : 16;
+        map->slots = sculpt_rt_alloc(map->slot_count * sizeof *map->slots);
+        memset(map->slots, 0xff, map->slot_count * sizeof *map->slots);
+        for (uint64_t i = 0; i + 1 < map->len; i++) {
+            map->slots[find_slot(map, sculpt_rt_map_entry(map, i))] = (int64_t)i;
+        }
+    }
+    map->slots[find_slot(map, entry)] = (int64_t)map->len - 1;
+}
+
+void sculpt_rt_map_remove(struct sculpt_rt_map *map, uint64_t index) {
+    /* Later slots the entry's pushed along are shifted back into its place, so each entry can
+     * still be found from the slot its hash starts at. */
+    uint64_t mask = map->slot_count - 1;
+    uint64_t hole = find_slot(map, sculpt_rt_map_entry(map, index));
+    for (uint64_t slot = (hole + 1) & mask; map->slots[slot] >= 0; slot = (slot + 1) & mask) {
+        uint64_t home =
+            hash_key(map, sculpt_rt_map_entry(map, (uint64_t)map->slots[slot])) & mask;
+        int stays = hole <= slot ? hole < home && home <= slot : hole < home || home <= slot;
+        if (!stays) {
+            map->slots[hole] = map->slots[slot];
+            hole = slot;
+        }
+    }
+    map->slots[hole] = -1;
+    uint64_t last = map->len - 1;
+    if (index != last) {
+        uint8_t *entry = sculpt_rt_map_entry(map, index);
+        memcpy(entry, sculpt_rt_map_entry(map, last), map->entry_size);
+        map->slots[find_slot(map, entry)] = (int64_t)index;
+    }
+    map->len--;
+}
+
+struct sculpt_rt_map *sculpt_rt_map_clone(const struct sculpt_rt_map *map) {
+    struct sculpt_rt_map *clone = sculpt_rt_alloc(sizeof *clone);
+    *clone = *map;
+    clone->entries = sculpt_rt_alloc(map->capacity * map->entry_size);
+    memcpy(clone->entries, map->entries, map->len * map->entry_size);
+    clone->slots = sculpt_rt_alloc(map->slot_count * sizeof *map->slots);
+    memcpy(clone->slots, map->slots, map->slot_count * sizeof *map->slots);
+    return clone;
+}
+
+void sculpt_rt_map_free(struct sculpt_rt_map *map) {
+    sculpt_rt_free(map->entries);
+    sculpt_rt_free(map->slots);
+    sculpt_rt_free(map);
+}
+
+static int compare_entries(const uint8_t *a, const uint8_t *b, uint64_t entry_size,
+                           uint64_t key_kind) {
+    if (key_kind == SCULPT_RT_KEY_STR) {
+        const uint8_t *a_bytes, *b_bytes;
+        uint64_t a_len, b_len;
+        memcpy(&a_bytes, a, sizeof a_bytes);
+        memcpy(&a_len, a + sizeof a_bytes, sizeof a_len);
+        memcpy(&b_bytes, b, sizeof b_bytes);
+        memcpy(&b_len, b + sizeof b_bytes, sizeof b_len);
+        uint64_t len = a_len < b_len ? a_len : b_len;
+        int ordering = len == 0 ? 0 : memcmp(a_bytes, b_bytes, len);
+        if (ordering != 0) {
+            return ordering;
+        }
+        if (a_len != b_len) {
+            return a_len < b_len ? -1 : 1;
+        }
+    } else {
+        int64_t a_key, b_key;
+        memcpy(&a_key, a, sizeof a_key);
+        memcpy(&b_key, b, sizeof b_key);
+        if (a_key != b_key) {
+            return a_key < b_key ? -1 : 1;
+        }
+    }
+    int64_t a_index, b_index;
+    memcpy(&a_index, a + entry_size - sizeof a_index, sizeof a_index);
+    memcpy(&b_index, b + entry_size - sizeof b_index, sizeof b_index);
+    return a_index < b_index ? -1 : a_index > b_index;
+}
+
+/* A bottom up merge sort, merging runs back and forth between the entries and a scratch copy. */
+void sculpt_rt_sort(uint8_t *entries, uint64_t len, uint64_t entry_size, uint64_t key_kind) {
+    if (len < 2) {
+        return;
+    }
+    uint8_t *from = entries;
+    uint8_t *to = sculpt_rt_alloc(len * entry_size);
+    for (uint64_t width = 1; width < len; width *= 2) {
+        for (uint64_t start = 0; start < len; start += 2 * width) {
+            uint64_t mid = start + width < len ? start + width : len;
+            uint64_t end = mid + width < len ? mid + width : len;
+            uint64_t i = start, j = mid;
+            for (uint64_t k = start; k < end; k++) {
+                int left = j == end || (i < mid && compare_entries(from + i * entry_size,
+                                                                   from + j * entry_size,
+                                                                   entry_size, key_kind) < 0);
+                uint64_t taken = left ? i++ : j++;
+                memcpy(to + k * entry_size, from + taken * entry_size, entry_size);
+            }
+        }
+        uint8_t *swap = from;
+        from = to;
+        to = swap;
+    }
+    if (from != entries) {
+        memcpy(entries, from, len * entry_size);
+        to = from;
+    }
+    sculpt_rt_free(to);
+}
+
+_Noreturn void sculpt_rt_panic(const char *message, size_t len) {
+    static const char prefix[] = "panicked: ";
+    (void)!write(2, prefix, sizeof prefix - 1);
+    (void)!write(2, message, len);
+    (void)!write(2, "\n", 1);
+    _exit(101);
+}
+
+_Noreturn void sculpt_rt_panic_at(const char *message, size_t len, uint64_t start, uint64_t end) {
+    (void)end;
+    char prefix[64];
+    int prefix_len = snprintf(prefix, sizeof prefix, "panicked at byte %llu: ",
+                              (unsigned long long)start);
+    (void)!write(2, prefix, (size_t)prefix_len);
+    (void)!write(2, message, len);
+    (void)!write(2, "\n", 1);
+    _exit(101);
+}
+
+/* Every allocation starts with a header holding its size, so the bytes it held are known when
+ * it's freed. It's as big as malloc's alignment, so what follows it stays aligned. */
+#define HEADER_SIZE 16
+
+static uint64_t heap_bytes = 0;
+
+void *sculpt_rt_alloc(size_t size) {
+    return sculpt_rt_realloc(NULL, size);
+}
+
+void *sculpt_rt_realloc(void *ptr, size_t size) {
+    static uint64_t max_heap_bytes = 0;
+    if (max_heap_bytes == 0) {
+        max_heap_bytes = limit("SCULPT_MAX_HEAP_BYTES");
+    }
+    uint8_t *header = ptr != NULL ? (uint8_t *)ptr - HEADER_SIZE : NULL;
+    uint64_t old_size = 0;
+    if (header != NULL) {
+        memcpy(&old_size, header, sizeof old_size);
+    }
+    if (size > max_heap_bytes || heap_bytes - old_size > max_heap_bytes - size) {
+        char message[64];
+        snprintf(message, sizeof message, "heap limit of %llu bytes exceeded",
+                 (unsigned long long)max_heap_bytes);
+        abort_run(message);
+    }
+    uint8_t *allocation = realloc(header, HEADER_SIZE + size);
+    if (allocation == NULL) {
+        static const char message[] = "out of memory";
+        sculpt_rt_panic(message, sizeof message - 1);
+    }
+    heap_bytes = heap_bytes - old_size + size;
+    uint64_t new_size = size;
+    memcpy(allocation, &new_size, sizeof new_size);
+    return allocation + HEADER_SIZE;
+}
+
+void sculpt_rt_free(void *ptr) {
+    if (ptr == NULL) {
+        return;
+    }
+    uint8_t *header = (uint8_t *)ptr - HEADER_SIZE;
+    uint64_t size;
+    memcpy(&size, header, sizeof size);
+    heap_bytes -= size;
+    free(header);
+}
@@ -0,0 +1,186 @@
+/* The sculpt runtime ABI. Compiled programs only ever call these functions and read these
+ * globals, whether they're JIT-compiled by `sculpt run`, where the host implements them, or linked
+ * into an executable by `sculpt build`, where sculpt_rt.c does. */
+#ifndef SCULPT_RT_H
+#define SCULPT_RT_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+/* A stream a program reads or writes. Only ever handled by pointer. */
+struct sculpt_rt_stream;
+
+extern struct sculpt_rt_stream sculpt_rt_stdin;
+extern struct sculpt_rt_stream sculpt_rt_stdout;
+extern struct sculpt_rt_stream sculpt_rt_stderr;
+
+/* Host state builtins other than reads and writes go through. Only ever handled by pointer. */
+struct sculpt_rt_host;
+
+extern struct sculpt_rt_host sculpt_rt_host;
+
+/* Exit status of the executable, set to 1 once a runtime function fails. */
+extern int32_t sculpt_rt_status;
+
+/* Steps the program may take before calling `sculpt_rt_refuel`. Every loop iteration and call
+ * takes one. */
+extern int64_t sculpt_rt_fuel;
+
+/* Refills `sculpt_rt_fuel` once it runs out, returning 0, or -1 if the program may take no more
+ * steps. Executables may take as many as the `SCULPT_FUEL` environment variable says, if it's
+ * set. */
+int64_t sculpt_rt_refuel(void);
+
+/* `sculpt_rt_read` modes. */
+#define SCULPT_RT_READ_LINE 0
+#define SCULPT_RT_READ_TO_END 1
+
+/* Writes all `len` bytes of `buffer` to `stream`, returning `len`, or -1 on failure. */
+int64_t sculpt_rt_write(struct sculpt_rt_stream *stream, const uint8_t *buffer, uint64_t len);
+
+/* Reads a line without its line ending, or everything left in `stream`, into a buffer that is
+ * reused by the next read. Points `buffer` at it and returns its length, or -1 on failure. */
+int64_t sculpt_rt_read(struct sculpt_rt_stream *stream, uint64_t mode, const uint8_t **buffer);
+
+/* Bytes `sculpt_rt_format_i64` may write, enough for `INT64_MIN`. */
+#define SCULPT_RT_FORMAT_I64_LEN 20
+
+/* Writes `value` in decimal to `buffer`, which must hold `SCULPT_RT_FORMAT_I64_LEN` bytes,
+ * returning how many were written. */
+int64_t sculpt_rt_format_i64(int64_t value, uint8_t *buffer);
+
+/* Bytes `sculpt_rt_format_radix` may write, enough for any value in binary. */
+#define SCULPT_RT_FORMAT_RADIX_LEN 64
+
+/* Writes the bits of `value`, as unsigned, in base `radix` to `buffer`, which must hold
+ * `SCULPT_RT_FORMAT_RADIX_LEN` bytes, with uppercase digits if `upper` isn't 0. Returns how many
+ * were written. */
+int64_t sculpt_rt_format_radix(int64_t value, uint64_t radix, uint64_t upper, uint8_t *buffer);
+
+/* Writes `message` to stderr and exits with status 101. */
+_Noreturn void sculpt_rt_panic(const char *message, size_t len);
+
+/* Like `sculpt_rt_panic`, for a panic at bytes `start..end` of the program's source, which is
+ * reported by the offset it starts at. */
+_Noreturn void sculpt_rt_panic_at(const char *message, size_t len, uint64_t start, uint64_t end);
+
+/* Looks up the environment variable named by the `len` bytes of `name`. Points `value` at a buffer
+ * holding its value, valid until the next lookup, and returns its length. Points it at NULL, and
+ * returns 0, if the variable is unset. Returns -1 on failure. */
+int64_t sculpt_rt_getenv(struct sculpt_rt_host *host, const uint8_t *name, uint64_t len,
+                         const uint8_t **value);
+
+/* Draws an integer from `lo..hi`, which must not be empty. Executables are seeded from the
+ * `SCULPT_SEED` environment variable, or the clock if it's unset, and draw the same integers as
+ * `sculpt run --seed` given the same seed. */
+int64_t sculpt_rt_random_int(struct sculpt_rt_host *host, int64_t lo, int64_t hi);
+
+/* Milliseconds since the Unix epoch, or -1 on failure. Executables read the host's clock unless
+ * the `SCULPT_CLOCK` environment variable is `virtual`, for a clock that starts at 0 and only
+ * moves when the program sleeps, or `denied`, which panics. */
+int64_t sculpt_rt_now_millis(struct sculpt_rt_host *host);
+
+/* Blocks for `millis`, which must not be negative, on the clock `sculpt_rt_now_millis` reads.
+ * Returns 0, or -1 on failure. */
+int64_t sculpt_rt_sleep_millis(struct sculpt_rt_host *host, int64_t millis);
+
+/* Returns 0 if `begin..end` slices the `len` bytes of `s` on char boundaries, and otherwise panics
+ * at bytes `start..end` of the program's source with the message Rust would. */
+int64_t sculpt_rt_check_slice(const uint8_t *s, uint64_t len, int64_t begin, int64_t end,
+                              uint64_t start, uint64_t span_end);
+
+/* Map each char of the `len` bytes of `s` to upper or lowercase. Point `value` at a buffer
+ * holding the result, valid until the next call that uses the host's buffer, and return its
+ * length. Chars are mapped one to one, so those Rust maps to several chars, like `ß` to `SS`, are
+ * left as they are. */
+int64_t sculpt_rt_to_uppercase(struct sculpt_rt_host *host, const uint8_t *s, uint64_t len,
+                               const uint8_t **value);
+int64_t sculpt_rt_to_lowercase(struct sculpt_rt_host *host, const uint8_t *s, uint64_t len,
+                               const uint8_t **value);
+
+/* Points `trimmed` into the `len` bytes of `s` past its leading whitespace, and returns the length
+ * left once its trailing whitespace is cut off too. */
+int64_t sculpt_rt_trim(const uint8_t *s, uint64_t len, const uint8_t **trimmed);
+
+/* Returns 1 if the `len` bytes of `s` contain the `pattern_len` bytes of `pattern`, and 0
+ * otherwise. */
+int64_t sculpt_rt_contains(const uint8_t *s, uint64_t len, const uint8_t *pattern,
+                           uint64_t pattern_len);
+
+/* Reads the whole file at the `len` bytes of `path`, relative to the working directory. Points
+ * `contents` at a buffer holding it, valid until the next read, and returns its length. Panics if
+ * the file can't be read. Executables use the host's file system unless the `SCULPT_FILES`
+ * environment variable is `virtual`, for an empty one in memory that only the program writes to,
+ * or `denied`, which panics. */
+int64_t sculpt_rt_read_file(struct sculpt_rt_host *host, const uint8_t *path, uint64_t len,
+                            const uint8_t **contents);
+
+/* Replaces the file at the `path_len` bytes of `path` with the `len` bytes of `contents`, creating
+ * it if needed, on the file system `sculpt_rt_read_file` reads. Returns 0, and panics if the file
+ * can't be written. */
+int64_t sculpt_rt_write_file(struct sculpt_rt_host *host, const uint8_t *path, uint64_t path_len,
+                             const uint8_t *contents, uint64_t len);
+
+/* Exits the executable with `status`, which must fit in an `int32_t`. Only returns, with -1, when
+ * the host runs the program in-process, so the program aborts back to it. */
+int64_t sculpt_rt_exit(struct sculpt_rt_host *host, int64_t status);
+
+/* A string built up by `sculpt_rt_append`, starting out zeroed. Its buffer is freed with
+ * `sculpt_rt_free`. */
+struct sculpt_rt_string {
+    uint8_t *buffer;
+    uint64_t len;
+    uint64_t capacity;
+};
+
+/* Appends the `len` bytes of `s` to `string`. */
+void sculpt_rt_append(struct sculpt_rt_string *string, const uint8_t *s, uint64_t len);
+
+/* Appends the `len` bytes of `s` to `string` quoted, like Rust's `{:?}` formats a string: with
+ * quotes and backslashes escaped, `\n`, `\r`, `\t` and `\0` for those chars, and `\u{7f}`
+ * style escapes for other ASCII control chars. */
+void sculpt_rt_append_debug_str(struct sculpt_rt_string *string, const uint8_t *s, uint64_t len);
+
+/* `sculpt_rt_map_new` and `sculpt_rt_sort` key kinds. */
+#define SCULPT_RT_KEY_INT 0
+#define SCULPT_RT_KEY_STR 1
+
+/* A hash map of entries that start with their key: an `int64_t`, or a string's pointer and
+ * length. Entries stay in the order they were pushed in, except that removing one moves the last
+ * into its place. Only ever handled by pointer, though programs read its first field, its number
+ * of entries, directly. */
+struct sculpt_rt_map;
+
+/* A new map of entries of `entry_size` bytes, whose keys are of `key_kind`. */
+struct sculpt_rt_map *sculpt_rt_map_new(uint64_t key_kind, uint64_t entry_size);
+
+/* A pointer to the entry at `index`, valid until the next push. */
+uint8_t *sculpt_rt_map_entry(struct sculpt_rt_map *map, uint64_t index);
+
+/* The index of the entry whose key equals the one `key` points to, or -1 if there's none. */
+int64_t sculpt_rt_map_find(const struct sculpt_rt_map *map, const uint8_t *key);
+
+/* Copies `entry`, whose key the map mustn't have, in after the last entry. */
+void sculpt_rt_map_push(struct sculpt_rt_map *map, const uint8_t *entry);
+
+/* Removes the entry at `index`, moving the last entry into its place. */
+void sculpt_rt_map_remove(struct sculpt_rt_map *map, uint64_t index);
+
+/* A new map with a copy of each entry's bytes. */
+struct sculpt_rt_map *sculpt_rt_map_clone(const struct sculpt_rt_map *map);
+
+/* Frees the map, but not what its entries point to. */
+void sculpt_rt_map_free(struct sculpt_rt_map *map);
+
+/* Sorts the `len` entries of `entry_size` bytes at `entries`, which start with a key of
+ * `key_kind` and end with an `int64_t` index. Strings are compared by their bytes, and entries
+ * with equal keys by their indices. */
+void sculpt_rt_sort(uint8_t *entries, uint64_t len, uint64_t entry_size, uint64_t key_kind);
+
+/* Allocator shims that panic instead of returning null, and abort the program once it has more
+ * bytes allocated than the `SCULPT_MAX_HEAP_BYTES` environment variable says, if it's set. */
+void *sculpt_rt_alloc(size_t size);
+void *sculpt_rt_realloc(void *ptr, size_t size);
+void sculpt_rt_free(void *ptr);
+
+#endif
@@ -0,0 +1,830 @@
+//! Ahead-of-time compilation to standalone executables. Programs are linked against sculpt-rt,
+//! the runtime in `runtime/`, with the system C compiler.
+
+use inkwell::context::Context;
+use inkwell::module::Linkage;
+use inkwell::OptimizationLevel;
+
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Output, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::backend::{Backend, Exit, RunOptions};
+use crate::hir::{self, Type};
+use crate::intern::Symbol;
+use crate::llvm::{write_object, Abi, Codegen};
+use crate::profile::{Phase, Profiler};
+
+/// sculpt-rt, compiled by the build script.
+const RUNTIME: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/libsculpt_rt.a"));
+
+/// Builds a program into a native executable.
+pub struct Aot<'ctx> {
+    codegen: Codegen<'ctx>,
+    opt_level: OptimizationLevel,
+    profiler: &'ctx Profiler,
+    /// Functions declared `pub`, which libraries export, and how.
+    exports: Vec<(Symbol, Export)>,
+    /// Whether archives are built the same wherever they're written, on any machine.
+    deterministic: bool,
+}
+
+impl<'ctx> Aot<'ctx> {
+    pub fn new(
+        context: &'ctx Context,
+        opt_level: OptimizationLevel,
+        profiler: &'ctx Profiler,
+    ) -> Self {
+        Self {
+            // Prefixed so functions like `write` don't collide with the C library.
+            codegen: Codegen::with_abi(context, "sculpt.".into(), Abi::Native),
+            opt_level,
+            profiler,
+            exports: Vec::new(),
+            deterministic: false,
+        }
+    }
+
+    /// Builds archives with no timestamps, owners or paths in them, so the same program always
+    /// archives to the same bytes.
+    pub fn deterministic(mut self, deterministic: bool) -> Self {
+        self.deterministic = deterministic;
+        self
+    }
+
+    /// Textual LLVM IR of everything built so far.
+    pub fn ir(&self) -> String {
+        self.codegen.ir()
+    }
+
+    /// Links the module and sculpt-rt into an executable at `path`.
+    pub fn link(&self, path: &Path) -> io::Result<()> {
+        self.link_from(path, "main")
+    }
+
+    /// Like [`Aot::link`], for an executable that starts in the function `entry`.
+    fn link_from(&self, path: &Path, entry: &str) -> io::Result<()> {
+        let object = path.with_extension("o");
+        let runtime = path.with_extension("rt.a");
+        self.codegen.build_entry_point(entry);
+        self.emit_object(&object)?;
+        fs::write(&runtime, RUNTIME)?;
+        let status = self.profiler.time(Phase::Link, || {
+            Command::new(env::var_os("CC").unwrap_or_else(|| "cc".into()))
+                .arg(&object)
+                .arg(&runtime)
+                .arg("-o")
+                .arg(path)
+                .status()
+        });
+        fs::remove_file(&object)?;
+        fs::remove_file(&runtime)?;
+        match status? {
+            status if status.success() => Ok(()),
+            status => Err(io::Error::other(format!("linker exited with {}", status))),
+        }
+    }
+
+    /// Archives the module and sculpt-rt into a static library at `path`. Each `pub fn name` is
+    /// exported as the C function `<crate_name>_<name>`, as [`export`] describes, and nothing else
+    /// the program defines is visible outside the library, so libraries built from different
+    /// files can be linked together.
+    pub fn archive(&self, crate_name: &str, path: &Path) -> io::Result<()> {
+        for function in self.codegen.module().get_functions() {
+            if function.count_basic_blocks() > 0 {
+                function.set_linkage(Linkage::Internal);
+            }
+        }
+        for (name, export) in &self.exports {
+            let symbol = export_symbol(crate_name, *name);
+            match export {
+                Export::Entry => self.codegen.build_export(name.as_str(), &symbol),
+                Export::C { params, ret } => {
+                    self.codegen.build_c_export(*name, &symbol, params, ret)
+                }
+            }
+        }
+
+        // The archive records the object's name, so a deterministic one is named after the crate
+        // rather than where the archive is written, and `D` leaves out timestamps and owners.
+        let (object, flags) = match self.deterministic {
+            true => {
+                let dir = temp_executable();
+                fs::create_dir(&dir)?;
+                (dir.join(format!("{}.o", crate_name)), "rsD")
+            }
+            false => (path.with_extension("o"), "rs"),
+        };
+        self.emit_object(&object)?;
+        fs::write(path, RUNTIME)?;
+        let status = self.profiler.time(Phase::Link, || {
+            Command::new(env::var_os("AR").unwrap_or_else(|| "ar".into()))
+                .arg(flags)
+                .arg(path)
+                .arg(&object)
+                .status()
+        });
+        match self.deterministic {
+            true => fs::remove_dir_all(object.parent().unwrap())?,
+            false => fs::remove_file(&object)?,
+        }
+        match status? {
+            status if status.success() => Ok(()),
+            status => Err(io::Error::other(format!("archiver exited with {}", status))),
+        }
+    }
+
+    /// C header declaring what [`Aot::archive`] exports.
+    pub fn header(&self, crate_name: &str) -> String {
+        let guard = format!("{}_H", crate_name.to_uppercase());
+        let mut header = format!(
+            "/* Generated by `sculpt build --crate-type=staticlib`. */\n\
+             #ifndef {guard}\n\
+             #define {guard}\n\
+             \n\
+             #include <stdbool.h>\n\
+             #include <stdint.h>\n\
+             \n\
+             /* Set to 1 once a function fails to read or write a stream. */\n\
+             extern int32_t sculpt_rt_status;\n\
+             \n\
+             /* Strings are passed as a pointer to their UTF-8 bytes, followed by their length. */\n\
+             \n"
+        );
+        for (name, export) in &self.exports {
+            let symbol = export_symbol(crate_name, *name);
+            header += &match export {
+                Export::Entry => format!("void {}(void);\n", symbol),
+                Export::C { params, ret } => {
+                    let params: Vec<_> = params.iter().map(|ty| c_type(ty)).collect();
+                    let params = match params.is_empty() {
+                        true => "void".to_string(),
+                        false => params.join(", "),
+                    };
+                    format!("{} {}({});\n", c_type(ret), symbol, params)
+                }
+            };
+        }
+        header += &format!("\n#endif /* {} */\n", guard);
+        header
+    }
+}
+
+/// Name a library built from `file` exports its functions under: the file's stem, with anything
+/// that can't appear in a C identifier replaced by `_`.
+pub fn crate_name(file: &Path) -> String {
+    let stem = file.file_stem().unwrap_or_default().to_string_lossy();
+    let mut name: String = stem
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if !name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        name.insert(0, '_');
+    }
+    name
+}
+
+fn export_symbol(crate_name: &str, name: Symbol) -> String {
+    format!("{}_{}", crate_name, name)
+}
+
+/// How a library exports one of its `pub fn`s.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Export {
+    /// As a C function taking and returning the C equivalents of the function's parameter and
+    /// return types: `int64_t` for `i64`, `bool` for `bool`, a pointer and a length for `&str`,
+    /// and `void` for `()`. It returns zero if the program aborts.
+    C { params: Vec<Type>, ret: Type },
+    /// As `void name(void)`, for a function taking no parameters that returns something C has no
+    /// equivalent of. The error of a `Result` it returns is reported like one `main` returns.
+    Entry,
+}
+
+/// How `function` is exported from a library, or the type C has no equivalent of that keeps it
+/// from being exported.
+pub fn export(function: &hir::Function) -> Result<Export, Type> {
+    if let Some(param) = function.generics.first() {
+        return Err(Type::Param(*param));
+    }
+    let params = function
+        .params
+        .iter()
+        .map(|(_, ty)| match ty {
+            Type::Int | Type::Bool | Type::Str => Ok(ty.clone()),
+            // References can't change what they refer to, so they're passed as a copy of it.
+            Type::Ref(inner, false) if matches!(**inner, Type::Int | Type::Bool | Type::Str) => {
+                Ok((**inner).clone())
+            }
+            ty => Err(ty.clone()),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    match &function.ret {
+        Type::Int | Type::Bool | Type::Unit => Ok(Export::C {
+            params,
+            ret: function.ret.clone(),
+        }),
+        _ if function.is_entry_point() => Ok(Export::Entry),
+        ret => Err(ret.clone()),
+    }
+}
+
+/// How a parameter or return value of type `ty` is declared in C.
+fn c_type(ty: &Type) -> &'static str {
+    match ty {
+        Type::Int => "int64_t",
+        Type::Bool => "bool",
+        Type::Str => "const char *, int64_t",
+        _ => "void",
+    }
+}
+
+impl Backend for Aot<'_> {
+    fn lower_module(
+        &mut self,
+        hir::Program {
+            structs,
+            statics,
+            functions,
+        }: hir::Program,
+    ) {
+        self.profiler.time(Phase::Codegen, || {
+            for s in structs {
+                self.codegen.define_struct(s);
+            }
+            for s in statics {
+                self.codegen.define_static(s);
+            }
+            for function in functions {
+                // Functions that can't be exported were reported before the library was built.
+                if let (true, Ok(export)) = (function.public, export(&function)) {
+                    self.exports.push((function.name, export));
+                }
+                tracing::trace!(function = %function.name, "building");
+                self.codegen.build_function(function);
+            }
+            self.codegen.build_instances();
+            self.codegen.log_built(self.profiler);
+        });
+        self.profiler.time(Phase::Verify, || {
+            if let Err(e) = self.codegen.module().verify() {
+                panic!("{}", e.to_string());
+            }
+        });
+    }
+
+    /// Links the module into a temporary executable and runs it as a child process. Stdin is read
+    /// to the end before the program starts, and its stdout is written before its stderr. A
+    /// sandboxed program gets an empty environment. A seed is passed in `SCULPT_SEED`, the clock
+    /// in `SCULPT_CLOCK`, the file system in `SCULPT_FILES`, and the fuel and heap limits in
+    /// `SCULPT_FUEL` and `SCULPT_MAX_HEAP_BYTES`. A program still running past its time limit is
+    /// killed.
+    fn run(
+        &mut self,
+        std_in: &mut dyn BufRead,
+        std_out: &mut dyn Write,
+        std_err: &mut dyn Write,
+        options: &RunOptions,
+    ) -> io::Result<()> {
+        let executable = temp_executable();
+        self.link_from(&executable, options.entry())?;
+        let mut input = Vec::new();
+        std_in.read_to_end(&mut input)?;
+
+        let output = self.profiler.time(Phase::Execute, || {
+            let mut command = Command::new(&executable);
+            if options.sandboxed {
+                command.env_clear();
+            }
+            if let Some(seed) = options.seed {
+                command.env("SCULPT_SEED", seed.to_string());
+            }
+            command.env("SCULPT_CLOCK", options.clock.name());
+            command.env("SCULPT_FILES", options.files.name());
+            if let Some(fuel) = options.fuel {
+                command.env("SCULPT_FUEL", fuel.to_string());
+            }
+            if let Some(max_heap_bytes) = options.max_heap_bytes {
+                command.env("SCULPT_MAX_HEAP_BYTES", max_heap_bytes.to_string());
+            }
+            let mut child = command
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .spawn()?;
+            let mut child_in = child.stdin.take().unwrap();
+            // The program may exit without reading all of its input, so a failed write is fine.
+            let feeder = thread::spawn(move || child_in.write_all(&input));
+            let output = match options.time_limit {
+                Some(time_limit) => wait_with_time_limit(child, time_limit),
+                None => child.wait_with_output().map(|output| (output, false)),
+            };
+            let _ = feeder.join();
+            output
+        });
+        fs::remove_file(&executable)?;
+        let (output, timed_out) = output?;
+
+        std_out.write_all(&output.stdout)?;
+        std_err.write_all(&output.stderr)?;
+        if let (true, Some(time_limit)) = (timed_out, options.time_limit) {
+            return Err(io::Error::other(format!(
+                "ran out of time after {} ms",
+                time_limit.as_millis()
+            )));
+        }
+        match output.status.code() {
+            Some(0) => Ok(()),
+            Some(status) => Err(io::Error::other(Exit(status))),
+            None => Err(io::Error::other(format!(
+                "program exited with {}",
+                output.status
+            ))),
+        }
+    }
+
+    fn emit_object(&self, path: &Path) -> io::Result<()> {
+        write_object(self.codegen.module(), self.opt_level, path)
+    }
+}
+
+/// Like [`Child::wait_with_output`], but kills `child` if it's still running after `time_limit`.
+/// Returns whether it was killed along with what it wrote until then.
+fn wait_with_time_limit(mut child: Child, time_limit: Duration) -> io::Result<(Output, bool)> {
+    let deadline = Instant::now() + time_limit;
+    let stdout = child.stdout.take().map(drain);
+    let stderr = child.stderr.take().map(drain);
+    let (status, timed_out) = loop {
+        if let Some(status) = child.try_wait()? {
+            break (status, false);
+        }
+        if Instant::now() >= deadline {
+            child.kill()?;
+            break (child.wait()?, true);
+        }
+        thread::sleep(Duration::from_millis(10));
+    };
+    let join = |pipe: Option<JoinHandle<io::Result<Vec<u8>>>>| match pipe {
+        Some(pipe) => pipe
+            .join()
+            .unwrap_or_else(|_| Err(io::Error::other("reading the program's output panicked"))),
+        None => Ok(Vec::new()),
+    };
+    let output = Output {
+        status,
+        stdout: join(stdout)?,
+        stderr: join(stderr)?,
+    };
+    Ok((output, timed_out))
+}
+
+/// Reads `pipe` to the end on a thread of its own.
+fn drain(mut pipe: impl Read + Send + 'static) -> JoinHandle<io::Result<Vec<u8>>> {
+    thread::spawn(move || {
+        let mut bytes = Vec::new();
+        pipe.read_to_end(&mut bytes)?;
+        Ok(bytes)
+    })
+}
+
+/// A path no other run in this process uses.
+fn temp_executable() -> PathBuf {
+    static RUNS: AtomicUsize = AtomicUsize::new(0);
+    let run = RUNS.fetch_add(1, Ordering::Relaxed);
+    env::temp_dir().join(format!("sculpt-{}-{}", std::process::id(), run))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::run::parse;
+    use proptest::prelude::*;
+    use proptest::sample::Index;
+
+    fn run(src: &str, input: &str) -> (String, String) {
+        let context = Context::create();
+        let profiler = Profiler::default();
+        let mut aot = Aot::new(&context, OptimizationLevel::None, &profiler);
+        aot.lower_module(hir::lower(parse(src).unwrap()).unwrap());
+        let mut output = Vec::new();
+        let mut errors = Vec::new();
+        aot.run(
+            &mut input.as_bytes(),
+            &mut output,
+            &mut errors,
+            &RunOptions::default(),
+        )
+        .unwrap();
+        (
+            String::from_utf8(output).unwrap(),
+            String::from_utf8(errors).unwrap(),
+        )
+    }
+
+    #[test]
+    fn executable_writes_to_stdout_and_stderr() {
+        assert_eq!(
+            run(
+                r#"fn main() { println!("Hello {}!", "world"); eprint!("oops"); }"#,
+                ""
+            ),
+            ("Hello world!\n".into(), "oops".into())
+        );
+    }
+
+    #[test]
+    fn executable_reads_stdin() {
+        let src = r#"
+            fn main() {
+                println!("[{}]", read_line!());
+                println!("[{}]", read_line!());
+                print!("[{}]", read_to_string!());
+            }
+        "#;
+        assert_eq!(
+            run(src, "one\r\ntwo\nthree\nfour").0,
+            "[one]\n[two]\n[three\nfour]"
+        );
+    }
+
+    #[test]
+    fn executable_formats_integers() {
+        assert_eq!(
+            run(
+                r#"fn main() { println!("{} {}", -9223372036854775807 - 1, 40 + 2); }"#,
+                ""
+            )
+            .0,
+            "-9223372036854775808 42\n"
+        );
+    }
+
+    #[test]
+    fn executable_formats_nested_macros() {
+        let src = r#"
+            fn main() {
+                println!("{}", format!("{} {}!", read_line!(), format!("{}", 6 * 7)));
+            }
+        "#;
+        assert_eq!(run(src, "hello\n").0, "hello 42!\n");
+    }
+
+    #[test]
+    fn executable_reads_the_environment() {
+        assert_eq!(
+            run(
+                r#"fn main() { print!("{} {:?}", getenv!("CARGO_PKG_NAME").unwrap(), getenv!("SCULPT_UNSET")); }"#,
+                ""
+            )
+            .0,
+            "sculpt None"
+        );
+    }
+
+    #[test]
+    fn executable_matches_options() {
+        let src = r#"
+            fn main() {
+                print!("{} ", match Some(read_line!()) { Some(line) => line, None => "" });
+                print!("{}", match Some(Some(5)) { Some(Some(x)) => x, Some(None) => 1, None => 2 });
+            }
+        "#;
+        assert_eq!(run(src, "a\n").0, "a 5");
+    }
+
+    #[test]
+    fn executable_reports_errors_from_main() {
+        let src = r#"fn main() -> Result<(), i64> { print!("a"); exit!(Err(7)?); }"#;
+        let context = Context::create();
+        let profiler = Profiler::default();
+        let mut aot = Aot::new(&context, OptimizationLevel::None, &profiler);
+        aot.lower_module(hir::lower(parse(src).unwrap()).unwrap());
+        let mut output = Vec::new();
+        let mut errors = Vec::new();
+        let error = aot
+            .run(
+                &mut io::empty(),
+                &mut output,
+                &mut errors,
+                &RunOptions::default(),
+            )
+            .unwrap_err();
+        assert_eq!(error.to_string(), "exited with status 1");
+        assert_eq!((output, errors), (b"a".to_vec(), b"Error: 7\n".to_vec()));
+    }
+
+    #[test]
+    fn functions_named_like_libc_do_not_collide() {
+        let src = r#"
+            fn write() { print!("mine"); }
+            fn main() { print!("ok"); }
+        "#;
+        assert_eq!(run(src, "").0, "ok");
+    }
+
+    #[test]
+    fn executable_calls_methods() {
+        let src = r#"
+            struct Greeting(str);
+            impl Greeting {
+                fn to(&self, name: str) -> str { format!("{}, {}!", self.0, name) }
+            }
+            fn main() { print!("{}", Greeting("Hello").to(read_line!())); }
+        "#;
+        assert_eq!(run(src, "world\n").0, "Hello, world!");
+    }
+
+    #[test]
+    fn executable_calls_each_instantiation() {
+        let src = r#"
+            fn id<T>(x: T) -> T { x }
+            fn main() { print!("{} {}", id(1), id(read_line!())); }
+        "#;
+        assert_eq!(run(src, "two\n").0, "1 two");
+    }
+
+    #[test]
+    fn library_exports_pub_functions_to_c() {
+        let src = r#"
+            pub fn greet() { println!("hello {}!", read_line!()); }
+            pub fn greet_again(name: &str, loud: bool) {
+                let greeting = match loud { true => "HELLO", false => "hello" };
+                println!("{} {}!", greeting, name);
+            }
+            pub fn add(a: i64, b: i64) -> i64 { a + b }
+            pub fn negate(b: bool) -> bool { match b { true => false, false => true } }
+            fn main() { print!("not exported"); }
+        "#;
+        let context = Context::create();
+        let profiler = Profiler::default();
+        let mut aot = Aot::new(&context, OptimizationLevel::None, &profiler);
+        aot.lower_module(hir::lower(parse(src).unwrap()).unwrap());
+
+        let dir = temp_executable();
+        fs::create_dir(&dir).unwrap();
+        aot.archive("greeter", &dir.join("libgreeter.a")).unwrap();
+        fs::write(dir.join("greeter.h"), aot.header("greeter")).unwrap();
+        fs::write(
+            dir.join("main.c"),
+            r#"#include <stdio.h>
+#include "greeter.h"
+int main(void) {
+    greeter_greet();
+    greeter_greet_again("again", 5, true);
+    printf("%lld %d\n", (long long)greeter_add(2, 40), greeter_negate(true));
+    return sculpt_rt_status;
+}
+"#,
+        )
+        .unwrap();
+        let status = Command::new("cc")
+            .current_dir(&dir)
+            .args(["main.c", "libgreeter.a", "-o", "main"])
+            .status()
+            .unwrap();
+        assert!(status.success());
+        let mut child = Command::new(dir.join("main"))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child.stdin.take().unwrap().write_all(b"world\n").unwrap();
+        let output = child.wait_with_output().unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(output.status.success());
+        assert_eq!(output.stdout, b"hello world!\nHELLO again!\n42 0\n");
+    }
+
+    #[test]
+    fn header_declares_c_equivalents_of_exported_signatures() {
+        let src = r#"
+            pub fn greet(name: &str) { println!("hello {}!", name); }
+            pub fn add(a: i64, b: i64) -> i64 { a + b }
+            pub fn check() -> Result<i64, str> { Ok(1) }
+        "#;
+        let context = Context::create();
+        let profiler = Profiler::default();
+        let mut aot = Aot::new(&context, OptimizationLevel::None, &profiler);
+        aot.lower_module(hir::lower(parse(src).unwrap()).unwrap());
+        let header = aot.header("lib");
+        let declarations: Vec<_> = header
+            .lines()
+            .filter(|line| line.starts_with("void lib_") || line.starts_with("int64_t lib_"))
+            .collect();
+        assert_eq!(
+            declarations,
+            [
+                "void lib_greet(const char *, int64_t);",
+                "int64_t lib_add(int64_t, int64_t);",
+                "void lib_check(void);",
+            ]
+        );
+    }
+
+    #[test]
+    fn deterministic_libraries_are_the_same_wherever_they_are_written() {
+        let src = "pub fn greet() { println!(\"hello\"); }\nfn main() {}";
+        let dir = temp_executable();
+        fs::create_dir(&dir).unwrap();
+        let archives: Vec<_> = ["liba.a", "libb.a"]
+            .into_iter()
+            .map(|name| {
+                let context = Context::create();
+                let profiler = Profiler::default();
+                let mut aot =
+                    Aot::new(&context, OptimizationLevel::None, &profiler).deterministic(true);
+                aot.lower_module(hir::lower(parse(src).unwrap()).unwrap());
+                aot.archive("greeter", &dir.join(name)).unwrap();
+                fs::read(dir.join(name)).unwrap()
+            })
+            .collect();
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(archives[0] == archives[1]);
+    }
+
+    /// A small program of integer arithmetic, locals, loops and calls, which every backend should
+    /// print the same thing for. Each function can call the ones before it, and `main` all of them.
+    #[derive(Clone, Debug)]
+    struct Program {
+        functions: Vec<Function>,
+        main: Vec<Statement>,
+    }
+
+    /// A function taking `a` and `b`.
+    #[derive(Clone, Debug)]
+    struct Function {
+        body: Vec<Statement>,
+        tail: Expr,
+    }
+
+    #[derive(Clone, Debug)]
+    enum Statement {
+        Let(Expr),
+        /// Prints the expression for each number below the bound, which it can read.
+        For(u8, Expr),
+        Print(Expr),
+    }
+
+    /// An expression whose value stays well inside `i64`, as every operation is kept below 1000 by
+    /// a remainder. Locals and functions are picked from those in scope when it's rendered.
+    #[derive(Clone, Debug)]
+    enum Expr {
+        Local(Index),
+        Int(i64),
+        Call(Index, Box<Expr>, Box<Expr>),
+        Neg(Box<Expr>),
+        Binary(Box<Expr>, &'static str, Box<Expr>, i64),
+    }
+
+    fn program() -> impl Strategy<Value = Program> {
+        let function = (statements(), expr()).prop_map(|(body, tail)| Function { body, tail });
+        (prop::collection::vec(function, 1..=4), statements())
+            .prop_map(|(functions, main)| Program { functions, main })
+    }
+
+    fn statements() -> impl Strategy<Value = Vec<Statement>> {
+        let statement = prop_oneof![
+            expr().prop_map(Statement::Let),
+            (0..4u8, expr()).prop_map(|(bound, body)| Statement::For(bound, body)),
+            expr().prop_map(Statement::Print),
+        ];
+        prop::collection::vec(statement, 1..=5)
+    }
+
+    fn expr() -> impl Strategy<Value = Expr> {
+        let leaf = prop_oneof![
+            any::<Index>().prop_map(Expr::Local),
+            (0..100i64).prop_map(Expr::Int),
+        ];
+        leaf.prop_recursive(3, 16, 2, |inner| {
+            prop_oneof![
+                (any::<Index>(), inner.clone(), inner.clone()).prop_map(|(f, a, b)| Expr::Call(
+                    f,
+                    Box::new(a),
+                    Box::new(b)
+                )),
+                inner.clone().prop_map(|value| Expr::Neg(Box::new(value))),
+                (
+                    inner.clone(),
+                    prop::sample::select(vec!["+", "-", "*"]),
+                    inner,
+                    991..=1000i64
+                )
+                    .prop_map(|(lhs, op, rhs, modulus)| {
+                        Expr::Binary(Box::new(lhs), op, Box::new(rhs), modulus)
+                    }),
+            ]
+        })
+    }
+
+    impl Program {
+        fn render(&self) -> String {
+            let mut names = 0;
+            let mut program = String::new();
+            for (i, function) in self.functions.iter().enumerate() {
+                let mut locals = vec!["a".to_string(), "b".to_string()];
+                let body = render_statements(&function.body, &mut locals, i, &mut names);
+                program.push_str(&format!(
+                    "fn f{}(a: i64, b: i64) -> i64 {{\n{}    {}\n}}\n",
+                    i,
+                    body,
+                    function.tail.render(&locals, i)
+                ));
+            }
+            let functions = self.functions.len();
+            let body = render_statements(&self.main, &mut Vec::new(), functions, &mut names);
+            program.push_str(&format!("fn main() {{\n{}}}\n", body));
+            program
+        }
+    }
+
+    /// Renders `statements`, which can read `locals` and call the first `functions` functions,
+    /// adding the locals they bind. `names` counts the names taken so far.
+    fn render_statements(
+        statements: &[Statement],
+        locals: &mut Vec<String>,
+        functions: usize,
+        names: &mut usize,
+    ) -> String {
+        let mut rendered = String::new();
+        for statement in statements {
+            *names += 1;
+            let statement = match statement {
+                Statement::Let(value) => {
+                    let value = value.render(locals, functions);
+                    locals.push(format!("x{}", names));
+                    format!("let x{} = {};", names, value)
+                }
+                Statement::For(bound, body) => {
+                    let mut scope = locals.clone();
+                    scope.push(format!("i{}", names));
+                    format!(
+                        "for i{} in 0..{} {{ println!(\"{{}}\", {}); }}",
+                        names,
+                        bound,
+                        body.render(&scope, functions)
+                    )
+                }
+                Statement::Print(value) => {
+                    format!("println!(\"{{}}\", {});", value.render(locals, functions))
+                }
+            };
+            rendered.push_str(&format!("    {}\n", statement));
+        }
+        rendered
+    }
+
+    impl Expr {
+        /// Renders the expression, reading `locals` and calling the first `functions` functions.
+        /// Without any, locals are replaced by `0` and calls by a sum.
+        fn render(&self, locals: &[String], functions: usize) -> String {
+            match self {
+                Expr::Local(_) if locals.is_empty() => "0".to_string(),
+                Expr::Local(local) => locals[local.index(locals.len())].clone(),
+                Expr::Int(value) => value.to_string(),
+                Expr::Call(_, a, b) if functions == 0 => format!(
+                    "(({} + {}) % 1000)",
+                    a.render(locals, functions),
+                    b.render(locals, functions)
+                ),
+                Expr::Call(function, a, b) => format!(
+                    "f{}({}, {})",
+                    function.index(functions),
+                    a.render(locals, functions),
+                    b.render(locals, functions)
+                ),
+                Expr::Neg(value) => format!("-{}", value.render(locals, functions)),
+                Expr::Binary(lhs, op, rhs, modulus) => format!(
+                    "(({} {} {}) % {})",
+                    lhs.render(locals, functions),
+                    op,
+                    rhs.render(locals, functions),
+                    modulus
+                ),
+            }
+        }
+    }
+
+    proptest! {
+        // Each case builds and links an executable, so there are fewer than the default 256.
+        #![proptest_config(ProptestConfig::with_cases(32))]
+
+        #[test]
+        fn executables_print_what_the_jit_does(program in program()) {
+            let src = program.render();
+            let mut jit_output = Vec::new();
+            crate::run::run(&src, &mut jit_output).unwrap();
+            prop_assert_eq!(run(&src, "").0, String::from_utf8(jit_output).unwrap(), "{}", src);
+        }
+    }
+
+    #[test]
+    fn crate_names_are_c_identifiers() {
+        assert_eq!(crate_name(Path::new("dir/my-lib.sculpt")), "my_lib");
+        assert_eq!(crate_name(Path::new("2d.sculpt")), "_2d");
+    }
+}
@@ -0,0 +1,145 @@
+use std::error;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::ops::Range;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::hir;
+
+/// A way of turning lowered programs into something that runs. Everything before the backend
+/// (parsing, lowering to HIR, error reporting) is shared, so a new backend only needs to
+/// implement these.
+pub trait Backend {
+    /// Lowers `program` into the backend's module.
+    fn lower_module(&mut self, program: hir::Program);
+
+    /// Runs the module's `main` with `std_in`, `std_out` and `std_err` as its standard streams.
+    /// Returns the I/O error that aborted the program, if reading or writing a stream failed, or a
+    /// [`Panic`] if the program panicked.
+    fn run(
+        &mut self,
+        std_in: &mut dyn BufRead,
+        std_out: &mut dyn Write,
+        std_err: &mut dyn Write,
+        options: &RunOptions,
+    ) -> io::Result<()>;
+
+    /// Writes the module to `path` as a native object file.
+    fn emit_object(&self, path: &Path) -> io::Result<()>;
+}
+
+/// How a program reaches the host beyond its standard streams.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RunOptions {
+    /// Cuts the program off from the host: `getenv!` sees an empty environment.
+    pub sandboxed: bool,
+    /// Seeds `random_int!`, so runs with the same seed draw the same integers. Unseeded runs draw
+    /// different ones each time.
+    pub seed: Option<u64>,
+    /// What `now_millis!` and `sleep_millis!` measure.
+    pub clock: Clock,
+    /// What `read_file!` and `write_file!` read and write.
+    pub files: Files,
+    /// How many steps, loop iterations and calls, the program may take before it's aborted.
+    pub fuel: Option<u64>,
+    /// How many bytes the program may have allocated at once before it's aborted.
+    pub max_heap_bytes: Option<usize>,
+    /// How long the program may run before it's aborted.
+    pub time_limit: Option<Duration>,
+    /// The function the program starts in, if not `main`.
+    pub entry: Option<String>,
+}
+
+impl RunOptions {
+    /// The name of the function the program starts in.
+    pub fn entry(&self) -> &str {
+        self.entry.as_deref().unwrap_or("main")
+    }
+}
+
+/// The clock a program sees.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Clock {
+    /// The host's: milliseconds since the Unix epoch, and sleeps block.
+    #[default]
+    Real,
+    /// Starts at 0 and only moves when the program sleeps, which returns immediately. Runs see
+    /// the same times however loaded the host is.
+    Virtual,
+    /// Reading the clock or sleeping panics.
+    Denied,
+}
+
+impl Clock {
+    /// Name of the clock in the `SCULPT_CLOCK` environment variable sculpt-rt reads.
+    pub fn name(self) -> &'static str {
+        match self {
+            Clock::Real => "real",
+            Clock::Virtual => "virtual",
+            Clock::Denied => "denied",
+        }
+    }
+}
+
+/// The file system a program sees.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Files {
+    /// The host's, with paths relative to the working directory.
+    #[default]
+    Real,
+    /// Starts empty and lives in memory for one run, so the program only reads what it wrote.
+    Virtual,
+    /// Reading or writing a file panics.
+    Denied,
+}
+
+impl Files {
+    /// Name of the file system in the `SCULPT_FILES` environment variable sculpt-rt reads.
+    pub fn name(self) -> &'static str {
+        match self {
+            Files::Real => "real",
+            Files::Virtual => "virtual",
+            Files::Denied => "denied",
+        }
+    }
+}
+
+/// Error a backend's `run` returns, wrapped in an [`io::Error`], when the program panicked, e.g. by
+/// dividing by zero.
+#[derive(Debug)]
+pub struct Panic(pub String);
+
+impl fmt::Display for Panic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "panicked: {}", self.0)
+    }
+}
+
+impl error::Error for Panic {}
+
+/// Like [`Panic`], for panics the program reports the source location of, like a failed
+/// `unwrap()`. Holds the span and the message.
+#[derive(Debug)]
+pub struct PanicAt(pub Range<usize>, pub String);
+
+impl fmt::Display for PanicAt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "panicked at byte {}: {}", self.0.start, self.1)
+    }
+}
+
+impl error::Error for PanicAt {}
+
+/// Error a backend's `run` returns, wrapped in an [`io::Error`], when the program called `exit!`,
+/// or an executable exited with a status other than 0. Holds the status.
+#[derive(Debug)]
+pub struct Exit(pub i32);
+
+impl fmt::Display for Exit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "exited with status {}", self.0)
+    }
+}
+
+impl error::Error for Exit {}
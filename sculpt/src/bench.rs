@@ -0,0 +1,144 @@
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+pub struct BenchOptions {
+    /// Calls made before any timing starts.
+    pub warmup: u32,
+    /// Number of timed samples the statistics are computed from.
+    pub samples: u32,
+    /// Calls averaged into each sample, so very short functions aren't dominated by timer overhead.
+    pub iterations: u32,
+}
+
+impl Default for BenchOptions {
+    fn default() -> Self {
+        Self {
+            warmup: 100,
+            samples: 30,
+            iterations: 100,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Stats {
+    pub mean: Duration,
+    pub median: Duration,
+    pub std_dev: Duration,
+}
+
+impl Stats {
+    pub fn from_samples(samples: &[Duration]) -> Self {
+        let mut sorted = samples.to_vec();
+        sorted.sort();
+        let n = sorted.len();
+        let median = match n {
+            0 => Duration::ZERO,
+            _ if n.is_multiple_of(2) => (sorted[n / 2 - 1] + sorted[n / 2]) / 2,
+            _ => sorted[n / 2],
+        };
+        let mean = match n {
+            0 => 0.0,
+            _ => sorted.iter().map(Duration::as_secs_f64).sum::<f64>() / n as f64,
+        };
+        let variance = match n {
+            0 | 1 => 0.0,
+            _ => {
+                sorted
+                    .iter()
+                    .map(|sample| (sample.as_secs_f64() - mean).powi(2))
+                    .sum::<f64>()
+                    / (n - 1) as f64
+            }
+        };
+        Self {
+            mean: Duration::from_secs_f64(mean),
+            median,
+            std_dev: Duration::from_secs_f64(variance.sqrt()),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct BenchResult {
+    pub name: String,
+    pub stats: Stats,
+}
+
+/// Times `f`, stopping at the first run that fails.
+pub fn measure<E>(f: impl Fn() -> Result<(), E>, options: &BenchOptions) -> Result<Stats, E> {
+    for _ in 0..options.warmup {
+        f()?;
+    }
+    let iterations = options.iterations.max(1);
+    let samples = (0..options.samples)
+        .map(|_| {
+            let start = Instant::now();
+            for _ in 0..iterations {
+                f()?;
+            }
+            Ok(start.elapsed() / iterations)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(Stats::from_samples(&samples))
+}
+
+pub fn write_results(results: &[BenchResult], mut writer: impl Write) -> io::Result<()> {
+    let width = results
+        .iter()
+        .map(|result| result.name.len())
+        .chain(["bench".len()])
+        .max()
+        .unwrap();
+    writeln!(
+        writer,
+        "{:<width$} {:>12} {:>12} {:>12}",
+        "bench", "mean", "median", "std dev"
+    )?;
+    for BenchResult { name, stats } in results {
+        writeln!(
+            writer,
+            "{:<width$} {:>12} {:>12} {:>12}",
+            name,
+            format!("{:.3?}", stats.mean),
+            format!("{:.3?}", stats.median),
+            format!("{:.3?}", stats.std_dev),
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn stats_of_odd_sample_count() {
+        let stats = Stats::from_samples(&[ms(3), ms(1), ms(2)]);
+        assert_eq!(stats.median, ms(2));
+        assert_eq!(stats.mean, ms(2));
+        assert_eq!(stats.std_dev, ms(1));
+    }
+
+    #[test]
+    fn median_of_even_sample_count_is_midpoint() {
+        let stats = Stats::from_samples(&[ms(4), ms(1), ms(2), ms(10)]);
+        assert_eq!(stats.median, ms(3));
+    }
+
+    #[test]
+    fn stats_of_no_samples_are_zero() {
+        assert_eq!(
+            Stats::from_samples(&[]),
+            Stats {
+                mean: Duration::ZERO,
+                median: Duration::ZERO,
+                std_dev: Duration::ZERO,
+            }
+        );
+    }
+}
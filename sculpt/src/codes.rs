@@ -0,0 +1,326 @@
+//! The codes diagnostics are reported with, like `[UnknownMacro]`. Their names are stable across
+//! versions, so editor plugins and CI filters can match on them; new ones may be added.
+
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+/// Declares [`DiagnosticCode`], whose variants' docs are what `sculpt explain` prints for them.
+macro_rules! codes {
+    ($($(#[doc = $doc:literal])+ $code:ident,)*) => {
+        /// What kind of problem a diagnostic reports.
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+        #[non_exhaustive]
+        pub enum DiagnosticCode {
+            $($(#[doc = $doc])+ $code,)*
+        }
+
+        impl DiagnosticCode {
+            /// Every code, in the order they're listed by `sculpt explain`.
+            pub const ALL: &'static [DiagnosticCode] = &[$(DiagnosticCode::$code),*];
+
+            /// The name diagnostics are reported with, like `UnknownMacro`.
+            pub fn name(self) -> &'static str {
+                match self {
+                    $(DiagnosticCode::$code => stringify!($code),)*
+                }
+            }
+
+            /// What the code means and how to fix what it reports, in paragraphs.
+            pub fn explanation(self) -> String {
+                let doc = match self {
+                    $(DiagnosticCode::$code => concat!($($doc, "\n"),+),)*
+                };
+                doc.lines()
+                    .map(|line| line.strip_prefix(' ').unwrap_or(line))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            }
+        }
+    };
+}
+
+codes! {
+    /// A keyword was used as a name, like `fn let() {}`. Keywords can't name functions,
+    /// variables or anything else; rename it, like to `let_`.
+    KeywordAsName,
+    /// A `macro_rules!` parameter has a fragment specifier other than `expr`, like `$x:ident`.
+    /// Only expressions can be passed to macros.
+    UnknownFragment,
+    /// A macro rule uses a `$param` that isn't one of its parameters.
+    UnboundMetaVar,
+    /// A macro defined with `macro_rules!` was invoked with a number of arguments none of its
+    /// rules take.
+    NoMatchingMacroRule,
+    /// Expanding a macro invoked more macros, which invoked more in turn, too deep to finish,
+    /// usually because a macro invokes itself forever.
+    MacroRecursionLimit,
+    /// A macro plugin loaded with `--plugin` failed to expand one of its macros.
+    PluginFailed,
+    /// A builtin macro like `include_str!` or `env!` was given something other than a string
+    /// literal.
+    ExpectedStrLit,
+    /// The file an `include_str!` names couldn't be read. Its path is relative to the file
+    /// including it.
+    IncludeFailed,
+    /// The environment variable an `env!` names wasn't set when compiling.
+    EnvVarUnset,
+    /// A `{` or `}` in a format string neither opens nor closes a `{}`. Write a literal brace
+    /// twice, like `{{`.
+    UnmatchedFmtBrace,
+    /// A formatting macro like `println!` was given arguments but no format string, which
+    /// comes first.
+    MissingFmtStr,
+    /// A formatting macro was given more arguments than its format string has `{}` for. Each
+    /// argument needs a `{}` or `{:?}` to be formatted with.
+    ExtraFmtArguments,
+    /// A formatting macro was given fewer arguments than its format string has `{}` for. Each
+    /// `{}` takes the next argument in order.
+    NotEnoughFmtArguments,
+    /// A macro was invoked that isn't builtin, defined with `macro_rules!` or registered by a
+    /// plugin.
+    UnknownMacro,
+    /// The format string of a formatting macro isn't a string literal. It has to be written out
+    /// to be checked when compiling, so format a string variable with `"{}"`.
+    FmtStrNotLiteral,
+    /// A builtin macro was given more arguments than it takes.
+    ExtraMacroArguments,
+    /// A builtin macro was given fewer arguments than it takes.
+    MissingMacroArguments,
+    /// A macro that doesn't produce a value, like `println!`, was used as one.
+    NotAnExpression,
+    /// A name was used that no variable, function or static in scope has.
+    UnresolvedName,
+    /// An integer literal doesn't fit in an `i64`.
+    IntLiteralTooLarge,
+    /// An operand of an arithmetic operator isn't an integer.
+    NonIntegerOperand,
+    /// An argument of a builtin isn't of the type it takes.
+    MismatchedArgumentType,
+    /// Something like `Some` was called with the wrong number of arguments.
+    MismatchedArgumentCount,
+    /// A method was called that the receiver's type doesn't have.
+    NoMethod,
+    /// The arms of a `match` produce values of different types. Every arm has to produce the
+    /// same type.
+    MismatchedArmTypes,
+    /// A pattern can't match values of the type being matched on.
+    MismatchedPatternType,
+    /// A `match` doesn't cover every value of what it matches on. Add an arm for the value
+    /// reported, or a `_` arm for everything else.
+    NonExhaustiveMatch,
+    /// A value formatted with `{}` isn't a string or integer, and its type doesn't implement
+    /// `Display`. Format it with `{:?}`, match on it to format what it holds, or, for a struct,
+    /// implement `Display` with a `fn fmt(&self) -> str` returning what to format it as.
+    NotDisplayable,
+    /// A value formatted with `{:?}`, like a closure, can't be formatted with it.
+    NotDebuggable,
+    /// A value is formatted with a spec that can't format its type, like a string with `{:x}`.
+    /// `{}` formats strings, integers, `bool`s and types implementing `Display`; `{:?}` formats
+    /// values built from builtin types and tuple structs; and `{:x}`, `{:X}`, `{:o}` and `{:b}`
+    /// format integers, in hexadecimal, octal and binary.
+    MismatchedFmtType,
+    /// Nothing pins down the type of an expression that needs one, like the `x` in
+    /// `match None { Some(x) => format!("{}", x), _ => ... }`.
+    TypeAnnotationsNeeded,
+    /// A type was named that doesn't exist.
+    UnknownType,
+    /// `main` returns something other than nothing, `Result<(), str>` or `Result<(), i64>`.
+    UnsupportedReturnType,
+    /// A function returns a value of a type other than the one it's declared to.
+    MismatchedReturnType,
+    /// A function the host calls, like `main` or a `#[test]`, takes parameters or type
+    /// parameters. It's called with no arguments.
+    EntryPointParameters,
+    /// The function a program starts in, `main` unless another is named with `--entry`, isn't
+    /// defined in the file being run.
+    MissingEntryPoint,
+    /// A `pub fn` in a file built as a library takes or returns a value C has no equivalent of.
+    /// Exported functions take `i64`, `bool` and `&str` parameters, and return `i64`, `bool` or
+    /// nothing; one taking no parameters may return anything `main` can. Remove the `pub` to keep
+    /// it out of the library.
+    UnexportableFunction,
+    /// An attribute was used that isn't `test`, `bench`, `allow`, `warn` or `deny`.
+    UnknownAttribute,
+    /// An attribute that only applies to functions, like `test`, was put at the top of a file,
+    /// like `#![test]`.
+    MisplacedAttribute,
+    /// `allow`, `warn` or `deny` named a lint that doesn't exist.
+    UnknownLint,
+    /// An attribute was given arguments it doesn't take, or not given ones it needs.
+    MalformedAttribute,
+    /// `self` was declared somewhere other than as the first parameter of a function in an
+    /// `impl`.
+    UnexpectedSelf,
+    /// A tuple struct holds a value of its own type, so it would be infinitely large. Hold it in
+    /// a `Box` instead.
+    RecursiveType,
+    /// A name was defined twice in the same scope, like two functions `f`, two methods `f` in
+    /// `impl`s for the same type, or two parameters `x` of one function. Uses of it couldn't tell
+    /// which was meant; remove or rename one of them.
+    DuplicateDefinition,
+    /// A `static mut` was declared. Values can't change, so it could never be written to.
+    StaticMut,
+    /// The value of a static uses something other than literals, arithmetic and other statics,
+    /// so it can't be worked out when compiling.
+    NonConstStatic,
+    /// The value of a static depends on itself, through the statics it uses.
+    CyclicStatic,
+    /// The value of a static isn't of the type it's declared with.
+    MismatchedStaticType,
+    /// Working out the value of a static failed, like by dividing by zero.
+    StaticEvalFailed,
+    /// An `impl` was written for a builtin type, like `i64`. Define a tuple struct holding it,
+    /// and implement methods for that.
+    ImplOnBuiltinType,
+    /// A field was accessed that the value's type doesn't have.
+    NoField,
+    /// A trait was named that doesn't exist.
+    UnknownTrait,
+    /// An `impl` of a trait doesn't define some of the trait's methods.
+    MissingTraitMethods,
+    /// A function in an `impl` of a trait isn't one of the trait's methods. Define it in an
+    /// `impl` of its own.
+    NotATraitMethod,
+    /// A method in an `impl` of a trait has a different signature than the trait's.
+    MismatchedTraitMethod,
+    /// A generic function was called with a type that doesn't implement a trait its type
+    /// parameter is bound by.
+    UnsatisfiedBound,
+    /// Something other than a function or closure was called.
+    NotCallable,
+    /// Something other than a reference was dereferenced with `*`.
+    NotAReference,
+    /// Something other than a string was sliced.
+    NotSliceable,
+    /// A bound of a slice or range isn't an integer.
+    NonIntegerBound,
+    /// A `for` loop is over something it can't iterate over. Loop over a range, a `Vec`, a
+    /// reference to one, or an iterator.
+    NotIterable,
+    /// The pattern of a `for` loop doesn't match every item. Use a `match` in the loop's body
+    /// to skip the items it doesn't cover.
+    RefutableForPattern,
+    /// The pattern of a `let` doesn't match every value of its type. Use a `match` to handle
+    /// the values it doesn't cover.
+    RefutableLetPattern,
+    /// The pattern of an `if let` matches every value of its type, like `if let x = 1 { ... }`,
+    /// so its `else` could never run. Bind the value with a `let` instead.
+    IrrefutableIfLetPattern,
+    /// The pattern of a `while let` matches every value of its type, so the loop could never
+    /// end. Match something that stops matching, like `Some(x)`.
+    IrrefutableWhileLetPattern,
+    /// The block of an `if let` without an `else` evaluates to something other than `()`, but
+    /// the `if let` would evaluate to `()` if the pattern didn't match. Add an `else` evaluating
+    /// to the same type.
+    MissingElse,
+    /// The value of a `let` isn't of the type it's annotated with.
+    MismatchedLetType,
+    /// Part of the type of a `let`'s value is never pinned down, like in `let x = None;`.
+    /// Annotate it, like `let x: Option<i64> = None;`.
+    AmbiguousType,
+    /// The body of a `for` or `while let` loop moves out of a variable declared before the loop,
+    /// so the next iteration would find it moved. Borrow it instead.
+    MovedInLoop,
+    /// A `HashMap` was given keys of a type other than strings, integers and `bool`s.
+    UnhashableKey,
+    /// A `Vec` was sorted by values of a type other than strings, integers and `bool`s. Sort it
+    /// by a key of one of those with `sort_by_key`.
+    Unsortable,
+    /// A variable was used after its value was moved out of it. Borrow it instead of moving
+    /// it.
+    UseAfterMove,
+    /// A variable was moved out of while borrowed.
+    MoveWhileBorrowed,
+    /// A variable was borrowed while another borrow of it was alive, and one of them is
+    /// mutable. A mutable borrow can't be alive at the same time as any other.
+    ConflictingBorrow,
+    /// A function returns a reference to a variable it owns, which is dropped when it returns.
+    DanglingReference,
+    /// The type argument of a call to a generic function can't be worked out from its
+    /// arguments. Specify it, like `f::<i64>(...)`.
+    UnresolvedTypeParam,
+    /// The arguments of a call to a generic function disagree about what one of its type
+    /// parameters is.
+    AmbiguousTypeParam,
+    /// A call passed the wrong number of type arguments.
+    MismatchedTypeArgumentCount,
+    /// `?` was used in a function that doesn't return a `Result`. Declare it
+    /// `-> Result<(), E>`.
+    TryOutsideResult,
+    /// `?` was applied to something other than a `Result`.
+    TryNotResult,
+    /// `?` was applied to a `Result` whose error type isn't the one the function returns.
+    MismatchedErrorType,
+    /// The source has syntax after the end of what could be parsed.
+    ExtraToken,
+    /// The source has a character that can't start any token, or a malformed one, like a
+    /// format string spec other than `{}` and `{:?}`.
+    InvalidToken,
+    /// The source ended in the middle of something, like a missing closing `}`.
+    UnrecognizedEof,
+    /// The source has syntax where something else was expected, like a missing `;`.
+    UnrecognizedToken,
+    /// The compiler broke one of its own invariants while compiling the program. This is a bug in
+    /// sculpt, not in the program; please report it along with the program.
+    InternalCompilerError,
+    /// A running program was aborted because reading or writing one of its streams failed.
+    RuntimeIoError,
+    /// A running program panicked, like by unwrapping `None` or dividing by zero.
+    Panicked,
+    /// A running program called `exit!` with a status other than 0.
+    Exited,
+    /// A running program wrote more than the output limit, and was aborted.
+    OutputLimitExceeded,
+    /// A variable is never read. Prefix its name with an underscore if it's meant to go
+    /// unused, or `#[allow(unused_variables)]` on the function.
+    UnusedVariable,
+}
+
+impl Display for DiagnosticCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// A name that isn't one of a [`DiagnosticCode`].
+#[derive(Debug, PartialEq)]
+pub struct UnknownCode(pub String);
+
+impl Display for UnknownCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "unknown diagnostic code `{}`", self.0)
+    }
+}
+
+impl FromStr for DiagnosticCode {
+    type Err = UnknownCode;
+
+    /// The code named `name`, ignoring case and any brackets, like `[UnknownMacro]`.
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        let bare = name.trim_start_matches('[').trim_end_matches(']');
+        DiagnosticCode::ALL
+            .iter()
+            .copied()
+            .find(|code| code.name().eq_ignore_ascii_case(bare))
+            .ok_or_else(|| UnknownCode(name.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codes_are_found_by_name_and_explained_from_their_docs() {
+        assert_eq!("[unknownmacro]".parse(), Ok(DiagnosticCode::UnknownMacro));
+        assert_eq!(
+            "Nope".parse::<DiagnosticCode>(),
+            Err(UnknownCode("Nope".to_string()))
+        );
+        assert_eq!(
+            DiagnosticCode::UnmatchedFmtBrace.explanation(),
+            "A `{` or `}` in a format string neither opens nor closes a `{}`. Write a literal brace\n\
+             twice, like `{{`."
+        );
+    }
+}
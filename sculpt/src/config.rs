@@ -0,0 +1,74 @@
+//! Settings for a project, read from the `sculpt.toml` in its directory, like
+//!
+//! ```toml
+//! [fmt]
+//! indent_width = 2
+//! brace_style = "next_line"
+//! ```
+
+use serde::Deserialize;
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::fmt;
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub fmt: fmt::Config,
+}
+
+impl Config {
+    pub const FILE_NAME: &'static str = "sculpt.toml";
+
+    pub fn parse(text: &str) -> Result<Config, String> {
+        toml::from_str(text).map_err(|error| error.message().to_string())
+    }
+
+    /// Reads the config at `path`.
+    pub fn read(path: &Path) -> io::Result<Config> {
+        Config::parse(&fs::read_to_string(path)?).map_err(|message| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {}", path.display(), message),
+            )
+        })
+    }
+
+    /// Reads the `sculpt.toml` in the directory `file` is in, or the nearest one above it, or the
+    /// default config if there's none.
+    pub fn find(file: &Path) -> io::Result<Config> {
+        let dir = file.parent().unwrap_or(Path::new(""));
+        let absolute = fs::canonicalize(dir).unwrap_or_else(|_| dir.to_path_buf());
+        match absolute
+            .ancestors()
+            .map(|dir| dir.join(Config::FILE_NAME))
+            .find(|path| path.is_file())
+        {
+            Some(path) => Config::read(&path),
+            None => Ok(Config::default()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fmt::BraceStyle;
+
+    #[test]
+    fn unset_settings_are_defaults() {
+        let config = Config::parse("[fmt]\nindent_width = 2\nbrace_style = \"next_line\"").unwrap();
+        assert_eq!(
+            config.fmt,
+            fmt::Config {
+                indent_width: 2,
+                brace_style: BraceStyle::NextLine,
+                ..fmt::Config::default()
+            }
+        );
+        assert!(Config::parse("[fmt]\nindent = 2").is_err());
+    }
+}
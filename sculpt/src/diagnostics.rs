@@ -0,0 +1,168 @@
+use serde::Serialize;
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::sync::Mutex;
+
+use crate::run::Error;
+
+/// How many diagnostics with the same headline [`write_grouped`] writes in full.
+pub const SHOWN_REPEATS: usize = 3;
+
+/// A diagnostic rendered for reporting, and where it points, as `file:line:column`.
+pub struct Rendered {
+    pub location: String,
+    pub text: String,
+}
+
+/// Writes `diagnostics` to `writer` in order, except that only the first [`SHOWN_REPEATS`] with
+/// the same headline, their first line, like ``[UnknownMacro] Error: cannot find macro `log!` ``,
+/// are written in full. The rest are summed up after them, with how many there were and where.
+pub fn write_grouped(diagnostics: Vec<Rendered>, mut writer: impl Write) -> io::Result<()> {
+    let mut repeats: HashMap<String, Vec<String>> = HashMap::new();
+    let mut order = Vec::new();
+    for Rendered { location, text } in diagnostics {
+        let headline = text.lines().next().unwrap_or_default().to_string();
+        let seen = repeats.entry(headline.clone()).or_default();
+        if seen.is_empty() {
+            order.push(headline);
+        }
+        if seen.len() < SHOWN_REPEATS {
+            writer.write_all(text.as_bytes())?;
+        }
+        seen.push(location);
+    }
+    for headline in order {
+        let locations = &repeats[&headline];
+        if let Some(rest) = locations
+            .get(SHOWN_REPEATS..)
+            .filter(|rest| !rest.is_empty())
+        {
+            writeln!(
+                writer,
+                "{}\n  ... and {} more at {}",
+                headline,
+                rest.len(),
+                rest.join(", ")
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// `offset` into `source_code` as a 1-based `line:column`, counting columns in characters.
+pub fn line_column(source_code: &str, offset: usize) -> String {
+    let before = &source_code[..offset.min(source_code.len())];
+    let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+    format!(
+        "{}:{}",
+        before.matches('\n').count() + 1,
+        before[line_start..].chars().count() + 1
+    )
+}
+
+/// How many errors and warnings a session reported, for CI pipelines that gate on them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct Counts {
+    pub errors: usize,
+    pub warnings: usize,
+}
+
+impl Counts {
+    /// Writes a line like `2 errors, 1 warning emitted`.
+    pub fn write_line(&self, mut writer: impl Write) -> io::Result<()> {
+        let plural = |count| if count == 1 { "" } else { "s" };
+        writeln!(
+            writer,
+            "{} error{}, {} warning{} emitted",
+            self.errors,
+            plural(self.errors),
+            self.warnings,
+            plural(self.warnings)
+        )
+    }
+
+    /// Writes a line like `{"errors":2,"warnings":1}`.
+    pub fn write_json(&self, mut writer: impl Write) -> io::Result<()> {
+        serde_json::to_writer(&mut writer, self)?;
+        writeln!(writer)
+    }
+}
+
+/// Collects errors from files compiled concurrently and hands them back ordered by file and then
+/// by source location, regardless of which thread finished first.
+#[derive(Default)]
+pub struct Collector<'src> {
+    errors: Mutex<Vec<(usize, Error<'src>)>>,
+}
+
+impl<'src> Collector<'src> {
+    pub fn push(&self, file: usize, error: Error<'src>) {
+        self.errors.lock().unwrap().push((file, error));
+    }
+
+    pub fn into_sorted(self) -> Vec<(usize, Error<'src>)> {
+        let mut errors = self.errors.into_inner().unwrap();
+        errors.sort_by_key(|(file, error)| (*file, error.location()));
+        errors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn errors_are_sorted_by_file_then_location() {
+        let collector = Collector::default();
+        collector.push(1, Error::MissingFmtStr(3..4));
+        collector.push(0, Error::MissingFmtStr(7..8));
+        collector.push(1, Error::MissingFmtStr(1..2));
+        collector.push(0, Error::MissingFmtStr(5..6));
+        assert_eq!(
+            collector.into_sorted(),
+            [
+                (0, Error::MissingFmtStr(5..6)),
+                (0, Error::MissingFmtStr(7..8)),
+                (1, Error::MissingFmtStr(1..2)),
+                (1, Error::MissingFmtStr(3..4)),
+            ]
+        );
+    }
+
+    #[test]
+    fn repeated_diagnostics_are_summed_up() {
+        let rendered = |line: usize, headline: &str| Rendered {
+            location: format!("a.sculpt:{}:5", line),
+            text: format!("{}\n  at line {}\n", headline, line),
+        };
+        let mut diagnostics: Vec<_> = (1..=5).map(|line| rendered(line, "error: a")).collect();
+        diagnostics.insert(1, rendered(9, "error: b"));
+        let mut written = Vec::new();
+        write_grouped(diagnostics, &mut written).unwrap();
+        assert_eq!(
+            String::from_utf8(written).unwrap(),
+            "error: a\n  at line 1\n\
+             error: b\n  at line 9\n\
+             error: a\n  at line 2\n\
+             error: a\n  at line 3\n\
+             error: a\n  ... and 2 more at a.sculpt:4:5, a.sculpt:5:5\n"
+        );
+        assert_eq!(line_column("ab\ncé\nd", 6), "2:3");
+    }
+
+    #[test]
+    fn counts_are_summed_up_as_a_line_or_json() {
+        let counts = Counts {
+            errors: 1,
+            warnings: 2,
+        };
+        let mut written = Vec::new();
+        counts.write_line(&mut written).unwrap();
+        counts.write_json(&mut written).unwrap();
+        assert_eq!(
+            String::from_utf8(written).unwrap(),
+            "1 error, 2 warnings emitted\n{\"errors\":1,\"warnings\":2}\n"
+        );
+    }
+}
@@ -0,0 +1,309 @@
+//! Renders the `///` comments on a program's structs and functions into a reference page, which
+//! lists each item with its signature and links the items its docs name like [`Point`].
+
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use crate::syntax::{Doc, Function, Program};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Html,
+    Markdown,
+}
+
+/// A documented item, in the order it's listed on the page.
+struct Item<'a> {
+    /// The name docs link to it by, like `Point` or `Point::new`.
+    name: String,
+    /// Where links to it point on the page, like `struct.Point`.
+    anchor: String,
+    signature: &'a str,
+    docs: &'a [Doc],
+}
+
+/// Renders the docs of `program`, parsed from `source_code`, into a page titled `title`. Each
+/// struct is followed by the functions implemented on it, and free functions come last.
+pub fn render(title: &str, program: &Program, source_code: &str, format: Format) -> String {
+    let mut items = Vec::new();
+    for s in &program.structs {
+        items.push(Item {
+            name: s.name.name.as_str().to_string(),
+            anchor: format!("struct.{}", s.name.name.as_str()),
+            signature: source_code[s.span.clone()].trim_end_matches(';'),
+            docs: &s.docs,
+        });
+        for i in program.impls.iter().filter(|i| i.ty.name == s.name.name) {
+            for function in &i.functions {
+                let name = format!("{}::{}", s.name.name.as_str(), function.name.name.as_str());
+                items.push(Item {
+                    anchor: format!("fn.{}", name.replace("::", ".")),
+                    name,
+                    signature: signature(function, source_code),
+                    docs: &function.docs,
+                });
+            }
+        }
+    }
+    for function in &program.functions {
+        items.push(Item {
+            name: function.name.name.as_str().to_string(),
+            anchor: format!("fn.{}", function.name.name.as_str()),
+            signature: signature(function, source_code),
+            docs: &function.docs,
+        });
+    }
+    let anchors: HashMap<_, _> = items
+        .iter()
+        .map(|item| (item.name.as_str(), item.anchor.as_str()))
+        .collect();
+
+    let mut page = String::new();
+    match format {
+        Format::Html => {
+            writeln!(page, "<!DOCTYPE html>").unwrap();
+            writeln!(page, "<html>").unwrap();
+            writeln!(
+                page,
+                "<head><meta charset=\"utf-8\"><title>{}</title></head>",
+                escape(title)
+            )
+            .unwrap();
+            writeln!(page, "<body>").unwrap();
+            writeln!(page, "<h1>{}</h1>", escape(title)).unwrap();
+            for item in &items {
+                writeln!(page, "<section id=\"{}\">", item.anchor).unwrap();
+                writeln!(page, "<h2><code>{}</code></h2>", link_types(item, &anchors)).unwrap();
+                html_docs(&mut page, item.docs, &anchors);
+                writeln!(page, "</section>").unwrap();
+            }
+            writeln!(page, "</body>").unwrap();
+            writeln!(page, "</html>").unwrap();
+        }
+        Format::Markdown => {
+            writeln!(page, "# {}", title).unwrap();
+            for item in &items {
+                writeln!(page).unwrap();
+                writeln!(page, "<a id=\"{}\"></a>", item.anchor).unwrap();
+                writeln!(page, "## `{}`", item.name).unwrap();
+                writeln!(page).unwrap();
+                writeln!(page, "```sculpt\n{}\n```", item.signature).unwrap();
+                markdown_docs(&mut page, item.docs, &anchors);
+            }
+        }
+    }
+    page
+}
+
+/// The source of `function` from `fn`, or `pub fn`, up to its body.
+fn signature<'a>(function: &Function, source_code: &'a str) -> &'a str {
+    let start = function
+        .attrs
+        .last()
+        .map_or(function.span.start, |attr| attr.span.end);
+    let source = &source_code[start..function.span.end];
+    source[..source.find('{').unwrap_or(source.len())].trim()
+}
+
+fn markdown_docs(page: &mut String, docs: &[Doc], anchors: &HashMap<&str, &str>) {
+    if docs.is_empty() {
+        return;
+    }
+    writeln!(page).unwrap();
+    let mut in_code = false;
+    for doc in docs {
+        if let Some(info) = doc.text.strip_prefix("```") {
+            in_code = !in_code;
+            match in_code && info.trim().is_empty() {
+                true => writeln!(page, "```sculpt").unwrap(),
+                false => writeln!(page, "{}", doc.text).unwrap(),
+            }
+        } else if in_code {
+            writeln!(page, "{}", doc.text).unwrap();
+        } else {
+            writeln!(page, "{}", inline(&doc.text, anchors, Format::Markdown)).unwrap();
+        }
+    }
+}
+
+/// Writes `docs` out as paragraphs, separated by blank lines, and fenced code blocks.
+fn html_docs(page: &mut String, docs: &[Doc], anchors: &HashMap<&str, &str>) {
+    let mut paragraph = Vec::new();
+    let mut code: Option<Vec<&str>> = None;
+    for doc in docs {
+        match &mut code {
+            Some(lines) if doc.text.starts_with("```") => {
+                writeln!(
+                    page,
+                    "<pre><code class=\"language-sculpt\">{}</code></pre>",
+                    escape(&lines.join("\n"))
+                )
+                .unwrap();
+                code = None;
+            }
+            Some(lines) => lines.push(doc.text.as_str()),
+            None if doc.text.starts_with("```") => {
+                end_paragraph(page, &mut paragraph, anchors);
+                code = Some(Vec::new());
+            }
+            None if doc.text.trim().is_empty() => end_paragraph(page, &mut paragraph, anchors),
+            None => paragraph.push(doc.text.as_str()),
+        }
+    }
+    end_paragraph(page, &mut paragraph, anchors);
+}
+
+fn end_paragraph(page: &mut String, paragraph: &mut Vec<&str>, anchors: &HashMap<&str, &str>) {
+    if !paragraph.is_empty() {
+        let text = paragraph.join(" ");
+        writeln!(page, "<p>{}</p>", inline(&text, anchors, Format::Html)).unwrap();
+        paragraph.clear();
+    }
+}
+
+/// Renders the `code` spans and [`item`] links in a line of docs. Links to items that aren't on
+/// the page are left as code.
+fn inline(text: &str, anchors: &HashMap<&str, &str>, format: Format) -> String {
+    let mut out = String::new();
+    let mut rest = text;
+    while !rest.is_empty() {
+        let (link, code) = if let Some(after) = rest.strip_prefix("[`") {
+            (true, after)
+        } else if let Some(after) = rest.strip_prefix('`') {
+            (false, after)
+        } else {
+            let c = rest.chars().next().unwrap();
+            match format {
+                Format::Html => out.push_str(&escape(&c.to_string())),
+                Format::Markdown => out.push(c),
+            }
+            rest = &rest[c.len_utf8()..];
+            continue;
+        };
+        let close = if link { "`]" } else { "`" };
+        let Some(end) = code.find(close) else {
+            match format {
+                Format::Html => out.push_str(&escape(rest)),
+                Format::Markdown => out.push_str(rest),
+            }
+            break;
+        };
+        let name = &code[..end];
+        let anchor = anchors.get(name).filter(|_| link);
+        out.push_str(&match (format, anchor) {
+            (Format::Html, Some(anchor)) => {
+                format!("<a href=\"#{}\"><code>{}</code></a>", anchor, escape(name))
+            }
+            (Format::Html, None) => format!("<code>{}</code>", escape(name)),
+            (Format::Markdown, Some(anchor)) => format!("[`{}`](#{})", name, anchor),
+            (Format::Markdown, None) => format!("`{}`", name),
+        });
+        rest = &code[end + close.len()..];
+    }
+    out
+}
+
+/// Escapes the signature of `item` for HTML, linking the other structs it names.
+fn link_types(item: &Item, anchors: &HashMap<&str, &str>) -> String {
+    let mut out = String::new();
+    let mut rest = item.signature;
+    while let Some(start) = rest.find(|c: char| c.is_alphabetic() || c == '_') {
+        out.push_str(&escape(&rest[..start]));
+        rest = &rest[start..];
+        let end = rest
+            .find(|c: char| !c.is_alphanumeric() && c != '_')
+            .unwrap_or(rest.len());
+        let name = &rest[..end];
+        match anchors.get(name) {
+            Some(anchor) if anchor.starts_with("struct.") && *anchor != item.anchor => {
+                write!(out, "<a href=\"#{}\">{}</a>", anchor, name).unwrap()
+            }
+            _ => out.push_str(name),
+        }
+        rest = &rest[end..];
+    }
+    out.push_str(&escape(rest));
+    out
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::run::parse;
+
+    const SRC: &str = "/// A point on the grid.
+struct Point(i64, i64);
+
+impl Point {
+    /// Moves [`Point`] right by `dx`.
+    fn right(self, dx: i64) -> Point { Point(self.0 + dx, self.1) }
+}
+
+/// Sums the coordinates of [`Point`]s, like [`Point::right`] moves them.
+///
+/// ```
+/// fn main() { println!(\"{}\", 1); }
+/// ```
+fn sum(p: Point) -> i64 { p.0 + p.1 }
+";
+
+    #[test]
+    fn markdown_lists_items_with_their_docs_and_links() {
+        let page = render("points", &parse(SRC).unwrap(), SRC, Format::Markdown);
+        assert_eq!(
+            page,
+            "# points
+
+<a id=\"struct.Point\"></a>
+## `Point`
+
+```sculpt
+struct Point(i64, i64)
+```
+
+A point on the grid.
+
+<a id=\"fn.Point.right\"></a>
+## `Point::right`
+
+```sculpt
+fn right(self, dx: i64) -> Point
+```
+
+Moves [`Point`](#struct.Point) right by `dx`.
+
+<a id=\"fn.sum\"></a>
+## `sum`
+
+```sculpt
+fn sum(p: Point) -> i64
+```
+
+Sums the coordinates of [`Point`](#struct.Point)s, like [`Point::right`](#fn.Point.right) moves them.
+
+```sculpt
+fn main() { println!(\"{}\", 1); }
+```
+"
+        );
+    }
+
+    #[test]
+    fn html_links_names_in_signatures() {
+        let page = render("points", &parse(SRC).unwrap(), SRC, Format::Html);
+        assert!(page.contains(
+            "<h2><code>fn sum(p: <a href=\"#struct.Point\">Point</a>) -&gt; i64</code></h2>\n\
+             <p>Sums the coordinates of <a href=\"#struct.Point\"><code>Point</code></a>s, like \
+             <a href=\"#fn.Point.right\"><code>Point::right</code></a> moves them.</p>\n\
+             <pre><code class=\"language-sculpt\">fn main() { println!(&quot;{}&quot;, 1); }\
+             </code></pre>"
+        ));
+    }
+}
@@ -0,0 +1,696 @@
+//! Expansion of user-defined `macro_rules!` macros, plugin macros, and builtins evaluated at
+//! compile time like `include_str!`, which runs on the syntax tree before it's lowered, so
+//! lowering only ever sees builtin macros that run with the program.
+
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::Path;
+
+use crate::intern::{sym, Symbol};
+use crate::plugin::Plugin;
+use crate::run::Error;
+use crate::source::{FileSystem, SourceProvider};
+use crate::syntax::{
+    Binary, Block, Call, Expr, For, IntLit, Let, Macro, MacroRule, MacroRules, Name, Neg, NodeId,
+    Program, Stmt, StrLit, WhileLet,
+};
+use crate::visit::{self, Fold};
+
+/// How deep macros may expand into other macros before expansion is assumed to never finish.
+pub const RECURSION_LIMIT: usize = 64;
+
+/// The builtin macros, and their signatures.
+pub const BUILTIN_MACROS: [(&str, &str); 18] = [
+    ("print!", "print!(format, args...)"),
+    ("println!", "println!(format, args...)"),
+    ("eprint!", "eprint!(format, args...)"),
+    ("eprintln!", "eprintln!(format, args...)"),
+    ("format!", "format!(format, args...) -> String"),
+    ("read_line!", "read_line!() -> Option<String>"),
+    ("read_to_string!", "read_to_string!() -> String"),
+    ("include_str!", "include_str!(path) -> &str"),
+    ("env!", "env!(name) -> &str"),
+    ("option_env!", "option_env!(name) -> Option<&str>"),
+    ("getenv!", "getenv!(name: str) -> Option<String>"),
+    ("random_int!", "random_int!(lo: i64, hi: i64) -> i64"),
+    ("now_millis!", "now_millis!() -> i64"),
+    ("sleep_millis!", "sleep_millis!(millis: i64)"),
+    (
+        "read_file!",
+        "read_file!(path: str) -> Result<String, String>",
+    ),
+    ("write_file!", "write_file!(path: str, contents: str)"),
+    ("exit!", "exit!(status: i64)"),
+    ("vec!", "vec![elements...] -> Vec<T>"),
+];
+
+/// Everything expansion draws on besides the program itself.
+#[derive(Clone, Copy)]
+pub struct ExpandContext<'a> {
+    /// Plugins whose macros are expanded. Macros the program defines shadow plugins' macros, and
+    /// earlier plugins shadow later ones.
+    pub plugins: &'a [Plugin],
+    /// The file being expanded, which `include_str!` paths are relative to. Without one, they're
+    /// relative to the current directory.
+    pub file: Option<&'a Path>,
+    /// Where `include_str!` reads files from, or `None` if the program may not read files.
+    pub sources: Option<&'a dyn SourceProvider>,
+    /// Whether `env!` and `option_env!` see the compiler's environment. Without it, every
+    /// variable is unset.
+    pub env: bool,
+}
+
+impl Default for ExpandContext<'_> {
+    fn default() -> Self {
+        Self {
+            plugins: &[],
+            file: None,
+            sources: Some(&FileSystem),
+            env: true,
+        }
+    }
+}
+
+/// Replaces every invocation of a macro `program` defines with the statements it expands to, and
+/// every `include_str!`, `env!` and `option_env!` with the literal it evaluates to.
+pub fn expand<'src>(program: Program) -> Result<Program, Error<'src>> {
+    expand_with(program, ExpandContext::default())
+}
+
+/// Like [`expand`], in the given context.
+pub fn expand_with<'src>(
+    mut program: Program,
+    context: ExpandContext,
+) -> Result<Program, Error<'src>> {
+    let macros = std::mem::take(&mut program.macros);
+    let expander = Expander::new(&macros, context);
+    let methods = program.impls.iter_mut().flat_map(|i| &mut i.functions);
+    for function in program.functions.iter_mut().chain(methods) {
+        let statements = std::mem::take(&mut function.statements);
+        function.statements = expander.expand_statements(statements, 0)?;
+        if let Some(tail) = function.tail.take() {
+            function.tail = expander.expand_args(vec![tail], 0)?.pop();
+        }
+    }
+    program.macros = macros;
+    Ok(program)
+}
+
+/// Where the expansion of a non-builtin macro comes from.
+enum Source<'a> {
+    Rules(&'a MacroRules),
+    Plugin(&'a Plugin),
+}
+
+struct Expander<'a> {
+    /// Macros by the name they're invoked with, including the `!`.
+    macros: HashMap<Symbol, Source<'a>>,
+    context: ExpandContext<'a>,
+}
+
+impl<'a> Expander<'a> {
+    fn new(macros: &'a [MacroRules], context: ExpandContext<'a>) -> Self {
+        let plugin_macros = context.plugins.iter().rev().flat_map(|plugin| {
+            plugin
+                .macros()
+                .iter()
+                .map(move |name| (*name, Source::Plugin(plugin)))
+        });
+        let rules = macros.iter().map(|m| {
+            (
+                Symbol::intern(&format!("{}!", m.name.name)),
+                Source::Rules(m),
+            )
+        });
+        Self {
+            macros: plugin_macros.chain(rules).collect(),
+            context,
+        }
+    }
+
+    fn expand_statements<'src>(
+        &self,
+        statements: Vec<Stmt>,
+        depth: usize,
+    ) -> Result<Vec<Stmt>, Error<'src>> {
+        let mut expanded = Vec::new();
+        for statement in statements {
+            let statement = match statement {
+                Stmt::Macro(m) => m,
+                Stmt::For(f) => {
+                    expanded.push(Stmt::For(For {
+                        iter: self.expand_args(vec![f.iter], depth)?.remove(0),
+                        body: self.expand_statements(f.body, depth)?,
+                        ..f
+                    }));
+                    continue;
+                }
+                Stmt::WhileLet(w) => {
+                    expanded.push(Stmt::WhileLet(WhileLet {
+                        value: self.expand_args(vec![w.value], depth)?.remove(0),
+                        body: self.expand_statements(w.body, depth)?,
+                        ..w
+                    }));
+                    continue;
+                }
+                // The blocks of an `if let` are expanded like those of any expression.
+                Stmt::IfLet(i) => {
+                    let Expr::IfLet(i) = self
+                        .expand_args(vec![Expr::IfLet(Box::new(i))], depth)?
+                        .remove(0)
+                    else {
+                        unreachable!()
+                    };
+                    expanded.push(Stmt::IfLet(*i));
+                    continue;
+                }
+                Stmt::Block(block) => {
+                    let block = Block {
+                        statements: self.expand_statements(block.statements, depth)?,
+                        ..block
+                    };
+                    expanded.push(Stmt::Block(block));
+                    continue;
+                }
+                Stmt::Let(l) => {
+                    expanded.push(Stmt::Let(Let {
+                        value: self.expand_args(vec![l.value], depth)?.remove(0),
+                        ..l
+                    }));
+                    continue;
+                }
+            };
+            let Some(m) = self.macros.get(&statement.name.name) else {
+                let statement = Macro {
+                    args: self.expand_args(statement.args, depth)?,
+                    ..statement
+                };
+                // The literal does nothing as a statement, but evaluating it may still fail.
+                match self.eval_builtin(&statement) {
+                    Some(val) => {
+                        val?;
+                    }
+                    None => expanded.push(Stmt::Macro(statement)),
+                }
+                continue;
+            };
+            if depth == RECURSION_LIMIT {
+                return Err(Error::MacroRecursionLimit(statement.name.span));
+            }
+            let statement = Macro {
+                args: self.check_args(statement.args, depth)?,
+                ..statement
+            };
+            let body = match m {
+                Source::Rules(m) => expand_rules(m, statement)?,
+                Source::Plugin(plugin) => {
+                    let body = plugin
+                        .expand(&statement)
+                        .map_err(|message| Error::PluginFailed(statement.name.span, message))?;
+                    let mut respan = Respan(statement.span);
+                    body.into_iter()
+                        .map(|m| Stmt::Macro(respan.fold_macro(m)))
+                        .collect()
+                }
+            };
+            expanded.extend(self.expand_statements(body, depth + 1)?);
+        }
+        Ok(expanded)
+    }
+
+    /// Checks the arguments of a statement, or a function's tail, and evaluates the builtins in
+    /// them that are evaluated at compile time.
+    fn expand_args<'src>(&self, args: Vec<Expr>, depth: usize) -> Result<Vec<Expr>, Error<'src>> {
+        self.eval_builtin_args(self.check_args(args, depth)?)
+    }
+
+    /// User macros expand to statements, so they can't be invoked as arguments, though they can
+    /// be in the statements of blocks in them.
+    fn check_args<'src>(&self, args: Vec<Expr>, depth: usize) -> Result<Vec<Expr>, Error<'src>> {
+        struct Check<'a, 'b, 'src> {
+            expander: &'b Expander<'a>,
+            depth: usize,
+            error: Option<Error<'src>>,
+        }
+
+        impl Fold for Check<'_, '_, '_> {
+            fn fold_expr(&mut self, expr: Expr) -> Expr {
+                match &expr {
+                    Expr::Macro(m) if self.expander.macros.contains_key(&m.name.name) => {
+                        self.error
+                            .get_or_insert(Error::NotAnExpression(m.span.clone()));
+                        expr
+                    }
+                    _ => visit::fold_expr(self, expr),
+                }
+            }
+
+            fn fold_block(&mut self, block: Block) -> Block {
+                let statements = self
+                    .expander
+                    .expand_statements(block.statements, self.depth)
+                    .unwrap_or_else(|error| {
+                        self.error.get_or_insert(error);
+                        Vec::new()
+                    });
+                let tail = block.tail.map(|tail| Box::new(self.fold_expr(*tail)));
+                Block {
+                    statements,
+                    tail,
+                    ..block
+                }
+            }
+        }
+
+        let mut check = Check {
+            expander: self,
+            depth,
+            error: None,
+        };
+        let args = args.into_iter().map(|arg| check.fold_expr(arg)).collect();
+        match check.error {
+            Some(error) => Err(error),
+            None => Ok(args),
+        }
+    }
+
+    /// Replaces each builtin evaluated at compile time in `args` with the literal it evaluates
+    /// to.
+    fn eval_builtin_args<'src>(&self, args: Vec<Expr>) -> Result<Vec<Expr>, Error<'src>> {
+        struct Eval<'a, 'b, 'src> {
+            expander: &'b Expander<'a>,
+            error: Option<Error<'src>>,
+        }
+
+        impl Fold for Eval<'_, '_, '_> {
+            fn fold_expr(&mut self, expr: Expr) -> Expr {
+                match expr {
+                    Expr::Macro(m) => match self.expander.eval_builtin(&m) {
+                        Some(Ok(expr)) => expr,
+                        Some(Err(error)) => {
+                            self.error.get_or_insert(error);
+                            Expr::Macro(m)
+                        }
+                        None => visit::fold_expr(self, Expr::Macro(m)),
+                    },
+                    expr => visit::fold_expr(self, expr),
+                }
+            }
+        }
+
+        let mut eval = Eval {
+            expander: self,
+            error: None,
+        };
+        let args = args.into_iter().map(|arg| eval.fold_expr(arg)).collect();
+        match eval.error {
+            Some(error) => Err(error),
+            None => Ok(args),
+        }
+    }
+
+    /// The literal `m` evaluates to if it's a builtin evaluated at compile time, or `None` if
+    /// it's any other macro.
+    fn eval_builtin<'src>(&self, m: &Macro) -> Option<Result<Expr, Error<'src>>> {
+        match m.name.name {
+            sym::INCLUDE_STR => Some(self.include(m).map(|val| str_lit(m, m.id, val))),
+            sym::ENV => Some(self.env(m).map(|val| str_lit(m, m.id, val))),
+            sym::OPTION_ENV => Some(self.option_env(m)),
+            _ => None,
+        }
+    }
+
+    /// Contents of the file the `include_str!` invocation `m` names.
+    fn include<'src>(&self, m: &Macro) -> Result<Symbol, Error<'src>> {
+        let path = str_args(m, 1)?[0];
+        let failed = |message: String| Error::IncludeFailed(m.span.clone(), path.into(), message);
+        let Some(sources) = self.context.sources else {
+            return Err(failed("this program may not read files".into()));
+        };
+        let dir = self
+            .context
+            .file
+            .and_then(Path::parent)
+            .unwrap_or(Path::new(""));
+        sources
+            .read(&dir.join(path))
+            .map(|contents| Symbol::intern(&contents))
+            .map_err(|e| failed(e.to_string()))
+    }
+
+    /// Value of the variable the `env!` invocation `m` names, like `env!("HOME")`. An optional
+    /// second argument replaces the error message reported if the variable is unset.
+    fn env<'src>(&self, m: &Macro) -> Result<Symbol, Error<'src>> {
+        let args = str_args(m, 2)?;
+        self.var(args[0]).ok_or_else(|| {
+            let message = args.get(1).map_or_else(
+                || {
+                    format!(
+                        "environment variable `{}` not defined at compile time",
+                        args[0]
+                    )
+                },
+                |message| message.to_string(),
+            );
+            Error::EnvVarUnset(m.span.clone(), message)
+        })
+    }
+
+    /// `Some` of the value of the variable the `option_env!` invocation `m` names, or `None` if
+    /// it's unset.
+    fn option_env<'src>(&self, m: &Macro) -> Result<Expr, Error<'src>> {
+        let name = str_args(m, 1)?[0];
+        let expr = match self.var(name) {
+            Some(value) => Expr::Call(Call {
+                id: m.id,
+                span: m.span.clone(),
+                ty: None,
+                name: Name {
+                    name: sym::SOME,
+                    ..m.name.clone()
+                },
+                generics: Vec::new(),
+                args: vec![str_lit(m, NodeId::default(), value)],
+            }),
+            None => Expr::Name(Name {
+                id: m.id,
+                span: m.span.clone(),
+                name: sym::NONE,
+            }),
+        };
+        Ok(expr)
+    }
+
+    /// Value of the environment variable `name` at compile time, if it's set and visible.
+    fn var(&self, name: &str) -> Option<Symbol> {
+        match self.context.env {
+            true => std::env::var(name).ok().map(|value| Symbol::intern(&value)),
+            false => None,
+        }
+    }
+}
+
+/// The string literal `val`, expanded from the builtin invocation `m`.
+fn str_lit(m: &Macro, id: NodeId, val: Symbol) -> Expr {
+    Expr::Str(StrLit {
+        id,
+        span: m.span.clone(),
+        val,
+        escapes: Vec::new(),
+    })
+}
+
+/// The string literal arguments of the builtin invocation `m`, which takes at least one and at
+/// most `max`.
+fn str_args<'src>(m: &Macro, max: usize) -> Result<Vec<&str>, Error<'src>> {
+    if m.args.is_empty() {
+        return Err(Error::ExpectedStrLit(m.name.span.clone()));
+    }
+    if m.args.len() > max {
+        return Err(Error::ExtraMacroArguments(
+            m.name.span.clone(),
+            max,
+            m.args[max..].iter().map(Expr::span).collect(),
+        ));
+    }
+    m.args
+        .iter()
+        .map(|arg| match arg {
+            Expr::Str(lit) => Ok(lit.val.as_str()),
+            arg => Err(Error::ExpectedStrLit(arg.span())),
+        })
+        .collect()
+}
+
+fn expand_rules<'src>(m: &MacroRules, statement: Macro) -> Result<Vec<Stmt>, Error<'src>> {
+    let Some(rule) = m
+        .rules
+        .iter()
+        .find(|rule| rule.params.len() == statement.args.len())
+    else {
+        return Err(Error::NoMatchingMacroRule(
+            statement.name.span,
+            statement.args.len(),
+        ));
+    };
+    substitute(rule, statement.args)
+}
+
+fn substitute<'src>(rule: &MacroRule, args: Vec<Expr>) -> Result<Vec<Stmt>, Error<'src>> {
+    let mut substitute = Substitute {
+        bindings: rule
+            .params
+            .iter()
+            .map(|param| param.name)
+            .zip(args)
+            .collect(),
+        error: None,
+    };
+    let body = rule
+        .body
+        .iter()
+        .map(|statement| substitute.fold_stmt(statement.clone()))
+        .collect();
+    match substitute.error {
+        Some(error) => Err(error),
+        None => Ok(body),
+    }
+}
+
+/// Points the nodes a plugin built without spans at the invocation they replace.
+struct Respan(Range<usize>);
+
+impl Respan {
+    fn span(&self, span: Range<usize>) -> Range<usize> {
+        if span.is_empty() {
+            self.0.clone()
+        } else {
+            span
+        }
+    }
+}
+
+impl Fold for Respan {
+    fn fold_macro(&mut self, m: Macro) -> Macro {
+        let span = self.span(m.span.clone());
+        visit::fold_macro(self, Macro { span, ..m })
+    }
+
+    fn fold_expr(&mut self, expr: Expr) -> Expr {
+        let expr = match expr {
+            Expr::Neg(neg) => Expr::Neg(Neg {
+                span: self.span(neg.span.clone()),
+                ..neg
+            }),
+            Expr::Binary(binary) => Expr::Binary(Binary {
+                span: self.span(binary.span.clone()),
+                op_span: self.span(binary.op_span.clone()),
+                ..binary
+            }),
+            expr => expr,
+        };
+        visit::fold_expr(self, expr)
+    }
+
+    fn fold_name(&mut self, name: Name) -> Name {
+        Name {
+            span: self.span(name.span.clone()),
+            ..name
+        }
+    }
+
+    fn fold_str_lit(&mut self, lit: StrLit) -> StrLit {
+        StrLit {
+            span: self.span(lit.span.clone()),
+            ..lit
+        }
+    }
+
+    fn fold_int_lit(&mut self, lit: IntLit) -> IntLit {
+        IntLit {
+            span: self.span(lit.span.clone()),
+            ..lit
+        }
+    }
+}
+
+/// Replaces each `$param` in a rule's body with the argument it was bound to.
+struct Substitute<'src> {
+    bindings: HashMap<Symbol, Expr>,
+    error: Option<Error<'src>>,
+}
+
+impl Fold for Substitute<'_> {
+    fn fold_expr(&mut self, expr: Expr) -> Expr {
+        match expr {
+            Expr::MetaVar(name) => match self.bindings.get(&name.name) {
+                Some(arg) => arg.clone(),
+                None => {
+                    self.error
+                        .get_or_insert(Error::UnboundMetaVar(name.span.clone()));
+                    Expr::MetaVar(name)
+                }
+            },
+            expr => visit::fold_expr(self, expr),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::run::parse;
+
+    /// The names of the macros `main` invokes once expanded, with its string arguments.
+    fn expand_main(src: &str) -> Result<Vec<String>, Error<'static>> {
+        let program = expand(parse(src).unwrap())?;
+        Ok(program.functions[0]
+            .statements
+            .iter()
+            .map(|statement| {
+                let Stmt::Macro(statement) = statement else {
+                    return "for".to_string();
+                };
+                let args: Vec<_> = statement
+                    .args
+                    .iter()
+                    .map(|arg| match arg {
+                        Expr::Str(lit) => lit.val.as_str(),
+                        _ => "_",
+                    })
+                    .collect();
+                format!("{}({})", statement.name.name, args.join(", "))
+            })
+            .collect())
+    }
+
+    #[test]
+    fn params_are_replaced_by_arguments() {
+        let src = r#"
+            macro_rules! greet {
+                ($greeting:expr, $name:expr) => {
+                    print!($greeting);
+                    println!(" {}!", $name);
+                };
+            }
+            fn main() { greet!("Hello", "world"); }
+        "#;
+        assert_eq!(
+            expand_main(src).unwrap(),
+            ["print!(Hello)", "println!( {}!, world)"]
+        );
+    }
+
+    #[test]
+    fn rules_are_chosen_by_argument_count() {
+        let src = r#"
+            macro_rules! log {
+                () => { println!("-"); };
+                ($x:expr) => { println!("{}", $x); }
+            }
+            fn main() { log!(); log!("a"); }
+        "#;
+        assert_eq!(
+            expand_main(src).unwrap(),
+            ["println!(-)", "println!({}, a)"]
+        );
+    }
+
+    #[test]
+    fn macros_expand_inside_macros() {
+        let src = r#"
+            macro_rules! twice { ($x:expr) => { once!($x); once!($x); } }
+            macro_rules! once { ($x:expr) => { print!($x); } }
+            fn main() { twice!("a"); }
+        "#;
+        assert_eq!(expand_main(src).unwrap(), ["print!(a)", "print!(a)"]);
+    }
+
+    #[test]
+    fn params_must_be_bound() {
+        let src = r#"
+            macro_rules! m { ($x:expr) => { print!($y); } }
+            fn main() { m!("a"); }
+        "#;
+        assert_eq!(expand_main(src), Err(Error::UnboundMetaVar(52..54)));
+    }
+
+    #[test]
+    fn invocations_must_match_a_rule() {
+        let src = r#"
+            macro_rules! m { ($x:expr) => { print!($x); } }
+            fn main() { m!(); }
+        "#;
+        assert_eq!(expand_main(src), Err(Error::NoMatchingMacroRule(85..87, 0)));
+    }
+
+    #[test]
+    fn endless_expansion_is_cut_off() {
+        let src = r#"
+            macro_rules! m { () => { m!(); } }
+            fn main() { m!(); }
+        "#;
+        assert_eq!(expand_main(src), Err(Error::MacroRecursionLimit(38..40)));
+    }
+
+    #[test]
+    fn user_macros_are_not_expressions() {
+        let src = r#"
+            macro_rules! m { () => { print!("a"); } }
+            fn main() { print!("{}", m!()); }
+        "#;
+        assert_eq!(expand_main(src), Err(Error::NotAnExpression(92..96)));
+    }
+
+    #[test]
+    fn option_env_expands_to_an_option() {
+        let src = r#"
+            fn main() {
+                let set = option_env!("CARGO_PKG_NAME");
+                let unset = option_env!("SCULPT_UNSET");
+            }
+        "#;
+        let program = expand(parse(src).unwrap()).unwrap();
+        let values: Vec<_> = program.functions[0]
+            .statements
+            .iter()
+            .map(|statement| match statement {
+                Stmt::Let(Let {
+                    value: Expr::Call(call),
+                    ..
+                }) => match &call.args[..] {
+                    [Expr::Str(lit)] => format!("{}({})", call.name.name, lit.val),
+                    _ => "_".to_string(),
+                },
+                Stmt::Let(Let {
+                    value: Expr::Name(name),
+                    ..
+                }) => name.name.to_string(),
+                _ => "_".to_string(),
+            })
+            .collect();
+        assert_eq!(values, ["Some(sculpt)", "None"]);
+    }
+
+    #[test]
+    fn sandboxed_option_env_is_none() {
+        let context = ExpandContext {
+            env: false,
+            ..ExpandContext::default()
+        };
+        let src = r#"fn main() { let name = option_env!("CARGO_PKG_NAME"); }"#;
+        let program = expand_with(parse(src).unwrap(), context).unwrap();
+        assert!(matches!(
+            &program.functions[0].statements[..],
+            [Stmt::Let(Let { value: Expr::Name(name), .. })] if name.name == sym::NONE
+        ));
+    }
+
+    #[test]
+    fn params_must_be_expressions() {
+        assert_eq!(
+            parse(r#"macro_rules! m { ($x:ty) => {} }"#).err(),
+            Some(Error::UnknownFragment(21..23))
+        );
+    }
+}
@@ -0,0 +1,163 @@
+//! Machine-applicable fixes for errors: edits to the source that make an error go away, which
+//! reports suggest, `sculpt fix` applies and the language server offers as code actions.
+
+use lalrpop_util::ParseError;
+
+use std::ops::Range;
+
+use crate::expand::BUILTIN_MACROS;
+use crate::locale::msg;
+use crate::run::{parse, Error};
+
+/// Replaces the source in `span` with `replacement`. Insertions have empty spans.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Edit {
+    pub span: Range<usize>,
+    pub replacement: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Fix {
+    /// What the fix does, like "add a `;` to end the statement".
+    pub message: String,
+    /// Edits that don't overlap.
+    pub edits: Vec<Edit>,
+}
+
+impl Fix {
+    fn replace(span: Range<usize>, replacement: impl Into<String>, message: String) -> Self {
+        Fix {
+            message,
+            edits: vec![Edit {
+                span,
+                replacement: replacement.into(),
+            }],
+        }
+    }
+
+    /// `source_code` with the fix's edits made.
+    pub fn apply(&self, source_code: &str) -> String {
+        let mut edits: Vec<_> = self.edits.iter().collect();
+        edits.sort_by_key(|edit| edit.span.start);
+        let mut fixed = String::new();
+        let mut end = 0;
+        for edit in edits {
+            fixed.push_str(&source_code[end..edit.span.start]);
+            fixed.push_str(&edit.replacement);
+            end = edit.span.end;
+        }
+        fixed.push_str(&source_code[end..]);
+        fixed
+    }
+}
+
+/// The fix for `error`, raised in `source_code`, if it has one.
+pub fn fix(error: &Error, source_code: &str) -> Option<Fix> {
+    match error {
+        Error::ParseError(ParseError::UnrecognizedToken {
+            token: (start, ..),
+            expected,
+        }) if expected.iter().any(|e| e == "\";\"") => {
+            let end = end_of_code(&source_code[..*start]);
+            Some(Fix::replace(end..end, ";", msg!("fix.add-semicolon")))
+        }
+        // A format string `include_str!` expanded to isn't in the source, so it can't be fixed.
+        Error::UnmatchedFmtBrace(range, brace) => {
+            source_code
+                .get(range.clone())
+                .filter(|text| text.len() == 1)?;
+            let escaped = brace.to_string().repeat(2);
+            Some(Fix::replace(
+                range.clone(),
+                escaped.clone(),
+                msg!("fix.escape-brace", escaped = escaped),
+            ))
+        }
+        Error::UnknownMacro(range) => {
+            let similar = similar_macro(&source_code[range.clone()], source_code)?;
+            Some(Fix::replace(
+                range.clone(),
+                similar.clone(),
+                msg!("fix.similar-macro", name = similar),
+            ))
+        }
+        _ => None,
+    }
+}
+
+/// Where the code in `before` ends, ignoring the whitespace and any `//` comment after it.
+fn end_of_code(before: &str) -> usize {
+    let end = before.trim_end().len();
+    let line_start = before[..end].rfind('\n').map_or(0, |i| i + 1);
+    let line = &before[line_start..end];
+    match line.find("//") {
+        Some(comment) if !line[..comment].contains('"') => {
+            line_start + line[..comment].trim_end().len()
+        }
+        _ => end,
+    }
+}
+
+/// The builtin or user macro named most like `name`, if any is only a typo or two away.
+fn similar_macro(name: &str, source_code: &str) -> Option<String> {
+    let user_macros = parse(source_code).map_or(Vec::new(), |program| {
+        program
+            .macros
+            .iter()
+            .map(|m| format!("{}!", m.name.name.as_str()))
+            .collect()
+    });
+    BUILTIN_MACROS
+        .iter()
+        .map(|(builtin, _)| builtin.to_string())
+        .chain(user_macros)
+        .map(|candidate| (edit_distance(name, &candidate), candidate))
+        .filter(|(distance, _)| *distance <= (name.len() / 3).max(1))
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// How many characters must be inserted, removed or replaced to turn `a` into `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let replaced = diagonal + (ca != *cb) as usize;
+            diagonal = row[j + 1];
+            row[j + 1] = replaced.min(row[j] + 1).min(row[j + 1] + 1);
+        }
+    }
+    row[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hir::lower;
+
+    /// Fixes the first error in `src`, returning the fixed source.
+    fn fixed(src: &str) -> Option<String> {
+        let error = parse(src).and_then(lower).unwrap_err();
+        fix(&error, src).map(|fix| fix.apply(src))
+    }
+
+    #[test]
+    fn fixes_make_errors_go_away() {
+        assert_eq!(
+            fixed("fn main() {\n    let x = 1 // one\n    println!(\"{}\", x);\n}").as_deref(),
+            Some("fn main() {\n    let x = 1; // one\n    println!(\"{}\", x);\n}")
+        );
+        assert_eq!(
+            fixed("fn main() { printlm!(\"a\"); }").as_deref(),
+            Some("fn main() { println!(\"a\"); }")
+        );
+        assert_eq!(
+            fixed("fn main() { print!(\"{} }\", 1); }").as_deref(),
+            Some("fn main() { print!(\"{} }}\", 1); }")
+        );
+        assert_eq!(fixed("fn main() { dbg!(1); }"), None);
+    }
+}
@@ -1,170 +1,566 @@
-use combine::parser::range::recognize;
-use combine::Parser;
-use combine::{satisfy, skip_many, skip_many1, token};
+//! The formatter behind `sculpt fmt`. It keeps the line breaks code was written with, along with
+//! its comments and single blank lines, reindenting each line by how deeply it's nested and
+//! respacing the tokens on it. Since only the tokens decide how a line comes out, formatting
+//! formatted code leaves it as it is.
+
+use serde::Deserialize;
 
 use std::ops::Range;
 
-use crate::syntax::StrLit;
+use crate::syntax::is_keyword;
 
-pub fn extract_fmt<'s>(input: &StrLit<'s>) -> Result<Vec<FmtSpec<'s>>, usize> {
-    let lit_parser = || recognize(skip_many1(satisfy(|c| c != '{' && c != '}')));
-    let spec_parser = || {
-        recognize((
-            token('{'),
-            skip_many(satisfy(|c| c != '{' && c != '}')),
-            token('}'),
-        ))
-    };
+/// How code is laid out, which the `[fmt]` table of a `sculpt.toml` can change.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// How many spaces each level of nesting is indented by.
+    pub indent_width: usize,
+    /// How many characters long lines can be before they're broken up. Lines are never joined,
+    /// so lines once broken up stay that way.
+    pub max_width: usize,
+    pub brace_style: BraceStyle,
+}
 
-    let mut location = input.span.start + 1;
-    let mut input = input.val;
-    let mut specs = Vec::new();
-
-    while !input.is_empty() {
-        let spec = if let Ok((val, rest)) = lit_parser().parse(input) {
-            let span = location..(location + val.len());
-            location = span.end;
-            input = rest;
-            Ok(FmtSpec::Lit { val, span })
-        } else if let Ok((spec, rest)) = spec_parser().parse(input) {
-            if let Some(offset) =
-                spec.find(|c: char| !c.is_ascii_whitespace() && c != '{' && c != '}')
-            {
-                Err(location + offset)
-            } else {
-                let span = location..(location + spec.len());
-                location = span.end;
-                input = rest;
-                Ok(FmtSpec::Arg { span })
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            indent_width: 4,
+            max_width: 100,
+            brace_style: BraceStyle::SameLine,
+        }
+    }
+}
+
+/// Where the `{` opening the body of a function, trait or `impl` goes.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum BraceStyle {
+    /// At the end of the line before it.
+    #[default]
+    SameLine,
+    /// On a line of its own.
+    NextLine,
+}
+
+/// Tokens longer than a character, longest first, so they're lexed before their prefixes are.
+const PUNCTUATION: [&str; 6] = ["::<", "->", "=>", "::", "..", "#!"];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Kind {
+    /// A name, a keyword, or the name of a macro like `println!`.
+    Word,
+    Int,
+    Str,
+    /// A `//` or `///` comment, up to the end of its line.
+    Comment,
+    Punct,
+}
+
+/// How a token that can be used more than one way is used, which decides its spacing.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Role {
+    Normal,
+    /// A `-`, `*` or `&` before its operand.
+    Prefix,
+    /// The `|` before a closure's parameters.
+    OpenPipe,
+    /// The `|` after a closure's parameters.
+    ClosePipe,
+    /// The `:` between a macro parameter and its fragment specifier, like in `$x:expr`.
+    Fragment,
+}
+
+#[derive(Debug)]
+struct Token<'a> {
+    kind: Kind,
+    text: &'a str,
+    span: Range<usize>,
+    role: Role,
+}
+
+/// The tokens starting on a line of the source. Strings can span line breaks, so a line can be
+/// more than one line of the source.
+#[derive(Debug)]
+struct Line {
+    /// The indices of its tokens.
+    tokens: Range<usize>,
+    /// Where the source after the line before starts, which any blank lines between them are in.
+    gap_start: usize,
+    /// Where the line starts.
+    start: usize,
+    /// Where the line ends, before its line break.
+    end: usize,
+    /// Whether a blank line separates it from the line before.
+    blank_before: bool,
+}
+
+/// Formats `source_code` with the default [`Config`], or only the lines that `range`, a range
+/// of offsets into it, touches. Lines around those are left as they were, but are still what
+/// the formatted lines are indented relative to.
+pub fn format_source(source_code: &str, range: Option<Range<usize>>) -> String {
+    format_source_with(source_code, range, &Config::default())
+}
+
+/// Formats `source_code` like [`format_source`], laid out as `config` says.
+pub fn format_source_with(
+    source_code: &str,
+    range: Option<Range<usize>>,
+    config: &Config,
+) -> String {
+    let newline = line_ending(source_code);
+    let tokens = lex(source_code);
+    let lines = lines(source_code, &tokens);
+    let indents = indents(&tokens, &lines);
+    let touched = |line: &Line| {
+        range.as_ref().is_none_or(|range| {
+            line.start < range.end.max(range.start + 1) && range.start <= line.end
+        })
+    };
+    let mut formatted = String::new();
+    // Whether the line was a `{` joined onto the line before it.
+    let mut joined = false;
+    for (i, (line, indent)) in lines.iter().zip(indents).enumerate() {
+        if std::mem::take(&mut joined) {
+            continue;
+        }
+        if !touched(line) {
+            match lines.get(i + 1) {
+                Some(_) => formatted.push_str(&source_code[line.gap_start..=line.end]),
+                None => formatted.push_str(&source_code[line.gap_start..]),
             }
-        } else {
-            match input.chars().next().unwrap() {
-                '{' => Err(location),
-                '}' => Err(location),
-                c => unreachable!("{}", c),
+            continue;
+        }
+        let mut line_tokens: Vec<_> = tokens[line.tokens.clone()].iter().collect();
+        // Blank lines are kept between lines, but not at the start of a file or block, or at
+        // the end of a block.
+        let after_opener = i
+            .checked_sub(1)
+            .is_none_or(|before| opens(&tokens[lines[before].tokens.end - 1]));
+        if line.blank_before && !after_opener && !closes(line_tokens[0]) {
+            formatted.push_str(newline);
+        }
+        let header = line_tokens
+            .iter()
+            .any(|token| token.kind == Kind::Word && matches!(token.text, "fn" | "trait" | "impl"));
+        let last = line_tokens[line_tokens.len() - 1];
+        let next = lines.get(i + 1).filter(|next| touched(next));
+        match config.brace_style {
+            BraceStyle::NextLine if header && last.text == "{" && line_tokens.len() > 1 => {
+                line_tokens.pop();
+                write_line(&mut formatted, &line_tokens, indent, config, newline);
+                write_line(&mut formatted, &[last], indent, config, newline);
+                continue;
+            }
+            BraceStyle::SameLine
+                if header
+                    && !opens(last)
+                    && !matches!(last.text, ";" | ",")
+                    && last.kind != Kind::Comment
+                    && next.is_some_and(|next| {
+                        next.tokens.len() == 1 && tokens[next.tokens.start].text == "{"
+                    }) =>
+            {
+                line_tokens.push(&tokens[next.unwrap().tokens.start]);
+                joined = true;
             }
-        }?;
-        specs.push(spec);
+            _ => {}
+        }
+        write_line(&mut formatted, &line_tokens, indent, config, newline);
     }
-
-    Ok(specs)
+    formatted
 }
 
-#[derive(Debug, PartialEq)]
-pub enum FmtSpec<'s> {
-    Lit { span: Range<usize>, val: &'s str },
-    Arg { span: Range<usize> },
+/// The line break `source_code` uses, going by its first one, which every line formatted ends
+/// with so a file written on Windows stays that way.
+fn line_ending(source_code: &str) -> &'static str {
+    match source_code.find('\n') {
+        Some(i) if source_code[..i].ends_with('\r') => "\r\n",
+        _ => "\n",
+    }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// Writes `tokens` out as a line indented `indent` levels and ended with `newline`. If it's longer
+/// than the config allows, the arguments of its first call, or the parameters of its function,
+/// are each put on a line of their own.
+fn write_line(
+    formatted: &mut String,
+    tokens: &[&Token],
+    indent: usize,
+    config: &Config,
+    newline: &str,
+) {
+    let text = join(tokens);
+    let width = indent * config.indent_width + text.chars().count();
+    if let (true, Some((open, close))) = (width > config.max_width, breakable(tokens)) {
+        write_line(formatted, &tokens[..=open], indent, config, newline);
+        let comma = Token {
+            kind: Kind::Punct,
+            text: ",",
+            span: 0..0,
+            role: Role::Normal,
+        };
+        let mut depth = 0;
+        let mut item = Vec::new();
+        for token in &tokens[open + 1..close] {
+            match token.text {
+                "," if depth == 0 => {
+                    item.push(&comma);
+                    write_line(formatted, &item, indent + 1, config, newline);
+                    item.clear();
+                    continue;
+                }
+                // Closure parameters and type arguments have commas of their own.
+                _ if opens(token) || matches!(token.role, Role::OpenPipe) || token.text == "<" => {
+                    depth += 1
+                }
+                _ if closes(token)
+                    || matches!(token.role, Role::ClosePipe)
+                    || token.text == ">" =>
+                {
+                    depth -= 1
+                }
+                _ => {}
+            }
+            item.push(*token);
+        }
+        if !item.is_empty() {
+            item.push(&comma);
+            write_line(formatted, &item, indent + 1, config, newline);
+        }
+        write_line(formatted, &tokens[close..], indent, config, newline);
+        return;
+    }
+    formatted.push_str(&" ".repeat(indent * config.indent_width));
+    formatted.push_str(&text);
+    formatted.push_str(newline);
+}
 
-    fn str_lit(val: &str) -> StrLit<'_> {
-        StrLit {
-            span: 0..val.len(),
-            val,
+/// The `tokens` of a line, spaced out.
+fn join(tokens: &[&Token]) -> String {
+    let mut text = tokens[0].text.to_string();
+    for pair in tokens.windows(2) {
+        if spaced(pair[0], pair[1]) {
+            text.push(' ');
         }
+        text.push_str(pair[1].text);
     }
+    text
+}
 
-    #[test]
-    fn literal_extracted_for_plain_str() {
-        assert_eq!(
-            extract_fmt(&str_lit("abc")).unwrap(),
-            [FmtSpec::Lit {
-                span: 1..4,
-                val: "abc"
-            }]
-        );
+/// The brackets around the arguments of the first call or macro invocation, or the parameters of
+/// the first function, on a line, if it has any between them. Strings running over lines are
+/// never broken up.
+fn breakable(tokens: &[&Token]) -> Option<(usize, usize)> {
+    if tokens.iter().any(|token| token.text.contains('\n')) {
+        return None;
     }
+    (1..tokens.len()).find_map(|open| {
+        let before = tokens[open - 1];
+        let call = match tokens[open].text {
+            "(" => name_like(before) || matches!(before.text, ")" | "]" | ">"),
+            "[" => before.kind == Kind::Word && before.text.ends_with('!'),
+            _ => false,
+        };
+        if !call {
+            return None;
+        }
+        let mut depth = 0;
+        for (close, token) in tokens.iter().enumerate().skip(open) {
+            if opens(token) {
+                depth += 1;
+            } else if closes(token) {
+                depth -= 1;
+                if depth == 0 {
+                    return (close > open + 1).then_some((open, close));
+                }
+            }
+        }
+        None
+    })
+}
 
-    #[test]
-    fn arg_extracted_for_only_arg_str() {
-        assert_eq!(
-            extract_fmt(&str_lit("{}")).unwrap(),
-            [FmtSpec::Arg { span: 1..3 }]
-        );
+fn lex(source_code: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut start = 0;
+    while let Some(c) = source_code[start..].chars().next() {
+        let rest = &source_code[start..];
+        let (kind, len) = if c.is_whitespace() {
+            start += c.len_utf8();
+            continue;
+        } else if rest.starts_with("//") {
+            let line = &rest[..rest.find('\n').unwrap_or(rest.len())];
+            (Kind::Comment, line.trim_end_matches('\r').len())
+        } else if c == '"' {
+            let mut escaped = false;
+            let end = rest[1..].find(|c| {
+                let closes = c == '"' && !escaped;
+                escaped = c == '\\' && !escaped;
+                closes
+            });
+            (Kind::Str, end.map_or(rest.len(), |end| end + 2))
+        } else if c.is_ascii_digit() {
+            let len = rest
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .unwrap_or(rest.len());
+            (Kind::Int, len)
+        } else if c.is_alphabetic() || c == '_' {
+            let len = rest
+                .find(|c: char| !c.is_alphanumeric() && c != '_')
+                .unwrap_or(rest.len());
+            // Macro names end with a `!`.
+            let len = len + rest[len..].starts_with('!') as usize;
+            (Kind::Word, len)
+        } else {
+            let len = PUNCTUATION
+                .iter()
+                .find(|punctuation| rest.starts_with(*punctuation))
+                .map_or(c.len_utf8(), |punctuation| punctuation.len());
+            (Kind::Punct, len)
+        };
+        let span = start..start + len;
+        tokens.push(Token {
+            kind,
+            text: &source_code[span.clone()],
+            span,
+            role: Role::Normal,
+        });
+        start += len;
     }
+    assign_roles(&mut tokens);
+    tokens
+}
 
-    #[test]
-    fn arg_extracted_for_only_arg_str_with_space_in_middle() {
-        assert_eq!(
-            extract_fmt(&str_lit("{  }")).unwrap(),
-            [FmtSpec::Arg { span: 1..5 }]
-        );
+fn assign_roles(tokens: &mut [Token]) {
+    let mut in_params = false;
+    let mut before: Vec<usize> = Vec::new();
+    for i in 0..tokens.len() {
+        if tokens[i].kind == Kind::Comment {
+            continue;
+        }
+        let operand_expected = before.last().is_none_or(|&b| expects_operand(&tokens[b]));
+        tokens[i].role = match tokens[i].text {
+            "-" | "*" | "&" if operand_expected => Role::Prefix,
+            "|" if !in_params => Role::OpenPipe,
+            "|" => Role::ClosePipe,
+            ":" if before.len() >= 2
+                && tokens[before[before.len() - 2]].text == "$"
+                && tokens[before[before.len() - 1]].kind == Kind::Word =>
+            {
+                Role::Fragment
+            }
+            _ => Role::Normal,
+        };
+        if tokens[i].text == "|" {
+            in_params = !in_params;
+        }
+        before.push(i);
     }
+}
 
-    #[test]
-    fn error_on_unexpected_close_in_first_chunk() {
-        assert_eq!(extract_fmt(&str_lit("abc} {} ")).unwrap_err(), 4);
+/// Whether what follows `token` starts an operand, so a `-`, `*` or `&` after it is a prefix.
+fn expects_operand(token: &Token) -> bool {
+    match token.kind {
+        Kind::Word => is_keyword(token.text) && !name_like(token),
+        Kind::Punct => !matches!(token.text, ")" | "]" | "?"),
+        Kind::Int | Kind::Str | Kind::Comment => false,
     }
+}
 
-    #[test]
-    fn error_on_unexpected_close_in_last_chunk() {
-        assert_eq!(extract_fmt(&str_lit("{} {} abc}")).unwrap_err(), 10);
+/// Whether `token` names something a `(` right after it calls or constructs, or a `[` indexes.
+fn name_like(token: &Token) -> bool {
+    token.kind == Kind::Word
+        && (!is_keyword(token.text) || matches!(token.text, "self" | "Self" | "true" | "false"))
+}
+
+fn opens(token: &Token) -> bool {
+    token.kind == Kind::Punct && matches!(token.text, "{" | "(" | "[")
+}
+
+fn closes(token: &Token) -> bool {
+    token.kind == Kind::Punct && matches!(token.text, "}" | ")" | "]")
+}
+
+/// Groups `tokens`, lexed from `source_code`, into the lines they start on.
+fn lines(source_code: &str, tokens: &[Token]) -> Vec<Line> {
+    let mut lines: Vec<Line> = Vec::new();
+    let mut last_end = 0;
+    for (i, token) in tokens.iter().enumerate() {
+        match lines.last_mut() {
+            Some(line) if !source_code[last_end..token.span.start].contains('\n') => {
+                line.tokens.end = i + 1;
+            }
+            last => {
+                let gap_start = last.map_or(0, |line| line.end + 1);
+                let gap = &source_code[gap_start..token.span.start];
+                lines.push(Line {
+                    tokens: i..i + 1,
+                    gap_start,
+                    start: gap_start + gap.rfind('\n').map_or(0, |i| i + 1),
+                    end: 0,
+                    blank_before: gap.contains('\n'),
+                });
+            }
+        }
+        last_end = token.span.end;
+        let line = lines.last_mut().unwrap();
+        line.end = source_code[last_end..]
+            .find('\n')
+            .map_or(source_code.len(), |i| last_end + i);
     }
+    lines
+}
 
-    #[test]
-    fn error_when_extracting_unclosed_arg() {
-        assert_eq!(extract_fmt(&str_lit("abc{  ")).unwrap_err(), 4);
+/// How many levels each line is indented by: one more than the line that opened the innermost
+/// bracket still open at its start, or as many as that line if it starts by closing it. Lines
+/// continuing a method chain, starting with `.`, are indented once more.
+fn indents(tokens: &[Token], lines: &[Line]) -> Vec<usize> {
+    // The indentation of the lines each open bracket was opened on.
+    let mut open: Vec<usize> = Vec::new();
+    lines
+        .iter()
+        .map(|line| {
+            let tokens = &tokens[line.tokens.clone()];
+            let mut indent = open.last().map_or(0, |indent| indent + 1);
+            let closers = tokens.iter().take_while(|token| closes(token)).count();
+            for _ in 0..closers {
+                if let Some(opener) = open.pop() {
+                    indent = opener;
+                }
+            }
+            if tokens[0].text == "." {
+                indent += 1;
+            }
+            for token in &tokens[closers..] {
+                if opens(token) {
+                    open.push(indent);
+                } else if closes(token) {
+                    open.pop();
+                }
+            }
+            indent
+        })
+        .collect()
+}
+
+/// Whether a space separates `before` from `after`, which follows it on the same line.
+fn spaced(before: &Token, after: &Token) -> bool {
+    if after.kind == Kind::Comment {
+        return true;
     }
+    if matches!(before.role, Role::Prefix | Role::OpenPipe | Role::Fragment)
+        || matches!(after.role, Role::ClosePipe | Role::Fragment)
+    {
+        return false;
+    }
+    match (before.text, after.text) {
+        ("$" | "(" | "[" | "." | ".." | "::" | "::<" | "<" | "#" | "#!", _) => false,
+        (_, ")" | "]" | "," | ";" | "." | ".." | "?" | ":" | "::" | "::<" | "<" | ">") => false,
+        ("{", "}") => false,
+        (_, "(") => !(name_like(before) || matches!(before.text, ")" | "]" | ">")),
+        (_, "[") => !(name_like(before) || matches!(before.text, ")" | "]")),
+        _ => true,
+    }
+}
 
-    #[test]
-    fn error_when_extracting_arg_with_non_whitespace_chars() {
-        assert_eq!(extract_fmt(&str_lit("abc{ a 1 ; }")).unwrap_err(), 6);
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MESSY: &str = "
+
+// Adds one.
+fn   add_one( x :i64 )->i64{
+x+1 // the sum
+}
+
+
+
+#[test]
+fn  main( ) {
+    let v=vec! [ 1 ,2 ];
+  let f=|a:i64|-a*2;
+
+    for i in 0 .. v.len( ) {
+            println!( \"{}  }}\" , f( v[ i ] ) ) ;
     }
+    let s = &mut v ;
+    s.iter()
+    .map( |x| {
+    *x
+    } );
+}
+";
+
+    const FORMATTED: &str = "// Adds one.
+fn add_one(x: i64) -> i64 {
+    x + 1 // the sum
+}
+
+#[test]
+fn main() {
+    let v = vec![1, 2];
+    let f = |a: i64| -a * 2;
+
+    for i in 0..v.len() {
+        println!(\"{}  }}\", f(v[i]));
+    }
+    let s = &mut v;
+    s.iter()
+        .map(|x| {
+            *x
+        });
+}
+";
 
     #[test]
-    fn arg_and_lit_extracted_when_arg_at_beginning_of_str() {
+    fn formatting_normalizes_indentation_spacing_and_blank_lines() {
+        assert_eq!(format_source(MESSY, None), FORMATTED);
+        assert_eq!(format_source(FORMATTED, None), FORMATTED);
+        let rules = "macro_rules! twice { ( $x : expr ) => { $x + $x } }\n";
         assert_eq!(
-            extract_fmt(&str_lit("{} abc")).unwrap(),
-            [
-                FmtSpec::Arg { span: 1..3 },
-                FmtSpec::Lit {
-                    span: 3..7,
-                    val: " abc"
-                }
-            ]
+            format_source(rules, None),
+            "macro_rules! twice { ($x:expr) => { $x + $x } }\n"
         );
     }
 
     #[test]
-    fn lit_and_arg_and_lit_extracted_when_arg_in_middle_of_str() {
+    fn config_sets_indent_width_line_width_and_brace_style() {
+        let config = Config {
+            indent_width: 2,
+            max_width: 30,
+            brace_style: BraceStyle::NextLine,
+        };
+        let src =
+            "fn f(a: i64, b: i64) -> i64 {\n    g(a, |x, y| x + y, long_name_that_doesnt_fit)\n}\n";
+        let formatted = "fn f(a: i64, b: i64) -> i64\n{\n  g(\n    a,\n    |x, y| x + y,\n    \
+                         long_name_that_doesnt_fit,\n  )\n}\n";
+        assert_eq!(format_source_with(src, None, &config), formatted);
+        assert_eq!(format_source_with(formatted, None, &config), formatted);
         assert_eq!(
-            extract_fmt(&str_lit("abc {} def")).unwrap(),
-            [
-                FmtSpec::Lit {
-                    span: 1..5,
-                    val: "abc "
-                },
-                FmtSpec::Arg { span: 5..7 },
-                FmtSpec::Lit {
-                    span: 7..11,
-                    val: " def"
-                }
-            ]
+            format_source("fn f()\n{\n    1\n}\n", None),
+            "fn f() {\n    1\n}\n"
         );
     }
 
     #[test]
-    fn lit_and_arg_extracted_when_arg_at_end_of_str() {
+    fn crlf_line_breaks_are_kept() {
+        let crlf = |src: &str| src.replace('\n', "\r\n");
+        assert_eq!(format_source(&crlf(MESSY), None), crlf(FORMATTED));
+        let src = crlf("fn  f( ) {\nlet  a=1; // one\n  let  b=2;\n}");
+        let start = src.find("let  b").unwrap();
         assert_eq!(
-            extract_fmt(&str_lit("abc {}")).unwrap(),
-            [
-                FmtSpec::Lit {
-                    span: 1..5,
-                    val: "abc "
-                },
-                FmtSpec::Arg { span: 5..7 },
-            ]
+            format_source(&src, Some(start..start + 3)),
+            crlf("fn  f( ) {\nlet  a=1; // one\n    let b = 2;\n}")
         );
     }
 
     #[test]
-    fn two_args_extracted_when_two_args_are_adjacent_in_str() {
+    fn range_formatting_only_touches_the_lines_in_range() {
+        let src = "fn  f( ) {\nlet  a=1;\n  let  b=2;\n}";
+        let start = src.find("let  b").unwrap();
         assert_eq!(
-            extract_fmt(&str_lit("{}{}")).unwrap(),
-            [FmtSpec::Arg { span: 1..3 }, FmtSpec::Arg { span: 3..5 },]
+            format_source(src, Some(start..start + 3)),
+            "fn  f( ) {\nlet  a=1;\n    let b = 2;\n}"
         );
     }
 }
@@ -0,0 +1,407 @@
+//! Parses the format strings `print!`, `format!` and the like take into literal pieces and the
+//! `{}` arguments between them.
+
+use combine::parser::range::recognize;
+use combine::Parser;
+use combine::{satisfy, skip_many, skip_many1, token};
+
+use std::ops::Range;
+
+use crate::syntax::StrLit;
+
+/// The specs in `input`, with spans in the source: past its escape sequences, or all of `input` if
+/// it was expanded from a macro like `include_str!`.
+pub fn extract_fmt(input: &StrLit) -> Result<Vec<FmtSpec<'static>>, FmtError> {
+    let lit = input;
+    extract_specs(lit.val.as_str(), |span| match lit.is_in_source() {
+        true => lit.source_offset(span.start)..lit.source_offset(span.end),
+        false => lit.span.clone(),
+    })
+}
+
+/// Extracts the specs from `input` as if it were the whole of a string literal, for fuzzing.
+/// Never panics; malformed strings are errors.
+pub fn extract_fmt_for_fuzzing(input: &str) -> Result<Vec<FmtSpec<'_>>, FmtError> {
+    // Past the literal's opening quote.
+    extract_specs(input, |span| span.start + 1..span.end + 1)
+}
+
+/// The specs in `input`, with spans in the source as `source_span` maps them from offsets into
+/// `input`.
+fn extract_specs(
+    input: &str,
+    source_span: impl Fn(Range<usize>) -> Range<usize>,
+) -> Result<Vec<FmtSpec<'_>>, FmtError> {
+    let lit_parser = || recognize(skip_many1(satisfy(|c| c != '{' && c != '}')));
+    let spec_parser = || {
+        recognize((
+            token('{'),
+            skip_many(satisfy(|c| c != '{' && c != '}')),
+            token('}'),
+        ))
+    };
+
+    let mut location = 0;
+    let mut input = input;
+    let mut specs = Vec::new();
+
+    while !input.is_empty() {
+        let spec = if input.starts_with("{{") || input.starts_with("}}") {
+            // A doubled brace is an escaped one.
+            let span = location..(location + 2);
+            location = span.end;
+            let val = &input[..1];
+            input = &input[2..];
+            Ok(FmtSpec::Lit {
+                val,
+                span: source_span(span),
+            })
+        } else if let Ok((val, rest)) = lit_parser().parse(input) {
+            let span = location..(location + val.len());
+            location = span.end;
+            input = rest;
+            Ok(FmtSpec::Lit {
+                val,
+                span: source_span(span),
+            })
+        } else if let Ok((spec, rest)) = spec_parser().parse(input) {
+            let format = Format::parse(
+                spec[1..spec.len() - 1].trim_matches(|c: char| c.is_ascii_whitespace()),
+            );
+            match format {
+                Some(format) => {
+                    let span = location..(location + spec.len());
+                    location = span.end;
+                    input = rest;
+                    Ok(FmtSpec::Arg {
+                        span: source_span(span),
+                        format,
+                    })
+                }
+                None => {
+                    let offset = spec
+                        .find(|c: char| !c.is_ascii_whitespace() && c != '{' && c != '}')
+                        .unwrap();
+                    Err(FmtError::InvalidSpec(
+                        source_span(location + offset..location + offset).start,
+                    ))
+                }
+            }
+        } else {
+            // Only a brace that doesn't open or close a spec stops both parsers.
+            Err(FmtError::UnmatchedBrace(
+                source_span(location..location + 1),
+                input.chars().next().unwrap(),
+            ))
+        }?;
+        specs.push(spec);
+    }
+
+    Ok(specs)
+}
+
+/// Why a format string couldn't be parsed, with where in the source it's reported.
+#[derive(Debug, PartialEq)]
+pub enum FmtError {
+    /// A `{` or `}` that neither opens nor closes a spec, and isn't escaped by doubling it.
+    UnmatchedBrace(Range<usize>, char),
+    /// Something other than whitespace or one of the formats after a `:` between a spec's braces.
+    InvalidSpec(usize),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum FmtSpec<'s> {
+    Lit {
+        span: Range<usize>,
+        val: &'s str,
+    },
+    /// `{}`, or `{:?}` and the like for other formats.
+    Arg {
+        span: Range<usize>,
+        format: Format,
+    },
+}
+
+/// How a spec formats its argument, by what follows the `:` between its braces.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    /// `{}`, for strings, integers, `bool`s and types implementing `Display`.
+    Display,
+    /// `{:?}`, for values built from builtin types and tuple structs.
+    Debug,
+    /// `{:x}`, for integers in lowercase hexadecimal.
+    LowerHex,
+    /// `{:X}`, for integers in uppercase hexadecimal.
+    UpperHex,
+    /// `{:o}`, for integers in octal.
+    Octal,
+    /// `{:b}`, for integers in binary.
+    Binary,
+}
+
+impl Format {
+    /// The format between a spec's braces, with the whitespace around it trimmed.
+    fn parse(spec: &str) -> Option<Format> {
+        Some(match spec {
+            "" => Format::Display,
+            ":?" => Format::Debug,
+            ":x" => Format::LowerHex,
+            ":X" => Format::UpperHex,
+            ":o" => Format::Octal,
+            ":b" => Format::Binary,
+            _ => return None,
+        })
+    }
+
+    /// The spec formatting this way, like `{:x}`.
+    pub fn spec(self) -> &'static str {
+        match self {
+            Format::Display => "{}",
+            Format::Debug => "{:?}",
+            Format::LowerHex => "{:x}",
+            Format::UpperHex => "{:X}",
+            Format::Octal => "{:o}",
+            Format::Binary => "{:b}",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intern::Symbol;
+    use crate::syntax::{unescape, NodeId};
+
+    fn str_lit(val: &str) -> StrLit {
+        StrLit {
+            id: NodeId(0),
+            span: 0..val.len() + 2,
+            val: Symbol::intern(val),
+            escapes: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn literal_extracted_for_plain_str() {
+        assert_eq!(
+            extract_fmt(&str_lit("abc")).unwrap(),
+            [FmtSpec::Lit {
+                span: 1..4,
+                val: "abc"
+            }]
+        );
+    }
+
+    #[test]
+    fn arg_extracted_for_only_arg_str() {
+        assert_eq!(
+            extract_fmt(&str_lit("{}")).unwrap(),
+            [FmtSpec::Arg {
+                span: 1..3,
+                format: Format::Display
+            }]
+        );
+    }
+
+    #[test]
+    fn arg_extracted_for_only_arg_str_with_space_in_middle() {
+        assert_eq!(
+            extract_fmt(&str_lit("{  }")).unwrap(),
+            [FmtSpec::Arg {
+                span: 1..5,
+                format: Format::Display
+            }]
+        );
+    }
+
+    #[test]
+    fn debug_arg_extracted_for_question_mark_spec() {
+        assert_eq!(
+            extract_fmt(&str_lit("{:?}")).unwrap(),
+            [FmtSpec::Arg {
+                span: 1..5,
+                format: Format::Debug
+            }]
+        );
+        assert_eq!(
+            extract_fmt(&str_lit("{:y}")).unwrap_err(),
+            FmtError::InvalidSpec(2)
+        );
+    }
+
+    #[test]
+    fn radix_args_extracted_for_their_specs() {
+        let formats: Vec<_> = extract_fmt(&str_lit("{:x}{:X}{ :o }{:b}"))
+            .unwrap()
+            .into_iter()
+            .map(|spec| match spec {
+                FmtSpec::Arg { format, .. } => format,
+                FmtSpec::Lit { .. } => unreachable!(),
+            })
+            .collect();
+        assert_eq!(
+            formats,
+            [
+                Format::LowerHex,
+                Format::UpperHex,
+                Format::Octal,
+                Format::Binary
+            ]
+        );
+    }
+
+    #[test]
+    fn error_on_unexpected_close_in_first_chunk() {
+        assert_eq!(
+            extract_fmt(&str_lit("abc} {} ")).unwrap_err(),
+            FmtError::UnmatchedBrace(4..5, '}')
+        );
+    }
+
+    #[test]
+    fn error_on_unexpected_close_in_last_chunk() {
+        assert_eq!(
+            extract_fmt(&str_lit("{} {} abc}")).unwrap_err(),
+            FmtError::UnmatchedBrace(10..11, '}')
+        );
+    }
+
+    #[test]
+    fn doubled_braces_are_extracted_as_literal_braces() {
+        assert_eq!(
+            extract_fmt(&str_lit("{{{}}}")).unwrap(),
+            [
+                FmtSpec::Lit {
+                    span: 1..3,
+                    val: "{"
+                },
+                FmtSpec::Arg {
+                    span: 3..5,
+                    format: Format::Display
+                },
+                FmtSpec::Lit {
+                    span: 5..7,
+                    val: "}"
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn error_when_extracting_unclosed_arg() {
+        assert_eq!(
+            extract_fmt(&str_lit("abc{  ")).unwrap_err(),
+            FmtError::UnmatchedBrace(4..5, '{')
+        );
+    }
+
+    #[test]
+    fn error_when_extracting_arg_with_non_whitespace_chars() {
+        assert_eq!(
+            extract_fmt(&str_lit("abc{ a 1 ; }")).unwrap_err(),
+            FmtError::InvalidSpec(6)
+        );
+    }
+
+    #[test]
+    fn arg_and_lit_extracted_when_arg_at_beginning_of_str() {
+        assert_eq!(
+            extract_fmt(&str_lit("{} abc")).unwrap(),
+            [
+                FmtSpec::Arg {
+                    span: 1..3,
+                    format: Format::Display
+                },
+                FmtSpec::Lit {
+                    span: 3..7,
+                    val: " abc"
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn lit_and_arg_and_lit_extracted_when_arg_in_middle_of_str() {
+        assert_eq!(
+            extract_fmt(&str_lit("abc {} def")).unwrap(),
+            [
+                FmtSpec::Lit {
+                    span: 1..5,
+                    val: "abc "
+                },
+                FmtSpec::Arg {
+                    span: 5..7,
+                    format: Format::Display
+                },
+                FmtSpec::Lit {
+                    span: 7..11,
+                    val: " def"
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn lit_and_arg_extracted_when_arg_at_end_of_str() {
+        assert_eq!(
+            extract_fmt(&str_lit("abc {}")).unwrap(),
+            [
+                FmtSpec::Lit {
+                    span: 1..5,
+                    val: "abc "
+                },
+                FmtSpec::Arg {
+                    span: 5..7,
+                    format: Format::Display
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn two_args_extracted_when_two_args_are_adjacent_in_str() {
+        assert_eq!(
+            extract_fmt(&str_lit("{}{}")).unwrap(),
+            [
+                FmtSpec::Arg {
+                    span: 1..3,
+                    format: Format::Display
+                },
+                FmtSpec::Arg {
+                    span: 3..5,
+                    format: Format::Display
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn spans_after_escape_sequences_point_into_the_source() {
+        let source = r#"\"{}\n}"#;
+        let (val, escapes) = unescape(source);
+        let lit = StrLit {
+            id: NodeId(0),
+            span: 10..source.len() + 12,
+            val: Symbol::intern(&val),
+            escapes,
+        };
+        assert_eq!(
+            extract_fmt(&lit).unwrap_err(),
+            FmtError::UnmatchedBrace(17..18, '}')
+        );
+        let (val, escapes) = unescape(&source[..source.len() - 1]);
+        let lit = StrLit {
+            span: 10..source.len() + 11,
+            val: Symbol::intern(&val),
+            escapes,
+            ..lit
+        };
+        assert_eq!(
+            extract_fmt(&lit).unwrap()[1],
+            FmtSpec::Arg {
+                span: 13..15,
+                format: Format::Display
+            }
+        );
+    }
+}
@@ -0,0 +1,194 @@
+//! The test harness behind `sculpt test`, which runs a file's `#[test]` functions and the code
+//! blocks in its doc comments, or every program in a directory of UI tests, comparing what each
+//! prints against the `.expected` file beside it.
+
+use std::io::{self, Write};
+
+use crate::syntax::{Doc, Program};
+
+/// A fenced code block in a doc comment, run as a program of its own.
+#[derive(Debug, PartialEq)]
+pub struct DocTest {
+    /// The item it documents and the line the block starts on, like `add (line 3)`.
+    pub name: String,
+    /// The file it's in, with everything but the block's code blanked out, so spans into it
+    /// are spans into the doc comment.
+    pub source_code: String,
+}
+
+impl DocTest {
+    /// Whether the block is the statements of a `main` function, rather than a whole program.
+    pub fn is_statements(&self) -> bool {
+        !self.source_code.contains("fn main")
+    }
+}
+
+/// Every ```` ``` ```` or ```` ```sculpt ```` block in the doc comments of `program`, which was
+/// parsed from `source_code`. Blocks tagged with another language, like ```` ```text ````,
+/// aren't tests.
+pub fn doctests(program: &Program, source_code: &str) -> Vec<DocTest> {
+    let items = program
+        .structs
+        .iter()
+        .map(|s| (s.name.name.as_str().to_string(), &s.docs))
+        .chain(program.all_functions().map(|(ty, function)| {
+            let name = match ty {
+                Some(ty) => format!("{}::{}", ty.name.as_str(), function.name.name.as_str()),
+                None => function.name.name.as_str().to_string(),
+            };
+            (name, &function.docs)
+        }));
+    let mut tests = Vec::new();
+    for (name, docs) in items {
+        let mut lines = docs.iter();
+        while let Some(fence) = lines.next() {
+            let Some(info) = fence.text.strip_prefix("```") else {
+                continue;
+            };
+            let code: Vec<&Doc> = lines
+                .by_ref()
+                .take_while(|doc| !doc.text.starts_with("```"))
+                .collect();
+            if matches!(info.trim(), "" | "sculpt") {
+                let line = source_code[..fence.span.start].matches('\n').count() + 1;
+                tests.push(DocTest {
+                    name: format!("{} (line {})", name, line),
+                    source_code: blank_except(source_code, &code),
+                });
+            }
+        }
+    }
+    tests
+}
+
+/// `source_code` with every character but line breaks and those in `code` replaced by a space.
+fn blank_except(source_code: &str, code: &[&Doc]) -> String {
+    let mut blanked: Vec<u8> = source_code
+        .bytes()
+        .map(|b| if b == b'\n' { b } else { b' ' })
+        .collect();
+    for doc in code {
+        blanked[doc.span.clone()].copy_from_slice(doc.text.as_bytes());
+    }
+    String::from_utf8(blanked).unwrap()
+}
+
+#[derive(Debug)]
+pub struct TestResult {
+    pub name: String,
+    pub passed: bool,
+}
+
+/// What a UI test wrote to stdout, and to stderr along with its rendered warnings and errors.
+#[derive(Debug, Default, PartialEq)]
+pub struct UiOutput {
+    pub stdout: String,
+    pub stderr: String,
+}
+
+impl UiOutput {
+    /// The contents of the test's `.expected` file: a `--- stdout` or `--- stderr` section for each
+    /// stream that was written to, with trailing whitespace trimmed from every line.
+    pub fn expected(&self) -> String {
+        let mut expected = String::new();
+        for (name, output) in [("stdout", &self.stdout), ("stderr", &self.stderr)] {
+            if output.trim().is_empty() {
+                continue;
+            }
+            expected.push_str(&format!("--- {}\n", name));
+            for line in output.trim_end().lines() {
+                expected.push_str(line.trim_end());
+                expected.push('\n');
+            }
+        }
+        expected
+    }
+}
+
+/// The lines of `expected` and `actual`, prefixed with `-` if only `expected` has them, `+` if
+/// only `actual` does, and a space if both do.
+pub fn diff(expected: &str, actual: &str) -> String {
+    let expected: Vec<&str> = expected.lines().collect();
+    let actual: Vec<&str> = actual.lines().collect();
+    // common[i][j] is the length of the longest common subsequence of expected[i..] and actual[j..].
+    let mut common = vec![vec![0; actual.len() + 1]; expected.len() + 1];
+    for i in (0..expected.len()).rev() {
+        for j in (0..actual.len()).rev() {
+            common[i][j] = if expected[i] == actual[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+    let (mut i, mut j) = (0, 0);
+    let mut diff = String::new();
+    while i < expected.len() || j < actual.len() {
+        let line = if i < expected.len() && j < actual.len() && expected[i] == actual[j] {
+            i += 1;
+            j += 1;
+            format!(" {}", expected[i - 1])
+        } else if i < expected.len() && (j == actual.len() || common[i + 1][j] >= common[i][j + 1])
+        {
+            i += 1;
+            format!("-{}", expected[i - 1])
+        } else {
+            j += 1;
+            format!("+{}", actual[j - 1])
+        };
+        diff.push_str(&line);
+        diff.push('\n');
+    }
+    diff
+}
+
+/// Writes a line per test, then a summary like `cargo test`'s.
+pub fn write_results(results: &[TestResult], mut writer: impl Write) -> io::Result<()> {
+    for result in results {
+        let outcome = if result.passed { "ok" } else { "FAILED" };
+        writeln!(writer, "test {} ... {}", result.name, outcome)?;
+    }
+    let passed = results.iter().filter(|result| result.passed).count();
+    writeln!(
+        writer,
+        "\ntest result: {}. {} passed; {} failed",
+        if passed == results.len() {
+            "ok"
+        } else {
+            "FAILED"
+        },
+        passed,
+        results.len() - passed
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::run::parse;
+
+    #[test]
+    fn doctests_keep_their_code_where_it_is_in_the_file() {
+        let src = "/// ```\n/// f();\n/// ```\n/// ```text\n/// no\n/// ```\nfn f() {}";
+        let tests = doctests(&parse(src).unwrap(), src);
+        assert_eq!(
+            tests,
+            [DocTest {
+                name: "f (line 1)".to_string(),
+                source_code: "       \n    f();\n       \n           \n      \n       \n         "
+                    .to_string(),
+            }]
+        );
+        assert!(tests[0].is_statements());
+    }
+
+    #[test]
+    fn ui_outputs_diff_by_line() {
+        let output = UiOutput {
+            stdout: String::new(),
+            stderr: "error  \n  here\n\n".to_string(),
+        };
+        assert_eq!(output.expected(), "--- stderr\nerror\n  here\n");
+        assert_eq!(diff("a\nb\nc\n", "a\nc\nd\n"), " a\n-b\n c\n+d\n");
+    }
+}
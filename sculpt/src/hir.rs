@@ -0,0 +1,4696 @@
+//! High-level IR that codegen lowers from. Macros are expanded, names are resolved to the
+//! builtins they refer to, and every expression has a type, so codegen never sees surface syntax.
+
+use lalrpop_util::ParseError;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::mem;
+use std::ops::Range;
+
+use crate::expand::expand;
+use crate::fmt_str::{extract_fmt, FmtError, FmtSpec, Format};
+use crate::intern::{sym, Symbol};
+use crate::lint;
+use crate::run::Error;
+use crate::syntax::{self, BinOp};
+
+#[derive(Debug, PartialEq)]
+pub struct Program {
+    pub structs: Vec<Struct>,
+    pub statics: Vec<Static>,
+    pub functions: Vec<Function>,
+}
+
+impl Program {
+    /// Orders the structs, statics and functions by name, rather than by where they're defined,
+    /// so they're emitted in the same order however the source is laid out.
+    pub fn sort_by_name(&mut self) {
+        self.structs.sort_by_key(|s| s.name.as_str());
+        self.statics.sort_by_key(|s| s.name.as_str());
+        self.functions
+            .sort_by_key(|function| function.name.as_str());
+    }
+}
+
+/// A `static`, whose value was worked out when the program was compiled.
+#[derive(Clone, Debug, Hash, PartialEq)]
+pub struct Static {
+    pub name: Symbol,
+    pub ty: Type,
+    pub value: Const,
+}
+
+/// The value of a `static`.
+#[derive(Clone, Debug, Hash, PartialEq)]
+pub enum Const {
+    Int(i64),
+    Str(Symbol),
+    Bool(bool),
+}
+
+impl Const {
+    fn ty(&self) -> Type {
+        match self {
+            Const::Int(_) => Type::Int,
+            Const::Str(_) => Type::Str,
+            Const::Bool(_) => Type::Bool,
+        }
+    }
+}
+
+/// A tuple struct.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Struct {
+    pub name: Symbol,
+    pub fields: Vec<Type>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Function {
+    /// Functions in an `impl` are named after the type it's for, like `Point::norm`.
+    pub name: Symbol,
+    pub attrs: Vec<Symbol>,
+    /// Declared `pub` outside of an `impl`, so libraries export it.
+    pub public: bool,
+    /// Type parameters. A generic function is only built once instantiated with the types its
+    /// callers pass.
+    pub generics: Vec<Symbol>,
+    pub params: Vec<(Local, Type)>,
+    pub ret: Type,
+    pub body: Vec<Stmt>,
+    /// What the function returns once its statements have run, unless it returns `()`.
+    pub tail: Option<Expr>,
+    /// The functions of the closures it defines, which take the values they capture followed by
+    /// their parameters. They're generic over the function's type parameters, if it has any.
+    pub closures: Vec<Function>,
+}
+
+impl Function {
+    /// Whether the host can call the function, as the program's `main`, a benchmark or a library
+    /// export. It can if it takes no parameters. Whatever it returns is discarded, except for the
+    /// error of a `Result` that can be written out, which is reported like one `main` returns.
+    pub fn is_entry_point(&self) -> bool {
+        self.params.is_empty() && self.generics.is_empty()
+    }
+
+    /// The function with its type parameters replaced by `args`, named after them.
+    pub fn instantiate(&self, args: &[Type]) -> Function {
+        let function = self.clone();
+        let generics = &self.generics;
+        Function {
+            name: instance_name(self.name, args),
+            attrs: function.attrs,
+            public: false,
+            generics: Vec::new(),
+            params: function
+                .params
+                .into_iter()
+                .map(|(local, ty)| (local, ty.substitute(generics, args)))
+                .collect(),
+            ret: function.ret.substitute(generics, args),
+            body: function
+                .body
+                .into_iter()
+                .map(|stmt| stmt.substitute(generics, args))
+                .collect(),
+            tail: function.tail.map(|tail| tail.substitute(generics, args)),
+            // They're instantiated as they're called, as generic functions of their own.
+            closures: Vec::new(),
+        }
+    }
+}
+
+/// The name of the function `name` instantiated with the type arguments `args`, like
+/// `show::<Point>`.
+pub fn instance_name(name: Symbol, args: &[Type]) -> Symbol {
+    let args: Vec<_> = args.iter().map(Type::to_string).collect();
+    Symbol::intern(&format!("{}::<{}>", name, args.join(", ")))
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Stmt {
+    /// Writes each expression to the stream in order.
+    Print(Stream, Vec<Expr>),
+    /// Evaluates the expression for its side effects.
+    Expr(Expr),
+    /// Blocks for the given number of milliseconds. Panics if it's negative.
+    Sleep(Expr),
+    /// Replaces the file at the first path with the second string. Panics if it can't be written.
+    WriteFile(Expr, Expr),
+    /// Stops the program with the given status. Panics if it doesn't fit in an `i32`.
+    Exit(Expr),
+    /// Runs the statements once for each item the iterator yields, bound to the pattern.
+    For(Pattern, Expr, Vec<Stmt>),
+    /// Evaluates the `bool` expression over and over, until it's `false`. A `while let` is one
+    /// evaluating a `match` whose arm for the pattern runs the body.
+    While(Expr),
+    /// Runs the statements in a scope of their own.
+    Block(Vec<Stmt>),
+    /// Binds the value to the pattern, which always matches it, for the rest of the block.
+    Let(Pattern, Expr),
+}
+
+impl Stmt {
+    fn substitute(self, params: &[Symbol], args: &[Type]) -> Stmt {
+        let sub = |expr: Expr| expr.substitute(params, args);
+        match self {
+            Stmt::Print(stream, exprs) => Stmt::Print(stream, exprs.into_iter().map(sub).collect()),
+            Stmt::Expr(expr) => Stmt::Expr(sub(expr)),
+            Stmt::Sleep(millis) => Stmt::Sleep(sub(millis)),
+            Stmt::WriteFile(path, contents) => Stmt::WriteFile(sub(path), sub(contents)),
+            Stmt::Exit(status) => Stmt::Exit(sub(status)),
+            Stmt::For(pattern, iter, body) => Stmt::For(
+                pattern,
+                sub(iter),
+                body.into_iter()
+                    .map(|stmt| stmt.substitute(params, args))
+                    .collect(),
+            ),
+            Stmt::While(condition) => Stmt::While(sub(condition)),
+            Stmt::Block(body) => Stmt::Block(
+                body.into_iter()
+                    .map(|stmt| stmt.substitute(params, args))
+                    .collect(),
+            ),
+            Stmt::Let(pattern, value) => Stmt::Let(pattern, sub(value)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expr {
+    Str(Symbol),
+    Int(i64),
+    /// The value of the named `static`, of the type.
+    Static(Symbol, Type),
+    /// Runs the statements in a scope of their own, then evaluates the expression in it.
+    Block(Vec<Stmt>, Box<Expr>),
+    /// Panics on overflow.
+    Neg(Box<Expr>),
+    /// Panics on overflow and division by zero.
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+    /// The next line of stdin, without its line ending.
+    ReadLine,
+    /// Everything left on stdin.
+    ReadToString,
+    /// `Some` of the value of the named environment variable when the program runs, or `None` if
+    /// it's unset.
+    GetEnv(Box<Expr>),
+    /// A random integer in `lo..hi`. Panics if the range is empty.
+    RandomInt(Box<Expr>, Box<Expr>),
+    /// Milliseconds since the Unix epoch.
+    NowMillis,
+    /// The contents of the file at the path. Panics if it can't be read.
+    ReadFile(Box<Expr>),
+    /// The pieces concatenated into a new string.
+    Format(Vec<Expr>),
+    /// `Some(value)`.
+    Some(Box<Expr>),
+    /// `None`, holding the type a `Some` in its place would hold.
+    None(Type),
+    /// The value an `Option` holds. Panics, reporting the span of the call, if it's `None`.
+    Unwrap(Box<Expr>, Range<usize>),
+    /// The body of the first arm whose pattern matches the scrutinee, which has the given type.
+    Match(Box<Expr>, Vec<Arm>, Type),
+    /// A value a pattern bound.
+    Local(Local, Type),
+    /// `Ok(value)`, holding the type an `Err` in its place would hold.
+    Ok(Box<Expr>, Type),
+    /// `Err(error)`, holding the type an `Ok` in its place would hold.
+    Err(Type, Box<Expr>),
+    /// `result?`, the value a `Result` holds if it's `Ok`. An `Err` is returned from the function,
+    /// which returns a `Result` with the same error type.
+    Try(Box<Expr>),
+    /// `()`.
+    Unit,
+    /// Calls the function with the arguments, methods' receivers first. Returns the type.
+    Call(Callee, Vec<Expr>, Type),
+    /// A value of the named tuple struct, built from its fields.
+    Struct(Symbol, Vec<Expr>),
+    /// The field of a tuple struct at the index, which has the type.
+    Field(Box<Expr>, usize, Type),
+    /// A closure, by the name of its function and the type arguments that function is
+    /// instantiated with, holding the values it captures.
+    Closure(Symbol, Vec<Type>, Vec<Expr>),
+    /// Calls the closure with the arguments. Returns the type.
+    CallClosure(Box<Expr>, Vec<Expr>, Type),
+    /// A reference to the value, mutable if the flag is set.
+    Ref(Box<Expr>, bool),
+    /// The value a reference, box or `Rc` refers to.
+    Deref(Box<Expr>),
+    /// `Box::new(value)`, which moves the value to the heap.
+    Box(Box<Expr>),
+    /// `Rc::new(value)`, which moves the value to the heap to be shared.
+    Rc(Box<Expr>),
+    /// A clone of the `Rc`, sharing what it holds.
+    CloneRc(Box<Expr>),
+    /// The bytes of the string from the start bound, or its start, to the end bound, or its end.
+    /// Panics, reporting the span of the slice, unless both bounds are in the string, in order and
+    /// on char boundaries.
+    Slice(
+        Box<Expr>,
+        Option<Box<Expr>>,
+        Option<Box<Expr>>,
+        Range<usize>,
+    ),
+    /// The length of the string in bytes, or of the `Vec` in elements.
+    Len(Box<Expr>),
+    /// `true` or `false`.
+    Bool(bool),
+    /// A new string with each char of the string mapped to uppercase.
+    ToUppercase(Box<Expr>),
+    /// A new string with each char of the string mapped to lowercase.
+    ToLowercase(Box<Expr>),
+    /// The string without its leading and trailing whitespace.
+    Trim(Box<Expr>),
+    /// Whether the first string contains the second.
+    Contains(Box<Expr>, Box<Expr>),
+    /// A new `Vec` of the elements, which have the type.
+    Vec(Vec<Expr>, Type),
+    /// The integers from the first up to but not including the second.
+    Range(Box<Expr>, Box<Expr>),
+    /// An iterator over the chars of the string, each yielded as a string of its own.
+    Chars(Box<Expr>),
+    /// An iterator over the elements of the `Vec`, or references to them if it's a reference to
+    /// one. An iterator is its own.
+    IntoIter(Box<Expr>),
+    /// The first item the iterator yields, or `None` if it yields none.
+    Next(Box<Expr>),
+    /// A new, empty `HashMap` with keys and values of the types.
+    HashMap(Type, Type),
+    /// A reference to the value the `HashMap` holds for the key, or `None` if it holds none.
+    Get(Box<Expr>, Box<Expr>),
+    /// A copy of the `HashMap` holding the value for the key, in place of any value it held for
+    /// it. Values can't change, so unlike Rust's, this leaves the `HashMap` itself as it was.
+    Insert(Box<Expr>, Box<Expr>, Box<Expr>),
+    /// A copy of the `HashMap` without the key, or any value for it.
+    Remove(Box<Expr>, Box<Expr>),
+    /// A copy of the `Vec` with its items in order, or in the order of the keys the closure, if
+    /// any, gives them. Like Rust's, the sort is stable, but it leaves the `Vec` itself as it was.
+    /// Holds the type of the keys with the closure.
+    Sort(Box<Expr>, Option<(Box<Expr>, Type)>),
+    /// The value formatted like `{:?}` formats it.
+    Debug(Box<Expr>),
+    /// The integer formatted in the base of the format, one of `{:x}`, `{:X}`, `{:o}` and `{:b}`.
+    Radix(Box<Expr>, Format),
+}
+
+/// The function a call calls.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Callee {
+    /// A function by its qualified name, instantiated with the type arguments if it's generic.
+    Function(Symbol, Vec<Type>),
+    /// The method of the type parameter's trait bounds with the name, which is resolved to the
+    /// function implementing it once the parameter is instantiated.
+    Method(Type, Symbol),
+}
+
+impl Callee {
+    fn substitute(self, params: &[Symbol], args: &[Type]) -> Callee {
+        match self {
+            Callee::Function(name, type_args) => Callee::Function(
+                name,
+                type_args
+                    .iter()
+                    .map(|ty| ty.substitute(params, args))
+                    .collect(),
+            ),
+            Callee::Method(ty, method) => match ty.substitute(params, args) {
+                Type::Struct(ty) => Callee::Function(qualified_name(Some(ty), method), Vec::new()),
+                ty => Callee::Method(ty, method),
+            },
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Arm {
+    pub pattern: Pattern,
+    pub body: Expr,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Pattern {
+    Wildcard,
+    /// Matches anything, binding it to the local.
+    Bind(Local),
+    /// `true` or `false`.
+    Bool(bool),
+    /// A string literal.
+    Str(Symbol),
+    None,
+    Some(Box<Pattern>),
+    Ok(Box<Pattern>),
+    Err(Box<Pattern>),
+    /// A tuple struct, whose fields match the patterns.
+    Struct(Vec<Pattern>),
+}
+
+/// A value bound by a pattern, numbered uniquely within its function.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Local(pub u32);
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Type {
+    Str,
+    Int,
+    Bool,
+    /// `()`, what functions without a return type return.
+    Unit,
+    Option(Box<Type>),
+    Result(Box<Type>, Box<Type>),
+    /// A tuple struct, by name.
+    Struct(Symbol),
+    /// A type parameter of the function it's used in, or `Self` in a trait.
+    Param(Symbol),
+    /// A closure, by the name of its function, with the type arguments that function is
+    /// instantiated with and the types of the values it captures.
+    Closure(Symbol, Box<[Type]>, Box<[Type]>),
+    /// `&T`, or `&mut T` if the flag is set. Nothing can change the value a reference refers to,
+    /// so reading through one copies it.
+    Ref(Box<Type>, bool),
+    /// `Box<T>`, a value on the heap that's freed when what owns the box is done with it.
+    Box(Box<Type>),
+    /// `Rc<T>`, a value on the heap shared by each clone of the `Rc`, and freed once they're all
+    /// done with it. Like a reference, reading through one copies what it holds.
+    Rc(Box<Type>),
+    /// `Vec<T>`, values on the heap, one after another.
+    Vec(Box<Type>),
+    /// `Range<i64>`, an iterator over the integers from its start up to its end.
+    Range,
+    /// `Chars`, an iterator over the chars of a string.
+    Chars,
+    /// `IntoIter<T>`, an iterator over the elements of the `Vec` it owns.
+    IntoIter(Box<Type>),
+    /// `Iter<T>`, an iterator over references to the elements of the `Vec` it borrows.
+    Iter(Box<Type>),
+    /// `HashMap<K, V>`, values on the heap found by their keys, which are strings, integers or
+    /// `bool`s.
+    HashMap(Box<Type>, Box<Type>),
+    /// A type nothing has pinned down yet, like the one a bare `None` holds.
+    Unknown,
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Str => f.write_str("str"),
+            Type::Int => f.write_str("i64"),
+            Type::Bool => f.write_str("bool"),
+            Type::Unit => f.write_str("()"),
+            Type::Option(ty) => write!(f, "Option<{}>", ty),
+            Type::Result(ok, error) => write!(f, "Result<{}, {}>", ok, error),
+            Type::Struct(name) | Type::Param(name) | Type::Closure(name, ..) => {
+                write!(f, "{}", name)
+            }
+            Type::Ref(ty, false) => write!(f, "&{}", ty),
+            Type::Ref(ty, true) => write!(f, "&mut {}", ty),
+            Type::Box(ty) => write!(f, "Box<{}>", ty),
+            Type::Rc(ty) => write!(f, "Rc<{}>", ty),
+            Type::Vec(ty) => write!(f, "Vec<{}>", ty),
+            Type::Range => f.write_str("Range<i64>"),
+            Type::Chars => f.write_str("Chars"),
+            Type::IntoIter(ty) => write!(f, "IntoIter<{}>", ty),
+            Type::Iter(ty) => write!(f, "Iter<{}>", ty),
+            Type::HashMap(key, value) => write!(f, "HashMap<{}, {}>", key, value),
+            Type::Unknown => f.write_str("_"),
+        }
+    }
+}
+
+impl Type {
+    /// What the outermost part of the type is, in words, for diagnostics to explain it with. The
+    /// types inside it are named as they're written.
+    pub fn describe(&self) -> String {
+        match self {
+            Type::Str => "a string".into(),
+            Type::Int => "a 64-bit signed integer".into(),
+            Type::Bool => "a boolean".into(),
+            Type::Unit => "the unit type, whose only value is `()`".into(),
+            Type::Option(ty) => format!("an optional `{}`", ty),
+            Type::Result(ok, error) => format!("either an `Ok({})` or an `Err({})`", ok, error),
+            Type::Struct(name) => format!("the tuple struct `{}`", name),
+            Type::Param(name) => format!("the type parameter `{}`", name),
+            Type::Closure(..) => "a closure".into(),
+            Type::Ref(ty, false) => format!("a reference to a `{}`", ty),
+            Type::Ref(ty, true) => format!("a mutable reference to a `{}`", ty),
+            Type::Box(ty) => format!("a `{}` on the heap", ty),
+            Type::Rc(ty) => format!("a shared, reference-counted `{}`", ty),
+            Type::Vec(ty) => format!("a growable list of `{}`s", ty),
+            Type::Range => "a range of integers".into(),
+            Type::Chars => "an iterator over the chars of a string".into(),
+            Type::IntoIter(ty) => format!("an iterator yielding `{}`s", ty),
+            Type::Iter(ty) => format!("an iterator yielding `&{}`s", ty),
+            Type::HashMap(key, value) => format!("a map from `{}` keys to `{}` values", key, value),
+            Type::Unknown => "a type nothing has pinned down yet".into(),
+        }
+    }
+
+    /// The most specific type that's both `self` and `other`, if they're compatible.
+    pub fn unify(&self, other: &Type) -> Option<Type> {
+        match (self, other) {
+            (Type::Unknown, ty) | (ty, Type::Unknown) => Some(ty.clone()),
+            (Type::Option(lhs), Type::Option(rhs)) => Some(Type::Option(Box::new(lhs.unify(rhs)?))),
+            (Type::Result(lhs_ok, lhs_error), Type::Result(rhs_ok, rhs_error)) => {
+                Some(Type::Result(
+                    Box::new(lhs_ok.unify(rhs_ok)?),
+                    Box::new(lhs_error.unify(rhs_error)?),
+                ))
+            }
+            (Type::Ref(lhs, lhs_mutable), Type::Ref(rhs, rhs_mutable))
+                if lhs_mutable == rhs_mutable =>
+            {
+                Some(Type::Ref(Box::new(lhs.unify(rhs)?), *lhs_mutable))
+            }
+            (Type::Box(lhs), Type::Box(rhs)) => Some(Type::Box(Box::new(lhs.unify(rhs)?))),
+            (Type::Rc(lhs), Type::Rc(rhs)) => Some(Type::Rc(Box::new(lhs.unify(rhs)?))),
+            (Type::Vec(lhs), Type::Vec(rhs)) => Some(Type::Vec(Box::new(lhs.unify(rhs)?))),
+            (Type::IntoIter(lhs), Type::IntoIter(rhs)) => {
+                Some(Type::IntoIter(Box::new(lhs.unify(rhs)?)))
+            }
+            (Type::Iter(lhs), Type::Iter(rhs)) => Some(Type::Iter(Box::new(lhs.unify(rhs)?))),
+            (Type::HashMap(lhs_key, lhs_value), Type::HashMap(rhs_key, rhs_value)) => {
+                Some(Type::HashMap(
+                    Box::new(lhs_key.unify(rhs_key)?),
+                    Box::new(lhs_value.unify(rhs_value)?),
+                ))
+            }
+            (lhs, rhs) if lhs == rhs => Some(lhs.clone()),
+            _ => None,
+        }
+    }
+
+    /// The type with each of the type parameters `params` replaced by the type at the same index
+    /// of `args`.
+    pub fn substitute(&self, params: &[Symbol], args: &[Type]) -> Type {
+        match self {
+            Type::Param(name) => match params.iter().position(|param| param == name) {
+                Some(i) => args[i].clone(),
+                None => self.clone(),
+            },
+            Type::Option(ty) => Type::Option(Box::new(ty.substitute(params, args))),
+            Type::Result(ok, error) => Type::Result(
+                Box::new(ok.substitute(params, args)),
+                Box::new(error.substitute(params, args)),
+            ),
+            Type::Closure(name, type_args, captures) => Type::Closure(
+                *name,
+                type_args
+                    .iter()
+                    .map(|ty| ty.substitute(params, args))
+                    .collect(),
+                captures
+                    .iter()
+                    .map(|ty| ty.substitute(params, args))
+                    .collect(),
+            ),
+            Type::Ref(ty, mutable) => Type::Ref(Box::new(ty.substitute(params, args)), *mutable),
+            Type::Box(ty) => Type::Box(Box::new(ty.substitute(params, args))),
+            Type::Rc(ty) => Type::Rc(Box::new(ty.substitute(params, args))),
+            Type::Vec(ty) => Type::Vec(Box::new(ty.substitute(params, args))),
+            Type::IntoIter(ty) => Type::IntoIter(Box::new(ty.substitute(params, args))),
+            Type::Iter(ty) => Type::Iter(Box::new(ty.substitute(params, args))),
+            Type::HashMap(key, value) => Type::HashMap(
+                Box::new(key.substitute(params, args)),
+                Box::new(value.substitute(params, args)),
+            ),
+            _ => self.clone(),
+        }
+    }
+
+    /// Whether some part of the type is still unknown.
+    fn has_unknown(&self) -> bool {
+        match self {
+            Type::Unknown => true,
+            Type::Option(ty) => ty.has_unknown(),
+            Type::Result(ok, error) | Type::HashMap(ok, error) => {
+                ok.has_unknown() || error.has_unknown()
+            }
+            Type::Ref(ty, _)
+            | Type::Box(ty)
+            | Type::Rc(ty)
+            | Type::Vec(ty)
+            | Type::IntoIter(ty)
+            | Type::Iter(ty) => ty.has_unknown(),
+            _ => false,
+        }
+    }
+
+    /// Whether values of the type are copied when used, rather than moved.
+    fn is_copy(&self) -> bool {
+        match self {
+            Type::Int | Type::Bool | Type::Unit | Type::Ref(..) | Type::Unknown => true,
+            Type::Option(ty) => ty.is_copy(),
+            Type::Result(ok, error) => ok.is_copy() && error.is_copy(),
+            Type::Closure(_, _, captures) => captures.iter().all(Type::is_copy),
+            // Iterators are used up by what they yield.
+            Type::Str
+            | Type::Struct(_)
+            | Type::Param(_)
+            | Type::Box(_)
+            | Type::Rc(_)
+            | Type::Vec(_)
+            | Type::Range
+            | Type::Chars
+            | Type::IntoIter(_)
+            | Type::Iter(_)
+            | Type::HashMap(..) => false,
+        }
+    }
+
+    /// Whether values of the type hold references.
+    fn has_ref(&self) -> bool {
+        match self {
+            Type::Ref(..) | Type::Iter(_) => true,
+            Type::Option(ty)
+            | Type::Box(ty)
+            | Type::Rc(ty)
+            | Type::Vec(ty)
+            | Type::IntoIter(ty) => ty.has_ref(),
+            Type::Result(ok, error) | Type::HashMap(ok, error) => ok.has_ref() || error.has_ref(),
+            Type::Closure(_, _, captures) => captures.iter().any(Type::has_ref),
+            _ => false,
+        }
+    }
+
+    /// Whether values of the type can be the keys of a `HashMap`.
+    fn is_hashable(&self) -> bool {
+        matches!(self, Type::Str | Type::Int | Type::Bool | Type::Unknown)
+    }
+
+    /// Whether values of the type can be compared to sort by them. The runtime compares them like
+    /// the keys of a `HashMap`.
+    fn is_ordered(&self) -> bool {
+        self.is_hashable()
+    }
+
+    /// Whether values of the type can be formatted with `{:?}`, given the field types of each
+    /// tuple struct. A struct can be if its fields can, and those of the structs in `seen`, which
+    /// are already being checked, are assumed to be, so one can hold another of its type in a box.
+    fn is_debug(&self, structs: &BTreeMap<Symbol, Vec<Type>>, seen: &mut Vec<Symbol>) -> bool {
+        match self {
+            Type::Str | Type::Int | Type::Bool | Type::Unit => true,
+            Type::Option(ty) | Type::Ref(ty, _) | Type::Box(ty) | Type::Rc(ty) | Type::Vec(ty) => {
+                ty.is_debug(structs, seen)
+            }
+            Type::Result(ok, error) | Type::HashMap(ok, error) => {
+                ok.is_debug(structs, seen) && error.is_debug(structs, seen)
+            }
+            Type::Struct(name) if seen.contains(name) => true,
+            Type::Struct(name) => {
+                seen.push(*name);
+                structs[name].iter().all(|ty| ty.is_debug(structs, seen))
+            }
+            _ => false,
+        }
+    }
+
+    /// The iterator a `for` loop over a value of the type iterates with, if it can loop over one.
+    fn iterator(&self) -> Option<Type> {
+        match self {
+            Type::Vec(ty) => Some(Type::IntoIter(ty.clone())),
+            Type::Ref(vec, _) => match &**vec {
+                Type::Vec(ty) => Some(Type::Iter(ty.clone())),
+                _ => None,
+            },
+            ty => ty.item().map(|_| ty.clone()),
+        }
+    }
+
+    /// What an iterator of the type yields, if it's an iterator.
+    pub fn item(&self) -> Option<Type> {
+        match self {
+            Type::Range => Some(Type::Int),
+            Type::Chars => Some(Type::Str),
+            Type::IntoIter(ty) => Some((**ty).clone()),
+            Type::Iter(ty) => Some(Type::Ref(ty.clone(), false)),
+            _ => None,
+        }
+    }
+}
+
+impl Expr {
+    pub fn ty(&self) -> Type {
+        match self {
+            Expr::Str(_)
+            | Expr::ReadLine
+            | Expr::ReadToString
+            | Expr::Format(_)
+            | Expr::ReadFile(_)
+            | Expr::Slice(..)
+            | Expr::ToUppercase(_)
+            | Expr::ToLowercase(_)
+            | Expr::Trim(_)
+            | Expr::Debug(_)
+            | Expr::Radix(..) => Type::Str,
+            Expr::Int(_)
+            | Expr::Neg(_)
+            | Expr::Binary(..)
+            | Expr::RandomInt(..)
+            | Expr::NowMillis
+            | Expr::Len(_) => Type::Int,
+            Expr::Vec(_, ty) => Type::Vec(Box::new(ty.clone())),
+            Expr::Range(..) => Type::Range,
+            Expr::Chars(_) => Type::Chars,
+            Expr::IntoIter(value) => value.ty().iterator().unwrap_or(Type::Unknown),
+            Expr::Next(iter) => Type::Option(Box::new(iter.ty().item().unwrap_or(Type::Unknown))),
+            Expr::GetEnv(_) => Type::Option(Box::new(Type::Str)),
+            Expr::HashMap(key, value) => {
+                Type::HashMap(Box::new(key.clone()), Box::new(value.clone()))
+            }
+            Expr::Get(map, _) => match map.ty() {
+                Type::HashMap(_, value) => Type::Option(Box::new(Type::Ref(value, false))),
+                _ => Type::Unknown,
+            },
+            Expr::Insert(map, ..) | Expr::Remove(map, _) | Expr::Sort(map, _) => map.ty(),
+            Expr::Bool(_) | Expr::Contains(..) => Type::Bool,
+            Expr::Static(_, ty) => ty.clone(),
+            Expr::Block(_, tail) => tail.ty(),
+            Expr::Some(value) => Type::Option(Box::new(value.ty())),
+            Expr::None(ty) => Type::Option(Box::new(ty.clone())),
+            Expr::Unwrap(option, _) => match option.ty() {
+                Type::Option(ty) => *ty,
+                _ => Type::Unknown,
+            },
+            Expr::Match(.., ty) | Expr::Local(_, ty) => ty.clone(),
+            Expr::Ok(value, error) => Type::Result(Box::new(value.ty()), Box::new(error.clone())),
+            Expr::Err(ok, error) => Type::Result(Box::new(ok.clone()), Box::new(error.ty())),
+            Expr::Try(result) => match result.ty() {
+                Type::Result(ok, _) => *ok,
+                _ => Type::Unknown,
+            },
+            Expr::Unit => Type::Unit,
+            Expr::Call(.., ty) | Expr::Field(.., ty) => ty.clone(),
+            Expr::Struct(name, _) => Type::Struct(*name),
+            Expr::Closure(name, type_args, captures) => Type::Closure(
+                *name,
+                type_args.clone().into(),
+                captures.iter().map(Expr::ty).collect(),
+            ),
+            Expr::CallClosure(.., ty) => ty.clone(),
+            Expr::Ref(value, mutable) => Type::Ref(Box::new(value.ty()), *mutable),
+            Expr::Deref(reference) => match reference.ty() {
+                Type::Ref(ty, _) | Type::Box(ty) | Type::Rc(ty) => *ty,
+                _ => Type::Unknown,
+            },
+            Expr::Box(value) => Type::Box(Box::new(value.ty())),
+            Expr::Rc(value) => Type::Rc(Box::new(value.ty())),
+            Expr::CloneRc(rc) => rc.ty(),
+        }
+    }
+
+    /// Fills in the unknown parts of the expression's type from `ty`, a type it unifies with, so
+    /// that `None` in `Some(None)` knows what it would hold.
+    fn coerce(self, ty: &Type) -> Expr {
+        match (self, ty) {
+            (Expr::None(_), Type::Option(inner)) => Expr::None((**inner).clone()),
+            (Expr::Some(value), Type::Option(inner)) => Expr::Some(Box::new(value.coerce(inner))),
+            (Expr::Unwrap(option, span), ty) => Expr::Unwrap(
+                Box::new(option.coerce(&Type::Option(Box::new(ty.clone())))),
+                span,
+            ),
+            (Expr::Match(scrutinee, arms, _), ty) => Expr::Match(
+                scrutinee,
+                arms.into_iter()
+                    .map(|arm| Arm {
+                        pattern: arm.pattern,
+                        body: arm.body.coerce(ty),
+                    })
+                    .collect(),
+                ty.clone(),
+            ),
+            (Expr::Local(local, _), ty) => Expr::Local(local, ty.clone()),
+            (Expr::Block(stmts, tail), ty) => Expr::Block(stmts, Box::new(tail.coerce(ty))),
+            (Expr::Ref(value, mutable), Type::Ref(ty, _)) => {
+                Expr::Ref(Box::new(value.coerce(ty)), mutable)
+            }
+            (Expr::Box(value), Type::Box(ty)) => Expr::Box(Box::new(value.coerce(ty))),
+            (Expr::Rc(value), Type::Rc(ty)) => Expr::Rc(Box::new(value.coerce(ty))),
+            (Expr::Vec(elements, _), Type::Vec(ty)) => Expr::Vec(
+                elements
+                    .into_iter()
+                    .map(|element| element.coerce(ty))
+                    .collect(),
+                (**ty).clone(),
+            ),
+            (Expr::HashMap(..), Type::HashMap(key, value)) => {
+                Expr::HashMap((**key).clone(), (**value).clone())
+            }
+            (Expr::Ok(value, _), Type::Result(ok, error)) => {
+                Expr::Ok(Box::new(value.coerce(ok)), (**error).clone())
+            }
+            (Expr::Err(_, value), Type::Result(ok, error)) => {
+                Expr::Err((**ok).clone(), Box::new(value.coerce(error)))
+            }
+            (Expr::Try(result), ty) => {
+                let error = match result.ty() {
+                    Type::Result(_, error) => error,
+                    _ => Box::new(Type::Unknown),
+                };
+                Expr::Try(Box::new(
+                    result.coerce(&Type::Result(Box::new(ty.clone()), error)),
+                ))
+            }
+            (expr, _) => expr,
+        }
+    }
+
+    /// Whether evaluating the expression might read from stdin.
+    pub fn reads_stdin(&self) -> bool {
+        match self {
+            // The function called might read.
+            Expr::ReadLine | Expr::ReadToString | Expr::Call(..) | Expr::CallClosure(..) => true,
+            Expr::Str(_)
+            | Expr::Int(_)
+            | Expr::NowMillis
+            | Expr::None(_)
+            | Expr::Local(..)
+            | Expr::Unit
+            | Expr::Bool(_)
+            | Expr::Static(..)
+            | Expr::HashMap(..) => false,
+            Expr::Neg(expr)
+            | Expr::Some(expr)
+            | Expr::Unwrap(expr, _)
+            | Expr::Ok(expr, _)
+            | Expr::Err(_, expr)
+            | Expr::Try(expr)
+            | Expr::Field(expr, ..)
+            | Expr::Ref(expr, _)
+            | Expr::Deref(expr)
+            | Expr::Box(expr)
+            | Expr::Rc(expr)
+            | Expr::CloneRc(expr)
+            | Expr::Len(expr)
+            | Expr::ToUppercase(expr)
+            | Expr::ToLowercase(expr)
+            | Expr::Trim(expr)
+            | Expr::Chars(expr)
+            | Expr::IntoIter(expr)
+            | Expr::Next(expr)
+            | Expr::Debug(expr)
+            | Expr::Radix(expr, _) => expr.reads_stdin(),
+            Expr::Slice(value, start, end, _) => [Some(value), start.as_ref(), end.as_ref()]
+                .into_iter()
+                .flatten()
+                .any(|expr| expr.reads_stdin()),
+            Expr::Struct(_, args) | Expr::Closure(_, _, args) | Expr::Vec(args, _) => {
+                args.iter().any(Expr::reads_stdin)
+            }
+            Expr::Match(scrutinee, arms, _) => {
+                scrutinee.reads_stdin() || arms.iter().any(|arm| arm.body.reads_stdin())
+            }
+            // Conservatively, any statement might read.
+            Expr::Block(stmts, tail) => !stmts.is_empty() || tail.reads_stdin(),
+            Expr::Binary(_, lhs, rhs)
+            | Expr::RandomInt(lhs, rhs)
+            | Expr::Contains(lhs, rhs)
+            | Expr::Range(lhs, rhs)
+            | Expr::Get(lhs, rhs)
+            | Expr::Remove(lhs, rhs) => lhs.reads_stdin() || rhs.reads_stdin(),
+            Expr::Insert(map, key, value) => {
+                map.reads_stdin() || key.reads_stdin() || value.reads_stdin()
+            }
+            Expr::Sort(vec, key) => {
+                vec.reads_stdin() || key.iter().any(|(key, _)| key.reads_stdin())
+            }
+            Expr::Format(pieces) => pieces.iter().any(Expr::reads_stdin),
+            Expr::GetEnv(name) => name.reads_stdin(),
+            Expr::ReadFile(path) => path.reads_stdin(),
+        }
+    }
+
+    /// The expression with the type parameters `params` replaced by `args`, and the methods called
+    /// on them resolved to the functions implementing them.
+    fn substitute(self, params: &[Symbol], args: &[Type]) -> Expr {
+        let sub = |expr: Box<Expr>| Box::new(expr.substitute(params, args));
+        let ty = |ty: Type| ty.substitute(params, args);
+        let all = |exprs: Vec<Expr>| {
+            exprs
+                .into_iter()
+                .map(|expr| expr.substitute(params, args))
+                .collect()
+        };
+        match self {
+            Expr::Str(_)
+            | Expr::Int(_)
+            | Expr::ReadLine
+            | Expr::ReadToString
+            | Expr::NowMillis
+            | Expr::Unit
+            | Expr::Bool(_) => self,
+            Expr::Static(name, static_ty) => Expr::Static(name, ty(static_ty)),
+            Expr::Block(stmts, tail) => Expr::Block(
+                stmts
+                    .into_iter()
+                    .map(|stmt| stmt.substitute(params, args))
+                    .collect(),
+                sub(tail),
+            ),
+            Expr::Neg(expr) => Expr::Neg(sub(expr)),
+            Expr::Binary(op, lhs, rhs) => Expr::Binary(op, sub(lhs), sub(rhs)),
+            Expr::GetEnv(name) => Expr::GetEnv(sub(name)),
+            Expr::RandomInt(lo, hi) => Expr::RandomInt(sub(lo), sub(hi)),
+            Expr::ReadFile(path) => Expr::ReadFile(sub(path)),
+            Expr::Format(pieces) => Expr::Format(all(pieces)),
+            Expr::Some(value) => Expr::Some(sub(value)),
+            Expr::None(payload) => Expr::None(ty(payload)),
+            Expr::Unwrap(option, span) => Expr::Unwrap(sub(option), span),
+            Expr::Match(scrutinee, arms, result) => Expr::Match(
+                sub(scrutinee),
+                arms.into_iter()
+                    .map(|arm| Arm {
+                        pattern: arm.pattern,
+                        body: arm.body.substitute(params, args),
+                    })
+                    .collect(),
+                ty(result),
+            ),
+            Expr::Local(local, local_ty) => Expr::Local(local, ty(local_ty)),
+            Expr::Ok(value, error) => Expr::Ok(sub(value), ty(error)),
+            Expr::Err(ok, error) => Expr::Err(ty(ok), sub(error)),
+            Expr::Try(result) => Expr::Try(sub(result)),
+            Expr::Call(callee, call_args, ret) => {
+                Expr::Call(callee.substitute(params, args), all(call_args), ty(ret))
+            }
+            Expr::Struct(name, fields) => Expr::Struct(name, all(fields)),
+            Expr::Field(value, index, field_ty) => Expr::Field(sub(value), index, ty(field_ty)),
+            Expr::Closure(name, type_args, captures) => {
+                Expr::Closure(name, type_args.into_iter().map(ty).collect(), all(captures))
+            }
+            Expr::CallClosure(closure, call_args, ret) => {
+                Expr::CallClosure(sub(closure), all(call_args), ty(ret))
+            }
+            Expr::Ref(value, mutable) => Expr::Ref(sub(value), mutable),
+            Expr::Deref(reference) => Expr::Deref(sub(reference)),
+            Expr::Box(value) => Expr::Box(sub(value)),
+            Expr::Rc(value) => Expr::Rc(sub(value)),
+            Expr::CloneRc(rc) => Expr::CloneRc(sub(rc)),
+            Expr::Slice(value, start, end, span) => {
+                Expr::Slice(sub(value), start.map(sub), end.map(sub), span)
+            }
+            Expr::Len(value) => Expr::Len(sub(value)),
+            Expr::ToUppercase(value) => Expr::ToUppercase(sub(value)),
+            Expr::ToLowercase(value) => Expr::ToLowercase(sub(value)),
+            Expr::Trim(value) => Expr::Trim(sub(value)),
+            Expr::Contains(value, pattern) => Expr::Contains(sub(value), sub(pattern)),
+            Expr::Vec(elements, element) => Expr::Vec(all(elements), ty(element)),
+            Expr::Range(start, end) => Expr::Range(sub(start), sub(end)),
+            Expr::Chars(value) => Expr::Chars(sub(value)),
+            Expr::IntoIter(value) => Expr::IntoIter(sub(value)),
+            Expr::Next(iter) => Expr::Next(sub(iter)),
+            Expr::HashMap(key, value) => Expr::HashMap(ty(key), ty(value)),
+            Expr::Get(map, key) => Expr::Get(sub(map), sub(key)),
+            Expr::Insert(map, key, value) => Expr::Insert(sub(map), sub(key), sub(value)),
+            Expr::Remove(map, key) => Expr::Remove(sub(map), sub(key)),
+            Expr::Sort(vec, key) => Expr::Sort(sub(vec), key.map(|(key, ret)| (sub(key), ty(ret)))),
+            Expr::Debug(value) => Expr::Debug(sub(value)),
+            Expr::Radix(value, format) => Expr::Radix(sub(value), format),
+        }
+    }
+}
+
+/// What lowering resolved the names in a program to, keyed by their spans, for editors to look
+/// up.
+#[derive(Debug, Default, PartialEq)]
+pub struct Names {
+    /// The type of each local, where it's bound and everywhere it's used.
+    pub types: Vec<(Range<usize>, Type)>,
+    /// Where the local, function, struct or static each name refers to is defined. Definitions
+    /// refer to themselves.
+    pub defs: Vec<(Range<usize>, Range<usize>)>,
+    /// Where each item is defined, under its qualified name.
+    items: BTreeMap<Symbol, Range<usize>>,
+}
+
+impl Names {
+    /// The type of the innermost local spanning `offset`, if any.
+    pub fn type_at(&self, offset: usize) -> Option<(Range<usize>, &Type)> {
+        self.types
+            .iter()
+            .filter(|(span, _)| span.contains(&offset))
+            .min_by_key(|(span, _)| span.len())
+            .map(|(span, ty)| (span.clone(), ty))
+    }
+
+    /// Where what the innermost name spanning `offset` refers to is defined, if anything.
+    pub fn definition(&self, offset: usize) -> Option<Range<usize>> {
+        self.defs
+            .iter()
+            .filter(|(span, _)| span.contains(&offset))
+            .min_by_key(|(span, _)| span.len())
+            .map(|(_, def)| def.clone())
+    }
+
+    /// Every name referring to what the name at `offset` does, its definition included, in the
+    /// order they're in the source.
+    pub fn references(&self, offset: usize) -> Vec<Range<usize>> {
+        let Some(def) = self.definition(offset) else {
+            return Vec::new();
+        };
+        let mut references: Vec<_> = self
+            .defs
+            .iter()
+            .filter(|(_, d)| *d == def)
+            .map(|(span, _)| span.clone())
+            .collect();
+        references.sort_by_key(|span| span.start);
+        references.dedup();
+        references
+    }
+
+    fn define_item(&mut self, name: Symbol, span: Range<usize>) {
+        self.defs.push((span.clone(), span.clone()));
+        self.items.insert(name, span);
+    }
+
+    /// Records that `span` refers to the item with the qualified name `name`.
+    fn use_item(&mut self, span: Range<usize>, name: Symbol) {
+        if let Some(def) = self.items.get(&name) {
+            self.defs.push((span, def.clone()));
+        }
+    }
+}
+
+pub fn lower<'src>(program: syntax::Program) -> Result<Program, Error<'src>> {
+    lower_recording(program, &mut Names::default())
+}
+
+/// Lowers `program`, recording what its names resolve to in `names` as it goes, so those before
+/// any error are known.
+pub fn lower_recording<'src>(
+    program: syntax::Program,
+    names: &mut Names,
+) -> Result<Program, Error<'src>> {
+    let program = expand(program)?;
+    check_attrs(&program.attrs)?;
+    if let Some(attr) = program
+        .attrs
+        .iter()
+        .find(|attr| lint::Level::from_attr(attr.name.name).is_none())
+    {
+        return Err(Error::MisplacedAttribute(attr.span.clone()));
+    }
+    let items = Items::collect(&program)?;
+    for s in &program.structs {
+        names.define_item(s.name.name, s.name.span.clone());
+    }
+    for s in &program.statics {
+        names.define_item(s.name.name, s.name.span.clone());
+    }
+    for (ty, function) in program.all_functions() {
+        let name = qualified_name(ty.map(|ty| ty.name), function.name.name);
+        names.define_item(name, function.name.span.clone());
+    }
+    let functions = program
+        .into_all_functions()
+        .map(|(ty, function)| lower_function(function, ty.map(|ty| ty.name), &items, names))
+        .collect::<Result<_, _>>()?;
+    Ok(Program {
+        structs: items.structs(),
+        statics: items.statics(),
+        functions,
+    })
+}
+
+/// The structs a program defines and the signatures of its functions, which bodies refer to by
+/// name. They're collected before any body is lowered, so functions can use ones defined after
+/// them.
+#[derive(Debug, Default, Hash)]
+pub struct Items {
+    /// The field types of each tuple struct.
+    structs: BTreeMap<Symbol, Vec<Type>>,
+    /// Functions in an `impl` are under their qualified names, like `Point::norm`.
+    functions: BTreeMap<Symbol, Signature>,
+    /// The signatures of each trait's methods, where `Self` is the type parameter `Self`.
+    traits: BTreeMap<Symbol, BTreeMap<Symbol, Signature>>,
+    /// The structs and the traits they implement.
+    impls: BTreeSet<(Symbol, Symbol)>,
+    statics: BTreeMap<Symbol, Static>,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq)]
+struct Signature {
+    /// Type parameters, and the bounds on each.
+    generics: Vec<(Symbol, Vec<Bound>)>,
+    /// Starting with the receiver, for a method.
+    params: Vec<Type>,
+    ret: Type,
+    /// Whether the first parameter is `self`, so it can be called with method syntax.
+    method: bool,
+}
+
+/// What a type argument must satisfy.
+#[derive(Clone, Debug, Hash, PartialEq)]
+enum Bound {
+    /// Implementing the trait.
+    Trait(Symbol),
+    /// Being a closure taking the parameters and returning the type.
+    Fn(Vec<Type>, Type),
+}
+
+impl Bound {
+    fn substitute(&self, params: &[Symbol], args: &[Type]) -> Bound {
+        match self {
+            Bound::Trait(name) => Bound::Trait(*name),
+            Bound::Fn(fn_params, ret) => Bound::Fn(
+                fn_params
+                    .iter()
+                    .map(|ty| ty.substitute(params, args))
+                    .collect(),
+                ret.substitute(params, args),
+            ),
+        }
+    }
+}
+
+impl fmt::Display for Bound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Bound::Trait(name) => write!(f, "{}", name),
+            Bound::Fn(params, ret) => {
+                let params: Vec<_> = params.iter().map(Type::to_string).collect();
+                write!(f, "Fn({})", params.join(", "))?;
+                if *ret != Type::Unit {
+                    write!(f, " -> {}", ret)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Signature {
+    fn generic_names(&self) -> Vec<Symbol> {
+        self.generics.iter().map(|(name, _)| *name).collect()
+    }
+
+    /// The parameters and return type of the `Fn` bound on `ty`, if it's a type parameter with
+    /// one.
+    fn fn_bound(&self, ty: &Type) -> Option<(&[Type], &Type)> {
+        let Type::Param(param) = ty else {
+            return None;
+        };
+        let (_, bounds) = self.generics.iter().find(|(name, _)| name == param)?;
+        bounds.iter().find_map(|bound| match bound {
+            Bound::Fn(params, ret) => Some((&params[..], ret)),
+            Bound::Trait(_) => None,
+        })
+    }
+
+    /// The signature with the type parameters `params` replaced by `args`.
+    fn substitute(&self, params: &[Symbol], args: &[Type]) -> Signature {
+        Signature {
+            generics: self.generics.clone(),
+            params: self
+                .params
+                .iter()
+                .map(|ty| ty.substitute(params, args))
+                .collect(),
+            ret: self.ret.substitute(params, args),
+            method: self.method,
+        }
+    }
+}
+
+impl fmt::Display for Signature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let params: Vec<_> = self
+            .params
+            .iter()
+            .enumerate()
+            .map(|(i, ty)| match (i, ty) {
+                (0, Type::Ref(_, false)) if self.method => "&self".to_string(),
+                (0, Type::Ref(_, true)) if self.method => "&mut self".to_string(),
+                (0, _) if self.method => "self".to_string(),
+                _ => ty.to_string(),
+            })
+            .collect();
+        write!(f, "fn({})", params.join(", "))?;
+        if self.ret != Type::Unit {
+            write!(f, " -> {}", self.ret)?;
+        }
+        Ok(())
+    }
+}
+
+impl Items {
+    pub fn collect<'src>(program: &syntax::Program) -> Result<Items, Error<'src>> {
+        let mut items = Items::default();
+        // Structs, statics, traits and functions share one namespace, so no two may have the same
+        // name. Those other than functions are checked before any is collected, in the order
+        // they're defined.
+        let mut defined = BTreeMap::new();
+        let mut names: Vec<_> = (program.structs.iter().map(|s| &s.name))
+            .chain(program.statics.iter().map(|s| &s.name))
+            .chain(program.traits.iter().map(|t| &t.name))
+            .collect();
+        names.sort_by_key(|name| name.span.start);
+        for name in names {
+            if let Some(first) = defined.insert(name.name, &name.span) {
+                return Err(Error::DuplicateDefinition(
+                    name.span.clone(),
+                    first.clone(),
+                    None,
+                ));
+            }
+        }
+        // Every struct is named before any field is lowered, so fields can be of any of them.
+        for s in &program.structs {
+            items.structs.insert(s.name.name, Vec::new());
+        }
+        for s in &program.structs {
+            let fields = s
+                .fields
+                .iter()
+                .map(|ty| lower_type(ty.clone(), &items, None, &[]))
+                .collect::<Result<_, _>>()?;
+            items.structs.insert(s.name.name, fields);
+        }
+        for s in &program.structs {
+            let fields = items.structs.get(&s.name.name).ok_or_else(|| {
+                Error::InternalCompilerError(s.name.span.clone(), "struct was never collected")
+            })?;
+            if fields
+                .iter()
+                .any(|field| holds(field, s.name.name, &items.structs, &mut Vec::new()))
+            {
+                return Err(Error::RecursiveType(s.name.span.clone()));
+            }
+        }
+        // Statics can use each other in any order, as long as none ends up using itself.
+        let statics: BTreeMap<_, _> = program.statics.iter().map(|s| (s.name.name, s)).collect();
+        for s in &program.statics {
+            items.eval_static(s, &statics, &mut Vec::new())?;
+        }
+        // `Display` is defined for every program, though one can define its own instead.
+        let display = BTreeMap::from([(sym::FMT, display_fmt())]);
+        items.traits.insert(sym::DISPLAY, display);
+        for t in &program.traits {
+            items.traits.insert(t.name.name, BTreeMap::new());
+        }
+        let trait_self = Type::Param(sym::SELF_TYPE);
+        for t in &program.traits {
+            let mut methods = BTreeMap::new();
+            for method in &t.methods {
+                let signature =
+                    items.lower_signature(&method.params, &method.ret, &[], Some(&trait_self))?;
+                methods.insert(method.name.name, signature);
+            }
+            items.traits.insert(t.name.name, methods);
+        }
+        for i in &program.impls {
+            let ty = syntax::Ty::Path(syntax::TyPath {
+                id: i.ty.id,
+                span: i.ty.span.clone(),
+                name: i.ty.clone(),
+                args: Vec::new(),
+            });
+            match lower_type(ty, &items, None, &[]) {
+                Ok(Type::Struct(_)) => {}
+                Ok(ty) => return Err(Error::ImplOnBuiltinType(i.ty.span.clone(), ty)),
+                Err(error) => return Err(error),
+            }
+            if let Some(trait_name) = &i.trait_name {
+                if !items.traits.contains_key(&trait_name.name) {
+                    return Err(Error::UnknownTrait(trait_name.span.clone()));
+                }
+                items.impls.insert((i.ty.name, trait_name.name));
+            }
+        }
+        for (ty, function) in program.all_functions() {
+            let self_ty = ty.map(|ty| ty.name);
+            let signature = items.signature(function, self_ty)?;
+            let name = qualified_name(self_ty, function.name.name);
+            if let Some(first) = defined.insert(name, &function.name.span) {
+                let span = function.name.span.clone();
+                let Some(first_signature) = items.functions.get(&name) else {
+                    // The function clashes with another kind of item, which may come after it.
+                    let (first, span) = match first.start < span.start {
+                        true => (first.clone(), span),
+                        false => (span, first.clone()),
+                    };
+                    return Err(Error::DuplicateDefinition(span, first, None));
+                };
+                return Err(Error::DuplicateDefinition(
+                    span,
+                    first.clone(),
+                    (*first_signature != signature)
+                        .then(|| (first_signature.to_string(), signature.to_string())),
+                ));
+            }
+            items.functions.insert(name, signature);
+        }
+        for i in &program.impls {
+            if let Some(trait_name) = &i.trait_name {
+                items.check_impl(i, trait_name)?;
+            }
+        }
+        Ok(items)
+    }
+
+    fn signature<'src>(
+        &self,
+        function: &syntax::Function,
+        self_ty: Option<Symbol>,
+    ) -> Result<Signature, Error<'src>> {
+        for (i, param) in function.params.iter().enumerate() {
+            if let Some(first) = function.params[..i]
+                .iter()
+                .find(|first| first.name.name == param.name.name)
+            {
+                return Err(Error::DuplicateDefinition(
+                    param.name.span.clone(),
+                    first.name.span.clone(),
+                    None,
+                ));
+            }
+        }
+        let self_ty = self_ty.map(Type::Struct);
+        let signature = self.lower_signature(
+            &function.params,
+            &function.ret,
+            &function.generics,
+            self_ty.as_ref(),
+        )?;
+        check_attrs(&function.attrs)?;
+        let entry_point = self_ty.is_none()
+            && (function.name.name == sym::MAIN
+                || function.has_attr(sym::BENCH)
+                || function.has_attr(sym::TEST));
+        if entry_point && !(function.params.is_empty() && function.generics.is_empty()) {
+            return Err(Error::EntryPointParameters(function.name.span.clone()));
+        }
+        if self_ty.is_none() && function.name.name == sym::MAIN {
+            check_main_return_type(&function.ret, &signature.ret)?;
+        }
+        Ok(signature)
+    }
+
+    /// Lowers the signature of a function in an `impl` or trait for `self_ty`, if given.
+    fn lower_signature<'src>(
+        &self,
+        params: &[syntax::Param],
+        ret: &Option<syntax::Ty>,
+        generics: &[syntax::GenericParam],
+        self_ty: Option<&Type>,
+    ) -> Result<Signature, Error<'src>> {
+        let names: Vec<_> = generics.iter().map(|generic| generic.name.name).collect();
+        let mut bounded = Vec::new();
+        for generic in generics {
+            let mut bounds = Vec::new();
+            for bound in &generic.bounds {
+                bounds.push(match bound {
+                    syntax::Bound::Trait(name) if self.traits.contains_key(&name.name) => {
+                        Bound::Trait(name.name)
+                    }
+                    syntax::Bound::Trait(name) => {
+                        return Err(Error::UnknownTrait(name.span.clone()))
+                    }
+                    syntax::Bound::Fn(f) => {
+                        let lower = |ty: &syntax::Ty| lower_type(ty.clone(), self, self_ty, &names);
+                        Bound::Fn(
+                            f.params.iter().map(lower).collect::<Result<_, _>>()?,
+                            f.ret.as_ref().map_or(Ok(Type::Unit), lower)?,
+                        )
+                    }
+                });
+            }
+            bounded.push((generic.name.name, bounds));
+        }
+        let mut lowered = Vec::new();
+        for (i, param) in params.iter().enumerate() {
+            let is_self = param.name.name == sym::SELF_VALUE;
+            lowered.push(match (&param.ty, self_ty) {
+                _ if is_self && (i > 0 || self_ty.is_none()) => {
+                    return Err(Error::UnexpectedSelf(param.span.clone()))
+                }
+                (Some(ty), _) => lower_type(ty.clone(), self, self_ty, &names)?,
+                // Only `self` is declared without a type.
+                (None, Some(self_ty)) => self_ty.clone(),
+                (None, None) => return Err(Error::UnexpectedSelf(param.span.clone())),
+            });
+        }
+        let ret = match ret {
+            Some(ty) => lower_type(ty.clone(), self, self_ty, &names)?,
+            None => Type::Unit,
+        };
+        Ok(Signature {
+            generics: bounded,
+            method: params
+                .first()
+                .is_some_and(|param| param.name.name == sym::SELF_VALUE),
+            params: lowered,
+            ret,
+        })
+    }
+
+    /// Checks that `i` defines exactly the methods of the trait it implements, with the same
+    /// signatures.
+    fn check_impl<'src>(
+        &self,
+        i: &syntax::Impl,
+        trait_name: &syntax::Name,
+    ) -> Result<(), Error<'src>> {
+        let methods = self.traits.get(&trait_name.name).ok_or_else(|| {
+            Error::InternalCompilerError(trait_name.span.clone(), "trait was never collected")
+        })?;
+        let self_ty = [Type::Struct(i.ty.name)];
+        for function in &i.functions {
+            let Some(expected) = methods.get(&function.name.name) else {
+                return Err(Error::NotATraitMethod(
+                    function.name.span.clone(),
+                    trait_name.name,
+                ));
+            };
+            let expected = expected.substitute(&[sym::SELF_TYPE], &self_ty);
+            let found = self
+                .functions
+                .get(&qualified_name(Some(i.ty.name), function.name.name));
+            if found != Some(&expected) {
+                return Err(Error::MismatchedTraitMethod(
+                    function.name.span.clone(),
+                    expected.to_string(),
+                ));
+            }
+        }
+        let missing: Vec<_> = methods
+            .keys()
+            .filter(|method| !i.functions.iter().any(|f| f.name.name == **method))
+            .copied()
+            .collect();
+        if !missing.is_empty() {
+            return Err(Error::MissingTraitMethods(
+                trait_name.span.start..i.ty.span.end,
+                missing,
+            ));
+        }
+        Ok(())
+    }
+
+    /// Works out the value of the static `s`, first working out those of the statics it uses.
+    /// `evaluating` are the statics whose values are being worked out, which it can't use.
+    fn eval_static<'src>(
+        &mut self,
+        s: &syntax::Static,
+        statics: &BTreeMap<Symbol, &syntax::Static>,
+        evaluating: &mut Vec<Symbol>,
+    ) -> Result<Const, Error<'src>> {
+        if let Some(s) = self.statics.get(&s.name.name) {
+            return Ok(s.value.clone());
+        }
+        if s.mutable {
+            return Err(Error::StaticMut(s.span.start..s.name.span.end));
+        }
+        let ty = lower_type(s.ty.clone(), self, None, &[])?;
+        evaluating.push(s.name.name);
+        let value = self.eval_const(&s.value, statics, evaluating)?;
+        evaluating.pop();
+        // A string can be declared as `&str`, like a Rust one.
+        let matches = match (&ty, &value) {
+            (Type::Ref(ty, false), Const::Str(_)) => **ty == Type::Str,
+            (ty, value) => *ty == value.ty(),
+        };
+        if !matches {
+            return Err(Error::MismatchedStaticType(s.value.span(), ty, value.ty()));
+        }
+        let name = s.name.name;
+        let s = Static {
+            name,
+            ty,
+            value: value.clone(),
+        };
+        self.statics.insert(name, s);
+        Ok(value)
+    }
+
+    /// Works out the value of the initializer of a static, which may only use literals,
+    /// arithmetic and other statics.
+    fn eval_const<'src>(
+        &mut self,
+        expr: &syntax::Expr,
+        statics: &BTreeMap<Symbol, &syntax::Static>,
+        evaluating: &mut Vec<Symbol>,
+    ) -> Result<Const, Error<'src>> {
+        match expr {
+            syntax::Expr::Str(lit) => Ok(Const::Str(str_value(lit.val.as_str()))),
+            syntax::Expr::Int(lit) => int_value(lit, "").map(Const::Int),
+            syntax::Expr::Neg(neg) => {
+                if let syntax::Expr::Int(lit) = &*neg.expr {
+                    return int_value(lit, "-").map(Const::Int);
+                }
+                let value = self.eval_int(&neg.expr, statics, evaluating)?;
+                value.checked_neg().map(Const::Int).ok_or_else(|| {
+                    Error::StaticEvalFailed(neg.span.clone(), "attempt to negate with overflow")
+                })
+            }
+            syntax::Expr::Binary(binary) => {
+                let lhs = self.eval_int(&binary.lhs, statics, evaluating)?;
+                let rhs = self.eval_int(&binary.rhs, statics, evaluating)?;
+                let (value, message) = match binary.op {
+                    BinOp::Add => (lhs.checked_add(rhs), "attempt to add with overflow"),
+                    BinOp::Sub => (lhs.checked_sub(rhs), "attempt to subtract with overflow"),
+                    BinOp::Mul => (lhs.checked_mul(rhs), "attempt to multiply with overflow"),
+                    BinOp::Div if rhs == 0 => (None, "attempt to divide by zero"),
+                    BinOp::Div => (lhs.checked_div(rhs), "attempt to divide with overflow"),
+                    BinOp::Rem if rhs == 0 => (
+                        None,
+                        "attempt to calculate the remainder with a divisor of zero",
+                    ),
+                    BinOp::Rem => (
+                        lhs.checked_rem(rhs),
+                        "attempt to calculate the remainder with overflow",
+                    ),
+                };
+                value
+                    .map(Const::Int)
+                    .ok_or_else(|| Error::StaticEvalFailed(binary.span.clone(), message))
+            }
+            syntax::Expr::Name(name) if matches!(name.name, sym::TRUE | sym::FALSE) => {
+                Ok(Const::Bool(name.name == sym::TRUE))
+            }
+            syntax::Expr::Name(name) => match statics.get(&name.name) {
+                Some(_) if evaluating.contains(&name.name) => {
+                    Err(Error::CyclicStatic(name.span.clone(), name.name))
+                }
+                Some(s) => self.eval_static(s, statics, evaluating),
+                None => Err(Error::UnresolvedName(name.span.clone())),
+            },
+            expr => Err(Error::NonConstStatic(expr.span())),
+        }
+    }
+
+    /// Works out the value of an operand of arithmetic in a static's initializer, which must be
+    /// an integer.
+    fn eval_int<'src>(
+        &mut self,
+        expr: &syntax::Expr,
+        statics: &BTreeMap<Symbol, &syntax::Static>,
+        evaluating: &mut Vec<Symbol>,
+    ) -> Result<i64, Error<'src>> {
+        match self.eval_const(expr, statics, evaluating)? {
+            Const::Int(value) => Ok(value),
+            value => Err(Error::NonIntegerOperand(expr.span(), value.ty())),
+        }
+    }
+
+    /// The structs, for codegen to lay out.
+    pub fn structs(&self) -> Vec<Struct> {
+        self.structs
+            .iter()
+            .map(|(name, fields)| Struct {
+                name: *name,
+                fields: fields.clone(),
+            })
+            .collect()
+    }
+
+    /// The statics, for codegen to emit.
+    pub fn statics(&self) -> Vec<Static> {
+        self.statics.values().cloned().collect()
+    }
+}
+
+/// Whether values of type `ty` hold a `name` inline, which would make them infinitely large.
+/// `seen` are the structs already looked inside.
+fn holds(
+    ty: &Type,
+    name: Symbol,
+    structs: &BTreeMap<Symbol, Vec<Type>>,
+    seen: &mut Vec<Symbol>,
+) -> bool {
+    match ty {
+        Type::Option(ty) => holds(ty, name, structs, seen),
+        Type::Result(ok, error) => {
+            holds(ok, name, structs, seen) || holds(error, name, structs, seen)
+        }
+        Type::Struct(s) if *s == name => true,
+        Type::Struct(s) if !seen.contains(s) => {
+            seen.push(*s);
+            structs[s]
+                .iter()
+                .any(|field| holds(field, name, structs, seen))
+        }
+        _ => false,
+    }
+}
+
+/// The name a function is lowered under: its own, qualified by the type of the `impl` it's in.
+pub fn qualified_name(self_ty: Option<Symbol>, name: Symbol) -> Symbol {
+    match self_ty {
+        Some(ty) => Symbol::intern(&format!("{}::{}", ty, name)),
+        None => name,
+    }
+}
+
+/// Fails unless each of `attrs` is one the compiler knows, given arguments only if it takes them.
+/// `#[test]` and `#[bench]` mark functions the test harness and benchmarks call, and
+/// `#[allow(lint, ...)]` lists lints to silence in the function.
+fn check_attrs<'src>(attrs: &[syntax::Attr]) -> Result<(), Error<'src>> {
+    for attr in attrs {
+        let form = match attr.name.name {
+            sym::TEST => "#[test]",
+            sym::BENCH => "#[bench]",
+            sym::ALLOW => "#[allow(lint, ...)]",
+            sym::WARN => "#[warn(lint, ...)]",
+            sym::DENY => "#[deny(lint, ...)]",
+            _ => return Err(Error::UnknownAttribute(attr.name.span.clone())),
+        };
+        if attr.args.is_empty() == lint::Level::from_attr(attr.name.name).is_some() {
+            return Err(Error::MalformedAttribute(attr.span.clone(), form));
+        }
+    }
+    Ok(())
+}
+
+/// `main` returns either nothing or a `Result<(), E>`, for an `E` that can be written out when
+/// it's returned.
+fn check_main_return_type<'src>(ty: &Option<syntax::Ty>, ret: &Type) -> Result<(), Error<'src>> {
+    match (ty, ret) {
+        (None, _) => Ok(()),
+        (_, Type::Result(ok, error))
+            if **ok == Type::Unit && matches!(**error, Type::Str | Type::Int) =>
+        {
+            Ok(())
+        }
+        (Some(ty), ret) => Err(Error::UnsupportedReturnType(ty.span(), ret.clone())),
+    }
+}
+
+/// Lowers `function`, which is in an `impl` for `self_ty` if given, against the items of the
+/// program it's in, recording what its names resolve to in `names`.
+pub fn lower_function<'src>(
+    function: syntax::Function,
+    self_ty: Option<Symbol>,
+    items: &Items,
+    names: &mut Names,
+) -> Result<Function, Error<'src>> {
+    let name = qualified_name(self_ty, function.name.name);
+    let signature = items.functions.get(&name).ok_or_else(|| {
+        Error::InternalCompilerError(function.name.span.clone(), "function was never collected")
+    })?;
+    let mut scope = Scope {
+        items,
+        names,
+        name,
+        self_ty,
+        generics: signature.generics.clone(),
+        bindings: Vec::new(),
+        locals: Vec::new(),
+        next_local: 0,
+        ret: signature.ret.clone(),
+        closures: Vec::new(),
+        captures: Vec::new(),
+        moves: Vec::new(),
+        borrows: Vec::new(),
+    };
+    let params = function
+        .params
+        .iter()
+        .zip(&signature.params)
+        .map(|(param, ty)| (scope.bind(&param.name, ty.clone()), ty.clone()))
+        .collect();
+    let mut body = Vec::new();
+    for stmt in function.statements {
+        // Borrows only live until the end of the statement making them.
+        scope.borrows.clear();
+        lower_stmt(stmt, &mut body, &mut scope)?;
+    }
+    scope.borrows.clear();
+    let ret = signature.ret.clone();
+    let tail = match (function.tail, &ret) {
+        (Some(tail), _) => {
+            let tail = lower_expecting(tail, &ret, &mut scope, |span, found| {
+                Error::MismatchedReturnType(span, ret.clone(), found)
+            })?;
+            // Conservatively, any reference returned could be to a local the tail borrowed.
+            if let (true, Some(borrow)) = (ret.has_ref(), scope.borrows.first()) {
+                let end = function.span.end - 1..function.span.end;
+                return Err(Error::DanglingReference(borrow.2.clone(), borrow.1, end));
+            }
+            Some(tail)
+        }
+        (None, Type::Unit) => None,
+        // Running to the end of a function returning `Result<(), E>` returns `Ok(())`.
+        (None, Type::Result(ok, error)) if **ok == Type::Unit => {
+            Some(Expr::Ok(Box::new(Expr::Unit), (**error).clone()))
+        }
+        (None, ret) => {
+            let span = function.ret.map_or(function.name.span, |ty| ty.span());
+            return Err(Error::MismatchedReturnType(span, ret.clone(), Type::Unit));
+        }
+    };
+    Ok(Function {
+        name,
+        attrs: function
+            .attrs
+            .into_iter()
+            .map(|attr| attr.name.name)
+            .collect(),
+        // Only free functions are exported.
+        public: function.public && self_ty.is_none(),
+        generics: signature.generic_names(),
+        params,
+        ret,
+        body,
+        tail,
+        closures: scope.closures,
+    })
+}
+
+/// What an expression being lowered can refer to.
+struct Scope<'a> {
+    items: &'a Items,
+    names: &'a mut Names,
+    /// The qualified name of the function, which its closures are named after.
+    name: Symbol,
+    /// The type `Self` names, in an `impl`.
+    self_ty: Option<Symbol>,
+    /// The function's type parameters, and the bounds on each.
+    generics: Vec<(Symbol, Vec<Bound>)>,
+    /// Names parameters and patterns have bound, innermost last, so they shadow the ones before
+    /// them.
+    bindings: Vec<(Symbol, Local, Type)>,
+    /// Where each local was bound, by number.
+    locals: Vec<Range<usize>>,
+    next_local: u32,
+    /// What the function returns, which `?` returns errors in if it's a `Result`.
+    ret: Type,
+    /// The functions of the closures lowered so far.
+    closures: Vec<Function>,
+    /// For each closure being lowered, innermost last, the first local it binds and the locals
+    /// bound before it that it uses, which it captures.
+    captures: Vec<(u32, Vec<(Local, Type)>)>,
+    /// Locals moved out of so far, and where.
+    moves: Vec<(Local, Range<usize>)>,
+    /// Locals the statement being lowered borrowed, where, and whether mutably.
+    borrows: Vec<(Local, Symbol, Range<usize>, bool)>,
+}
+
+/// A local an expression reads from in place, like `x` in `x.0`, rather than through a reference.
+struct Place {
+    local: Local,
+    name: Symbol,
+    /// The expression's.
+    span: Range<usize>,
+}
+
+impl Scope<'_> {
+    /// The name `local` was bound to, if it's still in scope.
+    fn binding_name(&self, local: Local) -> Option<Symbol> {
+        self.bindings
+            .iter()
+            .find(|(_, bound, _)| *bound == local)
+            .map(|(name, ..)| *name)
+    }
+
+    fn bind(&mut self, name: &syntax::Name, ty: Type) -> Local {
+        let local = Local(self.next_local);
+        self.next_local += 1;
+        self.names.types.push((name.span.clone(), ty.clone()));
+        self.names.defs.push((name.span.clone(), name.span.clone()));
+        self.locals.push(name.span.clone());
+        self.bindings.push((name.name, local, ty));
+        local
+    }
+
+    /// The local `name` refers to, which the closures being lowered capture if it's bound outside
+    /// of them.
+    fn lookup(&mut self, name: &syntax::Name) -> Option<(Local, Type)> {
+        let (local, ty) = self
+            .bindings
+            .iter()
+            .rev()
+            .find(|(bound, ..)| *bound == name.name)
+            .map(|(_, local, ty)| (*local, ty.clone()))?;
+        self.names.types.push((name.span.clone(), ty.clone()));
+        let def = self.locals[local.0 as usize].clone();
+        self.names.defs.push((name.span.clone(), def));
+        for (first, captures) in &mut self.captures {
+            if local.0 < *first && !captures.iter().any(|(captured, _)| *captured == local) {
+                captures.push((local, ty.clone()));
+            }
+        }
+        Some((local, ty))
+    }
+
+    /// Fails if the local `name` refers to was moved out of before its use at `span`.
+    fn check_moved<'src>(
+        &self,
+        local: Local,
+        name: Symbol,
+        span: Range<usize>,
+    ) -> Result<(), Error<'src>> {
+        match self.moves.iter().find(|(moved, _)| *moved == local) {
+            Some((_, moved)) => Err(Error::UseAfterMove(span, name, moved.clone())),
+            None => Ok(()),
+        }
+    }
+
+    /// Moves a value of type `ty` out of `place`, unless values of the type are copied.
+    fn move_out<'src>(&mut self, place: Place, ty: &Type) -> Result<(), Error<'src>> {
+        if ty.is_copy() {
+            return Ok(());
+        }
+        if let Some((.., borrow, _)) = self.borrows.iter().find(|(b, ..)| *b == place.local) {
+            return Err(Error::MoveWhileBorrowed(
+                place.span,
+                place.name,
+                borrow.clone(),
+            ));
+        }
+        self.moves.push((place.local, place.span));
+        Ok(())
+    }
+
+    /// Borrows `place` at `span` until the end of the statement, failing if that conflicts with a
+    /// borrow of it already alive.
+    fn borrow<'src>(
+        &mut self,
+        place: Place,
+        mutable: bool,
+        span: Range<usize>,
+    ) -> Result<(), Error<'src>> {
+        let conflict = self
+            .borrows
+            .iter()
+            .find(|(b, .., m)| *b == place.local && (mutable || *m));
+        if let Some((.., borrow, _)) = conflict {
+            return Err(Error::ConflictingBorrow(span, place.name, borrow.clone()));
+        }
+        self.borrows.push((place.local, place.name, span, mutable));
+        Ok(())
+    }
+
+    /// Lowers a type written in the function, which can name its type parameters.
+    fn lower_type<'src>(&self, ty: syntax::Ty) -> Result<Type, Error<'src>> {
+        let self_ty = self.self_ty.map(Type::Struct);
+        let params: Vec<_> = self.generics.iter().map(|(name, _)| *name).collect();
+        lower_type(ty, self.items, self_ty.as_ref(), &params)
+    }
+
+    /// Whether `ty` implements the trait `name`, which a type parameter does if it's bound by it.
+    fn implements(&self, ty: &Type, name: Symbol) -> bool {
+        match ty {
+            Type::Struct(ty) => self.items.impls.contains(&(*ty, name)),
+            Type::Param(param) => self
+                .generics
+                .iter()
+                .any(|(bound, bounds)| bound == param && bounds.contains(&Bound::Trait(name))),
+            _ => false,
+        }
+    }
+
+    /// The signature of the method `name` of the traits bounding the type parameter `param`.
+    fn bound_method(&self, param: Symbol, name: Symbol) -> Option<&Signature> {
+        let (_, bounds) = self.generics.iter().find(|(bound, _)| *bound == param)?;
+        bounds
+            .iter()
+            .find_map(|bound| match bound {
+                Bound::Trait(t) => self.items.traits[t].get(&name),
+                Bound::Fn(..) => None,
+            })
+            .filter(|signature| signature.method)
+    }
+
+    /// The parameters and return type of values of type `ty` if they can be called: closures and
+    /// type parameters with an `Fn` bound.
+    fn callable(&self, ty: &Type) -> Option<(Vec<Type>, Type)> {
+        match ty {
+            Type::Closure(name, _, captures) => {
+                let closure = self.closures.iter().find(|f| f.name == *name)?;
+                let params = closure.params[captures.len()..].iter();
+                Some((
+                    params.map(|(_, ty)| ty.clone()).collect(),
+                    closure.ret.clone(),
+                ))
+            }
+            Type::Param(param) => {
+                let (_, bounds) = self.generics.iter().find(|(bound, _)| bound == param)?;
+                bounds.iter().find_map(|bound| match bound {
+                    Bound::Fn(params, ret) => Some((params.clone(), ret.clone())),
+                    Bound::Trait(_) => None,
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Lowers a type as written, where `Self` is `self_ty` if given and `params` name type
+/// parameters.
+fn lower_type<'src>(
+    ty: syntax::Ty,
+    items: &Items,
+    self_ty: Option<&Type>,
+    params: &[Symbol],
+) -> Result<Type, Error<'src>> {
+    let path = match ty {
+        syntax::Ty::Unit(_) => return Ok(Type::Unit),
+        syntax::Ty::Path(path) => path,
+        syntax::Ty::Ref(r) => {
+            let ty = lower_type(*r.ty, items, self_ty, params)?;
+            return Ok(Type::Ref(Box::new(ty), r.mutable));
+        }
+    };
+    let name = path.name.name;
+    let param = match (name, self_ty) {
+        (sym::SELF_TYPE, Some(self_ty)) => Some(self_ty.clone()),
+        (name, _) if params.contains(&name) => Some(Type::Param(name)),
+        _ => None,
+    };
+    if let Some(param) = param {
+        if !path.args.is_empty() {
+            return Err(Error::MismatchedArgumentCount(
+                path.name.span,
+                0,
+                path.args.len(),
+            ));
+        }
+        return Ok(param);
+    }
+    let arity = match name {
+        sym::STR | sym::I64 | sym::BOOL => 0,
+        sym::OPTION | sym::BOX | sym::RC | sym::VEC => 1,
+        sym::RESULT | sym::HASH_MAP => 2,
+        name if items.structs.contains_key(&name) => 0,
+        _ => return Err(Error::UnknownType(path.name.span)),
+    };
+    if path.args.len() != arity {
+        return Err(Error::MismatchedArgumentCount(
+            path.name.span,
+            arity,
+            path.args.len(),
+        ));
+    }
+    let name_span = path.name.span;
+    let key_span = path
+        .args
+        .first()
+        .map_or(name_span.clone(), syntax::Ty::span);
+    let mut args = path
+        .args
+        .into_iter()
+        .map(|arg| lower_type(arg, items, self_ty, params).map(Box::new))
+        .collect::<Result<Vec<_>, _>>()?
+        .into_iter();
+    let mut arg = || {
+        args.next().ok_or(Error::InternalCompilerError(
+            name_span.clone(),
+            "a type had fewer arguments than were counted",
+        ))
+    };
+    Ok(match name {
+        sym::STR => Type::Str,
+        sym::I64 => Type::Int,
+        sym::BOOL => Type::Bool,
+        sym::OPTION => Type::Option(arg()?),
+        sym::BOX => Type::Box(arg()?),
+        sym::RC => Type::Rc(arg()?),
+        sym::VEC => Type::Vec(arg()?),
+        sym::RESULT => Type::Result(arg()?, arg()?),
+        sym::HASH_MAP => match (arg()?, arg()?) {
+            (key, value) if key.is_hashable() => Type::HashMap(key, value),
+            (key, _) => return Err(Error::UnhashableKey(key_span, *key)),
+        },
+        name => Type::Struct(name),
+    })
+}
+
+fn lower_stmt<'src>(
+    stmt: syntax::Stmt,
+    body: &mut Vec<Stmt>,
+    scope: &mut Scope,
+) -> Result<(), Error<'src>> {
+    match stmt {
+        syntax::Stmt::Macro(m) => lower_macro(m, body, scope),
+        syntax::Stmt::For(f) => lower_for(f, body, scope),
+        syntax::Stmt::WhileLet(w) => lower_while_let(w, body, scope),
+        syntax::Stmt::IfLet(i) => {
+            body.push(Stmt::Expr(lower_if_let(i, scope)?));
+            Ok(())
+        }
+        syntax::Stmt::Block(block) => {
+            let (stmts, _) = lower_block(block, scope)?;
+            body.push(Stmt::Block(stmts));
+            Ok(())
+        }
+        syntax::Stmt::Let(l) => lower_let(l, body, scope),
+    }
+}
+
+/// Lowers a `let`. Without an annotation, its type is the value's, which has to be known in full
+/// by the end of the `let`, as nothing after it pins down the rest.
+fn lower_let<'src>(
+    l: syntax::Let,
+    body: &mut Vec<Stmt>,
+    scope: &mut Scope,
+) -> Result<(), Error<'src>> {
+    let value = match l.ty {
+        Some(ty) => {
+            let annotation = ty.span();
+            let ty = scope.lower_type(ty)?;
+            lower_expecting(l.value, &ty, scope, |span, found| {
+                Error::MismatchedLetType(span, ty.clone(), found, annotation)
+            })?
+        }
+        None => lower_expr(l.value, scope)?,
+    };
+    let ty = value.ty();
+    let pattern_span = l.pattern.span();
+    if ty.has_unknown() {
+        return Err(Error::AmbiguousType(pattern_span, ty));
+    }
+    let pattern = lower_pattern(l.pattern, &ty, scope)?;
+    if let Some(witness) = uncovered(&[&pattern], &ty, scope.items) {
+        return Err(Error::RefutableLetPattern(pattern_span, witness));
+    }
+    body.push(Stmt::Let(pattern, value));
+    Ok(())
+}
+
+/// Lowers a block's statements and tail. The names bound in it go out of scope at its end, and,
+/// like a function's, each of its statements' borrows end with the statement. The tail's last as
+/// long as the statement the block is in.
+fn lower_block<'src>(
+    block: syntax::Block,
+    scope: &mut Scope,
+) -> Result<(Vec<Stmt>, Option<Expr>), Error<'src>> {
+    let (bound, borrows) = (scope.bindings.len(), scope.borrows.len());
+    let mut stmts = Vec::new();
+    for stmt in block.statements {
+        scope.borrows.truncate(borrows);
+        lower_stmt(stmt, &mut stmts, scope)?;
+    }
+    scope.borrows.truncate(borrows);
+    let tail = block
+        .tail
+        .map(|tail| lower_expr(*tail, scope))
+        .transpose()?;
+    scope.bindings.truncate(bound);
+    Ok((stmts, tail))
+}
+
+/// Lowers a `for` loop. What the loop borrows to iterate stays borrowed throughout its body, and
+/// the body can't move out of the locals bound before it, as the next iteration would find them
+/// gone.
+fn lower_for<'src>(
+    f: syntax::For,
+    body: &mut Vec<Stmt>,
+    scope: &mut Scope,
+) -> Result<(), Error<'src>> {
+    let span = f.iter.span();
+    let iter = lower_expr(f.iter, scope)?;
+    let Some(item) = iter.ty().iterator().and_then(|ty| ty.item()) else {
+        return Err(Error::NotIterable(span, iter.ty()));
+    };
+    let outer = scope.next_local;
+    let (bound, moves, borrows) = (scope.bindings.len(), scope.moves.len(), scope.borrows.len());
+    let pattern_span = f.pattern.span();
+    let lowered = lower_pattern(f.pattern, &item, scope).and_then(|pattern| {
+        if let Some(witness) = uncovered(&[&pattern], &item, scope.items) {
+            return Err(Error::RefutableForPattern(pattern_span, witness));
+        }
+        let mut stmts = Vec::new();
+        for stmt in f.body {
+            scope.borrows.truncate(borrows);
+            lower_stmt(stmt, &mut stmts, scope)?;
+        }
+        Ok((pattern, stmts))
+    });
+    let moved = scope.moves[moves..]
+        .iter()
+        .find(|(local, _)| local.0 < outer)
+        .map(|(local, span)| match scope.binding_name(*local) {
+            Some(name) => Error::MovedInLoop(span.clone(), name),
+            None => unbound(span),
+        });
+    scope.bindings.truncate(bound);
+    let (pattern, stmts) = lowered?;
+    if let Some(error) = moved {
+        return Err(error);
+    }
+    body.push(Stmt::For(pattern, Expr::IntoIter(Box::new(iter)), stmts));
+    Ok(())
+}
+
+/// Lowers a `while let` to a loop evaluating `match value { pattern => { body; true } _ => false }`
+/// until it's `false`. Like a `for` loop's body, neither the body nor the value, which is
+/// evaluated again each time around, can move out of the locals bound before it.
+fn lower_while_let<'src>(
+    w: syntax::WhileLet,
+    body: &mut Vec<Stmt>,
+    scope: &mut Scope,
+) -> Result<(), Error<'src>> {
+    let pattern_span = w.pattern.span();
+    let end = w.span.end..w.span.end;
+    let bool_lit = |name| {
+        syntax::Expr::Name(syntax::Name {
+            id: Default::default(),
+            span: end.clone(),
+            name,
+        })
+    };
+    let run = syntax::Expr::Block(syntax::Block {
+        id: Default::default(),
+        span: w.span.clone(),
+        statements: w.body,
+        tail: Some(Box::new(bool_lit(sym::TRUE))),
+    });
+    let m = let_match(w.pattern, w.value, run, bool_lit(sym::FALSE), w.span);
+    let outer = scope.next_local;
+    let moves = scope.moves.len();
+    let lowered = lower_match(m, scope);
+    let moved = scope.moves[moves..]
+        .iter()
+        .find(|(local, _)| local.0 < outer)
+        .map(|(local, span)| match scope.binding_name(*local) {
+            Some(name) => Error::MovedInLoop(span.clone(), name),
+            None => unbound(span),
+        });
+    let condition = lowered?;
+    if let Some(error) = moved {
+        return Err(error);
+    }
+    if always_matches(&condition, scope.items) {
+        return Err(Error::IrrefutableWhileLetPattern(pattern_span));
+    }
+    body.push(Stmt::While(condition));
+    Ok(())
+}
+
+/// Lowers an `if let` to a `match` with an arm for the pattern, and a `_` arm evaluating to the
+/// `else` block, or to `()` if there's none.
+fn lower_if_let<'src>(i: syntax::IfLet, scope: &mut Scope) -> Result<Expr, Error<'src>> {
+    let pattern_span = i.pattern.span();
+    let then_span = i
+        .then
+        .tail
+        .as_ref()
+        .map_or(i.then.span.clone(), |tail| tail.span());
+    let end = i.span.end..i.span.end;
+    let missing_else = i.otherwise.is_none();
+    let otherwise = i.otherwise.unwrap_or_else(|| syntax::Block {
+        id: Default::default(),
+        span: end.clone(),
+        statements: Vec::new(),
+        tail: None,
+    });
+    let m = let_match(
+        i.pattern,
+        *i.value,
+        syntax::Expr::Block(i.then),
+        syntax::Expr::Block(otherwise),
+        i.span,
+    );
+    let lowered = lower_match(m, scope).map_err(|error| match error {
+        Error::MismatchedArmTypes(span, ty, _) if missing_else && span == end => {
+            Error::MissingElse(then_span, ty)
+        }
+        error => error,
+    })?;
+    if always_matches(&lowered, scope.items) {
+        return Err(Error::IrrefutableIfLetPattern(pattern_span));
+    }
+    Ok(lowered)
+}
+
+/// `match value { pattern => then, _ => otherwise }`, what `if let` and `while let` desugar to.
+fn let_match(
+    pattern: syntax::Pattern,
+    value: syntax::Expr,
+    then: syntax::Expr,
+    otherwise: syntax::Expr,
+    span: Range<usize>,
+) -> syntax::Match {
+    let arm = |pattern, body: syntax::Expr| syntax::MatchArm {
+        id: Default::default(),
+        span: body.span(),
+        pattern,
+        body,
+    };
+    let wildcard = syntax::Pattern::Wildcard(otherwise.span());
+    syntax::Match {
+        id: Default::default(),
+        span,
+        scrutinee: Box::new(value),
+        arms: vec![arm(pattern, then), arm(wildcard, otherwise)],
+    }
+}
+
+/// The error for a local that was used, at `span`, after its binding went out of scope.
+fn unbound<'src>(span: &Range<usize>) -> Error<'src> {
+    Error::InternalCompilerError(span.clone(), "a local was used out of its scope")
+}
+
+/// Whether the first arm of `m`, a `match` lowered from an `if let` or `while let`, matches
+/// every value, making the `_` arm after it unreachable.
+fn always_matches(m: &Expr, items: &Items) -> bool {
+    // `if let` and `while let` are lowered to a `match`.
+    let Expr::Match(scrutinee, arms, _) = m else {
+        return false;
+    };
+    uncovered(&[&arms[0].pattern], &scrutinee.ty(), items).is_none()
+}
+
+fn lower_macro<'src>(
+    m: syntax::Macro,
+    body: &mut Vec<Stmt>,
+    scope: &mut Scope,
+) -> Result<(), Error<'src>> {
+    let (stream, newline) = match m.name.name {
+        sym::PRINT => (Stream::Stdout, false),
+        sym::PRINTLN => (Stream::Stdout, true),
+        sym::EPRINT => (Stream::Stderr, false),
+        sym::EPRINTLN => (Stream::Stderr, true),
+        sym::SLEEP_MILLIS => {
+            let [millis] = lower_args(m, Type::Int, scope)?;
+            body.push(Stmt::Sleep(millis));
+            return Ok(());
+        }
+        sym::WRITE_FILE => {
+            let [path, contents] = lower_args(m, Type::Str, scope)?;
+            body.push(Stmt::WriteFile(path, contents));
+            return Ok(());
+        }
+        sym::EXIT => {
+            let [status] = lower_args(m, Type::Int, scope)?;
+            body.push(Stmt::Exit(status));
+            return Ok(());
+        }
+        _ => {
+            body.push(Stmt::Expr(lower_macro_expr(m, scope)?));
+            return Ok(());
+        }
+    };
+    let syntax::Macro { name, args, .. } = m;
+    // `println!(...)` is `print!(...)` followed by a newline, and `println!()` is just the newline.
+    if !(newline && args.is_empty()) {
+        body.push(Stmt::Print(stream, lower_fmt(name.span, args, scope)?));
+    }
+    if newline {
+        body.push(Stmt::Print(stream, vec![Expr::Str(Symbol::intern("\n"))]));
+    }
+    Ok(())
+}
+
+fn lower_macro_expr<'src>(m: syntax::Macro, scope: &mut Scope) -> Result<Expr, Error<'src>> {
+    let expr = match m.name.name {
+        sym::FORMAT => return Ok(Expr::Format(lower_fmt(m.name.span, m.args, scope)?)),
+        sym::GETENV => {
+            let [name] = lower_args(m, Type::Str, scope)?;
+            return Ok(Expr::GetEnv(Box::new(name)));
+        }
+        sym::READ_FILE => {
+            let [path] = lower_args(m, Type::Str, scope)?;
+            return Ok(Expr::ReadFile(Box::new(path)));
+        }
+        sym::RANDOM_INT => {
+            let [lo, hi] = lower_args(m, Type::Int, scope)?;
+            return Ok(Expr::RandomInt(Box::new(lo), Box::new(hi)));
+        }
+        sym::READ_LINE => Expr::ReadLine,
+        sym::READ_TO_STRING => Expr::ReadToString,
+        sym::NOW_MILLIS => Expr::NowMillis,
+        sym::VEC_MACRO => {
+            let mut ty = Type::Unknown;
+            let mut elements = Vec::new();
+            for arg in m.args {
+                let span = arg.span();
+                let element = lower_expr(arg, scope)?;
+                ty = ty
+                    .unify(&element.ty())
+                    .ok_or_else(|| Error::MismatchedArgumentType(span, ty.clone(), element.ty()))?;
+                elements.push(element);
+            }
+            return Ok(Expr::Vec(elements, Type::Unknown).coerce(&Type::Vec(Box::new(ty))));
+        }
+        sym::PRINT
+        | sym::PRINTLN
+        | sym::EPRINT
+        | sym::EPRINTLN
+        | sym::SLEEP_MILLIS
+        | sym::WRITE_FILE
+        | sym::EXIT => return Err(Error::NotAnExpression(m.span)),
+        _ => return Err(Error::UnknownMacro(m.name.span)),
+    };
+    if !m.args.is_empty() {
+        return Err(Error::ExtraMacroArguments(
+            m.name.span,
+            0,
+            m.args.iter().map(syntax::Expr::span).collect(),
+        ));
+    }
+    Ok(expr)
+}
+
+fn lower_expr<'src>(expr: syntax::Expr, scope: &mut Scope) -> Result<Expr, Error<'src>> {
+    match expr {
+        syntax::Expr::Str(lit) => Ok(Expr::Str(str_value(lit.val.as_str()))),
+        syntax::Expr::Int(lit) => int_value(&lit, "").map(Expr::Int),
+        // Negated literals are lowered whole, as `i64::MIN` only fits once negated.
+        syntax::Expr::Neg(syntax::Neg { expr, .. }) => match *expr {
+            syntax::Expr::Int(lit) => int_value(&lit, "-").map(Expr::Int),
+            expr => Ok(Expr::Neg(Box::new(lower_int_operand(expr, scope)?))),
+        },
+        syntax::Expr::Binary(binary) => Ok(Expr::Binary(
+            binary.op,
+            Box::new(lower_int_operand(*binary.lhs, scope)?),
+            Box::new(lower_int_operand(*binary.rhs, scope)?),
+        )),
+        syntax::Expr::Name(name) if name.name == sym::NONE => Ok(Expr::None(Type::Unknown)),
+        syntax::Expr::Name(name) if matches!(name.name, sym::TRUE | sym::FALSE) => {
+            Ok(Expr::Bool(name.name == sym::TRUE))
+        }
+        expr @ (syntax::Expr::Name(_) | syntax::Expr::Field(_)) => {
+            let (expr, place) = lower_place(expr, scope)?;
+            if let Some(place) = place {
+                scope.move_out(place, &expr.ty())?;
+            }
+            Ok(expr)
+        }
+        syntax::Expr::MetaVar(name) => Err(Error::UnboundMetaVar(name.span)),
+        syntax::Expr::Macro(m) => lower_macro_expr(m, scope),
+        syntax::Expr::Call(call)
+            if call.ty.is_none()
+                && !call.generics.is_empty()
+                && matches!(call.name.name, sym::SOME | sym::OK | sym::ERR) =>
+        {
+            Err(Error::MismatchedTypeArgumentCount(
+                call.name.span,
+                0,
+                call.generics.len(),
+            ))
+        }
+        syntax::Expr::Call(call) if call.ty.is_none() && call.name.name == sym::SOME => {
+            let [value] = lower_call_args(call.name.span, call.args, scope)?;
+            Ok(Expr::Some(Box::new(value)))
+        }
+        syntax::Expr::Call(call) if call.ty.is_none() && call.name.name == sym::OK => {
+            let [value] = lower_call_args(call.name.span, call.args, scope)?;
+            Ok(Expr::Ok(Box::new(value), Type::Unknown))
+        }
+        syntax::Expr::Call(call) if call.ty.is_none() && call.name.name == sym::ERR => {
+            let [error] = lower_call_args(call.name.span, call.args, scope)?;
+            Ok(Expr::Err(Type::Unknown, Box::new(error)))
+        }
+        syntax::Expr::Call(call)
+            if matches!(
+                (call.ty.as_ref().map(|ty| ty.name), call.name.name),
+                (Some(sym::BOX | sym::RC), sym::NEW) | (Some(sym::RC), sym::CLONE)
+            ) =>
+        {
+            if !call.generics.is_empty() {
+                return Err(Error::MismatchedTypeArgumentCount(
+                    call.name.span,
+                    0,
+                    call.generics.len(),
+                ));
+            }
+            let span = call
+                .args
+                .first()
+                .map_or(call.name.span.clone(), syntax::Expr::span);
+            let [value] = lower_call_args(call.name.span, call.args, scope)?;
+            match (call.ty.map(|ty| ty.name), call.name.name) {
+                (Some(sym::BOX), _) => Ok(Expr::Box(Box::new(value))),
+                (_, sym::NEW) => Ok(Expr::Rc(Box::new(value))),
+                // `Rc::clone(&rc)`, which only reads the `Rc`.
+                (_, _) => {
+                    let ty = value.ty();
+                    let rc = deref_refs(value);
+                    match (&ty, rc.ty()) {
+                        (Type::Ref(..), Type::Rc(_)) => Ok(Expr::CloneRc(Box::new(rc))),
+                        _ => Err(Error::MismatchedArgumentType(
+                            span,
+                            Type::Ref(Box::new(Type::Rc(Box::new(Type::Unknown))), false),
+                            ty,
+                        )),
+                    }
+                }
+            }
+        }
+        syntax::Expr::Call(call)
+            if matches!(
+                (call.ty.as_ref().map(|ty| ty.name), call.name.name),
+                (Some(sym::VEC | sym::HASH_MAP), sym::NEW)
+            ) =>
+        {
+            if !call.generics.is_empty() {
+                return Err(Error::MismatchedTypeArgumentCount(
+                    call.name.span,
+                    0,
+                    call.generics.len(),
+                ));
+            }
+            let [] = lower_call_args(call.name.span, call.args, scope)?;
+            match call.ty.map(|ty| ty.name) {
+                Some(sym::VEC) => Ok(Expr::Vec(Vec::new(), Type::Unknown)),
+                _ => Ok(Expr::HashMap(Type::Unknown, Type::Unknown)),
+            }
+        }
+        syntax::Expr::Call(call) => lower_call(call, scope),
+        syntax::Expr::MethodCall(call) => {
+            let receiver_span = call.receiver.span();
+            let (receiver, place) = lower_place(*call.receiver, scope)?;
+            if let (Type::Rc(_), sym::CLONE) = (deref_refs(receiver.clone()).ty(), call.name.name) {
+                let [] = lower_call_args(call.name.span, call.args, scope)?;
+                return Ok(Expr::CloneRc(Box::new(deref_refs(receiver))));
+            }
+            let borrowed = matches!(receiver.ty(), Type::Ref(..));
+            // Methods are called on what references, boxes and `Rc`s refer to.
+            let (receiver, place) = deref_all(receiver, place);
+            match (receiver.ty(), call.name.name) {
+                // The length only reads the string, `Vec` or `HashMap`.
+                (Type::Str | Type::Vec(_) | Type::HashMap(..), sym::LEN) => {
+                    let [] = lower_call_args(call.name.span, call.args, scope)?;
+                    Ok(Expr::Len(Box::new(receiver)))
+                }
+                (Type::Str, sym::TO_UPPERCASE) => {
+                    let [] = lower_call_args(call.name.span, call.args, scope)?;
+                    Ok(Expr::ToUppercase(Box::new(receiver)))
+                }
+                (Type::Str, sym::TO_LOWERCASE) => {
+                    let [] = lower_call_args(call.name.span, call.args, scope)?;
+                    Ok(Expr::ToLowercase(Box::new(receiver)))
+                }
+                (Type::Str, sym::TRIM) => {
+                    let [] = lower_call_args(call.name.span, call.args, scope)?;
+                    Ok(Expr::Trim(Box::new(receiver)))
+                }
+                // The pattern is a string, or a reference to one.
+                (Type::Str, sym::CONTAINS) => {
+                    let spans: Vec<_> = call.args.iter().map(syntax::Expr::span).collect();
+                    let [pattern] = lower_call_args(call.name.span, call.args, scope)?;
+                    let pattern = deref_refs(pattern);
+                    match pattern.ty() {
+                        Type::Str => Ok(Expr::Contains(Box::new(receiver), Box::new(pattern))),
+                        ty => Err(Error::MismatchedArgumentType(
+                            spans[0].clone(),
+                            Type::Str,
+                            ty,
+                        )),
+                    }
+                }
+                (Type::Str, sym::CHARS) => {
+                    let [] = lower_call_args(call.name.span, call.args, scope)?;
+                    Ok(Expr::Chars(Box::new(receiver)))
+                }
+                // Iterating over a `Vec` moves it, unless it's only borrowed.
+                (Type::Vec(_), sym::ITER | sym::INTO_ITER) => {
+                    let [] = lower_call_args(call.name.span, call.args, scope)?;
+                    if call.name.name == sym::INTO_ITER && !borrowed {
+                        if let Some(place) = place {
+                            scope.move_out(place, &receiver.ty())?;
+                        }
+                        return Ok(Expr::IntoIter(Box::new(receiver)));
+                    }
+                    if let Some(place) = place {
+                        scope.borrow(place, false, receiver_span)?;
+                    }
+                    Ok(Expr::IntoIter(Box::new(Expr::Ref(
+                        Box::new(receiver),
+                        false,
+                    ))))
+                }
+                // Looking a key up only borrows the `HashMap`, for as long as the reference to
+                // the value lives.
+                (Type::HashMap(key_ty, value_ty), sym::GET) => {
+                    let spans: Vec<_> = call.args.iter().map(syntax::Expr::span).collect();
+                    let [key] = lower_call_args(call.name.span, call.args, scope)?;
+                    let key = lower_key(key, &key_ty, spans[0].clone())?;
+                    if let Some(place) = place {
+                        scope.borrow(place, false, receiver_span)?;
+                    }
+                    let ty = Type::HashMap(Box::new(key.ty()), value_ty);
+                    Ok(Expr::Get(Box::new(receiver.coerce(&ty)), Box::new(key)))
+                }
+                // Inserting and removing only read the `HashMap`, as they return a copy of it with
+                // the change.
+                (Type::HashMap(key_ty, value_ty), sym::INSERT | sym::REMOVE) => {
+                    let spans: Vec<_> = call.args.iter().map(syntax::Expr::span).collect();
+                    if call.name.name == sym::REMOVE {
+                        let [key] = lower_call_args(call.name.span, call.args, scope)?;
+                        let key = lower_key(key, &key_ty, spans[0].clone())?;
+                        let ty = Type::HashMap(Box::new(key.ty()), value_ty);
+                        return Ok(Expr::Remove(Box::new(receiver.coerce(&ty)), Box::new(key)));
+                    }
+                    let [key, value] = lower_call_args(call.name.span, call.args, scope)?;
+                    let key = lower_key(key, &key_ty, spans[0].clone())?;
+                    let Some(value_ty) = value_ty.unify(&value.ty()) else {
+                        return Err(Error::MismatchedArgumentType(
+                            spans[1].clone(),
+                            *value_ty,
+                            value.ty(),
+                        ));
+                    };
+                    let ty = Type::HashMap(Box::new(key.ty()), Box::new(value_ty.clone()));
+                    Ok(Expr::Insert(
+                        Box::new(receiver.coerce(&ty)),
+                        Box::new(key),
+                        Box::new(value.coerce(&value_ty)),
+                    ))
+                }
+                // Sorting only reads the `Vec`, as it returns a sorted copy of it.
+                (Type::Vec(item), sym::SORT) => {
+                    let [] = lower_call_args(call.name.span, call.args, scope)?;
+                    if !item.is_ordered() {
+                        return Err(Error::Unsortable(receiver_span, *item));
+                    }
+                    Ok(Expr::Sort(Box::new(receiver), None))
+                }
+                // The closure is given a reference to each item, for the key to sort it by.
+                (Type::Vec(item), sym::SORT_BY_KEY) => {
+                    let arg = match <[_; 1]>::try_from(call.args) {
+                        Ok([arg]) => arg,
+                        Err(args) => {
+                            let len = args.len();
+                            return Err(Error::MismatchedArgumentCount(call.name.span, 1, len));
+                        }
+                    };
+                    let span = arg.span();
+                    let params = [Type::Ref(item, false)];
+                    let key = match arg {
+                        syntax::Expr::Closure(closure) => {
+                            lower_closure(closure, Some(&params), scope)?
+                        }
+                        arg => lower_expr(arg, scope)?,
+                    };
+                    let ret = match scope.callable(&key.ty()) {
+                        Some((found, ret)) if found == params => ret,
+                        _ => {
+                            let bound = format!("Fn({})", params[0]);
+                            return Err(Error::UnsatisfiedBound(span, key.ty(), bound));
+                        }
+                    };
+                    if !ret.is_ordered() {
+                        return Err(Error::Unsortable(span, ret));
+                    }
+                    Ok(Expr::Sort(Box::new(receiver), Some((Box::new(key), ret))))
+                }
+                // Taking an item uses up the iterator.
+                (ty, sym::NEXT | sym::INTO_ITER) if ty.item().is_some() => {
+                    if let Some(place) = place {
+                        scope.move_out(place, &ty)?;
+                    }
+                    let [] = lower_call_args(call.name.span, call.args, scope)?;
+                    match call.name.name {
+                        sym::NEXT => Ok(Expr::Next(Box::new(receiver))),
+                        _ => Ok(receiver),
+                    }
+                }
+                (Type::Option(_), sym::UNWRAP) => {
+                    if let Some(place) = place {
+                        scope.move_out(place, &receiver.ty())?;
+                    }
+                    let [] = lower_call_args(call.name.span, call.args, scope)?;
+                    Ok(Expr::Unwrap(Box::new(receiver), call.span))
+                }
+                (Type::Struct(ty), name) => {
+                    let name = qualified_name(Some(ty), name);
+                    match scope.items.functions.get(&name) {
+                        Some(signature) if signature.method => {
+                            let receiver = lower_receiver(
+                                receiver,
+                                place,
+                                &signature.params[0],
+                                receiver_span,
+                                scope,
+                            )?;
+                            lower_fn_call(
+                                name,
+                                signature,
+                                call.name.span,
+                                Vec::new(),
+                                Some(receiver),
+                                call.args,
+                                scope,
+                            )
+                        }
+                        _ => Err(Error::NoMethod(call.name.span, Type::Struct(ty))),
+                    }
+                }
+                (Type::Param(param), name) => {
+                    let Some(signature) = scope.bound_method(param, name) else {
+                        return Err(Error::NoMethod(call.name.span, Type::Param(param)));
+                    };
+                    let signature = signature.substitute(&[sym::SELF_TYPE], &[Type::Param(param)]);
+                    let receiver = lower_receiver(
+                        receiver,
+                        place,
+                        &signature.params[0],
+                        receiver_span,
+                        scope,
+                    )?;
+                    let mut args = vec![receiver];
+                    args.extend(lower_fn_args(
+                        call.name.span,
+                        call.args,
+                        &signature.params[1..],
+                        scope,
+                    )?);
+                    Ok(Expr::Call(
+                        Callee::Method(Type::Param(param), name),
+                        args,
+                        signature.ret,
+                    ))
+                }
+                (ty, _) => Err(Error::NoMethod(call.name.span, ty)),
+            }
+        }
+        syntax::Expr::Borrow(borrow) => {
+            let (value, place) = lower_place(*borrow.expr, scope)?;
+            if let Some(place) = place {
+                scope.borrow(place, borrow.mutable, borrow.span)?;
+            }
+            Ok(Expr::Ref(Box::new(value), borrow.mutable))
+        }
+        syntax::Expr::Deref(deref) => {
+            let span = deref.expr.span();
+            let (reference, place) = lower_place(*deref.expr, scope)?;
+            match reference.ty() {
+                Type::Ref(..) | Type::Rc(_) => Ok(Expr::Deref(Box::new(reference))),
+                // Reading out of a box moves the box, unless what it holds is copied.
+                Type::Box(ty) => {
+                    if let Some(place) = place {
+                        scope.move_out(place, &ty)?;
+                    }
+                    Ok(Expr::Deref(Box::new(reference)))
+                }
+                ty => Err(Error::NotAReference(span, ty)),
+            }
+        }
+        syntax::Expr::Slice(slice) => {
+            let span = slice.expr.span();
+            // Slicing only reads the string.
+            let (value, place) = lower_place(*slice.expr, scope)?;
+            let (value, _) = deref_all(value, place);
+            if value.ty() != Type::Str {
+                return Err(Error::NotSliceable(span, value.ty()));
+            }
+            let start = slice
+                .start
+                .map(|start| lower_bound(*start, scope))
+                .transpose()?;
+            let end = slice.end.map(|end| lower_bound(*end, scope)).transpose()?;
+            Ok(Expr::Slice(Box::new(value), start, end, slice.span))
+        }
+        syntax::Expr::Range(range) => Ok(Expr::Range(
+            lower_bound(*range.start, scope)?,
+            lower_bound(*range.end, scope)?,
+        )),
+        syntax::Expr::Match(m) => lower_match(m, scope),
+        syntax::Expr::IfLet(i) => lower_if_let(*i, scope),
+        syntax::Expr::Block(block) => {
+            let (stmts, tail) = lower_block(block, scope)?;
+            Ok(Expr::Block(stmts, Box::new(tail.unwrap_or(Expr::Unit))))
+        }
+        syntax::Expr::Closure(closure) => lower_closure(closure, None, scope),
+        syntax::Expr::Try(t) => {
+            let Type::Result(_, error) = scope.ret.clone() else {
+                return Err(Error::TryOutsideResult(t.span));
+            };
+            let error = *error;
+            let span = t.expr.span();
+            let result = lower_expr(*t.expr, scope)?;
+            match result.ty() {
+                Type::Result(ok, found) => match found.unify(&error) {
+                    Some(error) => Ok(Expr::Try(Box::new(
+                        result.coerce(&Type::Result(ok, Box::new(error))),
+                    ))),
+                    None => Err(Error::MismatchedErrorType(span, error, *found)),
+                },
+                ty => Err(Error::TryNotResult(span, ty)),
+            }
+        }
+    }
+}
+
+/// Lowers an expression that's only read in place, like a borrowed or formatted one, without
+/// moving out of it. Also returns the local it reads, if it's read directly rather than through a
+/// reference, for the caller to borrow or move out of.
+fn lower_place<'src>(
+    expr: syntax::Expr,
+    scope: &mut Scope,
+) -> Result<(Expr, Option<Place>), Error<'src>> {
+    match expr {
+        syntax::Expr::Name(name) if !matches!(name.name, sym::NONE | sym::TRUE | sym::FALSE) => {
+            let Some((local, ty)) = scope.lookup(&name) else {
+                // A static is read like a literal, so there's nothing to move out of.
+                if let Some(s) = scope.items.statics.get(&name.name) {
+                    scope.names.use_item(name.span, name.name);
+                    return Ok((Expr::Static(s.name, s.ty.clone()), None));
+                }
+                return Err(Error::UnresolvedName(name.span));
+            };
+            scope.check_moved(local, name.name, name.span.clone())?;
+            let place = match ty {
+                Type::Ref(..) => None,
+                _ => Some(Place {
+                    local,
+                    name: name.name,
+                    span: name.span,
+                }),
+            };
+            Ok((Expr::Local(local, ty), place))
+        }
+        syntax::Expr::Field(field) => {
+            let (expr, place) = lower_place(*field.expr, scope)?;
+            // Fields are read through references and boxes.
+            let (expr, place) = deref_all(expr, place);
+            let ty = expr.ty();
+            let fields = match &ty {
+                Type::Struct(name) => &scope.items.structs[name][..],
+                _ => &[],
+            };
+            let index = field.index.digits.as_str().parse::<usize>().ok();
+            let Some((index, field_ty)) = index.and_then(|index| Some((index, fields.get(index)?)))
+            else {
+                return Err(Error::NoField(field.index.span, ty));
+            };
+            let place = place.map(|place| Place {
+                span: field.span,
+                ..place
+            });
+            Ok((Expr::Field(Box::new(expr), index, field_ty.clone()), place))
+        }
+        expr => Ok((lower_expr(expr, scope)?, None)),
+    }
+}
+
+/// `expr`, which reads `place` if given, dereferenced until it isn't a reference, a box or an
+/// `Rc`. What a box holds is part of the place holding the box, but what the others refer to
+/// isn't.
+fn deref_all(mut expr: Expr, mut place: Option<Place>) -> (Expr, Option<Place>) {
+    loop {
+        match expr.ty() {
+            Type::Ref(..) | Type::Rc(_) => place = None,
+            Type::Box(_) => {}
+            _ => return (expr, place),
+        }
+        expr = Expr::Deref(Box::new(expr));
+    }
+}
+
+/// `expr` dereferenced until it isn't a reference.
+fn deref_refs(mut expr: Expr) -> Expr {
+    while let Type::Ref(..) = expr.ty() {
+        expr = Expr::Deref(Box::new(expr));
+    }
+    expr
+}
+
+/// Passes `receiver`, which reads `place` if given, as the `self` of a method declared with type
+/// `self_ty`: borrowing it for `&self`, and moving it for `self`.
+fn lower_receiver<'src>(
+    receiver: Expr,
+    place: Option<Place>,
+    self_ty: &Type,
+    span: Range<usize>,
+    scope: &mut Scope,
+) -> Result<Expr, Error<'src>> {
+    match (self_ty, place) {
+        (Type::Ref(_, mutable), place) => {
+            if let Some(place) = place {
+                scope.borrow(place, *mutable, span)?;
+            }
+            Ok(Expr::Ref(Box::new(receiver), *mutable))
+        }
+        (_, Some(place)) => {
+            scope.move_out(place, &receiver.ty())?;
+            Ok(receiver)
+        }
+        (_, None) => Ok(receiver),
+    }
+}
+
+/// Lowers a call to a function, or to the constructor of a tuple struct.
+fn lower_call<'src>(call: syntax::Call, scope: &mut Scope) -> Result<Expr, Error<'src>> {
+    let self_ty = match &call.ty {
+        Some(ty) => match (ty.name, scope.self_ty) {
+            (sym::SELF_TYPE, Some(self_ty)) => Some(self_ty),
+            (name, _) if scope.items.structs.contains_key(&name) => {
+                scope.names.use_item(ty.span.clone(), name);
+                Some(name)
+            }
+            _ => return Err(Error::UnknownType(ty.span.clone())),
+        },
+        None => None,
+    };
+    let type_args: Vec<_> = call
+        .generics
+        .into_iter()
+        .map(|ty| scope.lower_type(ty))
+        .collect::<Result<_, _>>()?;
+    if let (None, Some((local, ty))) = (self_ty, scope.lookup(&call.name)) {
+        // Calling a closure only borrows it.
+        scope.check_moved(local, call.name.name, call.name.span.clone())?;
+        let Some((params, ret)) = scope.callable(&ty) else {
+            return Err(Error::NotCallable(call.name.span, ty));
+        };
+        if !type_args.is_empty() {
+            return Err(Error::MismatchedTypeArgumentCount(
+                call.name.span,
+                0,
+                type_args.len(),
+            ));
+        }
+        let args = lower_fn_args(call.name.span, call.args, &params, scope)?;
+        return Ok(Expr::CallClosure(
+            Box::new(Expr::Local(local, ty)),
+            args,
+            ret,
+        ));
+    }
+    if let (None, Some(fields)) = (self_ty, scope.items.structs.get(&call.name.name)) {
+        if !type_args.is_empty() {
+            return Err(Error::MismatchedTypeArgumentCount(
+                call.name.span,
+                0,
+                type_args.len(),
+            ));
+        }
+        scope.names.use_item(call.name.span.clone(), call.name.name);
+        let args = lower_fn_args(call.name.span, call.args, fields, scope)?;
+        return Ok(Expr::Struct(call.name.name, args));
+    }
+    let name = qualified_name(self_ty, call.name.name);
+    match (scope.items.functions.get(&name), self_ty) {
+        (Some(signature), _) => lower_fn_call(
+            name,
+            signature,
+            call.name.span,
+            type_args,
+            None,
+            call.args,
+            scope,
+        ),
+        (None, Some(ty)) => Err(Error::NoMethod(call.name.span, Type::Struct(ty))),
+        (None, None) => Err(Error::UnresolvedName(call.name.span)),
+    }
+}
+
+/// Lowers a call to the function `name`, passing `type_args` if any were given and inferring them
+/// from the arguments, starting with the receiver of a method call, otherwise.
+fn lower_fn_call<'src>(
+    name: Symbol,
+    signature: &Signature,
+    name_span: Range<usize>,
+    type_args: Vec<Type>,
+    receiver: Option<Expr>,
+    args: Vec<syntax::Expr>,
+    scope: &mut Scope,
+) -> Result<Expr, Error<'src>> {
+    scope.names.use_item(name_span.clone(), name);
+    let params = &signature.params[receiver.is_some() as usize..];
+    if args.len() != params.len() {
+        return Err(Error::MismatchedArgumentCount(
+            name_span,
+            params.len(),
+            args.len(),
+        ));
+    }
+    let generics = signature.generic_names();
+    let explicit = !type_args.is_empty();
+    let mut inferred = match type_args.len() {
+        0 => vec![Type::Unknown; generics.len()],
+        n if n == generics.len() => type_args,
+        n => {
+            return Err(Error::MismatchedTypeArgumentCount(
+                name_span,
+                generics.len(),
+                n,
+            ))
+        }
+    };
+    let receiver_span = name_span.clone();
+    let mut args: Vec<_> = receiver
+        .map(|receiver| Some((receiver_span, Ok(receiver))))
+        .into_iter()
+        .chain(args.into_iter().map(|arg| Some((arg.span(), Err(arg)))))
+        .collect();
+    // Closures are lowered last, so that the arguments after them can help infer the types of
+    // their parameters too.
+    let is_closure =
+        |arg: &Option<(_, Result<_, _>)>| matches!(arg, Some((_, Err(syntax::Expr::Closure(_)))));
+    let order: Vec<_> = (0..args.len())
+        .filter(|i| !is_closure(&args[*i]))
+        .chain((0..args.len()).filter(|i| is_closure(&args[*i])))
+        .collect();
+    let mut lowered: Vec<_> = args.iter().map(|_| None).collect();
+    for i in order {
+        let (span, arg) = args[i].take().ok_or(Error::InternalCompilerError(
+            name_span.clone(),
+            "an argument was lowered twice",
+        ))?;
+        let param = &signature.params[i];
+        let arg = match arg {
+            Ok(receiver) => receiver,
+            // A closure's parameters can be left for the bound on the type it's passed as to
+            // infer.
+            Err(syntax::Expr::Closure(closure)) => {
+                let expected: Option<Vec<_>> = signature.fn_bound(param).map(|(params, _)| {
+                    params
+                        .iter()
+                        .map(|ty| ty.substitute(&generics, &inferred))
+                        .collect()
+                });
+                lower_closure(closure, expected.as_deref(), scope)?
+            }
+            Err(arg) => lower_expr(arg, scope)?,
+        };
+        let ty = arg.ty();
+        if !infer(param, &ty, &generics, &mut inferred) {
+            // If the argument would have been fine on its own, it disagrees with the ones before
+            // it about what a type parameter is.
+            let mut alone = vec![Type::Unknown; generics.len()];
+            let disagreeing = match !explicit && infer(param, &ty, &generics, &mut alone) {
+                true => (0..generics.len()).find(|i| inferred[*i].unify(&alone[*i]).is_none()),
+                false => None,
+            };
+            if let Some(i) = disagreeing {
+                return Err(Error::AmbiguousTypeParam(
+                    span,
+                    generics[i],
+                    inferred[i].clone(),
+                    alone[i].clone(),
+                ));
+            }
+            let expected = param.substitute(&generics, &inferred);
+            return Err(Error::MismatchedArgumentType(span, expected, ty));
+        }
+        lowered[i] = Some(arg);
+    }
+    // What a closure returns can pin down the type parameters in the return type of its bound.
+    for (i, (_, bounds)) in signature.generics.iter().enumerate() {
+        for bound in bounds {
+            let (Bound::Fn(params, ret), Some((found_params, found_ret))) =
+                (bound, scope.callable(&inferred[i]))
+            else {
+                continue;
+            };
+            let satisfied = params.len() == found_params.len()
+                && params
+                    .iter()
+                    .zip(&found_params)
+                    .all(|(param, found)| infer(param, found, &generics, &mut inferred))
+                && infer(ret, &found_ret, &generics, &mut inferred);
+            if !satisfied {
+                let bound = bound.substitute(&generics, &inferred);
+                return Err(Error::UnsatisfiedBound(
+                    name_span,
+                    inferred[i].clone(),
+                    bound.to_string(),
+                ));
+            }
+        }
+    }
+    if let Some(i) = inferred.iter().position(Type::has_unknown) {
+        return Err(Error::UnresolvedTypeParam(name_span, generics[i]));
+    }
+    for ((_, bounds), ty) in signature.generics.iter().zip(&inferred) {
+        let unsatisfied = bounds.iter().find(|bound| match bound {
+            Bound::Trait(name) => !scope.implements(ty, *name),
+            Bound::Fn(..) => scope.callable(ty).is_none(),
+        });
+        if let Some(bound) = unsatisfied {
+            let bound = bound.substitute(&generics, &inferred);
+            return Err(Error::UnsatisfiedBound(
+                name_span,
+                ty.clone(),
+                bound.to_string(),
+            ));
+        }
+    }
+    let args = lowered
+        .into_iter()
+        .zip(&signature.params)
+        .map(|(arg, param)| match arg {
+            Some(arg) => Ok(arg.coerce(&param.substitute(&generics, &inferred))),
+            None => Err(Error::InternalCompilerError(
+                name_span.clone(),
+                "an argument was never lowered",
+            )),
+        })
+        .collect::<Result<_, _>>()?;
+    let ret = signature.ret.substitute(&generics, &inferred);
+    Ok(Expr::Call(Callee::Function(name, inferred), args, ret))
+}
+
+/// Lowers a closure to its function, which is added to the scope's closures, and the values it
+/// captures. Parameters without a type take theirs from `expected`, the parameters of the bound
+/// the closure is passed for, if given.
+fn lower_closure<'src>(
+    closure: syntax::Closure,
+    expected: Option<&[Type]>,
+    scope: &mut Scope,
+) -> Result<Expr, Error<'src>> {
+    let expected = expected.filter(|expected| expected.len() == closure.params.len());
+    let mut params = Vec::new();
+    for (i, param) in closure.params.into_iter().enumerate() {
+        let ty = match (param.ty, expected) {
+            (Some(ty), _) => scope.lower_type(ty)?,
+            (None, Some(expected)) if !expected[i].has_unknown() => expected[i].clone(),
+            (None, _) => return Err(Error::TypeAnnotationsNeeded(param.span)),
+        };
+        params.push((param.name, ty));
+    }
+    // Closures being lowered are numbered before the ones they contain, but only added once
+    // they're done.
+    let name = Symbol::intern(&format!(
+        "{}::{{closure#{}}}",
+        scope.name,
+        scope.closures.len() + scope.captures.len()
+    ));
+    let bindings = scope.bindings.len();
+    scope.captures.push((scope.next_local, Vec::new()));
+    let params: Vec<_> = params
+        .into_iter()
+        .map(|(name, ty)| (scope.bind(&name, ty.clone()), ty))
+        .collect();
+    // `?` would return from the closure, not the function.
+    let ret = mem::replace(&mut scope.ret, Type::Unit);
+    // The body moves and borrows the closure's own values, when it's called.
+    let (moves, borrows) = (scope.moves.len(), scope.borrows.len());
+    let body_span = closure.body.span();
+    let body = lower_expr(*closure.body, scope)?;
+    scope.ret = ret;
+    scope.moves.truncate(moves);
+    scope.borrows.truncate(borrows);
+    let (_, captures) = scope.captures.pop().ok_or(Error::InternalCompilerError(
+        closure.span.clone(),
+        "a closure's captures were lost",
+    ))?;
+    for (local, ty) in &captures {
+        let name = scope
+            .binding_name(*local)
+            .ok_or_else(|| unbound(&closure.span))?;
+        let place = Place {
+            local: *local,
+            name,
+            span: closure.span.clone(),
+        };
+        scope.move_out(place, ty)?;
+    }
+    scope.bindings.truncate(bindings);
+    let ret = body.ty();
+    if ret.has_unknown() {
+        return Err(Error::TypeAnnotationsNeeded(body_span));
+    }
+    let generics: Vec<_> = scope.generics.iter().map(|(name, _)| *name).collect();
+    scope.closures.push(Function {
+        name,
+        attrs: Vec::new(),
+        public: false,
+        generics: generics.clone(),
+        params: captures.iter().cloned().chain(params).collect(),
+        ret,
+        body: Vec::new(),
+        tail: Some(body),
+        closures: Vec::new(),
+    });
+    Ok(Expr::Closure(
+        name,
+        generics.into_iter().map(Type::Param).collect(),
+        captures
+            .into_iter()
+            .map(|(local, ty)| Expr::Local(local, ty))
+            .collect(),
+    ))
+}
+
+/// Unifies the type `arg` of an argument with the type `param` of its parameter, recording what
+/// the type parameters `generics` it mentions must be in `inferred`. Returns whether they're
+/// compatible.
+fn infer(param: &Type, arg: &Type, generics: &[Symbol], inferred: &mut [Type]) -> bool {
+    match (param, arg) {
+        (Type::Param(name), arg) if generics.contains(name) => {
+            let Some(i) = generics.iter().position(|g| g == name) else {
+                return false;
+            };
+            match inferred[i].unify(arg) {
+                Some(ty) => {
+                    inferred[i] = ty;
+                    true
+                }
+                None => false,
+            }
+        }
+        (Type::Option(param), Type::Option(arg)) => infer(param, arg, generics, inferred),
+        (Type::Result(ok, error), Type::Result(arg_ok, arg_error)) => {
+            infer(ok, arg_ok, generics, inferred) && infer(error, arg_error, generics, inferred)
+        }
+        (Type::Ref(param, mutable), Type::Ref(arg, arg_mutable)) if mutable == arg_mutable => {
+            infer(param, arg, generics, inferred)
+        }
+        (Type::Box(param), Type::Box(arg)) | (Type::Rc(param), Type::Rc(arg)) => {
+            infer(param, arg, generics, inferred)
+        }
+        (param, arg) => param.unify(arg).is_some_and(|unified| unified == *param),
+    }
+}
+
+/// Lowers the arguments of a call to a function taking `params`.
+fn lower_fn_args<'src>(
+    name_span: Range<usize>,
+    args: Vec<syntax::Expr>,
+    params: &[Type],
+    scope: &mut Scope,
+) -> Result<Vec<Expr>, Error<'src>> {
+    if args.len() != params.len() {
+        return Err(Error::MismatchedArgumentCount(
+            name_span,
+            params.len(),
+            args.len(),
+        ));
+    }
+    args.into_iter()
+        .zip(params)
+        .map(|(arg, ty)| {
+            lower_expecting(arg, ty, scope, |span, found| {
+                Error::MismatchedArgumentType(span, ty.clone(), found)
+            })
+        })
+        .collect()
+}
+
+/// Lowers `expr`, which must have type `ty`. If it doesn't, fails with the error `mismatch` makes
+/// from its span and type.
+fn lower_expecting<'src>(
+    expr: syntax::Expr,
+    ty: &Type,
+    scope: &mut Scope,
+    mismatch: impl FnOnce(Range<usize>, Type) -> Error<'src>,
+) -> Result<Expr, Error<'src>> {
+    let span = expr.span();
+    let expr = lower_expr(expr, scope)?;
+    match expr.ty().unify(ty) {
+        Some(unified) if unified == *ty => Ok(expr.coerce(ty)),
+        _ => Err(mismatch(span, expr.ty())),
+    }
+}
+
+/// Lowers the arguments of a call to something that takes `N` of them.
+fn lower_call_args<'src, const N: usize>(
+    name_span: Range<usize>,
+    args: Vec<syntax::Expr>,
+    scope: &mut Scope,
+) -> Result<[Expr; N], Error<'src>> {
+    if args.len() != N {
+        return Err(Error::MismatchedArgumentCount(name_span, N, args.len()));
+    }
+    let args = args
+        .into_iter()
+        .map(|arg| lower_expr(arg, scope))
+        .collect::<Result<Vec<_>, _>>()?;
+    args.try_into().map_err(|_| {
+        Error::InternalCompilerError(name_span, "a call had other than the arguments counted")
+    })
+}
+
+fn lower_match<'src>(m: syntax::Match, scope: &mut Scope) -> Result<Expr, Error<'src>> {
+    let scrutinee_span = m.scrutinee.span();
+    let scrutinee = lower_expr(*m.scrutinee, scope)?;
+    let scrutinee_ty = scrutinee.ty();
+    let mut ty = Type::Unknown;
+    let mut arms = Vec::new();
+    // Only one arm runs, so each starts out with what was moved and borrowed before the `match`,
+    // and what any of them moved or borrowed is afterwards.
+    let (moves, borrows) = (scope.moves.len(), scope.borrows.len());
+    let mut arm_moves = Vec::new();
+    let mut arm_borrows = Vec::new();
+    for arm in m.arms {
+        let bound = scope.bindings.len();
+        let pattern = lower_pattern(arm.pattern, &scrutinee_ty, scope);
+        let body_span = arm.body.span();
+        let body = pattern.and_then(|pattern| Ok((pattern, lower_expr(arm.body, scope)?)));
+        scope.bindings.truncate(bound);
+        let (pattern, body) = body?;
+        arm_moves.extend(scope.moves.drain(moves..));
+        arm_borrows.extend(scope.borrows.drain(borrows..));
+        ty = ty
+            .unify(&body.ty())
+            .ok_or_else(|| Error::MismatchedArmTypes(body_span, ty.clone(), body.ty()))?;
+        arms.push(Arm { pattern, body });
+    }
+    scope.moves.extend(arm_moves);
+    scope.borrows.extend(arm_borrows);
+    let patterns: Vec<_> = arms.iter().map(|arm| &arm.pattern).collect();
+    if let Some(witness) = uncovered(&patterns, &scrutinee_ty, scope.items) {
+        return Err(Error::NonExhaustiveMatch(scrutinee_span, witness));
+    }
+    let arms = arms
+        .into_iter()
+        .map(|arm| Arm {
+            pattern: arm.pattern,
+            body: arm.body.coerce(&ty),
+        })
+        .collect();
+    Ok(Expr::Match(Box::new(scrutinee), arms, ty))
+}
+
+/// Lowers a pattern matching values of type `ty`, binding the names in it.
+fn lower_pattern<'src>(
+    pattern: syntax::Pattern,
+    ty: &Type,
+    scope: &mut Scope,
+) -> Result<Pattern, Error<'src>> {
+    match pattern {
+        syntax::Pattern::Wildcard(_) => Ok(Pattern::Wildcard),
+        syntax::Pattern::Name(name) if arity(name.name).is_some() => {
+            let span = name.span.clone();
+            lower_variant_pattern(name, span, Vec::new(), ty, scope)
+        }
+        syntax::Pattern::Name(name) if matches!(name.name, sym::TRUE | sym::FALSE) => match ty {
+            Type::Bool => Ok(Pattern::Bool(name.name == sym::TRUE)),
+            Type::Unknown => Err(Error::TypeAnnotationsNeeded(name.span)),
+            ty => Err(Error::MismatchedPatternType(
+                name.span,
+                ty.clone(),
+                Type::Bool,
+            )),
+        },
+        syntax::Pattern::Name(name) => Ok(Pattern::Bind(scope.bind(&name, ty.clone()))),
+        syntax::Pattern::Str(lit) => match ty {
+            Type::Str => Ok(Pattern::Str(lit.val)),
+            Type::Unknown => Err(Error::TypeAnnotationsNeeded(lit.span)),
+            ty => Err(Error::MismatchedPatternType(
+                lit.span,
+                ty.clone(),
+                Type::Str,
+            )),
+        },
+        syntax::Pattern::TupleStruct(pattern)
+            if scope.items.structs.contains_key(&pattern.name.name) =>
+        {
+            lower_struct_pattern(pattern, ty, scope)
+        }
+        syntax::Pattern::TupleStruct(pattern) => {
+            lower_variant_pattern(pattern.name, pattern.span, pattern.fields, ty, scope)
+        }
+    }
+}
+
+/// Lowers a pattern like `Point(x, y)`, for a tuple struct.
+fn lower_struct_pattern<'src>(
+    pattern: syntax::TupleStructPattern,
+    ty: &Type,
+    scope: &mut Scope,
+) -> Result<Pattern, Error<'src>> {
+    let name = pattern.name.name;
+    match ty {
+        Type::Struct(s) if *s == name => scope.names.use_item(pattern.name.span.clone(), name),
+        Type::Unknown => return Err(Error::TypeAnnotationsNeeded(pattern.span)),
+        ty => {
+            return Err(Error::MismatchedPatternType(
+                pattern.span,
+                ty.clone(),
+                Type::Struct(name),
+            ))
+        }
+    }
+    let field_tys = scope.items.structs.get(&name).cloned().ok_or_else(|| {
+        Error::InternalCompilerError(pattern.name.span.clone(), "struct was never collected")
+    })?;
+    if pattern.fields.len() != field_tys.len() {
+        return Err(Error::MismatchedArgumentCount(
+            pattern.name.span,
+            field_tys.len(),
+            pattern.fields.len(),
+        ));
+    }
+    let fields = pattern
+        .fields
+        .into_iter()
+        .zip(&field_tys)
+        .map(|(field, ty)| lower_pattern(field, ty, scope))
+        .collect::<Result<_, _>>()?;
+    Ok(Pattern::Struct(fields))
+}
+
+/// How many fields the variant of `Option` or `Result` named `name` has, if it names one.
+pub(crate) fn arity(name: Symbol) -> Option<usize> {
+    match name {
+        sym::NONE => Some(0),
+        sym::SOME | sym::OK | sym::ERR => Some(1),
+        _ => None,
+    }
+}
+
+/// Lowers a pattern like `Some(x)`, which is spanned by `span`.
+fn lower_variant_pattern<'src>(
+    name: syntax::Name,
+    span: Range<usize>,
+    fields: Vec<syntax::Pattern>,
+    ty: &Type,
+    scope: &mut Scope,
+) -> Result<Pattern, Error<'src>> {
+    let Some(arity) = arity(name.name) else {
+        return Err(Error::UnresolvedName(name.span));
+    };
+    if fields.len() != arity {
+        return Err(Error::MismatchedArgumentCount(
+            name.span,
+            arity,
+            fields.len(),
+        ));
+    }
+    let field_ty = match (name.name, ty) {
+        (sym::SOME | sym::NONE, Type::Option(payload)) => payload,
+        (sym::OK, Type::Result(ok, _)) => ok,
+        (sym::ERR, Type::Result(_, error)) => error,
+        (_, Type::Unknown) => return Err(Error::TypeAnnotationsNeeded(span)),
+        (name, ty) => {
+            let unknown = || Box::new(Type::Unknown);
+            let found = match name {
+                sym::SOME | sym::NONE => Type::Option(unknown()),
+                _ => Type::Result(unknown(), unknown()),
+            };
+            return Err(Error::MismatchedPatternType(span, ty.clone(), found));
+        }
+    };
+    let field = match fields.into_iter().next() {
+        Some(field) => Box::new(lower_pattern(field, field_ty, scope)?),
+        None => return Ok(Pattern::None),
+    };
+    Ok(match name.name {
+        sym::SOME => Pattern::Some(field),
+        sym::OK => Pattern::Ok(field),
+        _ => Pattern::Err(field),
+    })
+}
+
+/// A value of type `ty` that none of `patterns` match, written as a pattern, if there is one.
+fn uncovered(patterns: &[&Pattern], ty: &Type, items: &Items) -> Option<String> {
+    let rows: Vec<_> = patterns.iter().map(|pattern| vec![*pattern]).collect();
+    uncovered_row(&rows, std::slice::from_ref(ty), items).map(|mut witness| witness.remove(0))
+}
+
+static WILDCARD: Pattern = Pattern::Wildcard;
+
+/// Values of types `tys` that no row of patterns in `rows` matches all of, written as patterns,
+/// if there are any. Each variant of the first type is tried in turn, against the rows that could
+/// match it with its fields in place of the first pattern.
+fn uncovered_row(rows: &[Vec<&Pattern>], tys: &[Type], items: &Items) -> Option<Vec<String>> {
+    let Some((ty, rest)) = tys.split_first() else {
+        return rows.is_empty().then(Vec::new);
+    };
+    let variants: Vec<(Symbol, Vec<Type>)> = match ty {
+        Type::Option(payload) => vec![(sym::NONE, vec![]), (sym::SOME, vec![(**payload).clone()])],
+        Type::Result(ok, error) => vec![
+            (sym::OK, vec![(**ok).clone()]),
+            (sym::ERR, vec![(**error).clone()]),
+        ],
+        Type::Struct(name) => vec![(*name, items.structs[name].clone())],
+        Type::Bool => vec![(sym::FALSE, vec![]), (sym::TRUE, vec![])],
+        // Only wildcards cover every string or integer; string literals each cover one.
+        _ => {
+            let rows: Vec<_> = rows
+                .iter()
+                .filter(|row| matches!(row[0], Pattern::Wildcard | Pattern::Bind(_)))
+                .map(|row| row[1..].to_vec())
+                .collect();
+            let mut witness = uncovered_row(&rows, rest, items)?;
+            witness.insert(0, "_".into());
+            return Some(witness);
+        }
+    };
+    for (variant, fields) in variants {
+        let rows: Vec<_> = rows
+            .iter()
+            .filter_map(|row| {
+                let matched: Vec<&Pattern> = match (row[0], variant) {
+                    (Pattern::Wildcard | Pattern::Bind(_), _) => vec![&WILDCARD; fields.len()],
+                    (Pattern::None, sym::NONE) => vec![],
+                    (Pattern::Bool(value), variant) if *value == (variant == sym::TRUE) => vec![],
+                    (Pattern::Some(field), sym::SOME)
+                    | (Pattern::Ok(field), sym::OK)
+                    | (Pattern::Err(field), sym::ERR) => vec![&**field],
+                    (Pattern::Struct(fields), _) if matches!(ty, Type::Struct(_)) => {
+                        fields.iter().collect()
+                    }
+                    _ => return None,
+                };
+                Some(
+                    matched
+                        .into_iter()
+                        .chain(row[1..].iter().copied())
+                        .collect(),
+                )
+            })
+            .collect();
+        let field_tys: Vec<_> = fields.iter().chain(rest).cloned().collect();
+        if let Some(mut witness) = uncovered_row(&rows, &field_tys, items) {
+            let rest = witness.split_off(fields.len());
+            let pattern = if fields.is_empty() && !matches!(ty, Type::Struct(_)) {
+                variant.to_string()
+            } else {
+                format!("{}({})", variant, witness.join(", "))
+            };
+            return Some([vec![pattern], rest].concat());
+        }
+    }
+    None
+}
+
+/// Lowers the arguments of a builtin that takes `N` arguments of type `ty`.
+fn lower_args<'src, const N: usize>(
+    m: syntax::Macro,
+    ty: Type,
+    scope: &mut Scope,
+) -> Result<[Expr; N], Error<'src>> {
+    if m.args.len() > N {
+        return Err(Error::ExtraMacroArguments(
+            m.name.span,
+            N,
+            m.args[N..].iter().map(syntax::Expr::span).collect(),
+        ));
+    }
+    let args = m
+        .args
+        .into_iter()
+        .map(|arg| {
+            lower_expecting(arg, &ty, scope, |span, found| {
+                Error::MismatchedArgumentType(span, ty.clone(), found)
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    args.try_into()
+        .map_err(|_| Error::MissingMacroArguments(m.name.span, N))
+}
+
+fn int_value<'src>(lit: &syntax::IntLit, sign: &str) -> Result<i64, Error<'src>> {
+    format!("{}{}", sign, lit.digits)
+        .parse()
+        .map_err(|_| Error::IntLiteralTooLarge(lit.span.clone()))
+}
+
+/// Lowers a bound of a slice or range, which must be an integer.
+fn lower_bound<'src>(expr: syntax::Expr, scope: &mut Scope) -> Result<Box<Expr>, Error<'src>> {
+    let span = expr.span();
+    let bound = lower_expr(expr, scope)?;
+    match bound.ty() {
+        Type::Int => Ok(Box::new(bound)),
+        Type::Unknown => Ok(Box::new(bound.coerce(&Type::Int))),
+        ty => Err(Error::NonIntegerBound(span, ty)),
+    }
+}
+
+/// Checks a key of a `HashMap` with keys of type `ty`, given by value or by reference, which
+/// must be a string, an integer or a `bool`.
+fn lower_key<'src>(key: Expr, ty: &Type, span: Range<usize>) -> Result<Expr, Error<'src>> {
+    let key = deref_refs(key);
+    match ty.unify(&key.ty()) {
+        Some(ty) if ty.is_hashable() => Ok(key.coerce(&ty)),
+        Some(ty) => Err(Error::UnhashableKey(span, ty)),
+        None => Err(Error::MismatchedArgumentType(span, ty.clone(), key.ty())),
+    }
+}
+
+/// Lowers an operand of an arithmetic operator, which must be an integer.
+fn lower_int_operand<'src>(expr: syntax::Expr, scope: &mut Scope) -> Result<Expr, Error<'src>> {
+    let span = expr.span();
+    let expr = lower_expr(expr, scope)?;
+    match expr.ty() {
+        Type::Int => Ok(expr),
+        Type::Unknown => Ok(expr.coerce(&Type::Int)),
+        ty => Err(Error::NonIntegerOperand(span, ty)),
+    }
+}
+
+/// The string a literal's contents evaluate to. Line breaks are `\n` even in sources with Windows
+/// line endings. Only values are normalized, so spans still point into the original source.
+fn str_value(contents: &str) -> Symbol {
+    Symbol::intern(&contents.replace("\r\n", "\n"))
+}
+
+/// The signature of `Display::fmt`, `fn fmt(&self) -> str`, which returns what `{}` formats a
+/// value of the type implementing it as.
+fn display_fmt() -> Signature {
+    Signature {
+        generics: Vec::new(),
+        params: vec![Type::Ref(Box::new(Type::Param(sym::SELF_TYPE)), false)],
+        ret: Type::Str,
+        method: true,
+    }
+}
+
+/// Lowers an argument to be formatted, which must be a string, an integer, a `bool`, or of a type
+/// implementing `Display`, which is formatted as what its `fmt` returns.
+fn lower_display<'src>(expr: syntax::Expr, scope: &mut Scope) -> Result<Expr, Error<'src>> {
+    let span = expr.span();
+    // Formatting only reads the value, so it isn't moved.
+    let (expr, place) = lower_place(expr, scope)?;
+    let (expr, _) = deref_all(expr, place);
+    let ty = expr.ty();
+    if scope.implements(&ty, sym::DISPLAY)
+        && scope
+            .items
+            .traits
+            .get(&sym::DISPLAY)
+            .and_then(|methods| methods.get(&sym::FMT))
+            == Some(&display_fmt())
+    {
+        let callee = match ty {
+            Type::Struct(name) => {
+                Callee::Function(qualified_name(Some(name), sym::FMT), Vec::new())
+            }
+            ty => Callee::Method(ty, sym::FMT),
+        };
+        let receiver = Expr::Ref(Box::new(expr), false);
+        return Ok(Expr::Call(callee, vec![receiver], Type::Str));
+    }
+    match ty {
+        Type::Str | Type::Int => Ok(expr),
+        // A `bool` is formatted as the string naming it.
+        Type::Bool => {
+            let arm = |value: bool| Arm {
+                pattern: Pattern::Bool(value),
+                body: Expr::Str(if value { sym::TRUE } else { sym::FALSE }),
+            };
+            Ok(Expr::Match(
+                Box::new(expr),
+                vec![arm(true), arm(false)],
+                Type::Str,
+            ))
+        }
+        Type::Unknown => Err(Error::TypeAnnotationsNeeded(span)),
+        ty => Err(Error::NotDisplayable(span, ty)),
+    }
+}
+
+/// Lowers an argument formatted with `{:?}`, which must be built from strings, integers, `bool`s,
+/// `()`, `Option`s, `Result`s, `Vec`s, `HashMap`s and tuple structs.
+fn lower_debug<'src>(expr: syntax::Expr, scope: &mut Scope) -> Result<Expr, Error<'src>> {
+    let span = expr.span();
+    let (expr, place) = lower_place(expr, scope)?;
+    let (expr, _) = deref_all(expr, place);
+    match expr.ty() {
+        ty if ty.has_unknown() => Err(Error::TypeAnnotationsNeeded(span)),
+        ty if ty.is_debug(&scope.items.structs, &mut Vec::new()) => Ok(Expr::Debug(Box::new(expr))),
+        ty => Err(Error::NotDebuggable(span, ty)),
+    }
+}
+
+/// Lowers an argument formatted with `{:x}`, `{:X}`, `{:o}` or `{:b}`, which must be an integer.
+fn lower_radix<'src>(
+    expr: syntax::Expr,
+    spec_span: Range<usize>,
+    format: Format,
+    scope: &mut Scope,
+) -> Result<Expr, Error<'src>> {
+    let span = expr.span();
+    let (expr, place) = lower_place(expr, scope)?;
+    let (expr, _) = deref_all(expr, place);
+    match expr.ty() {
+        Type::Int => Ok(Expr::Radix(Box::new(expr), format)),
+        Type::Unknown => Err(Error::TypeAnnotationsNeeded(span)),
+        ty => Err(Error::MismatchedFmtType(span, spec_span, format, ty)),
+    }
+}
+
+/// Expands a format string and its arguments into the sequence of pieces to print.
+fn lower_fmt<'src>(
+    print_name_span: Range<usize>,
+    args: Vec<syntax::Expr>,
+    scope: &mut Scope,
+) -> Result<Vec<Expr>, Error<'src>> {
+    let mut args = args.into_iter();
+    let fmt_str = match args.next() {
+        Some(syntax::Expr::Str(fmt_str)) => fmt_str,
+        Some(arg) => return Err(Error::FmtStrNotLiteral(arg.span())),
+        None => return Err(Error::MissingFmtStr(print_name_span)),
+    };
+    let args: Vec<_> = args.collect();
+    let specs = extract_fmt(&fmt_str).map_err(|error| match error {
+        FmtError::UnmatchedBrace(span, brace) => Error::UnmatchedFmtBrace(span, brace),
+        FmtError::InvalidSpec(location) => Error::ParseError(ParseError::InvalidToken { location }),
+    })?;
+    let format_specifier_spans: Vec<_> = specs
+        .iter()
+        .filter_map(|spec| match spec {
+            FmtSpec::Arg { span, .. } => Some(span.clone()),
+            FmtSpec::Lit { .. } => None,
+        })
+        .collect();
+
+    let expected_arg_count = format_specifier_spans.len();
+    if args.len() > expected_arg_count {
+        return Err(Error::ExtraFmtArguments(
+            print_name_span,
+            fmt_str.span.clone(),
+            format_specifier_spans,
+            args.iter().map(syntax::Expr::span).collect(),
+        ));
+    }
+    if args.len() < expected_arg_count {
+        return Err(Error::NotEnoughFmtArguments(
+            print_name_span,
+            format_specifier_spans,
+            args.iter().map(syntax::Expr::span).collect(),
+        ));
+    }
+
+    let mut args = args.into_iter();
+    specs
+        .into_iter()
+        .map(|spec| {
+            let (span, format) = match spec {
+                FmtSpec::Lit { val, .. } => return Ok(Expr::Str(str_value(val))),
+                FmtSpec::Arg { span, format } => (span, format),
+            };
+            let arg = args.next().ok_or(Error::InternalCompilerError(
+                span.clone(),
+                "a format string had more arguments than were counted",
+            ))?;
+            match format {
+                Format::Display => lower_display(arg, scope),
+                Format::Debug => lower_debug(arg, scope),
+                format => lower_radix(arg, span, format, scope),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::run::parse;
+
+    fn lower_main(src: &str) -> Result<Vec<Stmt>, Error<'static>> {
+        let program = lower(parse(src).unwrap())?;
+        Ok(program.functions.into_iter().next().unwrap().body)
+    }
+
+    fn str(val: &str) -> Expr {
+        Expr::Str(Symbol::intern(val))
+    }
+
+    #[test]
+    fn println_desugars_to_print_and_newline() {
+        assert_eq!(
+            lower_main(r#"fn main() { println!("a"); println!(); }"#).unwrap(),
+            [
+                Stmt::Print(Stream::Stdout, vec![str("a")]),
+                Stmt::Print(Stream::Stdout, vec![str("\n")]),
+                Stmt::Print(Stream::Stdout, vec![str("\n")]),
+            ]
+        );
+    }
+
+    #[test]
+    fn format_arguments_are_spliced_into_pieces() {
+        assert_eq!(
+            lower_main(r#"fn main() { print!("{} and {}!", "a", "b"); }"#).unwrap(),
+            [Stmt::Print(
+                Stream::Stdout,
+                vec![str("a"), str(" and "), str("b"), str("!"),]
+            )]
+        );
+    }
+
+    #[test]
+    fn eprint_writes_to_stderr() {
+        assert_eq!(
+            lower_main(r#"fn main() { eprintln!("a"); }"#).unwrap(),
+            [
+                Stmt::Print(Stream::Stderr, vec![str("a")]),
+                Stmt::Print(Stream::Stderr, vec![str("\n")]),
+            ]
+        );
+    }
+
+    #[test]
+    fn read_macros_lower_to_expressions() {
+        assert_eq!(
+            lower_main(r#"fn main() { print!("{}", read_line!()); read_to_string!(); }"#).unwrap(),
+            [
+                Stmt::Print(Stream::Stdout, vec![Expr::ReadLine]),
+                Stmt::Expr(Expr::ReadToString),
+            ]
+        );
+    }
+
+    #[test]
+    fn fmt_str_must_be_literal() {
+        assert_eq!(
+            lower_main(r#"fn main() { print!(read_line!()); }"#),
+            Err(Error::FmtStrNotLiteral(19..31))
+        );
+    }
+
+    #[test]
+    fn print_is_not_an_expression() {
+        assert_eq!(
+            lower_main(r#"fn main() { print!("{}", print!("a")); }"#),
+            Err(Error::NotAnExpression(25..36))
+        );
+    }
+
+    #[test]
+    fn unknown_macros_are_rejected() {
+        assert_eq!(
+            lower_main(r#"fn main() { dbg!("a"); }"#),
+            Err(Error::UnknownMacro(12..16))
+        );
+    }
+
+    #[test]
+    fn arithmetic_lowers_with_precedence() {
+        use BinOp::*;
+        let int = |val| Box::new(Expr::Int(val));
+        assert_eq!(
+            lower_main(r#"fn main() { print!("{}", 1 + 2 * -(3 - 4) % 5); }"#).unwrap(),
+            [Stmt::Print(
+                Stream::Stdout,
+                vec![Expr::Binary(
+                    Add,
+                    int(1),
+                    Box::new(Expr::Binary(
+                        Rem,
+                        Box::new(Expr::Binary(
+                            Mul,
+                            int(2),
+                            Box::new(Expr::Neg(Box::new(Expr::Binary(Sub, int(3), int(4)))))
+                        )),
+                        int(5)
+                    ))
+                )]
+            )]
+        );
+    }
+
+    #[test]
+    fn int_literals_must_fit_in_i64() {
+        assert_eq!(
+            lower_main(r#"fn main() { print!("{}", -9223372036854775808); }"#).unwrap(),
+            [Stmt::Print(Stream::Stdout, vec![Expr::Int(i64::MIN)])]
+        );
+        assert_eq!(
+            lower_main(r#"fn main() { print!("{}", 9223372036854775808); }"#),
+            Err(Error::IntLiteralTooLarge(25..44))
+        );
+    }
+
+    #[test]
+    fn arithmetic_operands_must_be_integers() {
+        assert_eq!(
+            lower_main(r#"fn main() { print!("{}", 1 + read_line!()); }"#),
+            Err(Error::NonIntegerOperand(29..41, Type::Str))
+        );
+    }
+
+    #[test]
+    fn names_are_unresolved() {
+        assert_eq!(
+            lower_main(r#"fn main() { print!("{}", name); }"#),
+            Err(Error::UnresolvedName(25..29))
+        );
+    }
+
+    #[test]
+    fn format_lowers_to_its_pieces() {
+        assert_eq!(
+            lower_main(r#"fn main() { print!("<{}>", format!("{}!", read_line!())); }"#).unwrap(),
+            [Stmt::Print(
+                Stream::Stdout,
+                vec![
+                    str("<"),
+                    Expr::Format(vec![Expr::ReadLine, str("!")]),
+                    str(">")
+                ]
+            )]
+        );
+        assert_eq!(
+            lower_main(r#"fn main() { format!(); }"#),
+            Err(Error::MissingFmtStr(12..19))
+        );
+    }
+
+    #[test]
+    fn windows_line_endings_in_literals_are_newlines() {
+        assert_eq!(
+            lower_main("fn main() {\r\n    print!(\"a\r\nb{}\", \"c\r\nd\");\r\n}\r\n").unwrap(),
+            [Stmt::Print(Stream::Stdout, vec![str("a\nb"), str("c\nd")])]
+        );
+    }
+
+    #[test]
+    fn getenv_takes_one_string() {
+        assert_eq!(
+            lower_main(r#"fn main() { getenv!(); }"#),
+            Err(Error::MissingMacroArguments(12..19, 1))
+        );
+        assert_eq!(
+            lower_main(r#"fn main() { getenv!(1 + 2); }"#),
+            Err(Error::MismatchedArgumentType(20..25, Type::Str, Type::Int))
+        );
+        let extra = 25..28;
+        assert_eq!(
+            lower_main(r#"fn main() { getenv!("A", "B"); }"#),
+            Err(Error::ExtraMacroArguments(12..19, 1, vec![extra]))
+        );
+    }
+
+    #[test]
+    fn sleep_is_a_statement() {
+        assert_eq!(
+            lower_main(r#"fn main() { sleep_millis!(now_millis!() % 10); }"#).unwrap(),
+            [Stmt::Sleep(Expr::Binary(
+                BinOp::Rem,
+                Box::new(Expr::NowMillis),
+                Box::new(Expr::Int(10))
+            ))]
+        );
+        assert_eq!(
+            lower_main(r#"fn main() { print!("{}", sleep_millis!(1)); }"#),
+            Err(Error::NotAnExpression(25..41))
+        );
+    }
+
+    #[test]
+    fn random_int_takes_two_integers() {
+        assert_eq!(
+            lower_main(r#"fn main() { random_int!(1, 10 - 1); }"#).unwrap(),
+            [Stmt::Expr(Expr::RandomInt(
+                Box::new(Expr::Int(1)),
+                Box::new(Expr::Binary(
+                    BinOp::Sub,
+                    Box::new(Expr::Int(10)),
+                    Box::new(Expr::Int(1))
+                ))
+            ))]
+        );
+        assert_eq!(
+            lower_main(r#"fn main() { random_int!(1, "2"); }"#),
+            Err(Error::MismatchedArgumentType(27..30, Type::Int, Type::Str))
+        );
+    }
+
+    #[test]
+    fn none_takes_its_type_from_the_other_arms() {
+        assert_eq!(
+            lower_main(r#"fn main() { exit!(match 1 { x => Some(x), _ => None }.unwrap()); }"#)
+                .unwrap(),
+            [Stmt::Exit(Expr::Unwrap(
+                Box::new(Expr::Match(
+                    Box::new(Expr::Int(1)),
+                    vec![
+                        Arm {
+                            pattern: Pattern::Bind(Local(0)),
+                            body: Expr::Some(Box::new(Expr::Local(Local(0), Type::Int))),
+                        },
+                        Arm {
+                            pattern: Pattern::Wildcard,
+                            body: Expr::None(Type::Int),
+                        },
+                    ],
+                    Type::Option(Box::new(Type::Int)),
+                )),
+                18..62
+            ))]
+        );
+    }
+
+    #[test]
+    fn matches_must_be_exhaustive() {
+        assert_eq!(
+            lower_main(r#"fn main() { exit!(match Some(1) { Some(x) => x }); }"#),
+            Err(Error::NonExhaustiveMatch(24..31, "None".into()))
+        );
+        assert_eq!(
+            lower_main(r#"fn main() { exit!(match Some(None) { None => 1, Some(None) => 2 }); }"#),
+            Err(Error::NonExhaustiveMatch(24..34, "Some(Some(_))".into()))
+        );
+        assert_eq!(
+            lower_main(r#"fn main() { exit!(match "a" { "a" => 1, "b" => 2 }); }"#),
+            Err(Error::NonExhaustiveMatch(24..27, "_".into()))
+        );
+        assert_eq!(
+            lower_main(r#"fn main() { exit!(match 1 { "a" => 1, _ => 2 }); }"#),
+            Err(Error::MismatchedPatternType(28..31, Type::Int, Type::Str))
+        );
+    }
+
+    #[test]
+    fn types_implementing_display_are_formatted_by_their_fmt() {
+        let src = r#"
+            struct P(); impl Display for P { fn fmt(&self) -> str { "p" } }
+            fn main() { print!("{}", P()); }
+        "#;
+        let fmt = Callee::Function(Symbol::intern("P::fmt"), Vec::new());
+        assert!(matches!(
+            &lower_main(src).unwrap()[..],
+            [Stmt::Print(Stream::Stdout, pieces)]
+                if matches!(&pieces[..], [Expr::Call(callee, args, Type::Str)]
+                    if *callee == fmt && matches!(args[..], [Expr::Ref(_, false)]))
+        ));
+        let src = "struct P(); fn main() { print!(\"{}\", P()); }";
+        assert_eq!(
+            lower_main(src),
+            Err(Error::NotDisplayable(
+                37..40,
+                Type::Struct(Symbol::intern("P"))
+            ))
+        );
+    }
+
+    #[test]
+    fn options_are_type_checked() {
+        assert_eq!(
+            lower_main(r#"fn main() { exit!(match Some(1) { Some(x) => x, None => "a" }); }"#),
+            Err(Error::MismatchedArmTypes(56..59, Type::Int, Type::Str))
+        );
+        assert_eq!(
+            lower_main(r#"fn main() { exit!(match 1 { None => 1, _ => 2 }); }"#),
+            Err(Error::MismatchedPatternType(
+                28..32,
+                Type::Int,
+                Type::Option(Box::new(Type::Unknown))
+            ))
+        );
+        assert_eq!(
+            lower_main(r#"fn main() { print!("{}", Some(1)); }"#),
+            Err(Error::NotDisplayable(
+                25..32,
+                Type::Option(Box::new(Type::Int))
+            ))
+        );
+        assert_eq!(
+            lower_main(r#"fn main() { exit!(read_line!().unwrap()); }"#),
+            Err(Error::NoMethod(31..37, Type::Str))
+        );
+        assert_eq!(
+            lower_main(r#"fn main() { exit!(Some(1, 2)); }"#),
+            Err(Error::MismatchedArgumentCount(18..22, 1, 2))
+        );
+    }
+
+    #[test]
+    fn try_needs_a_function_returning_result() {
+        assert_eq!(
+            lower_main(r#"fn main() { exit!(Ok(1)?); }"#),
+            Err(Error::TryOutsideResult(18..24))
+        );
+        assert_eq!(
+            lower_main(r#"fn main() -> Result<(), str> { exit!(Err(1)?); }"#),
+            Err(Error::MismatchedErrorType(37..43, Type::Str, Type::Int))
+        );
+        assert_eq!(
+            lower_main(r#"fn main() -> Result<(), str> { exit!(Some(1)?); }"#),
+            Err(Error::TryNotResult(
+                37..44,
+                Type::Option(Box::new(Type::Int))
+            ))
+        );
+        assert_eq!(
+            lower_main(r#"fn main() -> Option<i64> {}"#),
+            Err(Error::UnsupportedReturnType(
+                13..24,
+                Type::Option(Box::new(Type::Int))
+            ))
+        );
+        assert_eq!(
+            lower_main(r#"fn main() -> Result<(), Error> {}"#),
+            Err(Error::UnknownType(24..29))
+        );
+    }
+
+    #[test]
+    fn result_matches_must_cover_ok_and_err() {
+        assert_eq!(
+            lower_main(
+                r#"fn main() { exit!(match Err(Some(1)) { Ok(x) => x, Err(None) => 0 }); }"#
+            ),
+            Err(Error::NonExhaustiveMatch(24..36, "Err(Some(_))".into()))
+        );
+    }
+
+    #[test]
+    fn methods_are_called_by_their_qualified_name() {
+        let point = Symbol::intern("Point");
+        assert_eq!(
+            lower_main(
+                r#"
+                fn main() { exit!(Point::new(1, 2).sum()); }
+                struct Point(i64, i64);
+                impl Point {
+                    fn new(x: i64, y: i64) -> Self { Point(x, y) }
+                    fn sum(&self) -> i64 { self.0 + self.1 }
+                }
+                "#
+            )
+            .unwrap(),
+            [Stmt::Exit(Expr::Call(
+                Callee::Function(Symbol::intern("Point::sum"), vec![]),
+                // `&self` is borrowed.
+                vec![Expr::Ref(
+                    Box::new(Expr::Call(
+                        Callee::Function(Symbol::intern("Point::new"), vec![]),
+                        vec![Expr::Int(1), Expr::Int(2)],
+                        Type::Struct(point),
+                    )),
+                    false,
+                )],
+                Type::Int,
+            ))]
+        );
+    }
+
+    #[test]
+    fn calls_must_match_the_signature() {
+        let src = r#"fn main() { exit!(f("a")); } fn f(x: i64) -> i64 { x }"#;
+        assert_eq!(
+            lower_main(src),
+            Err(Error::MismatchedArgumentType(20..23, Type::Int, Type::Str))
+        );
+        let src = r#"fn main() { exit!(f()); } fn f(x: i64) -> i64 { x }"#;
+        assert_eq!(
+            lower_main(src),
+            Err(Error::MismatchedArgumentCount(18..19, 1, 0))
+        );
+        let src = r#"fn main() {} fn f() -> i64 { "a" }"#;
+        assert_eq!(
+            lower_main(src),
+            Err(Error::MismatchedReturnType(29..32, Type::Int, Type::Str))
+        );
+        let src = r#"fn main() {} struct P(i64); impl P { fn f(&self) -> i64 { self.1 } }"#;
+        assert_eq!(
+            lower_main(src),
+            Err(Error::NoField(63..64, Type::Struct(Symbol::intern("P"))))
+        );
+    }
+
+    #[test]
+    fn items_are_checked() {
+        assert_eq!(
+            lower_main("fn main(x: i64) {}"),
+            Err(Error::EntryPointParameters(3..7))
+        );
+        assert_eq!(
+            lower_main("fn main() {} fn f(self) {}"),
+            Err(Error::UnexpectedSelf(18..22))
+        );
+        assert_eq!(
+            lower_main("fn main() {} struct List(i64, Option<List>);"),
+            Err(Error::RecursiveType(20..24))
+        );
+        assert_eq!(
+            lower_main("fn main() {} fn main() {}"),
+            Err(Error::DuplicateDefinition(16..20, 3..7, None))
+        );
+        assert_eq!(
+            lower_main("fn main() {} fn f(x: i64) {} struct P(); impl P { fn f() {} } fn f() -> str { \"\" }"),
+            Err(Error::DuplicateDefinition(
+                65..66,
+                16..17,
+                Some(("fn(i64)".into(), "fn() -> str".into()))
+            ))
+        );
+        assert_eq!(
+            lower_main("fn main() {} fn f(x: i64, x: str) {}"),
+            Err(Error::DuplicateDefinition(26..27, 18..19, None))
+        );
+        assert_eq!(
+            lower_main("fn main() {} struct P(); static P: i64 = 1;"),
+            Err(Error::DuplicateDefinition(32..33, 20..21, None))
+        );
+        assert_eq!(
+            lower_main("fn main() {} trait T {} struct T();"),
+            Err(Error::DuplicateDefinition(31..32, 19..20, None))
+        );
+        assert_eq!(
+            lower_main("fn P() {} fn main() {} struct P();"),
+            Err(Error::DuplicateDefinition(30..31, 3..4, None))
+        );
+        assert_eq!(
+            lower_main("fn main() {} impl str {}"),
+            Err(Error::ImplOnBuiltinType(18..21, Type::Str))
+        );
+    }
+
+    #[test]
+    fn struct_matches_must_cover_every_field() {
+        let src = r#"
+            struct P(Option<i64>, i64);
+            fn main() { exit!(match P(None, 1) { P(Some(x), _) => x }); }
+        "#;
+        assert_eq!(
+            lower_main(src),
+            Err(Error::NonExhaustiveMatch(77..87, "P(None, _)".into()))
+        );
+    }
+
+    #[test]
+    fn generic_calls_are_instantiated_with_the_argument_types() {
+        let src = r#"
+            fn main() { exit!(first(Some(1))); }
+            fn first<T>(x: Option<T>) -> T { x.unwrap() }
+        "#;
+        assert_eq!(
+            lower_main(src).unwrap(),
+            [Stmt::Exit(Expr::Call(
+                Callee::Function(Symbol::intern("first"), vec![Type::Int]),
+                vec![Expr::Some(Box::new(Expr::Int(1)))],
+                Type::Int,
+            ))]
+        );
+        let src = r#"fn main() { exit!(first(None)); } fn first<T>(x: Option<T>) -> i64 { 0 }"#;
+        let t = Symbol::intern("T");
+        assert_eq!(lower_main(src), Err(Error::UnresolvedTypeParam(18..23, t)));
+        let src = r#"fn main() { exit!(same(1, "a")); } fn same<T>(x: T, y: T) -> i64 { 0 }"#;
+        assert_eq!(
+            lower_main(src),
+            Err(Error::AmbiguousTypeParam(26..29, t, Type::Int, Type::Str))
+        );
+    }
+
+    #[test]
+    fn closures_capture_the_locals_they_use() {
+        let src = r#"
+            fn main() { exit!(add(1)); }
+            fn add(n: i64) -> i64 { apply(|x| x + n, 2) }
+            fn apply<F: Fn(i64) -> i64>(f: F, x: i64) -> i64 { f(x) }
+        "#;
+        let program = lower(parse(src).unwrap()).unwrap();
+        let add = &program.functions[1];
+        let closure = Symbol::intern("add::{closure#0}");
+        let ty = Type::Closure(closure, Box::new([]), Box::new([Type::Int]));
+        assert_eq!(
+            add.tail,
+            Some(Expr::Call(
+                Callee::Function(Symbol::intern("apply"), vec![ty]),
+                vec![
+                    Expr::Closure(closure, vec![], vec![Expr::Local(Local(0), Type::Int)]),
+                    Expr::Int(2),
+                ],
+                Type::Int,
+            ))
+        );
+        assert_eq!(add.closures[0].name, closure);
+        assert_eq!(
+            add.closures[0].params,
+            [(Local(0), Type::Int), (Local(1), Type::Int)]
+        );
+    }
+
+    #[test]
+    fn only_closures_satisfying_the_bound_can_be_passed() {
+        let src = r#"
+            fn main() { exit!(apply(|x: str| 1, 2)); }
+            fn apply<F: Fn(i64) -> i64>(f: F, x: i64) -> i64 { f(x) }
+        "#;
+        assert_eq!(
+            lower_main(src),
+            Err(Error::UnsatisfiedBound(
+                31..36,
+                Type::Closure(
+                    Symbol::intern("main::{closure#0}"),
+                    Box::new([]),
+                    Box::new([])
+                ),
+                "Fn(i64) -> i64".into()
+            ))
+        );
+        let src = r#"fn main() { exit!(apply(|x| x, 2)); } fn apply<F>(f: F, x: i64) -> i64 { 0 }"#;
+        assert_eq!(lower_main(src), Err(Error::TypeAnnotationsNeeded(25..26)));
+        let src = r#"fn main() {} fn call(f: i64) -> i64 { f(1) }"#;
+        assert_eq!(lower_main(src), Err(Error::NotCallable(38..39, Type::Int)));
+    }
+
+    #[test]
+    fn moved_locals_cant_be_used() {
+        let p = Symbol::intern("p");
+        let src = r#"
+            struct P(str);
+            fn main() {}
+            fn take(p: P) -> i64 { 0 }
+            fn twice(p: P) -> i64 { take(p) + take(p) }
+        "#;
+        assert_eq!(
+            lower_main(src),
+            Err(Error::UseAfterMove(143..144, p, 133..134))
+        );
+        // Only one arm of a `match` runs, and borrowing or formatting doesn't move.
+        let src = r#"
+            struct P(str);
+            fn main() {}
+            fn take(p: P) -> i64 { 0 }
+            fn peek(p: &P) -> i64 { 0 }
+            fn len(s: str) -> i64 { 0 }
+            fn either(p: P) -> i64 {
+                match Some(1) { Some(_) => take(p), None => peek(&p) + len(format!("{}", p.0)) }
+            }
+        "#;
+        assert!(lower_main(src).is_ok());
+        let src = r#"
+            fn main() {}
+            fn apply<F: Fn() -> str>(f: F) -> str { f() }
+            fn twice(s: str) -> str { format!("{}{}", apply(|| s), s) }
+        "#;
+        let s = Symbol::intern("s");
+        assert_eq!(
+            lower_main(src),
+            Err(Error::UseAfterMove(151..152, s, 144..148))
+        );
+    }
+
+    #[test]
+    fn borrowed_locals_cant_be_moved_or_borrowed_mutably() {
+        let s = Symbol::intern("s");
+        let src = r#"
+            fn main() {}
+            fn both(a: &str, b: str) -> i64 { 0 }
+            fn f(s: str) -> i64 { both(&s, s) }
+        "#;
+        assert_eq!(
+            lower_main(src),
+            Err(Error::MoveWhileBorrowed(119..120, s, 115..117))
+        );
+        let src = r#"
+            fn main() {}
+            fn both(a: &mut str, b: &str) -> i64 { 0 }
+            fn f(s: str) -> i64 { both(&mut s, &s) + both(&mut s, "") }
+        "#;
+        assert_eq!(
+            lower_main(src),
+            Err(Error::ConflictingBorrow(128..130, s, 120..126))
+        );
+    }
+
+    #[test]
+    fn references_to_owned_locals_cant_be_returned() {
+        let src = r#"
+            fn main() {}
+            fn first(p: &Pair) -> &str { &p.0 }
+            fn own(p: Pair) -> &str { &p.0 }
+            struct Pair(str, str);
+        "#;
+        assert_eq!(
+            lower_main(src),
+            Err(Error::DanglingReference(
+                112..116,
+                Symbol::intern("p"),
+                117..118
+            ))
+        );
+        let src = r#"fn main() { exit!(*id(&1)); } fn id(x: &i64) -> i64 { *x }"#;
+        assert_eq!(
+            lower_main(src),
+            Err(Error::NotAReference(19..25, Type::Int))
+        );
+    }
+
+    #[test]
+    fn boxes_are_read_through_and_moved() {
+        let src = r#"
+            fn main() { exit!(*Box::new(1) + Box::new(Pair(2, 3)).1); }
+            struct Pair(i64, i64);
+        "#;
+        assert_eq!(
+            lower_main(src).unwrap(),
+            [Stmt::Exit(Expr::Binary(
+                BinOp::Add,
+                Box::new(Expr::Deref(Box::new(Expr::Box(Box::new(Expr::Int(1)))))),
+                Box::new(Expr::Field(
+                    Box::new(Expr::Deref(Box::new(Expr::Box(Box::new(Expr::Struct(
+                        Symbol::intern("Pair"),
+                        vec![Expr::Int(2), Expr::Int(3)]
+                    )))))),
+                    1,
+                    Type::Int
+                ))
+            ))]
+        );
+        let src = r#"
+            fn main() {}
+            fn copied(b: Box<i64>) -> Box<i64> { exit!(*b); b }
+            fn moved(b: Box<str>) -> Box<str> { exit!(len(*b)); b }
+            fn len(s: str) -> i64 { 0 }
+        "#;
+        assert_eq!(
+            lower_main(src),
+            Err(Error::UseAfterMove(154..155, Symbol::intern("b"), 149..150))
+        );
+        let src = "fn main() {} struct List(i64, Option<Box<List>>);";
+        assert!(lower_main(src).is_ok());
+    }
+
+    #[test]
+    fn rcs_are_cloned_without_being_moved() {
+        let src = r#"
+            fn main() {}
+            fn share(r: Rc<str>) -> Pair { Pair(r.clone(), r) }
+            fn read(r: &Rc<str>) -> str { *Rc::clone(r) }
+            struct Pair(Rc<str>, Rc<str>);
+        "#;
+        assert!(lower_main(src).is_ok());
+        let src = r#"fn main() {} fn f(r: Rc<i64>) -> Rc<i64> { Rc::clone(r) }"#;
+        assert_eq!(
+            lower_main(src),
+            Err(Error::MismatchedArgumentType(
+                53..54,
+                Type::Ref(Box::new(Type::Rc(Box::new(Type::Unknown))), false),
+                Type::Rc(Box::new(Type::Int))
+            ))
+        );
+    }
+
+    #[test]
+    fn strings_are_sliced_by_integers() {
+        let src = r#"fn main() { exit!("ab"[1..].len()); }"#;
+        assert_eq!(
+            lower_main(src).unwrap(),
+            [Stmt::Exit(Expr::Len(Box::new(Expr::Slice(
+                Box::new(Expr::Str(Symbol::intern("ab"))),
+                Some(Box::new(Expr::Int(1))),
+                None,
+                18..27
+            ))))]
+        );
+        let src = r#"fn main() { exit!(1[0..1].len()); }"#;
+        assert_eq!(lower_main(src), Err(Error::NotSliceable(18..19, Type::Int)));
+        let src = r#"fn main() { exit!("ab"[.."b"].len()); }"#;
+        assert_eq!(
+            lower_main(src),
+            Err(Error::NonIntegerBound(25..28, Type::Str))
+        );
+    }
+
+    #[test]
+    fn string_methods_read_their_receiver() {
+        let src = r#"fn main() { print!("{}", "a".contains("b")); }"#;
+        let contains = Expr::Contains(
+            Box::new(Expr::Str(Symbol::intern("a"))),
+            Box::new(Expr::Str(Symbol::intern("b"))),
+        );
+        assert_eq!(
+            lower_main(src).unwrap(),
+            [Stmt::Print(
+                Stream::Stdout,
+                vec![Expr::Match(
+                    Box::new(contains),
+                    vec![
+                        Arm {
+                            pattern: Pattern::Bool(true),
+                            body: Expr::Str(sym::TRUE)
+                        },
+                        Arm {
+                            pattern: Pattern::Bool(false),
+                            body: Expr::Str(sym::FALSE)
+                        },
+                    ],
+                    Type::Str
+                )]
+            )]
+        );
+        let src = r#"
+            fn main() {}
+            fn f(s: str) -> str { exit!(s.len()); s.trim().to_lowercase() }
+        "#;
+        assert!(lower_main(src).is_ok());
+        let src = r#"fn main() { exit!(match "a".contains(1) { true => 1, false => 0 }); }"#;
+        assert_eq!(
+            lower_main(src),
+            Err(Error::MismatchedArgumentType(37..38, Type::Str, Type::Int))
+        );
+        let src = r#"fn main() { exit!(match 1 { true => 1, _ => 0 }); }"#;
+        assert_eq!(
+            lower_main(src),
+            Err(Error::MismatchedPatternType(28..32, Type::Int, Type::Bool))
+        );
+    }
+
+    #[test]
+    fn for_loops_iterate_with_an_iterator() {
+        let src = r#"fn main() { for i in 0..2 { exit!(i); } }"#;
+        assert_eq!(
+            lower_main(src).unwrap(),
+            [Stmt::For(
+                Pattern::Bind(Local(0)),
+                Expr::IntoIter(Box::new(Expr::Range(
+                    Box::new(Expr::Int(0)),
+                    Box::new(Expr::Int(2))
+                ))),
+                vec![Stmt::Exit(Expr::Local(Local(0), Type::Int))]
+            )]
+        );
+        let src = r#"fn main() { for x in 1 {} }"#;
+        assert_eq!(lower_main(src), Err(Error::NotIterable(21..22, Type::Int)));
+        let src = r#"fn main() { for Some(x) in vec![None, Some(1)] {} }"#;
+        assert_eq!(
+            lower_main(src),
+            Err(Error::RefutableForPattern(16..23, "None".into()))
+        );
+        let src = r#"
+            fn main() {}
+            fn f(v: Vec<str>) { for s in &v { exit!(v.len()); } for c in "ab".chars() {} }
+        "#;
+        assert!(lower_main(src).is_ok());
+        let src = r#"fn main() { for i in 0..2 { for s in vec![format!("a")] {} } }"#;
+        assert!(lower_main(src).is_ok());
+        let src = r#"fn main() {} fn f(s: str) { for i in 0..2 { exit!(Box::new(s).len()); } }"#;
+        assert_eq!(
+            lower_main(src),
+            Err(Error::MovedInLoop(59..60, Symbol::intern("s")))
+        );
+    }
+
+    #[test]
+    fn type_arguments_can_be_given_explicitly() {
+        let src = r#"
+            fn main() { exit!(first::<i64>(None)); }
+            fn first<T>(x: Option<T>) -> i64 { 0 }
+        "#;
+        assert_eq!(
+            lower_main(src).unwrap(),
+            [Stmt::Exit(Expr::Call(
+                Callee::Function(Symbol::intern("first"), vec![Type::Int]),
+                vec![Expr::None(Type::Int)],
+                Type::Int,
+            ))]
+        );
+        let src = r#"fn main() { exit!(id::<str>(1)); } fn id<T>(x: T) -> i64 { 0 }"#;
+        assert_eq!(
+            lower_main(src),
+            Err(Error::MismatchedArgumentType(28..29, Type::Str, Type::Int))
+        );
+        let src = r#"fn main() { exit!(id::<i64, str>(1)); } fn id<T>(x: T) -> T { x }"#;
+        assert_eq!(
+            lower_main(src),
+            Err(Error::MismatchedTypeArgumentCount(18..20, 1, 2))
+        );
+        assert_eq!(
+            lower_main("fn main() { exit!(Some::<i64>(1).unwrap()); }"),
+            Err(Error::MismatchedTypeArgumentCount(18..22, 0, 1))
+        );
+    }
+
+    #[test]
+    fn trait_impls_must_match_the_trait() {
+        let src = r#"
+            fn main() {}
+            trait Area { fn area(self) -> i64; }
+            struct Square(i64);
+            impl Area for Square {}
+        "#;
+        assert_eq!(
+            lower_main(src),
+            Err(Error::MissingTraitMethods(
+                124..139,
+                vec![Symbol::intern("area")]
+            ))
+        );
+        let src = r#"
+            fn main() {}
+            trait Area { fn area(self) -> i64; }
+            struct Square(i64);
+            impl Area for Square { fn area(self) -> str { "" } fn side(self) {} }
+        "#;
+        assert_eq!(
+            lower_main(src),
+            Err(Error::MismatchedTraitMethod(
+                145..149,
+                "fn(self) -> i64".into()
+            ))
+        );
+        let src = r#"
+            fn main() {}
+            trait Area { fn area(self) -> i64; }
+            struct Square(i64);
+            impl Area for Square { fn side(self) {} }
+        "#;
+        assert_eq!(
+            lower_main(src),
+            Err(Error::NotATraitMethod(145..149, Symbol::intern("Area")))
+        );
+        assert_eq!(
+            lower_main("fn main() {} struct S(i64); impl Show for S {}"),
+            Err(Error::UnknownTrait(33..37))
+        );
+        assert_eq!(
+            lower_main("fn main() {} fn f<T: Show>(x: T) {}"),
+            Err(Error::UnknownTrait(21..25))
+        );
+        assert_eq!(
+            lower_main("fn main<T>() {}"),
+            Err(Error::EntryPointParameters(3..7))
+        );
+    }
+
+    #[test]
+    fn generic_arguments_must_satisfy_their_bounds() {
+        let src = r#"
+            trait Area { fn area(self) -> i64; }
+            fn main() { exit!(area_of(1)); }
+            fn area_of<T: Area>(x: T) -> i64 { x.area() }
+        "#;
+        assert_eq!(
+            lower_main(src),
+            Err(Error::UnsatisfiedBound(80..87, Type::Int, "Area".into()))
+        );
+        let src = r#"
+            trait Area { fn area(self) -> i64; }
+            fn main() {}
+            fn area_of<T>(x: T) -> i64 { x.area() }
+        "#;
+        let t = Type::Param(Symbol::intern("T"));
+        assert_eq!(lower_main(src), Err(Error::NoMethod(118..122, t)));
+    }
+
+    #[test]
+    fn hash_map_keys_must_be_hashable() {
+        let src = "fn main() { exit!(HashMap::new().insert(vec![1], 2).len()); }";
+        let vec = Type::Vec(Box::new(Type::Int));
+        assert_eq!(lower_main(src), Err(Error::UnhashableKey(40..47, vec)));
+        let src = "fn main() { println!(\"{:?}\", |x: i64| x); }";
+        assert!(matches!(
+            lower_main(src),
+            Err(Error::NotDebuggable(range, _)) if range == (29..39)
+        ));
+    }
+
+    #[test]
+    fn radix_formats_only_take_integers() {
+        let src = r#"fn main() { let n = 7; println!("{:b}{:X}", n, n); }"#;
+        assert_eq!(
+            lower_main(src).unwrap()[1],
+            Stmt::Print(
+                Stream::Stdout,
+                vec![
+                    Expr::Radix(Box::new(Expr::Local(Local(0), Type::Int)), Format::Binary),
+                    Expr::Radix(Box::new(Expr::Local(Local(0), Type::Int)), Format::UpperHex),
+                ]
+            )
+        );
+        let src = r#"fn main() { println!("{:x}", "hello"); }"#;
+        assert_eq!(
+            lower_main(src),
+            Err(Error::MismatchedFmtType(
+                29..36,
+                22..26,
+                Format::LowerHex,
+                Type::Str
+            ))
+        );
+    }
+
+    #[test]
+    fn vecs_sort_by_ordered_keys() {
+        let src = "fn main() { exit!(vec![vec![1]].sort().len()); }";
+        let vec = Type::Vec(Box::new(Type::Int));
+        assert_eq!(lower_main(src), Err(Error::Unsortable(18..31, vec)));
+        let src = "fn main() { exit!(vec![1].sort_by_key(|x| Some(*x)).len()); }";
+        let option = Type::Option(Box::new(Type::Int));
+        assert_eq!(lower_main(src), Err(Error::Unsortable(38..50, option)));
+    }
+
+    #[test]
+    fn statics_must_be_constant() {
+        let src = "static A: i64 = B; static B: i64 = A + 1; fn main() {}";
+        let a = Symbol::intern("A");
+        assert_eq!(lower_main(src), Err(Error::CyclicStatic(35..36, a)));
+        let src = "static A: i64 = f(); fn main() {} fn f() -> i64 { 1 }";
+        assert_eq!(lower_main(src), Err(Error::NonConstStatic(16..19)));
+        let src = "static A: str = 1 + 1; fn main() {}";
+        assert_eq!(
+            lower_main(src),
+            Err(Error::MismatchedStaticType(16..21, Type::Str, Type::Int))
+        );
+        let src = "static A: i64 = 1 % (2 - 2); fn main() {}";
+        let message = "attempt to calculate the remainder with a divisor of zero";
+        assert_eq!(
+            lower_main(src),
+            Err(Error::StaticEvalFailed(16..27, message))
+        );
+        let src = "static mut A: i64 = 1; fn main() {}";
+        assert_eq!(lower_main(src), Err(Error::StaticMut(0..12)));
+    }
+
+    #[test]
+    fn attributes_must_be_known() {
+        assert!(lower_main("#[test] #[allow(anything)] fn main() {}").is_ok());
+        assert_eq!(
+            lower_main("#[inline] fn main() {}"),
+            Err(Error::UnknownAttribute(2..8))
+        );
+        assert_eq!(
+            lower_main("#[test(x)] fn main() {}"),
+            Err(Error::MalformedAttribute(0..10, "#[test]"))
+        );
+        assert_eq!(
+            lower_main("#[allow] fn main() {}"),
+            Err(Error::MalformedAttribute(0..8, "#[allow(lint, ...)]"))
+        );
+        assert_eq!(
+            lower_main("fn main() {} #[test] fn t(x: i64) {}"),
+            Err(Error::EntryPointParameters(24..25))
+        );
+        assert!(lower_main("#![deny(anything)] fn main() {}").is_ok());
+        assert_eq!(
+            lower_main("#![test] fn main() {}"),
+            Err(Error::MisplacedAttribute(0..8))
+        );
+    }
+
+    #[test]
+    fn lets_infer_their_types() {
+        let src = "fn main() { let x = 3; let y = x + 1; exit!(y); }";
+        assert_eq!(
+            lower_main(src).unwrap(),
+            [
+                Stmt::Let(Pattern::Bind(Local(0)), Expr::Int(3)),
+                Stmt::Let(
+                    Pattern::Bind(Local(1)),
+                    Expr::Binary(
+                        BinOp::Add,
+                        Box::new(Expr::Local(Local(0), Type::Int)),
+                        Box::new(Expr::Int(1))
+                    )
+                ),
+                Stmt::Exit(Expr::Local(Local(1), Type::Int)),
+            ]
+        );
+        let src = "fn main() { let v = None; }";
+        assert_eq!(
+            lower_main(src),
+            Err(Error::AmbiguousType(
+                16..17,
+                Type::Option(Box::new(Type::Unknown))
+            ))
+        );
+        let src = "fn main() { let v: Option<i64> = None; { let v = 1; } exit!(v.unwrap()); }";
+        assert!(lower_main(src).is_ok());
+        let src = "fn main() { { let v = 1; } exit!(v); }";
+        assert_eq!(lower_main(src), Err(Error::UnresolvedName(33..34)));
+        let src = r#"fn main() { let x: i64 = "a"; }"#;
+        assert_eq!(
+            lower_main(src),
+            Err(Error::MismatchedLetType(
+                25..28,
+                Type::Int,
+                Type::Str,
+                19..22
+            ))
+        );
+        let src = "fn main() { let Some(x) = Some(1); }";
+        assert_eq!(
+            lower_main(src),
+            Err(Error::RefutableLetPattern(16..23, "None".into()))
+        );
+    }
+
+    #[test]
+    fn blocks_scope_their_statements() {
+        let src = "fn main() { for x in 0..1 { { exit!(x); } exit!({ for x in 0..1 {} x }); } }";
+        let range = Expr::IntoIter(Box::new(Expr::Range(
+            Box::new(Expr::Int(0)),
+            Box::new(Expr::Int(1)),
+        )));
+        let x = Expr::Local(Local(0), Type::Int);
+        assert_eq!(
+            lower_main(src).unwrap(),
+            [Stmt::For(
+                Pattern::Bind(Local(0)),
+                range.clone(),
+                vec![
+                    Stmt::Block(vec![Stmt::Exit(x.clone())]),
+                    Stmt::Exit(Expr::Block(
+                        vec![Stmt::For(Pattern::Bind(Local(1)), range, Vec::new())],
+                        Box::new(x)
+                    )),
+                ]
+            )]
+        );
+        // Borrows made by a block's statements end with them, while its tail's don't.
+        let src =
+            "fn main() {} fn f(v: Vec<i64>) { exit!({ exit!((&mut v).len()); (&v).len() }); }";
+        assert!(lower_main(src).is_ok());
+        let src = "fn main() {} fn f(v: Vec<i64>) { exit!({ (&mut v).len() } + (&v).len()); }";
+        assert!(matches!(lower_main(src), Err(Error::ConflictingBorrow(..))));
+    }
+
+    #[test]
+    fn if_let_and_while_let_desugar_to_matches() {
+        let src = "fn main() { if let Some(x) = Some(1) { exit!(x); } while let true = false {} }";
+        let x = Expr::Local(Local(0), Type::Int);
+        assert_eq!(
+            lower_main(src).unwrap(),
+            [
+                Stmt::Expr(Expr::Match(
+                    Box::new(Expr::Some(Box::new(Expr::Int(1)))),
+                    vec![
+                        Arm {
+                            pattern: Pattern::Some(Box::new(Pattern::Bind(Local(0)))),
+                            body: Expr::Block(vec![Stmt::Exit(x)], Box::new(Expr::Unit)),
+                        },
+                        Arm {
+                            pattern: Pattern::Wildcard,
+                            body: Expr::Block(Vec::new(), Box::new(Expr::Unit)),
+                        },
+                    ],
+                    Type::Unit
+                )),
+                Stmt::While(Expr::Match(
+                    Box::new(Expr::Bool(false)),
+                    vec![
+                        Arm {
+                            pattern: Pattern::Bool(true),
+                            body: Expr::Block(Vec::new(), Box::new(Expr::Bool(true))),
+                        },
+                        Arm {
+                            pattern: Pattern::Wildcard,
+                            body: Expr::Bool(false),
+                        },
+                    ],
+                    Type::Bool
+                )),
+            ]
+        );
+    }
+
+    #[test]
+    fn if_let_and_while_let_patterns_must_be_refutable() {
+        let src = "fn main() { if let x = 1 { exit!(x); } }";
+        assert_eq!(lower_main(src), Err(Error::IrrefutableIfLetPattern(19..20)));
+        let src = "fn main() { while let _ = read_line!() {} }";
+        assert_eq!(
+            lower_main(src),
+            Err(Error::IrrefutableWhileLetPattern(22..23))
+        );
+        let src = "fn main() {} fn f(o: Option<i64>) -> i64 { if let Some(n) = o { n } }";
+        assert_eq!(lower_main(src), Err(Error::MissingElse(64..65, Type::Int)));
+        let src = "fn main() { let s = \"a\"; while let Some(x) = Some(s) {} }";
+        assert_eq!(
+            lower_main(src),
+            Err(Error::MovedInLoop(50..51, Symbol::intern("s")))
+        );
+    }
+}
@@ -0,0 +1,158 @@
+//! Reporting internal compiler errors: panics in the compiler itself, as opposed to errors in
+//! the program it's compiling. The hook [`install`] sets writes a crash report to a temporary
+//! file and points the user at it, rather than leaving them with a raw panic message.
+
+use std::backtrace::Backtrace;
+use std::cell::Cell;
+use std::env;
+use std::fs;
+use std::panic::{self, PanicHookInfo, UnwindSafe};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Sources longer than this are left out of crash reports.
+const MAX_SOURCE_BYTES: usize = 16 * 1024;
+
+/// The file the compiler read last, which is likely the one it crashed on, and its source, or its
+/// length if it's too long to include.
+static SOURCE: Mutex<Option<(PathBuf, Result<String, usize>)>> = Mutex::new(None);
+
+thread_local! {
+    /// Whether the thread is running code whose panics are caught and turned into errors.
+    static CATCHING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Replaces the panic hook with one that writes a crash report for every panic not caught by
+/// [`catch_unwind`], and tells the user where it is.
+pub fn install() {
+    panic::set_hook(Box::new(|info| {
+        if CATCHING.with(Cell::get) {
+            return;
+        }
+        let message = message(info);
+        let source = SOURCE.lock().map_or(None, |source| source.clone());
+        let report = report(
+            &message,
+            &env::args().collect::<Vec<_>>(),
+            &Backtrace::force_capture().to_string(),
+            source.as_ref().map(|(file, source)| {
+                (
+                    file.as_path(),
+                    source.as_ref().map(String::as_str).map_err(|len| *len),
+                )
+            }),
+        );
+        eprintln!("error: internal compiler error: {}", message);
+        eprintln!("note: this is a bug in sculpt, not in your program");
+        let path = env::temp_dir().join(format!(
+            "sculpt-ice-{}-{}.txt",
+            process::id(),
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |time| time.as_secs())
+        ));
+        match fs::write(&path, &report) {
+            Ok(()) => eprintln!(
+                "note: a crash report was written to {}; please attach it to an issue",
+                path.display()
+            ),
+            Err(_) => eprintln!("note: please attach this report to an issue:\n\n{}", report),
+        }
+    }));
+}
+
+/// Remembers `source_code`, read from `file`, for crash reports. Only its length is kept if it's
+/// too long to include, so reading a large file doesn't copy it.
+pub fn note_source(file: &Path, source_code: &str) {
+    let noted = match source_code.len() {
+        len if len > MAX_SOURCE_BYTES => Err(len),
+        _ => Ok(source_code.to_string()),
+    };
+    if let Ok(mut source) = SOURCE.lock() {
+        *source = Some((file.to_path_buf(), noted));
+    }
+}
+
+/// Like [`panic::catch_unwind`], but panics it catches aren't reported as crashes, as the caller
+/// turns them into errors of its own.
+pub fn catch_unwind<R>(f: impl FnOnce() -> R + UnwindSafe) -> thread::Result<R> {
+    let catching = CATCHING.with(|slot| slot.replace(true));
+    let result = panic::catch_unwind(f);
+    CATCHING.with(|slot| slot.set(catching));
+    result
+}
+
+/// The panic's message and where it was raised.
+fn message(info: &PanicHookInfo) -> String {
+    let payload = info.payload();
+    let message = payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown panic");
+    match info.location() {
+        Some(location) => format!("{} at {}", message, location),
+        None => message.to_string(),
+    }
+}
+
+/// The crash report for a panic with `message`, in a compiler run with `args`.
+fn report(
+    message: &str,
+    args: &[String],
+    backtrace: &str,
+    source: Option<(&Path, Result<&str, usize>)>,
+) -> String {
+    let mut report = format!(
+        "sculpt {} internal compiler error\n\nmessage: {}\nargs: {}\n\nbacktrace:\n{}\n",
+        env!("CARGO_PKG_VERSION"),
+        message,
+        args.join(" "),
+        backtrace.trim_end()
+    );
+    match source {
+        Some((file, Ok(source))) => {
+            report.push_str(&format!("\nsource of {}:\n{}\n", file.display(), source));
+        }
+        Some((file, Err(len))) => report.push_str(&format!(
+            "\nsource of {} left out: {} bytes is too long\n",
+            file.display(),
+            len
+        )),
+        None => {}
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_include_small_sources() {
+        let args = [
+            "sculpt".to_string(),
+            "run".to_string(),
+            "a.sculpt".to_string(),
+        ];
+        let crash = report(
+            "oops at src/hir.rs:1:2",
+            &args,
+            "0: main\n",
+            Some((Path::new("a.sculpt"), Ok("fn main() {}"))),
+        );
+        assert!(crash.starts_with("sculpt 0.1.0 internal compiler error\n"));
+        assert!(crash.contains("\nargs: sculpt run a.sculpt\n"));
+        assert!(crash.ends_with("\nsource of a.sculpt:\nfn main() {}\n"));
+        let crash = report(
+            "oops",
+            &args,
+            "",
+            Some((Path::new("a.sculpt"), Err(MAX_SOURCE_BYTES + 1))),
+        );
+        assert!(crash.ends_with("left out: 16385 bytes is too long\n"));
+    }
+}
@@ -0,0 +1,427 @@
+use inkwell::context::Context;
+use inkwell::values::BasicValue;
+use inkwell::OptimizationLevel;
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::io::{self, BufRead, Write};
+
+use crate::expand::{expand_with, ExpandContext};
+use crate::hir;
+use crate::llvm::{call, link_runtime, Codegen, Streams};
+use crate::profile::{Phase, Profiler};
+use crate::run::{parse, Error};
+
+/// Keeps a lowered module alive across edits to a program, re-lowering only the functions whose
+/// source changed. Used by watch mode to keep iteration on large files fast.
+pub struct Incremental<'ctx> {
+    context: &'ctx Context,
+    codegen: Codegen<'ctx>,
+    opt_level: OptimizationLevel,
+    profiler: &'ctx Profiler,
+    expand_context: ExpandContext<'ctx>,
+    hashes: HashMap<String, u64>,
+    /// Hash of the structs and function signatures the module was built against.
+    items: Option<u64>,
+}
+
+impl<'ctx> Incremental<'ctx> {
+    pub fn new(
+        context: &'ctx Context,
+        opt_level: OptimizationLevel,
+        profiler: &'ctx Profiler,
+        expand_context: ExpandContext<'ctx>,
+    ) -> Self {
+        Self {
+            context,
+            codegen: Codegen::new(context, String::new()),
+            opt_level,
+            profiler,
+            expand_context,
+            hashes: HashMap::new(),
+            items: None,
+        }
+    }
+
+    /// Brings the module up to date with `source_code`, returning the names of the functions that
+    /// had to be lowered again.
+    pub fn update<'src>(&mut self, source_code: &'src str) -> Result<Vec<String>, Error<'src>> {
+        let program = self.profiler.time(Phase::Parse, || parse(source_code))?;
+        // Functions are hashed along with every macro they might expand, so editing a macro
+        // re-lowers them.
+        let macros: Vec<_> = program
+            .macros
+            .iter()
+            .map(|m| &source_code[m.span.clone()])
+            .collect();
+        let program = self
+            .profiler
+            .time(Phase::Lower, || expand_with(program, self.expand_context))?;
+        let items = self
+            .profiler
+            .time(Phase::Lower, || hir::Items::collect(&program))?;
+        // Any function could use a struct or call a function whose signature changed, and those
+        // removed might still be called, so the module is rebuilt from scratch. So is it when a
+        // generic function changes, as its instantiations are built into its callers' module.
+        let generic_functions: Vec<_> = program
+            .all_functions()
+            .filter(|(_, function)| !function.generics.is_empty())
+            .map(|(_, function)| &source_code[function.span.clone()])
+            .collect();
+        let items_hash = hash((&items, generic_functions));
+        if self.items != Some(items_hash) {
+            self.codegen = Codegen::new(self.context, String::new());
+            self.hashes.clear();
+            for s in items.structs() {
+                self.codegen.define_struct(s);
+            }
+            for s in items.statics() {
+                self.codegen.define_static(s);
+            }
+            self.items = Some(items_hash);
+        }
+
+        let mut relowered = Vec::new();
+        for (ty, function) in program.into_all_functions() {
+            let self_ty = ty.map(|ty| ty.name);
+            let name = hir::qualified_name(self_ty, function.name.name).to_string();
+            // The spans panics report are offsets into the whole file, so a function that moved is
+            // lowered again even if its source didn't change.
+            let hash = hash((
+                &macros,
+                function.span.start,
+                &source_code[function.span.clone()],
+            ));
+            if self.hashes.get(&name) == Some(&hash) {
+                continue;
+            }
+
+            self.hashes.remove(&name);
+            let function = self.profiler.time(Phase::Lower, || {
+                hir::lower_function(function, self_ty, &items, &mut hir::Names::default())
+            })?;
+            self.profiler
+                .time(Phase::Codegen, || self.replace(function));
+            self.hashes.insert(name.clone(), hash);
+            relowered.push(name);
+        }
+        self.profiler.time(Phase::Codegen, || {
+            self.codegen.build_instances();
+            self.codegen.log_built(self.profiler);
+        });
+        Ok(relowered)
+    }
+
+    /// JITs a copy of the current module and runs its `main`, if it has one. Returns the I/O error
+    /// that aborted the program, if any.
+    pub fn run(
+        &self,
+        std_in: impl BufRead,
+        std_out: impl Write,
+        std_err: impl Write,
+    ) -> io::Result<()> {
+        let module = self.codegen.module().clone();
+        let execution_engine = self.profiler.time(Phase::Jit, || {
+            module.create_jit_execution_engine(self.opt_level).unwrap()
+        });
+        let mut streams = Streams::new(std_in, std_out, std_err);
+        link_runtime(&module, &self.codegen, &execution_engine, &mut streams);
+        let main = self.profiler.time(Phase::Jit, || unsafe {
+            execution_engine.get_function::<unsafe extern "C" fn()>(&self.codegen.symbol("main"))
+        });
+        match main {
+            Ok(main) => self
+                .profiler
+                .time(Phase::Execute, || unsafe { call(&main) }),
+            Err(_) => Ok(()),
+        }
+    }
+
+    /// Builds `function` in place of the version the module has, if any. Callers of the old
+    /// version are pointed at the new one, as its signature hasn't changed.
+    ///
+    /// The closures it defined, and the instantiations they were passed to, are only called by
+    /// the old version, so they're deleted with it. Their new versions might capture something
+    /// else, which would make them a different type under the same name.
+    fn replace(&self, function: hir::Function) {
+        let module = self.codegen.module();
+        let symbols = [
+            self.codegen.symbol(function.name.as_str()),
+            self.codegen.body_symbol(function.name),
+        ];
+        let stale: Vec<_> = symbols
+            .into_iter()
+            .filter_map(|symbol| {
+                let old = module.get_function(&symbol)?;
+                old.as_global_value().set_name("");
+                Some((symbol, old))
+            })
+            .collect();
+        let prefix = self.codegen.symbol("");
+        let mut orphans: Vec<_> = module
+            .get_functions()
+            .filter(|old| {
+                let symbol = old.get_name().to_string_lossy();
+                symbol
+                    .strip_prefix(&prefix)
+                    .is_some_and(|symbol| mentions_closure_of(symbol, function.name.as_str()))
+            })
+            .collect();
+        for orphan in &orphans {
+            orphan.as_global_value().set_name("");
+            self.codegen.forget_instance(*orphan);
+        }
+        self.codegen.build_function(function);
+        for (symbol, old) in stale {
+            if let Some(new) = module.get_function(&symbol) {
+                old.replace_all_uses_with(new);
+            }
+            unsafe { old.delete() };
+        }
+        // Orphans can call each other, so deleting one can leave another unused.
+        loop {
+            let count = orphans.len();
+            orphans.retain(|orphan| {
+                let used = orphan
+                    .as_global_value()
+                    .as_pointer_value()
+                    .get_first_use()
+                    .is_some();
+                if !used {
+                    unsafe { orphan.delete() };
+                }
+                used
+            });
+            if orphans.len() == count {
+                break;
+            }
+        }
+    }
+}
+
+/// Whether `symbol` names one of the closures of the function `name`, or a function instantiated
+/// with one.
+fn mentions_closure_of(symbol: &str, name: &str) -> bool {
+    let closures = format!("{}::{{closure#", name);
+    symbol
+        .match_indices(&closures)
+        .any(|(i, _)| !symbol[..i].ends_with(|c: char| c.is_alphanumeric() || c == '_' || c == ':'))
+}
+
+fn hash(source_code: impl Hash) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    source_code.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::PanicAt;
+
+    fn run(incremental: &Incremental) -> String {
+        let mut output = Vec::new();
+        incremental
+            .run(std::io::empty(), &mut output, std::io::sink())
+            .unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn only_changed_functions_are_relowered() {
+        let context = Context::create();
+        let profiler = Profiler::default();
+        let mut incremental = Incremental::new(
+            &context,
+            OptimizationLevel::None,
+            &profiler,
+            ExpandContext::default(),
+        );
+
+        let src = r#"fn main() { print!("a"); } fn other() { print!("b"); }"#;
+        assert_eq!(incremental.update(src).unwrap(), ["main", "other"]);
+        assert_eq!(run(&incremental), "a");
+
+        let src = r#"fn main() { print!("c"); } fn other() { print!("b"); }"#;
+        assert_eq!(incremental.update(src).unwrap(), ["main"]);
+        assert_eq!(run(&incremental), "c");
+
+        assert!(incremental.update(src).unwrap().is_empty());
+    }
+
+    #[test]
+    fn removed_functions_are_deleted() {
+        let context = Context::create();
+        let profiler = Profiler::default();
+        let mut incremental = Incremental::new(
+            &context,
+            OptimizationLevel::None,
+            &profiler,
+            ExpandContext::default(),
+        );
+
+        incremental
+            .update(r#"fn main() {} fn other() { print!("b"); }"#)
+            .unwrap();
+        incremental.update("fn main() {}").unwrap();
+        assert!(incremental.codegen.module().get_function("other").is_none());
+    }
+
+    #[test]
+    fn moved_functions_panic_at_their_new_span() {
+        let context = Context::create();
+        let profiler = Profiler::default();
+        let mut incremental = Incremental::new(
+            &context,
+            OptimizationLevel::None,
+            &profiler,
+            ExpandContext::default(),
+        );
+        let panic_span = |incremental: &Incremental| {
+            let error = incremental
+                .run(std::io::empty(), std::io::sink(), std::io::sink())
+                .unwrap_err();
+            let PanicAt(span, _) = error.get_ref().unwrap().downcast_ref().unwrap();
+            span.clone()
+        };
+
+        let other = "fn other() { let x: Option<i64> = None; let y = x.unwrap(); }";
+        let src = format!("fn main() {{ other() }} {}", other);
+        incremental.update(&src).unwrap();
+        let start = src.find("x.unwrap()").unwrap();
+        assert_eq!(panic_span(&incremental), start..start + 10);
+
+        let src = format!(r#"fn main() {{ print!("moved"); other() }} {}"#, other);
+        assert_eq!(incremental.update(&src).unwrap(), ["main", "other"]);
+        let start = src.find("x.unwrap()").unwrap();
+        assert_eq!(panic_span(&incremental), start..start + 10);
+    }
+
+    #[test]
+    fn failed_function_is_relowered_after_fix() {
+        let context = Context::create();
+        let profiler = Profiler::default();
+        let mut incremental = Incremental::new(
+            &context,
+            OptimizationLevel::None,
+            &profiler,
+            ExpandContext::default(),
+        );
+
+        assert!(incremental.update("fn main() { print!(); }").is_err());
+        assert_eq!(
+            incremental
+                .update(r#"fn main() { print!("fixed"); }"#)
+                .unwrap(),
+            ["main"]
+        );
+        assert_eq!(run(&incremental), "fixed");
+    }
+
+    #[test]
+    fn editing_a_macro_relowers_its_users() {
+        let context = Context::create();
+        let profiler = Profiler::default();
+        let mut incremental = Incremental::new(
+            &context,
+            OptimizationLevel::None,
+            &profiler,
+            ExpandContext::default(),
+        );
+
+        let src =
+            r#"macro_rules! say { ($x:expr) => { print!("{}", $x); } } fn main() { say!("a"); }"#;
+        assert_eq!(incremental.update(src).unwrap(), ["main"]);
+        assert_eq!(run(&incremental), "a");
+
+        let src =
+            r#"macro_rules! say { ($x:expr) => { print!("<{}>", $x); } } fn main() { say!("a"); }"#;
+        assert_eq!(incremental.update(src).unwrap(), ["main"]);
+        assert_eq!(run(&incremental), "<a>");
+    }
+
+    #[test]
+    fn callers_use_the_relowered_function() {
+        let context = Context::create();
+        let profiler = Profiler::default();
+        let mut incremental = Incremental::new(
+            &context,
+            OptimizationLevel::None,
+            &profiler,
+            ExpandContext::default(),
+        );
+
+        let src = r#"fn main() { print!("{}", other()); } fn other() -> i64 { 1 }"#;
+        assert_eq!(incremental.update(src).unwrap(), ["main", "other"]);
+        assert_eq!(run(&incremental), "1");
+
+        let src = r#"fn main() { print!("{}", other()); } fn other() -> i64 { 2 }"#;
+        assert_eq!(incremental.update(src).unwrap(), ["other"]);
+        assert_eq!(run(&incremental), "2");
+
+        // Changing a signature relowers everything that might call it.
+        let src = r#"fn main() { print!("{}", other()); } fn other() -> str { "3" }"#;
+        assert_eq!(incremental.update(src).unwrap(), ["main", "other"]);
+        assert_eq!(run(&incremental), "3");
+    }
+
+    #[test]
+    fn generic_functions_are_instantiated_again_when_changed() {
+        let context = Context::create();
+        let profiler = Profiler::default();
+        let mut incremental = Incremental::new(
+            &context,
+            OptimizationLevel::None,
+            &profiler,
+            ExpandContext::default(),
+        );
+
+        let src = r#"fn main() { print!("{}", pick(1, 2)); } fn pick<T>(x: T, y: T) -> T { x }"#;
+        assert_eq!(incremental.update(src).unwrap(), ["main", "pick"]);
+        assert_eq!(run(&incremental), "1");
+
+        // The instantiation is built into the module with `main`, so it's rebuilt too.
+        let src = r#"fn main() { print!("{}", pick(1, 2)); } fn pick<T>(x: T, y: T) -> T { y }"#;
+        assert_eq!(incremental.update(src).unwrap(), ["main", "pick"]);
+        assert_eq!(run(&incremental), "2");
+    }
+
+    #[test]
+    fn closures_are_rebuilt_with_their_function() {
+        let context = Context::create();
+        let profiler = Profiler::default();
+        let mut incremental = Incremental::new(
+            &context,
+            OptimizationLevel::None,
+            &profiler,
+            ExpandContext::default(),
+        );
+
+        let src = |closure: &str| {
+            format!(
+                r#"
+                    fn main() {{ show(7) }}
+                    fn show(n: i64) {{ print!("{{}}", apply({})); }}
+                    fn apply<F: Fn(i64) -> i64>(f: F) -> i64 {{ f(1) }}
+                "#,
+                closure
+            )
+        };
+        assert_eq!(
+            incremental.update(&src("|x| x + n")).unwrap(),
+            ["main", "show", "apply"]
+        );
+        assert_eq!(run(&incremental), "8");
+
+        assert_eq!(incremental.update(&src("|x| x * n")).unwrap(), ["show"]);
+        assert_eq!(run(&incremental), "7");
+
+        // Without its capture, the closure is a different type under the same name.
+        assert_eq!(incremental.update(&src("|x| x - 1")).unwrap(), ["show"]);
+        assert_eq!(run(&incremental), "0");
+
+        assert_eq!(incremental.update(&src("|x| x + n")).unwrap(), ["show"]);
+        assert_eq!(run(&incremental), "8");
+    }
+}
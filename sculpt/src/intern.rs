@@ -0,0 +1,233 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Mutex, OnceLock};
+
+/// Handle to a string in the global interner. Comparing symbols is an integer comparison, and
+/// syntax trees holding symbols don't borrow from the source they were parsed from.
+///
+/// Interned strings live for the rest of the process.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Symbol(u32);
+
+/// Symbols the compiler matches on, interned up front so they can be used in patterns.
+pub mod sym {
+    use super::Symbol;
+
+    pub const PRINT: Symbol = Symbol(0);
+    pub const PRINTLN: Symbol = Symbol(1);
+    pub const MAIN: Symbol = Symbol(2);
+    pub const BENCH: Symbol = Symbol(3);
+    pub const EPRINT: Symbol = Symbol(4);
+    pub const EPRINTLN: Symbol = Symbol(5);
+    pub const READ_LINE: Symbol = Symbol(6);
+    pub const READ_TO_STRING: Symbol = Symbol(7);
+    pub const FORMAT: Symbol = Symbol(8);
+    pub const INCLUDE_STR: Symbol = Symbol(9);
+    pub const ENV: Symbol = Symbol(10);
+    pub const GETENV: Symbol = Symbol(11);
+    pub const RANDOM_INT: Symbol = Symbol(12);
+    pub const NOW_MILLIS: Symbol = Symbol(13);
+    pub const SLEEP_MILLIS: Symbol = Symbol(14);
+    pub const READ_FILE: Symbol = Symbol(15);
+    pub const WRITE_FILE: Symbol = Symbol(16);
+    pub const EXIT: Symbol = Symbol(17);
+    pub const SOME: Symbol = Symbol(18);
+    pub const NONE: Symbol = Symbol(19);
+    pub const UNWRAP: Symbol = Symbol(20);
+    pub const OK: Symbol = Symbol(21);
+    pub const ERR: Symbol = Symbol(22);
+    pub const STR: Symbol = Symbol(23);
+    pub const I64: Symbol = Symbol(24);
+    pub const OPTION: Symbol = Symbol(25);
+    pub const RESULT: Symbol = Symbol(26);
+    pub const SELF_VALUE: Symbol = Symbol(27);
+    pub const SELF_TYPE: Symbol = Symbol(28);
+    pub const BOX: Symbol = Symbol(29);
+    pub const NEW: Symbol = Symbol(30);
+    pub const RC: Symbol = Symbol(31);
+    pub const CLONE: Symbol = Symbol(32);
+    pub const LEN: Symbol = Symbol(33);
+    pub const BOOL: Symbol = Symbol(34);
+    pub const TRUE: Symbol = Symbol(35);
+    pub const FALSE: Symbol = Symbol(36);
+    pub const TO_UPPERCASE: Symbol = Symbol(37);
+    pub const TO_LOWERCASE: Symbol = Symbol(38);
+    pub const TRIM: Symbol = Symbol(39);
+    pub const CONTAINS: Symbol = Symbol(40);
+    pub const VEC_MACRO: Symbol = Symbol(41);
+    pub const VEC: Symbol = Symbol(42);
+    pub const NEXT: Symbol = Symbol(43);
+    pub const CHARS: Symbol = Symbol(44);
+    pub const ITER: Symbol = Symbol(45);
+    pub const INTO_ITER: Symbol = Symbol(46);
+    pub const HASH_MAP: Symbol = Symbol(47);
+    pub const INSERT: Symbol = Symbol(48);
+    pub const GET: Symbol = Symbol(49);
+    pub const REMOVE: Symbol = Symbol(50);
+    pub const SORT: Symbol = Symbol(51);
+    pub const SORT_BY_KEY: Symbol = Symbol(52);
+    pub const TEST: Symbol = Symbol(53);
+    pub const ALLOW: Symbol = Symbol(54);
+    pub const WARN: Symbol = Symbol(55);
+    pub const DENY: Symbol = Symbol(56);
+    pub const DISPLAY: Symbol = Symbol(57);
+    pub const FMT: Symbol = Symbol(58);
+    pub const OPTION_ENV: Symbol = Symbol(59);
+
+    pub(super) const PREDEFINED: [&str; 60] = [
+        "print!",
+        "println!",
+        "main",
+        "bench",
+        "eprint!",
+        "eprintln!",
+        "read_line!",
+        "read_to_string!",
+        "format!",
+        "include_str!",
+        "env!",
+        "getenv!",
+        "random_int!",
+        "now_millis!",
+        "sleep_millis!",
+        "read_file!",
+        "write_file!",
+        "exit!",
+        "Some",
+        "None",
+        "unwrap",
+        "Ok",
+        "Err",
+        "str",
+        "i64",
+        "Option",
+        "Result",
+        "self",
+        "Self",
+        "Box",
+        "new",
+        "Rc",
+        "clone",
+        "len",
+        "bool",
+        "true",
+        "false",
+        "to_uppercase",
+        "to_lowercase",
+        "trim",
+        "contains",
+        "vec!",
+        "Vec",
+        "next",
+        "chars",
+        "iter",
+        "into_iter",
+        "HashMap",
+        "insert",
+        "get",
+        "remove",
+        "sort",
+        "sort_by_key",
+        "test",
+        "allow",
+        "warn",
+        "deny",
+        "Display",
+        "fmt",
+        "option_env!",
+    ];
+}
+
+impl Symbol {
+    pub fn intern(string: &str) -> Self {
+        interner().lock().unwrap().intern(string)
+    }
+
+    pub fn as_str(self) -> &'static str {
+        interner().lock().unwrap().strings[self.0 as usize]
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Serialize for Symbol {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Symbol {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let string = String::deserialize(deserializer)?;
+        Ok(Symbol::intern(&string))
+    }
+}
+
+struct Interner {
+    symbols: HashMap<&'static str, Symbol>,
+    strings: Vec<&'static str>,
+}
+
+impl Interner {
+    fn intern(&mut self, string: &str) -> Symbol {
+        if let Some(symbol) = self.symbols.get(string) {
+            return *symbol;
+        }
+        let symbol = Symbol(self.strings.len().try_into().unwrap());
+        let string: &'static str = Box::leak(string.into());
+        self.symbols.insert(string, symbol);
+        self.strings.push(string);
+        symbol
+    }
+}
+
+fn interner() -> &'static Mutex<Interner> {
+    static INTERNER: OnceLock<Mutex<Interner>> = OnceLock::new();
+    INTERNER.get_or_init(|| {
+        let mut interner = Interner {
+            symbols: HashMap::new(),
+            strings: Vec::new(),
+        };
+        for string in sym::PREDEFINED {
+            interner.intern(string);
+        }
+        Mutex::new(interner)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn equal_strings_intern_to_equal_symbols() {
+        let owned = String::from("abc");
+        assert_eq!(Symbol::intern("abc"), Symbol::intern(&owned));
+        assert_ne!(Symbol::intern("abc"), Symbol::intern("abd"));
+    }
+
+    #[test]
+    fn symbols_resolve_to_their_string() {
+        assert_eq!(Symbol::intern("hello").as_str(), "hello");
+    }
+
+    #[test]
+    fn predefined_symbols_match_their_strings() {
+        for string in sym::PREDEFINED {
+            assert_eq!(Symbol::intern(string).as_str(), string);
+        }
+        assert_eq!(Symbol::intern("println!"), sym::PRINTLN);
+    }
+}
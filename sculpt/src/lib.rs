@@ -0,0 +1,41 @@
+use lalrpop_util::lalrpop_mod;
+
+#[cfg(feature = "llvm")]
+pub mod aot;
+pub mod backend;
+pub mod bench;
+pub mod codes;
+pub mod config;
+pub mod diagnostics;
+pub mod doc;
+pub mod expand;
+pub mod fix;
+pub mod fmt;
+pub mod fmt_str;
+pub mod harness;
+pub mod hir;
+pub mod ice;
+#[cfg(feature = "llvm")]
+pub mod incremental;
+pub mod intern;
+pub mod lint;
+#[cfg(feature = "llvm")]
+pub mod llvm;
+pub mod locale;
+pub mod lsp;
+pub mod plugin;
+pub mod profile;
+#[cfg(feature = "llvm")]
+pub mod repl;
+pub mod report;
+pub mod run;
+pub mod sandbox;
+#[cfg(feature = "llvm")]
+pub mod serve;
+pub mod session;
+pub mod source;
+pub mod syntax;
+pub mod testing;
+pub mod visit;
+
+lalrpop_mod!(#[allow(clippy::all)] pub grammar);
@@ -0,0 +1,337 @@
+//! Lints flag code that compiles but is probably a mistake. Each lint warns by default, and can be
+//! allowed, warned about or denied for a function with `#[allow(lint)]`, `#[warn(lint)]` or
+//! `#[deny(lint)]`, or for a whole file with `#![allow(lint)]` and the like at its top.
+
+use std::ops::Range;
+
+use crate::{
+    codes::DiagnosticCode,
+    hir,
+    intern::{sym, Symbol},
+    run::Error,
+    syntax::{Attr, Block, Expr, Function, IfLet, MacroRules, MatchArm, Pattern, Program, Stmt},
+    visit::{self, Visit},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Lint {
+    /// A variable bound by `let`, `for`, `if let`, `while let` or a `match` arm is never read.
+    UnusedVariables,
+}
+
+impl Lint {
+    pub const ALL: [Lint; 1] = [Lint::UnusedVariables];
+
+    /// How the lint is named in attributes, like `unused_variables`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Lint::UnusedVariables => "unused_variables",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Lint> {
+        Lint::ALL.into_iter().find(|lint| lint.name() == name)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Level {
+    Allow,
+    Warn,
+    Deny,
+}
+
+impl Level {
+    /// The level set by the attribute named `name`, if it sets one.
+    pub fn from_attr(name: Symbol) -> Option<Level> {
+        match name {
+            sym::ALLOW => Some(Level::Allow),
+            sym::WARN => Some(Level::Warn),
+            sym::DENY => Some(Level::Deny),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Warning {
+    /// A variable is never read. Holds its name.
+    UnusedVariable(Range<usize>, Symbol),
+}
+
+impl Warning {
+    pub fn lint(&self) -> Lint {
+        match self {
+            Warning::UnusedVariable(..) => Lint::UnusedVariables,
+        }
+    }
+
+    /// The code the warning is reported with.
+    pub fn code(&self) -> DiagnosticCode {
+        match self {
+            Warning::UnusedVariable(..) => DiagnosticCode::UnusedVariable,
+        }
+    }
+
+    pub fn span(&self) -> Range<usize> {
+        match self {
+            Warning::UnusedVariable(range, _) => range.clone(),
+        }
+    }
+}
+
+/// The levels set by the attributes around the code being checked, innermost last.
+#[derive(Default)]
+struct Levels(Vec<(Lint, Level, Range<usize>)>);
+
+impl Levels {
+    /// Sets the levels `attrs` name until the stack is truncated back to its current length, which
+    /// is returned.
+    fn push<'src>(&mut self, attrs: &[Attr]) -> Result<usize, Error<'src>> {
+        let len = self.0.len();
+        for attr in attrs {
+            let Some(level) = Level::from_attr(attr.name.name) else {
+                continue;
+            };
+            for arg in &attr.args {
+                let lint = Lint::from_name(arg.name.as_str())
+                    .ok_or_else(|| Error::UnknownLint(arg.span.clone()))?;
+                self.0.push((lint, level, attr.span.clone()));
+            }
+        }
+        Ok(len)
+    }
+
+    /// The level `lint` is at, along with the attribute that set it, if any did.
+    fn level(&self, lint: Lint) -> (Level, Option<Range<usize>>) {
+        self.0
+            .iter()
+            .rev()
+            .find(|(l, _, _)| *l == lint)
+            .map_or((Level::Warn, None), |(_, level, attr)| {
+                (*level, Some(attr.clone()))
+            })
+    }
+}
+
+/// Checks `program`, returning the warnings it raises, or the first one it denies as an error.
+pub fn check<'src>(program: &Program) -> Result<Vec<Warning>, Error<'src>> {
+    let mut checker = Checker::default();
+    checker.levels.push(&program.attrs)?;
+    for (_, function) in program.all_functions() {
+        checker.check_function(function)?;
+    }
+    checker.warnings.sort_by_key(|warning| warning.span().start);
+    Ok(checker.warnings)
+}
+
+#[derive(Default)]
+struct Checker {
+    levels: Levels,
+    warnings: Vec<Warning>,
+    /// The variables in scope, innermost last, and whether each has been read. Parameters are
+    /// in scope so they shadow outer variables, but are never warned about.
+    scope: Vec<Variable>,
+}
+
+struct Variable {
+    name: Symbol,
+    span: Range<usize>,
+    used: bool,
+    linted: bool,
+}
+
+impl Checker {
+    fn check_function<'src>(&mut self, function: &Function) -> Result<(), Error<'src>> {
+        let len = self.levels.push(&function.attrs)?;
+        let start = self.warnings.len();
+        for param in &function.params {
+            self.declare(param.name.name, param.name.span.clone(), false);
+        }
+        for statement in &function.statements {
+            self.visit_stmt(statement);
+        }
+        if let Some(tail) = &function.tail {
+            self.visit_expr(tail);
+        }
+        self.end_scope(0);
+        self.warnings[start..].sort_by_key(|warning| warning.span().start);
+        for warning in &self.warnings[start..] {
+            if let (Level::Deny, Some(attr)) = self.levels.level(warning.lint()) {
+                return Err(Error::DeniedLint(warning.clone(), attr));
+            }
+        }
+        self.levels.0.truncate(len);
+        Ok(())
+    }
+
+    fn declare(&mut self, name: Symbol, span: Range<usize>, linted: bool) {
+        self.scope.push(Variable {
+            name,
+            span,
+            used: false,
+            linted,
+        });
+    }
+
+    /// Marks the innermost variable named `name`, if any, as read.
+    fn read(&mut self, name: Symbol) {
+        if let Some(variable) = self.scope.iter_mut().rev().find(|v| v.name == name) {
+            variable.used = true;
+        }
+    }
+
+    /// Declares each variable `pattern` binds.
+    fn bind(&mut self, pattern: &Pattern) {
+        match pattern {
+            Pattern::Wildcard(_) | Pattern::Str(_) => {}
+            Pattern::Name(name)
+                if hir::arity(name.name).is_some()
+                    || matches!(name.name, sym::TRUE | sym::FALSE) => {}
+            Pattern::Name(name) => self.declare(name.name, name.span.clone(), true),
+            Pattern::TupleStruct(pattern) => pattern.fields.iter().for_each(|f| self.bind(f)),
+        }
+    }
+
+    /// Ends the scopes of the variables declared since the scope had `len` of them, warning
+    /// about those that were never read, unless they're allowed or named like `_name`.
+    fn end_scope(&mut self, len: usize) {
+        for variable in self.scope.drain(len..) {
+            if variable.linted && !variable.used && !variable.name.as_str().starts_with('_') {
+                let warning = Warning::UnusedVariable(variable.span, variable.name);
+                if self.levels.level(warning.lint()).0 != Level::Allow {
+                    self.warnings.push(warning);
+                }
+            }
+        }
+    }
+}
+
+impl<'ast> Visit<'ast> for Checker {
+    fn visit_macro_rules(&mut self, _m: &'ast MacroRules) {}
+
+    fn visit_stmt(&mut self, stmt: &'ast Stmt) {
+        match stmt {
+            Stmt::For(f) => {
+                self.visit_expr(&f.iter);
+                let len = self.scope.len();
+                self.bind(&f.pattern);
+                for statement in &f.body {
+                    self.visit_stmt(statement);
+                }
+                self.end_scope(len);
+            }
+            Stmt::WhileLet(w) => {
+                self.visit_expr(&w.value);
+                let len = self.scope.len();
+                self.bind(&w.pattern);
+                for statement in &w.body {
+                    self.visit_stmt(statement);
+                }
+                self.end_scope(len);
+            }
+            Stmt::Let(l) => {
+                self.visit_expr(&l.value);
+                self.bind(&l.pattern);
+            }
+            _ => visit::walk_stmt(self, stmt),
+        }
+    }
+
+    fn visit_block(&mut self, block: &'ast Block) {
+        let len = self.scope.len();
+        visit::walk_block(self, block);
+        self.end_scope(len);
+    }
+
+    fn visit_match_arm(&mut self, arm: &'ast MatchArm) {
+        let len = self.scope.len();
+        self.bind(&arm.pattern);
+        self.visit_expr(&arm.body);
+        self.end_scope(len);
+    }
+
+    fn visit_if_let(&mut self, i: &'ast IfLet) {
+        self.visit_expr(&i.value);
+        let len = self.scope.len();
+        self.bind(&i.pattern);
+        self.visit_block(&i.then);
+        self.end_scope(len);
+        if let Some(otherwise) = &i.otherwise {
+            self.visit_block(otherwise);
+        }
+    }
+
+    fn visit_expr(&mut self, expr: &'ast Expr) {
+        match expr {
+            Expr::Name(name) => self.read(name.name),
+            // Calls can be to closures held in variables.
+            Expr::Call(call) if call.ty.is_none() => {
+                self.read(call.name.name);
+                visit::walk_expr(self, expr);
+            }
+            Expr::Closure(closure) => {
+                let len = self.scope.len();
+                for param in &closure.params {
+                    self.declare(param.name.name, param.name.span.clone(), false);
+                }
+                self.visit_expr(&closure.body);
+                self.end_scope(len);
+            }
+            _ => visit::walk_expr(self, expr),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::run::parse;
+
+    fn unused(src: &str) -> Result<Vec<usize>, Error<'_>> {
+        let warnings = check(&parse(src).unwrap())?;
+        Ok(warnings
+            .iter()
+            .map(|warning| warning.span().start)
+            .collect())
+    }
+
+    #[test]
+    fn unused_variables_are_warned_about_unless_allowed() {
+        let src = "fn f(x: i64) -> i64 { let y = x; for i in 0..x { let _z = 1; } y }
+                   fn g() -> i64 { match Some(1) { Some(a) => 1, None => 2 } }
+                   #[allow(unused_variables)] fn h() { let b = 1; }";
+        assert_eq!(unused(src), Ok(vec![37, 123]));
+        assert_eq!(
+            unused("#![allow(unused_variables)] fn f() { let a = 1; }"),
+            Ok(vec![])
+        );
+        assert_eq!(
+            unused("#![allow(unused_variables)] #[warn(unused_variables)] fn f() { let a = 1; }"),
+            Ok(vec![67])
+        );
+        assert_eq!(
+            unused("fn f() -> i64 { let a = 1; let g = |a| a; g(2) }"),
+            Ok(vec![20])
+        );
+        assert_eq!(
+            unused("fn f(o: Option<i64>) { if let Some(a) = o {} while let Some(b) = o {} }"),
+            Ok(vec![35, 60])
+        );
+    }
+
+    #[test]
+    fn denied_lints_are_errors() {
+        assert_eq!(
+            unused("#[deny(unused_variables)] fn f() { let a = 1; }"),
+            Err(Error::DeniedLint(
+                Warning::UnusedVariable(39..40, Symbol::intern("a")),
+                0..25
+            ))
+        );
+        assert_eq!(
+            unused("#[deny(unused_vars)] fn f() {}"),
+            Err(Error::UnknownLint(7..18))
+        );
+    }
+}
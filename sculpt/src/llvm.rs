@@ -0,0 +1,5309 @@
+use inkwell::basic_block::BasicBlock;
+use inkwell::builder::Builder;
+use inkwell::context::Context;
+use inkwell::execution_engine::{ExecutionEngine, JitFunction};
+use inkwell::module::{Linkage, Module};
+use inkwell::passes::{PassManager, PassManagerBuilder};
+use inkwell::targets::{
+    CodeModel, FileType, InitializationConfig, RelocMode, Target, TargetMachine,
+};
+use inkwell::types::{BasicMetadataTypeEnum, BasicType, BasicTypeEnum};
+use inkwell::types::{FunctionType, StructType};
+use inkwell::values::{
+    AnyValue, BasicMetadataValueEnum, BasicValue, BasicValueEnum, FunctionValue, GlobalValue,
+    IntValue, PointerValue,
+};
+use inkwell::{AddressSpace, IntPredicate, OptimizationLevel};
+use llvm_sys::support::LLVMAddSymbol;
+
+use std::cell::{Cell, RefCell};
+use std::collections::hash_map::RandomState;
+use std::collections::{BTreeSet, HashMap};
+use std::ffi::{c_void, CString};
+use std::fs;
+use std::hash::{BuildHasher, Hasher};
+use std::io::{self, BufRead, Write};
+use std::iter;
+use std::mem;
+use std::ops::Range;
+use std::panic::AssertUnwindSafe;
+use std::path::{Path, PathBuf};
+use std::sync::Once;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::backend::{Backend, Clock, Exit, Files, Panic, PanicAt, RunOptions};
+use crate::fmt_str::Format;
+use crate::hir::{self, Arm, Callee, Const, Expr, Local, Pattern, Stmt, Stream, Type};
+use crate::ice;
+use crate::intern::Symbol;
+use crate::profile::{Phase, Profiler};
+use crate::run::{parse, Error};
+use crate::syntax::{BinOp, Program};
+
+/// Host streams a running program reads and writes, and the rest of the host state its builtins
+/// use. The module's stream and host globals point into this, so it must outlive every call into
+/// the program.
+pub struct Streams<'a> {
+    std_in: Input<'a>,
+    std_out: Box<dyn Write + 'a>,
+    std_err: Box<dyn Write + 'a>,
+    host: Host,
+}
+
+impl<'a> Streams<'a> {
+    pub fn new(
+        std_in: impl BufRead + 'a,
+        std_out: impl Write + 'a,
+        std_err: impl Write + 'a,
+    ) -> Self {
+        Self {
+            std_in: Input {
+                reader: Box::new(std_in),
+                buffer: Vec::new(),
+            },
+            std_out: Box::new(std_out),
+            std_err: Box::new(std_err),
+            host: Host {
+                options: RunOptions::default(),
+                buffer: Vec::new(),
+                rng: RandomState::new().build_hasher().finish(),
+                virtual_millis: 0,
+                virtual_files: HashMap::new(),
+            },
+        }
+    }
+
+    pub fn with_options(mut self, options: RunOptions) -> Self {
+        if let Some(seed) = options.seed {
+            self.host.rng = seed;
+        }
+        self.host.options = options;
+        self
+    }
+}
+
+/// Stdin along with the buffer the last read was made into, which the program reads from until
+/// its next read.
+struct Input<'a> {
+    reader: Box<dyn BufRead + 'a>,
+    buffer: Vec<u8>,
+}
+
+/// What `sculpt_rt_host` points to: how the program may reach the host, the buffer the last
+/// lookup or read was made into, the random number generator's state, and the time and files of a
+/// virtual clock and file system.
+struct Host {
+    options: RunOptions,
+    buffer: Vec<u8>,
+    rng: u64,
+    virtual_millis: i64,
+    virtual_files: HashMap<Vec<u8>, Vec<u8>>,
+}
+
+impl Host {
+    /// SplitMix64, as sculpt-rt implements it, so both draw the same integers from a seed.
+    fn next_random(&mut self) -> u64 {
+        self.rng = self.rng.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.rng;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+}
+
+/// Where the sculpt runtime a [`Codegen`]'s module calls into comes from. Either way the module
+/// calls the functions declared in `runtime/sculpt_rt.h`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Abi {
+    /// [`link_runtime`] maps the runtime to host functions and streams in a JIT execution engine.
+    /// Every program gets its own streams, so their globals are prefixed like its functions.
+    #[default]
+    Host,
+    /// The module is linked against sculpt-rt, the static library built from
+    /// `runtime/sculpt_rt.c`, to make a standalone executable.
+    Native,
+}
+
+/// Lowers programs into an LLVM module, independent of how the module ends up being run.
+pub struct Codegen<'ctx> {
+    context: &'ctx Context,
+    module: Module<'ctx>,
+    builder: Builder<'ctx>,
+    prefix: String,
+    abi: Abi,
+    /// Strings and boxes allocated while building the current statement, freed once it's done
+    /// with them.
+    temporaries: RefCell<Vec<Allocation<'ctx>>>,
+    /// Strings and boxes the values `let`s bound own, innermost scope last, freed as their scopes
+    /// end.
+    owned: RefCell<Vec<Allocation<'ctx>>>,
+    /// Values the parameters and patterns of the current function bound.
+    locals: RefCell<HashMap<Local, Value<'ctx>>>,
+    /// What the current function returns.
+    ret: RefCell<Type>,
+    /// The field types of the tuple structs the program defines.
+    structs: RefCell<HashMap<Symbol, Vec<Type>>>,
+    /// Generic functions by name, which are built once per instantiation.
+    generic_functions: RefCell<HashMap<Symbol, hir::Function>>,
+    /// The bodies of the instantiations of generic functions declared so far, by the function and
+    /// its type arguments, so each is built once however many calls it has.
+    instances: RefCell<HashMap<(Symbol, Vec<Type>), FunctionValue<'ctx>>>,
+    /// Instantiations that have been declared but not built yet.
+    pending_instances: RefCell<Vec<(Symbol, Vec<Type>)>>,
+    /// Functions built since [`Codegen::log_built`] last logged them.
+    built: RefCell<Vec<FunctionValue<'ctx>>>,
+    /// The globals holding the bytes of string constants, by their contents, named `str.0`,
+    /// `str.1` and so on in the order they're first used.
+    strings: RefCell<HashMap<String, GlobalValue<'ctx>>>,
+}
+
+impl<'ctx> Codegen<'ctx> {
+    /// Symbols are prefixed with `prefix` so they don't collide with other programs' symbols when
+    /// sharing an execution engine.
+    pub fn new(context: &'ctx Context, prefix: String) -> Self {
+        Self::with_abi(context, prefix, Abi::Host)
+    }
+
+    pub fn with_abi(context: &'ctx Context, prefix: String, abi: Abi) -> Self {
+        Self {
+            context,
+            module: context.create_module(&format!("{}main", prefix)),
+            builder: context.create_builder(),
+            prefix,
+            abi,
+            temporaries: RefCell::default(),
+            owned: RefCell::default(),
+            locals: RefCell::default(),
+            ret: RefCell::new(Type::Unit),
+            structs: RefCell::default(),
+            generic_functions: RefCell::default(),
+            instances: RefCell::default(),
+            pending_instances: RefCell::default(),
+            built: RefCell::default(),
+            strings: RefCell::default(),
+        }
+    }
+
+    pub fn module(&self) -> &Module<'ctx> {
+        &self.module
+    }
+
+    pub fn symbol(&self, name: &str) -> String {
+        format!("{}{}", self.prefix, name)
+    }
+
+    /// Name of the body of the function `name`, which [`Codegen::build_function`] builds.
+    pub fn body_symbol(&self, name: Symbol) -> String {
+        self.symbol(&format!("{}.body", name))
+    }
+
+    /// Name of the global for one of the runtime's streams, or its host state.
+    pub fn stream_symbol(&self, name: &str) -> String {
+        match self.abi {
+            Abi::Host => self.symbol(name),
+            Abi::Native => name.to_string(),
+        }
+    }
+
+    /// Deletes every function `entry` can't reach, so the execution engine, which compiles the
+    /// whole module to machine code before running any of it, only compiles what can be called.
+    /// Nothing but `entry` can be looked up afterwards.
+    pub fn keep_reachable_from(&self, entry: &str) {
+        let entry = self.symbol(entry);
+        for function in self.module.get_functions() {
+            if function.count_basic_blocks() > 0
+                && function.get_name().to_bytes() != entry.as_bytes()
+            {
+                function.set_linkage(Linkage::Internal);
+            }
+        }
+        self.built.borrow_mut().clear();
+        let passes = PassManager::create(());
+        passes.add_global_dce_pass();
+        passes.run_on(&self.module);
+    }
+
+    /// Logs the IR of each function built since this was last called to `profiler`, if its codegen
+    /// is verbose, noting the functions that fail to verify.
+    pub fn log_built(&self, profiler: &Profiler) {
+        for function in self.built.take() {
+            let name = function.get_name().to_string_lossy();
+            profiler.codegen(&name, || {
+                let ir = function.print_to_string().to_string();
+                match function.verify(false) {
+                    true => ir,
+                    false => format!("; fails to verify\n{}", ir),
+                }
+            });
+        }
+    }
+
+    /// Textual LLVM IR of everything built so far.
+    pub fn ir(&self) -> String {
+        self.module.print_to_string().to_string()
+    }
+
+    /// Registers a tuple struct, so values of it can be laid out. Every struct a function uses
+    /// must be defined before it's built.
+    pub fn define_struct(&self, s: hir::Struct) {
+        self.structs.borrow_mut().insert(s.name, s.fields);
+    }
+
+    /// Emits a static as a constant global, which functions using it read. A string's global is
+    /// its bytes.
+    pub fn define_static(&self, s: hir::Static) {
+        let i64_type = self.context.i64_type();
+        let value: BasicValueEnum = match s.value {
+            Const::Int(value) => i64_type.const_int(value as u64, true).into(),
+            Const::Bool(value) => i64_type.const_int(value.into(), false).into(),
+            Const::Str(value) => self
+                .context
+                .const_string(value.as_str().as_bytes(), false)
+                .into(),
+        };
+        let global = self
+            .module
+            .add_global(value.get_type(), None, &self.static_symbol(s.name));
+        global.set_initializer(&value);
+        global.set_constant(true);
+        global.set_linkage(Linkage::Private);
+    }
+
+    /// A pointer to the bytes of `val`, in the constant global every use of them shares.
+    fn const_str(&self, val: &str) -> PointerValue<'ctx> {
+        let mut strings = self.strings.borrow_mut();
+        let count = strings.len();
+        let global = *strings.entry(val.to_string()).or_insert_with(|| {
+            let value = self.context.const_string(val.as_bytes(), false);
+            let global = self.module.add_global(
+                value.get_type(),
+                None,
+                &self.symbol(&format!("str.{}", count)),
+            );
+            global.set_initializer(&value);
+            global.set_constant(true);
+            global.set_linkage(Linkage::Private);
+            global.set_unnamed_addr(true);
+            global
+        });
+        let i8_ptr_type = self.context.i8_type().ptr_type(AddressSpace::default());
+        global.as_pointer_value().const_cast(i8_ptr_type)
+    }
+
+    fn static_symbol(&self, name: Symbol) -> String {
+        self.symbol(&format!("static.{}", name))
+    }
+
+    /// Builds `function` as its body, which takes its parameters and returns whether it ran to
+    /// completion along with what it returned. A function the host can call also gets a wrapper
+    /// under its own name, which runs the body and discards what it returns.
+    ///
+    /// A generic function is only kept, to be built for the type arguments it's called with by
+    /// [`Codegen::build_instances`]. So are the closures it defines, which are generic over the
+    /// same type parameters.
+    pub fn build_function(&self, mut function: hir::Function) {
+        for closure in mem::take(&mut function.closures) {
+            if closure.generics.is_empty() {
+                self.build_body(closure);
+            } else {
+                self.generic_functions
+                    .borrow_mut()
+                    .insert(closure.name, closure);
+            }
+        }
+        if !function.generics.is_empty() {
+            self.generic_functions
+                .borrow_mut()
+                .insert(function.name, function);
+            return;
+        }
+        if function.is_entry_point() {
+            let (name, ret) = (function.name, function.ret.clone());
+            let body = self.build_body(function);
+            self.build_entry_wrapper(name, &ret, body);
+        } else {
+            self.build_body(function);
+        }
+    }
+
+    /// Builds the body of `function`, which isn't generic.
+    fn build_body(&self, function: hir::Function) -> FunctionValue<'ctx> {
+        let params: Vec<_> = function.params.iter().map(|(_, ty)| ty.clone()).collect();
+        let fn_value = self.declare_body(function.name, &params, &function.ret);
+        let fn_body = self.context.append_basic_block(fn_value, "");
+        let abort = self.context.append_basic_block(fn_value, "abort");
+        self.builder.position_at_end(abort);
+        let aborted = fn_value.get_type().get_return_type().unwrap().const_zero();
+        self.builder.build_return(Some(&aborted));
+        let runtime = self.runtime(fn_value, abort);
+
+        self.builder.position_at_end(fn_body);
+        self.build_tick(runtime);
+        self.locals.borrow_mut().clear();
+        for ((local, ty), param) in function.params.iter().zip(fn_value.get_param_iter()) {
+            let value = self.build_unpack(param, ty);
+            self.locals.borrow_mut().insert(*local, value);
+        }
+        self.ret.replace(function.ret.clone());
+        for stmt in function.body {
+            self.build_stmt(stmt, runtime);
+        }
+        let value = match function.tail {
+            Some(tail) => self.build_expr(tail, runtime),
+            None => Value::Int(self.context.i64_type().const_zero()),
+        };
+        self.build_return(&value);
+        self.temporaries.borrow_mut().clear();
+        self.owned.borrow_mut().clear();
+        self.built.borrow_mut().push(fn_value);
+        fn_value
+    }
+
+    /// Builds the instantiations of generic functions called since they were last built,
+    /// including those the instantiations themselves call.
+    pub fn build_instances(&self) {
+        loop {
+            let Some((name, args)) = self.pending_instances.borrow_mut().pop() else {
+                break;
+            };
+            let instance = self.generic_functions.borrow()[&name].instantiate(&args);
+            self.build_body(instance);
+        }
+    }
+
+    /// Declares the body of the function `name`, unless a call to it already has.
+    fn declare_body(&self, name: Symbol, params: &[Type], ret: &Type) -> FunctionValue<'ctx> {
+        let symbol = self.body_symbol(name);
+        self.module.get_function(&symbol).unwrap_or_else(|| {
+            let params: Vec<_> = params.iter().map(|ty| self.llvm_type(ty).into()).collect();
+            let returned = self.context.struct_type(
+                &[self.context.bool_type().into(), self.llvm_type(ret)],
+                false,
+            );
+            self.module
+                .add_function(&symbol, returned.fn_type(&params, false), None)
+        })
+    }
+
+    /// Declares the body of the generic function `name` instantiated with `type_args`, queueing it
+    /// to be built by [`Codegen::build_instances`] unless it already has been.
+    fn declare_instance(
+        &self,
+        name: Symbol,
+        type_args: Vec<Type>,
+        params: &[Type],
+        ret: &Type,
+    ) -> FunctionValue<'ctx> {
+        let key = (name, type_args);
+        if let Some(function) = self.instances.borrow().get(&key) {
+            return *function;
+        }
+        let function = self.declare_body(hir::instance_name(name, &key.1), params, ret);
+        self.pending_instances.borrow_mut().push(key.clone());
+        self.instances.borrow_mut().insert(key, function);
+        function
+    }
+
+    /// Forgets that `function` is an instantiation, so calls to it build a new one instead.
+    pub fn forget_instance(&self, function: FunctionValue<'ctx>) {
+        self.instances
+            .borrow_mut()
+            .retain(|_, instance| *instance != function);
+    }
+
+    /// Defines the function the host calls to run the function `name`, whose body is `body`.
+    /// What it returns is freed, except for the error of a `Result`, which is reported like one
+    /// returned from Rust's `main`.
+    fn build_entry_wrapper(&self, name: Symbol, ret: &Type, body: FunctionValue<'ctx>) {
+        let symbol = self.symbol(name.as_str());
+        let function = self.module.get_function(&symbol).unwrap_or_else(|| {
+            self.module
+                .add_function(&symbol, self.context.void_type().fn_type(&[], false), None)
+        });
+        let entry = self.context.append_basic_block(function, "");
+        let abort = self.context.append_basic_block(function, "abort");
+        self.builder.position_at_end(abort);
+        self.builder.build_return(None);
+        let runtime = self.runtime(function, abort);
+
+        self.builder.position_at_end(entry);
+        let value = self.build_body_call(body, &[], ret, runtime);
+        match (ret, value) {
+            (Type::Result(_, error_ty), Value::Result(is_ok, _, error))
+                if matches!(**error_ty, Type::Str | Type::Int) =>
+            {
+                let failed = self.context.append_basic_block(function, "error");
+                let next = self.context.append_basic_block(function, "");
+                self.builder.build_conditional_branch(is_ok, next, failed);
+                self.builder.position_at_end(failed);
+                self.build_error_exit(*error, runtime);
+                self.builder.position_at_end(next);
+            }
+            _ => {}
+        }
+        for temporary in self.temporaries.take() {
+            self.build_free(&temporary);
+        }
+        self.builder.build_return(None);
+        self.built.borrow_mut().push(function);
+    }
+
+    /// The runtime `function` calls into, aborting to `abort`.
+    fn runtime(&self, function: FunctionValue<'ctx>, abort: BasicBlock<'ctx>) -> Runtime<'ctx> {
+        Runtime {
+            function,
+            abort,
+            write: declare_write(&self.module),
+            read: declare_read(&self.module),
+            format_i64: declare_format_i64(&self.module),
+            format_radix: declare_format_radix(&self.module),
+            panic: declare_panic(&self.module),
+            panic_at: declare_panic_at(&self.module),
+            getenv: declare_getenv(&self.module),
+            random_int: declare_random_int(&self.module),
+            now_millis: declare_now_millis(&self.module),
+            sleep_millis: declare_sleep_millis(&self.module),
+            check_slice: declare_check_slice(&self.module),
+            read_file: declare_read_file(&self.module),
+            to_uppercase: declare_host_str(&self.module, TO_UPPERCASE),
+            to_lowercase: declare_host_str(&self.module, TO_LOWERCASE),
+            trim: declare_trim(&self.module),
+            contains: declare_contains(&self.module),
+            write_file: declare_write_file(&self.module),
+            exit: declare_exit(&self.module),
+            refuel: declare_refuel(&self.module),
+            fuel: declare_fuel(&self.module, &self.stream_symbol(FUEL)),
+            host: declare_stream(&self.module, &self.stream_symbol(HOST)),
+            std_in: declare_stream(&self.module, &self.stream_symbol(STDIN)),
+            std_out: declare_stream(&self.module, &self.stream_symbol(STDOUT)),
+            std_err: declare_stream(&self.module, &self.stream_symbol(STDERR)),
+        }
+    }
+
+    /// Calls the body of a function returning `ty`, aborting the current function if it aborted.
+    /// The strings and boxes it returns are the caller's, so they're freed along with the
+    /// statement's temporaries.
+    fn build_body_call(
+        &self,
+        function: FunctionValue<'ctx>,
+        args: &[BasicMetadataValueEnum<'ctx>],
+        ty: &Type,
+        runtime: Runtime<'ctx>,
+    ) -> Value<'ctx> {
+        let returned = self
+            .builder
+            .build_call(function, args, "")
+            .try_as_basic_value()
+            .left()
+            .unwrap()
+            .into_struct_value();
+        let completed = self
+            .builder
+            .build_extract_value(returned, 0, "")
+            .unwrap()
+            .into_int_value();
+        let next = self.context.append_basic_block(runtime.function, "");
+        self.builder
+            .build_conditional_branch(completed, next, runtime.abort);
+        self.builder.position_at_end(next);
+        let value = self.build_unpack(
+            self.builder.build_extract_value(returned, 1, "").unwrap(),
+            ty,
+        );
+        self.temporaries.borrow_mut().extend(value.allocations());
+        value
+    }
+
+    /// Returns `value` from the current function's body. Its strings and boxes are copied first,
+    /// as the statement's temporaries, which they may be among, are freed.
+    fn build_return(&self, value: &Value<'ctx>) {
+        let value = self.build_detached(value);
+        for temporary in self
+            .temporaries
+            .borrow()
+            .iter()
+            .chain(self.owned.borrow().iter())
+        {
+            self.build_free(temporary);
+        }
+        let completed = self.context.bool_type().const_all_ones();
+        let returned = self.build_struct(&[completed.into(), self.build_pack(&value)]);
+        self.builder.build_return(Some(&returned));
+    }
+
+    /// Copies the strings and boxes `value` holds into allocations whoever it's returned to frees.
+    fn build_detached(&self, value: &Value<'ctx>) -> Value<'ctx> {
+        match value {
+            Value::Str(buffer, len) => {
+                let copy = self.build_alloc(*len);
+                self.builder
+                    .build_memcpy(copy, 1, *buffer, 1, *len)
+                    .unwrap();
+                Value::Str(copy, *len)
+            }
+            Value::Int(_) => value.clone(),
+            Value::Option(is_some, value) => {
+                Value::Option(*is_some, Box::new(self.build_detached(value)))
+            }
+            Value::Result(is_ok, ok, error) => Value::Result(
+                *is_ok,
+                Box::new(self.build_detached(ok)),
+                Box::new(self.build_detached(error)),
+            ),
+            Value::Struct(fields) => Value::Struct(
+                fields
+                    .iter()
+                    .map(|field| self.build_detached(field))
+                    .collect(),
+            ),
+            Value::Heap(pointer, ty) => {
+                let glue = match ty {
+                    Type::Rc(_) => self.retain_glue(),
+                    _ => self.clone_glue(ty),
+                };
+                let copy = self
+                    .builder
+                    .build_call(glue, &[(*pointer).into()], "")
+                    .try_as_basic_value()
+                    .left()
+                    .unwrap()
+                    .into_pointer_value();
+                Value::Heap(copy, ty.clone())
+            }
+        }
+    }
+
+    /// How what a box or an `Rc` of type `ty` points to is laid out. An `Rc`'s value follows the
+    /// count of its clones.
+    fn heap_type(&self, ty: &Type) -> BasicTypeEnum<'ctx> {
+        match ty {
+            Type::Box(ty) => self.llvm_type(ty),
+            Type::Rc(ty) => self
+                .context
+                .struct_type(&[self.context.i64_type().into(), self.llvm_type(ty)], false)
+                .into(),
+            _ => unreachable!("only boxes and `Rc`s point to the heap"),
+        }
+    }
+
+    /// A pointer to the value the box or `Rc` at `pointer`, of type `ty`, holds.
+    fn build_contents(&self, pointer: PointerValue<'ctx>, ty: &Type) -> PointerValue<'ctx> {
+        let heap_type = self.heap_type(ty).ptr_type(AddressSpace::default());
+        let contents = self.builder.build_pointer_cast(pointer, heap_type, "");
+        match ty {
+            Type::Rc(_) => self.builder.build_struct_gep(contents, 1, "").unwrap(),
+            _ => contents,
+        }
+    }
+
+    /// A pointer to the count of clones of the `Rc` at `pointer`.
+    fn build_count(&self, pointer: PointerValue<'ctx>) -> PointerValue<'ctx> {
+        let i64_ptr_type = self.context.i64_type().ptr_type(AddressSpace::default());
+        self.builder.build_pointer_cast(pointer, i64_ptr_type, "")
+    }
+
+    /// Moves `value` into a new box or `Rc` of type `ty`, which owns a copy of its strings, boxes
+    /// and clones of `Rc`s.
+    fn build_heap(&self, value: &Value<'ctx>, ty: &Type) -> PointerValue<'ctx> {
+        let pointer = self.build_alloc(self.heap_type(ty).size_of().unwrap());
+        if let Type::Rc(_) = ty {
+            let one = self.context.i64_type().const_int(1, false);
+            self.builder.build_store(self.build_count(pointer), one);
+        }
+        let value = self.build_pack(&self.build_detached(value));
+        self.builder
+            .build_store(self.build_contents(pointer, ty), value);
+        pointer
+    }
+
+    /// What the box or `Rc` at `pointer`, of type `ty`, holds. Its strings and boxes are the
+    /// box's or `Rc`'s.
+    fn build_load_heap(&self, pointer: PointerValue<'ctx>, ty: &Type) -> Value<'ctx> {
+        let (Type::Box(inner) | Type::Rc(inner)) = ty else {
+            unreachable!("only boxes and `Rc`s point to the heap")
+        };
+        let contents = self.build_contents(pointer, ty);
+        self.build_unpack(self.builder.build_load(contents, ""), inner)
+    }
+
+    /// Frees a temporary. A box is freed along with what it holds, and an `Rc` along with what it
+    /// holds if it was the last clone.
+    fn build_free(&self, allocation: &Allocation<'ctx>) {
+        match allocation {
+            Allocation::Buffer(buffer) => {
+                let free = declare_free(&self.module);
+                self.builder.build_call(free, &[(*buffer).into()], "");
+            }
+            Allocation::Heap(pointer, ty) => {
+                self.builder
+                    .build_call(self.drop_glue(ty), &[(*pointer).into()], "");
+            }
+        }
+    }
+
+    /// The function dropping a box or an `Rc` of type `ty`. Built the first time it's needed.
+    fn drop_glue(&self, ty: &Type) -> FunctionValue<'ctx> {
+        let i8_ptr_type = self.context.i8_type().ptr_type(AddressSpace::default());
+        let fn_type = self
+            .context
+            .void_type()
+            .fn_type(&[i8_ptr_type.into()], false);
+        self.build_glue(&format!("drop.{}", ty), fn_type, |pointer| {
+            let done = self.context.append_basic_block(
+                self.builder
+                    .get_insert_block()
+                    .unwrap()
+                    .get_parent()
+                    .unwrap(),
+                "",
+            );
+            if let Type::Rc(_) = ty {
+                // What an `Rc` holds is only freed with its last clone.
+                let count = self.build_count(pointer);
+                let clones = self.builder.build_load(count, "").into_int_value();
+                let one = self.context.i64_type().const_int(1, false);
+                let clones = self.builder.build_int_sub(clones, one, "");
+                self.builder.build_store(count, clones);
+                let last = self.builder.build_int_compare(
+                    IntPredicate::EQ,
+                    clones,
+                    self.context.i64_type().const_zero(),
+                    "",
+                );
+                let free = self.context.append_basic_block(
+                    self.builder
+                        .get_insert_block()
+                        .unwrap()
+                        .get_parent()
+                        .unwrap(),
+                    "",
+                );
+                self.builder.build_conditional_branch(last, free, done);
+                self.builder.position_at_end(free);
+            }
+            match ty {
+                Type::Vec(element) => {
+                    let len = self.build_vec_len(pointer);
+                    self.build_counted_loop(len, |i| {
+                        for allocation in self.build_load_element(pointer, element, i).allocations()
+                        {
+                            self.build_free(&allocation);
+                        }
+                    });
+                }
+                Type::HashMap(..) => {
+                    let len = self.build_vec_len(pointer);
+                    self.build_counted_loop(len, |i| self.build_free_entry(pointer, ty, i));
+                }
+                _ => {
+                    for allocation in self.build_load_heap(pointer, ty).allocations() {
+                        self.build_free(&allocation);
+                    }
+                }
+            }
+            match ty {
+                Type::HashMap(..) => {
+                    self.build_map_call(MAP_FREE, &[pointer.into()]);
+                }
+                _ => {
+                    let free = declare_free(&self.module);
+                    self.builder.build_call(free, &[pointer.into()], "");
+                }
+            }
+            self.builder.build_unconditional_branch(done);
+            self.builder.position_at_end(done);
+            None
+        })
+    }
+
+    /// The function copying a box, `Vec` or `HashMap` of type `ty` into a new one, which owns
+    /// copies of the strings and boxes it holds. Built the first time it's needed.
+    fn clone_glue(&self, ty: &Type) -> FunctionValue<'ctx> {
+        let i8_ptr_type = self.context.i8_type().ptr_type(AddressSpace::default());
+        let fn_type = i8_ptr_type.fn_type(&[i8_ptr_type.into()], false);
+        self.build_glue(&format!("clone.{}", ty), fn_type, |pointer| {
+            if let Type::HashMap(key_ty, value_ty) = ty {
+                // The runtime copies the entries' bytes, which are then detached in place.
+                let copy = self
+                    .build_map_call(MAP_CLONE, &[pointer.into()])
+                    .unwrap()
+                    .into_pointer_value();
+                let len = self.build_vec_len(copy);
+                self.build_counted_loop(len, |i| {
+                    let (key, value) = self.build_map_entry(copy, ty, i);
+                    for (pointer, ty) in [(key, key_ty), (value, value_ty)] {
+                        let loaded = self.build_unpack(self.builder.build_load(pointer, ""), ty);
+                        let detached = self.build_pack(&self.build_detached(&loaded));
+                        self.builder.build_store(pointer, detached);
+                    }
+                });
+                return Some(copy.into());
+            }
+            let Type::Vec(element) = ty else {
+                let value = self.build_load_heap(pointer, ty);
+                return Some(self.build_heap(&value, ty).into());
+            };
+            let len = self.build_vec_len(pointer);
+            let copy = self.build_alloc(self.build_vec_size(len, element));
+            self.builder.build_store(self.build_count(copy), len);
+            self.build_counted_loop(len, |i| {
+                let value = self.build_load_element(pointer, element, i);
+                let value = self.build_pack(&self.build_detached(&value));
+                self.builder
+                    .build_store(self.build_element(copy, element, i), value);
+            });
+            Some(copy.into())
+        })
+    }
+
+    /// How the elements of a `Vec` of `element`s are laid out, after its length.
+    fn vec_type(&self, element: &Type) -> StructType<'ctx> {
+        self.context.struct_type(
+            &[
+                self.context.i64_type().into(),
+                self.llvm_type(element).array_type(0).into(),
+            ],
+            false,
+        )
+    }
+
+    /// The size of a `Vec` of `len` `element`s.
+    fn build_vec_size(&self, len: IntValue<'ctx>, element: &Type) -> IntValue<'ctx> {
+        let null = self
+            .vec_type(element)
+            .ptr_type(AddressSpace::default())
+            .const_null();
+        let end = self.build_element(null, element, len);
+        self.builder
+            .build_ptr_to_int(end, self.context.i64_type(), "")
+    }
+
+    /// The length of the `Vec` at `pointer`, which is laid out like an `Rc`'s count.
+    fn build_vec_len(&self, pointer: PointerValue<'ctx>) -> IntValue<'ctx> {
+        self.builder
+            .build_load(self.build_count(pointer), "")
+            .into_int_value()
+    }
+
+    /// A pointer to the element at `index` of the `Vec` of `element`s at `pointer`.
+    fn build_element(
+        &self,
+        pointer: PointerValue<'ctx>,
+        element: &Type,
+        index: IntValue<'ctx>,
+    ) -> PointerValue<'ctx> {
+        let vec_type = self.vec_type(element).ptr_type(AddressSpace::default());
+        let vec = self.builder.build_pointer_cast(pointer, vec_type, "");
+        let i32_type = self.context.i32_type();
+        let indices = [i32_type.const_zero(), i32_type.const_int(1, false), index];
+        unsafe { self.builder.build_in_bounds_gep(vec, &indices, "") }
+    }
+
+    /// The element at `index` of the `Vec` of `element`s at `pointer`. Its strings and boxes are
+    /// the `Vec`'s.
+    fn build_load_element(
+        &self,
+        pointer: PointerValue<'ctx>,
+        element: &Type,
+        index: IntValue<'ctx>,
+    ) -> Value<'ctx> {
+        let element_pointer = self.build_element(pointer, element, index);
+        self.build_unpack(self.builder.build_load(element_pointer, ""), element)
+    }
+
+    /// Moves `values` into a new `Vec` of `element`s, which owns copies of their strings, boxes
+    /// and clones of `Rc`s.
+    fn build_vec(&self, values: &[Value<'ctx>], element: &Type) -> PointerValue<'ctx> {
+        let len = self
+            .context
+            .i64_type()
+            .const_int(values.len().try_into().unwrap(), false);
+        let pointer = self.build_alloc(self.build_vec_size(len, element));
+        self.builder.build_store(self.build_count(pointer), len);
+        for (i, value) in values.iter().enumerate() {
+            let index = self.context.i64_type().const_int(i as u64, false);
+            let value = self.build_pack(&self.build_detached(value));
+            self.builder
+                .build_store(self.build_element(pointer, element, index), value);
+        }
+        pointer
+    }
+
+    /// How an entry of a `HashMap` of type `ty` is laid out: its key, then its value.
+    fn entry_type(&self, ty: &Type) -> StructType<'ctx> {
+        let Type::HashMap(key, value) = ty else {
+            unreachable!("only `HashMap`s have entries")
+        };
+        self.context
+            .struct_type(&[self.llvm_type(key), self.llvm_type(value)], false)
+    }
+
+    /// A new, empty `HashMap` of type `ty`, which tells the runtime how its keys are laid out.
+    fn build_map(&self, ty: &Type) -> PointerValue<'ctx> {
+        let Type::HashMap(key, _) = ty else {
+            unreachable!("only `HashMap`s are built as maps")
+        };
+        let key_kind = self.key_kind(key);
+        let entry_size = self.entry_type(ty).size_of().unwrap();
+        self.build_map_call(MAP_NEW, &[key_kind.into(), entry_size.into()])
+            .unwrap()
+            .into_pointer_value()
+    }
+
+    /// How the runtime compares and hashes keys of type `key`.
+    fn key_kind(&self, key: &Type) -> IntValue<'ctx> {
+        let key_kind = match key {
+            Type::Str => KEY_STR,
+            _ => KEY_INT,
+        };
+        self.context.i64_type().const_int(key_kind, false)
+    }
+
+    /// A pointer to the entry at `index` of the `HashMap` at `pointer`, of type `ty`.
+    fn build_entry(
+        &self,
+        pointer: PointerValue<'ctx>,
+        ty: &Type,
+        index: IntValue<'ctx>,
+    ) -> PointerValue<'ctx> {
+        let entry = self
+            .build_map_call(MAP_ENTRY, &[pointer.into(), index.into()])
+            .unwrap()
+            .into_pointer_value();
+        let entry_type = self.entry_type(ty).ptr_type(AddressSpace::default());
+        self.builder.build_pointer_cast(entry, entry_type, "")
+    }
+
+    /// Pointers to the key and the value of the entry at `index` of the `HashMap` at `pointer`, of
+    /// type `ty`.
+    fn build_map_entry(
+        &self,
+        pointer: PointerValue<'ctx>,
+        ty: &Type,
+        index: IntValue<'ctx>,
+    ) -> (PointerValue<'ctx>, PointerValue<'ctx>) {
+        let entry = self.build_entry(pointer, ty, index);
+        (
+            self.builder.build_struct_gep(entry, 0, "").unwrap(),
+            self.builder.build_struct_gep(entry, 1, "").unwrap(),
+        )
+    }
+
+    /// Frees the strings and boxes of the entry at `index` of the `HashMap` at `pointer`, of type
+    /// `ty`.
+    fn build_free_entry(&self, pointer: PointerValue<'ctx>, ty: &Type, index: IntValue<'ctx>) {
+        for allocation in self.build_entry_allocations(pointer, ty, index) {
+            self.build_free(&allocation);
+        }
+    }
+
+    /// The strings and boxes of the entry at `index` of the `HashMap` at `pointer`, of type `ty`.
+    fn build_entry_allocations(
+        &self,
+        pointer: PointerValue<'ctx>,
+        ty: &Type,
+        index: IntValue<'ctx>,
+    ) -> Vec<Allocation<'ctx>> {
+        let Type::HashMap(key_ty, value_ty) = ty else {
+            unreachable!("only `HashMap`s have entries")
+        };
+        let (key, value) = self.build_map_entry(pointer, ty, index);
+        [(key, key_ty), (value, value_ty)]
+            .into_iter()
+            .flat_map(|(pointer, ty)| {
+                self.build_unpack(self.builder.build_load(pointer, ""), ty)
+                    .allocations()
+            })
+            .collect()
+    }
+
+    /// The index of the entry of the `HashMap` at `pointer` with the key `key`, or -1 if it has
+    /// none.
+    fn build_find(&self, pointer: PointerValue<'ctx>, key: &Value<'ctx>) -> IntValue<'ctx> {
+        let key = self.build_pack(key);
+        let slot = self.build_stack_slot(key.get_type());
+        self.builder.build_store(slot, key);
+        let i8_ptr_type = self.context.i8_type().ptr_type(AddressSpace::default());
+        let slot = self.builder.build_pointer_cast(slot, i8_ptr_type, "");
+        self.build_map_call(MAP_FIND, &[pointer.into(), slot.into()])
+            .unwrap()
+            .into_int_value()
+    }
+
+    fn build_map_call(
+        &self,
+        name: &str,
+        args: &[BasicMetadataValueEnum<'ctx>],
+    ) -> Option<BasicValueEnum<'ctx>> {
+        self.builder
+            .build_call(declare_map(&self.module, name), args, "")
+            .try_as_basic_value()
+            .left()
+    }
+
+    /// Runs `body` with each index from 0 up to `count`.
+    fn build_counted_loop(&self, count: IntValue<'ctx>, body: impl FnOnce(IntValue<'ctx>)) {
+        let entry = self.builder.get_insert_block().unwrap();
+        let function = entry.get_parent().unwrap();
+        let header = self.context.append_basic_block(function, "");
+        let next = self.context.append_basic_block(function, "");
+        let done = self.context.append_basic_block(function, "");
+        self.builder.build_unconditional_branch(header);
+        self.builder.position_at_end(header);
+        let i64_type = self.context.i64_type();
+        let index = self.builder.build_phi(i64_type, "");
+        index.add_incoming(&[(&i64_type.const_zero(), entry)]);
+        let i = index.as_basic_value().into_int_value();
+        let more = self
+            .builder
+            .build_int_compare(IntPredicate::ULT, i, count, "");
+        self.builder.build_conditional_branch(more, next, done);
+        self.builder.position_at_end(next);
+        body(i);
+        let incremented = self
+            .builder
+            .build_int_add(i, i64_type.const_int(1, false), "");
+        index.add_incoming(&[(&incremented, self.builder.get_insert_block().unwrap())]);
+        self.builder.build_unconditional_branch(header);
+        self.builder.position_at_end(done);
+    }
+
+    /// The next item of the iterator `state` of type `ty`: whether there is one, the item, which
+    /// is a placeholder if there isn't, and the iterator's state after yielding it. The strings
+    /// and boxes of items are the string's or `Vec`'s the iterator iterates over.
+    fn build_next(
+        &self,
+        state: &Value<'ctx>,
+        ty: &Type,
+    ) -> (IntValue<'ctx>, Value<'ctx>, Value<'ctx>) {
+        let i64_type = self.context.i64_type();
+        let one = i64_type.const_int(1, false);
+        let Value::Struct(fields) = state else {
+            unreachable!("iterators are built as structs")
+        };
+        match (ty, &fields[..]) {
+            (Type::Range, [Value::Int(start), Value::Int(end)]) => {
+                let is_some = self
+                    .builder
+                    .build_int_compare(IntPredicate::SLT, *start, *end, "");
+                let next = self.builder.build_int_add(*start, one, "");
+                let next = Value::Struct(vec![Value::Int(next), Value::Int(*end)]);
+                (is_some, Value::Int(*start), next)
+            }
+            (Type::Chars, [string @ Value::Str(buffer, len), Value::Int(offset)]) => {
+                let is_some = self
+                    .builder
+                    .build_int_compare(IntPredicate::ULT, *offset, *len, "");
+                let char_start =
+                    unsafe { self.builder.build_in_bounds_gep(*buffer, &[*offset], "") };
+                // A char's width is read off its first byte, which is only there if the char is.
+                let zero = Value::Int(i64_type.const_zero());
+                let width = self.build_if(is_some, zero, || {
+                    let byte = self.builder.build_load(char_start, "").into_int_value();
+                    let byte = self.builder.build_int_z_extend(byte, i64_type, "");
+                    let width = [0xC0, 0xE0, 0xF0].into_iter().fold(one, |width, lead| {
+                        let at_least = self.builder.build_int_compare(
+                            IntPredicate::UGE,
+                            byte,
+                            i64_type.const_int(lead, false),
+                            "",
+                        );
+                        let at_least = self.builder.build_int_z_extend(at_least, i64_type, "");
+                        self.builder.build_int_add(width, at_least, "")
+                    });
+                    Value::Int(width)
+                });
+                let Value::Int(width) = width else {
+                    unreachable!()
+                };
+                let next = self.builder.build_int_add(*offset, width, "");
+                let next = Value::Struct(vec![string.clone(), Value::Int(next)]);
+                (is_some, Value::Str(char_start, width), next)
+            }
+            (
+                Type::IntoIter(element) | Type::Iter(element),
+                [vec @ Value::Heap(pointer, _), Value::Int(index)],
+            ) => {
+                let len = self.build_vec_len(*pointer);
+                let is_some = self
+                    .builder
+                    .build_int_compare(IntPredicate::ULT, *index, len, "");
+                let item = self.build_if(is_some, self.build_placeholder(element), || {
+                    self.build_load_element(*pointer, element, *index)
+                });
+                let next = self.builder.build_int_add(*index, one, "");
+                let next = Value::Struct(vec![vec.clone(), Value::Int(next)]);
+                (is_some, item, next)
+            }
+            _ => unreachable!("only iterators are iterated over after lowering"),
+        }
+    }
+
+    /// The value `then` builds if `condition` holds, or `otherwise`.
+    fn build_if(
+        &self,
+        condition: IntValue<'ctx>,
+        otherwise: Value<'ctx>,
+        then: impl FnOnce() -> Value<'ctx>,
+    ) -> Value<'ctx> {
+        let entry = self.builder.get_insert_block().unwrap();
+        let function = entry.get_parent().unwrap();
+        let then_block = self.context.append_basic_block(function, "");
+        let done = self.context.append_basic_block(function, "");
+        self.builder
+            .build_conditional_branch(condition, then_block, done);
+        self.builder.position_at_end(then_block);
+        let value = then();
+        let then_block = self.builder.get_insert_block().unwrap();
+        self.builder.build_unconditional_branch(done);
+        self.builder.position_at_end(done);
+        self.build_merge(vec![(value, then_block), (otherwise, entry)])
+    }
+
+    /// Runs `then` if `condition` holds, and `otherwise` if it doesn't.
+    fn build_if_else(
+        &self,
+        condition: IntValue<'ctx>,
+        then: impl FnOnce(),
+        otherwise: impl FnOnce(),
+    ) {
+        let function = self
+            .builder
+            .get_insert_block()
+            .unwrap()
+            .get_parent()
+            .unwrap();
+        let then_block = self.context.append_basic_block(function, "");
+        let otherwise_block = self.context.append_basic_block(function, "");
+        let done = self.context.append_basic_block(function, "");
+        self.builder
+            .build_conditional_branch(condition, then_block, otherwise_block);
+        self.builder.position_at_end(then_block);
+        then();
+        self.builder.build_unconditional_branch(done);
+        self.builder.position_at_end(otherwise_block);
+        otherwise();
+        self.builder.build_unconditional_branch(done);
+        self.builder.position_at_end(done);
+    }
+
+    /// The function counting another clone of an `Rc`, which it returns. Built the first time it's
+    /// needed.
+    fn retain_glue(&self) -> FunctionValue<'ctx> {
+        let i8_ptr_type = self.context.i8_type().ptr_type(AddressSpace::default());
+        let fn_type = i8_ptr_type.fn_type(&[i8_ptr_type.into()], false);
+        self.build_glue("retain.Rc", fn_type, |pointer| {
+            let count = self.build_count(pointer);
+            let clones = self.builder.build_load(count, "").into_int_value();
+            let one = self.context.i64_type().const_int(1, false);
+            let clones = self.builder.build_int_add(clones, one, "");
+            self.builder.build_store(count, clones);
+            Some(pointer.into())
+        })
+    }
+
+    /// Gets the glue function `name`, or builds it with `body`, which is given a pointer to a box
+    /// or an `Rc` and returns what the function does. Given a null pointer, which placeholders
+    /// are, the function does nothing and returns it.
+    fn build_glue(
+        &self,
+        name: &str,
+        fn_type: FunctionType<'ctx>,
+        body: impl FnOnce(PointerValue<'ctx>) -> Option<BasicValueEnum<'ctx>>,
+    ) -> FunctionValue<'ctx> {
+        let symbol = self.symbol(name);
+        if let Some(function) = self.module.get_function(&symbol) {
+            return function;
+        }
+        let function = self.module.add_function(&symbol, fn_type, None);
+        let resume = self.builder.get_insert_block();
+        let entry = self.context.append_basic_block(function, "");
+        let non_null = self.context.append_basic_block(function, "");
+        let null = self.context.append_basic_block(function, "null");
+        self.builder.position_at_end(entry);
+        let pointer = function.get_first_param().unwrap().into_pointer_value();
+        let is_null = self.builder.build_is_null(pointer, "");
+        self.builder
+            .build_conditional_branch(is_null, null, non_null);
+        self.builder.position_at_end(non_null);
+        let returned = body(pointer);
+        self.builder
+            .build_return(returned.as_ref().map(|value| value as &dyn BasicValue));
+        self.builder.position_at_end(null);
+        let returned = returned.map(|_| pointer.as_basic_value_enum());
+        self.builder
+            .build_return(returned.as_ref().map(|value| value as &dyn BasicValue));
+        if let Some(resume) = resume {
+            self.builder.position_at_end(resume);
+        }
+        function
+    }
+
+    /// The types of the fields of a tuple struct, of the values a closure captures, or of an
+    /// iterator's state: what it iterates over, then its position.
+    fn field_types(&self, ty: &Type) -> Vec<Type> {
+        match ty {
+            Type::Struct(name) => self.structs.borrow()[name].clone(),
+            Type::Closure(_, _, captures) => captures.to_vec(),
+            // A range's position is its start.
+            Type::Range => vec![Type::Int, Type::Int],
+            Type::Chars => vec![Type::Str, Type::Int],
+            Type::IntoIter(ty) | Type::Iter(ty) => vec![Type::Vec(ty.clone()), Type::Int],
+            _ => unreachable!("only structs, closures and iterators have fields"),
+        }
+    }
+
+    /// How a value of type `ty` is passed to and returned from functions.
+    fn llvm_type(&self, ty: &Type) -> BasicTypeEnum<'ctx> {
+        let fields: Vec<BasicTypeEnum> = match ty {
+            Type::Str => vec![
+                self.context
+                    .i8_type()
+                    .ptr_type(AddressSpace::default())
+                    .into(),
+                self.context.i64_type().into(),
+            ],
+            Type::Int | Type::Bool | Type::Unit | Type::Unknown => {
+                return self.context.i64_type().into()
+            }
+            // The value referred to can't change, so references are passed as a copy of it.
+            Type::Ref(ty, _) => return self.llvm_type(ty),
+            Type::Box(_) | Type::Rc(_) | Type::Vec(_) | Type::HashMap(..) => {
+                return self
+                    .context
+                    .i8_type()
+                    .ptr_type(AddressSpace::default())
+                    .into()
+            }
+            Type::Option(ty) => vec![self.context.bool_type().into(), self.llvm_type(ty)],
+            Type::Result(ok, error) => vec![
+                self.context.bool_type().into(),
+                self.llvm_type(ok),
+                self.llvm_type(error),
+            ],
+            Type::Struct(_)
+            | Type::Closure(..)
+            | Type::Range
+            | Type::Chars
+            | Type::IntoIter(_)
+            | Type::Iter(_) => self
+                .field_types(ty)
+                .iter()
+                .map(|field| self.llvm_type(field))
+                .collect(),
+            Type::Param(_) => unreachable!("type parameters are substituted before codegen"),
+        };
+        self.context.struct_type(&fields, false).into()
+    }
+
+    /// Packs `value` into a single LLVM value, laid out as [`Codegen::llvm_type`] lays out its
+    /// type.
+    fn build_pack(&self, value: &Value<'ctx>) -> BasicValueEnum<'ctx> {
+        let fields = match value {
+            Value::Int(value) => return (*value).into(),
+            Value::Str(buffer, len) => vec![(*buffer).into(), (*len).into()],
+            Value::Option(is_some, value) => vec![(*is_some).into(), self.build_pack(value)],
+            Value::Result(is_ok, ok, error) => {
+                vec![(*is_ok).into(), self.build_pack(ok), self.build_pack(error)]
+            }
+            Value::Struct(fields) => fields.iter().map(|field| self.build_pack(field)).collect(),
+            Value::Heap(pointer, _) => return (*pointer).into(),
+        };
+        self.build_struct(&fields)
+    }
+
+    fn build_struct(&self, fields: &[BasicValueEnum<'ctx>]) -> BasicValueEnum<'ctx> {
+        let types: Vec<_> = fields.iter().map(|field| field.get_type()).collect();
+        let mut packed = self.context.struct_type(&types, false).get_undef();
+        for (i, field) in fields.iter().enumerate() {
+            packed = self
+                .builder
+                .build_insert_value(packed, *field, i.try_into().unwrap(), "")
+                .unwrap()
+                .into_struct_value();
+        }
+        packed.into()
+    }
+
+    /// Unpacks a value of type `ty` that [`Codegen::build_pack`] packed.
+    fn build_unpack(&self, packed: BasicValueEnum<'ctx>, ty: &Type) -> Value<'ctx> {
+        let field = |i: usize| {
+            self.builder
+                .build_extract_value(packed.into_struct_value(), i.try_into().unwrap(), "")
+                .unwrap()
+        };
+        match ty {
+            Type::Str => Value::Str(field(0).into_pointer_value(), field(1).into_int_value()),
+            Type::Int | Type::Bool | Type::Unit | Type::Unknown => {
+                Value::Int(packed.into_int_value())
+            }
+            Type::Ref(ty, _) => self.build_unpack(packed, ty),
+            Type::Box(_) | Type::Rc(_) | Type::Vec(_) | Type::HashMap(..) => {
+                Value::Heap(packed.into_pointer_value(), ty.clone())
+            }
+            Type::Option(ty) => Value::Option(
+                field(0).into_int_value(),
+                Box::new(self.build_unpack(field(1), ty)),
+            ),
+            Type::Result(ok, error) => Value::Result(
+                field(0).into_int_value(),
+                Box::new(self.build_unpack(field(1), ok)),
+                Box::new(self.build_unpack(field(2), error)),
+            ),
+            Type::Struct(_)
+            | Type::Closure(..)
+            | Type::Range
+            | Type::Chars
+            | Type::IntoIter(_)
+            | Type::Iter(_) => Value::Struct(
+                self.field_types(ty)
+                    .iter()
+                    .enumerate()
+                    .map(|(i, ty)| self.build_unpack(field(i), ty))
+                    .collect(),
+            ),
+            Type::Param(_) => unreachable!("type parameters are substituted before codegen"),
+        }
+    }
+
+    fn build_stmt(&self, stmt: Stmt, runtime: Runtime<'ctx>) {
+        match stmt {
+            Stmt::Print(stream, exprs) => {
+                let pieces = self.build_pieces(exprs, runtime);
+                self.build_print(runtime.writer(stream), pieces, runtime);
+            }
+            Stmt::Expr(expr) => {
+                self.build_expr(expr, runtime);
+            }
+            Stmt::Sleep(millis) => {
+                let millis = self.build_int(millis, runtime);
+                let negative = self.builder.build_int_compare(
+                    IntPredicate::SLT,
+                    millis,
+                    self.context.i64_type().const_zero(),
+                    "",
+                );
+                self.build_panic_if(negative, "cannot sleep for a negative duration", runtime);
+                self.build_runtime_call(
+                    runtime,
+                    runtime.sleep_millis,
+                    &[runtime.host.as_pointer_value().into(), millis.into()],
+                );
+            }
+            Stmt::WriteFile(path, contents) => {
+                let pieces = self.build_pieces(vec![path, contents], runtime);
+                let [(path, path_len), (contents, len)] = pieces[..] else {
+                    unreachable!()
+                };
+                self.build_runtime_call(
+                    runtime,
+                    runtime.write_file,
+                    &[
+                        runtime.host.as_pointer_value().into(),
+                        path.into(),
+                        path_len.into(),
+                        contents.into(),
+                        len.into(),
+                    ],
+                );
+            }
+            Stmt::Exit(status) => {
+                let status = self.build_int(status, runtime);
+                self.build_runtime_call(
+                    runtime,
+                    runtime.exit,
+                    &[runtime.host.as_pointer_value().into(), status.into()],
+                );
+            }
+            Stmt::For(pattern, iter, body) => self.build_for(pattern, iter, body, runtime),
+            Stmt::While(condition) => self.build_while(condition, runtime),
+            Stmt::Block(body) => {
+                let owned = self.owned.borrow().len();
+                for stmt in body {
+                    self.build_stmt(stmt, runtime);
+                }
+                self.build_scope_end(owned);
+            }
+            // The value is copied, as it may be a temporary or another local's.
+            Stmt::Let(pattern, value) => {
+                let value = self.build_expr(value, runtime);
+                let value = self.build_detached(&value);
+                self.owned.borrow_mut().extend(value.allocations());
+                self.bind(&pattern, &value);
+            }
+        }
+        for temporary in self.temporaries.take() {
+            self.build_free(&temporary);
+        }
+    }
+
+    /// Frees what the values bound by the `let`s after the first `owned` own, as their scope ends.
+    fn build_scope_end(&self, owned: usize) {
+        let allocations = self.owned.borrow_mut().split_off(owned);
+        for allocation in allocations {
+            self.build_free(&allocation);
+        }
+    }
+
+    /// Builds a `for` loop, which keeps the iterator's state in a phi. The statements of its body
+    /// free their temporaries as they go, while those of the loop itself, like the `Vec` it
+    /// iterates over, are freed once it's done.
+    fn build_for(&self, pattern: Pattern, iter: Expr, body: Vec<Stmt>, runtime: Runtime<'ctx>) {
+        let ty = iter.ty();
+        let state = self.build_expr(iter, runtime);
+        let temporaries = self.temporaries.take();
+        let packed = self.build_pack(&state);
+        let entry = self.builder.get_insert_block().unwrap();
+        let header = self.context.append_basic_block(runtime.function, "");
+        let next = self.context.append_basic_block(runtime.function, "");
+        let done = self.context.append_basic_block(runtime.function, "");
+        self.builder.build_unconditional_branch(header);
+        self.builder.position_at_end(header);
+        let phi = self.builder.build_phi(self.llvm_type(&ty), "");
+        phi.add_incoming(&[(&packed, entry)]);
+        let state = self.build_unpack(phi.as_basic_value(), &ty);
+        let (is_some, item, state) = self.build_next(&state, &ty);
+        self.builder.build_conditional_branch(is_some, next, done);
+        self.builder.position_at_end(next);
+        self.bind(&pattern, &item);
+        let owned = self.owned.borrow().len();
+        for stmt in body {
+            self.build_stmt(stmt, runtime);
+        }
+        self.build_scope_end(owned);
+        self.build_tick(runtime);
+        let packed = self.build_pack(&state);
+        phi.add_incoming(&[(&packed, self.builder.get_insert_block().unwrap())]);
+        self.builder.build_unconditional_branch(header);
+        self.builder.position_at_end(done);
+        self.temporaries.replace(temporaries);
+    }
+
+    /// Takes a step of the program's fuel, refueling once it runs out, which aborts the program if
+    /// it may take no more. Loops take one every iteration and functions every call, so a program
+    /// can't run forever when its fuel is limited.
+    fn build_tick(&self, runtime: Runtime<'ctx>) {
+        let fuel = runtime.fuel.as_pointer_value();
+        let left = self.builder.build_load(fuel, "").into_int_value();
+        let left = self
+            .builder
+            .build_int_sub(left, left.get_type().const_int(1, false), "");
+        self.builder.build_store(fuel, left);
+        let empty = self.builder.build_int_compare(
+            IntPredicate::SLT,
+            left,
+            left.get_type().const_zero(),
+            "",
+        );
+        let refuel = self.context.append_basic_block(runtime.function, "refuel");
+        let next = self.context.append_basic_block(runtime.function, "");
+        self.builder.build_conditional_branch(empty, refuel, next);
+        self.builder.position_at_end(refuel);
+        self.build_runtime_call(runtime, runtime.refuel, &[]);
+        self.builder.build_unconditional_branch(next);
+        self.builder.position_at_end(next);
+    }
+
+    /// Builds a loop evaluating `condition` until it's `false`. The temporaries of each evaluation
+    /// are freed before the next.
+    fn build_while(&self, condition: Expr, runtime: Runtime<'ctx>) {
+        let temporaries = self.temporaries.take();
+        let header = self.context.append_basic_block(runtime.function, "");
+        let done = self.context.append_basic_block(runtime.function, "");
+        self.builder.build_unconditional_branch(header);
+        self.builder.position_at_end(header);
+        self.build_tick(runtime);
+        let value = self.build_int(condition, runtime);
+        for temporary in self.temporaries.take() {
+            self.build_free(&temporary);
+        }
+        let zero = value.get_type().const_zero();
+        let again = self
+            .builder
+            .build_int_compare(IntPredicate::NE, value, zero, "");
+        self.builder.build_conditional_branch(again, header, done);
+        self.builder.position_at_end(done);
+        self.temporaries.replace(temporaries);
+    }
+
+    /// Builds the pieces of a format string. Like Rust, every piece is evaluated, left to right,
+    /// before any is used, so a read is copied if a later piece would reuse its buffer.
+    fn build_pieces(
+        &self,
+        exprs: Vec<Expr>,
+        runtime: Runtime<'ctx>,
+    ) -> Vec<(PointerValue<'ctx>, IntValue<'ctx>)> {
+        let reads: Vec<_> = exprs.iter().map(Expr::reads_stdin).collect();
+        let mut pieces = Vec::new();
+        for (i, expr) in exprs.into_iter().enumerate() {
+            let reuses_buffer = matches!(expr, Expr::ReadLine | Expr::ReadToString)
+                && reads[i + 1..].contains(&true);
+            let (buffer, len) = self.build_str(expr, runtime);
+            pieces.push(if reuses_buffer {
+                (self.build_copy(buffer, len), len)
+            } else {
+                (buffer, len)
+            });
+        }
+        pieces
+    }
+
+    /// Writes `pieces` in order to `writer`, the global of the stream `print!` and the like write
+    /// to.
+    fn build_print(
+        &self,
+        writer: GlobalValue<'ctx>,
+        pieces: impl IntoIterator<Item = (PointerValue<'ctx>, IntValue<'ctx>)>,
+        runtime: Runtime<'ctx>,
+    ) {
+        for (buffer, len) in pieces {
+            self.build_runtime_call(
+                runtime,
+                runtime.write,
+                &[writer.as_pointer_value().into(), buffer.into(), len.into()],
+            );
+        }
+    }
+
+    /// Copies `len` bytes from `buffer` into a new temporary.
+    fn build_copy(&self, buffer: PointerValue<'ctx>, len: IntValue<'ctx>) -> PointerValue<'ctx> {
+        let copy = self.build_temporary(len);
+        self.builder.build_memcpy(copy, 1, buffer, 1, len).unwrap();
+        copy
+    }
+
+    /// Allocates a `len` byte string that's freed at the end of the current statement.
+    fn build_temporary(&self, len: IntValue<'ctx>) -> PointerValue<'ctx> {
+        let temporary = self.build_alloc(len);
+        self.temporaries
+            .borrow_mut()
+            .push(Allocation::Buffer(temporary));
+        temporary
+    }
+
+    /// Stack space for a value of type `ty`, allocated in the current function's entry block so
+    /// a loop running the code needing it doesn't allocate it again each iteration.
+    fn build_stack_slot(&self, ty: impl BasicType<'ctx>) -> PointerValue<'ctx> {
+        let function = self
+            .builder
+            .get_insert_block()
+            .unwrap()
+            .get_parent()
+            .unwrap();
+        let entry = function.get_first_basic_block().unwrap();
+        let builder = self.context.create_builder();
+        match entry.get_first_instruction() {
+            Some(first) => builder.position_before(&first),
+            None => builder.position_at_end(entry),
+        }
+        builder.build_alloca(ty, "")
+    }
+
+    fn build_alloc(&self, len: IntValue<'ctx>) -> PointerValue<'ctx> {
+        self.builder
+            .build_call(declare_alloc(&self.module), &[len.into()], "")
+            .try_as_basic_value()
+            .left()
+            .unwrap()
+            .into_pointer_value()
+    }
+
+    /// Builds `expr`, formatting it if it isn't already a string, and returns a pointer to its
+    /// bytes and their length.
+    fn build_str(
+        &self,
+        expr: Expr,
+        runtime: Runtime<'ctx>,
+    ) -> (PointerValue<'ctx>, IntValue<'ctx>) {
+        let value = self.build_expr(expr, runtime);
+        self.build_display(value, runtime)
+    }
+
+    /// Formats `value` if it isn't already a string.
+    fn build_display(
+        &self,
+        value: Value<'ctx>,
+        runtime: Runtime<'ctx>,
+    ) -> (PointerValue<'ctx>, IntValue<'ctx>) {
+        match value {
+            Value::Str(buffer, len) => (buffer, len),
+            Value::Int(value) => {
+                let buffer_type = self.context.i8_type().array_type(FORMAT_I64_LEN as u32);
+                let buffer = self.builder.build_pointer_cast(
+                    self.build_stack_slot(buffer_type),
+                    self.context.i8_type().ptr_type(AddressSpace::default()),
+                    "",
+                );
+                let len = self
+                    .builder
+                    .build_call(runtime.format_i64, &[value.into(), buffer.into()], "")
+                    .try_as_basic_value()
+                    .left()
+                    .unwrap()
+                    .into_int_value();
+                (buffer, len)
+            }
+            Value::Option(..) | Value::Result(..) | Value::Struct(_) | Value::Heap(..) => {
+                unreachable!("only strings and integers are formatted")
+            }
+        }
+    }
+
+    fn build_expr(&self, expr: Expr, runtime: Runtime<'ctx>) -> Value<'ctx> {
+        let i64_type = self.context.i64_type();
+        let mode = match expr {
+            Expr::Str(val) => {
+                let val = val.as_str();
+                let buffer = self.const_str(val);
+                let len = i64_type.const_int(val.len().try_into().unwrap(), false);
+                return Value::Str(buffer, len);
+            }
+            Expr::Int(val) => return Value::Int(i64_type.const_int(val as u64, true)),
+            Expr::Static(name, ty) => {
+                let global = self.module.get_global(&self.static_symbol(name)).unwrap();
+                let pointer = global.as_pointer_value();
+                if !matches!(ty, Type::Str | Type::Ref(..)) {
+                    return Value::Int(self.builder.build_load(pointer, "").into_int_value());
+                }
+                let len = pointer
+                    .get_type()
+                    .get_element_type()
+                    .into_array_type()
+                    .len();
+                let i8_ptr_type = self.context.i8_type().ptr_type(AddressSpace::default());
+                let buffer = self.builder.build_pointer_cast(pointer, i8_ptr_type, "");
+                return Value::Str(buffer, i64_type.const_int(len.into(), false));
+            }
+            // The block's statements free their temporaries as they go, while the tail's are
+            // freed with those of the statement the block is in. The tail is copied if it may be
+            // one of the block's locals, which are freed as it ends.
+            Expr::Block(stmts, tail) => {
+                let temporaries = self.temporaries.take();
+                let owned = self.owned.borrow().len();
+                for stmt in stmts {
+                    self.build_stmt(stmt, runtime);
+                }
+                let mut value = self.build_expr(*tail, runtime);
+                if self.owned.borrow().len() > owned {
+                    value = self.build_detached(&value);
+                    self.temporaries.borrow_mut().extend(value.allocations());
+                }
+                self.build_scope_end(owned);
+                self.temporaries.borrow_mut().splice(0..0, temporaries);
+                return value;
+            }
+            Expr::Neg(expr) => {
+                let value = self.build_int(*expr, runtime);
+                return Value::Int(self.build_overflowing(
+                    "llvm.ssub.with.overflow.i64",
+                    i64_type.const_zero(),
+                    value,
+                    "attempt to negate with overflow",
+                    runtime,
+                ));
+            }
+            Expr::Binary(op, lhs, rhs) => {
+                let lhs = self.build_int(*lhs, runtime);
+                let rhs = self.build_int(*rhs, runtime);
+                return Value::Int(self.build_arithmetic(op, lhs, rhs, runtime));
+            }
+            Expr::Format(exprs) => {
+                let pieces = self.build_pieces(exprs, runtime);
+                let len = pieces
+                    .iter()
+                    .fold(i64_type.const_zero(), |total, (_, len)| {
+                        self.builder.build_int_add(total, *len, "")
+                    });
+                let buffer = self.build_temporary(len);
+                let mut end = buffer;
+                for (piece, len) in pieces {
+                    self.builder.build_memcpy(end, 1, piece, 1, len).unwrap();
+                    end = unsafe { self.builder.build_in_bounds_gep(end, &[len], "") };
+                }
+                return Value::Str(buffer, len);
+            }
+            Expr::GetEnv(name) => return self.build_getenv(*name, runtime),
+            Expr::ReadFile(path) => return self.build_host_str(runtime.read_file, *path, runtime),
+            Expr::ToUppercase(value) => {
+                return self.build_host_str(runtime.to_uppercase, *value, runtime)
+            }
+            Expr::ToLowercase(value) => {
+                return self.build_host_str(runtime.to_lowercase, *value, runtime)
+            }
+            Expr::Trim(value) => {
+                let (buffer, len) = self.build_str(*value, runtime);
+                let trimmed =
+                    self.build_stack_slot(self.context.i8_type().ptr_type(AddressSpace::default()));
+                let len = self
+                    .builder
+                    .build_call(
+                        runtime.trim,
+                        &[buffer.into(), len.into(), trimmed.into()],
+                        "",
+                    )
+                    .try_as_basic_value()
+                    .left()
+                    .unwrap()
+                    .into_int_value();
+                // What's left points into the string, like a slice of it.
+                let trimmed = self.builder.build_load(trimmed, "").into_pointer_value();
+                return Value::Str(trimmed, len);
+            }
+            Expr::Contains(value, pattern) => {
+                let (buffer, len) = self.build_str(*value, runtime);
+                let (pattern, pattern_len) = self.build_str(*pattern, runtime);
+                return Value::Int(
+                    self.builder
+                        .build_call(
+                            runtime.contains,
+                            &[
+                                buffer.into(),
+                                len.into(),
+                                pattern.into(),
+                                pattern_len.into(),
+                            ],
+                            "",
+                        )
+                        .try_as_basic_value()
+                        .left()
+                        .unwrap()
+                        .into_int_value(),
+                );
+            }
+            Expr::Bool(value) => return Value::Int(i64_type.const_int(value.into(), false)),
+            Expr::RandomInt(lo, hi) => {
+                let lo = self.build_int(*lo, runtime);
+                let hi = self.build_int(*hi, runtime);
+                let empty = self
+                    .builder
+                    .build_int_compare(IntPredicate::SGE, lo, hi, "");
+                self.build_panic_if(empty, "cannot sample empty range", runtime);
+                let value = self
+                    .builder
+                    .build_call(
+                        runtime.random_int,
+                        &[runtime.host.as_pointer_value().into(), lo.into(), hi.into()],
+                        "",
+                    )
+                    .try_as_basic_value()
+                    .left()
+                    .unwrap()
+                    .into_int_value();
+                return Value::Int(value);
+            }
+            Expr::NowMillis => {
+                return Value::Int(self.build_runtime_call(
+                    runtime,
+                    runtime.now_millis,
+                    &[runtime.host.as_pointer_value().into()],
+                ));
+            }
+            Expr::Some(value) => {
+                let value = self.build_owned(*value, runtime);
+                return Value::Option(self.context.bool_type().const_all_ones(), Box::new(value));
+            }
+            Expr::None(ty) => {
+                return Value::Option(
+                    self.context.bool_type().const_zero(),
+                    Box::new(self.build_placeholder(&ty)),
+                );
+            }
+            Expr::Unwrap(option, span) => {
+                let Value::Option(is_some, value) = self.build_expr(*option, runtime) else {
+                    unreachable!("only options are unwrapped after lowering")
+                };
+                let is_none = self.builder.build_not(is_some, "");
+                self.build_panic_at_if(
+                    is_none,
+                    "called `Option::unwrap()` on a `None` value",
+                    Some(span),
+                    runtime,
+                );
+                return *value;
+            }
+            Expr::Match(scrutinee, arms, _) => return self.build_match(*scrutinee, arms, runtime),
+            Expr::Local(local, ty) => {
+                let value = self.locals.borrow()[&local].clone();
+                // A local of a type lowering only pinned down where it's used, like the `x` in
+                // `match None { Some(x) => x, ... }`, is never bound to anything but a
+                // placeholder.
+                return if value.has_type(&ty) {
+                    value
+                } else {
+                    self.build_placeholder(&ty)
+                };
+            }
+            Expr::Ok(value, error) => {
+                let value = self.build_owned(*value, runtime);
+                return Value::Result(
+                    self.context.bool_type().const_all_ones(),
+                    Box::new(value),
+                    Box::new(self.build_placeholder(&error)),
+                );
+            }
+            Expr::Err(ok, error) => {
+                let error = self.build_owned(*error, runtime);
+                return Value::Result(
+                    self.context.bool_type().const_zero(),
+                    Box::new(self.build_placeholder(&ok)),
+                    Box::new(error),
+                );
+            }
+            Expr::Try(result) => {
+                let Value::Result(is_ok, ok, error) = self.build_expr(*result, runtime) else {
+                    unreachable!("only results are tried after lowering")
+                };
+                let failed = self.context.append_basic_block(runtime.function, "error");
+                let next = self.context.append_basic_block(runtime.function, "");
+                self.builder.build_conditional_branch(is_ok, next, failed);
+                self.builder.position_at_end(failed);
+                let Type::Result(ok_ty, _) = self.ret.borrow().clone() else {
+                    unreachable!("only functions returning results try after lowering")
+                };
+                let returned = Value::Result(
+                    self.context.bool_type().const_zero(),
+                    Box::new(self.build_placeholder(&ok_ty)),
+                    error,
+                );
+                self.build_return(&returned);
+                self.builder.position_at_end(next);
+                return *ok;
+            }
+            Expr::Unit => return Value::Int(i64_type.const_zero()),
+            Expr::Call(callee, args, ty) => {
+                let Callee::Function(name, type_args) = callee else {
+                    unreachable!("methods of type parameters are resolved once instantiated")
+                };
+                let params: Vec<_> = args.iter().map(Expr::ty).collect();
+                let function = match type_args.is_empty() {
+                    true => self.declare_body(name, &params, &ty),
+                    false => self.declare_instance(name, type_args, &params, &ty),
+                };
+                let args: Vec<_> = args
+                    .into_iter()
+                    .map(|arg| self.build_pack(&self.build_owned(arg, runtime)).into())
+                    .collect();
+                return self.build_body_call(function, &args, &ty, runtime);
+            }
+            // A reference is the value it refers to.
+            Expr::Ref(value, _) => return self.build_owned(*value, runtime),
+            Expr::Deref(reference) => {
+                let ty = reference.ty();
+                return match self.build_expr(*reference, runtime) {
+                    Value::Heap(pointer, _) if matches!(ty, Type::Box(_) | Type::Rc(_)) => {
+                        self.build_load_heap(pointer, &ty)
+                    }
+                    value => value,
+                };
+            }
+            Expr::Slice(value, start, end, span) => {
+                let Value::Str(buffer, len) = self.build_expr(*value, runtime) else {
+                    unreachable!("only strings are sliced after lowering")
+                };
+                let i64_type = self.context.i64_type();
+                let start = match start {
+                    Some(start) => self.build_int(*start, runtime),
+                    None => i64_type.const_zero(),
+                };
+                let end = match end {
+                    Some(end) => self.build_int(*end, runtime),
+                    None => len,
+                };
+                self.build_runtime_call(
+                    runtime,
+                    runtime.check_slice,
+                    &[
+                        buffer.into(),
+                        len.into(),
+                        start.into(),
+                        end.into(),
+                        i64_type
+                            .const_int(span.start.try_into().unwrap(), false)
+                            .into(),
+                        i64_type
+                            .const_int(span.end.try_into().unwrap(), false)
+                            .into(),
+                    ],
+                );
+                let buffer = unsafe { self.builder.build_in_bounds_gep(buffer, &[start], "") };
+                return Value::Str(buffer, self.builder.build_int_sub(end, start, ""));
+            }
+            Expr::Len(value) => {
+                return match self.build_expr(*value, runtime) {
+                    Value::Str(_, len) => Value::Int(len),
+                    // A `HashMap` starts with its length, like a `Vec`.
+                    Value::Heap(pointer, _) => Value::Int(self.build_vec_len(pointer)),
+                    _ => unreachable!("only strings and collections have lengths after lowering"),
+                };
+            }
+            Expr::Vec(elements, element) => {
+                let values: Vec<_> = elements
+                    .into_iter()
+                    .map(|element| self.build_owned(element, runtime))
+                    .collect();
+                let pointer = self.build_vec(&values, &element);
+                let ty = Type::Vec(Box::new(element));
+                self.temporaries
+                    .borrow_mut()
+                    .push(Allocation::Heap(pointer, ty.clone()));
+                return Value::Heap(pointer, ty);
+            }
+            Expr::Range(start, end) => {
+                let start = self.build_int(*start, runtime);
+                let end = self.build_int(*end, runtime);
+                return Value::Struct(vec![Value::Int(start), Value::Int(end)]);
+            }
+            Expr::Chars(value) => {
+                let value = self.build_owned(*value, runtime);
+                return Value::Struct(vec![value, Value::Int(i64_type.const_zero())]);
+            }
+            Expr::IntoIter(value) => {
+                let iterator = value.ty().item().is_some();
+                let value = self.build_expr(*value, runtime);
+                return match iterator {
+                    true => value,
+                    false => Value::Struct(vec![value, Value::Int(i64_type.const_zero())]),
+                };
+            }
+            Expr::Next(iter) => {
+                let ty = iter.ty();
+                let state = self.build_expr(*iter, runtime);
+                let (is_some, item, _) = self.build_next(&state, &ty);
+                return Value::Option(is_some, Box::new(item));
+            }
+            Expr::Box(value) => {
+                let ty = Type::Box(Box::new(value.ty()));
+                return self.build_new(*value, ty, runtime);
+            }
+            Expr::Rc(value) => {
+                let ty = Type::Rc(Box::new(value.ty()));
+                return self.build_new(*value, ty, runtime);
+            }
+            Expr::CloneRc(rc) => {
+                let Value::Heap(pointer, ty) = self.build_expr(*rc, runtime) else {
+                    unreachable!("only `Rc`s are cloned after lowering")
+                };
+                let clone = self
+                    .builder
+                    .build_call(self.retain_glue(), &[pointer.into()], "")
+                    .try_as_basic_value()
+                    .left()
+                    .unwrap()
+                    .into_pointer_value();
+                self.temporaries
+                    .borrow_mut()
+                    .push(Allocation::Heap(clone, ty.clone()));
+                return Value::Heap(clone, ty);
+            }
+            Expr::Closure(_, _, captures) => {
+                return Value::Struct(
+                    captures
+                        .into_iter()
+                        .map(|capture| self.build_owned(capture, runtime))
+                        .collect(),
+                );
+            }
+            Expr::CallClosure(closure, args, ty) => {
+                let Type::Closure(name, type_args, captures) = closure.ty() else {
+                    unreachable!("only closures are called through values after lowering")
+                };
+                let params: Vec<_> = captures
+                    .iter()
+                    .cloned()
+                    .chain(args.iter().map(Expr::ty))
+                    .collect();
+                let function = match type_args.is_empty() {
+                    true => self.declare_body(name, &params, &ty),
+                    false => self.declare_instance(name, type_args.into(), &params, &ty),
+                };
+                let Value::Struct(captured) = self.build_owned(*closure, runtime) else {
+                    unreachable!("closures are built as structs of their captures")
+                };
+                let args: Vec<_> = captured
+                    .into_iter()
+                    .chain(args.into_iter().map(|arg| self.build_owned(arg, runtime)))
+                    .map(|arg| self.build_pack(&arg).into())
+                    .collect();
+                return self.build_body_call(function, &args, &ty, runtime);
+            }
+            Expr::Struct(_, fields) => {
+                return Value::Struct(
+                    fields
+                        .into_iter()
+                        .map(|field| self.build_owned(field, runtime))
+                        .collect(),
+                );
+            }
+            Expr::Field(value, index, _) => {
+                let Value::Struct(fields) = self.build_expr(*value, runtime) else {
+                    unreachable!("only structs have fields after lowering")
+                };
+                return fields.into_iter().nth(index).unwrap();
+            }
+            Expr::HashMap(key, value) => {
+                let ty = Type::HashMap(Box::new(key), Box::new(value));
+                let pointer = self.build_map(&ty);
+                self.temporaries
+                    .borrow_mut()
+                    .push(Allocation::Heap(pointer, ty.clone()));
+                return Value::Heap(pointer, ty);
+            }
+            Expr::Get(map, key) => {
+                let Type::HashMap(_, value_ty) = map.ty() else {
+                    unreachable!("only `HashMap`s are looked up after lowering")
+                };
+                let Value::Heap(pointer, ty) = self.build_expr(*map, runtime) else {
+                    unreachable!("`HashMap`s are built as heap pointers")
+                };
+                let key = self.build_expr(*key, runtime);
+                let index = self.build_find(pointer, &key);
+                let found = self.builder.build_int_compare(
+                    IntPredicate::SGE,
+                    index,
+                    i64_type.const_zero(),
+                    "",
+                );
+                let value = self.build_if(found, self.build_placeholder(&value_ty), || {
+                    let (_, value) = self.build_map_entry(pointer, &ty, index);
+                    self.build_unpack(self.builder.build_load(value, ""), &value_ty)
+                });
+                return Value::Option(found, Box::new(value));
+            }
+            Expr::Insert(map, key, value) => {
+                let Value::Heap(pointer, ty) = self.build_expr(*map, runtime) else {
+                    unreachable!("`HashMap`s are built as heap pointers")
+                };
+                let key = self.build_detached(&self.build_owned(*key, runtime));
+                let value = self.build_detached(&self.build_owned(*value, runtime));
+                let copy = self.build_map_copy(pointer, &ty);
+                let index = self.build_find(copy, &key);
+                let entry = self.build_struct(&[self.build_pack(&key), self.build_pack(&value)]);
+                let found = self.builder.build_int_compare(
+                    IntPredicate::SGE,
+                    index,
+                    i64_type.const_zero(),
+                    "",
+                );
+                self.build_if_else(
+                    found,
+                    || {
+                        self.build_free_entry(copy, &ty, index);
+                        let entry_pointer = self.build_entry(copy, &ty, index);
+                        self.builder.build_store(entry_pointer, entry);
+                    },
+                    || {
+                        let slot = self.build_stack_slot(entry.get_type());
+                        self.builder.build_store(slot, entry);
+                        let i8_ptr_type = self.context.i8_type().ptr_type(AddressSpace::default());
+                        let slot = self.builder.build_pointer_cast(slot, i8_ptr_type, "");
+                        self.build_map_call(MAP_PUSH, &[copy.into(), slot.into()]);
+                    },
+                );
+                return Value::Heap(copy, ty);
+            }
+            Expr::Remove(map, key) => {
+                let Value::Heap(pointer, ty) = self.build_expr(*map, runtime) else {
+                    unreachable!("`HashMap`s are built as heap pointers")
+                };
+                let key = self.build_expr(*key, runtime);
+                let copy = self.build_map_copy(pointer, &ty);
+                let index = self.build_find(copy, &key);
+                let found = self.builder.build_int_compare(
+                    IntPredicate::SGE,
+                    index,
+                    i64_type.const_zero(),
+                    "",
+                );
+                self.build_if_else(
+                    found,
+                    || {
+                        // The runtime finds the entry by its key, so it's only freed once removed.
+                        let allocations = self.build_entry_allocations(copy, &ty, index);
+                        self.build_map_call(MAP_REMOVE, &[copy.into(), index.into()]);
+                        for allocation in allocations {
+                            self.build_free(&allocation);
+                        }
+                    },
+                    || {},
+                );
+                return Value::Heap(copy, ty);
+            }
+            Expr::Sort(vec, key) => {
+                let Value::Heap(pointer, ty) = self.build_expr(*vec, runtime) else {
+                    unreachable!("`Vec`s are built as heap pointers")
+                };
+                let Type::Vec(item) = &ty else {
+                    unreachable!("only `Vec`s are sorted after lowering")
+                };
+                return Value::Heap(self.build_sorted(pointer, item, key, runtime), ty);
+            }
+            Expr::Debug(value) => {
+                let ty = value.ty();
+                let value = self.build_expr(*value, runtime);
+                let string_type = self.string_type();
+                let string = self.build_stack_slot(string_type);
+                self.builder.build_store(string, string_type.const_zero());
+                self.build_debug(string, &value, &ty, runtime);
+                let buffer = self.builder.build_struct_gep(string, 0, "").unwrap();
+                let buffer = self.builder.build_load(buffer, "").into_pointer_value();
+                let len = self.builder.build_struct_gep(string, 1, "").unwrap();
+                let len = self.builder.build_load(len, "").into_int_value();
+                self.temporaries
+                    .borrow_mut()
+                    .push(Allocation::Buffer(buffer));
+                return Value::Str(buffer, len);
+            }
+            Expr::Radix(value, format) => {
+                let value = self.build_int(*value, runtime);
+                let (radix, upper) = match format {
+                    Format::LowerHex => (16, false),
+                    Format::UpperHex => (16, true),
+                    Format::Octal => (8, false),
+                    Format::Binary => (2, false),
+                    Format::Display | Format::Debug => {
+                        unreachable!("only integer formats are lowered to `Radix`")
+                    }
+                };
+                let buffer_type = self.context.i8_type().array_type(FORMAT_RADIX_LEN as u32);
+                let buffer = self.builder.build_pointer_cast(
+                    self.build_stack_slot(buffer_type),
+                    self.context.i8_type().ptr_type(AddressSpace::default()),
+                    "",
+                );
+                let args = [
+                    value.into(),
+                    i64_type.const_int(radix, false).into(),
+                    i64_type.const_int(upper.into(), false).into(),
+                    buffer.into(),
+                ];
+                let len = self
+                    .builder
+                    .build_call(runtime.format_radix, &args, "")
+                    .try_as_basic_value()
+                    .left()
+                    .unwrap()
+                    .into_int_value();
+                return Value::Str(buffer, len);
+            }
+            Expr::ReadLine => READ_LINE,
+            Expr::ReadToString => READ_TO_END,
+        };
+        let buffer =
+            self.build_stack_slot(self.context.i8_type().ptr_type(AddressSpace::default()));
+        let len = self.build_runtime_call(
+            runtime,
+            runtime.read,
+            &[
+                runtime.std_in.as_pointer_value().into(),
+                i64_type.const_int(mode, false).into(),
+                buffer.into(),
+            ],
+        );
+        let buffer = self.builder.build_load(buffer, "").into_pointer_value();
+        Value::Str(buffer, len)
+    }
+
+    /// A copy of the `HashMap` at `pointer`, of type `ty`, which the statement owns.
+    fn build_map_copy(&self, pointer: PointerValue<'ctx>, ty: &Type) -> PointerValue<'ctx> {
+        let copy = self
+            .builder
+            .build_call(self.clone_glue(ty), &[pointer.into()], "")
+            .try_as_basic_value()
+            .left()
+            .unwrap()
+            .into_pointer_value();
+        self.temporaries
+            .borrow_mut()
+            .push(Allocation::Heap(copy, ty.clone()));
+        copy
+    }
+
+    /// A sorted copy of the `Vec` of `item`s at `pointer`. The items are sorted by the keys the
+    /// closure `key`, of keys of the type it holds, gives them, or by themselves without one.
+    fn build_sorted(
+        &self,
+        pointer: PointerValue<'ctx>,
+        item: &Type,
+        key: Option<(Box<Expr>, Type)>,
+        runtime: Runtime<'ctx>,
+    ) -> PointerValue<'ctx> {
+        let key_ty = key.as_ref().map_or(item, |(_, ret)| ret).clone();
+        let key = key.map(|(closure, ret)| {
+            let Type::Closure(name, type_args, captures) = closure.ty() else {
+                unreachable!("only closures give the keys to sort by after lowering")
+            };
+            let params: Vec<_> = captures
+                .iter()
+                .cloned()
+                .chain([Type::Ref(Box::new(item.clone()), false)])
+                .collect();
+            let function = match type_args.is_empty() {
+                true => self.declare_body(name, &params, &ret),
+                false => self.declare_instance(name, type_args.into(), &params, &ret),
+            };
+            let Value::Struct(captured) = self.build_owned(*closure, runtime) else {
+                unreachable!("closures are built as structs of their captures")
+            };
+            (function, captured)
+        });
+        // The runtime sorts each item's key along with its index, which breaks ties between equal
+        // keys so the sort is stable.
+        let i64_type = self.context.i64_type();
+        let entry_type = self
+            .context
+            .struct_type(&[self.llvm_type(&key_ty), i64_type.into()], false);
+        let entry_size = entry_type.size_of().unwrap();
+        let len = self.build_vec_len(pointer);
+        let entries = self.build_alloc(self.builder.build_int_mul(entry_size, len, ""));
+        let entries_type = entry_type.ptr_type(AddressSpace::default());
+        let entries = self.builder.build_pointer_cast(entries, entries_type, "");
+        self.build_counted_loop(len, |i| {
+            let value = self.build_load_element(pointer, item, i);
+            let key = match &key {
+                Some((function, captured)) => {
+                    // The key outlives the call's temporaries, which are freed each iteration.
+                    let temporaries = self.temporaries.borrow().len();
+                    let args: Vec<_> = captured
+                        .iter()
+                        .chain([&value])
+                        .map(|arg| self.build_pack(arg).into())
+                        .collect();
+                    let key = self.build_body_call(*function, &args, &key_ty, runtime);
+                    let key = self.build_detached(&key);
+                    let freed: Vec<_> =
+                        self.temporaries.borrow_mut().drain(temporaries..).collect();
+                    for temporary in &freed {
+                        self.build_free(temporary);
+                    }
+                    key
+                }
+                None => value,
+            };
+            let entry = self.build_struct(&[self.build_pack(&key), i.into()]);
+            let entry_pointer = unsafe { self.builder.build_in_bounds_gep(entries, &[i], "") };
+            self.builder.build_store(entry_pointer, entry);
+        });
+        let i8_ptr_type = self.context.i8_type().ptr_type(AddressSpace::default());
+        let entries = self.builder.build_pointer_cast(entries, i8_ptr_type, "");
+        self.builder.build_call(
+            declare_sort(&self.module),
+            &[
+                entries.into(),
+                len.into(),
+                entry_size.into(),
+                self.key_kind(&key_ty).into(),
+            ],
+            "",
+        );
+        let entries = self.builder.build_pointer_cast(entries, entries_type, "");
+        let copy = self.build_alloc(self.build_vec_size(len, item));
+        self.builder.build_store(self.build_count(copy), len);
+        self.build_counted_loop(len, |i| {
+            let entry_pointer = unsafe { self.builder.build_in_bounds_gep(entries, &[i], "") };
+            let entry = self
+                .builder
+                .build_load(entry_pointer, "")
+                .into_struct_value();
+            if key.is_some() {
+                let key = self.builder.build_extract_value(entry, 0, "").unwrap();
+                for allocation in self.build_unpack(key, &key_ty).allocations() {
+                    self.build_free(&allocation);
+                }
+            }
+            let index = self
+                .builder
+                .build_extract_value(entry, 1, "")
+                .unwrap()
+                .into_int_value();
+            let value = self.build_load_element(pointer, item, index);
+            let value = self.build_pack(&self.build_detached(&value));
+            self.builder
+                .build_store(self.build_element(copy, item, i), value);
+        });
+        let entries = self.builder.build_pointer_cast(entries, i8_ptr_type, "");
+        self.build_free(&Allocation::Buffer(entries));
+        let ty = Type::Vec(Box::new(item.clone()));
+        self.temporaries
+            .borrow_mut()
+            .push(Allocation::Heap(copy, ty));
+        copy
+    }
+
+    /// How the runtime lays out a string being built: its buffer, length and capacity.
+    fn string_type(&self) -> StructType<'ctx> {
+        let i64_type = self.context.i64_type();
+        let i8_ptr_type = self.context.i8_type().ptr_type(AddressSpace::default());
+        self.context.struct_type(
+            &[i8_ptr_type.into(), i64_type.into(), i64_type.into()],
+            false,
+        )
+    }
+
+    /// Appends `value`, of type `ty`, to the string being built at `string`, formatted like Rust's
+    /// `{:?}` formats it.
+    fn build_debug(
+        &self,
+        string: PointerValue<'ctx>,
+        value: &Value<'ctx>,
+        ty: &Type,
+        runtime: Runtime<'ctx>,
+    ) {
+        let append = |name: &str, (buffer, len): (PointerValue<'ctx>, IntValue<'ctx>)| {
+            let i8_ptr_type = self.context.i8_type().ptr_type(AddressSpace::default());
+            let string = self.builder.build_pointer_cast(string, i8_ptr_type, "");
+            self.builder.build_call(
+                declare_append(&self.module, name),
+                &[string.into(), buffer.into(), len.into()],
+                "",
+            );
+        };
+        let append_str = |s: &str| {
+            append(
+                APPEND,
+                self.build_str(Expr::Str(Symbol::intern(s)), runtime),
+            )
+        };
+        match (ty, value) {
+            (Type::Ref(ty, _), value) => self.build_debug(string, value, ty, runtime),
+            (Type::Box(inner) | Type::Rc(inner), Value::Heap(pointer, _)) => {
+                let value = self.build_load_heap(*pointer, ty);
+                self.build_debug(string, &value, inner, runtime);
+            }
+            (Type::Str, Value::Str(buffer, len)) => append(APPEND_DEBUG_STR, (*buffer, *len)),
+            (Type::Int, value) => append(APPEND, self.build_display(value.clone(), runtime)),
+            (Type::Bool, Value::Int(value)) => {
+                let is_true = self.builder.build_int_compare(
+                    IntPredicate::NE,
+                    *value,
+                    value.get_type().const_zero(),
+                    "",
+                );
+                self.build_if_else(is_true, || append_str("true"), || append_str("false"));
+            }
+            (Type::Unit, _) => append_str("()"),
+            (Type::Struct(_), value) => {
+                self.builder.build_call(
+                    self.debug_glue(ty, runtime),
+                    &[string.into(), self.build_pack(value).into()],
+                    "",
+                );
+            }
+            (Type::Option(inner), Value::Option(is_some, value)) => self.build_if_else(
+                *is_some,
+                || {
+                    append_str("Some(");
+                    self.build_debug(string, value, inner, runtime);
+                    append_str(")");
+                },
+                || append_str("None"),
+            ),
+            (Type::Result(ok_ty, error_ty), Value::Result(is_ok, ok, error)) => self.build_if_else(
+                *is_ok,
+                || {
+                    append_str("Ok(");
+                    self.build_debug(string, ok, ok_ty, runtime);
+                    append_str(")");
+                },
+                || {
+                    append_str("Err(");
+                    self.build_debug(string, error, error_ty, runtime);
+                    append_str(")");
+                },
+            ),
+            (Type::Vec(element), Value::Heap(pointer, _)) => {
+                append_str("[");
+                self.build_counted_loop(self.build_vec_len(*pointer), |i| {
+                    self.build_separator(i, &append_str);
+                    let element_value = self.build_load_element(*pointer, element, i);
+                    self.build_debug(string, &element_value, element, runtime);
+                });
+                append_str("]");
+            }
+            (Type::HashMap(key_ty, value_ty), Value::Heap(pointer, _)) => {
+                append_str("{");
+                self.build_counted_loop(self.build_vec_len(*pointer), |i| {
+                    self.build_separator(i, &append_str);
+                    let (key, value) = self.build_map_entry(*pointer, ty, i);
+                    let key = self.build_unpack(self.builder.build_load(key, ""), key_ty);
+                    self.build_debug(string, &key, key_ty, runtime);
+                    append_str(": ");
+                    let value = self.build_unpack(self.builder.build_load(value, ""), value_ty);
+                    self.build_debug(string, &value, value_ty, runtime);
+                });
+                append_str("}");
+            }
+            _ => unreachable!("lowering checked the value can be formatted with `{{:?}}`"),
+        }
+    }
+
+    /// The function appending a tuple struct of type `ty` to a string being built, formatted like
+    /// `#[derive(Debug)]` formats it, as `Name(fields)`. Built the first time it's needed, so a
+    /// struct can hold another of its type in a box.
+    fn debug_glue(&self, ty: &Type, runtime: Runtime<'ctx>) -> FunctionValue<'ctx> {
+        let symbol = self.symbol(&format!("debug.{}", ty));
+        if let Some(function) = self.module.get_function(&symbol) {
+            return function;
+        }
+        let string_ptr_type = self.string_type().ptr_type(AddressSpace::default());
+        let fn_type = self
+            .context
+            .void_type()
+            .fn_type(&[string_ptr_type.into(), self.llvm_type(ty).into()], false);
+        let function = self.module.add_function(&symbol, fn_type, None);
+        let resume = self.builder.get_insert_block();
+        self.builder
+            .position_at_end(self.context.append_basic_block(function, ""));
+        let string = function.get_nth_param(0).unwrap().into_pointer_value();
+        let Value::Struct(fields) = self.build_unpack(function.get_nth_param(1).unwrap(), ty)
+        else {
+            unreachable!("only tuple structs have debug glue")
+        };
+        let append_str = |s: &str| {
+            let (buffer, len) = self.build_str(Expr::Str(Symbol::intern(s)), runtime);
+            let i8_ptr_type = self.context.i8_type().ptr_type(AddressSpace::default());
+            let string = self.builder.build_pointer_cast(string, i8_ptr_type, "");
+            self.builder.build_call(
+                declare_append(&self.module, APPEND),
+                &[string.into(), buffer.into(), len.into()],
+                "",
+            );
+        };
+        append_str(&ty.to_string());
+        if !fields.is_empty() {
+            append_str("(");
+            for (i, (field, field_ty)) in fields.iter().zip(self.field_types(ty)).enumerate() {
+                if i > 0 {
+                    append_str(", ");
+                }
+                self.build_debug(string, field, &field_ty, runtime);
+            }
+            append_str(")");
+        }
+        self.builder.build_return(None);
+        if let Some(resume) = resume {
+            self.builder.position_at_end(resume);
+        }
+        function
+    }
+
+    /// Appends `, ` with `append_str` before every item of a list but the first, at `index`.
+    fn build_separator(&self, index: IntValue<'ctx>, append_str: &dyn Fn(&str)) {
+        let later = self.builder.build_int_compare(
+            IntPredicate::NE,
+            index,
+            index.get_type().const_zero(),
+            "",
+        );
+        self.build_if_else(later, || append_str(", "), || {});
+    }
+
+    /// Writes `Error: <error>` to stderr and exits with status 1, like returning an error from
+    /// Rust's `main` does.
+    fn build_error_exit(&self, error: Value<'ctx>, runtime: Runtime<'ctx>) {
+        let i64_type = self.context.i64_type();
+        let pieces = [
+            self.build_str(Expr::Str(Symbol::intern("Error: ")), runtime),
+            self.build_display(error, runtime),
+            self.build_str(Expr::Str(Symbol::intern("\n")), runtime),
+        ];
+        self.build_print(runtime.writer(Stream::Stderr), pieces, runtime);
+        self.build_runtime_call(
+            runtime,
+            runtime.exit,
+            &[
+                runtime.host.as_pointer_value().into(),
+                i64_type.const_int(1, false).into(),
+            ],
+        );
+        self.builder.build_unconditional_branch(runtime.abort);
+    }
+
+    /// Moves `value` into a new box or `Rc` of type `ty`, which the statement owns.
+    fn build_new(&self, value: Expr, ty: Type, runtime: Runtime<'ctx>) -> Value<'ctx> {
+        let value = self.build_owned(value, runtime);
+        let pointer = self.build_heap(&value, &ty);
+        self.temporaries
+            .borrow_mut()
+            .push(Allocation::Heap(pointer, ty.clone()));
+        Value::Heap(pointer, ty)
+    }
+
+    /// Builds `expr`, copying it if it's a read, whose buffer the next read reuses, so it can be
+    /// held onto.
+    fn build_owned(&self, expr: Expr, runtime: Runtime<'ctx>) -> Value<'ctx> {
+        let is_read = matches!(expr, Expr::ReadLine | Expr::ReadToString);
+        match self.build_expr(expr, runtime) {
+            Value::Str(buffer, len) if is_read => Value::Str(self.build_copy(buffer, len), len),
+            value => value,
+        }
+    }
+
+    /// A value of type `ty` for a `None` to hold in place of one. Values of unknown types and of
+    /// `()` never exist, so those are integers.
+    fn build_placeholder(&self, ty: &Type) -> Value<'ctx> {
+        match ty {
+            Type::Str => Value::Str(
+                self.context
+                    .i8_type()
+                    .ptr_type(AddressSpace::default())
+                    .const_null(),
+                self.context.i64_type().const_zero(),
+            ),
+            Type::Int | Type::Bool | Type::Unit | Type::Unknown => {
+                Value::Int(self.context.i64_type().const_zero())
+            }
+            Type::Ref(ty, _) => self.build_placeholder(ty),
+            Type::Box(_) | Type::Rc(_) | Type::Vec(_) | Type::HashMap(..) => Value::Heap(
+                self.context
+                    .i8_type()
+                    .ptr_type(AddressSpace::default())
+                    .const_null(),
+                ty.clone(),
+            ),
+            Type::Option(ty) => Value::Option(
+                self.context.bool_type().const_zero(),
+                Box::new(self.build_placeholder(ty)),
+            ),
+            Type::Result(ok, error) => Value::Result(
+                self.context.bool_type().const_zero(),
+                Box::new(self.build_placeholder(ok)),
+                Box::new(self.build_placeholder(error)),
+            ),
+            Type::Struct(_)
+            | Type::Closure(..)
+            | Type::Range
+            | Type::Chars
+            | Type::IntoIter(_)
+            | Type::Iter(_) => Value::Struct(
+                self.field_types(ty)
+                    .iter()
+                    .map(|field| self.build_placeholder(field))
+                    .collect(),
+            ),
+            Type::Param(_) => unreachable!("type parameters are substituted before codegen"),
+        }
+    }
+
+    /// Builds a `match` as a chain of tests, one per arm, whose bodies rejoin with the value of
+    /// the one that ran. A run of arms matching string literals is dispatched on together, by
+    /// [`Codegen::build_str_switch`].
+    fn build_match(&self, scrutinee: Expr, arms: Vec<Arm>, runtime: Runtime<'ctx>) -> Value<'ctx> {
+        let scrutinee = self.build_owned(scrutinee, runtime);
+        let done = self.context.append_basic_block(runtime.function, "");
+        let mut results = Vec::new();
+        let mut temporaries = Vec::new();
+        let mut build_arm = |arm: Arm, body: BasicBlock<'ctx>| {
+            self.builder.position_at_end(body);
+            self.bind(&arm.pattern, &scrutinee);
+            let allocated = self.temporaries.borrow().len();
+            let value = self.build_owned(arm.body, runtime);
+            temporaries.push(self.temporaries.borrow_mut().split_off(allocated));
+            results.push((value, self.builder.get_insert_block().unwrap()));
+            self.builder.build_unconditional_branch(done);
+        };
+        let is_str = |arm: &Arm| matches!(arm.pattern, Pattern::Str(_));
+        let mut arms = arms.into_iter().peekable();
+        while let Some(arm) = arms.next() {
+            if let (Pattern::Str(_), Value::Str(buffer, len)) = (&arm.pattern, &scrutinee) {
+                let group: Vec<_> = iter::once(arm)
+                    .chain(iter::from_fn(|| arms.next_if(is_str)))
+                    .collect();
+                let literals: Vec<_> = group
+                    .iter()
+                    .map(|arm| match arm.pattern {
+                        Pattern::Str(literal) => literal.as_str(),
+                        _ => unreachable!(),
+                    })
+                    .collect();
+                let (bodies, next) = self.build_str_switch(*buffer, *len, &literals, runtime);
+                for (arm, body) in group.into_iter().zip(bodies) {
+                    build_arm(arm, body);
+                }
+                self.builder.position_at_end(next);
+                continue;
+            }
+            let matched = self.build_pattern_test(&arm.pattern, &scrutinee);
+            let body = self.context.append_basic_block(runtime.function, "");
+            let next = self.context.append_basic_block(runtime.function, "");
+            self.builder.build_conditional_branch(matched, body, next);
+            build_arm(arm, body);
+            self.builder.position_at_end(next);
+        }
+        // Lowering checked that some arm matches.
+        self.builder.build_unreachable();
+        self.builder.position_at_end(done);
+
+        // Temporaries are freed at the end of the statement, so the arms that didn't allocate one
+        // pass a null pointer to free instead.
+        let null = self
+            .context
+            .i8_type()
+            .ptr_type(AddressSpace::default())
+            .const_null();
+        for (arm, allocated) in temporaries.into_iter().enumerate() {
+            for temporary in allocated {
+                let incoming = results
+                    .iter()
+                    .enumerate()
+                    .map(|(i, (_, block))| {
+                        let pointer = if i == arm { temporary.pointer() } else { null };
+                        (pointer.into(), *block)
+                    })
+                    .collect();
+                let pointer = self.build_phi(incoming).into_pointer_value();
+                self.temporaries
+                    .borrow_mut()
+                    .push(temporary.with_pointer(pointer));
+            }
+        }
+        self.build_merge(results)
+    }
+
+    /// Branches on the string `buffer` of length `len` to the first of `literals` it equals:
+    /// switching on its length, then comparing its bytes to those of each literal of that length
+    /// in turn. Returns the block branched to for each literal, and the one for none of them.
+    fn build_str_switch(
+        &self,
+        buffer: PointerValue<'ctx>,
+        len: IntValue<'ctx>,
+        literals: &[&str],
+        runtime: Runtime<'ctx>,
+    ) -> (Vec<BasicBlock<'ctx>>, BasicBlock<'ctx>) {
+        let none = self.context.append_basic_block(runtime.function, "");
+        let bodies: Vec<_> = literals
+            .iter()
+            .map(|_| self.context.append_basic_block(runtime.function, ""))
+            .collect();
+        let lens: BTreeSet<_> = literals.iter().map(|literal| literal.len()).collect();
+        let cases: Vec<_> = lens
+            .iter()
+            .map(|len| {
+                let len = self.context.i64_type().const_int(*len as u64, false);
+                (len, self.context.append_basic_block(runtime.function, ""))
+            })
+            .collect();
+        self.builder.build_switch(len, none, &cases);
+        for (len, (_, case)) in lens.into_iter().zip(cases) {
+            self.builder.position_at_end(case);
+            for (literal, body) in literals.iter().zip(&bodies) {
+                if literal.len() == len {
+                    let next = self.context.append_basic_block(runtime.function, "");
+                    let equal = self.build_bytes_eq(buffer, literal);
+                    self.builder.build_conditional_branch(equal, *body, next);
+                    self.builder.position_at_end(next);
+                }
+            }
+            self.builder.build_unconditional_branch(none);
+        }
+        (bodies, none)
+    }
+
+    /// Whether the string `buffer` of length `len` is `literal`. Its bytes are only compared if
+    /// its length matches.
+    fn build_str_eq(
+        &self,
+        buffer: PointerValue<'ctx>,
+        len: IntValue<'ctx>,
+        literal: &str,
+    ) -> IntValue<'ctx> {
+        let expected_len = self
+            .context
+            .i64_type()
+            .const_int(literal.len() as u64, false);
+        let same_len = self
+            .builder
+            .build_int_compare(IntPredicate::EQ, len, expected_len, "");
+        if literal.is_empty() {
+            return same_len;
+        }
+        let entry = self.builder.get_insert_block().unwrap();
+        let function = entry.get_parent().unwrap();
+        let compare = self.context.append_basic_block(function, "");
+        let done = self.context.append_basic_block(function, "");
+        self.builder
+            .build_conditional_branch(same_len, compare, done);
+        self.builder.position_at_end(compare);
+        let equal = self.build_bytes_eq(buffer, literal);
+        self.builder.build_unconditional_branch(done);
+        self.builder.position_at_end(done);
+        self.build_phi(vec![(same_len.into(), entry), (equal.into(), compare)])
+            .into_int_value()
+    }
+
+    /// Whether the first `literal.len()` bytes at `buffer` are those of `literal`.
+    fn build_bytes_eq(&self, buffer: PointerValue<'ctx>, literal: &str) -> IntValue<'ctx> {
+        if literal.is_empty() {
+            return self.context.bool_type().const_all_ones();
+        }
+        let expected = self.const_str(literal);
+        let len = self
+            .context
+            .i64_type()
+            .const_int(literal.len() as u64, false);
+        let ordering = self
+            .builder
+            .build_call(
+                declare_memcmp(&self.module),
+                &[buffer.into(), expected.into(), len.into()],
+                "",
+            )
+            .try_as_basic_value()
+            .left()
+            .unwrap()
+            .into_int_value();
+        self.builder.build_int_compare(
+            IntPredicate::EQ,
+            ordering,
+            ordering.get_type().const_zero(),
+            "",
+        )
+    }
+
+    /// Whether `value` matches `pattern`.
+    fn build_pattern_test(&self, pattern: &Pattern, value: &Value<'ctx>) -> IntValue<'ctx> {
+        match (pattern, value) {
+            (Pattern::Wildcard | Pattern::Bind(_), _) => self.context.bool_type().const_all_ones(),
+            (Pattern::Str(literal), Value::Str(buffer, len)) => {
+                self.build_str_eq(*buffer, *len, literal.as_str())
+            }
+            (Pattern::None, Value::Option(is_some, _)) => self.builder.build_not(*is_some, ""),
+            (Pattern::Bool(expected), Value::Int(value)) => self.builder.build_int_compare(
+                IntPredicate::EQ,
+                *value,
+                value.get_type().const_int((*expected).into(), false),
+                "",
+            ),
+            (Pattern::Some(field), Value::Option(is_some, value)) => {
+                let field = self.build_pattern_test(field, value);
+                self.builder.build_and(*is_some, field, "")
+            }
+            (Pattern::Ok(field), Value::Result(is_ok, ok, _)) => {
+                let field = self.build_pattern_test(field, ok);
+                self.builder.build_and(*is_ok, field, "")
+            }
+            (Pattern::Err(field), Value::Result(is_ok, _, error)) => {
+                let field = self.build_pattern_test(field, error);
+                let is_err = self.builder.build_not(*is_ok, "");
+                self.builder.build_and(is_err, field, "")
+            }
+            (Pattern::Struct(fields), Value::Struct(values)) => fields.iter().zip(values).fold(
+                self.context.bool_type().const_all_ones(),
+                |matched, (field, value)| {
+                    let field = self.build_pattern_test(field, value);
+                    self.builder.build_and(matched, field, "")
+                },
+            ),
+            _ => unreachable!("patterns match their scrutinee's type after lowering"),
+        }
+    }
+
+    /// Binds the locals in `pattern`, which `value` matched.
+    fn bind(&self, pattern: &Pattern, value: &Value<'ctx>) {
+        match (pattern, value) {
+            (Pattern::Bind(local), value) => {
+                self.locals.borrow_mut().insert(*local, value.clone());
+            }
+            (Pattern::Some(field), Value::Option(_, value))
+            | (Pattern::Ok(field), Value::Result(_, value, _))
+            | (Pattern::Err(field), Value::Result(_, _, value)) => self.bind(field, value),
+            (Pattern::Struct(fields), Value::Struct(values)) => {
+                for (field, value) in fields.iter().zip(values) {
+                    self.bind(field, value);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Joins the values flowing in from each block, which all have the same type.
+    fn build_merge(&self, incoming: Vec<(Value<'ctx>, BasicBlock<'ctx>)>) -> Value<'ctx> {
+        match incoming[0].0 {
+            Value::Str(..) => {
+                let (buffers, lens) = incoming
+                    .into_iter()
+                    .map(|(value, block)| match value {
+                        Value::Str(buffer, len) => ((buffer.into(), block), (len.into(), block)),
+                        _ => unreachable!(),
+                    })
+                    .unzip();
+                Value::Str(
+                    self.build_phi(buffers).into_pointer_value(),
+                    self.build_phi(lens).into_int_value(),
+                )
+            }
+            Value::Int(_) => Value::Int(
+                self.build_phi(
+                    incoming
+                        .into_iter()
+                        .map(|(value, block)| match value {
+                            Value::Int(value) => (value.into(), block),
+                            _ => unreachable!(),
+                        })
+                        .collect(),
+                )
+                .into_int_value(),
+            ),
+            Value::Option(..) => {
+                let (flags, values) = incoming
+                    .into_iter()
+                    .map(|(value, block)| match value {
+                        Value::Option(is_some, value) => ((is_some.into(), block), (*value, block)),
+                        _ => unreachable!(),
+                    })
+                    .unzip();
+                Value::Option(
+                    self.build_phi(flags).into_int_value(),
+                    Box::new(self.build_merge(values)),
+                )
+            }
+            Value::Result(..) => {
+                let mut flags = Vec::new();
+                let mut oks = Vec::new();
+                let mut errors = Vec::new();
+                for (value, block) in incoming {
+                    let Value::Result(is_ok, ok, error) = value else {
+                        unreachable!()
+                    };
+                    flags.push((is_ok.into(), block));
+                    oks.push((*ok, block));
+                    errors.push((*error, block));
+                }
+                Value::Result(
+                    self.build_phi(flags).into_int_value(),
+                    Box::new(self.build_merge(oks)),
+                    Box::new(self.build_merge(errors)),
+                )
+            }
+            Value::Heap(_, ref ty) => {
+                let ty = ty.clone();
+                let pointers = incoming
+                    .into_iter()
+                    .map(|(value, block)| match value {
+                        Value::Heap(pointer, _) => (pointer.into(), block),
+                        _ => unreachable!(),
+                    })
+                    .collect();
+                Value::Heap(self.build_phi(pointers).into_pointer_value(), ty)
+            }
+            Value::Struct(_) => {
+                let mut fields: Vec<Vec<_>> = Vec::new();
+                for (value, block) in incoming {
+                    let Value::Struct(values) = value else {
+                        unreachable!()
+                    };
+                    fields.resize_with(values.len(), Vec::new);
+                    for (field, value) in fields.iter_mut().zip(values) {
+                        field.push((value, block));
+                    }
+                }
+                Value::Struct(
+                    fields
+                        .into_iter()
+                        .map(|field| self.build_merge(field))
+                        .collect(),
+                )
+            }
+        }
+    }
+
+    fn build_phi(
+        &self,
+        incoming: Vec<(BasicValueEnum<'ctx>, BasicBlock<'ctx>)>,
+    ) -> BasicValueEnum<'ctx> {
+        let phi = self.builder.build_phi(incoming[0].0.get_type(), "");
+        for (value, block) in &incoming {
+            phi.add_incoming(&[(value, *block)]);
+        }
+        phi.as_basic_value()
+    }
+
+    fn build_int(&self, expr: Expr, runtime: Runtime<'ctx>) -> IntValue<'ctx> {
+        match self.build_expr(expr, runtime) {
+            Value::Int(value) => value,
+            _ => unreachable!("arithmetic operands are integers after lowering"),
+        }
+    }
+
+    /// Builds `lhs op rhs`, panicking like Rust does in debug builds on overflow and division by
+    /// zero.
+    fn build_arithmetic(
+        &self,
+        op: BinOp,
+        lhs: IntValue<'ctx>,
+        rhs: IntValue<'ctx>,
+        runtime: Runtime<'ctx>,
+    ) -> IntValue<'ctx> {
+        let (intrinsic, message) = match op {
+            BinOp::Add => (
+                "llvm.sadd.with.overflow.i64",
+                "attempt to add with overflow",
+            ),
+            BinOp::Sub => (
+                "llvm.ssub.with.overflow.i64",
+                "attempt to subtract with overflow",
+            ),
+            BinOp::Mul => (
+                "llvm.smul.with.overflow.i64",
+                "attempt to multiply with overflow",
+            ),
+            BinOp::Div => {
+                self.build_division_checks(
+                    lhs,
+                    rhs,
+                    "attempt to divide by zero",
+                    "attempt to divide with overflow",
+                    runtime,
+                );
+                return self.builder.build_int_signed_div(lhs, rhs, "");
+            }
+            BinOp::Rem => {
+                self.build_division_checks(
+                    lhs,
+                    rhs,
+                    "attempt to calculate the remainder with a divisor of zero",
+                    "attempt to calculate the remainder with overflow",
+                    runtime,
+                );
+                return self.builder.build_int_signed_rem(lhs, rhs, "");
+            }
+        };
+        self.build_overflowing(intrinsic, lhs, rhs, message, runtime)
+    }
+
+    /// Calls one of LLVM's `*.with.overflow` intrinsics, panicking with `message` if the result
+    /// overflowed.
+    fn build_overflowing(
+        &self,
+        intrinsic: &str,
+        lhs: IntValue<'ctx>,
+        rhs: IntValue<'ctx>,
+        message: &str,
+        runtime: Runtime<'ctx>,
+    ) -> IntValue<'ctx> {
+        let i64_type = self.context.i64_type();
+        let intrinsic = self.module.get_function(intrinsic).unwrap_or_else(|| {
+            let result_type = self
+                .context
+                .struct_type(&[i64_type.into(), self.context.bool_type().into()], false);
+            self.module.add_function(
+                intrinsic,
+                result_type.fn_type(&[i64_type.into(), i64_type.into()], false),
+                None,
+            )
+        });
+        let result = self
+            .builder
+            .build_call(intrinsic, &[lhs.into(), rhs.into()], "")
+            .try_as_basic_value()
+            .left()
+            .unwrap()
+            .into_struct_value();
+        let overflowed = self
+            .builder
+            .build_extract_value(result, 1, "")
+            .unwrap()
+            .into_int_value();
+        self.build_panic_if(overflowed, message, runtime);
+        self.builder
+            .build_extract_value(result, 0, "")
+            .unwrap()
+            .into_int_value()
+    }
+
+    /// Panics if `rhs` is zero, or if dividing `i64::MIN` by -1 would overflow.
+    fn build_division_checks(
+        &self,
+        lhs: IntValue<'ctx>,
+        rhs: IntValue<'ctx>,
+        by_zero: &str,
+        overflow: &str,
+        runtime: Runtime<'ctx>,
+    ) {
+        let i64_type = self.context.i64_type();
+        let is_zero =
+            self.builder
+                .build_int_compare(IntPredicate::EQ, rhs, i64_type.const_zero(), "");
+        self.build_panic_if(is_zero, by_zero, runtime);
+        let is_min = self.builder.build_int_compare(
+            IntPredicate::EQ,
+            lhs,
+            i64_type.const_int(i64::MIN as u64, true),
+            "",
+        );
+        let is_minus_one =
+            self.builder
+                .build_int_compare(IntPredicate::EQ, rhs, i64_type.const_all_ones(), "");
+        let overflowed = self.builder.build_and(is_min, is_minus_one, "");
+        self.build_panic_if(overflowed, overflow, runtime);
+    }
+
+    /// Calls a runtime function that looks up the string `key` names on the host, or otherwise
+    /// computes one from it, like `sculpt_rt_read_file`.
+    fn build_host_str(
+        &self,
+        function: FunctionValue<'ctx>,
+        key: Expr,
+        runtime: Runtime<'ctx>,
+    ) -> Value<'ctx> {
+        let (key, len) = self.build_str(key, runtime);
+        let value = self.build_stack_slot(self.context.i8_type().ptr_type(AddressSpace::default()));
+        let len = self.build_runtime_call(
+            runtime,
+            function,
+            &[
+                runtime.host.as_pointer_value().into(),
+                key.into(),
+                len.into(),
+                value.into(),
+            ],
+        );
+        let value = self.builder.build_load(value, "").into_pointer_value();
+        // The runtime reuses its buffer for the next lookup.
+        Value::Str(self.build_copy(value, len), len)
+    }
+
+    /// Looks up the environment variable `name`, which `sculpt_rt_getenv` finds no value of, a
+    /// null pointer, if it's unset.
+    fn build_getenv(&self, name: Expr, runtime: Runtime<'ctx>) -> Value<'ctx> {
+        let (name, len) = self.build_str(name, runtime);
+        let value = self.build_stack_slot(self.context.i8_type().ptr_type(AddressSpace::default()));
+        let len = self.build_runtime_call(
+            runtime,
+            runtime.getenv,
+            &[
+                runtime.host.as_pointer_value().into(),
+                name.into(),
+                len.into(),
+                value.into(),
+            ],
+        );
+        let value = self.builder.build_load(value, "").into_pointer_value();
+        let is_some = self.builder.build_is_not_null(value, "");
+        // The runtime reuses its buffer for the next lookup. An unset variable's copy is empty.
+        Value::Option(
+            is_some,
+            Box::new(Value::Str(self.build_copy(value, len), len)),
+        )
+    }
+
+    /// Panics with `message` if `condition` holds, aborting the current function.
+    fn build_panic_if(&self, condition: IntValue<'ctx>, message: &str, runtime: Runtime<'ctx>) {
+        self.build_panic_at_if(condition, message, None, runtime)
+    }
+
+    /// Like [`Codegen::build_panic_if`], reporting the panic at `span` of the source if given.
+    fn build_panic_at_if(
+        &self,
+        condition: IntValue<'ctx>,
+        message: &str,
+        span: Option<Range<usize>>,
+        runtime: Runtime<'ctx>,
+    ) {
+        let panic = self.context.append_basic_block(runtime.function, "panic");
+        let next = self.context.append_basic_block(runtime.function, "");
+        self.builder
+            .build_conditional_branch(condition, panic, next);
+        self.builder.position_at_end(panic);
+        let buffer = self.const_str(message);
+        let len = self
+            .context
+            .i64_type()
+            .const_int(message.len().try_into().unwrap(), false);
+        match span {
+            Some(span) => {
+                let i64_type = self.context.i64_type();
+                let start = i64_type.const_int(span.start.try_into().unwrap(), false);
+                let end = i64_type.const_int(span.end.try_into().unwrap(), false);
+                self.builder.build_call(
+                    runtime.panic_at,
+                    &[buffer.into(), len.into(), start.into(), end.into()],
+                    "",
+                );
+            }
+            None => {
+                self.builder
+                    .build_call(runtime.panic, &[buffer.into(), len.into()], "");
+            }
+        }
+        self.builder.build_unconditional_branch(runtime.abort);
+        self.builder.position_at_end(next);
+    }
+
+    /// Calls a runtime function, returning from the current function if it reports a failure
+    /// with a negative result.
+    fn build_runtime_call(
+        &self,
+        runtime: Runtime<'ctx>,
+        function: FunctionValue<'ctx>,
+        args: &[BasicMetadataValueEnum<'ctx>],
+    ) -> IntValue<'ctx> {
+        let result = self
+            .builder
+            .build_call(function, args, "")
+            .try_as_basic_value()
+            .left()
+            .unwrap()
+            .into_int_value();
+        let failed = self.builder.build_int_compare(
+            IntPredicate::SLT,
+            result,
+            result.get_type().const_zero(),
+            "",
+        );
+        let next = self.context.append_basic_block(runtime.function, "");
+        self.builder
+            .build_conditional_branch(failed, runtime.abort, next);
+        self.builder.position_at_end(next);
+        result
+    }
+
+    /// Defines `export`, a C function that calls the program's function `name`, for a library to
+    /// expose it under.
+    pub fn build_export(&self, name: &str, export: &str) {
+        let function = self.module.get_function(&self.symbol(name)).unwrap();
+        let export =
+            self.module
+                .add_function(export, self.context.void_type().fn_type(&[], false), None);
+        self.builder
+            .position_at_end(self.context.append_basic_block(export, ""));
+        self.builder.build_call(function, &[], "");
+        self.builder.build_return(None);
+    }
+
+    /// Defines `export`, a C function that calls the body of the program's function `name`, which
+    /// takes `params` and returns `ret`, each an `i64`, a `bool` or a string, or `()` for `ret`.
+    /// Strings are passed as a pointer to their bytes followed by their length, and `bool`s as C
+    /// `bool`s. What it returns is zero if the program aborts.
+    pub fn build_c_export(&self, name: Symbol, export: &str, params: &[Type], ret: &Type) {
+        let body = self.declare_body(name, params, ret);
+        let i64_type = self.context.i64_type();
+        let bool_type = self.context.bool_type();
+        let c_params: Vec<BasicMetadataTypeEnum> = params
+            .iter()
+            .flat_map(|ty| match ty {
+                Type::Str => vec![
+                    self.context
+                        .i8_type()
+                        .ptr_type(AddressSpace::default())
+                        .into(),
+                    i64_type.into(),
+                ],
+                Type::Bool => vec![bool_type.into()],
+                _ => vec![i64_type.into()],
+            })
+            .collect();
+        let fn_type = match ret {
+            Type::Unit => self.context.void_type().fn_type(&c_params, false),
+            Type::Bool => bool_type.fn_type(&c_params, false),
+            _ => i64_type.fn_type(&c_params, false),
+        };
+        let export = self.module.add_function(export, fn_type, None);
+        self.builder
+            .position_at_end(self.context.append_basic_block(export, ""));
+        let mut c_args = export.get_param_iter();
+        let args: Vec<BasicMetadataValueEnum> = params
+            .iter()
+            .map(|ty| match ty {
+                Type::Str => {
+                    let str_type = self.llvm_type(ty).into_struct_type();
+                    let (ptr, len) = (c_args.next().unwrap(), c_args.next().unwrap());
+                    let value = self
+                        .builder
+                        .build_insert_value(str_type.get_undef(), ptr, 0, "")
+                        .unwrap();
+                    let value = self.builder.build_insert_value(value, len, 1, "").unwrap();
+                    value.into_struct_value().into()
+                }
+                Type::Bool => {
+                    let value = c_args.next().unwrap().into_int_value();
+                    self.builder.build_int_z_extend(value, i64_type, "").into()
+                }
+                _ => c_args.next().unwrap().into(),
+            })
+            .collect();
+        let returned = self
+            .builder
+            .build_call(body, &args, "")
+            .try_as_basic_value()
+            .left()
+            .unwrap()
+            .into_struct_value();
+        // An aborted body returns zero.
+        let value = self
+            .builder
+            .build_extract_value(returned, 1, "")
+            .unwrap()
+            .into_int_value();
+        match ret {
+            Type::Unit => self.builder.build_return(None),
+            Type::Bool => {
+                let value = self.builder.build_int_truncate(value, bool_type, "");
+                self.builder.build_return(Some(&value))
+            }
+            _ => self.builder.build_return(Some(&value)),
+        };
+    }
+
+    /// Defines the C `main` that a standalone executable starts in, which runs the program's
+    /// function `entry` and exits with the runtime's status. Only meaningful with [`Abi::Native`],
+    /// once `entry` has been built.
+    pub fn build_entry_point(&self, entry: &str) {
+        let Some(main) = self.module.get_function(&self.symbol(entry)) else {
+            return;
+        };
+        let i32_type = self.context.i32_type();
+        let entry_point = self
+            .module
+            .add_function("main", i32_type.fn_type(&[], false), None);
+        self.builder
+            .position_at_end(self.context.append_basic_block(entry_point, ""));
+        self.builder.build_call(main, &[], "");
+        let status = self
+            .module
+            .get_global(STATUS)
+            .unwrap_or_else(|| self.module.add_global(i32_type, None, STATUS));
+        let status = self.builder.build_load(status.as_pointer_value(), "");
+        self.builder.build_return(Some(&status));
+    }
+}
+
+/// Runtime functions and streams a function body calls into, along with the block to branch to
+/// when one of them fails.
+#[derive(Clone, Copy)]
+struct Runtime<'ctx> {
+    function: FunctionValue<'ctx>,
+    abort: BasicBlock<'ctx>,
+    write: FunctionValue<'ctx>,
+    read: FunctionValue<'ctx>,
+    format_i64: FunctionValue<'ctx>,
+    format_radix: FunctionValue<'ctx>,
+    panic: FunctionValue<'ctx>,
+    panic_at: FunctionValue<'ctx>,
+    getenv: FunctionValue<'ctx>,
+    random_int: FunctionValue<'ctx>,
+    now_millis: FunctionValue<'ctx>,
+    sleep_millis: FunctionValue<'ctx>,
+    check_slice: FunctionValue<'ctx>,
+    read_file: FunctionValue<'ctx>,
+    to_uppercase: FunctionValue<'ctx>,
+    to_lowercase: FunctionValue<'ctx>,
+    trim: FunctionValue<'ctx>,
+    contains: FunctionValue<'ctx>,
+    write_file: FunctionValue<'ctx>,
+    exit: FunctionValue<'ctx>,
+    refuel: FunctionValue<'ctx>,
+    fuel: GlobalValue<'ctx>,
+    host: GlobalValue<'ctx>,
+    std_in: GlobalValue<'ctx>,
+    std_out: GlobalValue<'ctx>,
+    std_err: GlobalValue<'ctx>,
+}
+
+impl<'ctx> Runtime<'ctx> {
+    /// The global of the stream `stream` is written through.
+    fn writer(&self, stream: Stream) -> GlobalValue<'ctx> {
+        match stream {
+            Stream::Stdout => self.std_out,
+            Stream::Stderr => self.std_err,
+        }
+    }
+}
+
+/// A built expression.
+#[derive(Clone)]
+enum Value<'ctx> {
+    /// A pointer to the string's bytes and their length.
+    Str(PointerValue<'ctx>, IntValue<'ctx>),
+    Int(IntValue<'ctx>),
+    /// Whether it's `Some`, and the value it holds, which is a placeholder if it's `None`.
+    Option(IntValue<'ctx>, Box<Value<'ctx>>),
+    /// Whether it's `Ok`, the value it holds if so and the error it holds if not. The one it
+    /// doesn't hold is a placeholder.
+    Result(IntValue<'ctx>, Box<Value<'ctx>>, Box<Value<'ctx>>),
+    /// The fields of a tuple struct.
+    Struct(Vec<Value<'ctx>>),
+    /// A pointer to a box or an `Rc` of the type, whose value is loaded when it's read. A null
+    /// pointer if it's a placeholder.
+    Heap(PointerValue<'ctx>, Type),
+}
+
+/// Memory a value owns.
+#[derive(Clone)]
+enum Allocation<'ctx> {
+    /// A string's buffer.
+    Buffer(PointerValue<'ctx>),
+    /// A box or a clone of an `Rc` of the type, which owns the allocations of the value it holds
+    /// along with its other clones.
+    Heap(PointerValue<'ctx>, Type),
+}
+
+impl<'ctx> Allocation<'ctx> {
+    fn pointer(&self) -> PointerValue<'ctx> {
+        match self {
+            Allocation::Buffer(pointer) | Allocation::Heap(pointer, _) => *pointer,
+        }
+    }
+
+    /// The same kind of allocation at `pointer`.
+    fn with_pointer(&self, pointer: PointerValue<'ctx>) -> Self {
+        match self {
+            Allocation::Buffer(_) => Allocation::Buffer(pointer),
+            Allocation::Heap(_, ty) => Allocation::Heap(pointer, ty.clone()),
+        }
+    }
+}
+
+impl Value<'_> {
+    /// Whether the value is laid out like one of type `ty`.
+    fn has_type(&self, ty: &Type) -> bool {
+        match (self, ty) {
+            (Value::Str(..), Type::Str)
+            | (Value::Int(_), Type::Int | Type::Bool | Type::Unit | Type::Unknown) => true,
+            (Value::Option(_, value), Type::Option(ty)) => value.has_type(ty),
+            (Value::Result(_, ok, error), Type::Result(ok_ty, error_ty)) => {
+                ok.has_type(ok_ty) && error.has_type(error_ty)
+            }
+            (
+                Value::Struct(_),
+                Type::Struct(_)
+                | Type::Closure(..)
+                | Type::Range
+                | Type::Chars
+                | Type::IntoIter(_)
+                | Type::Iter(_),
+            ) => true,
+            (value, Type::Ref(ty, _)) => value.has_type(ty),
+            (Value::Heap(..), Type::Box(_) | Type::Rc(_) | Type::Vec(_) | Type::HashMap(..)) => {
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+impl<'ctx> Value<'ctx> {
+    /// Buffers of the strings the value holds, and its boxes and `Rc`s, placeholders included.
+    /// What the boxes and `Rc`s hold is theirs.
+    fn allocations(&self) -> Vec<Allocation<'ctx>> {
+        match self {
+            Value::Str(buffer, _) => vec![Allocation::Buffer(*buffer)],
+            Value::Int(_) => Vec::new(),
+            Value::Option(_, value) => value.allocations(),
+            Value::Result(_, ok, error) => [ok.allocations(), error.allocations()].concat(),
+            Value::Struct(fields) => fields.iter().flat_map(Value::allocations).collect(),
+            Value::Heap(pointer, ty) => vec![Allocation::Heap(*pointer, ty.clone())],
+        }
+    }
+}
+
+/// Runtime symbols, as declared in `runtime/sculpt_rt.h`.
+const WRITE: &str = "sculpt_rt_write";
+const READ: &str = "sculpt_rt_read";
+const STDIN: &str = "sculpt_rt_stdin";
+const STDOUT: &str = "sculpt_rt_stdout";
+const STDERR: &str = "sculpt_rt_stderr";
+const STATUS: &str = "sculpt_rt_status";
+const FORMAT_I64: &str = "sculpt_rt_format_i64";
+const FORMAT_RADIX: &str = "sculpt_rt_format_radix";
+const PANIC: &str = "sculpt_rt_panic";
+const PANIC_AT: &str = "sculpt_rt_panic_at";
+const ALLOC: &str = "sculpt_rt_alloc";
+const FREE: &str = "sculpt_rt_free";
+const GETENV: &str = "sculpt_rt_getenv";
+const RANDOM_INT: &str = "sculpt_rt_random_int";
+const NOW_MILLIS: &str = "sculpt_rt_now_millis";
+const SLEEP_MILLIS: &str = "sculpt_rt_sleep_millis";
+const CHECK_SLICE: &str = "sculpt_rt_check_slice";
+const READ_FILE: &str = "sculpt_rt_read_file";
+const TO_UPPERCASE: &str = "sculpt_rt_to_uppercase";
+const TO_LOWERCASE: &str = "sculpt_rt_to_lowercase";
+const TRIM: &str = "sculpt_rt_trim";
+const CONTAINS: &str = "sculpt_rt_contains";
+/// The C library's, which the JIT and the linker both find without sculpt-rt.
+const MEMCMP: &str = "memcmp";
+const WRITE_FILE: &str = "sculpt_rt_write_file";
+const EXIT: &str = "sculpt_rt_exit";
+const APPEND: &str = "sculpt_rt_append";
+const APPEND_DEBUG_STR: &str = "sculpt_rt_append_debug_str";
+const MAP_NEW: &str = "sculpt_rt_map_new";
+const MAP_ENTRY: &str = "sculpt_rt_map_entry";
+const MAP_FIND: &str = "sculpt_rt_map_find";
+const MAP_PUSH: &str = "sculpt_rt_map_push";
+const MAP_REMOVE: &str = "sculpt_rt_map_remove";
+const MAP_CLONE: &str = "sculpt_rt_map_clone";
+const MAP_FREE: &str = "sculpt_rt_map_free";
+const SORT: &str = "sculpt_rt_sort";
+const HOST: &str = "sculpt_rt_host";
+const FUEL: &str = "sculpt_rt_fuel";
+const REFUEL: &str = "sculpt_rt_refuel";
+
+/// Bytes `sculpt_rt_format_i64` needs, enough for `i64::MIN`.
+const FORMAT_I64_LEN: u64 = 20;
+
+/// Bytes `sculpt_rt_format_radix` needs, enough for any value in binary.
+const FORMAT_RADIX_LEN: u64 = 64;
+
+/// `sculpt_rt_map_new` and `sculpt_rt_sort` key kinds.
+const KEY_INT: u64 = 0;
+const KEY_STR: u64 = 1;
+
+/// `sculpt_rt_read` modes.
+const READ_LINE: u64 = 0;
+const READ_TO_END: u64 = 1;
+
+/// Owns the LLVM state needed to lower and JIT a single program.
+pub struct Compiler<'ctx> {
+    codegen: Codegen<'ctx>,
+    execution_engine: ExecutionEngine<'ctx>,
+    shared_engine: bool,
+    profiler: &'ctx Profiler,
+}
+
+impl<'ctx> Compiler<'ctx> {
+    pub fn new(
+        context: &'ctx Context,
+        opt_level: OptimizationLevel,
+        profiler: &'ctx Profiler,
+    ) -> Self {
+        let codegen = Codegen::new(context, String::new());
+        let execution_engine = codegen
+            .module
+            .create_jit_execution_engine(opt_level)
+            .unwrap();
+        Self {
+            codegen,
+            execution_engine,
+            shared_engine: false,
+            profiler,
+        }
+    }
+
+    /// Creates a compiler whose program is added to an existing execution engine once built.
+    /// Symbols are prefixed with `prefix` so they don't collide with other programs in the engine.
+    pub fn with_engine(
+        context: &'ctx Context,
+        execution_engine: &ExecutionEngine<'ctx>,
+        prefix: String,
+        profiler: &'ctx Profiler,
+    ) -> Self {
+        Self {
+            codegen: Codegen::new(context, prefix),
+            execution_engine: execution_engine.clone(),
+            shared_engine: true,
+            profiler,
+        }
+    }
+
+    pub fn build_main<'src>(
+        &self,
+        source_code: &'src str,
+        streams: &mut Streams,
+    ) -> Result<JitFunction<'ctx, unsafe extern "C" fn()>, Error<'src>> {
+        let program = self.profiler.time(Phase::Parse, || parse(source_code))?;
+        self.build_parsed_main(program, "main", streams)
+    }
+
+    /// Lowers `program` and hands the module to the execution engine, returning the function
+    /// `entry` it starts in.
+    pub fn build_parsed_main<'src>(
+        &self,
+        program: Program,
+        entry: &str,
+        streams: &mut Streams,
+    ) -> Result<JitFunction<'ctx, unsafe extern "C" fn()>, Error<'src>> {
+        let program = self.profiler.time(Phase::Lower, || hir::lower(program))?;
+        Ok(self.build_lowered_main(program, entry, streams))
+    }
+
+    /// Like [`Compiler::build_parsed_main`], for a program that's already lowered.
+    pub fn build_lowered_main(
+        &self,
+        program: hir::Program,
+        entry: &str,
+        streams: &mut Streams,
+    ) -> JitFunction<'ctx, unsafe extern "C" fn()> {
+        self.build_functions(program);
+        self.codegen.keep_reachable_from(entry);
+        self.add_to_engine();
+        link_runtime(
+            &self.codegen.module,
+            &self.codegen,
+            &self.execution_engine,
+            streams,
+        );
+        self.function(entry).unwrap()
+    }
+
+    /// Lowers every function in `program` and hands the module to the execution engine. Functions
+    /// can then be looked up by name with [`Compiler::function`].
+    pub fn build_program<'src>(
+        &self,
+        program: Program,
+        streams: &mut Streams,
+    ) -> Result<(), Error<'src>> {
+        let program = self.profiler.time(Phase::Lower, || hir::lower(program))?;
+        self.build_module(program);
+        link_runtime(
+            &self.codegen.module,
+            &self.codegen,
+            &self.execution_engine,
+            streams,
+        );
+        Ok(())
+    }
+
+    /// Lowers every function in `program` and hands the module to the execution engine.
+    pub fn build_module(&self, program: hir::Program) {
+        self.build_functions(program);
+        self.add_to_engine();
+    }
+
+    fn build_functions(
+        &self,
+        hir::Program {
+            structs,
+            statics,
+            functions,
+        }: hir::Program,
+    ) {
+        self.profiler.time(Phase::Codegen, || {
+            for s in structs {
+                self.codegen.define_struct(s);
+            }
+            for s in statics {
+                self.codegen.define_static(s);
+            }
+            for function in functions {
+                tracing::trace!(function = %function.name, "building");
+                self.codegen.build_function(function);
+            }
+            self.codegen.build_instances();
+            self.codegen.log_built(self.profiler);
+        });
+        self.profiler.time(Phase::Verify, || {
+            if let Err(e) = self.codegen.module.verify() {
+                panic!("{}", e.to_string());
+            }
+        });
+    }
+
+    /// Hands the module to a shared execution engine, which can't see it until then.
+    fn add_to_engine(&self) {
+        if self.shared_engine {
+            self.profiler.time(Phase::Jit, || {
+                self.execution_engine
+                    .add_module(&self.codegen.module)
+                    .unwrap();
+            });
+        }
+    }
+
+    pub fn function(&self, name: &str) -> Option<JitFunction<'ctx, unsafe extern "C" fn()>> {
+        self.profiler.time(Phase::Jit, || {
+            unsafe {
+                self.execution_engine
+                    .get_function(&self.codegen.symbol(name))
+            }
+            .ok()
+        })
+    }
+
+    /// Runs LLVM's standard optimization pipeline for `opt_level` over everything built so far.
+    pub fn optimize(&self, opt_level: OptimizationLevel) {
+        self.profiler.time(Phase::Optimize, || {
+            let builder = PassManagerBuilder::create();
+            builder.set_optimization_level(opt_level);
+            let passes = PassManager::create(());
+            builder.populate_module_pass_manager(&passes);
+            passes.run_on(&self.codegen.module);
+        });
+    }
+
+    /// Textual LLVM IR of everything built so far.
+    pub fn ir(&self) -> String {
+        self.codegen.ir()
+    }
+
+    /// Writes everything built so far to `path` as LLVM bitcode.
+    pub fn write_bitcode(&self, path: &Path) -> io::Result<()> {
+        if self.codegen.module.write_bitcode_to_path(path) {
+            Ok(())
+        } else {
+            Err(io::Error::other(format!(
+                "failed to write bitcode to {}",
+                path.display()
+            )))
+        }
+    }
+}
+
+impl Backend for Compiler<'_> {
+    fn lower_module(&mut self, program: hir::Program) {
+        self.build_module(program);
+    }
+
+    fn run(
+        &mut self,
+        std_in: &mut dyn BufRead,
+        std_out: &mut dyn Write,
+        std_err: &mut dyn Write,
+        options: &RunOptions,
+    ) -> io::Result<()> {
+        let mut streams = Streams::new(std_in, std_out, std_err).with_options(options.clone());
+        self.codegen.keep_reachable_from(options.entry());
+        link_runtime(
+            &self.codegen.module,
+            &self.codegen,
+            &self.execution_engine,
+            &mut streams,
+        );
+        let main = self.function(options.entry()).unwrap();
+        self.profiler
+            .time(Phase::Execute, || unsafe { call(&main) })
+    }
+
+    fn emit_object(&self, path: &Path) -> io::Result<()> {
+        write_object(&self.codegen.module, OptimizationLevel::Default, path)
+    }
+}
+
+/// Compiles `module` for the host machine and writes it to `path` as a native object file.
+pub(crate) fn write_object(
+    module: &Module,
+    opt_level: OptimizationLevel,
+    path: &Path,
+) -> io::Result<()> {
+    Target::initialize_native(&InitializationConfig::default()).map_err(io::Error::other)?;
+    let triple = TargetMachine::get_default_triple();
+    let target = Target::from_triple(&triple).map_err(|e| io::Error::other(e.to_string()))?;
+    let machine = target
+        .create_target_machine(
+            &triple,
+            &TargetMachine::get_host_cpu_name().to_string(),
+            &TargetMachine::get_host_cpu_features().to_string(),
+            opt_level,
+            RelocMode::PIC,
+            CodeModel::Default,
+        )
+        .ok_or_else(|| io::Error::other(format!("no target machine for {}", triple)))?;
+    machine
+        .write_to_file(module, FileType::Object, path)
+        .map_err(|e| io::Error::other(e.to_string()))
+}
+
+/// Runs many programs against one shared context and execution engine, so the cost of
+/// constructing the JIT is paid once rather than once per program.
+pub struct Jit<'ctx> {
+    context: &'ctx Context,
+    execution_engine: ExecutionEngine<'ctx>,
+    programs: Cell<usize>,
+    profiler: &'ctx Profiler,
+}
+
+impl<'ctx> Jit<'ctx> {
+    pub fn new(
+        context: &'ctx Context,
+        opt_level: OptimizationLevel,
+        profiler: &'ctx Profiler,
+    ) -> Self {
+        let execution_engine = context
+            .create_module("runtime")
+            .create_jit_execution_engine(opt_level)
+            .unwrap();
+        Self {
+            context,
+            execution_engine,
+            programs: Cell::new(0),
+            profiler,
+        }
+    }
+
+    pub fn run<'src>(
+        &self,
+        source_code: &'src str,
+        std_in: impl BufRead,
+        std_out: impl Write,
+        std_err: impl Write,
+        options: &RunOptions,
+    ) -> Result<(), Error<'src>> {
+        let program = self.profiler.time(Phase::Parse, || parse(source_code))?;
+        self.run_parsed(program, std_in, std_out, std_err, options)
+    }
+
+    pub fn run_parsed<'src>(
+        &self,
+        program: Program,
+        std_in: impl BufRead,
+        std_out: impl Write,
+        std_err: impl Write,
+        options: &RunOptions,
+    ) -> Result<(), Error<'src>> {
+        let program = self.profiler.time(Phase::Lower, || hir::lower(program))?;
+        self.run_lowered(program, std_in, std_out, std_err, options)
+    }
+
+    /// Like [`Jit::run_parsed`], for a program that's already lowered.
+    pub fn run_lowered<'src>(
+        &self,
+        program: hir::Program,
+        std_in: impl BufRead,
+        std_out: impl Write,
+        std_err: impl Write,
+        options: &RunOptions,
+    ) -> Result<(), Error<'src>> {
+        let id = self.programs.replace(self.programs.get() + 1);
+        let compiler = Compiler::with_engine(
+            self.context,
+            &self.execution_engine,
+            format!("program{}.", id),
+            self.profiler,
+        );
+        let mut streams = Streams::new(std_in, std_out, std_err).with_options(options.clone());
+        let main = compiler.build_lowered_main(program, options.entry(), &mut streams);
+        self.profiler
+            .time(Phase::Execute, || unsafe { call(&main) })?;
+        Ok(())
+    }
+}
+
+fn declare_write<'ctx>(module: &Module<'ctx>) -> FunctionValue<'ctx> {
+    if let Some(ext_write) = module.get_function(WRITE) {
+        return ext_write;
+    }
+
+    let context = module.get_context();
+    let i64_type = context.i64_type();
+    let i8_type = context.i8_type();
+    let box_type = i8_type.ptr_type(AddressSpace::default());
+
+    module.add_function(
+        WRITE,
+        i64_type.fn_type(
+            &[
+                box_type.ptr_type(AddressSpace::default()).into(),
+                i8_type.ptr_type(AddressSpace::default()).into(),
+                i64_type.into(),
+            ],
+            false,
+        ),
+        None,
+    )
+}
+
+fn declare_read<'ctx>(module: &Module<'ctx>) -> FunctionValue<'ctx> {
+    if let Some(ext_read) = module.get_function(READ) {
+        return ext_read;
+    }
+
+    let context = module.get_context();
+    let i64_type = context.i64_type();
+    let i8_ptr_type = context.i8_type().ptr_type(AddressSpace::default());
+
+    module.add_function(
+        READ,
+        i64_type.fn_type(
+            &[
+                i8_ptr_type.ptr_type(AddressSpace::default()).into(),
+                i64_type.into(),
+                i8_ptr_type.ptr_type(AddressSpace::default()).into(),
+            ],
+            false,
+        ),
+        None,
+    )
+}
+
+fn declare_format_i64<'ctx>(module: &Module<'ctx>) -> FunctionValue<'ctx> {
+    if let Some(ext_format_i64) = module.get_function(FORMAT_I64) {
+        return ext_format_i64;
+    }
+
+    let context = module.get_context();
+    let i64_type = context.i64_type();
+    let i8_ptr_type = context.i8_type().ptr_type(AddressSpace::default());
+
+    module.add_function(
+        FORMAT_I64,
+        i64_type.fn_type(&[i64_type.into(), i8_ptr_type.into()], false),
+        None,
+    )
+}
+
+fn declare_format_radix<'ctx>(module: &Module<'ctx>) -> FunctionValue<'ctx> {
+    if let Some(ext_format_radix) = module.get_function(FORMAT_RADIX) {
+        return ext_format_radix;
+    }
+
+    let context = module.get_context();
+    let i64_type = context.i64_type();
+    let i8_ptr_type = context.i8_type().ptr_type(AddressSpace::default());
+
+    module.add_function(
+        FORMAT_RADIX,
+        i64_type.fn_type(
+            &[
+                i64_type.into(),
+                i64_type.into(),
+                i64_type.into(),
+                i8_ptr_type.into(),
+            ],
+            false,
+        ),
+        None,
+    )
+}
+
+fn declare_panic<'ctx>(module: &Module<'ctx>) -> FunctionValue<'ctx> {
+    if let Some(ext_panic) = module.get_function(PANIC) {
+        return ext_panic;
+    }
+
+    let context = module.get_context();
+    let i8_ptr_type = context.i8_type().ptr_type(AddressSpace::default());
+
+    module.add_function(
+        PANIC,
+        context
+            .void_type()
+            .fn_type(&[i8_ptr_type.into(), context.i64_type().into()], false),
+        None,
+    )
+}
+
+fn declare_panic_at<'ctx>(module: &Module<'ctx>) -> FunctionValue<'ctx> {
+    if let Some(ext_panic_at) = module.get_function(PANIC_AT) {
+        return ext_panic_at;
+    }
+
+    let context = module.get_context();
+    let i8_ptr_type = context.i8_type().ptr_type(AddressSpace::default());
+    let i64_type = context.i64_type();
+
+    module.add_function(
+        PANIC_AT,
+        context.void_type().fn_type(
+            &[
+                i8_ptr_type.into(),
+                i64_type.into(),
+                i64_type.into(),
+                i64_type.into(),
+            ],
+            false,
+        ),
+        None,
+    )
+}
+
+fn declare_alloc<'ctx>(module: &Module<'ctx>) -> FunctionValue<'ctx> {
+    if let Some(ext_alloc) = module.get_function(ALLOC) {
+        return ext_alloc;
+    }
+
+    let context = module.get_context();
+    let i8_ptr_type = context.i8_type().ptr_type(AddressSpace::default());
+
+    module.add_function(
+        ALLOC,
+        i8_ptr_type.fn_type(&[context.i64_type().into()], false),
+        None,
+    )
+}
+
+fn declare_free<'ctx>(module: &Module<'ctx>) -> FunctionValue<'ctx> {
+    if let Some(ext_free) = module.get_function(FREE) {
+        return ext_free;
+    }
+
+    let context = module.get_context();
+    let i8_ptr_type = context.i8_type().ptr_type(AddressSpace::default());
+
+    module.add_function(
+        FREE,
+        context.void_type().fn_type(&[i8_ptr_type.into()], false),
+        None,
+    )
+}
+
+fn declare_getenv<'ctx>(module: &Module<'ctx>) -> FunctionValue<'ctx> {
+    declare_host_str(module, GETENV)
+}
+
+fn declare_read_file<'ctx>(module: &Module<'ctx>) -> FunctionValue<'ctx> {
+    declare_host_str(module, READ_FILE)
+}
+
+/// Declares a runtime function with the signature of `sculpt_rt_getenv` and
+/// `sculpt_rt_read_file`.
+fn declare_host_str<'ctx>(module: &Module<'ctx>, name: &str) -> FunctionValue<'ctx> {
+    if let Some(ext_function) = module.get_function(name) {
+        return ext_function;
+    }
+
+    let context = module.get_context();
+    let i64_type = context.i64_type();
+    let i8_ptr_type = context.i8_type().ptr_type(AddressSpace::default());
+
+    module.add_function(
+        name,
+        i64_type.fn_type(
+            &[
+                i8_ptr_type.ptr_type(AddressSpace::default()).into(),
+                i8_ptr_type.into(),
+                i64_type.into(),
+                i8_ptr_type.ptr_type(AddressSpace::default()).into(),
+            ],
+            false,
+        ),
+        None,
+    )
+}
+
+fn declare_trim<'ctx>(module: &Module<'ctx>) -> FunctionValue<'ctx> {
+    if let Some(ext_trim) = module.get_function(TRIM) {
+        return ext_trim;
+    }
+
+    let context = module.get_context();
+    let i64_type = context.i64_type();
+    let i8_ptr_type = context.i8_type().ptr_type(AddressSpace::default());
+
+    module.add_function(
+        TRIM,
+        i64_type.fn_type(
+            &[
+                i8_ptr_type.into(),
+                i64_type.into(),
+                i8_ptr_type.ptr_type(AddressSpace::default()).into(),
+            ],
+            false,
+        ),
+        None,
+    )
+}
+
+/// Declares the runtime's sort, which takes the entries to sort as an `i8*`, then how many there
+/// are, their size and the kind of key they start with.
+fn declare_sort<'ctx>(module: &Module<'ctx>) -> FunctionValue<'ctx> {
+    if let Some(ext_sort) = module.get_function(SORT) {
+        return ext_sort;
+    }
+
+    let context = module.get_context();
+    let i64_type = context.i64_type();
+    let i8_ptr_type = context.i8_type().ptr_type(AddressSpace::default());
+
+    module.add_function(
+        SORT,
+        context.void_type().fn_type(
+            &[
+                i8_ptr_type.into(),
+                i64_type.into(),
+                i64_type.into(),
+                i64_type.into(),
+            ],
+            false,
+        ),
+        None,
+    )
+}
+
+fn declare_contains<'ctx>(module: &Module<'ctx>) -> FunctionValue<'ctx> {
+    if let Some(ext_contains) = module.get_function(CONTAINS) {
+        return ext_contains;
+    }
+
+    let context = module.get_context();
+    let i64_type = context.i64_type();
+    let i8_ptr_type = context.i8_type().ptr_type(AddressSpace::default());
+
+    module.add_function(
+        CONTAINS,
+        i64_type.fn_type(
+            &[
+                i8_ptr_type.into(),
+                i64_type.into(),
+                i8_ptr_type.into(),
+                i64_type.into(),
+            ],
+            false,
+        ),
+        None,
+    )
+}
+
+fn declare_memcmp<'ctx>(module: &Module<'ctx>) -> FunctionValue<'ctx> {
+    if let Some(ext_memcmp) = module.get_function(MEMCMP) {
+        return ext_memcmp;
+    }
+
+    let context = module.get_context();
+    let i8_ptr_type = context.i8_type().ptr_type(AddressSpace::default());
+
+    module.add_function(
+        MEMCMP,
+        context.i32_type().fn_type(
+            &[
+                i8_ptr_type.into(),
+                i8_ptr_type.into(),
+                context.i64_type().into(),
+            ],
+            false,
+        ),
+        None,
+    )
+}
+
+fn declare_random_int<'ctx>(module: &Module<'ctx>) -> FunctionValue<'ctx> {
+    if let Some(ext_random_int) = module.get_function(RANDOM_INT) {
+        return ext_random_int;
+    }
+
+    let context = module.get_context();
+    let i64_type = context.i64_type();
+    let i8_ptr_type = context.i8_type().ptr_type(AddressSpace::default());
+
+    module.add_function(
+        RANDOM_INT,
+        i64_type.fn_type(
+            &[
+                i8_ptr_type.ptr_type(AddressSpace::default()).into(),
+                i64_type.into(),
+                i64_type.into(),
+            ],
+            false,
+        ),
+        None,
+    )
+}
+
+fn declare_now_millis<'ctx>(module: &Module<'ctx>) -> FunctionValue<'ctx> {
+    if let Some(ext_now_millis) = module.get_function(NOW_MILLIS) {
+        return ext_now_millis;
+    }
+
+    let context = module.get_context();
+    let i8_ptr_type = context.i8_type().ptr_type(AddressSpace::default());
+
+    module.add_function(
+        NOW_MILLIS,
+        context.i64_type().fn_type(
+            &[i8_ptr_type.ptr_type(AddressSpace::default()).into()],
+            false,
+        ),
+        None,
+    )
+}
+
+fn declare_sleep_millis<'ctx>(module: &Module<'ctx>) -> FunctionValue<'ctx> {
+    if let Some(ext_sleep_millis) = module.get_function(SLEEP_MILLIS) {
+        return ext_sleep_millis;
+    }
+
+    let context = module.get_context();
+    let i64_type = context.i64_type();
+    let i8_ptr_type = context.i8_type().ptr_type(AddressSpace::default());
+
+    module.add_function(
+        SLEEP_MILLIS,
+        i64_type.fn_type(
+            &[
+                i8_ptr_type.ptr_type(AddressSpace::default()).into(),
+                i64_type.into(),
+            ],
+            false,
+        ),
+        None,
+    )
+}
+
+fn declare_check_slice<'ctx>(module: &Module<'ctx>) -> FunctionValue<'ctx> {
+    if let Some(ext_check_slice) = module.get_function(CHECK_SLICE) {
+        return ext_check_slice;
+    }
+
+    let context = module.get_context();
+    let i64_type = context.i64_type();
+    let i8_ptr_type = context.i8_type().ptr_type(AddressSpace::default());
+
+    module.add_function(
+        CHECK_SLICE,
+        i64_type.fn_type(
+            &[
+                i8_ptr_type.into(),
+                i64_type.into(),
+                i64_type.into(),
+                i64_type.into(),
+                i64_type.into(),
+                i64_type.into(),
+            ],
+            false,
+        ),
+        None,
+    )
+}
+
+fn declare_write_file<'ctx>(module: &Module<'ctx>) -> FunctionValue<'ctx> {
+    if let Some(ext_write_file) = module.get_function(WRITE_FILE) {
+        return ext_write_file;
+    }
+
+    let context = module.get_context();
+    let i64_type = context.i64_type();
+    let i8_ptr_type = context.i8_type().ptr_type(AddressSpace::default());
+
+    module.add_function(
+        WRITE_FILE,
+        i64_type.fn_type(
+            &[
+                i8_ptr_type.ptr_type(AddressSpace::default()).into(),
+                i8_ptr_type.into(),
+                i64_type.into(),
+                i8_ptr_type.into(),
+                i64_type.into(),
+            ],
+            false,
+        ),
+        None,
+    )
+}
+
+fn declare_exit<'ctx>(module: &Module<'ctx>) -> FunctionValue<'ctx> {
+    if let Some(ext_exit) = module.get_function(EXIT) {
+        return ext_exit;
+    }
+
+    let context = module.get_context();
+    let i64_type = context.i64_type();
+    let i8_ptr_type = context.i8_type().ptr_type(AddressSpace::default());
+
+    module.add_function(
+        EXIT,
+        i64_type.fn_type(
+            &[
+                i8_ptr_type.ptr_type(AddressSpace::default()).into(),
+                i64_type.into(),
+            ],
+            false,
+        ),
+        None,
+    )
+}
+
+/// Declares `sculpt_rt_append` or `sculpt_rt_append_debug_str`, which take the string being built
+/// as an `i8*`.
+fn declare_append<'ctx>(module: &Module<'ctx>, name: &str) -> FunctionValue<'ctx> {
+    if let Some(ext_append) = module.get_function(name) {
+        return ext_append;
+    }
+
+    let context = module.get_context();
+    let i64_type = context.i64_type();
+    let i8_ptr_type = context.i8_type().ptr_type(AddressSpace::default());
+
+    module.add_function(
+        name,
+        context.void_type().fn_type(
+            &[i8_ptr_type.into(), i8_ptr_type.into(), i64_type.into()],
+            false,
+        ),
+        None,
+    )
+}
+
+/// Declares one of the runtime's `HashMap` functions, which take maps and entries as `i8*`s.
+fn declare_map<'ctx>(module: &Module<'ctx>, name: &str) -> FunctionValue<'ctx> {
+    if let Some(ext_map) = module.get_function(name) {
+        return ext_map;
+    }
+
+    let context = module.get_context();
+    let i64_type = context.i64_type();
+    let i8_ptr_type = context.i8_type().ptr_type(AddressSpace::default());
+    let void_type = context.void_type();
+
+    let fn_type = match name {
+        MAP_NEW => i8_ptr_type.fn_type(&[i64_type.into(), i64_type.into()], false),
+        MAP_ENTRY => i8_ptr_type.fn_type(&[i8_ptr_type.into(), i64_type.into()], false),
+        MAP_FIND => i64_type.fn_type(&[i8_ptr_type.into(), i8_ptr_type.into()], false),
+        MAP_PUSH => void_type.fn_type(&[i8_ptr_type.into(), i8_ptr_type.into()], false),
+        MAP_REMOVE => void_type.fn_type(&[i8_ptr_type.into(), i64_type.into()], false),
+        MAP_CLONE => i8_ptr_type.fn_type(&[i8_ptr_type.into()], false),
+        MAP_FREE => void_type.fn_type(&[i8_ptr_type.into()], false),
+        _ => unreachable!("{} isn't a map function", name),
+    };
+    module.add_function(name, fn_type, None)
+}
+
+fn declare_refuel<'ctx>(module: &Module<'ctx>) -> FunctionValue<'ctx> {
+    if let Some(ext_refuel) = module.get_function(REFUEL) {
+        return ext_refuel;
+    }
+
+    let i64_type = module.get_context().i64_type();
+    module.add_function(REFUEL, i64_type.fn_type(&[], false), None)
+}
+
+fn declare_fuel<'ctx>(module: &Module<'ctx>, name: &str) -> GlobalValue<'ctx> {
+    if let Some(ext_fuel) = module.get_global(name) {
+        return ext_fuel;
+    }
+
+    let i64_type = module.get_context().i64_type();
+    module.add_global(i64_type, None, name)
+}
+
+fn declare_stream<'ctx>(module: &Module<'ctx>, name: &str) -> GlobalValue<'ctx> {
+    if let Some(ext_stream) = module.get_global(name) {
+        return ext_stream;
+    }
+
+    let context = module.get_context();
+    let box_type = context.i8_type().ptr_type(AddressSpace::default());
+    module.add_global(box_type, None, name)
+}
+
+thread_local! {
+    /// The first I/O error a runtime function hit while the current program was running.
+    static RUNTIME_ERROR: RefCell<Option<io::Error>> = const { RefCell::new(None) };
+    /// Steps the current program may take before calling `sculpt_rt_refuel`. Its module's
+    /// `sculpt_rt_fuel` global points here, so it must run on the thread it was linked on.
+    static FUEL_LEFT: Cell<i64> = const { Cell::new(0) };
+    /// What the current program may use, and the bytes it has allocated.
+    static LIMITS: Cell<Limits> = const {
+        Cell::new(Limits {
+            fuel: None,
+            reserve: 0,
+            time_limit: None,
+            deadline: None,
+            max_heap_bytes: None,
+            heap_bytes: 0,
+        })
+    };
+}
+
+/// Steps a program with a time limit takes between checks of the clock.
+const STEPS_PER_CLOCK_CHECK: u64 = 1 << 20;
+
+/// The fuel and heap limits a program was linked with, and how much of its heap it's using.
+#[derive(Clone, Copy)]
+struct Limits {
+    fuel: Option<u64>,
+    /// Fuel not yet handed to the program. It's handed over in chunks when the program has a
+    /// time limit, so it refuels, and checks the clock, every so often.
+    reserve: u64,
+    time_limit: Option<Duration>,
+    deadline: Option<Instant>,
+    max_heap_bytes: Option<usize>,
+    heap_bytes: usize,
+}
+
+/// How much of its reserve to hand a program when it runs out of fuel: all of it, unless it has
+/// a time limit to check every so often.
+fn fuel_chunk(limits: &Limits) -> u64 {
+    let chunk = match limits.time_limit {
+        Some(_) => STEPS_PER_CLOCK_CHECK,
+        None => i64::MAX as u64,
+    };
+    limits.reserve.min(chunk)
+}
+
+/// Stores `error` for `call` to return, unless an earlier one is already stored, and empties
+/// the program's fuel, so it aborts at its next step even if the runtime function that failed
+/// can't report it.
+fn fail(error: io::Error) {
+    RUNTIME_ERROR.with(|slot| {
+        slot.borrow_mut().get_or_insert(error);
+    });
+    FUEL_LEFT.set(0);
+}
+
+/// Counts `allocated` more bytes and `freed` fewer as allocated by the program, failing if it
+/// now has more than it may.
+fn account(allocated: usize, freed: usize) {
+    let mut limits = LIMITS.get();
+    limits.heap_bytes = limits
+        .heap_bytes
+        .saturating_sub(freed)
+        .saturating_add(allocated);
+    LIMITS.set(limits);
+    if let Some(max_heap_bytes) = limits.max_heap_bytes {
+        if limits.heap_bytes > max_heap_bytes {
+            fail(io::Error::other(format!(
+                "heap limit of {} bytes exceeded",
+                max_heap_bytes
+            )));
+        }
+    }
+}
+
+/// Calls a compiled function, returning the I/O error that aborted it, if any.
+///
+/// # Safety
+///
+/// The function's runtime must be linked to streams that outlive the call.
+pub(crate) unsafe fn call(function: &JitFunction<unsafe extern "C" fn()>) -> io::Result<()> {
+    RUNTIME_ERROR.with(|slot| slot.borrow_mut().take());
+    function.call();
+    match RUNTIME_ERROR.with(|slot| slot.borrow_mut().take()) {
+        Some(error) => Err(error),
+        None => Ok(()),
+    }
+}
+
+/// Points the runtime symbols declared in `module` at their host implementations.
+pub(crate) fn link_runtime<'ctx>(
+    module: &Module<'ctx>,
+    codegen: &Codegen<'ctx>,
+    execution_engine: &ExecutionEngine<'ctx>,
+    streams: &mut Streams,
+) {
+    extern "C" fn write(os: *mut Box<dyn Write>, s: *const u8, l: u64) -> i64 {
+        guard(|| {
+            let os = unsafe { os.as_mut() }.unwrap();
+            let s = unsafe { std::slice::from_raw_parts(s, l.try_into().unwrap()) };
+            os.write_all(s)?;
+            Ok(s.len())
+        })
+    }
+
+    extern "C" fn read(input: *mut Input, mode: u64, buffer: *mut *const u8) -> i64 {
+        guard(|| {
+            let Input {
+                reader,
+                buffer: line,
+            } = unsafe { input.as_mut() }.unwrap();
+            line.clear();
+            if mode == READ_LINE {
+                reader.read_until(b'\n', line)?;
+                if line.ends_with(b"\n") {
+                    line.pop();
+                    if line.ends_with(b"\r") {
+                        line.pop();
+                    }
+                }
+            } else {
+                reader.read_to_end(line)?;
+            }
+            unsafe { *buffer = line.as_ptr() };
+            Ok(line.len())
+        })
+    }
+
+    /// The program uses the length it returns as is, so it formats nothing if it fails.
+    extern "C" fn format_i64(value: i64, buffer: *mut u8) -> i64 {
+        guarded(
+            || 0,
+            || {
+                let formatted = value.to_string();
+                let buffer = unsafe { std::slice::from_raw_parts_mut(buffer, formatted.len()) };
+                buffer.copy_from_slice(formatted.as_bytes());
+                Ok(formatted.len().try_into().unwrap())
+            },
+        )
+    }
+
+    extern "C" fn format_radix(value: i64, radix: u64, upper: u64, buffer: *mut u8) -> i64 {
+        guarded(
+            || 0,
+            || {
+                let formatted = match (radix, upper) {
+                    (16, 0) => format!("{:x}", value),
+                    (16, _) => format!("{:X}", value),
+                    (8, _) => format!("{:o}", value),
+                    _ => format!("{:b}", value),
+                };
+                let buffer = unsafe { std::slice::from_raw_parts_mut(buffer, formatted.len()) };
+                buffer.copy_from_slice(formatted.as_bytes());
+                Ok(formatted.len().try_into().unwrap())
+            },
+        )
+    }
+
+    /// Unlike sculpt-rt's, returns so the program can abort back to `call`, which reports the
+    /// panic.
+    extern "C" fn panic(message: *const u8, len: u64) {
+        guard(|| {
+            let message = unsafe { std::slice::from_raw_parts(message, len.try_into().unwrap()) };
+            Err(io::Error::other(Panic(
+                String::from_utf8_lossy(message).into_owned(),
+            )))
+        });
+    }
+
+    extern "C" fn panic_at(message: *const u8, len: u64, start: u64, end: u64) {
+        guard(|| {
+            let message = unsafe { std::slice::from_raw_parts(message, len.try_into().unwrap()) };
+            Err(io::Error::other(PanicAt(
+                start.try_into().unwrap()..end.try_into().unwrap(),
+                String::from_utf8_lossy(message).into_owned(),
+            )))
+        });
+    }
+
+    extern "C" fn getenv(host: *mut Host, name: *const u8, len: u64, value: *mut *const u8) -> i64 {
+        guard(|| {
+            let Host {
+                options, buffer, ..
+            } = unsafe { host.as_mut() }.unwrap();
+            let name = unsafe { std::slice::from_raw_parts(name, len.try_into().unwrap()) };
+            buffer.clear();
+            let found = match options.sandboxed {
+                true => None,
+                false => std::env::var_os(String::from_utf8_lossy(name).as_ref()),
+            };
+            match found {
+                Some(found) => {
+                    buffer.extend_from_slice(found.as_encoded_bytes());
+                    unsafe { *value = buffer.as_ptr() };
+                }
+                None => unsafe { *value = std::ptr::null() },
+            }
+            Ok(buffer.len())
+        })
+    }
+
+    extern "C" fn random_int(host: *mut Host, lo: i64, hi: i64) -> i64 {
+        guarded(
+            || lo,
+            || {
+                let host = unsafe { host.as_mut() }.unwrap();
+                let span = hi.wrapping_sub(lo) as u64;
+                let offset = ((u128::from(host.next_random()) * u128::from(span)) >> 64) as u64;
+                Ok(lo.wrapping_add(offset as i64))
+            },
+        )
+    }
+
+    extern "C" fn now_millis(host: *mut Host) -> i64 {
+        guard(|| {
+            let host = unsafe { host.as_mut() }.unwrap();
+            match host.options.clock {
+                Clock::Real => Ok(SystemTime::now()
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .map_err(io::Error::other)?
+                    .as_millis()
+                    .try_into()
+                    .unwrap_or(usize::MAX)),
+                Clock::Virtual => Ok(host.virtual_millis.try_into().unwrap()),
+                Clock::Denied => Err(clock_denied()),
+            }
+        })
+    }
+
+    extern "C" fn sleep_millis(host: *mut Host, millis: i64) -> i64 {
+        guard(|| {
+            let host = unsafe { host.as_mut() }.unwrap();
+            match host.options.clock {
+                Clock::Real => thread::sleep(Duration::from_millis(millis.try_into().unwrap())),
+                Clock::Virtual => host.virtual_millis = host.virtual_millis.saturating_add(millis),
+                Clock::Denied => return Err(clock_denied()),
+            }
+            Ok(0)
+        })
+    }
+
+    extern "C" fn check_slice(
+        s: *const u8,
+        len: u64,
+        begin: i64,
+        end: i64,
+        start: u64,
+        span_end: u64,
+    ) -> i64 {
+        guard(|| {
+            let s = unsafe { std::slice::from_raw_parts(s, len.try_into().unwrap()) };
+            match slice_error(s, begin, end) {
+                Some(message) => Err(io::Error::other(PanicAt(
+                    start.try_into().unwrap()..span_end.try_into().unwrap(),
+                    message,
+                ))),
+                None => Ok(0),
+            }
+        })
+    }
+
+    /// Why slicing `s[begin..end]` would panic in Rust, if it would, worded like Rust does.
+    fn slice_error(s: &[u8], begin: i64, end: i64) -> Option<String> {
+        const MAX_DISPLAY_LEN: usize = 256;
+        let is_boundary = |i: usize| s.get(i).is_none_or(|b| (*b as i8) >= -0x40);
+        let mut shown = s.len().min(MAX_DISPLAY_LEN);
+        while !is_boundary(shown) {
+            shown -= 1;
+        }
+        let ellipsis = if shown < s.len() { "[...]" } else { "" };
+        let text = String::from_utf8_lossy(&s[..shown]);
+        let (Ok(begin), Ok(end)) = (usize::try_from(begin), usize::try_from(end)) else {
+            let index = if begin < 0 { begin } else { end };
+            return Some(format!(
+                "byte index {} is out of bounds of `{}`{}",
+                index, text, ellipsis
+            ));
+        };
+        if begin > s.len() || end > s.len() {
+            let index = if begin > s.len() { begin } else { end };
+            return Some(format!(
+                "byte index {} is out of bounds of `{}`{}",
+                index, text, ellipsis
+            ));
+        }
+        if begin > end {
+            return Some(format!(
+                "begin <= end ({} <= {}) when slicing `{}`{}",
+                begin, end, text, ellipsis
+            ));
+        }
+        let index = if is_boundary(begin) { end } else { begin };
+        if is_boundary(index) {
+            return None;
+        }
+        let char_start = (0..index).rev().find(|&i| is_boundary(i)).unwrap();
+        let char_end = (index + 1..=s.len()).find(|&i| is_boundary(i)).unwrap();
+        let ch = String::from_utf8_lossy(&s[char_start..char_end]);
+        Some(format!(
+            "byte index {} is not a char boundary; it is inside {:?} (bytes {}..{}) of `{}`{}",
+            index,
+            ch.chars().next().unwrap(),
+            char_start,
+            char_end,
+            text,
+            ellipsis
+        ))
+    }
+
+    /// Maps each char of `s` with `map`, keeping the chars it would map to several, like `ß` to
+    /// `SS`, as they are, since sculpt-rt's C library maps chars one to one.
+    fn map_chars(
+        host: *mut Host,
+        s: *const u8,
+        len: u64,
+        value: *mut *const u8,
+        map: fn(char) -> Vec<char>,
+    ) -> i64 {
+        guard(|| {
+            let Host { buffer, .. } = unsafe { host.as_mut() }.unwrap();
+            let s = unsafe { std::slice::from_raw_parts(s, len.try_into().unwrap()) };
+            let mapped: String = String::from_utf8_lossy(s)
+                .chars()
+                .map(|c| match map(c)[..] {
+                    [mapped] => mapped,
+                    _ => c,
+                })
+                .collect();
+            buffer.clear();
+            buffer.extend_from_slice(mapped.as_bytes());
+            unsafe { *value = buffer.as_ptr() };
+            Ok(buffer.len())
+        })
+    }
+
+    extern "C" fn to_uppercase(
+        host: *mut Host,
+        s: *const u8,
+        len: u64,
+        value: *mut *const u8,
+    ) -> i64 {
+        map_chars(host, s, len, value, |c| c.to_uppercase().collect())
+    }
+
+    extern "C" fn to_lowercase(
+        host: *mut Host,
+        s: *const u8,
+        len: u64,
+        value: *mut *const u8,
+    ) -> i64 {
+        map_chars(host, s, len, value, |c| c.to_lowercase().collect())
+    }
+
+    extern "C" fn trim(s: *const u8, len: u64, trimmed: *mut *const u8) -> i64 {
+        guarded(
+            || {
+                unsafe { *trimmed = s };
+                0
+            },
+            || {
+                let s = unsafe { std::slice::from_raw_parts(s, len.try_into().unwrap()) };
+                let text = String::from_utf8_lossy(s);
+                let start = text.len() - text.trim_start().len();
+                let end = text.trim_end().len().max(start);
+                unsafe { *trimmed = s[start..].as_ptr() };
+                Ok((end - start) as i64)
+            },
+        )
+    }
+
+    extern "C" fn contains(s: *const u8, len: u64, pattern: *const u8, pattern_len: u64) -> i64 {
+        guarded(
+            || 0,
+            || {
+                let s = unsafe { std::slice::from_raw_parts(s, len.try_into().unwrap()) };
+                let pattern =
+                    unsafe { std::slice::from_raw_parts(pattern, pattern_len.try_into().unwrap()) };
+                let found =
+                    pattern.is_empty() || s.windows(pattern.len()).any(|window| window == pattern);
+                Ok(found.into())
+            },
+        )
+    }
+
+    /// Laid out like sculpt-rt's `struct sculpt_rt_string`.
+    #[repr(C)]
+    struct RtString {
+        buffer: *mut u8,
+        len: u64,
+        capacity: u64,
+    }
+
+    /// Leaves the string as it was if it fails.
+    extern "C" fn append(string: *mut RtString, s: *const u8, len: u64) {
+        guarded(
+            || (),
+            || {
+                let string = unsafe { string.as_mut() }.unwrap();
+                if len == 0 {
+                    return Ok(());
+                }
+                if string.len + len > string.capacity {
+                    let capacity = (string.len + len).max(2 * string.capacity);
+                    let size = capacity.try_into().map_err(io::Error::other)?;
+                    string.buffer = reallocate(string.buffer, size)?;
+                    string.capacity = capacity;
+                }
+                unsafe {
+                    let end = string.buffer.add(string.len.try_into().unwrap());
+                    std::ptr::copy_nonoverlapping(s, end, len.try_into().unwrap());
+                }
+                string.len += len;
+                Ok(())
+            },
+        )
+    }
+
+    /// Escapes like sculpt-rt's, which only escapes ASCII, rather than like Rust's `{:?}`.
+    extern "C" fn append_debug_str(string: *mut RtString, s: *const u8, len: u64) {
+        guarded(
+            || (),
+            || {
+                let s = unsafe { std::slice::from_raw_parts(s, len.try_into().unwrap()) };
+                let mut quoted = vec![b'"'];
+                for &byte in s {
+                    match byte {
+                        b'"' | b'\\' => quoted.extend([b'\\', byte]),
+                        b'\n' => quoted.extend(b"\\n"),
+                        b'\r' => quoted.extend(b"\\r"),
+                        b'\t' => quoted.extend(b"\\t"),
+                        0 => quoted.extend(b"\\0"),
+                        0..=0x1f | 0x7f => quoted.extend(format!("\\u{{{:x}}}", byte).as_bytes()),
+                        byte => quoted.push(byte),
+                    }
+                }
+                quoted.push(b'"');
+                append(string, quoted.as_ptr(), quoted.len().try_into().unwrap());
+                Ok(())
+            },
+        )
+    }
+
+    /// Holds what sculpt-rt's `struct sculpt_rt_map` does, starting with its length, which
+    /// programs read directly. Entries are whole words, as they're laid out by LLVM with the
+    /// alignment of their keys, and are found by the bytes of their keys.
+    #[repr(C)]
+    struct Map {
+        len: u64,
+        key_kind: u64,
+        words: usize,
+        entries: Vec<u64>,
+        index: HashMap<Vec<u8>, u64>,
+    }
+
+    impl Map {
+        /// The bytes of the key `key` points to.
+        fn key(&self, key: *const u8) -> Vec<u8> {
+            unsafe {
+                if self.key_kind == KEY_STR {
+                    let bytes = key.cast::<*const u8>().read_unaligned();
+                    let len = key.cast::<u64>().add(1).read_unaligned();
+                    std::slice::from_raw_parts(bytes, len.try_into().unwrap()).to_vec()
+                } else {
+                    std::slice::from_raw_parts(key, mem::size_of::<i64>()).to_vec()
+                }
+            }
+        }
+
+        fn entry(&mut self, index: u64) -> *mut u8 {
+            let start = usize::try_from(index).unwrap() * self.words;
+            self.entries[start..].as_mut_ptr().cast()
+        }
+    }
+
+    /// A map holding nothing, for a map function that failed to return in place of the map it
+    /// would have.
+    fn empty_map(key_kind: u64, words: usize) -> *mut Map {
+        Box::into_raw(Box::new(Map {
+            len: 0,
+            key_kind,
+            words,
+            entries: Vec::new(),
+            index: HashMap::new(),
+        }))
+    }
+
+    thread_local! {
+        /// Where `map_entry` points the program when it fails, so what it writes there is
+        /// discarded.
+        static SCRATCH: RefCell<Vec<u64>> = const { RefCell::new(Vec::new()) };
+    }
+
+    extern "C" fn map_new(key_kind: u64, entry_size: u64) -> *mut Map {
+        guarded(
+            || empty_map(key_kind, 0),
+            || {
+                let words = usize::try_from(entry_size).map_err(io::Error::other)?;
+                Ok(empty_map(key_kind, words / mem::size_of::<u64>()))
+            },
+        )
+    }
+
+    extern "C" fn map_entry(map: *mut Map, index: u64) -> *mut u8 {
+        guarded(
+            || {
+                let words = unsafe { map.as_ref() }.map_or(0, |map| map.words);
+                SCRATCH.with(|scratch| {
+                    let mut scratch = scratch.borrow_mut();
+                    let len = scratch.len().max(words);
+                    scratch.resize(len, 0);
+                    scratch.as_mut_ptr().cast()
+                })
+            },
+            || Ok(unsafe { map.as_mut() }.unwrap().entry(index)),
+        )
+    }
+
+    extern "C" fn map_find(map: *mut Map, key: *const u8) -> i64 {
+        guarded(
+            || -1,
+            || {
+                let map = unsafe { map.as_ref() }.unwrap();
+                Ok(map
+                    .index
+                    .get(&map.key(key))
+                    .map_or(-1, |index| (*index).try_into().unwrap()))
+            },
+        )
+    }
+
+    extern "C" fn map_push(map: *mut Map, entry: *const u8) {
+        guarded(
+            || (),
+            || {
+                let map = unsafe { map.as_mut() }.unwrap();
+                let words =
+                    (0..map.words).map(|i| unsafe { entry.cast::<u64>().add(i).read_unaligned() });
+                map.entries.extend(words);
+                account(map.words * mem::size_of::<u64>(), 0);
+                map.index.insert(map.key(entry), map.len);
+                map.len += 1;
+                Ok(())
+            },
+        )
+    }
+
+    /// Moves the last entry into the removed one's place, like sculpt-rt's, so entries are in
+    /// the same order.
+    extern "C" fn map_remove(map: *mut Map, index: u64) {
+        guarded(
+            || (),
+            || {
+                let map = unsafe { map.as_mut() }.unwrap();
+                let entry = map.entry(index);
+                map.index.remove(&map.key(entry));
+                map.len -= 1;
+                let words = map.words;
+                let removed = usize::try_from(index).unwrap();
+                let last = usize::try_from(map.len).unwrap();
+                if removed != last {
+                    map.entries
+                        .copy_within(last * words..(last + 1) * words, removed * words);
+                    let entry = map.entry(index);
+                    map.index.insert(map.key(entry), index);
+                }
+                map.entries.truncate(last * words);
+                account(0, words * mem::size_of::<u64>());
+                Ok(())
+            },
+        )
+    }
+
+    /// Sorts by comparing keys like sculpt-rt's, then indices, which end each entry.
+    extern "C" fn sort(entries: *mut u8, len: u64, entry_size: u64, key_kind: u64) {
+        guarded(
+            || (),
+            || {
+                let words = usize::try_from(entry_size).unwrap() / mem::size_of::<u64>();
+                let len = usize::try_from(len).unwrap();
+                let entries =
+                    unsafe { std::slice::from_raw_parts_mut(entries.cast::<u64>(), len * words) };
+                let mut sorted: Vec<_> = entries.chunks(words).map(<[u64]>::to_vec).collect();
+                let bytes = |entry: &[u64]| unsafe {
+                    std::slice::from_raw_parts(entry[0] as *const u8, entry[1].try_into().unwrap())
+                };
+                sorted.sort_by(|a, b| {
+                    let ordering = match key_kind {
+                        KEY_STR => bytes(a).cmp(bytes(b)),
+                        _ => (a[0] as i64).cmp(&(b[0] as i64)),
+                    };
+                    ordering.then(a[words - 1].cmp(&b[words - 1]))
+                });
+                for (entry, sorted) in entries.chunks_mut(words).zip(sorted) {
+                    entry.copy_from_slice(&sorted);
+                }
+                Ok(())
+            },
+        )
+    }
+
+    extern "C" fn map_clone(map: *mut Map) -> *mut Map {
+        guarded(
+            || {
+                let map = unsafe { map.as_ref() };
+                empty_map(
+                    map.map_or(0, |map| map.key_kind),
+                    map.map_or(0, |map| map.words),
+                )
+            },
+            || {
+                let map = unsafe { map.as_ref() }.unwrap();
+                account(map.entries.len() * mem::size_of::<u64>(), 0);
+                Ok(Box::into_raw(Box::new(Map {
+                    entries: map.entries.clone(),
+                    index: map.index.clone(),
+                    ..*map
+                })))
+            },
+        )
+    }
+
+    extern "C" fn map_free(map: *mut Map) {
+        guarded(
+            || (),
+            || {
+                let map = unsafe { Box::from_raw(map) };
+                account(0, map.entries.len() * mem::size_of::<u64>());
+                Ok(())
+            },
+        )
+    }
+
+    fn clock_denied() -> io::Error {
+        io::Error::other(Panic("this program may not use the clock".into()))
+    }
+
+    extern "C" fn read_file(
+        host: *mut Host,
+        path: *const u8,
+        len: u64,
+        contents: *mut *const u8,
+    ) -> i64 {
+        guard(|| {
+            let host = unsafe { host.as_mut() }.unwrap();
+            let path = unsafe { std::slice::from_raw_parts(path, len.try_into().unwrap()) };
+            let read = match host.options.files {
+                Files::Real => fs::read(host_path(path)),
+                Files::Virtual => host
+                    .virtual_files
+                    .get(path)
+                    .cloned()
+                    .ok_or_else(|| io::ErrorKind::NotFound.into()),
+                Files::Denied => return Err(files_denied()),
+            };
+            host.buffer = read.map_err(|e| file_panic("read", path, e))?;
+            unsafe { *contents = host.buffer.as_ptr() };
+            Ok(host.buffer.len())
+        })
+    }
+
+    extern "C" fn write_file(
+        host: *mut Host,
+        path: *const u8,
+        path_len: u64,
+        contents: *const u8,
+        len: u64,
+    ) -> i64 {
+        guard(|| {
+            let host = unsafe { host.as_mut() }.unwrap();
+            let path = unsafe { std::slice::from_raw_parts(path, path_len.try_into().unwrap()) };
+            let contents = unsafe { std::slice::from_raw_parts(contents, len.try_into().unwrap()) };
+            match host.options.files {
+                Files::Real => fs::write(host_path(path), contents)
+                    .map_err(|e| file_panic("write", path, e))?,
+                Files::Virtual => {
+                    host.virtual_files.insert(path.to_vec(), contents.to_vec());
+                }
+                Files::Denied => return Err(files_denied()),
+            }
+            Ok(0)
+        })
+    }
+
+    fn host_path(path: &[u8]) -> PathBuf {
+        PathBuf::from(String::from_utf8_lossy(path).into_owned())
+    }
+
+    fn file_panic(action: &str, path: &[u8], error: io::Error) -> io::Error {
+        io::Error::other(Panic(format!(
+            "could not {} `{}`: {}",
+            action,
+            String::from_utf8_lossy(path),
+            error
+        )))
+    }
+
+    fn files_denied() -> io::Error {
+        io::Error::other(Panic("this program may not use files".into()))
+    }
+
+    /// Unlike sculpt-rt's, doesn't exit the process: the program aborts back to `call`, which
+    /// returns the status for the embedder to exit with.
+    extern "C" fn exit(_host: *mut Host, status: i64) -> i64 {
+        guard(|| match i32::try_from(status) {
+            Ok(status) => Err(io::Error::other(Exit(status))),
+            Err(_) => Err(io::Error::other(Panic(format!(
+                "exit status {} out of range",
+                status
+            )))),
+        })
+    }
+
+    /// Refills the fuel of a program without a limit. One with a limit has used it up.
+    extern "C" fn refuel() -> i64 {
+        guard(|| {
+            let mut limits = LIMITS.get();
+            if let (Some(time_limit), Some(deadline)) = (limits.time_limit, limits.deadline) {
+                if Instant::now() >= deadline {
+                    return Err(io::Error::other(format!(
+                        "ran out of time after {} ms",
+                        time_limit.as_millis()
+                    )));
+                }
+            }
+            if limits.reserve == 0 {
+                return Err(io::Error::other(format!(
+                    "ran out of fuel after {} steps",
+                    limits.fuel.unwrap_or(u64::MAX)
+                )));
+            }
+            // The step that found the fuel empty takes one.
+            let chunk = fuel_chunk(&limits);
+            limits.reserve -= chunk;
+            LIMITS.set(limits);
+            FUEL_LEFT.set(chunk as i64 - 1);
+            Ok(0)
+        })
+    }
+
+    extern "C" {
+        fn realloc(ptr: *mut u8, size: usize) -> *mut u8;
+        fn free(ptr: *mut u8);
+    }
+
+    /// Every allocation starts with a header holding its size, like sculpt-rt's, so the bytes it
+    /// held are known when it's freed. It's as big as malloc's alignment, so what follows it stays
+    /// aligned.
+    const HEADER_SIZE: usize = 16;
+
+    /// Allocates with the C allocator, like sculpt-rt's does, as allocating with Rust's would need
+    /// the size again to free. Going over the heap limit fails the program, which aborts at its
+    /// next step, though the allocation is still made. Running out of memory leaves `ptr` as it
+    /// was.
+    fn reallocate(ptr: *mut u8, size: usize) -> io::Result<*mut u8> {
+        let header = if ptr.is_null() {
+            ptr
+        } else {
+            unsafe { ptr.sub(HEADER_SIZE) }
+        };
+        let old_size = if header.is_null() {
+            0
+        } else {
+            unsafe { header.cast::<usize>().read() }
+        };
+        let allocation = unsafe { realloc(header, size.saturating_add(HEADER_SIZE)) };
+        if allocation.is_null() {
+            return Err(io::Error::other(Panic("out of memory".into())));
+        }
+        account(size, old_size);
+        unsafe {
+            allocation.cast::<usize>().write(size);
+            Ok(allocation.add(HEADER_SIZE))
+        }
+    }
+
+    /// Still allocates once the program has failed, as it uses what it returns until it aborts,
+    /// and only returns null when there's no memory left.
+    extern "C" fn alloc(size: u64) -> *mut u8 {
+        let size = size.try_into().unwrap_or(usize::MAX);
+        let allocate = || reallocate(std::ptr::null_mut(), size);
+        guarded(|| allocate().unwrap_or(std::ptr::null_mut()), allocate)
+    }
+
+    extern "C" fn dealloc(ptr: *mut u8) {
+        guarded(
+            || (),
+            || {
+                if !ptr.is_null() {
+                    unsafe {
+                        let header = ptr.sub(HEADER_SIZE);
+                        account(0, header.cast::<usize>().read());
+                        free(header)
+                    }
+                }
+                Ok(())
+            },
+        )
+    }
+
+    /// Runs the body of a runtime function, which must not unwind into the JIT frames calling it.
+    /// Errors and panics are stored for `call` to return, and reported to the program as -1 so it
+    /// aborts. So is an error an earlier runtime function stored, without running the body.
+    fn guard(body: impl FnOnce() -> io::Result<usize>) -> i64 {
+        guarded(
+            || -1,
+            || body().map(|len| len.try_into().unwrap_or(i64::MAX)),
+        )
+    }
+
+    /// Like `guard`, for runtime functions the program doesn't check: what `fallback` returns is
+    /// returned in place of what failed, which the program uses until it aborts at its next step.
+    fn guarded<T>(fallback: impl FnOnce() -> T, body: impl FnOnce() -> io::Result<T>) -> T {
+        if RUNTIME_ERROR.with(|slot| slot.borrow().is_some()) {
+            return fallback();
+        }
+        let error = match ice::catch_unwind(AssertUnwindSafe(body)) {
+            Ok(Ok(value)) => return value,
+            Ok(Err(error)) => error,
+            Err(payload) => {
+                let message = payload
+                    .downcast_ref::<&str>()
+                    .copied()
+                    .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+                    .unwrap_or("unknown panic");
+                io::Error::other(format!("runtime panicked: {}", message))
+            }
+        };
+        fail(error);
+        fallback()
+    }
+
+    // The runtime's functions are the same for every program, so they're registered by name
+    // once, for every execution engine to resolve, instead of mapped in each program's module.
+    static REGISTER: Once = Once::new();
+    REGISTER.call_once(|| {
+        let functions = [
+            (WRITE, write as *const ()),
+            (READ, read as *const ()),
+            (FORMAT_I64, format_i64 as *const ()),
+            (FORMAT_RADIX, format_radix as *const ()),
+            (PANIC, panic as *const ()),
+            (PANIC_AT, panic_at as *const ()),
+            (ALLOC, alloc as *const ()),
+            (FREE, dealloc as *const ()),
+            (GETENV, getenv as *const ()),
+            (RANDOM_INT, random_int as *const ()),
+            (NOW_MILLIS, now_millis as *const ()),
+            (SLEEP_MILLIS, sleep_millis as *const ()),
+            (CHECK_SLICE, check_slice as *const ()),
+            (READ_FILE, read_file as *const ()),
+            (WRITE_FILE, write_file as *const ()),
+            (TO_UPPERCASE, to_uppercase as *const ()),
+            (TO_LOWERCASE, to_lowercase as *const ()),
+            (TRIM, trim as *const ()),
+            (CONTAINS, contains as *const ()),
+            (SORT, sort as *const ()),
+            (APPEND, append as *const ()),
+            (APPEND_DEBUG_STR, append_debug_str as *const ()),
+            (MAP_NEW, map_new as *const ()),
+            (MAP_ENTRY, map_entry as *const ()),
+            (MAP_FIND, map_find as *const ()),
+            (MAP_PUSH, map_push as *const ()),
+            (MAP_REMOVE, map_remove as *const ()),
+            (MAP_CLONE, map_clone as *const ()),
+            (MAP_FREE, map_free as *const ()),
+            (EXIT, exit as *const ()),
+            (REFUEL, refuel as *const ()),
+        ];
+        for (name, function) in functions {
+            let name = CString::new(name).unwrap();
+            unsafe { LLVMAddSymbol(name.as_ptr(), function as *mut c_void) };
+        }
+    });
+
+    let options = &streams.host.options;
+    let mut limits = Limits {
+        fuel: options.fuel,
+        reserve: options.fuel.unwrap_or(u64::MAX),
+        time_limit: options.time_limit,
+        deadline: options.time_limit.map(|limit| Instant::now() + limit),
+        max_heap_bytes: options.max_heap_bytes,
+        heap_bytes: 0,
+    };
+    let chunk = fuel_chunk(&limits);
+    limits.reserve -= chunk;
+    LIMITS.set(limits);
+    FUEL_LEFT.set(chunk as i64);
+    if let Some(ext_fuel) = module.get_global(&codegen.stream_symbol(FUEL)) {
+        execution_engine.add_global_mapping(&ext_fuel, FUEL_LEFT.with(Cell::as_ptr) as usize);
+    }
+
+    if let Some(ext_host) = module.get_global(&codegen.stream_symbol(HOST)) {
+        let host_ptr = &mut streams.host as *mut Host;
+        execution_engine.add_global_mapping(&ext_host, host_ptr as usize);
+    }
+
+    if let Some(ext_std_in) = module.get_global(&codegen.stream_symbol(STDIN)) {
+        let std_in_ptr = &mut streams.std_in as *mut Input;
+        execution_engine.add_global_mapping(&ext_std_in, std_in_ptr as usize);
+    }
+
+    let stream_globals = [
+        (STDOUT, &mut streams.std_out),
+        (STDERR, &mut streams.std_err),
+    ];
+    for (name, stream) in stream_globals {
+        if let Some(ext_stream) = module.get_global(&codegen.stream_symbol(name)) {
+            let stream_ptr = stream as *mut Box<dyn Write + '_>;
+            execution_engine.add_global_mapping(&ext_stream, stream_ptr as usize);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    fn lower(compiler: &mut Compiler, src: &str) {
+        compiler.lower_module(hir::lower(parse(src).unwrap()).unwrap());
+    }
+
+    #[test]
+    fn backend_runs_lowered_module() {
+        let context = Context::create();
+        let profiler = Profiler::default();
+        let mut compiler = Compiler::new(&context, OptimizationLevel::None, &profiler);
+        lower(&mut compiler, r#"fn main() { println!("hi"); }"#);
+        let mut output = Vec::new();
+        let mut errors = Vec::new();
+        compiler
+            .run(
+                &mut io::empty(),
+                &mut output,
+                &mut errors,
+                &RunOptions::default(),
+            )
+            .unwrap();
+        assert_eq!(output, b"hi\n");
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn running_only_compiles_functions_main_can_reach() {
+        let context = Context::create();
+        let profiler = Profiler::default();
+        let mut compiler = Compiler::new(&context, OptimizationLevel::None, &profiler);
+        let src = r#"
+            fn used() -> i64 { 1 }
+            fn unused() -> i64 { 2 }
+            fn main() { print!("{}", used()); }
+        "#;
+        lower(&mut compiler, src);
+        let mut output = Vec::new();
+        compiler
+            .run(
+                &mut io::empty(),
+                &mut output,
+                &mut io::sink(),
+                &RunOptions::default(),
+            )
+            .unwrap();
+        assert_eq!(output, b"1");
+        let module = &compiler.codegen.module;
+        assert!(module.get_function("used.body").is_some());
+        assert!(module.get_function("unused").is_none());
+        assert!(module.get_function("unused.body").is_none());
+    }
+
+    #[test]
+    fn generic_functions_are_built_once_per_instantiation() {
+        let context = Context::create();
+        let profiler = Profiler::default();
+        let mut compiler = Compiler::new(&context, OptimizationLevel::None, &profiler);
+        let src = r#"
+            fn id<T>(x: T) -> T { x }
+            fn main() { print!("{}{}{}", id(1), id(2), id("a")); }
+        "#;
+        lower(&mut compiler, src);
+        let ir = compiler.codegen.ir();
+        assert_eq!(ir.matches("define").count(), 4);
+        assert!(ir.contains(r#"define { i1, i64 } @"id::<i64>.body"(i64"#));
+        assert!(ir.contains(r#"define { i1, { i8*, i64 } } @"id::<str>.body"("#));
+    }
+
+    #[test]
+    fn string_constants_are_numbered_globals_shared_by_their_uses() {
+        let context = Context::create();
+        let profiler = Profiler::default();
+        let mut compiler = Compiler::new(&context, OptimizationLevel::None, &profiler);
+        let src = r#"
+            fn main() {
+                println!("a {}", 1);
+                println!("a {}", 2);
+                let n = match read_line!() { "b" => 1, _ => 2 };
+            }
+        "#;
+        lower(&mut compiler, src);
+        let ir = compiler.codegen.ir();
+        assert!(ir.contains(r#"@str.0 = private unnamed_addr constant [2 x i8] c"a ""#));
+        assert!(ir.contains(r#"@str.1 = private unnamed_addr constant [1 x i8] c"\0A""#));
+        assert!(ir.contains(r#"@str.2 = private unnamed_addr constant [1 x i8] c"b""#));
+        assert!(!ir.contains("@str.3"));
+        assert_eq!(ir.matches("[2 x i8]* @str.0").count(), 2);
+    }
+
+    #[test]
+    fn string_arms_switch_on_length_then_compare_bytes() {
+        let context = Context::create();
+        let profiler = Profiler::default();
+        let mut compiler = Compiler::new(&context, OptimizationLevel::None, &profiler);
+        let src = r#"
+            fn number(word: str) -> i64 {
+                match word { "one" => 1, "two" => 2, "three" => 3, "" => 0, _ => -1 }
+            }
+            fn main() { print!("{}", number("two")); }
+        "#;
+        lower(&mut compiler, src);
+        let ir = compiler.codegen.ir();
+        let body = &ir[ir.find("@number.body").unwrap()..];
+        let body = &body[..body.find("\n}\n").unwrap()];
+        assert_eq!(body.matches("switch i64").count(), 1);
+        let cases = &body[body.find("switch i64").unwrap()..];
+        let cases = &cases[..cases.find(']').unwrap()];
+        assert_eq!(cases.matches("i64 0, label").count(), 1);
+        assert_eq!(cases.matches("i64 3, label").count(), 1);
+        assert_eq!(cases.matches("i64 5, label").count(), 1);
+        // The empty string is matched by its length alone.
+        assert_eq!(body.matches("call i32 @memcmp").count(), 3);
+    }
+
+    #[test]
+    fn backend_emits_object_file() {
+        let context = Context::create();
+        let profiler = Profiler::default();
+        let mut compiler = Compiler::new(&context, OptimizationLevel::None, &profiler);
+        lower(&mut compiler, r#"fn main() { println!("hi"); }"#);
+        let path = std::env::temp_dir().join(format!("sculpt-{}.o", std::process::id()));
+        compiler.emit_object(&path).unwrap();
+        let object = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(!object.is_empty());
+    }
+
+    /// Writer that fails every write, either with an error or by panicking.
+    struct Failing {
+        panic: bool,
+    }
+
+    impl Write for Failing {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            if self.panic {
+                panic!("writer panicked");
+            }
+            Err(io::ErrorKind::BrokenPipe.into())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Read for Failing {
+        fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+            Err(io::ErrorKind::ConnectionReset.into())
+        }
+    }
+
+    fn run_failing(src: &str, std_in: &mut dyn BufRead, std_err: &mut dyn Write) -> io::Result<()> {
+        let context = Context::create();
+        let profiler = Profiler::default();
+        let mut compiler = Compiler::new(&context, OptimizationLevel::None, &profiler);
+        lower(&mut compiler, src);
+        compiler.run(
+            std_in,
+            &mut Failing { panic: false },
+            std_err,
+            &RunOptions::default(),
+        )
+    }
+
+    #[test]
+    fn write_errors_abort_the_program() {
+        let mut errors = Vec::new();
+        let error = run_failing(
+            r#"fn main() { print!("a"); eprint!("b"); }"#,
+            &mut io::empty(),
+            &mut errors,
+        )
+        .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::BrokenPipe);
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn read_errors_abort_the_program() {
+        let mut errors = Vec::new();
+        let error = run_failing(
+            r#"fn main() { eprint!("{}", read_line!()); eprint!("b"); }"#,
+            &mut io::BufReader::new(Failing { panic: false }),
+            &mut errors,
+        )
+        .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::ConnectionReset);
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn panics_in_the_runtime_abort_the_program() {
+        let error = run_failing(
+            r#"fn main() { eprint!("a"); print!("b"); }"#,
+            &mut io::empty(),
+            &mut Failing { panic: true },
+        )
+        .unwrap_err();
+        assert_eq!(error.to_string(), "runtime panicked: writer panicked");
+    }
+
+    #[test]
+    fn errors_do_not_leak_into_the_next_run() {
+        let context = Context::create();
+        let profiler = Profiler::default();
+        let mut compiler = Compiler::new(&context, OptimizationLevel::None, &profiler);
+        lower(&mut compiler, r#"fn main() { print!("a"); }"#);
+        let mut failing = Failing { panic: false };
+        assert!(compiler
+            .run(
+                &mut io::empty(),
+                &mut failing,
+                &mut io::sink(),
+                &RunOptions::default(),
+            )
+            .is_err());
+        let mut output = Vec::new();
+        compiler
+            .run(
+                &mut io::empty(),
+                &mut output,
+                &mut io::sink(),
+                &RunOptions::default(),
+            )
+            .unwrap();
+        assert_eq!(output, b"a");
+    }
+}
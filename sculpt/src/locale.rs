@@ -0,0 +1,184 @@
+//! The catalogs the messages of diagnostics are looked up in by key, so they can be translated.
+//!
+//! A catalog is a text file of `key = message` lines, with `#` comments. A message refers to the
+//! arguments it's given like `{$name}`. One that depends on a count has forms for particular
+//! counts, like `key.1`, tried before the plain `key`.
+
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+use std::sync::OnceLock;
+
+/// The locales there are catalogs for, by name. The first is the default, and has a message for
+/// every key; the rest fall back to it for the ones they don't translate.
+pub const LOCALES: [(&str, &str); 1] = [("en", include_str!("../locales/en.txt"))];
+
+static CATALOGS: OnceLock<Vec<Catalog>> = OnceLock::new();
+static CURRENT: OnceLock<usize> = OnceLock::new();
+
+/// Looks up the message for `key` in the current locale, with each of `args` filled in.
+///
+/// ```ignore
+/// msg!("unknown-macro", name = fg(format!("`{}`", name), a))
+/// ```
+macro_rules! msg {
+    ($key:literal $(, $arg:ident = $val:expr)* $(,)?) => {
+        $crate::locale::message(
+            $key,
+            None,
+            &[$((stringify!($arg), &$val as &dyn std::fmt::Display)),*],
+        )
+    };
+}
+
+/// Like [`msg!`], using the form of `key` for `count`, which is also given as `{$count}`.
+macro_rules! msg_n {
+    ($key:literal, $count:expr $(, $arg:ident = $val:expr)* $(,)?) => {
+        $crate::locale::message(
+            $key,
+            Some($count),
+            &[("count", &$count as &dyn std::fmt::Display) $(, (stringify!($arg), &$val as &dyn std::fmt::Display))*],
+        )
+    };
+}
+
+pub(crate) use {msg, msg_n};
+
+/// A locale's messages by key.
+pub struct Catalog {
+    name: &'static str,
+    messages: HashMap<&'static str, &'static str>,
+}
+
+impl Catalog {
+    /// Parses the catalog `text` for the locale `name`.
+    pub fn parse(name: &'static str, text: &'static str) -> Result<Catalog, String> {
+        let messages = text
+            .lines()
+            .enumerate()
+            .map(|(i, line)| (i, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+            .map(|(i, line)| match line.split_once(" = ") {
+                Some((key, message)) => Ok((key.trim(), message.trim())),
+                None => Err(format!("{}:{}: expected `key = message`", name, i + 1)),
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Catalog { name, messages })
+    }
+
+    /// The message for `key`, in the form for `count` if it has one.
+    fn get(&self, key: &str, count: Option<usize>) -> Option<&'static str> {
+        count
+            .and_then(|count| self.messages.get(format!("{}.{}", key, count).as_str()))
+            .or_else(|| self.messages.get(key))
+            .copied()
+    }
+}
+
+fn catalogs() -> &'static [Catalog] {
+    CATALOGS.get_or_init(|| {
+        LOCALES
+            .iter()
+            .map(|(name, text)| Catalog::parse(name, text).unwrap())
+            .collect()
+    })
+}
+
+/// The locale messages are looked up in.
+pub fn current() -> &'static str {
+    catalogs()[CURRENT.get().copied().unwrap_or(0)].name
+}
+
+/// Looks messages up in the locale `name` from now on. Only the first locale set is used.
+pub fn set(name: &str) -> Result<(), UnknownLocale> {
+    let index = catalogs()
+        .iter()
+        .position(|catalog| catalog.name == name)
+        .ok_or_else(|| UnknownLocale(name.to_string()))?;
+    CURRENT.get_or_init(|| index);
+    Ok(())
+}
+
+/// A locale there's no catalog for.
+#[derive(Debug, PartialEq)]
+pub struct UnknownLocale(pub String);
+
+impl Display for UnknownLocale {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let known: Vec<_> = LOCALES.iter().map(|(name, _)| *name).collect();
+        write!(
+            f,
+            "unknown locale `{}`, expected one of: {}",
+            self.0,
+            known.join(", ")
+        )
+    }
+}
+
+/// The message for `key` in the current locale, or the default one if it isn't translated, in
+/// the form for `count`, with `{$name}` replaced by the argument `name` in `args`.
+pub fn message(key: &str, count: Option<usize>, args: &[(&str, &dyn Display)]) -> String {
+    let catalogs = catalogs();
+    let text = catalogs[CURRENT.get().copied().unwrap_or(0)]
+        .get(key, count)
+        .or_else(|| catalogs[0].get(key, count))
+        .unwrap_or_else(|| panic!("no message for `{}`", key));
+    let mut message = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("{$") {
+        message.push_str(&rest[..start]);
+        let end = start + rest[start..].find('}').expect("unclosed argument");
+        let name = &rest[start + 2..end];
+        let (_, value) = args
+            .iter()
+            .find(|(arg, _)| *arg == name)
+            .unwrap_or_else(|| panic!("no argument `{}` for `{}`", name, key));
+        message.push_str(&value.to_string());
+        rest = &rest[end + 1..];
+    }
+    message.push_str(rest);
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_message_used_is_in_the_default_catalog() {
+        let default = &catalogs()[0];
+        let keys = [include_str!("report.rs"), include_str!("fix.rs")]
+            .into_iter()
+            .flat_map(|source| ["msg!(\"", "msg_n!(\""].map(|call| source.split(call).skip(1)))
+            .flatten()
+            .filter_map(|rest| rest.split_once('"').map(|(key, _)| key));
+        for key in keys {
+            assert!(default.get(key, None).is_some(), "no message for `{}`", key);
+        }
+        for catalog in &catalogs()[1..] {
+            for key in catalog.messages.keys() {
+                assert!(default.messages.contains_key(key), "`{}` isn't a key", key);
+            }
+        }
+    }
+
+    #[test]
+    fn messages_use_the_form_for_their_count() {
+        let catalog = Catalog::parse(
+            "test",
+            "# Arguments.\ntakes = takes {$count} arguments\ntakes.1 = takes {$count} argument\n",
+        )
+        .unwrap();
+        assert_eq!(
+            catalog.get("takes", Some(1)),
+            Some("takes {$count} argument")
+        );
+        assert_eq!(
+            catalog.get("takes", Some(2)),
+            Some("takes {$count} arguments")
+        );
+        assert_eq!(
+            message("unknown-macro", None, &[("name", &"`foo!`")]),
+            "cannot find macro `foo!`"
+        );
+    }
+}
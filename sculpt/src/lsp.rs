@@ -0,0 +1,820 @@
+//! A language server, speaking the Language Server Protocol over stdio, that checks open files as
+//! they're edited and answers editors' questions about them.
+
+use serde_json::{json, Value};
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use crate::config::Config;
+use crate::expand::BUILTIN_MACROS;
+use crate::fix::{fix, Fix};
+use crate::fmt::format_source_with;
+use crate::report::{report_error, report_warning};
+use crate::run::parse;
+use crate::session::Session;
+use crate::syntax::{
+    is_keyword, Expr, Function, IntLit, Macro, MacroRules, Pattern, Program, StrLit,
+};
+use crate::visit::{self, Visit};
+
+/// The kinds of semantic tokens, in the order the legend lists them.
+const TOKEN_TYPES: [&str; 10] = [
+    "keyword",
+    "comment",
+    "function",
+    "method",
+    "struct",
+    "parameter",
+    "variable",
+    "macro",
+    "string",
+    "number",
+];
+/// The modifier set on names where they're declared.
+const DECLARATION: usize = 1;
+
+/// Serves requests read from `input`, writing responses and notifications to `output`, until the
+/// client sends `exit`.
+pub fn serve_lsp(session: Session, mut input: impl BufRead, output: impl Write) -> io::Result<()> {
+    let mut server = Server {
+        session,
+        documents: HashMap::new(),
+        fixes: HashMap::new(),
+        output,
+    };
+    while let Some(message) = read_message(&mut input)? {
+        if !server.handle(message)? {
+            break;
+        }
+    }
+    Ok(())
+}
+
+struct Server<W> {
+    session: Session,
+    /// The text of each open document, by URI.
+    documents: HashMap<String, String>,
+    /// The fixes for the diagnostics last published for each open document, by URI, along with
+    /// the span and diagnostic each fixes.
+    fixes: HashMap<String, Vec<(Range<usize>, Value, Fix)>>,
+    output: W,
+}
+
+impl<W: Write> Server<W> {
+    /// Handles a request or notification, returning whether to keep serving.
+    fn handle(&mut self, message: Value) -> io::Result<bool> {
+        let params = &message["params"];
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        let result = match message["method"].as_str().unwrap_or_default() {
+            "initialize" => json!({
+                "capabilities": {
+                    "textDocumentSync": 1,
+                    "hoverProvider": true,
+                    "definitionProvider": true,
+                    "referencesProvider": true,
+                    "codeActionProvider": true,
+                    "documentFormattingProvider": true,
+                    "documentRangeFormattingProvider": true,
+                    "semanticTokensProvider": {
+                        "legend": {
+                            "tokenTypes": TOKEN_TYPES,
+                            "tokenModifiers": ["declaration"],
+                        },
+                        "full": true,
+                    },
+                },
+            }),
+            "shutdown" => Value::Null,
+            "exit" => return Ok(false),
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or_default();
+                self.documents.insert(uri.to_string(), text.to_string());
+                self.publish_diagnostics(uri)?;
+                return Ok(true);
+            }
+            "textDocument/didChange" => {
+                // Every change holds the whole text, as the server asks for full syncs.
+                if let Some(text) = params["contentChanges"]
+                    .as_array()
+                    .and_then(|changes| changes.last())
+                    .and_then(|change| change["text"].as_str())
+                {
+                    self.documents.insert(uri.to_string(), text.to_string());
+                }
+                self.publish_diagnostics(uri)?;
+                return Ok(true);
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(uri);
+                self.fixes.remove(uri);
+                self.session.forget(&path(uri));
+                self.notify(
+                    "textDocument/publishDiagnostics",
+                    json!({ "uri": uri, "diagnostics": [] }),
+                )?;
+                return Ok(true);
+            }
+            "textDocument/semanticTokens/full" => {
+                let text = self.documents.get(uri).map_or("", String::as_str);
+                json!({ "data": semantic_tokens(text) })
+            }
+            "textDocument/hover" => {
+                let text = self.documents.get(uri).map_or("", String::as_str);
+                let index = LineIndex::new(text);
+                let offset = index.offset(&params["position"]);
+                match hover(&self.session, &path(uri), text, offset) {
+                    Some((span, contents)) => json!({
+                        "contents": { "kind": "markdown", "value": contents },
+                        "range": index.range(span),
+                    }),
+                    None => Value::Null,
+                }
+            }
+            "textDocument/definition" => {
+                let text = self.documents.get(uri).map_or("", String::as_str);
+                let index = LineIndex::new(text);
+                let offset = index.offset(&params["position"]);
+                self.session
+                    .names(&path(uri))
+                    .and_then(|names| names.definition(offset))
+                    .map_or(
+                        Value::Null,
+                        |def| json!({ "uri": uri, "range": index.range(def) }),
+                    )
+            }
+            "textDocument/references" => {
+                let text = self.documents.get(uri).map_or("", String::as_str);
+                let index = LineIndex::new(text);
+                let offset = index.offset(&params["position"]);
+                let include_declaration = params["context"]["includeDeclaration"]
+                    .as_bool()
+                    .unwrap_or(true);
+                let references = self.session.names(&path(uri)).map_or(Vec::new(), |names| {
+                    let def = names.definition(offset);
+                    names
+                        .references(offset)
+                        .into_iter()
+                        .filter(|span| include_declaration || Some(span) != def.as_ref())
+                        .map(|span| json!({ "uri": uri, "range": index.range(span) }))
+                        .collect()
+                });
+                Value::Array(references)
+            }
+            "textDocument/formatting" | "textDocument/rangeFormatting" => {
+                let text = self.documents.get(uri).map_or("", String::as_str);
+                let index = LineIndex::new(text);
+                let range = params
+                    .get("range")
+                    .map(|range| index.offset(&range["start"])..index.offset(&range["end"]));
+                // A broken `sculpt.toml` shouldn't stop editors formatting.
+                let config = Config::find(&path(uri)).unwrap_or_default();
+                let formatted = format_source_with(text, range, &config.fmt);
+                match formatted == text {
+                    true => json!([]),
+                    // Editors diff the text themselves, so replacing all of it is enough.
+                    false => json!([{ "range": index.range(0..text.len()), "newText": formatted }]),
+                }
+            }
+            "textDocument/codeAction" => {
+                let text = self.documents.get(uri).map_or("", String::as_str);
+                let index = LineIndex::new(text);
+                let start = index.offset(&params["range"]["start"]);
+                let end = index.offset(&params["range"]["end"]);
+                let actions = self
+                    .fixes
+                    .get(uri)
+                    .into_iter()
+                    .flatten()
+                    .filter(|(span, ..)| span.start <= end && start <= span.end)
+                    .map(|(_, diagnostic, fix)| {
+                        let edits: Vec<_> = fix
+                            .edits
+                            .iter()
+                            .map(|edit| {
+                                json!({
+                                    "range": index.range(edit.span.clone()),
+                                    "newText": edit.replacement,
+                                })
+                            })
+                            .collect();
+                        json!({
+                            "title": fix.message,
+                            "kind": "quickfix",
+                            "diagnostics": [diagnostic],
+                            "isPreferred": true,
+                            "edit": { "changes": { uri: edits } },
+                        })
+                    })
+                    .collect();
+                Value::Array(actions)
+            }
+            _ if message.get("id").is_none() => return Ok(true),
+            method => return self
+                .respond(json!({
+                    "jsonrpc": "2.0",
+                    "id": message["id"],
+                    "error": { "code": -32601, "message": format!("unknown method {}", method) },
+                }))
+                .map(|()| true),
+        };
+        self.respond(json!({ "jsonrpc": "2.0", "id": message["id"], "result": result }))?;
+        Ok(true)
+    }
+
+    /// Checks the document at `uri`, sending the client its first error and every warning.
+    fn publish_diagnostics(&mut self, uri: &str) -> io::Result<()> {
+        let text = self.documents.get(uri).map_or("", String::as_str);
+        let index = LineIndex::new(text);
+        let path = path(uri);
+        let mut diagnostics = Vec::new();
+        let mut fixes = Vec::new();
+        let (warnings, checked) = self.session.analyze(&path, text);
+        for warning in warnings {
+            let mut report = Vec::new();
+            let span = warning.span();
+            let code = warning.code();
+            report_warning(&path, text, warning, false, &mut report);
+            diagnostics.push(json!({
+                "range": index.range(span),
+                "severity": 2,
+                "code": code.name(),
+                "message": headline(&report),
+            }));
+        }
+        if let Err(error) = checked {
+            let start = error.location().min(text.len());
+            let span = start..token_end(text, start);
+            let error_fix = fix(&error, text);
+            let code = error.code();
+            let mut report = Vec::new();
+            report_error(&path, text, error, false, &mut report);
+            let diagnostic = json!({
+                "range": index.range(span.clone()),
+                "severity": 1,
+                "code": code.name(),
+                "message": headline(&report),
+            });
+            if let Some(error_fix) = error_fix {
+                fixes.push((span, diagnostic.clone(), error_fix));
+            }
+            diagnostics.push(diagnostic);
+        }
+        self.fixes.insert(uri.to_string(), fixes);
+        self.notify(
+            "textDocument/publishDiagnostics",
+            json!({ "uri": uri, "diagnostics": diagnostics }),
+        )
+    }
+
+    fn notify(&mut self, method: &str, params: Value) -> io::Result<()> {
+        self.respond(json!({ "jsonrpc": "2.0", "method": method, "params": params }))
+    }
+
+    fn respond(&mut self, message: Value) -> io::Result<()> {
+        let body = message.to_string();
+        write!(
+            self.output,
+            "Content-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        )?;
+        self.output.flush()
+    }
+}
+
+/// Reads a message framed by a `Content-Length` header, or `None` once the input ends.
+fn read_message(input: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let mut len = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(value) = line.strip_prefix("Content-Length:") {
+            len = value.trim().parse().ok();
+        }
+    }
+    let len = len.ok_or_else(|| io::Error::other("message without a Content-Length"))?;
+    let mut body = vec![0; len];
+    input.read_exact(&mut body)?;
+    Ok(Some(serde_json::from_slice(&body)?))
+}
+
+/// The path a `file://` URI names, which is where `include_str!` paths are relative to.
+fn path(uri: &str) -> PathBuf {
+    PathBuf::from(uri.strip_prefix("file://").unwrap_or(uri))
+}
+
+/// The message of a rendered diagnostic, like `unused variable `x`` from
+/// `[UnusedVariable] Warning: unused variable `x``.
+fn headline(report: &[u8]) -> String {
+    let report = String::from_utf8_lossy(report);
+    let first = report.lines().next().unwrap_or_default();
+    first
+        .split_once(": ")
+        .map_or(first, |(_, message)| message)
+        .to_string()
+}
+
+/// The end of the name or other token starting at `start`, so errors, which are only located by
+/// where they start, underline something.
+fn token_end(text: &str, start: usize) -> usize {
+    let rest = &text[start..];
+    match rest.find(|c: char| !c.is_alphanumeric() && c != '_') {
+        Some(0) => start + rest.chars().next().map_or(0, char::len_utf8),
+        Some(end) => start + end,
+        None => text.len(),
+    }
+}
+
+/// Converts between byte offsets and LSP positions, whose characters count UTF-16 code units.
+struct LineIndex<'a> {
+    text: &'a str,
+    /// The offset each line starts at.
+    starts: Vec<usize>,
+}
+
+impl<'a> LineIndex<'a> {
+    fn new(text: &'a str) -> Self {
+        let starts = [0]
+            .into_iter()
+            .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        LineIndex { text, starts }
+    }
+
+    fn position(&self, offset: usize) -> (usize, usize) {
+        let line = self.starts.partition_point(|&start| start <= offset) - 1;
+        let character = self.text[self.starts[line]..offset].encode_utf16().count();
+        (line, character)
+    }
+
+    fn range(&self, span: Range<usize>) -> Value {
+        let (start_line, start_char) = self.position(span.start);
+        let (end_line, end_char) = self.position(span.end);
+        json!({
+            "start": { "line": start_line, "character": start_char },
+            "end": { "line": end_line, "character": end_char },
+        })
+    }
+
+    fn offset(&self, position: &Value) -> usize {
+        let line = position["line"].as_u64().unwrap_or_default() as usize;
+        let Some(&start) = self.starts.get(line) else {
+            return self.text.len();
+        };
+        let mut units = position["character"].as_u64().unwrap_or_default() as usize;
+        let mut offset = start;
+        for c in self.text[start..].chars().take_while(|&c| c != '\n') {
+            if units < c.len_utf16() {
+                break;
+            }
+            units -= c.len_utf16();
+            offset += c.len_utf8();
+        }
+        offset
+    }
+}
+
+/// What to show when hovering over `offset` in `text`: the type of a local, or the signature of
+/// a macro, along with the span it's for. Types are those found when `path` was last checked.
+fn hover(
+    session: &Session,
+    path: &Path,
+    text: &str,
+    offset: usize,
+) -> Option<(Range<usize>, String)> {
+    let program = parse(text).ok()?;
+    let mut finder = MacroFinder {
+        offset,
+        found: None,
+    };
+    finder.visit_program(&program);
+    if let Some(m) = finder.found {
+        let name = m.name.name.as_str();
+        let signature = BUILTIN_MACROS
+            .iter()
+            .find(|(builtin, _)| *builtin == name)
+            .map(|(_, signature)| signature.to_string())
+            .or_else(|| user_macro_signature(&program, m))?;
+        return Some((
+            m.name.span.clone(),
+            format!("```sculpt\n{}\n```", signature),
+        ));
+    }
+    let names = session.names(path)?;
+    let (span, ty) = names.type_at(offset)?;
+    Some((
+        span.clone(),
+        format!("```sculpt\n{}: {}\n```", &text[span], ty),
+    ))
+}
+
+/// The rules of the user macro `m` invokes, like `twice!($x)`, one per line.
+fn user_macro_signature(program: &Program, m: &Macro) -> Option<String> {
+    let rules = program
+        .macros
+        .iter()
+        .find(|rules| rules.name.name == m.name.name)?;
+    let signatures: Vec<_> = rules
+        .rules
+        .iter()
+        .map(|rule| {
+            let params: Vec<_> = rule
+                .params
+                .iter()
+                .map(|param| format!("${}", param.name.as_str()))
+                .collect();
+            format!("{}({})", m.name.name.as_str(), params.join(", "))
+        })
+        .collect();
+    Some(signatures.join("\n"))
+}
+
+/// Finds the macro invocation whose name spans `offset`.
+struct MacroFinder<'ast> {
+    offset: usize,
+    found: Option<&'ast Macro>,
+}
+
+impl<'ast> Visit<'ast> for MacroFinder<'ast> {
+    fn visit_macro(&mut self, m: &'ast Macro) {
+        if m.name.span.contains(&self.offset) {
+            self.found = Some(m);
+        }
+        visit::walk_macro(self, m);
+    }
+}
+
+/// The semantic tokens of `text`, encoded as LSP expects: five integers per token, giving its
+/// line and start relative to the token before it, its length, type and modifiers.
+fn semantic_tokens(text: &str) -> Vec<u32> {
+    let mut highlighter = Highlighter::default();
+    if let Ok(program) = parse(text) {
+        highlighter.visit_program(&program);
+    }
+    let mut tokens = highlighter.tokens;
+    tokens.extend(lex_keywords_and_comments(text));
+    // Names like `self` are keywords too, but the syntax tree knows more about them.
+    tokens.sort_by_key(|(span, ..)| span.start);
+    tokens.dedup_by_key(|(span, ..)| span.start);
+
+    let index = LineIndex::new(text);
+    let mut data = Vec::new();
+    let (mut last_line, mut last_start) = (0, 0);
+    for (span, ty, modifiers) in tokens {
+        let (line, start) = index.position(span.start);
+        // Tokens can't span lines, so ones that do, like multi-line strings, are cut short.
+        let end = text[span.clone()]
+            .find('\n')
+            .map_or(span.end, |i| span.start + i);
+        let len = text[span.start..end].encode_utf16().count();
+        let delta_start = if line == last_line {
+            start - last_start
+        } else {
+            start
+        };
+        data.extend([line - last_line, delta_start, len, ty, modifiers].map(|n| n as u32));
+        (last_line, last_start) = (line, start);
+    }
+    data
+}
+
+fn token_type(name: &str) -> usize {
+    TOKEN_TYPES.iter().position(|ty| *ty == name).unwrap()
+}
+
+/// The keywords and `//` comments in `text`, which the syntax tree doesn't keep.
+fn lex_keywords_and_comments(text: &str) -> Vec<(Range<usize>, usize, usize)> {
+    let mut tokens = Vec::new();
+    let mut rest = text.char_indices().peekable();
+    while let Some((start, c)) = rest.next() {
+        if c == '"' {
+            // Skip strings, which can contain `//`, words and escaped quotes.
+            while let Some((_, c)) = rest.next() {
+                if c == '"' {
+                    break;
+                } else if c == '\\' {
+                    rest.next();
+                }
+            }
+        } else if c == '/' && text[start..].starts_with("//") {
+            let end = text[start..].find('\n').map_or(text.len(), |i| start + i);
+            tokens.push((start..end, token_type("comment"), 0));
+            while rest.next_if(|&(i, _)| i < end).is_some() {}
+        } else if c.is_alphabetic() || c == '_' {
+            let mut end = start + c.len_utf8();
+            while let Some((i, c)) = rest.next_if(|&(_, c)| c.is_alphanumeric() || c == '_') {
+                end = i + c.len_utf8();
+            }
+            let macro_name = rest.peek().is_some_and(|&(_, c)| c == '!');
+            if is_keyword(&text[start..end]) && !macro_name {
+                tokens.push((start..end, token_type("keyword"), 0));
+            }
+        }
+    }
+    tokens
+}
+
+/// Classifies the names and literals of a program.
+#[derive(Default)]
+struct Highlighter {
+    tokens: Vec<(Range<usize>, usize, usize)>,
+}
+
+impl Highlighter {
+    fn push(&mut self, span: Range<usize>, ty: &str, modifiers: usize) {
+        self.tokens.push((span, token_type(ty), modifiers));
+    }
+}
+
+impl<'ast> Visit<'ast> for Highlighter {
+    fn visit_program(&mut self, program: &'ast Program) {
+        for s in &program.structs {
+            self.push(s.name.span.clone(), "struct", DECLARATION);
+        }
+        for i in &program.impls {
+            self.push(i.ty.span.clone(), "struct", 0);
+        }
+        for function in &program.functions {
+            self.visit_function(function);
+        }
+        for i in &program.impls {
+            for function in &i.functions {
+                self.visit_function(function);
+            }
+        }
+        for m in &program.macros {
+            self.visit_macro_rules(m);
+        }
+        for s in &program.statics {
+            self.push(s.name.span.clone(), "variable", DECLARATION);
+            self.visit_expr(&s.value);
+        }
+    }
+
+    fn visit_function(&mut self, function: &'ast Function) {
+        self.push(function.name.span.clone(), "function", DECLARATION);
+        for param in &function.params {
+            self.push(param.name.span.clone(), "parameter", DECLARATION);
+        }
+        for statement in &function.statements {
+            self.visit_stmt(statement);
+        }
+        if let Some(tail) = &function.tail {
+            self.visit_expr(tail);
+        }
+    }
+
+    fn visit_macro_rules(&mut self, m: &'ast MacroRules) {
+        self.push(m.name.span.clone(), "macro", DECLARATION);
+        for rule in &m.rules {
+            visit::walk_macro_rule(self, rule);
+        }
+    }
+
+    fn visit_macro(&mut self, m: &'ast Macro) {
+        self.push(m.name.span.clone(), "macro", 0);
+        for arg in &m.args {
+            self.visit_expr(arg);
+        }
+    }
+
+    fn visit_expr(&mut self, expr: &'ast Expr) {
+        match expr {
+            Expr::Name(name) => self.push(name.span.clone(), "variable", 0),
+            Expr::Call(call) => {
+                if let Some(ty) = &call.ty {
+                    self.push(ty.span.clone(), "struct", 0);
+                }
+                self.push(call.name.span.clone(), "function", 0);
+                for arg in &call.args {
+                    self.visit_expr(arg);
+                }
+            }
+            Expr::MethodCall(call) => {
+                self.visit_expr(&call.receiver);
+                self.push(call.name.span.clone(), "method", 0);
+                for arg in &call.args {
+                    self.visit_expr(arg);
+                }
+            }
+            Expr::Closure(closure) => {
+                for param in &closure.params {
+                    self.push(param.name.span.clone(), "parameter", DECLARATION);
+                }
+                self.visit_expr(&closure.body);
+            }
+            _ => visit::walk_expr(self, expr),
+        }
+    }
+
+    fn visit_pattern(&mut self, pattern: &'ast Pattern) {
+        match pattern {
+            Pattern::Name(name) => self.push(name.span.clone(), "variable", DECLARATION),
+            _ => visit::walk_pattern(self, pattern),
+        }
+    }
+
+    fn visit_str_lit(&mut self, lit: &'ast StrLit) {
+        self.push(lit.span.clone(), "string", 0);
+    }
+
+    fn visit_int_lit(&mut self, lit: &'ast IntLit) {
+        self.push(lit.span.clone(), "number", 0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sends `messages` to a fresh server, returning every message it sent back.
+    fn exchange(messages: &[Value]) -> Vec<Value> {
+        let mut input = Vec::new();
+        for message in messages {
+            let body = message.to_string();
+            write!(input, "Content-Length: {}\r\n\r\n{}", body.len(), body).unwrap();
+        }
+        let mut output = Vec::new();
+        serve_lsp(Session::builder().build(), &input[..], &mut output).unwrap();
+        let mut output = &output[..];
+        let mut messages = Vec::new();
+        while let Some(message) = read_message(&mut output).unwrap() {
+            messages.push(message);
+        }
+        messages
+    }
+
+    fn open(text: &str) -> Value {
+        json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didOpen",
+            "params": { "textDocument": { "uri": "file:///a.sculpt", "text": text } },
+        })
+    }
+
+    fn request(id: u32, method: &str, position: Option<(u32, u32)>) -> Value {
+        let mut params = json!({ "textDocument": { "uri": "file:///a.sculpt" } });
+        if let Some((line, character)) = position {
+            params["position"] = json!({ "line": line, "character": character });
+        }
+        json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params })
+    }
+
+    #[test]
+    fn hovering_shows_types_and_macro_signatures() {
+        let src = "fn main() {\n    let x = 1;\n    println!(\"{}\", x);\n}";
+        let responses = exchange(&[
+            open(src),
+            request(1, "textDocument/hover", Some((2, 19))),
+            request(2, "textDocument/hover", Some((2, 6))),
+            request(3, "textDocument/hover", Some((0, 1))),
+        ]);
+        assert_eq!(responses[0]["params"]["diagnostics"], json!([]));
+        assert_eq!(
+            responses[1]["result"]["contents"]["value"],
+            "```sculpt\nx: i64\n```"
+        );
+        assert_eq!(
+            responses[1]["result"]["range"],
+            json!({
+                "start": { "line": 2, "character": 19 },
+                "end": { "line": 2, "character": 20 },
+            })
+        );
+        assert_eq!(
+            responses[2]["result"]["contents"]["value"],
+            "```sculpt\nprintln!(format, args...)\n```"
+        );
+        assert_eq!(responses[3]["result"], Value::Null);
+    }
+
+    #[test]
+    fn definitions_and_references_are_found_for_names() {
+        let src = "fn f(a: i64) -> i64 { a + a }\nfn main() {\n    let x = f(1);\n    \
+                   println!(\"{}\", f(x));\n}";
+        let mut references = request(3, "textDocument/references", Some((0, 3)));
+        references["params"]["context"] = json!({ "includeDeclaration": false });
+        let responses = exchange(&[
+            open(src),
+            request(1, "textDocument/definition", Some((3, 19))),
+            request(2, "textDocument/definition", Some((3, 21))),
+            references,
+        ]);
+        let range = |line, start, end| {
+            json!({
+                "start": { "line": line, "character": start },
+                "end": { "line": line, "character": end },
+            })
+        };
+        assert_eq!(
+            responses[1]["result"],
+            json!({ "uri": "file:///a.sculpt", "range": range(0, 3, 4) })
+        );
+        assert_eq!(responses[2]["result"]["range"], range(2, 8, 9));
+        let ranges: Vec<_> = responses[3]["result"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|location| location["range"].clone())
+            .collect();
+        assert_eq!(ranges, [range(2, 12, 13), range(3, 19, 20)]);
+    }
+
+    #[test]
+    fn fixable_errors_have_code_actions() {
+        let mut action = request(1, "textDocument/codeAction", None);
+        action["params"]["range"] = json!({
+            "start": { "line": 0, "character": 12 },
+            "end": { "line": 0, "character": 12 },
+        });
+        let responses = exchange(&[open("fn main() { printlm!(\"a\"); }"), action]);
+        let actions = &responses[1]["result"];
+        assert_eq!(
+            actions[0]["title"],
+            "a macro with a similar name exists: `println!`"
+        );
+        assert_eq!(
+            actions[0]["diagnostics"][0],
+            responses[0]["params"]["diagnostics"][0]
+        );
+        assert_eq!(
+            actions[0]["edit"]["changes"]["file:///a.sculpt"],
+            json!([{
+                "range": {
+                    "start": { "line": 0, "character": 12 },
+                    "end": { "line": 0, "character": 20 },
+                },
+                "newText": "println!",
+            }])
+        );
+    }
+
+    #[test]
+    fn formatting_replaces_the_document() {
+        let responses = exchange(&[
+            open("fn main() {\nlet x=1;\n}\n"),
+            request(1, "textDocument/formatting", None),
+        ]);
+        assert_eq!(
+            responses[1]["result"],
+            json!([{
+                "range": {
+                    "start": { "line": 0, "character": 0 },
+                    "end": { "line": 3, "character": 0 },
+                },
+                "newText": "fn main() {\n    let x = 1;\n}\n",
+            }])
+        );
+    }
+
+    #[test]
+    fn opened_documents_get_their_errors_and_warnings() {
+        let responses = exchange(&[open("fn main() { let x = y; }")]);
+        let diagnostics = &responses[0]["params"]["diagnostics"];
+        assert_eq!(diagnostics[0]["severity"], 2);
+        assert_eq!(diagnostics[0]["message"], "unused variable `x`");
+        assert_eq!(diagnostics[0]["code"], "UnusedVariable");
+        assert_eq!(diagnostics[1]["severity"], 1);
+        assert_eq!(diagnostics[1]["code"], "UnresolvedName");
+        assert_eq!(
+            diagnostics[1]["range"],
+            json!({
+                "start": { "line": 0, "character": 20 },
+                "end": { "line": 0, "character": 21 },
+            })
+        );
+    }
+
+    #[test]
+    fn semantic_tokens_classify_names_keywords_and_literals() {
+        let src = "// hi\nfn f(a: i64) -> i64 { a + 1 }";
+        let responses = exchange(&[
+            open(src),
+            request(1, "textDocument/semanticTokens/full", None),
+        ]);
+        let data: Vec<u64> =
+            serde_json::from_value(responses[1]["result"]["data"].clone()).unwrap();
+        let tokens: Vec<_> = data.chunks(5).collect();
+        // Line and start relative to the token before, length, type, and modifiers.
+        assert_eq!(
+            tokens,
+            [
+                [0, 0, 5, 1, 0],
+                [1, 0, 2, 0, 0],
+                [0, 3, 1, 2, 1],
+                [0, 2, 1, 5, 1],
+                [0, 17, 1, 6, 0],
+                [0, 4, 1, 9, 0],
+            ]
+        );
+    }
+}
@@ -1,41 +1,548 @@
-use clap::{Parser, Subcommand};
-use lalrpop_util::lalrpop_mod;
+use clap::{Parser, Subcommand, ValueEnum};
+#[cfg(feature = "llvm")]
+use inkwell::OptimizationLevel;
+use tracing::level_filters::LevelFilter;
+use tracing_subscriber::EnvFilter;
 
-use std::fs::read_to_string;
-use std::io::{self};
+use std::env;
+use std::fs::File;
+use std::io::{self, IsTerminal};
+#[cfg(feature = "llvm")]
+use std::net::TcpListener;
+use std::ops::Range;
+#[cfg(feature = "llvm")]
+use std::path::Path;
 use std::path::PathBuf;
+use std::process;
+#[cfg(feature = "llvm")]
+use std::thread;
+#[cfg(feature = "llvm")]
+use std::time::{Duration, Instant};
 
-mod fmt;
-mod report;
-mod run;
-mod syntax;
-
-lalrpop_mod!(grammar);
-
-use report::report_error;
-use run::run;
+#[cfg(feature = "llvm")]
+use sculpt::aot::crate_name;
+#[cfg(feature = "llvm")]
+use sculpt::bench::BenchOptions;
+use sculpt::codes::DiagnosticCode;
+use sculpt::config::Config;
+use sculpt::doc;
+use sculpt::ice;
+use sculpt::locale;
+use sculpt::lsp::serve_lsp;
+use sculpt::plugin::Plugin;
+#[cfg(feature = "llvm")]
+use sculpt::repl::Repl;
+use sculpt::report::MAX_LABELS;
+#[cfg(feature = "llvm")]
+use sculpt::sandbox::Sandbox;
+#[cfg(feature = "llvm")]
+use sculpt::serve::serve;
+use sculpt::session::Session;
 
 #[derive(Parser)]
 struct Args {
     #[command(subcommand)]
     command: Command,
+    /// Print how long each compilation phase took.
+    #[arg(long = "time-passes", short = 'v', global = true)]
+    time_passes: bool,
+    /// Log what the compiler does to stderr: `info` for how long each phase took, `debug` for
+    /// each phase as it starts and each file read too, `trace` for each function built too.
+    /// Defaults to the `SCULPT_LOG` filter, like `info` or `sculpt::llvm=trace`.
+    #[arg(long = "log-level", value_name = "LEVEL", global = true)]
+    log_level: Option<LevelFilter>,
+    /// Print the IR of each function as it's built, before the module is verified, marking the
+    /// functions that fail to verify.
+    #[arg(long = "verbose-codegen", global = true)]
+    verbose_codegen: bool,
+    /// Most labels an error renders for each list of spans it points at, like the unused
+    /// arguments of a macro call. The rest are summed up in a note.
+    #[arg(long = "max-labels", value_name = "N", global = true, default_value_t = MAX_LABELS)]
+    max_labels: usize,
+    /// The language to report diagnostics in, like `en`. Defaults to `SCULPT_LOCALE`, then `en`.
+    #[arg(long = "locale", value_name = "LOCALE", global = true)]
+    locale: Option<String>,
+    /// Report only errors: no warnings, and nothing about what was done. A program's own output
+    /// is still written.
+    #[arg(long = "quiet", short = 'q', global = true)]
+    quiet: bool,
+    /// Finish with a line counting the errors and warnings reported.
+    #[arg(long = "summary", global = true)]
+    summary: bool,
+    /// Finish with a JSON object counting the errors and warnings reported, like
+    /// `{"errors":0,"warnings":2}`, for CI to gate on.
+    #[arg(long = "warnings-as-json-summary", global = true)]
+    json_summary: bool,
+    /// Load a native macro plugin. May be given more than once; earlier plugins take precedence.
+    #[arg(long = "plugin", value_name = "PATH", global = true)]
+    plugins: Vec<PathBuf>,
+    /// Optimization level, from 0 (none) to 3 (aggressive).
+    #[cfg(feature = "llvm")]
+    #[arg(
+        short = 'O',
+        long = "opt-level",
+        global = true,
+        default_value_t = 0,
+        value_parser = clap::value_parser!(u8).range(0..=3)
+    )]
+    opt_level: u8,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum CrateType {
+    Bin,
+    Staticlib,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum DocFormat {
+    Html,
+    Markdown,
 }
 
 #[derive(Subcommand)]
 enum Command {
-    Run { file: PathBuf },
+    /// Parse and check files for errors without running them.
+    Check {
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+        /// What the files are. Only a `bin` needs a `main`.
+        #[arg(long, value_enum, default_value_t = CrateType::Bin)]
+        crate_type: CrateType,
+    },
+    /// Fix the errors in files that have a fix, like a missing `;`, in place.
+    Fix {
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+    },
+    /// Format files in place.
+    Fmt {
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+        /// Only format the lines touching this range of byte offsets, like `10..42`.
+        #[arg(long, value_name = "START..END", value_parser = parse_range)]
+        range: Option<Range<usize>>,
+        /// Read settings from this file, instead of the `sculpt.toml` nearest each file.
+        #[arg(long, value_name = "PATH")]
+        config: Option<PathBuf>,
+    },
+    /// Start a language server speaking the Language Server Protocol over stdio.
+    Lsp,
+    /// Render the `///` comments on a file's structs and functions into a reference page.
+    Doc {
+        file: PathBuf,
+        #[arg(long, value_enum, default_value_t = DocFormat::Html)]
+        format: DocFormat,
+        /// Where to write the page. Defaults to printing it.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Parse a corpus of snippets and compare the trees they parse to against the `.ast` file
+    /// beside each, to check changes to the grammar.
+    SelfCheckGrammar {
+        #[arg(default_value = "tests/grammar")]
+        dir: PathBuf,
+        /// Write the `.ast` files instead of comparing against them.
+        #[arg(long)]
+        bless: bool,
+    },
+    /// Explain what a diagnostic code, like `UnknownMacro`, means and how to fix it. Without a
+    /// code, list them all.
+    Explain { code: Option<String> },
+    /// Run files one after another. Given a directory, run every program in it on its own,
+    /// prefixing what each writes with its name.
+    #[cfg(feature = "llvm")]
+    Run {
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+        /// Run with the limits used for untrusted code.
+        #[arg(long)]
+        sandbox: bool,
+        /// Abort a program once it writes more than this many bytes to stdout or stderr.
+        #[arg(long, value_name = "N")]
+        max_output_bytes: Option<usize>,
+        /// Seed `random_int!`, so every run draws the same integers.
+        #[arg(long, value_name = "N")]
+        seed: Option<u64>,
+        /// Start in this function instead of `main`.
+        #[arg(long, value_name = "NAME")]
+        entry: Option<String>,
+        /// Print how long it took until the program started running, split by phase, and how
+        /// long it ran.
+        #[arg(long)]
+        cold_start_stats: bool,
+    },
+    /// Compile a file into a standalone executable or a static library.
+    #[cfg(feature = "llvm")]
+    Build {
+        file: PathBuf,
+        /// Where to write the output. Defaults to the file's name without its extension for
+        /// executables, and `lib<name>.a` next to the file for static libraries.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// What to build. A `staticlib` exports each `pub fn name` as the C function
+        /// `<crate>_<name>`, where the crate is named after the file, and gets a `<crate>.h`.
+        #[arg(long, value_enum, default_value_t = CrateType::Bin)]
+        crate_type: CrateType,
+        /// Build the same bytes for the same file on any machine: no timestamps or host paths,
+        /// and functions and globals sorted by name.
+        #[arg(long)]
+        deterministic: bool,
+    },
+    /// Print the LLVM IR a file compiles to.
+    #[cfg(feature = "llvm")]
+    Ir {
+        file: PathBuf,
+        /// Show the IR after the optimization pipeline for `-O` has run.
+        #[arg(long)]
+        optimized: bool,
+        /// Write LLVM bitcode to this path instead of printing textual IR.
+        #[arg(long, value_name = "PATH")]
+        bitcode: Option<PathBuf>,
+        /// Emit functions and globals sorted by name, like `build --deterministic` does.
+        #[arg(long)]
+        deterministic: bool,
+    },
+    /// Time every `#[bench]` function in a file.
+    #[cfg(feature = "llvm")]
+    Bench {
+        file: PathBuf,
+        /// Calls made before timing starts.
+        #[arg(long, default_value_t = BenchOptions::default().warmup)]
+        warmup: u32,
+        /// Number of timed samples.
+        #[arg(long, default_value_t = BenchOptions::default().samples)]
+        samples: u32,
+        /// Calls averaged into each sample.
+        #[arg(long, default_value_t = BenchOptions::default().iterations)]
+        iterations: u32,
+    },
+    /// Run every `#[test]` function in a file, and every code block in its doc comments. Given a
+    /// directory, run every program in it and compare its output to the `.expected` file beside
+    /// it.
+    #[cfg(feature = "llvm")]
+    Test {
+        file: PathBuf,
+        /// Write the `.expected` files instead of comparing against them.
+        #[arg(long)]
+        bless: bool,
+    },
+    /// Re-run a file every time it changes.
+    #[cfg(feature = "llvm")]
+    Watch { file: PathBuf },
+    /// Start an interactive session. Type `:help` for commands.
+    #[cfg(feature = "llvm")]
+    Repl,
+    /// Serve a playground HTTP endpoint (`POST /run`) that runs programs in a sandbox.
+    #[cfg(feature = "llvm")]
+    Serve {
+        #[arg(long, default_value = "127.0.0.1")]
+        host: String,
+        #[arg(long, default_value_t = 8080)]
+        port: u16,
+    },
 }
 
 fn main() {
-    let Args { command } = Args::parse();
-
-    match command {
-        Command::Run { file } => {
-            let source_code = read_to_string(&file).unwrap();
-            if let Err(error) = run(&source_code, io::stdout()) {
-                let colored = true;
-                report_error(&file, &source_code, error, colored, io::stderr());
+    #[cfg(feature = "llvm")]
+    let started = Instant::now();
+    ice::install();
+    let Args {
+        command,
+        time_passes,
+        log_level,
+        verbose_codegen,
+        max_labels,
+        locale,
+        quiet,
+        summary,
+        json_summary,
+        plugins,
+        #[cfg(feature = "llvm")]
+        opt_level,
+    } = Args::parse();
+    if let Some(locale) = locale.or_else(|| env::var("SCULPT_LOCALE").ok()) {
+        locale::set(&locale).unwrap_or_else(|error| {
+            eprintln!("error: {}", error);
+            process::exit(1)
+        });
+    }
+    let session = plugins
+        .iter()
+        .fold(Session::builder().colored(true), |session, path| {
+            session.plugin(or_exit(Plugin::load(path)))
+        });
+    init_logging(log_level);
+    let session = session
+        .verbose_codegen(verbose_codegen)
+        .max_labels(max_labels)
+        .quiet(quiet);
+    #[cfg(feature = "llvm")]
+    let session = session.opt_level(match opt_level {
+        0 => OptimizationLevel::None,
+        1 => OptimizationLevel::Less,
+        2 => OptimizationLevel::Default,
+        _ => OptimizationLevel::Aggressive,
+    });
+    #[cfg(feature = "llvm")]
+    let session = match command {
+        Command::Run {
+            sandbox,
+            max_output_bytes,
+            seed,
+            ref entry,
+            ..
+        } => {
+            let session = match sandbox {
+                true => session.sandbox(Sandbox::default()),
+                false => session,
+            };
+            let session = match seed {
+                Some(seed) => session.seed(seed),
+                None => session,
+            };
+            let session = match entry {
+                Some(entry) => session.entry(entry),
+                None => session,
+            };
+            match max_output_bytes {
+                Some(max_output_bytes) => session.max_output_bytes(max_output_bytes),
+                None => session,
+            }
+        }
+        Command::Build { deterministic, .. } | Command::Ir { deterministic, .. } => {
+            session.deterministic(deterministic)
+        }
+        _ => session,
+    };
+    let session = session.build();
+
+    let status = match command {
+        Command::Check { files, crate_type } => {
+            let library = matches!(crate_type, CrateType::Staticlib);
+            i32::from(!or_exit(session.check_files(&files, library)))
+        }
+        Command::Fix { files } => {
+            let mut fixed = true;
+            for file in files {
+                fixed &= or_exit(session.fix_file(&file));
+            }
+            i32::from(!fixed)
+        }
+        Command::Fmt {
+            files,
+            range,
+            config,
+        } => {
+            let config = config.map(|path| or_exit(Config::read(&path)));
+            for file in files {
+                let config = match &config {
+                    Some(config) => config.clone(),
+                    None => or_exit(Config::find(&file)),
+                };
+                or_exit(session.fmt_file(&file, range.clone(), &config.fmt));
+            }
+            0
+        }
+        Command::SelfCheckGrammar { dir, bless } => {
+            i32::from(!or_exit(session.check_grammar(&dir, bless, io::stdout())))
+        }
+        Command::Explain { code: Some(code) } => {
+            let code: DiagnosticCode = code.parse().unwrap_or_else(|error| {
+                eprintln!("error: {}", error);
+                process::exit(1)
+            });
+            println!("{}\n\n{}", code, code.explanation());
+            0
+        }
+        Command::Explain { code: None } => {
+            for code in DiagnosticCode::ALL {
+                let explanation = code.explanation();
+                let paragraph = explanation.split("\n\n").next().unwrap_or_default();
+                let paragraph = paragraph.split_whitespace().collect::<Vec<_>>().join(" ");
+                let summary = paragraph.split_inclusive(". ").next().unwrap_or_default();
+                println!("{:<28} {}", code.name(), summary.trim_end());
+            }
+            0
+        }
+        Command::Lsp => {
+            or_exit(serve_lsp(session, io::stdin().lock(), io::stdout()));
+            return;
+        }
+        Command::Doc {
+            file,
+            format,
+            output,
+        } => {
+            let format = match format {
+                DocFormat::Html => doc::Format::Html,
+                DocFormat::Markdown => doc::Format::Markdown,
+            };
+            or_exit(match output {
+                Some(output) => session.doc_file(&file, format, or_exit(File::create(output))),
+                None => session.doc_file(&file, format, io::stdout()),
+            });
+            0
+        }
+        #[cfg(feature = "llvm")]
+        Command::Run {
+            files,
+            cold_start_stats,
+            ..
+        } => {
+            let status = match files.as_slice() {
+                [dir] if dir.is_dir() => or_exit(session.run_dir(dir, io::stdout(), io::stderr())),
+                _ => or_exit(session.run_files(&files, io::stdin(), io::stdout(), io::stderr())),
+            };
+            if cold_start_stats {
+                or_exit(session.profiler().write_cold_start(started, io::stderr()));
+            }
+            status
+        }
+        #[cfg(feature = "llvm")]
+        Command::Build {
+            file,
+            output,
+            crate_type: CrateType::Bin,
+            ..
+        } => {
+            let output = output.unwrap_or_else(|| file.with_extension(""));
+            i32::from(!or_exit(session.build_file(&file, &output)))
+        }
+        #[cfg(feature = "llvm")]
+        Command::Build {
+            file,
+            output,
+            crate_type: CrateType::Staticlib,
+            ..
+        } => {
+            let output = output
+                .unwrap_or_else(|| file.with_file_name(format!("lib{}.a", crate_name(&file))));
+            i32::from(!or_exit(session.build_library_file(&file, &output)))
+        }
+        #[cfg(feature = "llvm")]
+        Command::Ir {
+            file,
+            optimized,
+            bitcode,
+            ..
+        } => {
+            or_exit(session.ir_file(&file, optimized, bitcode.as_deref(), io::stdout()));
+            0
+        }
+        #[cfg(feature = "llvm")]
+        Command::Bench {
+            file,
+            warmup,
+            samples,
+            iterations,
+        } => {
+            let options = BenchOptions {
+                warmup,
+                samples,
+                iterations,
+            };
+            i32::from(!or_exit(session.bench_file(&file, &options, io::stdout())))
+        }
+        #[cfg(feature = "llvm")]
+        Command::Test { file, bless } => {
+            let passed = if file.is_dir() {
+                session.test_ui(&file, bless, io::stdout())
+            } else {
+                session.test_file(&file, io::stdout())
+            };
+            i32::from(!or_exit(passed))
+        }
+        #[cfg(feature = "llvm")]
+        Command::Watch { file } => {
+            or_exit(watch(&session, &file));
+            0
+        }
+        #[cfg(feature = "llvm")]
+        Command::Repl => {
+            or_exit(Repl::new(session).run(io::stdin().lock(), io::stdout()));
+            return;
+        }
+        #[cfg(feature = "llvm")]
+        Command::Serve { host, port } => {
+            or_exit(TcpListener::bind((host, port)).and_then(serve));
+            0
+        }
+    };
+
+    if time_passes {
+        or_exit(session.profiler().write_summary(io::stderr()));
+    }
+    if summary {
+        or_exit(session.counts().write_line(io::stderr()));
+    }
+    if json_summary {
+        or_exit(session.counts().write_json(io::stderr()));
+    }
+    if status != 0 {
+        process::exit(status);
+    }
+}
+
+/// Logs what the compiler does to stderr at `level`, or as the `SCULPT_LOG` filter says if no
+/// level is given. Nothing is logged if neither is set.
+fn init_logging(level: Option<LevelFilter>) {
+    let filter = match level {
+        Some(level) => EnvFilter::new(level.to_string()),
+        None => match env::var("SCULPT_LOG") {
+            Ok(directives) => EnvFilter::try_new(directives).unwrap_or_else(|error| {
+                eprintln!("error: SCULPT_LOG: {}", error);
+                process::exit(1)
+            }),
+            Err(_) => return,
+        },
+    };
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(io::stderr)
+        .with_ansi(io::stderr().is_terminal())
+        .init();
+}
+
+/// Unwraps the result of a command, or reports the error that stopped it before it could report
+/// anything about the source code, like a file that can't be read, and exits.
+fn or_exit<T>(result: io::Result<T>) -> T {
+    result.unwrap_or_else(|error| {
+        eprintln!("error: {}", error);
+        process::exit(1)
+    })
+}
+
+/// Parses a `--range` like `10..42`.
+fn parse_range(range: &str) -> Result<Range<usize>, String> {
+    let (start, end) = range
+        .split_once("..")
+        .ok_or_else(|| "expected a range like `10..42`".to_string())?;
+    let offset = |offset: &str| offset.parse::<usize>().map_err(|error| error.to_string());
+    Ok(offset(start)?..offset(end)?)
+}
+
+/// Polls `file` for changes, re-lowering only the functions that changed before running it again.
+#[cfg(feature = "llvm")]
+fn watch(session: &Session, file: &Path) -> io::Result<()> {
+    let mut incremental = session.incremental(Some(file));
+    let mut last_modified = None;
+    loop {
+        let modified = std::fs::metadata(file)?.modified()?;
+        if last_modified != Some(modified) {
+            last_modified = Some(modified);
+            let source_code = session.sources().read(file)?;
+            match incremental.update(&source_code) {
+                Ok(_) => {
+                    if let Err(error) =
+                        incremental.run(io::stdin().lock(), io::stdout(), io::stderr())
+                    {
+                        session.report(file, &source_code, error.into());
+                    }
+                }
+                Err(error) => session.report(file, &source_code, error),
             }
         }
+        thread::sleep(Duration::from_millis(250));
     }
 }
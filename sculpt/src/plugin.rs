@@ -0,0 +1,217 @@
+//! Native macro plugins: dynamic libraries, loaded with `--plugin`, that expand macros the
+//! compiler doesn't know about. A plugin is a C ABI library exporting:
+//!
+//! ```c
+//! /* Must return PLUGIN_ABI_VERSION. */
+//! uint32_t sculpt_plugin_abi_version(void);
+//!
+//! /* JSON array of the macro names the plugin expands, including their `!`. */
+//! const char *sculpt_plugin_macros(void);
+//!
+//! /* Expands an invocation, given as the JSON of a `syntax::Macro`. Returns JSON of either
+//!  * {"Ok": [statements]}, the statements to replace the invocation with, or
+//!  * {"Err": "message"}. */
+//! char *sculpt_plugin_expand(const char *invocation);
+//!
+//! /* Frees a string sculpt_plugin_expand returned. */
+//! void sculpt_plugin_free(char *json);
+//! ```
+//!
+//! Syntax trees cross the boundary as JSON, in the shape the `syntax` module serializes to. Node
+//! ids and spans can be left out of a plugin's statements, and missing spans point at the
+//! invocation.
+
+use libloading::Library;
+
+use std::ffi::{c_char, CStr, CString};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::intern::Symbol;
+use crate::syntax::Macro;
+
+/// Version of the ABI above. Bumped whenever it changes incompatibly.
+pub const PLUGIN_ABI_VERSION: u32 = 1;
+
+type ExpandFn = unsafe extern "C" fn(*const c_char) -> *mut c_char;
+type FreeFn = unsafe extern "C" fn(*mut c_char);
+
+pub struct Plugin {
+    path: PathBuf,
+    macros: Vec<Symbol>,
+    expand: ExpandFn,
+    free: FreeFn,
+    /// Keeps the functions above loaded.
+    _library: Library,
+}
+
+impl Plugin {
+    /// Loads the plugin at `path`, checking it was built against this version of the ABI.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let error = |message: String| io::Error::other(format!("{}: {}", path.display(), message));
+        // Safety: loading a plugin runs its initializers, which is what passing `--plugin` opts
+        // into. The symbols' types are the ABI's.
+        unsafe {
+            let library = Library::new(path).map_err(|e| error(e.to_string()))?;
+            let version = library
+                .get::<unsafe extern "C" fn() -> u32>(b"sculpt_plugin_abi_version")
+                .map_err(|e| error(e.to_string()))?();
+            if version != PLUGIN_ABI_VERSION {
+                return Err(error(format!(
+                    "plugin ABI version {} is unsupported, expected {}",
+                    version, PLUGIN_ABI_VERSION
+                )));
+            }
+            let macros = library
+                .get::<unsafe extern "C" fn() -> *const c_char>(b"sculpt_plugin_macros")
+                .map_err(|e| error(e.to_string()))?();
+            let macros: Vec<String> = serde_json::from_slice(CStr::from_ptr(macros).to_bytes())
+                .map_err(|e| error(format!("invalid macro list: {}", e)))?;
+            let expand = *library
+                .get::<ExpandFn>(b"sculpt_plugin_expand")
+                .map_err(|e| error(e.to_string()))?;
+            let free = *library
+                .get::<FreeFn>(b"sculpt_plugin_free")
+                .map_err(|e| error(e.to_string()))?;
+            Ok(Self {
+                path: path.to_path_buf(),
+                macros: macros.iter().map(|name| Symbol::intern(name)).collect(),
+                expand,
+                free,
+                _library: library,
+            })
+        }
+    }
+
+    /// Names of the macros the plugin expands, including their `!`.
+    pub fn macros(&self) -> &[Symbol] {
+        &self.macros
+    }
+
+    /// Asks the plugin for the statements to replace `invocation` with, or the message it failed
+    /// with.
+    pub fn expand(&self, invocation: &Macro) -> Result<Vec<Macro>, String> {
+        let invocation = CString::new(serde_json::to_string(invocation).unwrap()).unwrap();
+        // Safety: the plugin returns a string it owns until it's passed back to its free.
+        let json = unsafe {
+            let json = (self.expand)(invocation.as_ptr());
+            if json.is_null() {
+                return Err(format!("{} returned nothing", self.path.display()));
+            }
+            let owned = CStr::from_ptr(json).to_bytes().to_vec();
+            (self.free)(json);
+            owned
+        };
+        serde_json::from_slice::<Result<Vec<Macro>, String>>(&json).unwrap_or_else(|e| {
+            Err(format!(
+                "{} returned an invalid expansion: {}",
+                self.path.display(),
+                e
+            ))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expand::{expand_with, ExpandContext};
+    use crate::run::{parse, Error};
+    use crate::syntax::{Expr, Stmt};
+
+    use std::env;
+    use std::fs;
+    use std::process::Command;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Expands `hello!()` to a `println!`, and fails when given arguments.
+    const HELLO: &str = r#"
+        #include <stdint.h>
+        #include <stdlib.h>
+        #include <string.h>
+
+        uint32_t sculpt_plugin_abi_version(void) { return VERSION; }
+
+        const char *sculpt_plugin_macros(void) { return "[\"hello!\"]"; }
+
+        char *sculpt_plugin_expand(const char *invocation) {
+            if (!strstr(invocation, "\"args\":[]")) {
+                return strdup("{\"Err\":\"hello! takes no arguments\"}");
+            }
+            return strdup("{\"Ok\":[{\"name\":{\"name\":\"println!\"},"
+                          "\"args\":[{\"Str\":{\"val\":\"hello from a plugin\"}}]}]}");
+        }
+
+        void sculpt_plugin_free(char *json) { free(json); }
+    "#;
+
+    /// Compiles `HELLO` as a plugin reporting ABI `version`, and loads it.
+    fn load_hello(version: u32) -> io::Result<Plugin> {
+        static BUILDS: AtomicUsize = AtomicUsize::new(0);
+        let build = BUILDS.fetch_add(1, Ordering::Relaxed);
+        let dir = env::temp_dir().join(format!("sculpt-plugin-{}-{}", std::process::id(), build));
+        fs::create_dir_all(&dir)?;
+        fs::write(dir.join("hello.c"), HELLO)?;
+        let status = Command::new("cc")
+            .current_dir(&dir)
+            .args(["-shared", "-fPIC", "hello.c", "-o", "libhello.so"])
+            .arg(format!("-DVERSION={}", version))
+            .status()?;
+        assert!(status.success());
+        let plugin = Plugin::load(&dir.join("libhello.so"));
+        fs::remove_dir_all(&dir)?;
+        plugin
+    }
+
+    #[test]
+    fn plugin_macros_expand_to_their_statements() {
+        let plugin = load_hello(PLUGIN_ABI_VERSION).unwrap();
+        assert_eq!(plugin.macros(), [Symbol::intern("hello!")]);
+
+        let src = r#"fn main() { hello!(); }"#;
+        let program = expand_with(
+            parse(src).unwrap(),
+            ExpandContext {
+                plugins: &[plugin],
+                ..ExpandContext::default()
+            },
+        )
+        .unwrap();
+        let Stmt::Macro(statement) = &program.functions[0].statements[0] else {
+            panic!("expected a macro statement");
+        };
+        assert_eq!(statement.name.name, Symbol::intern("println!"));
+        assert_eq!(statement.span, 12..20);
+        match &statement.args[..] {
+            [Expr::Str(lit)] => assert_eq!(lit.val.as_str(), "hello from a plugin"),
+            args => panic!("unexpected arguments {:?}", args),
+        }
+    }
+
+    #[test]
+    fn plugin_errors_point_at_the_macro_name() {
+        let plugin = load_hello(PLUGIN_ABI_VERSION).unwrap();
+        let src = r#"fn main() { hello!("world"); }"#;
+        assert_eq!(
+            expand_with(
+                parse(src).unwrap(),
+                ExpandContext {
+                    plugins: &[plugin],
+                    ..ExpandContext::default()
+                },
+            ),
+            Err(Error::PluginFailed(
+                12..18,
+                "hello! takes no arguments".into()
+            ))
+        );
+    }
+
+    #[test]
+    fn plugins_built_for_another_abi_are_rejected() {
+        let error = load_hello(PLUGIN_ABI_VERSION + 1).err().unwrap();
+        assert!(error
+            .to_string()
+            .ends_with("plugin ABI version 2 is unsupported, expected 1"));
+    }
+}
@@ -0,0 +1,283 @@
+use std::cell::{Cell, RefCell};
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Phase {
+    Parse,
+    Lower,
+    Codegen,
+    Verify,
+    Optimize,
+    Link,
+    Jit,
+    Execute,
+}
+
+impl Phase {
+    const ALL: [Phase; 8] = [
+        Phase::Parse,
+        Phase::Lower,
+        Phase::Codegen,
+        Phase::Verify,
+        Phase::Optimize,
+        Phase::Link,
+        Phase::Jit,
+        Phase::Execute,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Phase::Parse => "parse",
+            Phase::Lower => "lower",
+            Phase::Codegen => "codegen",
+            Phase::Verify => "verify",
+            Phase::Optimize => "optimize",
+            Phase::Link => "link",
+            Phase::Jit => "jit",
+            Phase::Execute => "execute",
+        }
+    }
+}
+
+/// Accumulates how long each compilation phase takes across every program run in a session.
+/// Each phase also runs in a `tracing` span, logging when it starts at debug and how long it
+/// took at info.
+#[derive(Default)]
+pub struct Profiler {
+    timings: RefCell<Vec<(Phase, Duration)>>,
+    /// When the first program started executing, and how many timings were recorded before it.
+    first_execute: Cell<Option<(Instant, usize)>>,
+    /// Where the IR of every function built is written, if codegen is verbose.
+    codegen: Option<RefCell<Box<dyn Write>>>,
+}
+
+impl Profiler {
+    /// The profiler, also writing the IR of every function as it's built to `writer`, so a module
+    /// that fails to verify can be pinned on a function.
+    pub fn with_verbose_codegen(self, writer: impl Write + 'static) -> Self {
+        Profiler {
+            codegen: Some(RefCell::new(Box::new(writer))),
+            ..self
+        }
+    }
+
+    /// Writes `ir`, built for the function `name`, if codegen is verbose.
+    pub fn codegen(&self, name: &str, ir: impl FnOnce() -> String) {
+        if let Some(writer) = &self.codegen {
+            // Dumping IR is best effort; a broken writer shouldn't fail compilation.
+            let _ = writeln!(writer.borrow_mut(), "; {}\n{}\n", name, ir().trim_end());
+        }
+    }
+
+    pub fn time<T>(&self, phase: Phase, f: impl FnOnce() -> T) -> T {
+        let _span = tracing::info_span!("phase", name = phase.name()).entered();
+        tracing::debug!("started");
+        let start = Instant::now();
+        if phase == Phase::Execute && self.first_execute.get().is_none() {
+            let recorded = self.timings.borrow().len();
+            self.first_execute.set(Some((start, recorded)));
+        }
+        let result = f();
+        let elapsed = start.elapsed();
+        self.timings.borrow_mut().push((phase, elapsed));
+        tracing::info!(?elapsed, "finished");
+        result
+    }
+
+    /// Total time spent in each phase, in pipeline order. Phases that never ran are omitted.
+    pub fn timings(&self) -> Vec<(Phase, Duration)> {
+        let timings = self.timings.borrow();
+        Phase::ALL
+            .into_iter()
+            .filter(|phase| timings.iter().any(|(p, _)| p == phase))
+            .map(|phase| {
+                let total = timings
+                    .iter()
+                    .filter(|(p, _)| *p == phase)
+                    .map(|(_, duration)| *duration)
+                    .sum();
+                (phase, total)
+            })
+            .collect()
+    }
+
+    pub fn write_summary(&self, mut writer: impl Write) -> io::Result<()> {
+        let timings = self.timings();
+        let total: Duration = timings.iter().map(|(_, duration)| *duration).sum();
+        writeln!(writer, "{:<10} {:>12} {:>7}", "phase", "time", "%")?;
+        for (phase, duration) in timings {
+            writeln!(
+                writer,
+                "{:<10} {:>12} {:>6.1}%",
+                phase.name(),
+                format!("{:.3?}", duration),
+                100.0 * duration.as_secs_f64() / total.as_secs_f64().max(f64::EPSILON),
+            )?;
+        }
+        writeln!(writer, "{:<10} {:>12}", "total", format!("{:.3?}", total))
+    }
+
+    /// Writes how long it took from `started`, when the process started, until the first program
+    /// began executing, split into the phases that ran before it, and how long executing took.
+    pub fn write_cold_start(&self, started: Instant, mut writer: impl Write) -> io::Result<()> {
+        let Some((executed, recorded)) = self.first_execute.get() else {
+            return writeln!(writer, "no program was executed");
+        };
+        let startup = executed.saturating_duration_since(started);
+        let timings = self.timings.borrow();
+        writeln!(
+            writer,
+            "{:<10} {:>12}",
+            "startup",
+            format!("{:.3?}", startup)
+        )?;
+        let mut compiling = Duration::ZERO;
+        for phase in Phase::ALL {
+            let ran: Vec<_> = timings[..recorded]
+                .iter()
+                .filter(|(p, _)| *p == phase)
+                .map(|(_, duration)| *duration)
+                .collect();
+            if !ran.is_empty() {
+                let duration: Duration = ran.into_iter().sum();
+                compiling += duration;
+                writeln!(
+                    writer,
+                    "  {:<8} {:>12}",
+                    phase.name(),
+                    format!("{:.3?}", duration)
+                )?;
+            }
+        }
+        let other = startup.saturating_sub(compiling);
+        writeln!(writer, "  {:<8} {:>12}", "other", format!("{:.3?}", other))?;
+        let executing: Duration = timings[recorded..]
+            .iter()
+            .filter(|(p, _)| *p == Phase::Execute)
+            .map(|(_, duration)| *duration)
+            .sum();
+        writeln!(
+            writer,
+            "{:<10} {:>12}",
+            "execute",
+            format!("{:.3?}", executing)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn timings_are_summed_per_phase_in_pipeline_order() {
+        let profiler = Profiler::default();
+        profiler.timings.borrow_mut().extend([
+            (Phase::Codegen, Duration::from_millis(2)),
+            (Phase::Parse, Duration::from_millis(1)),
+            (Phase::Codegen, Duration::from_millis(3)),
+        ]);
+        assert_eq!(
+            profiler.timings(),
+            [
+                (Phase::Parse, Duration::from_millis(1)),
+                (Phase::Codegen, Duration::from_millis(5)),
+            ]
+        );
+    }
+
+    #[test]
+    fn cold_start_only_counts_phases_before_the_first_execution() {
+        let started = Instant::now();
+        let profiler = Profiler::default();
+        profiler.time(Phase::Parse, || {});
+        profiler.time(Phase::Execute, || {});
+        profiler.time(Phase::Codegen, || {});
+        let mut stats = Vec::new();
+        profiler.write_cold_start(started, &mut stats).unwrap();
+        let stats = String::from_utf8(stats).unwrap();
+        let rows: Vec<_> = stats
+            .lines()
+            .map(|line| line.split_whitespace().next())
+            .collect();
+        assert_eq!(
+            rows,
+            [
+                Some("startup"),
+                Some("parse"),
+                Some("other"),
+                Some("execute")
+            ]
+        );
+    }
+
+    /// A log that can still be read once the subscriber or profiler writing to it has it.
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Shared {
+        fn take(&self) -> Vec<u8> {
+            std::mem::take(&mut *self.0.lock().unwrap())
+        }
+    }
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn phases_are_logged_in_nested_spans() {
+        let log = Shared::default();
+        let writer = log.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_max_level(tracing::Level::DEBUG)
+            .with_writer(move || writer.clone())
+            .with_ansi(false)
+            .with_target(false)
+            .without_time()
+            .finish();
+        let profiler = Profiler::default();
+        tracing::subscriber::with_default(subscriber, || {
+            profiler.time(Phase::Lower, || profiler.time(Phase::Parse, || {}))
+        });
+        let log = String::from_utf8(log.take()).unwrap();
+        let lines: Vec<_> = log
+            .lines()
+            .map(|line| line.split(" elapsed=").next())
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                Some("DEBUG phase{name=\"lower\"}: started"),
+                Some("DEBUG phase{name=\"lower\"}:phase{name=\"parse\"}: started"),
+                Some(" INFO phase{name=\"lower\"}:phase{name=\"parse\"}: finished"),
+                Some(" INFO phase{name=\"lower\"}: finished"),
+            ]
+        );
+    }
+
+    #[test]
+    fn codegen_is_only_written_when_verbose() {
+        let profiler = Profiler::default();
+        profiler.codegen("f", || unreachable!());
+        let log = Shared::default();
+        let profiler = profiler.with_verbose_codegen(log.clone());
+        profiler.codegen("f", || "define void @f() {\n}\n".to_string());
+        assert_eq!(log.take(), b"; f\ndefine void @f() {\n}\n\n");
+    }
+
+    #[test]
+    fn time_returns_result_of_timed_closure() {
+        let profiler = Profiler::default();
+        assert_eq!(profiler.time(Phase::Parse, || 42), 42);
+        assert_eq!(profiler.timings().len(), 1);
+    }
+}
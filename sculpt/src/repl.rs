@@ -0,0 +1,252 @@
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+
+use crate::hir::{self, Stmt};
+use crate::intern::sym;
+use crate::llvm::{call, Streams};
+use crate::run::{parse, parse_statements, Error};
+use crate::session::Session;
+
+const HELP: &str = "\
+:ast    show the syntax tree of the last input
+:ir     show the LLVM IR of everything entered so far
+:type   show the type of an expression, as in `:type 1 + 2`
+:clear  forget everything entered so far
+:help   show this message";
+
+/// Interactive loop running each line of `input`. Lines declaring items, like `fn`s, add them to
+/// the program, and other lines are run as statements at the end of its `main`, after those of
+/// the lines before them, so they can use what those bound.
+pub struct Repl {
+    session: Session,
+    /// The items entered so far, a line each.
+    items: String,
+    /// The statements entered so far, a line each, which are run again before each new line.
+    statements: String,
+    /// How many bytes running `statements` writes to stdout and stderr, which running them again
+    /// writes without showing.
+    printed: (usize, usize),
+    last: Option<String>,
+}
+
+impl Repl {
+    pub fn new(session: Session) -> Self {
+        Self {
+            session,
+            items: String::new(),
+            statements: String::new(),
+            printed: (0, 0),
+            last: None,
+        }
+    }
+
+    pub fn run(&mut self, input: impl BufRead, mut output: impl Write) -> io::Result<()> {
+        write!(output, "> ")?;
+        output.flush()?;
+        for line in input.lines() {
+            let line = line?;
+            let line = line.trim();
+            match line.strip_prefix(':') {
+                Some(command) => self.meta_command(command, &mut output)?,
+                None if line.is_empty() => {}
+                None => self.eval(line.to_string(), &mut output)?,
+            }
+            write!(output, "> ")?;
+            output.flush()?;
+        }
+        writeln!(output)
+    }
+
+    fn meta_command(&mut self, command: &str, mut output: impl Write) -> io::Result<()> {
+        let (command, arg) = command
+            .split_once(char::is_whitespace)
+            .unwrap_or((command, ""));
+        match (command, &self.last) {
+            ("help", _) => writeln!(output, "{}", HELP),
+            ("type", _) => self.type_of(arg.trim(), output),
+            ("clear", _) => {
+                self.items.clear();
+                self.statements.clear();
+                self.printed = (0, 0);
+                self.last = None;
+                Ok(())
+            }
+            ("ast" | "ir", None) => writeln!(output, "no previous input"),
+            ("ast", Some(last)) => match parse(last).or_else(|_| parse_statements(last)) {
+                Ok(program) => writeln!(output, "{:#?}", program),
+                Err(error) => {
+                    self.report(last, error);
+                    Ok(())
+                }
+            },
+            ("ir", Some(_)) => {
+                let source = program(&self.items, &self.statements);
+                let compiler = self.session.compiler();
+                let mut streams = Streams::new(io::empty(), io::sink(), io::sink());
+                match parse(&source).and_then(|p| compiler.build_program(p, &mut streams)) {
+                    Ok(()) => write!(output, "{}", compiler.ir()),
+                    Err(error) => {
+                        self.report(&source, error);
+                        Ok(())
+                    }
+                }
+            }
+            _ => writeln!(output, "unknown command `:{}`\n{}", command, HELP),
+        }
+    }
+
+    /// Writes the type lowering infers for `expr`, checked as the value of a `let` after the
+    /// statements entered so far.
+    fn type_of(&self, expr: &str, mut output: impl Write) -> io::Result<()> {
+        if expr.is_empty() {
+            return writeln!(output, "usage: :type <expr>");
+        }
+        let statements = format!("{}let _ = {};\n", self.statements, expr);
+        let source = program(&self.items, &statements);
+        match parse(&source).and_then(hir::lower) {
+            Ok(program) => {
+                let main = program.functions.iter().find(|f| f.name == sym::MAIN);
+                match main.and_then(|main| main.body.last()) {
+                    Some(Stmt::Let(_, value)) => writeln!(output, "{}", value.ty()),
+                    _ => writeln!(output, "`{}` has no type of its own", expr),
+                }
+            }
+            Err(error) => {
+                self.report(&source, error);
+                Ok(())
+            }
+        }
+    }
+
+    /// Runs the program with `line` added to it, keeping it if it compiles and runs.
+    fn eval(&mut self, line: String, mut output: impl Write) -> io::Result<()> {
+        let (mut items, mut statements) = (self.items.clone(), self.statements.clone());
+        let added = if parse(&line).is_ok() {
+            &mut items
+        } else {
+            &mut statements
+        };
+        added.push_str(&line);
+        added.push('\n');
+        let source = program(&items, &statements);
+        let mut std_out = Vec::new();
+        let mut std_err = Vec::new();
+        let succeeded = {
+            let compiler = self.session.compiler();
+            let mut streams = Streams::new(io::empty(), &mut std_out, &mut std_err);
+            let result = parse(&source)
+                .and_then(|p| compiler.build_program(p, &mut streams))
+                .and_then(|()| {
+                    let main = compiler.function("main").unwrap();
+                    unsafe { call(&main) }.map_err(Error::from)
+                });
+            result.map_err(|error| self.report(&source, error)).is_ok()
+        };
+        let (out, err) = self.printed;
+        output.write_all(&std_out[out.min(std_out.len())..])?;
+        io::stderr().write_all(&std_err[err.min(std_err.len())..])?;
+        output.flush()?;
+        if succeeded {
+            self.items = items;
+            self.statements = statements;
+            self.printed = (std_out.len(), std_err.len());
+        }
+        self.last = Some(line);
+        Ok(())
+    }
+
+    fn report(&self, source: &str, error: Error) {
+        self.session.report(Path::new("<repl>"), source, error);
+    }
+}
+
+/// The program made of `items` and a `main` running `statements`.
+fn program(items: &str, statements: &str) -> String {
+    format!("{}fn main() {{\n{}}}\n", items, statements)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn repl(input: &str) -> String {
+        let session = Session::builder().diagnostics(io::sink()).build();
+        let mut output = Vec::new();
+        Repl::new(session)
+            .run(input.as_bytes(), &mut output)
+            .unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn statements_are_run_immediately() {
+        assert_eq!(
+            repl("print!(\"a\"); println!(\"b\");\nprintln!(\"c\");\n"),
+            "> ab\n> c\n> \n"
+        );
+    }
+
+    #[test]
+    fn ir_of_last_input_is_shown() {
+        let output = repl("println!(\"hi\");\n:ir\n");
+        assert!(output.contains("define void @main()"));
+    }
+
+    #[test]
+    fn ast_of_last_input_is_shown() {
+        let output = repl("print!(\"hi\");\n:ast\n");
+        assert!(output.contains("name: \"print!\""));
+    }
+
+    #[test]
+    fn type_of_expression_is_shown() {
+        assert_eq!(
+            repl(":type 1 + 2\n:type \"a\"\n:type Some(1)\n"),
+            "> i64\n> str\n> Option<i64>\n> \n"
+        );
+    }
+
+    #[test]
+    fn type_without_expression_shows_usage() {
+        assert_eq!(repl(":type\n"), "> usage: :type <expr>\n> \n");
+    }
+
+    #[test]
+    fn clear_forgets_last_input() {
+        assert_eq!(
+            repl("print!(\"hi\");\n:clear\n:ast\n"),
+            "> hi> > no previous input\n> \n"
+        );
+    }
+
+    #[test]
+    fn bindings_and_items_outlive_their_line() {
+        assert_eq!(
+            repl(concat!(
+                "let x = 4;\n",
+                "fn double(n: i64) -> i64 { n * 2 }\n",
+                "println!(\"{}\", double(x));\n",
+                ":type x\n",
+                "let y = x + 1;\n",
+                "println!(\"{} {}\", x, y);\n",
+            )),
+            "> > > 8\n> i64\n> > 4 5\n> \n"
+        );
+    }
+
+    #[test]
+    fn failed_lines_are_forgotten() {
+        assert_eq!(
+            repl("let x = 4;\nlet y = z;\nprintln!(\"{}\", x);\n"),
+            "> > > 4\n> \n"
+        );
+    }
+
+    #[test]
+    fn clear_forgets_bindings() {
+        assert_eq!(
+            repl("let x = 4;\n:clear\nprintln!(\"{}\", x);\n:type x\n"),
+            "> > > > > \n"
+        );
+    }
+}
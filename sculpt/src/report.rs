@@ -1,10 +1,24 @@
-use ariadne::{sources, ColorGenerator, Config, Fmt, Label, Report, ReportKind};
+use ariadne::{
+    sources, Color, ColorGenerator, Config, Fmt, Label, Report, ReportBuilder, ReportKind,
+};
 use lalrpop_util::ParseError;
 
+use std::ops::Range;
+
+use crate::expand::RECURSION_LIMIT;
+use crate::fix::fix;
 use crate::grammar::Token;
+use crate::lint::{Lint, Warning};
+use crate::locale::{msg, msg_n};
 use crate::run::Error;
+use crate::syntax::SyntaxError;
 
-// TODO: Print `identifier` instead of regex string. Might require custom token type?
+/// How many labels an error that points at every one of a list of spans, like the unused
+/// arguments of a macro call, renders by default. The rest are summed up in a note.
+pub const MAX_LABELS: usize = 16;
+
+/// Writes `error`, raised in `source_code` read from `file`, out to `writer`, with at most
+/// [`MAX_LABELS`] labels for each list of spans it points at.
 pub fn report_error(
     file: &std::path::Path,
     source_code: &str,
@@ -12,96 +26,1362 @@ pub fn report_error(
     colored: bool,
     writer: impl std::io::Write,
 ) {
-    let file = file.as_os_str().to_str().unwrap().to_string();
+    report_error_with_max_labels(file, source_code, error, colored, MAX_LABELS, writer)
+}
+
+/// The first `max_labels` of `spans`, and how many were left out, so generated code with a huge
+/// list of them, like a format string with 100k `{}`, doesn't make an enormous report.
+fn cap<T>(mut spans: Vec<T>, max_labels: usize) -> (Vec<T>, usize) {
+    let more = spans.len().saturating_sub(max_labels);
+    spans.truncate(max_labels);
+    (spans, more)
+}
+
+/// `builder` with a note saying `more` labels were left out, if any were.
+fn note_more<'a>(
+    builder: ReportBuilder<'a, (String, Range<usize>)>,
+    more: usize,
+) -> ReportBuilder<'a, (String, Range<usize>)> {
+    match more {
+        0 => builder,
+        more => builder.with_note(msg_n!("more-labels", more)),
+    }
+}
+
+/// `builder` with `note`, followed by one saying `more` labels were left out if any were, as a
+/// report only has room for one.
+fn with_notes<'a>(
+    builder: ReportBuilder<'a, (String, Range<usize>)>,
+    note: String,
+    more: usize,
+) -> ReportBuilder<'a, (String, Range<usize>)> {
+    match more {
+        0 => builder.with_note(note),
+        more => builder.with_note(msg_n!("note-and-more", more, note = note)),
+    }
+}
+
+/// A note teaching what the formatting macro `name` takes.
+fn fmt_signature<D: std::fmt::Display>(
+    source_code: &str,
+    name: Range<usize>,
+    fg: impl Fn(String, Color) -> D,
+    color: Color,
+) -> String {
+    msg!(
+        "fmt-signature",
+        name = fg(format!("`{}`", &source_code[name]), color)
+    )
+}
+
+/// Labels showing the `{}` at the first span of `pair` takes the argument at the second, the
+/// `index`th.
+fn argument_labels(
+    file: &str,
+    index: usize,
+    (specifier, argument): (Range<usize>, Range<usize>),
+    color: Color,
+) -> [Label<(String, Range<usize>)>; 2] {
+    let nth = index + 1;
+    [
+        Label::new((file.to_string(), specifier))
+            .with_message(msg!("takes-argument", nth = nth))
+            .with_color(color),
+        Label::new((file.to_string(), argument))
+            .with_message(msg!("argument", nth = nth))
+            .with_color(color),
+    ]
+}
+
+// TODO: Print `identifier` instead of regex string. Might require custom token type?
+/// Like [`report_error`], rendering at most `max_labels` labels for each list of spans.
+pub fn report_error_with_max_labels(
+    file: &std::path::Path,
+    source_code: &str,
+    error: Error,
+    colored: bool,
+    max_labels: usize,
+    writer: impl std::io::Write,
+) {
+    let file = file.to_string_lossy().into_owned();
     let config = Config::default().with_color(colored);
     let mut colors = ColorGenerator::new();
     let a = colors.next();
     let b = colors.next();
     let fg = |text: String, color| text.to_string().fg(colored.then_some(color));
+    let fix = fix(&error, source_code);
+    let code = error.code();
 
     let builder = match error {
+        Error::KeywordAsName(range)
+        | Error::ParseError(ParseError::User {
+            error: SyntaxError::KeywordAsName(range),
+        }) => {
+            let keyword = &source_code[range.clone()];
+            Report::build(ReportKind::Error, file.clone(), range.start)
+                .with_config(config)
+                .with_code(code)
+                .with_message(msg!(
+                    "keyword-as-name",
+                    keyword = fg(format!("`{}`", keyword), a)
+                ))
+                .with_label(
+                    Label::new((file.clone(), range))
+                        .with_message(msg!("keyword-as-name.label"))
+                        .with_color(a),
+                )
+                .with_help(msg!("keyword-as-name.help", keyword = keyword))
+        }
+        Error::UnknownFragment(range)
+        | Error::ParseError(ParseError::User {
+            error: SyntaxError::UnknownFragment(range),
+        }) => Report::build(ReportKind::Error, file.clone(), range.start)
+            .with_config(config)
+            .with_code(code)
+            .with_message(msg!(
+                "unknown-fragment",
+                fragment = fg(format!("`{}`", &source_code[range.clone()]), a)
+            ))
+            .with_label(
+                Label::new((file.clone(), range))
+                    .with_message(msg!("unknown-fragment.label"))
+                    .with_color(a),
+            ),
+        Error::UnboundMetaVar(range) => Report::build(ReportKind::Error, file.clone(), range.start)
+            .with_config(config)
+            .with_code(code)
+            .with_message(msg!(
+                "unbound-meta-var",
+                name = fg(format!("`{}`", &source_code[range.clone()]), a)
+            ))
+            .with_label(
+                Label::new((file.clone(), range))
+                    .with_message(msg!("unbound-meta-var.label"))
+                    .with_color(a),
+            ),
+        Error::NoMatchingMacroRule(range, args) => {
+            Report::build(ReportKind::Error, file.clone(), range.start)
+                .with_config(config)
+                .with_code(code)
+                .with_message(msg_n!(
+                    "no-matching-macro-rule",
+                    args,
+                    name = fg(format!("`{}`", &source_code[range.clone()]), a)
+                ))
+                .with_label(
+                    Label::new((file.clone(), range))
+                        .with_message(msg!("no-matching-macro-rule.label"))
+                        .with_color(a),
+                )
+        }
+        Error::MacroRecursionLimit(range) => {
+            Report::build(ReportKind::Error, file.clone(), range.start)
+                .with_config(config)
+                .with_code(code)
+                .with_message(msg!(
+                    "macro-recursion-limit",
+                    name = fg(format!("`{}`", &source_code[range.clone()]), a)
+                ))
+                .with_label(
+                    Label::new((file.clone(), range))
+                        .with_message(msg!("macro-recursion-limit.label", limit = RECURSION_LIMIT))
+                        .with_color(a),
+                )
+        }
+        Error::PluginFailed(range, message) => {
+            Report::build(ReportKind::Error, file.clone(), range.start)
+                .with_config(config)
+                .with_code(code)
+                .with_message(msg!(
+                    "plugin-failed",
+                    name = fg(format!("`{}`", &source_code[range.clone()]), a)
+                ))
+                .with_label(
+                    Label::new((file.clone(), range))
+                        .with_message(message)
+                        .with_color(a),
+                )
+        }
+        Error::ExpectedStrLit(range) => Report::build(ReportKind::Error, file.clone(), range.start)
+            .with_config(config)
+            .with_code(code)
+            .with_message(msg!("expected-str-lit"))
+            .with_label(
+                Label::new((file.clone(), range))
+                    .with_message(msg!("expected-str-lit.label"))
+                    .with_color(a),
+            ),
+        Error::IncludeFailed(range, path, message) => {
+            Report::build(ReportKind::Error, file.clone(), range.start)
+                .with_config(config)
+                .with_code(code)
+                .with_message(msg!("include-failed", path = fg(format!("`{}`", path), a)))
+                .with_label(
+                    Label::new((file.clone(), range))
+                        .with_message(message)
+                        .with_color(a),
+                )
+        }
+        Error::EnvVarUnset(range, message) => {
+            Report::build(ReportKind::Error, file.clone(), range.start)
+                .with_config(config)
+                .with_code(code)
+                .with_message(message)
+                .with_label(
+                    Label::new((file.clone(), range))
+                        .with_message(msg!("env-var-unset.label"))
+                        .with_color(a),
+                )
+        }
         Error::MissingFmtStr(range) => Report::build(ReportKind::Error, file.clone(), range.start)
             .with_config(config)
-            .with_code("MissingFmtStr")
+            .with_code(code)
+            .with_label(
+                Label::new((file.clone(), range.clone()))
+                    .with_message(msg!("missing-fmt-str.label"))
+                    .with_color(a),
+            )
+            .with_note(fmt_signature(source_code, range, fg, a)),
+        Error::UnmatchedFmtBrace(range, brace) => {
+            // A brace in the source is fixed by escaping it, which the fix's help suggests.
+            let help = (range.len() != 1).then(|| {
+                msg!(
+                    "unmatched-fmt-brace.help",
+                    brace = brace,
+                    escaped = brace.to_string().repeat(2)
+                )
+            });
+            let builder = Report::build(ReportKind::Error, file.clone(), range.start)
+                .with_config(config)
+                .with_code(code)
+                .with_message(msg!(
+                    "unmatched-fmt-brace",
+                    brace = fg(format!("`{}`", brace), a)
+                ))
+                .with_label(
+                    Label::new((file.clone(), range))
+                        .with_message(match brace {
+                            '{' => msg!("unmatched-fmt-brace.opens"),
+                            _ => msg!("unmatched-fmt-brace.closes"),
+                        })
+                        .with_color(a),
+                );
+            match help {
+                Some(help) => builder.with_help(help),
+                None => builder,
+            }
+        }
+        Error::UnknownMacro(range) => Report::build(ReportKind::Error, file.clone(), range.start)
+            .with_config(config)
+            .with_code(code)
+            .with_message(msg!(
+                "unknown-macro",
+                name = fg(format!("`{}`", &source_code[range.clone()]), a)
+            ))
+            .with_label(
+                Label::new((file.clone(), range))
+                    .with_message(msg!("unknown-macro.label"))
+                    .with_color(a),
+            ),
+        Error::FmtStrNotLiteral(range) => {
+            Report::build(ReportKind::Error, file.clone(), range.start)
+                .with_config(config)
+                .with_code(code)
+                .with_message(msg!("fmt-str-not-literal"))
+                .with_label(
+                    Label::new((file.clone(), range))
+                        .with_message(msg!("fmt-str-not-literal.label"))
+                        .with_color(a),
+                )
+        }
+        Error::UnresolvedName(range) => Report::build(ReportKind::Error, file.clone(), range.start)
+            .with_config(config)
+            .with_code(code)
+            .with_message(msg!(
+                "unresolved-name",
+                name = fg(format!("`{}`", &source_code[range.clone()]), a)
+            ))
+            .with_label(
+                Label::new((file.clone(), range))
+                    .with_message(msg!("not-found-in-scope"))
+                    .with_color(a),
+            ),
+        Error::IntLiteralTooLarge(range) => {
+            Report::build(ReportKind::Error, file.clone(), range.start)
+                .with_config(config)
+                .with_code(code)
+                .with_message(msg!("int-literal-too-large"))
+                .with_label(
+                    Label::new((file.clone(), range))
+                        .with_message(msg!(
+                            "int-literal-too-large.label",
+                            ty = fg("`i64`".into(), a)
+                        ))
+                        .with_color(a),
+                )
+        }
+        Error::NonIntegerOperand(range, ty) => {
+            Report::build(ReportKind::Error, file.clone(), range.start)
+                .with_config(config)
+                .with_code(code)
+                .with_message(msg!(
+                    "expected-found",
+                    expected = fg("`i64`".into(), a),
+                    found = fg(format!("`{}`", ty), b)
+                ))
+                .with_label(
+                    Label::new((file.clone(), range))
+                        .with_message(msg!("non-integer-operand.label"))
+                        .with_color(b),
+                )
+        }
+        Error::MismatchedArgumentType(range, expected, found) => {
+            Report::build(ReportKind::Error, file.clone(), range.start)
+                .with_config(config)
+                .with_code(code)
+                .with_message(msg!(
+                    "expected-found",
+                    expected = fg(format!("`{}`", expected), a),
+                    found = fg(format!("`{}`", found), b)
+                ))
+                .with_label(
+                    Label::new((file.clone(), range))
+                        .with_message(msg!("mismatched-argument-type.label", expected = expected))
+                        .with_color(b),
+                )
+        }
+        Error::MismatchedArgumentCount(name, expected, found) => {
+            let arguments = |count| msg_n!("arguments", count);
+            Report::build(ReportKind::Error, file.clone(), name.start)
+                .with_config(config)
+                .with_code(code)
+                .with_message(msg_n!(
+                    "takes-but-supplied",
+                    found,
+                    name = fg(format!("`{}`", &source_code[name.clone()]), a),
+                    expected = arguments(expected),
+                    found = arguments(found)
+                ))
+                .with_label(
+                    Label::new((file.clone(), name))
+                        .with_message(msg!("expected-count", expected = arguments(expected)))
+                        .with_color(a),
+                )
+        }
+        Error::NoMethod(name, ty) => Report::build(ReportKind::Error, file.clone(), name.start)
+            .with_config(config)
+            .with_code(code)
+            .with_message(msg!(
+                "no-method",
+                name = fg(format!("`{}`", &source_code[name.clone()]), a),
+                ty = fg(format!("`{}`", ty), b)
+            ))
+            .with_label(
+                Label::new((file.clone(), name))
+                    .with_message(msg!("no-method.label", ty = ty))
+                    .with_color(a),
+            ),
+        Error::MismatchedArmTypes(range, expected, found) => {
+            Report::build(ReportKind::Error, file.clone(), range.start)
+                .with_config(config)
+                .with_code(code)
+                .with_message(msg!("mismatched-arm-types"))
+                .with_label(
+                    Label::new((file.clone(), range))
+                        .with_message(msg!(
+                            "expected-found",
+                            expected = fg(format!("`{}`", expected), a),
+                            found = fg(format!("`{}`", found), b)
+                        ))
+                        .with_color(b),
+                )
+        }
+        Error::MismatchedPatternType(range, ty, found) => {
+            Report::build(ReportKind::Error, file.clone(), range.start)
+                .with_config(config)
+                .with_code(code)
+                .with_message(msg!(
+                    "expected-found",
+                    expected = fg(format!("`{}`", ty), a),
+                    found = fg(format!("`{}`", found), b)
+                ))
+                .with_label(
+                    Label::new((file.clone(), range))
+                        .with_message(msg!("mismatched-pattern-type.label", ty = ty))
+                        .with_color(b),
+                )
+        }
+        Error::NonExhaustiveMatch(range, witness) => {
+            Report::build(ReportKind::Error, file.clone(), range.start)
+                .with_config(config)
+                .with_code(code)
+                .with_message(msg!(
+                    "non-exhaustive-match",
+                    witness = fg(format!("`{}`", witness), a)
+                ))
+                .with_label(
+                    Label::new((file.clone(), range))
+                        .with_message(msg!("pattern-not-covered", witness = witness))
+                        .with_color(a),
+                )
+                .with_help(msg!("non-exhaustive-match.help", witness = witness))
+        }
+        Error::NotDisplayable(range, ty) => {
+            Report::build(ReportKind::Error, file.clone(), range.start)
+                .with_config(config)
+                .with_code(code)
+                .with_message(msg!("not-displayable", ty = fg(format!("`{}`", ty), a)))
+                .with_label(
+                    Label::new((file.clone(), range))
+                        .with_message(msg!("not-displayable.label"))
+                        .with_color(a),
+                )
+                .with_help(msg!("not-displayable.help"))
+        }
+        Error::NotDebuggable(range, ty) => {
+            Report::build(ReportKind::Error, file.clone(), range.start)
+                .with_config(config)
+                .with_code(code)
+                .with_message(msg!("not-debuggable", ty = fg(format!("`{}`", ty), a)))
+                .with_label(
+                    Label::new((file.clone(), range))
+                        .with_message(msg!("not-debuggable.label"))
+                        .with_color(a),
+                )
+                .with_help(msg!("not-debuggable.help"))
+        }
+        Error::MismatchedFmtType(range, spec_range, format, ty) => {
+            Report::build(ReportKind::Error, file.clone(), range.start)
+                .with_config(config)
+                .with_code(code)
+                .with_message(msg!(
+                    "mismatched-fmt-type",
+                    ty = fg(format!("`{}`", ty), a),
+                    spec = format.spec()
+                ))
+                .with_label(
+                    Label::new((file.clone(), spec_range))
+                        .with_message(msg!("mismatched-fmt-type.spec", spec = format.spec()))
+                        .with_color(b),
+                )
+                .with_label(
+                    Label::new((file.clone(), range))
+                        .with_message(msg!("mismatched-fmt-type.label", ty = ty))
+                        .with_color(a),
+                )
+                .with_help(msg!("mismatched-fmt-type.help"))
+        }
+        Error::TypeAnnotationsNeeded(range) => {
+            Report::build(ReportKind::Error, file.clone(), range.start)
+                .with_config(config)
+                .with_code(code)
+                .with_message(msg!("type-annotations-needed"))
+                .with_label(
+                    Label::new((file.clone(), range))
+                        .with_message(msg!("type-annotations-needed.label"))
+                        .with_color(a),
+                )
+        }
+        Error::UnknownType(range) => Report::build(ReportKind::Error, file.clone(), range.start)
+            .with_config(config)
+            .with_code(code)
+            .with_message(msg!(
+                "unknown-type",
+                name = fg(format!("`{}`", &source_code[range.clone()]), a)
+            ))
+            .with_label(
+                Label::new((file.clone(), range))
+                    .with_message(msg!("not-found-in-scope"))
+                    .with_color(a),
+            ),
+        Error::UnsupportedReturnType(range, ty) => {
+            Report::build(ReportKind::Error, file.clone(), range.start)
+                .with_config(config)
+                .with_code(code)
+                .with_message(msg!(
+                    "unsupported-return-type",
+                    ty = fg(format!("`{}`", ty), a)
+                ))
+                .with_label(
+                    Label::new((file.clone(), range))
+                        .with_message(msg!("unsupported-return-type.label"))
+                        .with_color(a),
+                )
+                .with_help(msg!("unsupported-return-type.help"))
+        }
+        Error::MismatchedReturnType(range, expected, found) => {
+            Report::build(ReportKind::Error, file.clone(), range.start)
+                .with_config(config)
+                .with_code(code)
+                .with_message(msg!(
+                    "expected-found",
+                    expected = fg(format!("`{}`", expected), a),
+                    found = fg(format!("`{}`", found), b)
+                ))
+                .with_label(
+                    Label::new((file.clone(), range))
+                        .with_message(msg!("mismatched-return-type.label", expected = expected))
+                        .with_color(b),
+                )
+        }
+        Error::EntryPointParameters(name) => {
+            let name_str = &source_code[name.clone()];
+            Report::build(ReportKind::Error, file.clone(), name.start)
+                .with_config(config)
+                .with_code(code)
+                .with_message(msg!(
+                    "entry-point-parameters",
+                    name = fg(format!("`{}`", name_str), a)
+                ))
+                .with_label(
+                    Label::new((file.clone(), name))
+                        .with_message(msg!("entry-point-parameters.label"))
+                        .with_color(a),
+                )
+        }
+        Error::MissingEntryPoint(end, name) => {
+            Report::build(ReportKind::Error, file.clone(), end.start)
+                .with_config(config)
+                .with_code(code)
+                .with_message(msg!(
+                    "missing-entry-point",
+                    name = fg(format!("`{}`", name), a)
+                ))
+                .with_label(
+                    Label::new((file.clone(), end))
+                        .with_message(msg!("missing-entry-point.label", name = name))
+                        .with_color(a),
+                )
+                .with_help(msg!("missing-entry-point.help", name = name))
+        }
+        Error::UnexportableFunction(name, ty) => {
+            let name_str = &source_code[name.clone()];
+            Report::build(ReportKind::Error, file.clone(), name.start)
+                .with_config(config)
+                .with_code(code)
+                .with_message(msg!(
+                    "unexportable-function",
+                    name = fg(format!("`{}`", name_str), a)
+                ))
+                .with_label(
+                    Label::new((file.clone(), name))
+                        .with_message(msg!("unexportable-function.label", ty = ty))
+                        .with_color(a),
+                )
+                .with_help(msg!("unexportable-function.help"))
+        }
+        Error::UnknownAttribute(name) => {
+            let name_str = &source_code[name.clone()];
+            Report::build(ReportKind::Error, file.clone(), name.start)
+                .with_config(config)
+                .with_code(code)
+                .with_message(msg!(
+                    "unknown-attribute",
+                    name = fg(format!("`{}`", name_str), a)
+                ))
+                .with_label(
+                    Label::new((file.clone(), name))
+                        .with_message(msg!("unknown-attribute.label"))
+                        .with_color(a),
+                )
+                .with_help(msg!("unknown-attribute.help"))
+        }
+        Error::MisplacedAttribute(range) => {
+            let attr = &source_code[range.clone()];
+            Report::build(ReportKind::Error, file.clone(), range.start)
+                .with_config(config)
+                .with_code(code)
+                .with_message(msg!(
+                    "misplaced-attribute",
+                    attr = fg(format!("`{}`", attr), a)
+                ))
+                .with_label(
+                    Label::new((file.clone(), range))
+                        .with_message(msg!("misplaced-attribute.label"))
+                        .with_color(a),
+                )
+                .with_help(msg!("misplaced-attribute.help"))
+        }
+        Error::UnknownLint(name) => {
+            let name_str = &source_code[name.clone()];
+            Report::build(ReportKind::Error, file.clone(), name.start)
+                .with_config(config)
+                .with_code(code)
+                .with_message(msg!(
+                    "unknown-lint",
+                    name = fg(format!("`{}`", name_str), a)
+                ))
+                .with_label(
+                    Label::new((file.clone(), name))
+                        .with_message(msg!("unknown-lint.label"))
+                        .with_color(a),
+                )
+                .with_help(msg!(
+                    "unknown-lint.help",
+                    lints = Lint::ALL
+                        .map(|lint| format!("`{}`", lint.name()))
+                        .join(", ")
+                ))
+        }
+        Error::DeniedLint(warning, attr) => {
+            let (message, label, help) = describe_warning(&warning, source_code);
+            let range = warning.span();
+            Report::build(ReportKind::Error, file.clone(), range.start)
+                .with_config(config)
+                .with_code(code)
+                .with_message(message)
+                .with_label(
+                    Label::new((file.clone(), range))
+                        .with_message(label)
+                        .with_color(a),
+                )
+                .with_label(
+                    Label::new((file.clone(), attr))
+                        .with_message(msg!("denied-lint.label", lint = warning.lint().name()))
+                        .with_color(b),
+                )
+                .with_help(help)
+        }
+        Error::MalformedAttribute(range, form) => {
+            Report::build(ReportKind::Error, file.clone(), range.start)
+                .with_config(config)
+                .with_code(code)
+                .with_message(msg!("malformed-attribute"))
+                .with_label(
+                    Label::new((file.clone(), range))
+                        .with_message(msg!("malformed-attribute.label", form = form))
+                        .with_color(a),
+                )
+        }
+        Error::UnexpectedSelf(range) => Report::build(ReportKind::Error, file.clone(), range.start)
+            .with_config(config)
+            .with_code(code)
+            .with_message(msg!("unexpected-self"))
+            .with_label(
+                Label::new((file.clone(), range))
+                    .with_message(msg!("unexpected-self.label"))
+                    .with_color(a),
+            )
+            .with_help(msg!("unexpected-self.help")),
+        Error::RecursiveType(name) => {
+            let name_str = &source_code[name.clone()];
+            Report::build(ReportKind::Error, file.clone(), name.start)
+                .with_config(config)
+                .with_code(code)
+                .with_message(msg!(
+                    "recursive-type",
+                    name = fg(format!("`{}`", name_str), a)
+                ))
+                .with_label(
+                    Label::new((file.clone(), name))
+                        .with_message(msg!("recursive-type.label"))
+                        .with_color(a),
+                )
+                .with_help(msg!("recursive-type.help", name = name_str))
+        }
+        Error::DuplicateDefinition(name, first, signatures) => {
+            let name_str = &source_code[name.clone()];
+            let report = Report::build(ReportKind::Error, file.clone(), name.start)
+                .with_config(config)
+                .with_code(code)
+                .with_message(msg!(
+                    "duplicate-definition",
+                    name = fg(format!("`{}`", name_str), a)
+                ))
+                .with_label(
+                    Label::new((file.clone(), first))
+                        .with_message(msg!("duplicate-definition.first", name = name_str))
+                        .with_color(b),
+                )
+                .with_label(
+                    Label::new((file.clone(), name))
+                        .with_message(msg!("duplicate-definition.label", name = name_str))
+                        .with_color(a),
+                )
+                .with_help(msg!("duplicate-definition.help"));
+            match signatures {
+                Some((first, second)) => report.with_note(msg!(
+                    "duplicate-definition.note",
+                    first = first,
+                    second = second
+                )),
+                None => report,
+            }
+        }
+        Error::StaticMut(range) => Report::build(ReportKind::Error, file.clone(), range.start)
+            .with_config(config)
+            .with_code(code)
+            .with_message(msg!("static-mut"))
+            .with_label(
+                Label::new((file.clone(), range))
+                    .with_message(msg!("static-mut.label"))
+                    .with_color(a),
+            )
+            .with_help(msg!("static-mut.help")),
+        Error::NonConstStatic(range) => Report::build(ReportKind::Error, file.clone(), range.start)
+            .with_config(config)
+            .with_code(code)
+            .with_message(msg!("non-const-static"))
+            .with_label(
+                Label::new((file.clone(), range))
+                    .with_message(msg!("non-const-static.label"))
+                    .with_color(a),
+            )
+            .with_help(msg!("non-const-static.help")),
+        Error::CyclicStatic(range, name) => {
+            Report::build(ReportKind::Error, file.clone(), range.start)
+                .with_config(config)
+                .with_code(code)
+                .with_message(msg!("cyclic-static", name = fg(format!("`{}`", name), a)))
+                .with_label(
+                    Label::new((file.clone(), range))
+                        .with_message(msg!("cyclic-static.label"))
+                        .with_color(a),
+                )
+        }
+        Error::MismatchedStaticType(range, expected, found) => {
+            Report::build(ReportKind::Error, file.clone(), range.start)
+                .with_config(config)
+                .with_code(code)
+                .with_message(msg!(
+                    "expected-found",
+                    expected = fg(format!("`{}`", expected), a),
+                    found = fg(format!("`{}`", found), b)
+                ))
+                .with_label(
+                    Label::new((file.clone(), range))
+                        .with_message(msg!("mismatched-static-type.label", expected = expected))
+                        .with_color(b),
+                )
+        }
+        Error::StaticEvalFailed(range, message) => {
+            Report::build(ReportKind::Error, file.clone(), range.start)
+                .with_config(config)
+                .with_code(code)
+                .with_message(msg!("static-eval-failed"))
+                .with_label(
+                    Label::new((file.clone(), range))
+                        .with_message(message)
+                        .with_color(a),
+                )
+        }
+        Error::ImplOnBuiltinType(range, ty) => {
+            Report::build(ReportKind::Error, file.clone(), range.start)
+                .with_config(config)
+                .with_code(code)
+                .with_message(msg!(
+                    "impl-on-builtin-type",
+                    ty = fg(format!("`{}`", ty), a)
+                ))
+                .with_label(
+                    Label::new((file.clone(), range))
+                        .with_message(msg!("impl-on-builtin-type.label"))
+                        .with_color(a),
+                )
+                .with_help(msg!("impl-on-builtin-type.help"))
+        }
+        Error::NoField(range, ty) => Report::build(ReportKind::Error, file.clone(), range.start)
+            .with_config(config)
+            .with_code(code)
+            .with_message(msg!(
+                "no-field",
+                name = fg(format!("`{}`", &source_code[range.clone()]), a),
+                ty = fg(format!("`{}`", ty), b)
+            ))
+            .with_label(
+                Label::new((file.clone(), range))
+                    .with_message(msg!("no-field.label"))
+                    .with_color(a),
+            ),
+        Error::UnknownTrait(range) => Report::build(ReportKind::Error, file.clone(), range.start)
+            .with_config(config)
+            .with_code(code)
+            .with_message(msg!(
+                "unknown-trait",
+                name = fg(format!("`{}`", &source_code[range.clone()]), a)
+            ))
             .with_label(
                 Label::new((file.clone(), range))
-                    .with_message("requires at least a format string argument")
+                    .with_message(msg!("not-found-in-scope"))
                     .with_color(a),
             ),
-        Error::ExtraFmtArguments(fmt_str, args) => {
-            Report::build(ReportKind::Error, file.clone(), fmt_str.start)
-                .with_config(config)
-                .with_code("ExtraFmtArguments")
-                .with_message(if args.len() == 1 {
-                    "unused formatting argument"
-                } else {
-                    "multiple unused formatting arguments"
-                })
+        Error::MissingTraitMethods(range, methods) => {
+            let methods: Vec<_> = methods.iter().map(|m| format!("`{}`", m)).collect();
+            Report::build(ReportKind::Error, file.clone(), range.start)
+                .with_config(config)
+                .with_code(code)
+                .with_message(msg!(
+                    "missing-trait-methods",
+                    methods = fg(methods.join(", "), a)
+                ))
+                .with_label(
+                    Label::new((file.clone(), range))
+                        .with_message(msg!("missing-trait-methods.label"))
+                        .with_color(a),
+                )
+        }
+        Error::NotATraitMethod(range, name) => {
+            Report::build(ReportKind::Error, file.clone(), range.start)
+                .with_config(config)
+                .with_code(code)
+                .with_message(msg!(
+                    "not-a-trait-method",
+                    name = fg(format!("`{}`", &source_code[range.clone()]), a),
+                    trait = fg(format!("`{}`", name), b)
+                ))
+                .with_label(
+                    Label::new((file.clone(), range))
+                        .with_message(msg!("not-a-trait-method.label", trait = name))
+                        .with_color(a),
+                )
+                .with_help(msg!("not-a-trait-method.help"))
+        }
+        Error::MismatchedTraitMethod(range, expected) => {
+            Report::build(ReportKind::Error, file.clone(), range.start)
+                .with_config(config)
+                .with_code(code)
+                .with_message(msg!(
+                    "mismatched-trait-method",
+                    name = fg(format!("`{}`", &source_code[range.clone()]), a)
+                ))
+                .with_label(
+                    Label::new((file.clone(), range))
+                        .with_message(msg!("mismatched-trait-method.label", expected = expected))
+                        .with_color(a),
+                )
+        }
+        Error::UnsatisfiedBound(range, ty, bound) => {
+            Report::build(ReportKind::Error, file.clone(), range.start)
+                .with_config(config)
+                .with_code(code)
+                .with_message(msg!(
+                    "unsatisfied-bound",
+                    bound = fg(format!("`{}`", bound), a),
+                    ty = fg(format!("`{}`", ty), b)
+                ))
+                .with_label(
+                    Label::new((file.clone(), range))
+                        .with_message(msg!("unsatisfied-bound.label", ty = ty, bound = bound))
+                        .with_color(a),
+                )
+        }
+        Error::NotCallable(range, ty) => {
+            Report::build(ReportKind::Error, file.clone(), range.start)
+                .with_config(config)
+                .with_code(code)
+                .with_message(msg!("not-callable", ty = fg(format!("`{}`", ty), a)))
+                .with_label(
+                    Label::new((file.clone(), range))
+                        .with_message(msg!("not-callable.label"))
+                        .with_color(a),
+                )
+        }
+        Error::NotAReference(range, ty) => {
+            Report::build(ReportKind::Error, file.clone(), range.start)
+                .with_config(config)
+                .with_code(code)
+                .with_message(msg!("not-a-reference", ty = fg(format!("`{}`", ty), a)))
+                .with_label(
+                    Label::new((file.clone(), range))
+                        .with_message(msg!("not-a-reference.label"))
+                        .with_color(a),
+                )
+        }
+        Error::NotSliceable(range, ty) => {
+            Report::build(ReportKind::Error, file.clone(), range.start)
+                .with_config(config)
+                .with_code(code)
+                .with_message(msg!("not-sliceable", ty = fg(format!("`{}`", ty), a)))
+                .with_label(
+                    Label::new((file.clone(), range))
+                        .with_message(msg!("not-sliceable.label"))
+                        .with_color(a),
+                )
+        }
+        Error::NonIntegerBound(range, ty) => {
+            Report::build(ReportKind::Error, file.clone(), range.start)
+                .with_config(config)
+                .with_code(code)
+                .with_message(msg!(
+                    "expected-found",
+                    expected = fg("`i64`".into(), a),
+                    found = fg(format!("`{}`", ty), b)
+                ))
+                .with_label(
+                    Label::new((file.clone(), range))
+                        .with_message(msg!("non-integer-bound.label"))
+                        .with_color(b),
+                )
+        }
+        Error::NotIterable(range, ty) => {
+            Report::build(ReportKind::Error, file.clone(), range.start)
+                .with_config(config)
+                .with_code(code)
+                .with_message(msg!("not-iterable", ty = fg(format!("`{}`", ty), a)))
+                .with_label(
+                    Label::new((file.clone(), range))
+                        .with_message(msg!("not-iterable.label", ty = ty))
+                        .with_color(a),
+                )
+                .with_help(msg!("not-iterable.help"))
+        }
+        Error::RefutableForPattern(range, witness) => {
+            Report::build(ReportKind::Error, file.clone(), range.start)
+                .with_config(config)
+                .with_code(code)
+                .with_message(msg!(
+                    "refutable-for-pattern",
+                    witness = fg(format!("`{}`", witness), a)
+                ))
+                .with_label(
+                    Label::new((file.clone(), range))
+                        .with_message(msg!("pattern-not-covered", witness = witness))
+                        .with_color(a),
+                )
+                .with_help(msg!("refutable-for-pattern.help"))
+        }
+        Error::RefutableLetPattern(range, witness) => {
+            Report::build(ReportKind::Error, file.clone(), range.start)
+                .with_config(config)
+                .with_code(code)
+                .with_message(msg!(
+                    "refutable-let-pattern",
+                    witness = fg(format!("`{}`", witness), a)
+                ))
+                .with_label(
+                    Label::new((file.clone(), range))
+                        .with_message(msg!("pattern-not-covered", witness = witness))
+                        .with_color(a),
+                )
+                .with_help(msg!("refutable-let-pattern.help"))
+        }
+        Error::IrrefutableIfLetPattern(range) => {
+            Report::build(ReportKind::Error, file.clone(), range.start)
+                .with_config(config)
+                .with_code(code)
+                .with_message(msg!("irrefutable-if-let-pattern"))
+                .with_label(
+                    Label::new((file.clone(), range))
+                        .with_message(msg!("irrefutable-if-let-pattern.label"))
+                        .with_color(a),
+                )
+                .with_help(msg!("irrefutable-if-let-pattern.help"))
+        }
+        Error::IrrefutableWhileLetPattern(range) => {
+            Report::build(ReportKind::Error, file.clone(), range.start)
+                .with_config(config)
+                .with_code(code)
+                .with_message(msg!("irrefutable-while-let-pattern"))
+                .with_label(
+                    Label::new((file.clone(), range))
+                        .with_message(msg!("irrefutable-while-let-pattern.label"))
+                        .with_color(a),
+                )
+                .with_help(msg!("irrefutable-while-let-pattern.help"))
+        }
+        Error::MissingElse(range, ty) => {
+            Report::build(ReportKind::Error, file.clone(), range.start)
+                .with_config(config)
+                .with_code(code)
+                .with_message(msg!("missing-else"))
+                .with_label(
+                    Label::new((file.clone(), range))
+                        .with_message(msg!("missing-else.label", ty = ty))
+                        .with_color(a),
+                )
+                .with_help(msg!("missing-else.help", ty = ty))
+        }
+        Error::MismatchedLetType(range, expected, found, annotation) => {
+            Report::build(ReportKind::Error, file.clone(), range.start)
+                .with_config(config)
+                .with_code(code)
+                .with_message(msg!(
+                    "expected-found",
+                    expected = fg(format!("`{}`", expected), a),
+                    found = fg(format!("`{}`", found), b)
+                ))
+                .with_label(
+                    Label::new((file.clone(), annotation))
+                        .with_message(msg!("mismatched-let-type.annotation", expected = expected))
+                        .with_color(a),
+                )
+                .with_label(
+                    Label::new((file.clone(), range))
+                        .with_message(msg!("mismatched-let-type.label", found = found))
+                        .with_color(b),
+                )
+                .with_note(msg!(
+                    "mismatched-let-type.note",
+                    expected = expected,
+                    expected_is = expected.describe(),
+                    found = found,
+                    found_is = found.describe()
+                ))
+        }
+        Error::AmbiguousType(range, ty) => {
+            let pattern = &source_code[range.clone()];
+            Report::build(ReportKind::Error, file.clone(), range.start)
+                .with_config(config)
+                .with_code(code)
+                .with_message(msg!("ambiguous-type"))
+                .with_label(
+                    Label::new((file.clone(), range))
+                        .with_message(msg!(
+                            "ambiguous-type.label",
+                            ty = fg(format!("`{}`", ty), a)
+                        ))
+                        .with_color(a),
+                )
+                .with_help(msg!("ambiguous-type.help", pattern = pattern, ty = ty))
+        }
+        Error::MovedInLoop(range, name) => {
+            Report::build(ReportKind::Error, file.clone(), range.start)
+                .with_config(config)
+                .with_code(code)
+                .with_message(msg!(
+                    "use-of-moved-value",
+                    name = fg(format!("`{}`", name), a)
+                ))
+                .with_label(
+                    Label::new((file.clone(), range))
+                        .with_message(msg!("moved-in-loop.label"))
+                        .with_color(a),
+                )
+                .with_help(msg!("borrow-instead", name = name))
+        }
+        Error::UnhashableKey(range, ty) => {
+            Report::build(ReportKind::Error, file.clone(), range.start)
+                .with_config(config)
+                .with_code(code)
+                .with_message(msg!("unhashable-key", ty = fg(format!("`{}`", ty), a)))
+                .with_label(
+                    Label::new((file.clone(), range))
+                        .with_message(msg!("unhashable-key.label"))
+                        .with_color(a),
+                )
+        }
+        Error::Unsortable(range, ty) => Report::build(ReportKind::Error, file.clone(), range.start)
+            .with_config(config)
+            .with_code(code)
+            .with_message(msg!("unsortable", ty = fg(format!("`{}`", ty), a)))
+            .with_label(
+                Label::new((file.clone(), range))
+                    .with_message(msg!("unsortable.label"))
+                    .with_color(a),
+            )
+            .with_help(msg!("unsortable.help")),
+        Error::UseAfterMove(range, name, moved) => {
+            Report::build(ReportKind::Error, file.clone(), range.start)
+                .with_config(config)
+                .with_code(code)
+                .with_message(msg!(
+                    "use-of-moved-value",
+                    name = fg(format!("`{}`", name), a)
+                ))
+                .with_label(
+                    Label::new((file.clone(), moved))
+                        .with_message(msg!("use-after-move.moved"))
+                        .with_color(b),
+                )
+                .with_label(
+                    Label::new((file.clone(), range))
+                        .with_message(msg!("use-after-move.label"))
+                        .with_color(a),
+                )
+                .with_help(msg!("borrow-instead", name = name))
+        }
+        Error::MoveWhileBorrowed(range, name, borrowed) => {
+            Report::build(ReportKind::Error, file.clone(), range.start)
+                .with_config(config)
+                .with_code(code)
+                .with_message(msg!(
+                    "move-while-borrowed",
+                    name = fg(format!("`{}`", name), a)
+                ))
+                .with_label(
+                    Label::new((file.clone(), borrowed))
+                        .with_message(msg!("move-while-borrowed.borrowed", name = name))
+                        .with_color(b),
+                )
+                .with_label(
+                    Label::new((file.clone(), range))
+                        .with_message(msg!("move-while-borrowed.label", name = name))
+                        .with_color(a),
+                )
+        }
+        Error::ConflictingBorrow(range, name, borrowed) => {
+            Report::build(ReportKind::Error, file.clone(), range.start)
+                .with_config(config)
+                .with_code(code)
+                .with_message(msg!(
+                    "conflicting-borrow",
+                    name = fg(format!("`{}`", name), a)
+                ))
+                .with_label(
+                    Label::new((file.clone(), borrowed))
+                        .with_message(msg!("conflicting-borrow.borrowed"))
+                        .with_color(b),
+                )
+                .with_label(
+                    Label::new((file.clone(), range))
+                        .with_message(msg!("conflicting-borrow.label"))
+                        .with_color(a),
+                )
+                .with_help(msg!("conflicting-borrow.help"))
+        }
+        Error::DanglingReference(range, name, dropped) => {
+            Report::build(ReportKind::Error, file.clone(), range.start)
+                .with_config(config)
+                .with_code(code)
+                .with_message(msg!(
+                    "dangling-reference",
+                    name = fg(format!("`{}`", name), a)
+                ))
+                .with_label(
+                    Label::new((file.clone(), range))
+                        .with_message(msg!("dangling-reference.label", name = name))
+                        .with_color(a),
+                )
+                .with_label(
+                    Label::new((file.clone(), dropped))
+                        .with_message(msg!("dangling-reference.dropped", name = name))
+                        .with_color(b),
+                )
+        }
+        Error::UnresolvedTypeParam(range, param) => {
+            let name = &source_code[range.clone()];
+            Report::build(ReportKind::Error, file.clone(), range.start)
+                .with_config(config)
+                .with_code(code)
+                .with_message(msg!(
+                    "unresolved-type-param",
+                    param = fg(format!("`{}`", param), a),
+                    name = fg(format!("`{}`", name), b)
+                ))
+                .with_label(
+                    Label::new((file.clone(), range.clone()))
+                        .with_message(msg!("unresolved-type-param.label", param = param))
+                        .with_color(a),
+                )
+                .with_help(msg!("unresolved-type-param.help", name = name))
+        }
+        Error::AmbiguousTypeParam(range, param, before, found) => {
+            Report::build(ReportKind::Error, file.clone(), range.start)
+                .with_config(config)
+                .with_code(code)
+                .with_message(msg!(
+                    "ambiguous-type-param",
+                    param = fg(format!("`{}`", param), a),
+                    before = fg(format!("`{}`", before), a),
+                    found = fg(format!("`{}`", found), b)
+                ))
+                .with_label(
+                    Label::new((file.clone(), range))
+                        .with_message(msg!(
+                            "ambiguous-type-param.label",
+                            param = param,
+                            found = found,
+                            before = before
+                        ))
+                        .with_color(b),
+                )
+        }
+        Error::MismatchedTypeArgumentCount(name, expected, found) => {
+            let arguments = |count| msg_n!("type-arguments", count);
+            Report::build(ReportKind::Error, file.clone(), name.start)
+                .with_config(config)
+                .with_code(code)
+                .with_message(msg_n!(
+                    "takes-but-supplied",
+                    found,
+                    name = fg(format!("`{}`", &source_code[name.clone()]), a),
+                    expected = arguments(expected),
+                    found = arguments(found)
+                ))
+                .with_label(
+                    Label::new((file.clone(), name))
+                        .with_message(msg!("expected-count", expected = arguments(expected)))
+                        .with_color(a),
+                )
+        }
+        Error::TryOutsideResult(range) => {
+            Report::build(ReportKind::Error, file.clone(), range.start)
+                .with_config(config)
+                .with_code(code)
+                .with_message(msg!("try-outside-result"))
+                .with_label(
+                    Label::new((file.clone(), range))
+                        .with_message(msg!("try-outside-result.label"))
+                        .with_color(a),
+                )
+                .with_help(msg!("try-outside-result.help"))
+        }
+        Error::TryNotResult(range, ty) => {
+            Report::build(ReportKind::Error, file.clone(), range.start)
+                .with_config(config)
+                .with_code(code)
+                .with_message(msg!("try-not-result", ty = fg(format!("`{}`", ty), a)))
+                .with_label(
+                    Label::new((file.clone(), range))
+                        .with_message(msg!("try-not-result.label", ty = ty))
+                        .with_color(a),
+                )
+        }
+        Error::MismatchedErrorType(range, expected, found) => {
+            Report::build(ReportKind::Error, file.clone(), range.start)
+                .with_config(config)
+                .with_code(code)
+                .with_message(msg!(
+                    "mismatched-error-type",
+                    found = fg(format!("`{}`", found), b),
+                    expected = fg(format!("`{}`", expected), a)
+                ))
+                .with_label(
+                    Label::new((file.clone(), range))
+                        .with_message(msg!("mismatched-error-type.label", expected = expected))
+                        .with_color(b),
+                )
+        }
+        Error::ExtraMacroArguments(name, count, args) => {
+            let (args, more) = cap(args, max_labels);
+            let builder = Report::build(ReportKind::Error, file.clone(), name.start)
+                .with_config(config)
+                .with_code(code)
+                .with_message(msg_n!(
+                    "extra-macro-arguments",
+                    count,
+                    name = fg(format!("`{}`", &source_code[name.clone()]), a)
+                ))
                 .with_labels(args.into_iter().map(|span| {
                     Label::new((file.clone(), span))
-                        .with_message("argument never used")
+                        .with_message(msg!("extra-macro-arguments.label"))
                         .with_color(a)
-                }))
+                }));
+            note_more(builder, more)
+        }
+        Error::MissingMacroArguments(name, count) => {
+            Report::build(ReportKind::Error, file.clone(), name.start)
+                .with_config(config)
+                .with_code(code)
+                .with_message(msg_n!(
+                    "missing-macro-arguments",
+                    count,
+                    name = fg(format!("`{}`", &source_code[name.clone()]), a)
+                ))
                 .with_label(
-                    Label::new((file.clone(), fmt_str))
-                        .with_message("multiple missing formatting specifiers")
-                        .with_color(b),
+                    Label::new((file.clone(), name))
+                        .with_message(msg!("missing-macro-arguments.label"))
+                        .with_color(a),
                 )
         }
-        Error::NotEnoughFmtArguments(fmt_specifiers, args) => {
-            let arguments_a = if fmt_specifiers.len() == 1 {
-                "argument"
-            } else {
-                "arguments"
-            };
-            let (is_are, arguments_b) = if args.len() == 1 {
-                ("is", "argument")
-            } else {
-                ("are", "arguments")
-            };
-            Report::build(ReportKind::Error, file.clone(), fmt_specifiers[0].start)
+        Error::NotAnExpression(range) => {
+            let name = source_code[range.clone()].split('(').next().unwrap();
+            Report::build(ReportKind::Error, file.clone(), range.start)
                 .with_config(config)
-                .with_code("NotEnoughFmtArguments")
-                .with_message(format!(
-                    "{} positional {} in format string, but there {} {} {}",
-                    fmt_specifiers.len(),
-                    arguments_a,
-                    is_are,
-                    args.len(),
-                    arguments_b,
+                .with_code(code)
+                .with_message(msg!(
+                    "not-an-expression",
+                    name = fg(format!("`{}`", name), a)
                 ))
+                .with_label(
+                    Label::new((file.clone(), range))
+                        .with_message(msg!("not-an-expression.label"))
+                        .with_color(a),
+                )
+        }
+        Error::ExtraFmtArguments(name, fmt_str, fmt_specifiers, args) => {
+            let extra = args.len() - fmt_specifiers.len();
+            let mapped = fmt_specifiers.len();
+            let (pairs, more_pairs) = cap(
+                fmt_specifiers.into_iter().zip(args.clone()).collect(),
+                max_labels,
+            );
+            let (extra_args, more_args) = cap(args[mapped..].to_vec(), max_labels);
+            let builder = Report::build(ReportKind::Error, file.clone(), fmt_str.start)
+                .with_config(config)
+                .with_code(code)
+                .with_message(msg_n!("extra-fmt-arguments", extra))
                 .with_labels(
-                    fmt_specifiers
+                    pairs
                         .into_iter()
-                        .map(|span| Label::new((file.clone(), span)).with_color(a)),
+                        .enumerate()
+                        .flat_map(|(i, pair)| argument_labels(&file, i, pair, a)),
                 )
+                .with_labels(extra_args.into_iter().map(|span| {
+                    Label::new((file.clone(), span))
+                        .with_message(msg!("extra-fmt-arguments.label"))
+                        .with_color(b)
+                }));
+            let builder = match mapped {
+                0 => builder.with_label(
+                    Label::new((file.clone(), fmt_str))
+                        .with_message(msg!("extra-fmt-arguments.no-specifiers"))
+                        .with_color(b),
+                ),
+                _ => builder,
+            };
+            with_notes(
+                builder,
+                fmt_signature(source_code, name, fg, a),
+                more_pairs + more_args,
+            )
+        }
+        Error::NotEnoughFmtArguments(name, fmt_specifiers, args) => {
+            let message = msg_n!(
+                "not-enough-fmt-arguments",
+                fmt_specifiers.len(),
+                found = msg_n!("not-enough-fmt-arguments.found", args.len())
+            );
+            let start = fmt_specifiers[0].start;
+            let mapped = args.len();
+            let (pairs, more_pairs) = cap(
+                fmt_specifiers[..mapped].iter().cloned().zip(args).collect(),
+                max_labels,
+            );
+            let (missing, more_missing) = cap(fmt_specifiers[mapped..].to_vec(), max_labels);
+            let builder = Report::build(ReportKind::Error, file.clone(), start)
+                .with_config(config)
+                .with_code(code)
+                .with_message(message)
                 .with_labels(
-                    args.into_iter()
-                        .map(|span| Label::new((file.clone(), span)).with_color(b)),
+                    pairs
+                        .into_iter()
+                        .enumerate()
+                        .flat_map(|(i, pair)| argument_labels(&file, i, pair, a)),
                 )
+                .with_labels(missing.into_iter().map(|span| {
+                    Label::new((file.clone(), span))
+                        .with_message(msg!("not-enough-fmt-arguments.label"))
+                        .with_color(b)
+                }));
+            with_notes(
+                builder,
+                fmt_signature(source_code, name, fg, a),
+                more_pairs + more_missing,
+            )
         }
         Error::ParseError(ParseError::ExtraToken {
             token: (l, Token(_, t), r),
         }) => Report::build(ReportKind::Error, file.clone(), l)
             .with_config(config)
-            .with_code("ExtraToken")
-            .with_message(format!(
-                "encountered unexpected syntax {}",
-                fg(format!("\"{}\"", t), a)
+            .with_code(code)
+            .with_message(msg!(
+                "encountered-unexpected-syntax",
+                token = fg(format!("\"{}\"", t), a)
             ))
             .with_label(
                 Label::new((file.clone(), l..r))
-                    .with_message("unexpected syntax")
+                    .with_message(msg!("unexpected-syntax"))
                     .with_color(a),
             ),
         Error::ParseError(ParseError::InvalidToken { location }) => {
             Report::build(ReportKind::Error, file.clone(), location)
                 .with_config(config)
-                .with_code("InvalidToken")
-                .with_message(format!("encountered unexpected syntax"))
+                .with_code(code)
+                .with_message(msg!("unexpected-syntax"))
                 .with_label(
                     Label::new((file.clone(), location..location + 1))
-                        .with_message("unexpected syntax")
+                        .with_message(msg!("unexpected-syntax"))
                         .with_color(a),
                 )
         }
@@ -113,11 +1393,11 @@ pub fn report_error(
                 .join(", ");
             Report::build(ReportKind::Error, file.clone(), location)
                 .with_config(config)
-                .with_code("UnrecognizedEof")
-                .with_message(format!("unexpected end of file"))
+                .with_code(code)
+                .with_message(msg!("unrecognized-eof"))
                 .with_label(
                     Label::new((file.clone(), location..location + 1))
-                        .with_message(format!("Expected one of: {}", expected))
+                        .with_message(msg!("expected-one-of", expected = expected))
                         .with_color(b),
                 )
         }
@@ -132,31 +1412,128 @@ pub fn report_error(
                 .join(", ");
             let report = Report::build(ReportKind::Error, file.clone(), l)
                 .with_config(config)
-                .with_code("UnrecognizedToken")
-                .with_message(format!(
-                    "encountered unexpected syntax {}",
-                    fg(format!("\"{}\"", t), a)
+                .with_code(code)
+                .with_message(msg!(
+                    "encountered-unexpected-syntax",
+                    token = fg(format!("\"{}\"", t), a)
                 ))
                 .with_label(
                     Label::new((file.clone(), l..r))
-                        .with_message("unexpected syntax")
+                        .with_message(msg!("unexpected-syntax"))
                         .with_color(a),
                 );
             if !expected.is_empty() {
                 report.with_label(
                     Label::new((file.clone(), l..r))
-                        .with_message(format!("Expected one of: {}", expected))
+                        .with_message(msg!("expected-one-of", expected = expected))
                         .with_color(b),
                 )
             } else {
                 report
             }
         }
-        Error::ParseError(error @ ParseError::User { .. }) => unreachable!("{:#?}", error),
+        Error::InternalCompilerError(range, message) => {
+            Report::build(ReportKind::Error, file.clone(), range.start)
+                .with_config(config)
+                .with_code(code)
+                .with_message(msg!("internal-compiler-error", message = message))
+                .with_label(
+                    Label::new((file.clone(), range))
+                        .with_message(msg!("internal-compiler-error.label"))
+                        .with_color(a),
+                )
+                .with_note(msg!("internal-compiler-error.note"))
+        }
+        Error::RuntimeIoError(message) => Report::build(ReportKind::Error, file.clone(), 0)
+            .with_config(config)
+            .with_code(code)
+            .with_message(msg!("runtime-io-error", message = message)),
+        Error::Panicked(message) => Report::build(ReportKind::Error, file.clone(), 0)
+            .with_config(config)
+            .with_code(code)
+            .with_message(msg!("panicked", message = message)),
+        Error::PanickedAt(range, message) => {
+            Report::build(ReportKind::Error, file.clone(), range.start)
+                .with_config(config)
+                .with_code(code)
+                .with_message(msg!("panicked", message = message))
+                .with_label(
+                    Label::new((file.clone(), range))
+                        .with_message(msg!("panicked.label"))
+                        .with_color(a),
+                )
+        }
+        Error::Exited(status) => Report::build(ReportKind::Error, file.clone(), 0)
+            .with_config(config)
+            .with_code(code)
+            .with_message(msg!("exited", status = status)),
+        Error::OutputLimitExceeded(limit, truncated) => {
+            Report::build(ReportKind::Error, file.clone(), 0)
+                .with_config(config)
+                .with_code(code)
+                .with_message(msg!(
+                    "output-limit-exceeded",
+                    limit = limit,
+                    truncated = truncated
+                ))
+        }
+    };
+    let builder = match fix {
+        Some(fix) => builder.with_help(fix.message),
+        None => builder,
     };
 
-    builder
-        .finish()
-        .write(sources(vec![(file.to_string(), source_code)]), writer)
-        .unwrap();
+    write_report(builder.finish(), file, source_code, writer);
+}
+
+/// Writes `report`, raised in `source_code` read from `file`, out to `writer`. A report that
+/// can't be written, like to a closed stderr, is dropped rather than taking the compiler down.
+fn write_report(
+    report: Report<(String, Range<usize>)>,
+    file: String,
+    source_code: &str,
+    writer: impl std::io::Write,
+) {
+    let _ = report.write(sources(vec![(file, source_code)]), writer);
+}
+
+/// Writes `warning`, raised in `source_code` read from `file`, out to `writer`.
+pub fn report_warning(
+    file: &std::path::Path,
+    source_code: &str,
+    warning: Warning,
+    colored: bool,
+    writer: impl std::io::Write,
+) {
+    let file = file.to_string_lossy().into_owned();
+    let config = Config::default().with_color(colored);
+    let a = ColorGenerator::new().next();
+    let (message, label, help) = describe_warning(&warning, source_code);
+    let range = warning.span();
+    let report = Report::build(ReportKind::Warning, file.clone(), range.start)
+        .with_config(config)
+        .with_code(warning.code())
+        .with_message(message)
+        .with_label(
+            Label::new((file.clone(), range))
+                .with_message(label)
+                .with_color(a),
+        )
+        .with_help(help)
+        .with_note(msg!("allow-note", lint = warning.lint().name()));
+    write_report(report.finish(), file, source_code, writer);
+}
+
+/// The message, label and help `warning` is reported with, whether it's warned about or denied.
+fn describe_warning(warning: &Warning, source_code: &str) -> (String, String, String) {
+    match warning {
+        Warning::UnusedVariable(range, _) => {
+            let name = &source_code[range.clone()];
+            (
+                msg!("unused-variable", name = name),
+                msg!("unused-variable.label"),
+                msg!("unused-variable.help", name = name),
+            )
+        }
+    }
 }
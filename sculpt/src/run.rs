@@ -1,257 +1,610 @@
-use inkwell::builder::Builder;
-use inkwell::context::Context;
-use inkwell::execution_engine::{ExecutionEngine, JitFunction};
-use inkwell::module::Module;
-use inkwell::values::{FunctionValue, GlobalValue};
-use inkwell::{AddressSpace, OptimizationLevel};
 use lalrpop_util::ParseError;
 
-use std::io::Write;
+use std::io;
+#[cfg(feature = "llvm")]
+use std::io::{Read, Write};
 use std::ops::Range;
+use std::path::Path;
 
-use crate::fmt::{extract_fmt, FmtSpec};
-use crate::grammar::{MainParser, Token};
-use crate::syntax::{Macro, Main, StrLit};
+use crate::backend::{Exit, Panic, PanicAt};
+use crate::codes::DiagnosticCode;
+use crate::fmt_str::Format;
+use crate::grammar::{ProgramParser, StatementsParser, Token};
+use crate::hir::Type;
+use crate::intern::{sym, Symbol};
+use crate::lint::Warning;
+use crate::report::report_error;
+use crate::sandbox::Sandbox;
+use crate::session::Session;
+use crate::syntax::{is_keyword, Function, Name, NodeIds, Program, SyntaxError};
 
 #[derive(Debug, PartialEq)]
 pub enum Error<'src> {
-    ParseError(ParseError<usize, Token<'src>, &'src str>),
+    ParseError(ParseError<usize, Token<'src>, SyntaxError>),
+    /// A keyword was used as a name, e.g. `fn let() {}`.
+    KeywordAsName(Range<usize>),
+    /// A `macro_rules!` parameter had a fragment specifier other than `expr`.
+    UnknownFragment(Range<usize>),
+    /// A `$param` that isn't a parameter of the macro rule it's used in, if any.
+    UnboundMetaVar(Range<usize>),
+    /// No rule of the invoked user macro takes the given number of arguments.
+    NoMatchingMacroRule(Range<usize>, usize),
+    /// Expanding the invoked user macro nested more than [`RECURSION_LIMIT`] expansions deep.
+    ///
+    /// [`RECURSION_LIMIT`]: crate::expand::RECURSION_LIMIT
+    MacroRecursionLimit(Range<usize>),
+    /// The plugin expanding the invoked macro failed with the given message.
+    PluginFailed(Range<usize>, String),
+    /// An argument of a builtin like `include_str!` wasn't a string literal, or the macro's name
+    /// if it had none.
+    ExpectedStrLit(Range<usize>),
+    /// The file an `include_str!` names couldn't be read. Holds the path as written and why.
+    IncludeFailed(Range<usize>, String, String),
+    /// The variable an `env!` names isn't set at compile time. Holds the message to report.
+    EnvVarUnset(Range<usize>, String),
+    /// A brace in a format string neither opens nor closes a `{}`, and isn't escaped by doubling
+    /// it. Holds the brace.
+    UnmatchedFmtBrace(Range<usize>, char),
+    /// A formatting macro was called without a format string. Holds the macro's name.
     MissingFmtStr(Range<usize>),
-    ExtraFmtArguments(Range<usize>, Vec<Range<usize>>),
-    NotEnoughFmtArguments(Vec<Range<usize>>, Vec<Range<usize>>),
+    /// A formatting macro was given more arguments than its format string has specifiers. Holds
+    /// the macro's name, the format string, its specifiers and every argument.
+    ExtraFmtArguments(
+        Range<usize>,
+        Range<usize>,
+        Vec<Range<usize>>,
+        Vec<Range<usize>>,
+    ),
+    /// A formatting macro was given fewer arguments than its format string has specifiers. Holds
+    /// the macro's name, the specifiers and every argument.
+    NotEnoughFmtArguments(Range<usize>, Vec<Range<usize>>, Vec<Range<usize>>),
+    UnknownMacro(Range<usize>),
+    FmtStrNotLiteral(Range<usize>),
+    /// A builtin macro was given more arguments than it takes. Holds its name, how many it takes,
+    /// and the extra arguments.
+    ExtraMacroArguments(Range<usize>, usize, Vec<Range<usize>>),
+    /// A builtin macro was given fewer arguments than it takes. Holds its name and how many it
+    /// takes.
+    MissingMacroArguments(Range<usize>, usize),
+    NotAnExpression(Range<usize>),
+    UnresolvedName(Range<usize>),
+    IntLiteralTooLarge(Range<usize>),
+    /// An operand of an arithmetic operator had the given non-integer type.
+    NonIntegerOperand(Range<usize>, Type),
+    /// A builtin's argument had the wrong type. Holds the type it takes and the one it was given.
+    MismatchedArgumentType(Range<usize>, Type, Type),
+    /// Something like `Some` was called with the wrong number of arguments. Holds its name, how
+    /// many it takes and how many it was given.
+    MismatchedArgumentCount(Range<usize>, usize, usize),
+    /// The named method doesn't exist on the receiver's type, which it holds.
+    NoMethod(Range<usize>, Type),
+    /// A `match` arm's body had a type other than the arms before it. Holds the type of those and
+    /// of the body.
+    MismatchedArmTypes(Range<usize>, Type, Type),
+    /// A pattern can't match values of the scrutinee's type. Holds that type and the pattern's.
+    MismatchedPatternType(Range<usize>, Type, Type),
+    /// A `match` doesn't cover every value of its scrutinee. Holds a pattern for one it misses.
+    NonExhaustiveMatch(Range<usize>, String),
+    /// A format argument had a type, like an `Option`, that can't be formatted.
+    NotDisplayable(Range<usize>, Type),
+    /// A `{:?}` format argument had a type, like a closure's, that can't be formatted with it.
+    NotDebuggable(Range<usize>, Type),
+    /// A format argument had a type its spec, like `{:x}` for integers, can't format. Holds where
+    /// the spec is, its format, and the argument's type.
+    MismatchedFmtType(Range<usize>, Range<usize>, Format, Type),
+    /// Nothing pins down the type of an expression that needs one, like the `x` formatted in
+    /// `match None { Some(x) => format!("{}", x), ... }`.
+    TypeAnnotationsNeeded(Range<usize>),
+    /// A type was named that doesn't exist.
+    UnknownType(Range<usize>),
+    /// `main`'s return type, which it holds, was something other than `()` or `Result<(), E>` for
+    /// a string or integer `E`.
+    UnsupportedReturnType(Range<usize>, Type),
+    /// A function returned something other than its return type. Holds that type and the one
+    /// returned.
+    MismatchedReturnType(Range<usize>, Type, Type),
+    /// A function the host calls, like `main`, takes parameters or type parameters. Holds its
+    /// name.
+    EntryPointParameters(Range<usize>),
+    /// The function a program starts in, `main` unless the session names another, isn't defined.
+    /// Points at the end of the file, and holds the function's name.
+    MissingEntryPoint(Range<usize>, String),
+    /// A library's `pub fn` takes or returns a value of a type, which it holds, that C has no
+    /// equivalent of, so it can't be exported.
+    UnexportableFunction(Range<usize>, Type),
+    /// An attribute was used that the compiler doesn't know. Holds its name.
+    UnknownAttribute(Range<usize>),
+    /// An attribute was given arguments it doesn't take, or not given ones it needs. Holds the
+    /// form it's used in.
+    MalformedAttribute(Range<usize>, &'static str),
+    /// An attribute that only applies to functions was put at the top of a file, like `#![test]`.
+    MisplacedAttribute(Range<usize>),
+    /// `allow`, `warn` or `deny` named a lint the compiler doesn't know. Holds its name.
+    UnknownLint(Range<usize>),
+    /// A lint was raised where an attribute denies it. Holds the attribute.
+    DeniedLint(Warning, Range<usize>),
+    /// `self` was declared somewhere other than as the first parameter of a function in an `impl`.
+    UnexpectedSelf(Range<usize>),
+    /// The tuple struct has a field of its own type, so it would be infinitely large. Holds its
+    /// name.
+    RecursiveType(Range<usize>),
+    /// A name was defined a second time in the same scope. Holds the second definition's name and
+    /// the first's, and for functions with different signatures, the first's and the second's.
+    DuplicateDefinition(Range<usize>, Range<usize>, Option<(String, String)>),
+    /// A `static mut` was declared, though values can't change.
+    StaticMut(Range<usize>),
+    /// The initializer of a static used something other than literals, arithmetic and other
+    /// statics.
+    NonConstStatic(Range<usize>),
+    /// The initializer of a static used the named static, whose own value depends on it.
+    CyclicStatic(Range<usize>, Symbol),
+    /// The initializer of a static evaluated to a value of a type other than the one it's
+    /// declared with. Holds both.
+    MismatchedStaticType(Range<usize>, Type, Type),
+    /// Working out the value of a static would have panicked. Holds the panic message.
+    StaticEvalFailed(Range<usize>, &'static str),
+    /// An `impl` was written for a builtin type, which it holds.
+    ImplOnBuiltinType(Range<usize>, Type),
+    /// The expression's type, which it holds, has no field with the index.
+    NoField(Range<usize>, Type),
+    /// A trait was named that doesn't exist.
+    UnknownTrait(Range<usize>),
+    /// An `impl` of a trait doesn't define some of its methods, which it holds the names of.
+    MissingTraitMethods(Range<usize>, Vec<Symbol>),
+    /// A function in an `impl` of a trait isn't one of the methods of the trait, which it holds.
+    NotATraitMethod(Range<usize>, Symbol),
+    /// A method in an `impl` of a trait has a different signature than the trait's, which it
+    /// holds.
+    MismatchedTraitMethod(Range<usize>, String),
+    /// A generic function was called without a type argument for the named type parameter, and
+    /// none could be inferred from its arguments.
+    UnresolvedTypeParam(Range<usize>, Symbol),
+    /// The arguments of a call to a generic function disagree about what the named type parameter
+    /// is. Holds what the arguments before this one made it, and what this one would.
+    AmbiguousTypeParam(Range<usize>, Symbol, Type, Type),
+    /// A call passed the wrong number of type arguments. Holds how many the function takes and how
+    /// many it was passed.
+    MismatchedTypeArgumentCount(Range<usize>, usize, usize),
+    /// A generic function was called with a type argument, which it holds, that doesn't satisfy
+    /// the bound on its parameter, which it holds written out.
+    UnsatisfiedBound(Range<usize>, Type, String),
+    /// Something other than a closure was called. Holds its type.
+    NotCallable(Range<usize>, Type),
+    /// Something other than a reference was dereferenced. Holds its type.
+    NotAReference(Range<usize>, Type),
+    /// Something other than a string was sliced. Holds its type.
+    NotSliceable(Range<usize>, Type),
+    /// A bound of a slice or range had the given non-integer type.
+    NonIntegerBound(Range<usize>, Type),
+    /// A `for` loop was over something it can't iterate over. Holds its type.
+    NotIterable(Range<usize>, Type),
+    /// The pattern of a `for` loop doesn't match every item. Holds an item it doesn't match,
+    /// written as a pattern.
+    RefutableForPattern(Range<usize>, String),
+    /// The pattern of a `let` doesn't match every value of its type. Holds one it doesn't match.
+    RefutableLetPattern(Range<usize>, String),
+    /// The pattern of an `if let` matches every value of its type, so its `else` could never run.
+    IrrefutableIfLetPattern(Range<usize>),
+    /// The pattern of a `while let` matches every value of its type, so the loop could never end.
+    IrrefutableWhileLetPattern(Range<usize>),
+    /// The block of an `if let` without an `else` evaluated to something other than `()`. Holds
+    /// its type.
+    MissingElse(Range<usize>, Type),
+    /// The value of a `let` wasn't of the type it was annotated with. Holds the annotated type, the
+    /// value's, and where the annotation is.
+    MismatchedLetType(Range<usize>, Type, Type, Range<usize>),
+    /// Part of the type of the value a `let` binds, which it holds with that part as `_`, is never
+    /// pinned down, like the one a `let x = None;` would hold.
+    AmbiguousType(Range<usize>, Type),
+    /// The body of a `for` or `while let` loop moved out of the named local, bound before the
+    /// loop, so the next iteration would find it moved.
+    MovedInLoop(Range<usize>, Symbol),
+    /// A `HashMap` was given keys of a type, which it holds, other than strings, integers and
+    /// `bool`s.
+    UnhashableKey(Range<usize>, Type),
+    /// A `Vec` was sorted by values of a type, which it holds, other than strings, integers and
+    /// `bool`s.
+    Unsortable(Range<usize>, Type),
+    /// The named local was used after being moved out of. Holds where it was moved.
+    UseAfterMove(Range<usize>, Symbol, Range<usize>),
+    /// The named local was moved out of while borrowed. Holds where it was borrowed.
+    MoveWhileBorrowed(Range<usize>, Symbol, Range<usize>),
+    /// The named local was borrowed while a borrow it conflicts with, which it holds the location
+    /// of, was still alive: either of them is mutable.
+    ConflictingBorrow(Range<usize>, Symbol, Range<usize>),
+    /// A reference to the named local was returned from the function owning it. Holds where the
+    /// function ends, dropping the local.
+    DanglingReference(Range<usize>, Symbol, Range<usize>),
+    /// `?` was used in a function that doesn't return a `Result`.
+    TryOutsideResult(Range<usize>),
+    /// `?` was applied to something other than a `Result`. Holds its type.
+    TryNotResult(Range<usize>, Type),
+    /// `?` was applied to a `Result` whose error type isn't the function's. Holds the function's
+    /// error type and the `Result`'s.
+    MismatchedErrorType(Range<usize>, Type, Type),
+    /// The compiler broke one of its own invariants while lowering the program, which is a bug in
+    /// sculpt rather than in the program. Holds what it was lowering and what went wrong.
+    InternalCompilerError(Range<usize>, &'static str),
+    /// The running program was aborted because reading or writing one of its streams failed.
+    RuntimeIoError(String),
+    /// The running program panicked, e.g. by dividing by zero. Holds the panic message.
+    Panicked(String),
+    /// The running program panicked at a source location, e.g. by unwrapping `None`. Holds the
+    /// location and the panic message.
+    PanickedAt(Range<usize>, String),
+    /// The running program called `exit!` with a status other than 0. Holds the status.
+    Exited(i32),
+    /// The running program was aborted for writing more than the output limit. Holds the limit and
+    /// the number of bytes that were cut off.
+    OutputLimitExceeded(usize, usize),
 }
 
-pub fn run<'src>(source_code: &'src str, std_out: impl Write) -> Result<(), Error<'src>> {
-    let context = &Context::create();
-    let module = &context.create_module("main");
-    let builder = &context.create_builder();
-    let execution_engine = &module
-        .create_jit_execution_engine(OptimizationLevel::None)
-        .unwrap();
-    let mut std_out: Box<dyn Write> = Box::new(std_out);
-
-    let main = build_main(
-        context,
-        module,
-        builder,
-        execution_engine,
-        source_code,
-        &mut std_out,
-    )?;
-
-    Ok(unsafe { main.call() })
-}
-
-pub fn build_main<'src, 'ctx>(
-    context: &'ctx Context,
-    module: &Module<'ctx>,
-    builder: &Builder<'ctx>,
-    execution_engine: &ExecutionEngine<'ctx>,
-    source_code: &'src str,
-    std_out: &mut Box<dyn Write + 'ctx>,
-) -> Result<JitFunction<'ctx, unsafe extern "C" fn()>, Error<'src>> {
-    let Main { statements } = MainParser::new()
-        .parse(source_code)
-        .map_err(Error::ParseError)?;
+impl Error<'_> {
+    /// Offset of the source location the error is reported at.
+    pub fn location(&self) -> usize {
+        self.span().start
+    }
 
-    let ext_write = link_write(module, execution_engine);
-    let ext_std_out = link_std_out(std_out, module, execution_engine);
+    /// The source the error is reported at, which its first label underlines. Errors raised
+    /// while running a program without a location have an empty span at the start.
+    pub fn span(&self) -> Range<usize> {
+        match self {
+            Error::ParseError(ParseError::InvalidToken { location }) => *location..*location + 1,
+            Error::ParseError(ParseError::UnrecognizedEof { location, .. }) => {
+                *location..*location + 1
+            }
+            Error::ParseError(ParseError::UnrecognizedToken {
+                token: (l, _, r), ..
+            }) => *l..*r,
+            Error::ParseError(ParseError::ExtraToken { token: (l, _, r) }) => *l..*r,
+            Error::ParseError(ParseError::User { .. }) => 0..0,
+            Error::KeywordAsName(range) => range.clone(),
+            Error::UnknownFragment(range) => range.clone(),
+            Error::UnboundMetaVar(range) => range.clone(),
+            Error::NoMatchingMacroRule(range, _) => range.clone(),
+            Error::MacroRecursionLimit(range) => range.clone(),
+            Error::PluginFailed(range, _) => range.clone(),
+            Error::ExpectedStrLit(range) => range.clone(),
+            Error::IncludeFailed(range, ..) => range.clone(),
+            Error::EnvVarUnset(range, _) => range.clone(),
+            Error::UnmatchedFmtBrace(range, _) => range.clone(),
+            Error::MissingFmtStr(range) => range.clone(),
+            Error::ExtraFmtArguments(_, fmt_str, ..) => fmt_str.clone(),
+            Error::NotEnoughFmtArguments(_, fmt_specifiers, _) => fmt_specifiers[0].clone(),
+            Error::UnknownMacro(range) => range.clone(),
+            Error::FmtStrNotLiteral(range) => range.clone(),
+            Error::ExtraMacroArguments(name, ..) => name.clone(),
+            Error::MissingMacroArguments(name, _) => name.clone(),
+            Error::NotAnExpression(range) => range.clone(),
+            Error::UnresolvedName(range) => range.clone(),
+            Error::IntLiteralTooLarge(range) => range.clone(),
+            Error::NonIntegerOperand(range, _) => range.clone(),
+            Error::MismatchedArgumentType(range, ..) => range.clone(),
+            Error::MismatchedArgumentCount(range, ..) => range.clone(),
+            Error::NoMethod(range, _) => range.clone(),
+            Error::MismatchedArmTypes(range, ..) => range.clone(),
+            Error::MismatchedPatternType(range, ..) => range.clone(),
+            Error::NonExhaustiveMatch(range, _) => range.clone(),
+            Error::NotDisplayable(range, _) => range.clone(),
+            Error::NotDebuggable(range, _) => range.clone(),
+            Error::MismatchedFmtType(range, ..) => range.clone(),
+            Error::TypeAnnotationsNeeded(range) => range.clone(),
+            Error::UnknownType(range) => range.clone(),
+            Error::UnsupportedReturnType(range, _) => range.clone(),
+            Error::MismatchedReturnType(range, ..) => range.clone(),
+            Error::EntryPointParameters(range) => range.clone(),
+            Error::MissingEntryPoint(range, _) => range.clone(),
+            Error::UnexportableFunction(range, _) => range.clone(),
+            Error::UnknownAttribute(range) => range.clone(),
+            Error::MalformedAttribute(range, _) => range.clone(),
+            Error::MisplacedAttribute(range) => range.clone(),
+            Error::UnknownLint(range) => range.clone(),
+            Error::DeniedLint(warning, _) => warning.span(),
+            Error::UnexpectedSelf(range) => range.clone(),
+            Error::RecursiveType(range) => range.clone(),
+            Error::DuplicateDefinition(range, ..) => range.clone(),
+            Error::StaticMut(range) => range.clone(),
+            Error::NonConstStatic(range) => range.clone(),
+            Error::CyclicStatic(range, _) => range.clone(),
+            Error::MismatchedStaticType(range, ..) => range.clone(),
+            Error::StaticEvalFailed(range, _) => range.clone(),
+            Error::ImplOnBuiltinType(range, _) => range.clone(),
+            Error::NoField(range, _) => range.clone(),
+            Error::UnknownTrait(range) => range.clone(),
+            Error::MissingTraitMethods(range, _) => range.clone(),
+            Error::NotATraitMethod(range, _) => range.clone(),
+            Error::MismatchedTraitMethod(range, _) => range.clone(),
+            Error::UnsatisfiedBound(range, ..) => range.clone(),
+            Error::NotCallable(range, _) => range.clone(),
+            Error::NotAReference(range, _) => range.clone(),
+            Error::NotSliceable(range, _) => range.clone(),
+            Error::NonIntegerBound(range, _) => range.clone(),
+            Error::NotIterable(range, _) => range.clone(),
+            Error::RefutableForPattern(range, _) => range.clone(),
+            Error::RefutableLetPattern(range, _) => range.clone(),
+            Error::IrrefutableIfLetPattern(range) => range.clone(),
+            Error::IrrefutableWhileLetPattern(range) => range.clone(),
+            Error::MissingElse(range, _) => range.clone(),
+            Error::MismatchedLetType(range, ..) => range.clone(),
+            Error::AmbiguousType(range, _) => range.clone(),
+            Error::MovedInLoop(range, _) => range.clone(),
+            Error::UnhashableKey(range, _) => range.clone(),
+            Error::Unsortable(range, _) => range.clone(),
+            Error::UseAfterMove(range, ..) => range.clone(),
+            Error::MoveWhileBorrowed(range, ..) => range.clone(),
+            Error::ConflictingBorrow(range, ..) => range.clone(),
+            Error::DanglingReference(range, ..) => range.clone(),
+            Error::UnresolvedTypeParam(range, _) => range.clone(),
+            Error::AmbiguousTypeParam(range, ..) => range.clone(),
+            Error::MismatchedTypeArgumentCount(range, ..) => range.clone(),
+            Error::TryOutsideResult(range) => range.clone(),
+            Error::TryNotResult(range, _) => range.clone(),
+            Error::MismatchedErrorType(range, ..) => range.clone(),
+            Error::InternalCompilerError(range, _) => range.clone(),
+            Error::RuntimeIoError(_) => 0..0,
+            Error::Panicked(_) => 0..0,
+            Error::PanickedAt(range, _) => range.clone(),
+            Error::Exited(_) => 0..0,
+            Error::OutputLimitExceeded(..) => 0..0,
+        }
+    }
 
-    let main_fn = module.add_function("main", context.void_type().fn_type(&[], false), None);
-    let main_fn_body = context.append_basic_block(main_fn, "");
-    builder.position_at_end(main_fn_body);
-    for m in statements {
-        build_macro_invocation(m, context, builder, ext_write, ext_std_out)?;
+    /// The code the error is reported with.
+    pub fn code(&self) -> DiagnosticCode {
+        match self {
+            Error::ParseError(ParseError::InvalidToken { .. }) => DiagnosticCode::InvalidToken,
+            Error::ParseError(ParseError::UnrecognizedEof { .. }) => {
+                DiagnosticCode::UnrecognizedEof
+            }
+            Error::ParseError(ParseError::UnrecognizedToken { .. }) => {
+                DiagnosticCode::UnrecognizedToken
+            }
+            Error::ParseError(ParseError::ExtraToken { .. }) => DiagnosticCode::ExtraToken,
+            Error::ParseError(ParseError::User {
+                error: SyntaxError::KeywordAsName(_),
+            }) => DiagnosticCode::KeywordAsName,
+            Error::ParseError(ParseError::User {
+                error: SyntaxError::UnknownFragment(_),
+            }) => DiagnosticCode::UnknownFragment,
+            Error::KeywordAsName(..) => DiagnosticCode::KeywordAsName,
+            Error::UnknownFragment(..) => DiagnosticCode::UnknownFragment,
+            Error::UnboundMetaVar(..) => DiagnosticCode::UnboundMetaVar,
+            Error::NoMatchingMacroRule(..) => DiagnosticCode::NoMatchingMacroRule,
+            Error::MacroRecursionLimit(..) => DiagnosticCode::MacroRecursionLimit,
+            Error::PluginFailed(..) => DiagnosticCode::PluginFailed,
+            Error::ExpectedStrLit(..) => DiagnosticCode::ExpectedStrLit,
+            Error::IncludeFailed(..) => DiagnosticCode::IncludeFailed,
+            Error::EnvVarUnset(..) => DiagnosticCode::EnvVarUnset,
+            Error::UnmatchedFmtBrace(..) => DiagnosticCode::UnmatchedFmtBrace,
+            Error::MissingFmtStr(..) => DiagnosticCode::MissingFmtStr,
+            Error::ExtraFmtArguments(..) => DiagnosticCode::ExtraFmtArguments,
+            Error::NotEnoughFmtArguments(..) => DiagnosticCode::NotEnoughFmtArguments,
+            Error::UnknownMacro(..) => DiagnosticCode::UnknownMacro,
+            Error::FmtStrNotLiteral(..) => DiagnosticCode::FmtStrNotLiteral,
+            Error::ExtraMacroArguments(..) => DiagnosticCode::ExtraMacroArguments,
+            Error::MissingMacroArguments(..) => DiagnosticCode::MissingMacroArguments,
+            Error::NotAnExpression(..) => DiagnosticCode::NotAnExpression,
+            Error::UnresolvedName(..) => DiagnosticCode::UnresolvedName,
+            Error::IntLiteralTooLarge(..) => DiagnosticCode::IntLiteralTooLarge,
+            Error::NonIntegerOperand(..) => DiagnosticCode::NonIntegerOperand,
+            Error::MismatchedArgumentType(..) => DiagnosticCode::MismatchedArgumentType,
+            Error::MismatchedArgumentCount(..) => DiagnosticCode::MismatchedArgumentCount,
+            Error::NoMethod(..) => DiagnosticCode::NoMethod,
+            Error::MismatchedArmTypes(..) => DiagnosticCode::MismatchedArmTypes,
+            Error::MismatchedPatternType(..) => DiagnosticCode::MismatchedPatternType,
+            Error::NonExhaustiveMatch(..) => DiagnosticCode::NonExhaustiveMatch,
+            Error::NotDisplayable(..) => DiagnosticCode::NotDisplayable,
+            Error::NotDebuggable(..) => DiagnosticCode::NotDebuggable,
+            Error::MismatchedFmtType(..) => DiagnosticCode::MismatchedFmtType,
+            Error::TypeAnnotationsNeeded(..) => DiagnosticCode::TypeAnnotationsNeeded,
+            Error::UnknownType(..) => DiagnosticCode::UnknownType,
+            Error::UnsupportedReturnType(..) => DiagnosticCode::UnsupportedReturnType,
+            Error::MismatchedReturnType(..) => DiagnosticCode::MismatchedReturnType,
+            Error::EntryPointParameters(..) => DiagnosticCode::EntryPointParameters,
+            Error::MissingEntryPoint(..) => DiagnosticCode::MissingEntryPoint,
+            Error::UnexportableFunction(..) => DiagnosticCode::UnexportableFunction,
+            Error::UnknownAttribute(..) => DiagnosticCode::UnknownAttribute,
+            Error::MalformedAttribute(..) => DiagnosticCode::MalformedAttribute,
+            Error::MisplacedAttribute(..) => DiagnosticCode::MisplacedAttribute,
+            Error::UnknownLint(..) => DiagnosticCode::UnknownLint,
+            Error::DeniedLint(warning, _) => warning.code(),
+            Error::UnexpectedSelf(..) => DiagnosticCode::UnexpectedSelf,
+            Error::RecursiveType(..) => DiagnosticCode::RecursiveType,
+            Error::DuplicateDefinition(..) => DiagnosticCode::DuplicateDefinition,
+            Error::StaticMut(..) => DiagnosticCode::StaticMut,
+            Error::NonConstStatic(..) => DiagnosticCode::NonConstStatic,
+            Error::CyclicStatic(..) => DiagnosticCode::CyclicStatic,
+            Error::MismatchedStaticType(..) => DiagnosticCode::MismatchedStaticType,
+            Error::StaticEvalFailed(..) => DiagnosticCode::StaticEvalFailed,
+            Error::ImplOnBuiltinType(..) => DiagnosticCode::ImplOnBuiltinType,
+            Error::NoField(..) => DiagnosticCode::NoField,
+            Error::UnknownTrait(..) => DiagnosticCode::UnknownTrait,
+            Error::MissingTraitMethods(..) => DiagnosticCode::MissingTraitMethods,
+            Error::NotATraitMethod(..) => DiagnosticCode::NotATraitMethod,
+            Error::MismatchedTraitMethod(..) => DiagnosticCode::MismatchedTraitMethod,
+            Error::UnsatisfiedBound(..) => DiagnosticCode::UnsatisfiedBound,
+            Error::NotCallable(..) => DiagnosticCode::NotCallable,
+            Error::NotAReference(..) => DiagnosticCode::NotAReference,
+            Error::NotSliceable(..) => DiagnosticCode::NotSliceable,
+            Error::NonIntegerBound(..) => DiagnosticCode::NonIntegerBound,
+            Error::NotIterable(..) => DiagnosticCode::NotIterable,
+            Error::RefutableForPattern(..) => DiagnosticCode::RefutableForPattern,
+            Error::RefutableLetPattern(..) => DiagnosticCode::RefutableLetPattern,
+            Error::IrrefutableIfLetPattern(_) => DiagnosticCode::IrrefutableIfLetPattern,
+            Error::IrrefutableWhileLetPattern(_) => DiagnosticCode::IrrefutableWhileLetPattern,
+            Error::MissingElse(..) => DiagnosticCode::MissingElse,
+            Error::MismatchedLetType(..) => DiagnosticCode::MismatchedLetType,
+            Error::AmbiguousType(..) => DiagnosticCode::AmbiguousType,
+            Error::MovedInLoop(..) => DiagnosticCode::MovedInLoop,
+            Error::UnhashableKey(..) => DiagnosticCode::UnhashableKey,
+            Error::Unsortable(..) => DiagnosticCode::Unsortable,
+            Error::UseAfterMove(..) => DiagnosticCode::UseAfterMove,
+            Error::MoveWhileBorrowed(..) => DiagnosticCode::MoveWhileBorrowed,
+            Error::ConflictingBorrow(..) => DiagnosticCode::ConflictingBorrow,
+            Error::DanglingReference(..) => DiagnosticCode::DanglingReference,
+            Error::UnresolvedTypeParam(..) => DiagnosticCode::UnresolvedTypeParam,
+            Error::AmbiguousTypeParam(..) => DiagnosticCode::AmbiguousTypeParam,
+            Error::MismatchedTypeArgumentCount(..) => DiagnosticCode::MismatchedTypeArgumentCount,
+            Error::TryOutsideResult(..) => DiagnosticCode::TryOutsideResult,
+            Error::TryNotResult(..) => DiagnosticCode::TryNotResult,
+            Error::MismatchedErrorType(..) => DiagnosticCode::MismatchedErrorType,
+            Error::InternalCompilerError(..) => DiagnosticCode::InternalCompilerError,
+            Error::RuntimeIoError(..) => DiagnosticCode::RuntimeIoError,
+            Error::Panicked(..) => DiagnosticCode::Panicked,
+            Error::PanickedAt(..) => DiagnosticCode::Panicked,
+            Error::Exited(..) => DiagnosticCode::Exited,
+            Error::OutputLimitExceeded(..) => DiagnosticCode::OutputLimitExceeded,
+        }
     }
-    builder.build_return(None);
+}
 
-    if let Err(e) = module.verify() {
-        panic!("{}", e.to_string());
+impl From<io::Error> for Error<'_> {
+    fn from(error: io::Error) -> Self {
+        if let Some(Exit(status)) = error.get_ref().and_then(|error| error.downcast_ref()) {
+            return Error::Exited(*status);
+        }
+        if let Some(PanicAt(span, message)) = error.get_ref().and_then(|error| error.downcast_ref())
+        {
+            return Error::PanickedAt(span.clone(), message.clone());
+        }
+        match error
+            .get_ref()
+            .and_then(|error| error.downcast_ref::<Panic>())
+        {
+            Some(Panic(message)) => Error::Panicked(message.clone()),
+            None => Error::RuntimeIoError(error.to_string()),
+        }
     }
-    let main: JitFunction<unsafe extern "C" fn()> =
-        unsafe { execution_engine.get_function("main") }.unwrap();
-    Ok(main)
 }
 
-fn build_macro_invocation<'src>(
-    m: Macro<'src>,
-    context: &Context,
-    builder: &Builder,
-    write: FunctionValue,
-    std_out: GlobalValue,
-) -> Result<(), Error<'src>> {
-    let Macro { name, args } = m;
-    match name.name {
-        "println!" => build_println(context, builder, write, std_out, name.span, args.as_slice()),
-        "print!" => build_print(context, builder, write, std_out, name.span, args.as_slice()),
-        _ => todo!(),
+/// Keywords the grammar uses are lexed as their own tokens, so using one as a name fails to parse
+/// with the keyword's token where an identifier was expected. That's reported just like a keyword
+/// the grammar reserves.
+impl<'src> From<ParseError<usize, Token<'src>, SyntaxError>> for Error<'src> {
+    fn from(error: ParseError<usize, Token<'src>, SyntaxError>) -> Self {
+        match error {
+            ParseError::User {
+                error: SyntaxError::KeywordAsName(range),
+            } => Error::KeywordAsName(range),
+            ParseError::User {
+                error: SyntaxError::UnknownFragment(range),
+            } => Error::UnknownFragment(range),
+            ParseError::UnrecognizedToken {
+                token: (l, Token(_, token), r),
+                expected,
+            } if is_keyword(token) && expected.iter().any(|e| e == IDENT_TOKEN) => {
+                Error::KeywordAsName(l..r)
+            }
+            error => Error::ParseError(error),
+        }
     }
 }
 
-fn build_println<'src>(
-    context: &Context,
-    builder: &Builder,
-    write: FunctionValue,
-    std_out: GlobalValue,
-    println_name_span: Range<usize>,
-    args: &[StrLit<'src>],
-) -> Result<(), Error<'src>> {
-    if args.len() > 0 {
-        build_print(
-            context,
-            builder,
-            write,
-            std_out,
-            println_name_span.clone(),
-            args,
-        )?;
-    }
-    build_print_str(context, builder, write, std_out, "\n");
-    Ok(())
+/// How lalrpop names the identifier token in a parse error's expected tokens.
+const IDENT_TOKEN: &str = r###"r#"[a-zA-Z_][a-zA-Z0-9_]*"#"###;
+
+#[cfg(feature = "llvm")]
+pub fn run<'src>(source_code: &'src str, std_out: impl Write) -> Result<(), Error<'src>> {
+    Session::builder().build().run(source_code, std_out)
 }
 
-fn build_print<'src>(
-    context: &Context,
-    builder: &Builder,
-    write: FunctionValue,
-    std_out: GlobalValue,
-    print_name_span: Range<usize>,
-    args: &[StrLit<'src>],
+#[cfg(feature = "llvm")]
+pub fn run_with_streams<'src>(
+    source_code: &'src str,
+    std_in: impl Read,
+    std_out: impl Write,
+    std_err: impl Write,
 ) -> Result<(), Error<'src>> {
-    if args.is_empty() {
-        return Err(Error::MissingFmtStr(print_name_span.clone()));
-    }
-
-    let fmt_str = &args[0];
-    let specs = extract_fmt(fmt_str)
-        .map_err(|location| Error::ParseError(ParseError::InvalidToken { location }))?;
-    let specs = specs.iter();
-    let format_specifier_spans: Vec<_> = specs
-        .clone()
-        .filter_map(|spec| match spec {
-            FmtSpec::Arg { span } => Some(span.clone()),
-            FmtSpec::Lit { .. } => None,
-        })
-        .collect();
-
-    let args = &args[1..];
-    let expected_arg_count = format_specifier_spans.len();
-    if args.len() > expected_arg_count {
-        return Err(Error::ExtraFmtArguments(
-            fmt_str.span.clone(),
-            args[expected_arg_count..]
-                .into_iter()
-                .map(|arg| arg.span.clone())
-                .collect(),
-        ));
-    }
-    if args.len() < expected_arg_count {
-        return Err(Error::NotEnoughFmtArguments(
-            format_specifier_spans,
-            args.into_iter().map(|arg| arg.span.clone()).collect(),
-        ));
-    }
-
-    let mut args = args.into_iter();
-    for spec in specs {
-        let lit = match spec {
-            FmtSpec::Lit { val, .. } => val,
-            FmtSpec::Arg { .. } => args.next().unwrap().val,
-        };
-
-        build_print_str(context, builder, write, std_out, lit);
-    }
-    Ok(())
+    Session::builder()
+        .build()
+        .run_with_streams(source_code, std_in, std_out, std_err)
 }
 
-fn build_print_str<'src>(
-    context: &Context,
-    builder: &Builder,
-    write: FunctionValue,
-    std_out: GlobalValue,
-    lit: &'src str,
-) {
-    let writer = std_out.as_pointer_value().into();
-    let buffer = builder
-        .build_global_string_ptr(lit, "")
-        .as_pointer_value()
-        .into();
-    let len = context
-        .i64_type()
-        .const_int(lit.len().try_into().unwrap(), false)
-        .into();
-    builder.build_call(write, &[writer, buffer, len], "");
+pub fn parse(source_code: &str) -> Result<Program, Error<'_>> {
+    ProgramParser::new()
+        .parse(&NodeIds::default(), source_code)
+        .map_err(Error::from)
 }
 
-fn link_write<'ctx>(
-    module: &Module<'ctx>,
-    execution_engine: &ExecutionEngine<'ctx>,
-) -> FunctionValue<'ctx> {
-    let context = module.get_context();
-    let i64_type = context.i64_type();
-    let i8_type = context.i8_type();
-    let box_type = i8_type.ptr_type(AddressSpace::default());
-
-    let ext_write = module.add_function(
-        "write",
-        i64_type.fn_type(
-            &[
-                box_type.ptr_type(AddressSpace::default()).into(),
-                i8_type.ptr_type(AddressSpace::default()).into(),
-                i64_type.into(),
-            ],
-            false,
-        ),
-        None,
-    );
-
-    extern "C" fn write(os: *mut Box<dyn Write>, s: *const u8, l: u64) -> u64 {
-        let os = unsafe { os.as_mut() }.unwrap();
-        let s = unsafe { std::slice::from_raw_parts(s, l.try_into().unwrap()) };
-        os.write(s).unwrap().try_into().unwrap()
-    }
-
-    execution_engine.add_global_mapping(&ext_write, write as usize);
-    ext_write
+/// Checks `data` in a sandbox and renders any error it raises, for fuzzing. Returns whether it's a
+/// valid program; input that isn't UTF-8 isn't one. Never panics.
+pub fn parse_for_fuzzing(data: &[u8]) -> bool {
+    let Ok(source_code) = std::str::from_utf8(data) else {
+        return false;
+    };
+    let session = Session::builder().sandbox(Sandbox::default()).build();
+    match session.check(source_code) {
+        Ok(()) => true,
+        Err(error) => {
+            report_error(
+                Path::new("fuzz.sculpt"),
+                source_code,
+                error,
+                false,
+                io::sink(),
+            );
+            false
+        }
+    }
 }
 
-fn link_std_out<'ctx>(
-    std_out: &mut Box<dyn Write + 'ctx>,
-    module: &Module<'ctx>,
-    execution_engine: &ExecutionEngine<'ctx>,
-) -> GlobalValue<'ctx> {
-    let context = module.get_context();
-    let box_type = context.i8_type().ptr_type(AddressSpace::default());
-
-    let ext_std_out = module.add_global(box_type, None, "std_out");
-
-    let std_out_ptr = std_out as *mut Box<dyn Write>;
-    let std_out_addr = std_out_ptr as usize;
-
-    execution_engine.add_global_mapping(&ext_std_out, std_out_addr);
-    ext_std_out
+/// Parses `source_code` as the statements of a `main` function, like a source_code typed into the REPL.
+pub fn parse_statements(source_code: &str) -> Result<Program, Error<'_>> {
+    let ids = NodeIds::default();
+    let statements = StatementsParser::new()
+        .parse(&ids, source_code)
+        .map_err(Error::from)?;
+    Ok(Program {
+        id: ids.next(),
+        attrs: Vec::new(),
+        functions: vec![Function {
+            id: ids.next(),
+            span: 0..source_code.len(),
+            docs: Vec::new(),
+            attrs: Vec::new(),
+            public: false,
+            name: Name {
+                id: ids.next(),
+                span: 0..0,
+                name: sym::MAIN,
+            },
+            generics: Vec::new(),
+            params: Vec::new(),
+            ret: None,
+            statements,
+            tail: None,
+        }],
+        structs: Vec::new(),
+        impls: Vec::new(),
+        traits: Vec::new(),
+        macros: Vec::new(),
+        statics: Vec::new(),
+    })
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "llvm"))]
 mod tests {
     use super::*;
     use crate::report::report_error;
-    use textwrap;
-
-    fn dedent(s: &str) -> String {
-        textwrap::dedent(s).trim().to_string()
-    }
 
     trait Code {
-        fn run<'src>(&'src self) -> Result<String, String>;
+        /// Runs the program, returning what it wrote to stdout and stderr, or the rendered error.
+        fn run(&self) -> Result<(String, String), String>;
     }
 
     impl Code for str {
-        fn run<'src>(&'src self) -> Result<String, String> {
+        fn run(&self) -> Result<(String, String), String> {
             let mut output_buf = Vec::new();
+            let mut stderr_buf = Vec::new();
             let stdout = std::io::BufWriter::new(&mut output_buf);
-            run(self, stdout)
-                .map(|_| String::from_utf8(output_buf).unwrap())
+            let stderr = std::io::BufWriter::new(&mut stderr_buf);
+            run_with_streams(self, std::io::empty(), stdout, stderr)
+                .map(|_| {
+                    (
+                        String::from_utf8(output_buf).unwrap(),
+                        String::from_utf8(stderr_buf).unwrap(),
+                    )
+                })
                 .map_err(|error| {
                     let mut error_buf = Vec::new();
                     let stderr = std::io::BufWriter::new(&mut error_buf);
@@ -278,7 +631,9 @@ mod tests {
             fn main() {
             }
         "#;
-        assert_eq!(src.run().unwrap(), "");
+        let (stdout, stderr) = src.run().unwrap();
+        assert_eq!(stdout, "");
+        assert_eq!(stderr, "");
     }
 
     #[test]
@@ -291,7 +646,79 @@ mod tests {
                 println!();
             }
         "#;
-        assert_eq!(src.run().unwrap(), "Hello world!\n");
+        let (stdout, stderr) = src.run().unwrap();
+        assert_eq!(stdout, "Hello world!\n");
+        assert_eq!(stderr, "");
+    }
+
+    #[test]
+    fn eprint_writes_to_stderr() {
+        let src = r#"
+            fn main() {
+                print!("out");
+                eprint!("err");
+                eprintln!(" {}", "line");
+            }
+        "#;
+        let (stdout, stderr) = src.run().unwrap();
+        assert_eq!(stdout, "out");
+        assert_eq!(stderr, "err line\n");
+    }
+
+    #[test]
+    fn stdin_is_read_by_read_macros() {
+        let src = r#"
+            fn main() {
+                println!("Hello {}!", read_line!());
+                read_line!();
+                print!("{}", read_to_string!());
+            }
+        "#;
+        let mut output = Vec::new();
+        run_with_streams(
+            src,
+            &b"Alice\r\nBob\nrest\n"[..],
+            &mut output,
+            std::io::sink(),
+        )
+        .unwrap();
+        assert_eq!(output, b"Hello Alice!\nrest\n");
+    }
+
+    #[test]
+    fn format_arguments_are_evaluated_in_order() {
+        let src = r#"
+            fn main() {
+                println!("{} {} {}", read_line!(), format!("<{}>", read_line!()), read_line!());
+            }
+        "#;
+        let mut output = Vec::new();
+        run_with_streams(src, &b"a\nb\nc\n"[..], &mut output, io::sink()).unwrap();
+        assert_eq!(output, b"a <b> c\n");
+    }
+
+    #[test]
+    fn format_arguments_are_evaluated_before_writing() {
+        let mut output = Vec::new();
+        assert_eq!(
+            run(r#"fn main() { print!("a{}", 1 / 0); }"#, &mut output),
+            Err(Error::Panicked("attempt to divide by zero".into()))
+        );
+        assert!(output.is_empty());
+    }
+
+    #[test]
+    fn user_macros_are_expanded() {
+        let src = r#"
+            macro_rules! square {
+                ($label:expr, $x:expr) => { println!("{}: {}", $label, $x * $x); };
+            }
+            fn main() {
+                square!("three", 1 + 2);
+            }
+        "#;
+        let (stdout, _) = src.run().unwrap();
+        assert_eq!(stdout, "three: 9\n");
     }
 
     #[test]
@@ -301,118 +728,813 @@ mod tests {
                 println!("Hello {} and {}!", "Alice", "Bob");
             }
         "#;
-        assert_eq!(src.run().unwrap(), "Hello Alice and Bob!\n");
+        let (stdout, stderr) = src.run().unwrap();
+        assert_eq!(stdout, "Hello Alice and Bob!\n");
+        assert_eq!(stderr, "");
     }
 
     #[test]
-    fn invalid_fmt_string_errors_are_reported() {
-        let src = dedent(
-            r#"
+    fn fuzzing_entry_points_reject_malformed_input() {
+        assert!(parse_for_fuzzing(b"fn main() { println!(\"{{}}\"); }"));
+        assert!(!parse_for_fuzzing(b"fn main() { println!(\"{\"); }"));
+        assert!(!parse_for_fuzzing(b"fn main() {} \xff"));
+        assert_eq!(
+            crate::fmt_str::extract_fmt_for_fuzzing("a{b").unwrap_err(),
+            crate::fmt_str::FmtError::UnmatchedBrace(2..3, '{')
+        );
+    }
+
+    #[test]
+    fn keywords_are_reserved_everywhere_names_are_expected() {
+        for (src, span) in [
+            ("fn fn() {}", 3..5),
+            ("#[pub] fn main() {}", 2..5),
+            (r#"fn main() { print!("{}", return); }"#, 25..31),
+            (r#"fn main() { print!("{}", 1 + loop); }"#, 29..33),
+        ] {
+            assert_eq!(
+                parse(src).err(),
+                Some(Error::KeywordAsName(span)),
+                "{}",
+                src
+            );
+        }
+        assert!(parse("fn lets() {}").is_ok());
+    }
+
+    #[test]
+    fn syntax_errors_left_unconverted_are_reported_like_converted_ones() {
+        let src = "fn fn() {}";
+        let unconverted = Error::ParseError(ParseError::User {
+            error: SyntaxError::KeywordAsName(3..5),
+        });
+        assert_eq!(
+            crate::testing::render(src, unconverted),
+            crate::testing::render(src, Error::KeywordAsName(3..5))
+        );
+    }
+
+    #[test]
+    fn env_reads_variables_at_compile_time() {
+        assert_eq!(
+            r#"fn main() { print!("{}", env!("CARGO_PKG_NAME")); }"#.run(),
+            Ok(("sculpt".into(), "".into()))
+        );
+    }
+
+    #[test]
+    fn option_env_reads_variables_at_compile_time() {
+        let src = r#"
             fn main() {
-                println!("}");
+                let unset: Option<str> = option_env!("SCULPT_UNSET");
+                print!("{} {:?}", option_env!("CARGO_PKG_NAME").unwrap(), unset);
             }
-            "#,
+        "#;
+        assert_eq!(src.run(), Ok(("sculpt None".into(), "".into())));
+    }
+
+    #[test]
+    fn getenv_reads_the_environment_at_run_time() {
+        let src = r#"
+            fn main() {
+                let unset = getenv!(format!("SCULPT_{}", "UNSET"));
+                print!("{} {:?}", getenv!("CARGO_PKG_NAME").unwrap(), unset);
+            }
+        "#;
+        assert_eq!(src.run(), Ok(("sculpt None".into(), "".into())));
+    }
+
+    #[test]
+    fn trailing_commas_are_accepted() {
+        let mut output = Vec::new();
+        run(
+            r#"fn main() { println!("{} {}", "a", 1 + 1,); println!("b",); }"#,
+            &mut output,
+        )
+        .unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "a 2\nb\n");
+    }
+
+    struct BrokenPipe;
+
+    impl Write for BrokenPipe {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            Err(io::ErrorKind::BrokenPipe.into())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn write_failures_abort_the_program() {
+        let src = r#"
+            fn main() {
+                println!("a");
+                eprintln!("b");
+            }
+        "#;
+        let mut std_err = Vec::new();
+        assert_eq!(
+            run_with_streams(src, io::empty(), BrokenPipe, &mut std_err),
+            Err(Error::RuntimeIoError("broken pipe".into()))
+        );
+        assert!(std_err.is_empty());
+    }
+
+    #[test]
+    fn arithmetic_is_printed_in_decimal() {
+        let mut output = Vec::new();
+        run(
+            r#"fn main() { println!("{} {} {}", 1 + 2 * 3, (1 - 8) / 2 % 2, -9223372036854775808); }"#,
+            &mut output,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "7 -1 -9223372036854775808\n"
         );
+    }
+
+    #[test]
+    fn division_by_zero_panics() {
+        let mut output = Vec::new();
+        assert_eq!(
+            run(
+                r#"fn main() { print!("a"); print!("{}", 1 / (2 - 2)); print!("b"); }"#,
+                &mut output
+            ),
+            Err(Error::Panicked("attempt to divide by zero".into()))
+        );
+        assert_eq!(output, b"a");
+    }
+
+    #[test]
+    fn overflow_panics() {
+        assert_eq!(
+            run(
+                r#"fn main() { print!("{}", 9223372036854775807 + 1); }"#,
+                io::sink()
+            ),
+            Err(Error::Panicked("attempt to add with overflow".into()))
+        );
+        assert_eq!(
+            run(
+                r#"fn main() { print!("{}", -(-9223372036854775807 - 1)); }"#,
+                io::sink()
+            ),
+            Err(Error::Panicked("attempt to negate with overflow".into()))
+        );
+    }
+
+    #[test]
+    fn sleep_waits_on_the_host_clock() {
+        let mut output = Vec::new();
+        run(
+            r#"fn main() { print!("{} ", now_millis!()); sleep_millis!(20); print!("{}", now_millis!()); }"#,
+            &mut output,
+        )
+        .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let (before, after) = output.split_once(' ').unwrap();
+        let (before, after) = (
+            before.parse::<i64>().unwrap(),
+            after.parse::<i64>().unwrap(),
+        );
+        assert!(before > 1_600_000_000_000);
+        assert!(after - before >= 20);
+        assert_eq!(
+            run(r#"fn main() { sleep_millis!(-1); }"#, io::sink()),
+            Err(Error::Panicked(
+                "cannot sleep for a negative duration".into()
+            ))
+        );
+    }
+
+    #[test]
+    fn files_are_read_and_written_on_the_host() {
+        let path = std::env::temp_dir().join(format!("sculpt-files-{}", std::process::id()));
+        let path = path.display();
+        let src = format!(
+            r#"fn main() {{ write_file!("{path}", "a"); write_file!("{path}", format!("{{}}b", read_file!("{path}"))); }}"#
+        );
+        run(&src, io::sink()).unwrap();
+        assert_eq!(std::fs::read_to_string(path.to_string()).unwrap(), "ab");
+        std::fs::remove_file(path.to_string()).unwrap();
         assert_eq!(
-            src.run().err().unwrap(),
-            dedent(
-                r#"
-                [InvalidToken] Error: encountered unexpected syntax
-                   ╭─[file.sculpt:2:15]
-                   │
-                 2 │     println!("}");
-                   │               ┬
-                   │               ╰── unexpected syntax
-                ───╯
-                "#
-            )
+            run(
+                &format!(r#"fn main() {{ read_file!("{path}"); }}"#),
+                io::sink()
+            ),
+            Err(Error::Panicked(format!(
+                "could not read `{path}`: No such file or directory (os error 2)"
+            )))
         );
     }
 
     #[test]
-    fn missing_fmt_string_errors_are_reported() {
-        let src = dedent(
+    fn random_int_panics_on_empty_ranges() {
+        assert_eq!(
+            run(
+                r#"fn main() { print!("{}", random_int!(3, 3)); }"#,
+                io::sink()
+            ),
+            Err(Error::Panicked("cannot sample empty range".into()))
+        );
+    }
+
+    #[test]
+    fn options_are_matched_and_unwrapped() {
+        let mut output = Vec::new();
+        run(
+            r#"
+            fn main() {
+                println!("{}", match Some(Some(2)) { Some(Some(x)) => x * 21, Some(None) => 1, None => 0 });
+                println!("{}", match None { Some(s) => s, None => "none" });
+                println!("{}", Some(format!("{}!", "hi")).unwrap());
+            }
+            "#,
+            &mut output,
+        )
+        .unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "42\nnone\nhi!\n");
+    }
+
+    #[test]
+    fn strings_are_matched_against_literals() {
+        let mut output = Vec::new();
+        run(
             r#"
+            fn number(word: str) -> i64 {
+                match word { "one" => 1, "two" => 2, "" => 0, "three" => 3, "ten" => 10, _ => -1 }
+            }
             fn main() {
-                print!();
+                println!("{} {} {} {}", number("one"), number("ten"), number("tan"), number(""));
+                println!("{}", number("three") + number("eleven"));
+                println!("{}", match Some("b") { Some("a") => 1, Some("b") => 2, _ => 3 });
             }
             "#,
+            &mut output,
+        )
+        .unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "1 10 -1 0\n2\n2\n");
+    }
+
+    #[test]
+    fn if_let_and_while_let_run_their_blocks_when_the_pattern_matches() {
+        let src = r#"
+            fn describe(o: Option<i64>) -> str {
+                if let Some(n) = o { format!("some {}", n) } else { "none" }
+            }
+            fn main() {
+                println!("{} {}", describe(Some(3)), describe(None));
+                if let None = Some(1) { println!("none"); } else if let Some(s) = Some("hi") {
+                    println!("{}", s);
+                }
+                while let "y" = read_line!() {
+                    println!("again");
+                }
+            }
+        "#;
+        let mut output = Vec::new();
+        run_with_streams(
+            src,
+            &b"y
+y
+n
+y
+"[..],
+            &mut output,
+            io::sink(),
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "some 3 none\nhi\nagain\nagain\n"
         );
+    }
+
+    #[test]
+    fn unwrapping_none_panics_at_the_call() {
+        let src = r#"fn main() { print!("a"); print!("{}", match 1 { x => None }.unwrap() + 1); }"#;
+        let mut output = Vec::new();
         assert_eq!(
-            src.run().err().unwrap(),
-            dedent(
-                r#"
-                [MissingFmtStr] Error:
-                   ╭─[file.sculpt:2:5]
-                   │
-                 2 │     print!();
-                   │     ───┬──
-                   │        ╰──── requires at least a format string argument
-                ───╯
-                "#
-            )
+            run(src, &mut output),
+            Err(Error::PanickedAt(
+                38..68,
+                "called `Option::unwrap()` on a `None` value".into()
+            ))
         );
+        assert_eq!(output, b"a");
     }
 
     #[test]
-    fn extra_fmt_argument_errors_are_reported() {
-        let src = dedent(
-            r#"
+    fn strings_are_sliced_on_char_boundaries() {
+        let src = r#"
             fn main() {
-                print!(" {} ", "a", "b", "c");
+                println!("{} {} {}", "hello"[1..3], "héllo"[..3].len(), "héllo"[3..]);
+                println!("{}", "héllo"[..2]);
             }
-            "#,
+        "#;
+        let mut output = Vec::new();
+        assert_eq!(
+            run(src, &mut output),
+            Err(Error::PanickedAt(
+                145..158,
+                "byte index 2 is not a char boundary; it is inside 'é' (bytes 1..3) of `héllo`"
+                    .into()
+            ))
         );
+        assert_eq!(String::from_utf8(output).unwrap(), "el 3 llo\n");
+        let mut output = Vec::new();
         assert_eq!(
-            src.run().err().unwrap(),
-            dedent(
-                r#"
-                [ExtraFmtArguments] Error: multiple unused formatting arguments
-                   ╭─[file.sculpt:2:12]
-                   │
-                 2 │     print!(" {} ", "a", "b", "c");
-                   │            ───┬──       ─┬─  ─┬─
-                   │               ╰─────────────────── multiple missing formatting specifiers
-                   │                          │    │
-                   │                          ╰──────── argument never used
-                   │                               │
-                   │                               ╰─── argument never used
-                ───╯
-                "#
-            )
+            run(r#"fn main() { print!("{}", "abc"[2..1]); }"#, &mut output),
+            Err(Error::PanickedAt(
+                25..36,
+                "begin <= end (2 <= 1) when slicing `abc`".into()
+            ))
         );
     }
 
-    // TODO: Modify labels or trim output before writing so that there's less dead space at the end
-    // of the report.
     #[test]
-    fn missing_fmt_argument_errors_are_reported() {
-        let src = dedent(
-            r#"
+    fn strings_have_methods() {
+        let src = r#"
+            fn shout(s: &str) -> str { s.trim().to_uppercase() }
+
             fn main() {
-                print!("{} {} {}", "a");
+                println!("[{}] {}", shout(&"  hé  "), "MiXeD".to_lowercase());
+                println!("{} {}", "haystack".contains("st"), "haystack".contains(&"x"));
             }
-            "#,
+        "#;
+        let (stdout, stderr) = src.run().unwrap();
+        assert_eq!(stdout, "[HÉ] mixed\ntrue false\n");
+        assert_eq!(stderr, "");
+    }
+
+    #[test]
+    fn for_loops_iterate_over_ranges_vecs_and_chars() {
+        let src = r#"
+            fn total(v: &Vec<Box<i64>>) -> i64 { v.len() }
+
+            fn first(s: str) -> str { match s.chars().next() { Some(c) => c, None => "-" } }
+
+            fn main() {
+                for i in 0..3 { print!("{}", i); }
+                for s in vec!["a", "b"] { print!(" {}", s); }
+                for c in "hé!".chars() { print!(" [{}]", c); }
+                for b in &vec![Box::new(7)] { print!(" {}", **b); }
+                println!(" {} {}{}", total(&vec![Box::new(1)]), first("xy"), first(""));
+            }
+        "#;
+        let (stdout, stderr) = src.run().unwrap();
+        assert_eq!(stdout, "012 a b [h] [é] [!] 7 1 x-\n");
+        assert_eq!(stderr, "");
+    }
+
+    #[test]
+    fn errors_propagate_out_of_main() {
+        let src = r#"
+            fn main() -> Result<(), str> {
+                print!("{} ", Ok(1)?);
+                print!("{}", match Err("boom") { Ok(x) => Ok(x + 1), Err(e) => Err(format!("{}!", e)) }?);
+                print!("unreachable");
+            }
+        "#;
+        let mut output = Vec::new();
+        let mut errors = Vec::new();
+        assert_eq!(
+            run_with_streams(src, io::empty(), &mut output, &mut errors),
+            Err(Error::Exited(1))
+        );
+        assert_eq!(output, b"1 ");
+        assert_eq!(errors, b"Error: boom!\n");
+    }
+
+    #[test]
+    fn methods_are_called_on_structs() {
+        let src = r#"
+            struct Point(i64, i64);
+
+            impl Point {
+                fn new(x: i64, y: i64) -> Self {
+                    Point(x, y)
+                }
+
+                fn norm_squared(&self) -> i64 {
+                    self.0 * self.0 + self.1 * self.1
+                }
+
+                fn describe(&self) -> str {
+                    format!("({}, {}) has norm² {}", self.0, self.1, self.norm_squared())
+                }
+            }
+
+            fn main() {
+                println!("{}", Point::new(3, 4).describe());
+                println!("{}", match Point(1, 2) { Point(x, y) => x - y });
+            }
+        "#;
+        let (stdout, stderr) = src.run().unwrap();
+        assert_eq!(stdout, "(3, 4) has norm² 25\n-1\n");
+        assert_eq!(stderr, "");
+    }
+
+    #[test]
+    fn errors_propagate_through_calls() {
+        let src = r#"
+            fn half(x: Option<i64>) -> Result<i64, str> {
+                match x {
+                    Some(x) => Ok(x / 2),
+                    None => Err(format!("{} is missing", "x")),
+                }
+            }
+
+            fn quarter(x: Option<i64>) -> Result<i64, str> {
+                Ok(half(x)? / 2)
+            }
+
+            fn main() -> Result<(), str> {
+                print!("{} ", quarter(Some(8))?);
+                print!("{}", quarter(None)?);
+                print!("unreachable");
+            }
+        "#;
+        let mut output = Vec::new();
+        let mut errors = Vec::new();
+        assert_eq!(
+            run_with_streams(src, io::empty(), &mut output, &mut errors),
+            Err(Error::Exited(1))
+        );
+        assert_eq!(output, b"2 ");
+        assert_eq!(errors, b"Error: x is missing\n");
+    }
+
+    #[test]
+    fn panics_in_called_functions_abort_the_program() {
+        let src = r#"
+            fn div(x: i64, y: i64) -> i64 { x / y }
+            fn main() { print!("{}", div(4, 2)); print!("{}", div(1, 0)); print!("b"); }
+        "#;
+        let mut output = Vec::new();
+        assert_eq!(
+            run_with_streams(src, io::empty(), &mut output, io::sink()),
+            Err(Error::Panicked("attempt to divide by zero".into()))
         );
+        assert_eq!(output, b"2");
+    }
+
+    #[test]
+    fn generic_functions_are_instantiated_for_each_type() {
+        let src = r#"
+            fn id<T>(x: T) -> T { x }
+
+            fn or<T>(x: Option<T>, default: T) -> T {
+                match x { Some(x) => x, None => default }
+            }
+
+            fn main() {
+                println!("{} {}", id(1), id("one"));
+                println!("{}", or(None, id::<str>("two")));
+                println!("{}", or::<i64>(None, 3));
+            }
+        "#;
+        let (stdout, stderr) = src.run().unwrap();
+        assert_eq!(stdout, "1 one\ntwo\n3\n");
+        assert_eq!(stderr, "");
+    }
+
+    #[test]
+    fn generic_functions_call_trait_methods_of_their_arguments() {
+        let src = r#"
+            trait Describe {
+                fn describe(self) -> str;
+            }
+
+            struct Cat(str);
+            struct Dice(i64, i64);
+
+            impl Describe for Cat {
+                fn describe(self) -> str { format!("a cat named {}", self.0) }
+            }
+
+            impl Describe for Dice {
+                fn describe(self) -> str { format!("dice showing {} and {}", self.0, self.1) }
+            }
+
+            fn show<T: Describe>(x: T) -> str {
+                format!("this is {}", x.describe())
+            }
+
+            fn either<T: Describe>(x: Option<T>, default: T) -> str {
+                match x { Some(x) => show(x), None => show(default) }
+            }
+
+            fn main() {
+                println!("{}", show(Cat("Tom")));
+                println!("{}", show(Dice(3, 5)));
+                println!("{}", either(None, Cat("Felix")));
+            }
+        "#;
+        let (stdout, stderr) = src.run().unwrap();
         assert_eq!(
-            src.run().err().unwrap(),
-            dedent(
-                r#"
-                [NotEnoughFmtArguments] Error: 3 positional arguments in format string, but there is 1 argument
-                   ╭─[file.sculpt:2:13]
-                   │
-                 2 │     print!("{} {} {}", "a");
-                   │             ── ── ──   ───
-                   │
-                   │
-                   │
-                   │
-                   │
-                   │
-                   │
-                ───╯
-                "#
-            )
+            stdout,
+            "this is a cat named Tom\nthis is dice showing 3 and 5\nthis is a cat named Felix\n"
         );
+        assert_eq!(stderr, "");
+    }
+
+    #[test]
+    fn closures_are_called_with_the_locals_they_captured() {
+        let src = r#"
+            fn map<T, U, F: Fn(T) -> U>(x: Option<T>, f: F) -> Option<U> {
+                match x { Some(x) => Some(f(x)), None => None }
+            }
+
+            fn twice<T, F: Fn(T) -> T>(f: F, x: T) -> T {
+                map(Some(x), |x| f(f(x))).unwrap()
+            }
+
+            fn greet(n: i64, name: str) {
+                println!("{}", map(Some(1), |x| x + n).unwrap());
+                println!("{}", map(Some(n), |x| format!("{} is {}", name, x)).unwrap());
+                println!("{}", twice(|x| x * n, 3));
+            }
+
+            fn main() {
+                greet(10, "ten")
+            }
+        "#;
+        let (stdout, stderr) = src.run().unwrap();
+        assert_eq!(stdout, "11\nten is 10\n300\n");
+        assert_eq!(stderr, "");
+    }
+
+    #[test]
+    fn references_are_read_through() {
+        let src = r#"
+            struct Point(i64, i64);
+            struct Named(str, Point);
+
+            impl Point {
+                fn sum(&self) -> i64 { self.0 + self.1 }
+            }
+
+            fn size(n: &Named) -> i64 { n.1.sum() + n.1.0 }
+            fn name(n: &Named) -> &str { &n.0 }
+            fn pick<T>(x: &T) -> &T { x }
+
+            fn show(n: Named) -> str {
+                format!("{} {} {}", name(&n), size(&n), *pick(&n.1.1) + 1)
+            }
+
+            fn main() {
+                println!("{}", show(Named("a", Point(1, 2))));
+            }
+        "#;
+        let (stdout, stderr) = src.run().unwrap();
+        assert_eq!(stdout, "a 4 3\n");
+        assert_eq!(stderr, "");
+    }
+
+    #[test]
+    fn boxes_hold_recursive_types() {
+        let src = r#"
+            struct List(str, Option<Box<List>>);
+
+            fn cons(head: str, tail: List) -> List { List(head, Some(Box::new(tail))) }
+
+            fn len(list: &List) -> i64 {
+                match list.1 {
+                    Some(tail) => 1 + len(&*tail),
+                    None => 1,
+                }
+            }
+
+            fn last(list: List) -> str {
+                match list {
+                    List(_, Some(tail)) => last(*tail),
+                    List(head, None) => head,
+                }
+            }
+
+            fn main() {
+                println!("{}", len(&cons("a", cons(format!("{}", 1), List("c", None)))));
+                println!("{}", last(cons("a", cons("b", List(format!("c{}", 2), None)))));
+            }
+        "#;
+        let (stdout, stderr) = src.run().unwrap();
+        assert_eq!(stdout, "3\nc2\n");
+        assert_eq!(stderr, "");
+    }
+
+    #[test]
+    fn rcs_share_what_they_hold() {
+        let src = r#"
+            struct Node(str, i64);
+            struct Pair(Rc<Node>, Rc<Node>);
+
+            impl Node {
+                fn describe(&self) -> str { format!("{}={}", self.0, self.1) }
+            }
+
+            fn share(node: Rc<Node>) -> Pair { Pair(node.clone(), node) }
+            fn keep(pair: Pair) -> Rc<Node> { pair.1 }
+
+            fn main() {
+                println!("{}", keep(share(Rc::new(Node(format!("n{}", 1), 2)))).describe());
+                println!("{}", *Rc::clone(&Rc::new(4)).clone() + 1);
+            }
+        "#;
+        let (stdout, stderr) = src.run().unwrap();
+        assert_eq!(stdout, "n1=2\n5\n");
+        assert_eq!(stderr, "");
+    }
+
+    #[test]
+    fn hash_maps_count_words() {
+        let src = r#"
+            fn bump(counts: HashMap<str, i64>, word: &str) -> HashMap<str, i64> {
+                match counts.get(word) {
+                    Some(n) => counts.insert(*word, *n + 1),
+                    None => counts.insert(*word, 1),
+                }
+            }
+
+            fn main() {
+                println!("{:?}", bump(bump(bump(HashMap::new(), &"a"), &"b"), &"a"));
+                println!("{:?}", bump(bump(HashMap::new(), &"a"), &"b").remove(&"a"));
+                println!("{}", bump(HashMap::new(), &"a").len());
+                println!("{:?}", HashMap::new().insert(1, vec![Some(true)]).get(&2));
+            }
+        "#;
+        let (stdout, stderr) = src.run().unwrap();
+        assert_eq!(stdout, "{\"a\": 2, \"b\": 1}\n{\"b\": 1}\n1\nNone\n");
+        assert_eq!(stderr, "");
+    }
+
+    #[test]
+    fn structs_are_debug_formatted_like_derived_debug() {
+        let src = r#"
+            struct Point(i64, i64);
+            struct Origin();
+            struct Shape(str, Vec<Point>, Option<Origin>);
+            struct List(i64, Option<Box<List>>);
+
+            fn main() {
+                println!("{:?}", Shape("tri\"", vec![Point(0, 1), Point(-2, 3)], Some(Origin())));
+                println!("{:?}", Some(&List(1, Some(Box::new(List(2, None))))));
+            }
+        "#;
+        let (stdout, stderr) = src.run().unwrap();
+        assert_eq!(
+            stdout,
+            "Shape(\"tri\\\"\", [Point(0, 1), Point(-2, 3)], Some(Origin))\n\
+             Some(List(1, Some(List(2, None))))\n"
+        );
+        assert_eq!(stderr, "");
+    }
+
+    #[test]
+    fn display_implementations_format_values_with_braces() {
+        let src = r#"
+            struct Point(i64, i64);
+            impl Display for Point {
+                fn fmt(&self) -> str { format!("({}, {})", self.0, self.1) }
+            }
+            struct Line(Point, Point);
+            impl Display for Line {
+                fn fmt(&self) -> str { format!("{} -> {}", self.0, self.1) }
+            }
+            fn tagged<T: Display>(value: T) -> str { format!("<{}>", value) }
+
+            fn main() {
+                let p = Point(1, 2);
+                println!("{} {}", &p, tagged(Point(5, 6)));
+                println!("{}", Line(Point(0, 0), p));
+            }
+        "#;
+        let (stdout, stderr) = src.run().unwrap();
+        assert_eq!(stdout, "(1, 2) <(5, 6)>\n(0, 0) -> (1, 2)\n");
+        assert_eq!(stderr, "");
+    }
+
+    #[test]
+    fn integers_are_formatted_in_hexadecimal_octal_and_binary() {
+        let src = r#"
+            fn main() {
+                let n = 255;
+                println!("{:x} {:X} {:o} {:b}", n, &n, 8, 5);
+                println!("{:x} {:b}", -1, 0);
+            }
+        "#;
+        let (stdout, _) = src.run().unwrap();
+        assert_eq!(stdout, "ff FF 10 101\nffffffffffffffff 0\n");
+    }
+
+    #[test]
+    fn vecs_sort_stably() {
+        let src = r#"
+            struct Person(str, i64);
+
+            fn main() {
+                println!("{:?}", vec![3, -1, 10, 0].sort());
+                println!("{:?}", vec!["pear", "apple", "", "app"].sort());
+                for p in vec![Person("bo", 30), Person("al", 25), Person("cy", 30)].sort_by_key(|p| p.1) {
+                    print!("{} ", p.0);
+                }
+                for p in vec![Person("b", 1), Person("a", 2)].sort_by_key(|p| format!("{}", p.0)) {
+                    print!("{} ", p.0);
+                }
+            }
+        "#;
+        let (stdout, stderr) = src.run().unwrap();
+        assert_eq!(
+            stdout,
+            "[-1, 0, 3, 10]\n[\"\", \"app\", \"apple\", \"pear\"]\nal bo cy a b "
+        );
+        assert_eq!(stderr, "");
+    }
+
+    #[test]
+    fn statics_are_read_by_name() {
+        let src = r#"
+            static LIMIT: i64 = BASE * 2 + 1;
+            static BASE: i64 = -4 / 2 + 10;
+            static GREETING: &str = "hi";
+            static VERBOSE: bool = true;
+
+            fn shout(s: &str) -> str { s.to_uppercase() }
+
+            fn main() {
+                println!("{} {} {} {}", LIMIT, BASE, shout(GREETING), VERBOSE);
+            }
+        "#;
+        let (stdout, stderr) = src.run().unwrap();
+        assert_eq!(stdout, "17 8 HI true\n");
+        assert_eq!(stderr, "");
+    }
+
+    #[test]
+    fn lets_bind_values_for_the_rest_of_the_block() {
+        let src = r#"
+            struct Point(i64, i64);
+
+            fn shout(s: &str) -> Result<str, str> {
+                let loud = format!("{}!", s);
+                let checked = match loud.contains("?") {
+                    true => Err(format!("no questions")),
+                    false => Ok(loud),
+                };
+                checked
+            }
+
+            fn main() {
+                println!("{:?}", greet());
+            }
+
+            fn greet() -> Result<(), str> {
+                let x = 3;
+                let y = x + 1;
+                let Point(a, b) = Point(x, y);
+                let words = vec![format!("hi"), format!("yo")];
+                for word in &words {
+                    let shouted = shout(word)?;
+                    print!("{} ", shouted);
+                }
+                let last = { let s = format!("{}", a * b); s };
+                println!("{}", last);
+                let _ = shout(&"?")?;
+                println!("unreachable");
+            }
+        "#;
+        let (stdout, stderr) = src.run().unwrap();
+        assert_eq!(stdout, "hi! yo! 12\nErr(\"no questions\")\n");
+        assert_eq!(stderr, "");
+    }
+
+    #[test]
+    fn blocks_run_their_statements_before_their_tail() {
+        let src = r#"
+            fn double(n: Option<i64>) -> i64 {
+                match n {
+                    Some(v) => {
+                        eprintln!("doubling {}", v);
+                        v * 2
+                    },
+                    None => { 0 },
+                }
+            }
+
+            fn main() {
+                {
+                    println!("{}", double(Some(4)));
+                }
+                println!("{}", { print!("{} ", double(None)); format!("{}!", double(Some(1))) });
+            }
+        "#;
+        let (stdout, stderr) = src.run().unwrap();
+        assert_eq!(stdout, "8\n0 2!\n");
+        assert_eq!(stderr, "doubling 4\ndoubling 1\n");
     }
 }
@@ -0,0 +1,116 @@
+use std::io::{self, Write};
+use std::time::Duration;
+
+use crate::backend::{Clock, Files};
+
+/// Limits applied when running untrusted programs, e.g. code submitted to a playground.
+///
+/// Programs in a sandboxed session can only reach the host through the writers handed to the
+/// session; runtime helpers that would touch anything else must check for a sandbox first.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sandbox {
+    /// Writing more than this many bytes to stdout or stderr aborts the program.
+    pub max_output_bytes: usize,
+    /// The clock programs see. Virtual by default, so they can't time the host or tie it up
+    /// sleeping.
+    pub clock: Clock,
+    /// The file system programs see. Virtual by default, so they can't touch the host's.
+    pub files: Files,
+    /// Having more than this many bytes allocated at once aborts the program.
+    pub max_heap_bytes: usize,
+    /// Taking more than this many steps, loop iterations and calls, aborts the program, so it
+    /// can't loop or recurse forever.
+    pub fuel: u64,
+    /// Running for longer than this aborts the program, so it can't tie up the host even while
+    /// it has fuel left.
+    pub time_limit: Duration,
+}
+
+impl Default for Sandbox {
+    fn default() -> Self {
+        Self {
+            max_output_bytes: 64 * 1024,
+            clock: Clock::Virtual,
+            files: Files::Virtual,
+            max_heap_bytes: 64 * 1024 * 1024,
+            fuel: 100_000_000,
+            time_limit: Duration::from_secs(10),
+        }
+    }
+}
+
+/// Writer that fails once more than a byte limit has been written. Whatever still fits under the
+/// limit is written before failing, so output is cut off exactly at the limit.
+pub struct Limited<W> {
+    inner: W,
+    limit: Option<usize>,
+    remaining: Option<usize>,
+    truncated: usize,
+}
+
+impl<W: Write> Limited<W> {
+    pub fn new(inner: W, limit: Option<usize>) -> Self {
+        Self {
+            inner,
+            limit,
+            remaining: limit,
+            truncated: 0,
+        }
+    }
+
+    pub fn limit(&self) -> Option<usize> {
+        self.limit
+    }
+
+    /// Number of bytes dropped because the limit was reached.
+    pub fn truncated(&self) -> usize {
+        self.truncated
+    }
+}
+
+impl<W: Write> Write for Limited<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let Some(remaining) = &mut self.remaining else {
+            return self.inner.write(buf);
+        };
+        if *remaining == 0 && !buf.is_empty() {
+            self.truncated += buf.len();
+            return Err(io::Error::other(format!(
+                "output limit of {} bytes exceeded",
+                self.limit.unwrap()
+            )));
+        }
+        let len = buf.len().min(*remaining);
+        let written = self.inner.write(&buf[..len])?;
+        *remaining -= written;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn output_past_limit_fails() {
+        let mut output = Vec::new();
+        let mut limited = Limited::new(&mut output, Some(5));
+        limited.write_all(b"abc").unwrap();
+        assert!(limited.write_all(b"defg").is_err());
+        assert_eq!(limited.truncated(), 2);
+        assert_eq!(output, b"abcde");
+    }
+
+    #[test]
+    fn output_without_limit_is_kept() {
+        let mut output = Vec::new();
+        let mut limited = Limited::new(&mut output, None);
+        limited.write_all(b"abcdefgh").unwrap();
+        assert_eq!(limited.truncated(), 0);
+        assert_eq!(output, b"abcdefgh");
+    }
+}
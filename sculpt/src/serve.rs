@@ -0,0 +1,220 @@
+use serde::{Deserialize, Serialize};
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use crate::report::report_error;
+use crate::sandbox::Sandbox;
+use crate::session::Session;
+
+/// Requests claiming a larger body than this are rejected rather than buffered.
+const MAX_BODY_BYTES: usize = 1 << 20;
+
+/// Runs are sandboxed to this time limit, and answered with the error it aborts them with.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How much longer than the time limit a request is waited on, to compile the program and report
+/// a run that ran out of time, before it's answered with a timeout instead.
+const RESPONSE_MARGIN: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Deserialize)]
+pub struct RunRequest {
+    pub source: String,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct RunResponse {
+    pub success: bool,
+    pub stdout: String,
+    pub stderr: String,
+    pub diagnostics: String,
+}
+
+/// Compiles and runs playground source under the default sandbox limits, with at most
+/// `time_limit` to run.
+pub fn run_request(RunRequest { source }: RunRequest, time_limit: Duration) -> RunResponse {
+    let sandbox = Sandbox {
+        time_limit,
+        ..Sandbox::default()
+    };
+    let session = Session::builder().sandbox(sandbox).build();
+    let mut stdout = Vec::new();
+    let mut stderr = Vec::new();
+    let mut diagnostics = Vec::new();
+    let success = match session.run_with_streams(&source, io::empty(), &mut stdout, &mut stderr) {
+        Ok(()) => true,
+        Err(error) => {
+            let file = Path::new("playground.sculpt");
+            report_error(file, &source, error, false, &mut diagnostics);
+            false
+        }
+    };
+    RunResponse {
+        success,
+        stdout: String::from_utf8_lossy(&stdout).into_owned(),
+        stderr: String::from_utf8_lossy(&stderr).into_owned(),
+        diagnostics: String::from_utf8_lossy(&diagnostics).into_owned(),
+    }
+}
+
+/// Serves `POST /run` on `listener`, handling each connection on its own thread.
+pub fn serve(listener: TcpListener) -> io::Result<()> {
+    for stream in listener.incoming() {
+        let stream = stream?;
+        thread::spawn(move || {
+            // The client going away mid-request isn't something the server can act on.
+            let _ = handle_connection(stream);
+        });
+    }
+    Ok(())
+}
+
+fn handle_connection(stream: TcpStream) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let (status, body) = match read_request(&mut reader)? {
+        Some((method, path, body)) if method == "POST" && path == "/run" => {
+            match serde_json::from_slice::<RunRequest>(&body) {
+                Ok(request) => match run_within(request, REQUEST_TIMEOUT) {
+                    Some(response) => ("200 OK", serde_json::to_string(&response)?),
+                    None => (
+                        "504 Gateway Timeout",
+                        timeout_body(REQUEST_TIMEOUT + RESPONSE_MARGIN),
+                    ),
+                },
+                Err(error) => ("400 Bad Request", error_body(error.to_string())),
+            }
+        }
+        Some(_) => ("404 Not Found", error_body("not found".to_string())),
+        None => (
+            "400 Bad Request",
+            error_body("malformed request".to_string()),
+        ),
+    };
+    write_response(stream, status, &body)
+}
+
+/// Runs `request` on a thread of its own with at most `time_limit` to run, or `None` if it isn't
+/// done [`RESPONSE_MARGIN`] after that, which only a program that takes that long to compile is.
+fn run_within(request: RunRequest, time_limit: Duration) -> Option<RunResponse> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        // Nobody is waiting for a response that took too long.
+        let _ = sender.send(run_request(request, time_limit));
+    });
+    receiver.recv_timeout(time_limit + RESPONSE_MARGIN).ok()
+}
+
+/// Reads the method, path, and body of an HTTP/1.1 request, or `None` if it's malformed.
+fn read_request(reader: &mut impl BufRead) -> io::Result<Option<(String, String, Vec<u8>)>> {
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let (Some(method), Some(path)) = (parts.next(), parts.next()) else {
+        return Ok(None);
+    };
+
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                match value.trim().parse() {
+                    Ok(len) if len <= MAX_BODY_BYTES => content_length = len,
+                    _ => return Ok(None),
+                }
+            }
+        }
+    }
+
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+    Ok(Some((method.to_string(), path.to_string(), body)))
+}
+
+fn write_response(mut stream: impl Write, status: &str, body: &str) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+fn error_body(error: String) -> String {
+    serde_json::json!({ "error": error }).to_string()
+}
+
+fn timeout_body(timeout: Duration) -> String {
+    error_body(format!("run timed out after {} s", timeout.as_secs()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(source: &str) -> RunRequest {
+        RunRequest {
+            source: source.to_string(),
+        }
+    }
+
+    fn run(source: &str) -> RunResponse {
+        run_request(request(source), REQUEST_TIMEOUT)
+    }
+
+    #[test]
+    fn successful_run_returns_output() {
+        assert_eq!(
+            run(r#"fn main() { println!("Hello {}!", "playground"); eprint!("done"); }"#),
+            RunResponse {
+                success: true,
+                stdout: "Hello playground!\n".to_string(),
+                stderr: "done".to_string(),
+                diagnostics: String::new(),
+            }
+        );
+    }
+
+    #[test]
+    fn failed_run_returns_uncolored_diagnostics() {
+        let response = run("fn main() { print!(); }");
+        assert!(!response.success);
+        assert!(response.diagnostics.starts_with("[MissingFmtStr] Error:"));
+    }
+
+    #[test]
+    fn endless_run_runs_out_of_time() {
+        let source = r#"fn main() { while let true = true {} }"#;
+        let response = run_within(request(source), Duration::from_millis(50)).unwrap();
+        assert!(!response.success);
+        assert!(response.diagnostics.contains("ran out of time after 50 ms"));
+    }
+
+    #[test]
+    fn run_within_timeout_returns_its_response() {
+        let source = r#"fn main() { print!("done"); }"#;
+        let response = run_within(request(source), REQUEST_TIMEOUT).unwrap();
+        assert_eq!(response.stdout, "done");
+    }
+
+    #[test]
+    fn request_is_read_up_to_content_length() {
+        let request = b"POST /run HTTP/1.1\r\nHost: x\r\nContent-Length: 4\r\n\r\nbodyextra";
+        let (method, path, body) = read_request(&mut &request[..]).unwrap().unwrap();
+        assert_eq!((method.as_str(), path.as_str()), ("POST", "/run"));
+        assert_eq!(body, b"body");
+    }
+}
@@ -0,0 +1,1995 @@
+#[cfg(feature = "llvm")]
+use inkwell::context::Context;
+#[cfg(feature = "llvm")]
+use inkwell::OptimizationLevel;
+#[cfg(feature = "llvm")]
+use rayon::prelude::*;
+
+use std::cell::{Cell, Ref, RefCell};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+#[cfg(feature = "llvm")]
+use std::io::{BufReader, Read};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+#[cfg(feature = "llvm")]
+use crate::aot::{self, crate_name, Aot};
+#[cfg(feature = "llvm")]
+use crate::backend::Backend;
+use crate::backend::{Clock, Files, RunOptions};
+#[cfg(feature = "llvm")]
+use crate::bench::{measure, write_results, BenchOptions, BenchResult};
+#[cfg(feature = "llvm")]
+use crate::diagnostics::Collector;
+use crate::diagnostics::{line_column, write_grouped, Counts, Rendered};
+use crate::doc;
+use crate::expand::{expand_with, ExpandContext};
+use crate::fix::fix;
+use crate::fmt::{self, format_source_with};
+use crate::harness::{diff, write_results as write_test_results, TestResult};
+#[cfg(feature = "llvm")]
+use crate::harness::{doctests, DocTest, UiOutput};
+use crate::hir::{self, Names};
+use crate::ice;
+#[cfg(feature = "llvm")]
+use crate::incremental::Incremental;
+#[cfg(feature = "llvm")]
+use crate::intern::{sym, Symbol};
+use crate::lint::{self, Warning};
+#[cfg(feature = "llvm")]
+use crate::llvm::{call, Compiler, Jit, Streams};
+use crate::plugin::Plugin;
+use crate::profile::{Phase, Profiler};
+use crate::report::{report_error, report_error_with_max_labels, report_warning, MAX_LABELS};
+#[cfg(feature = "llvm")]
+use crate::run::parse_statements;
+use crate::run::{parse, Error};
+#[cfg(feature = "llvm")]
+use crate::sandbox::Limited;
+use crate::sandbox::Sandbox;
+use crate::source::{FileSystem, SourceProvider};
+use crate::syntax::{dump, Program};
+
+/// The most fixes `sculpt fix` makes to a file.
+const MAX_FIXES: usize = 100;
+
+#[cfg(feature = "llvm")]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum BackendKind {
+    #[default]
+    Jit,
+    /// Links programs into native executables and runs them as child processes.
+    Aot,
+}
+
+/// Configuration and long-lived state shared by every program compiled in a session.
+pub struct Session {
+    #[cfg(feature = "llvm")]
+    context: Context,
+    #[cfg(feature = "llvm")]
+    opt_level: OptimizationLevel,
+    #[cfg(feature = "llvm")]
+    backend: BackendKind,
+    colored: bool,
+    max_labels: usize,
+    quiet: bool,
+    sandbox: Option<Sandbox>,
+    #[cfg(feature = "llvm")]
+    max_output_bytes: Option<usize>,
+    #[cfg(feature = "llvm")]
+    deterministic: bool,
+    seed: Option<u64>,
+    entry: Option<String>,
+    plugins: Vec<Plugin>,
+    sources: Box<dyn SourceProvider>,
+    diagnostics: RefCell<Box<dyn Write>>,
+    /// How many errors and warnings were reported, quiet or not.
+    counts: Cell<Counts>,
+    profiler: Profiler,
+    /// What the names in each file resolved to when it was last analyzed.
+    names: RefCell<HashMap<PathBuf, Names>>,
+}
+
+impl Session {
+    pub fn builder() -> SessionBuilder {
+        SessionBuilder::default()
+    }
+
+    pub fn sandbox(&self) -> Option<&Sandbox> {
+        self.sandbox.as_ref()
+    }
+
+    pub fn sources(&self) -> &dyn SourceProvider {
+        self.sources.as_ref()
+    }
+
+    pub fn profiler(&self) -> &Profiler {
+        &self.profiler
+    }
+
+    /// How many errors and warnings the session has reported so far.
+    pub fn counts(&self) -> Counts {
+        self.counts.get()
+    }
+
+    /// Parses `source_code`, read from `file` if it was, and expands its macros.
+    pub fn parse<'src>(
+        &self,
+        file: Option<&Path>,
+        source_code: &'src str,
+    ) -> Result<Program, Error<'src>> {
+        let program = parse_and_expand(source_code, self.expand_context(file))?;
+        let warnings = lint::check(&program)?;
+        if let Some(file) = file {
+            self.warn(file, source_code, warnings);
+        }
+        Ok(program)
+    }
+
+    /// What macros in `file` expand with. Sandboxed programs can't include files or see the
+    /// compiler's environment.
+    pub fn expand_context<'a>(&'a self, file: Option<&'a Path>) -> ExpandContext<'a> {
+        ExpandContext {
+            plugins: &self.plugins,
+            file,
+            sources: match self.sandbox {
+                Some(_) => None,
+                None => Some(self.sources.as_ref()),
+            },
+            env: self.sandbox.is_none(),
+        }
+    }
+
+    /// How the session's programs may reach the host. Sandboxed programs can't see its environment,
+    /// see the sandbox's clock and file system, and are held to its fuel, heap and time limits.
+    pub fn run_options(&self) -> RunOptions {
+        RunOptions {
+            sandboxed: self.sandbox.is_some(),
+            seed: self.seed,
+            clock: self
+                .sandbox
+                .as_ref()
+                .map_or(Clock::Real, |sandbox| sandbox.clock),
+            files: self
+                .sandbox
+                .as_ref()
+                .map_or(Files::Real, |sandbox| sandbox.files),
+            fuel: self.sandbox.as_ref().map(|sandbox| sandbox.fuel),
+            max_heap_bytes: self.sandbox.as_ref().map(|sandbox| sandbox.max_heap_bytes),
+            time_limit: self.sandbox.as_ref().map(|sandbox| sandbox.time_limit),
+            entry: self.entry.clone(),
+        }
+    }
+
+    /// Parses and lowers `source_code`, a program, without running it, reporting the first error
+    /// found. `include_str!` paths are relative to the current directory.
+    pub fn check<'src>(&self, source_code: &'src str) -> Result<(), Error<'src>> {
+        self.check_in(None, source_code, false)
+    }
+
+    /// Checks `source_code`, read from `file`, like [`Session::check`]. Only a program, and not a
+    /// `library`, needs the function it starts in.
+    fn check_in<'src>(
+        &self,
+        file: Option<&Path>,
+        source_code: &'src str,
+        library: bool,
+    ) -> Result<(), Error<'src>> {
+        let program = self
+            .profiler
+            .time(Phase::Parse, || self.parse(file, source_code))?;
+        if !library {
+            check_entry_point(&program, source_code, &self.run_options())?;
+        }
+        self.profiler.time(Phase::Lower, || hir::lower(program))?;
+        Ok(())
+    }
+
+    /// Checks `source_code`, read from `file`, returning the warnings it raises along with whether
+    /// it checked cleanly. Unlike [`Session::check`], it keeps what the names in it resolved to,
+    /// up to any error, for [`Session::names`] to look up.
+    pub fn analyze<'src>(
+        &self,
+        file: &Path,
+        source_code: &'src str,
+    ) -> (Vec<Warning>, Result<(), Error<'src>>) {
+        let mut names = Names::default();
+        let mut warnings = Vec::new();
+        let checked = parse_and_expand(source_code, self.expand_context(Some(file)))
+            .and_then(|program| {
+                warnings = lint::check(&program)?;
+                hir::lower_recording(program, &mut names)
+            })
+            .map(|_| ());
+        self.names.borrow_mut().insert(file.to_path_buf(), names);
+        (warnings, checked)
+    }
+
+    /// What the names in `file` resolved to when it was last analyzed, if it has been.
+    pub fn names(&self, file: &Path) -> Option<Ref<'_, Names>> {
+        Ref::filter_map(self.names.borrow(), |names| names.get(file)).ok()
+    }
+
+    /// Forgets what the names in `file` resolved to, once it won't be asked about again.
+    pub fn forget(&self, file: &Path) {
+        self.names.borrow_mut().remove(file);
+    }
+
+    /// Reads and checks each of `files`, programs or else libraries, writing any errors to the
+    /// diagnostics sink. Returns whether every file checked cleanly.
+    pub fn check_files(&self, files: &[PathBuf], library: bool) -> io::Result<bool> {
+        let source_codes = files
+            .iter()
+            .map(|file| self.read(file))
+            .collect::<io::Result<Vec<_>>>()?;
+        let errors: Vec<_> = files
+            .iter()
+            .zip(&source_codes)
+            .enumerate()
+            .filter_map(|(i, (file, source_code))| {
+                self.check_in(Some(file), source_code, library)
+                    .err()
+                    .map(|error| (i, error))
+            })
+            .collect();
+        let checked = errors.is_empty();
+        self.report_grouped(files, &source_codes, errors)?;
+        Ok(checked)
+    }
+
+    /// Reads `file`, fixes each error in it that has a fix, and writes it back. Errors are found
+    /// one at a time, so the file is checked again after every fix. Reports the first error left
+    /// without one, and returns whether the file now checks cleanly.
+    pub fn fix_file(&self, file: &Path) -> io::Result<bool> {
+        let mut source_code = self.read(file)?;
+        let mut fixes = 0;
+        let checked = loop {
+            let fix = match self.analyze(file, &source_code).1 {
+                Ok(()) => break true,
+                // A fix that doesn't take leaves its error, which would otherwise be fixed
+                // forever.
+                Err(error) => match fix(&error, &source_code).filter(|_| fixes < MAX_FIXES) {
+                    Some(fix) => fix,
+                    None => {
+                        self.report(file, &source_code, error);
+                        break false;
+                    }
+                },
+            };
+            source_code = fix.apply(&source_code);
+            fixes += 1;
+        };
+        if fixes > 0 {
+            fs::write(file, &source_code)?;
+            if self.quiet {
+                return Ok(checked);
+            }
+            let mut diagnostics = self.diagnostics.borrow_mut();
+            let plural = if fixes == 1 { "" } else { "es" };
+            writeln!(
+                diagnostics,
+                "applied {} fix{} to {}",
+                fixes,
+                plural,
+                file.display()
+            )?;
+        }
+        Ok(checked)
+    }
+
+    /// Formats `file` in place as `config` says, or only the lines `range`, a range of offsets
+    /// into it, touches. Returns whether it changed.
+    pub fn fmt_file(
+        &self,
+        file: &Path,
+        range: Option<Range<usize>>,
+        config: &fmt::Config,
+    ) -> io::Result<bool> {
+        let source_code = self.read(file)?;
+        let formatted = format_source_with(&source_code, range, config);
+        if formatted == source_code {
+            return Ok(false);
+        }
+        fs::write(file, formatted)?;
+        Ok(true)
+    }
+
+    /// Reads `file` and writes the reference page its doc comments render to in `format` out to
+    /// `writer`. Returns whether the file parsed.
+    pub fn doc_file(
+        &self,
+        file: &Path,
+        format: doc::Format,
+        mut writer: impl Write,
+    ) -> io::Result<bool> {
+        let source_code = self.read(file)?;
+        match self.parse(Some(file), &source_code) {
+            Ok(program) => {
+                let title = file.file_stem().unwrap_or_default().to_string_lossy();
+                write!(
+                    writer,
+                    "{}",
+                    doc::render(&title, &program, &source_code, format)
+                )?;
+                Ok(true)
+            }
+            Err(error) => {
+                self.report(file, &source_code, error);
+                Ok(false)
+            }
+        }
+    }
+
+    /// Parses every `.sculpt` file in `dir`, in name order, and compares the tree it parses to, or
+    /// the error it raises, to the `.ast` file beside it, writing a diff for each mismatch, a line
+    /// per file and a summary to `writer`. With `bless`, writes the `.ast` files instead. Returns
+    /// whether every file matched.
+    pub fn check_grammar(&self, dir: &Path, bless: bool, writer: impl Write) -> io::Result<bool> {
+        self.test_golden(dir, "ast", bless, writer, |file, source_code| {
+            match parse(source_code) {
+                Ok(program) => dump(&program),
+                Err(error) => {
+                    let mut rendered = Vec::new();
+                    let name = Path::new(file.file_name().unwrap_or_default());
+                    report_error(name, source_code, error, false, &mut rendered);
+                    let rendered = String::from_utf8_lossy(&rendered);
+                    rendered
+                        .lines()
+                        .map(|line| line.trim_end().to_string() + "\n")
+                        .collect()
+                }
+            }
+        })
+    }
+
+    /// Compares `output` for every `.sculpt` file in `dir`, in name order, to the file beside it
+    /// with the extension `extension`, writing a diff for each mismatch, a line per file and a
+    /// summary to `writer`. With `bless`, writes those files instead. Returns whether every file
+    /// matched.
+    fn test_golden(
+        &self,
+        dir: &Path,
+        extension: &str,
+        bless: bool,
+        mut writer: impl Write,
+        output: impl Fn(&Path, &str) -> String,
+    ) -> io::Result<bool> {
+        let mut files = fs::read_dir(dir)?
+            .map(|entry| Ok(entry?.path()))
+            .collect::<io::Result<Vec<_>>>()?;
+        files.retain(|file| {
+            file.extension()
+                .is_some_and(|extension| extension == "sculpt")
+        });
+        files.sort();
+
+        let mut results = Vec::new();
+        for file in files {
+            let source_code = self.read(&file)?;
+            let actual = output(&file, &source_code);
+            let expected_file = file.with_extension(extension);
+            let name = file.file_stem().unwrap_or_default().to_string_lossy();
+            let passed = if bless {
+                if fs::read_to_string(&expected_file).ok().as_ref() != Some(&actual) {
+                    fs::write(&expected_file, &actual)?;
+                }
+                true
+            } else {
+                match fs::read_to_string(&expected_file) {
+                    Ok(expected) if expected == actual => true,
+                    Ok(expected) => {
+                        writeln!(writer, "--- {} differs:", name)?;
+                        writeln!(writer, "{}", diff(&expected, &actual))?;
+                        false
+                    }
+                    Err(error) if error.kind() == io::ErrorKind::NotFound => {
+                        writeln!(
+                            writer,
+                            "--- {} has no `{}`; run with `--bless` to write it\n",
+                            name,
+                            expected_file.display()
+                        )?;
+                        false
+                    }
+                    Err(error) => return Err(error),
+                }
+            };
+            results.push(TestResult {
+                name: name.to_string(),
+                passed,
+            });
+        }
+        write_test_results(&results, writer)?;
+        Ok(results.iter().all(|result| result.passed))
+    }
+
+    /// Reads `file` from the session's sources.
+    fn read(&self, file: &Path) -> io::Result<String> {
+        tracing::debug!(file = %file.display(), "reading");
+        let source_code = self.sources.read(file)?;
+        ice::note_source(file, &source_code);
+        Ok(source_code)
+    }
+
+    pub fn report(&self, file: &Path, source_code: &str, error: Error) {
+        self.count(1, 0);
+        let mut diagnostics = self.diagnostics.borrow_mut();
+        report_error_with_max_labels(
+            file,
+            source_code,
+            error,
+            self.colored,
+            self.max_labels,
+            &mut *diagnostics,
+        );
+    }
+
+    /// Reports `errors`, each raised in the source code of one of `files`, in order, writing only
+    /// the first few of those that are the same error in full and summing up the rest.
+    pub fn report_grouped(
+        &self,
+        files: &[PathBuf],
+        source_codes: &[String],
+        errors: Vec<(usize, Error)>,
+    ) -> io::Result<()> {
+        self.count(errors.len(), 0);
+        let rendered = errors
+            .into_iter()
+            .map(|(file, error)| {
+                let location = format!(
+                    "{}:{}",
+                    files[file].display(),
+                    line_column(&source_codes[file], error.location())
+                );
+                let mut text = Vec::new();
+                report_error_with_max_labels(
+                    &files[file],
+                    &source_codes[file],
+                    error,
+                    self.colored,
+                    self.max_labels,
+                    &mut text,
+                );
+                Rendered {
+                    location,
+                    text: String::from_utf8_lossy(&text).into_owned(),
+                }
+            })
+            .collect();
+        write_grouped(rendered, &mut *self.diagnostics.borrow_mut())
+    }
+
+    /// Reports `warnings`, raised in `source_code` read from `file`, to the session's diagnostics,
+    /// unless it's quiet.
+    pub fn warn(&self, file: &Path, source_code: &str, warnings: Vec<Warning>) {
+        self.count(0, warnings.len());
+        if self.quiet {
+            return;
+        }
+        let mut diagnostics = self.diagnostics.borrow_mut();
+        for warning in warnings {
+            report_warning(file, source_code, warning, self.colored, &mut *diagnostics);
+        }
+    }
+
+    fn count(&self, errors: usize, warnings: usize) {
+        let counts = self.counts.get();
+        self.counts.set(Counts {
+            errors: counts.errors + errors,
+            warnings: counts.warnings + warnings,
+        });
+    }
+}
+
+#[cfg(feature = "llvm")]
+impl Session {
+    /// A fresh instance of the backend the session was configured with.
+    pub fn backend(&self) -> Box<dyn Backend + '_> {
+        match self.backend {
+            BackendKind::Jit => Box::new(self.compiler()),
+            BackendKind::Aot => Box::new(self.aot()),
+        }
+    }
+
+    pub fn aot(&self) -> Aot<'_> {
+        Aot::new(&self.context, self.opt_level, &self.profiler).deterministic(self.deterministic)
+    }
+
+    pub fn compiler(&self) -> Compiler<'_> {
+        Compiler::new(&self.context, self.opt_level, &self.profiler)
+    }
+
+    pub fn jit(&self) -> Jit<'_> {
+        Jit::new(&self.context, self.opt_level, &self.profiler)
+    }
+
+    /// Incremental compiler for `file`, or for source that wasn't read from a file.
+    pub fn incremental<'a>(&'a self, file: Option<&'a Path>) -> Incremental<'a> {
+        Incremental::new(
+            &self.context,
+            self.opt_level,
+            &self.profiler,
+            self.expand_context(file),
+        )
+    }
+
+    pub fn run<'src>(
+        &self,
+        source_code: &'src str,
+        std_out: impl Write,
+    ) -> Result<(), Error<'src>> {
+        self.run_with_streams(source_code, io::stdin(), std_out, io::stderr())
+    }
+
+    /// Compiles and runs `source_code` against the given streams. `include_str!` paths are
+    /// relative to the current directory.
+    pub fn run_with_streams<'src>(
+        &self,
+        source_code: &'src str,
+        std_in: impl Read,
+        std_out: impl Write,
+        std_err: impl Write,
+    ) -> Result<(), Error<'src>> {
+        self.run_in(None, source_code, std_in, std_out, std_err)
+    }
+
+    fn run_in<'src>(
+        &self,
+        file: Option<&Path>,
+        source_code: &'src str,
+        std_in: impl Read,
+        std_out: impl Write,
+        std_err: impl Write,
+    ) -> Result<(), Error<'src>> {
+        let program = self
+            .profiler
+            .time(Phase::Parse, || self.parse(file, source_code))?;
+        check_entry_point(&program, source_code, &self.run_options())?;
+        let program = self.profiler.time(Phase::Lower, || hir::lower(program))?;
+        let mut backend = self.backend();
+        backend.lower_module(program);
+        let mut std_out = self.limit(std_out);
+        let mut std_err = self.limit(std_err);
+        let result = backend.run(
+            &mut BufReader::new(std_in),
+            &mut std_out,
+            &mut std_err,
+            &self.run_options(),
+        );
+        match check_output_limit(result.map_err(Error::from), &std_out, &std_err) {
+            // As far as the caller can tell, a program that exits with 0 ran to completion.
+            Err(Error::Exited(0)) => Ok(()),
+            result => result,
+        }
+    }
+
+    /// Reads and runs `file`, writing any errors to the diagnostics sink. Returns whether the
+    /// program compiled.
+    pub fn run_file(&self, file: &Path, std_out: impl Write) -> io::Result<bool> {
+        let source_code = self.read(file)?;
+        match self.run_in(Some(file), &source_code, io::stdin(), std_out, io::stderr()) {
+            Ok(()) => Ok(true),
+            Err(error) => {
+                self.report(file, &source_code, error);
+                Ok(false)
+            }
+        }
+    }
+
+    /// Parses and lowers `files` in parallel, then runs each in order against a single shared JIT. Errors are
+    /// reported once every program has run, ordered by file and location. Returns the status to
+    /// exit with: the one a program passed to `exit!`, which skips the programs after it, or else
+    /// 1 if any program failed and 0 if none did.
+    pub fn run_files(
+        &self,
+        files: &[PathBuf],
+        std_in: impl Read,
+        mut std_out: impl Write,
+        mut std_err: impl Write,
+    ) -> io::Result<i32> {
+        let source_codes = files
+            .iter()
+            .map(|file| self.read(file))
+            .collect::<io::Result<Vec<_>>>()?;
+
+        let contexts: Vec<_> = files
+            .iter()
+            .map(|file| self.expand_context(Some(file)))
+            .collect();
+        let errors = Collector::default();
+        let options = self.run_options();
+        let programs: Vec<_> = self.profiler.time(Phase::Parse, || {
+            source_codes
+                .par_iter()
+                .enumerate()
+                .map(|(file, source_code)| {
+                    let (warnings, program) = parse_and_expand(source_code, contexts[file])
+                        .and_then(|program| {
+                            check_entry_point(&program, source_code, &options)?;
+                            Ok((lint::check(&program)?, program))
+                        })
+                        .map_err(|error| errors.push(file, error))
+                        .ok()?;
+                    // The lint warnings are still reported for a program that fails to lower.
+                    let program = hir::lower(program)
+                        .map_err(|error| errors.push(file, error))
+                        .ok();
+                    Some((warnings, program))
+                })
+                .collect()
+        });
+
+        let mut std_in = BufReader::new(std_in);
+        let jit = self.jit();
+        let mut exited = None;
+        for (file, program) in programs.into_iter().enumerate() {
+            if let Some((warnings, program)) = program {
+                self.warn(&files[file], &source_codes[file], warnings);
+                let Some(program) = program else {
+                    continue;
+                };
+                let mut std_out = self.limit(&mut std_out);
+                let mut std_err = self.limit(&mut std_err);
+                let result =
+                    jit.run_lowered(program, &mut std_in, &mut std_out, &mut std_err, &options);
+                match check_output_limit(result, &std_out, &std_err) {
+                    Ok(()) => {}
+                    Err(Error::Exited(status)) => {
+                        exited = Some(status);
+                        break;
+                    }
+                    Err(error) => errors.push(file, error),
+                }
+            }
+        }
+
+        let errors = errors.into_sorted();
+        let failed = !errors.is_empty();
+        self.report_grouped(files, &source_codes, errors)?;
+        Ok(exited.unwrap_or(i32::from(failed)))
+    }
+
+    /// Runs every `.sculpt` file in `dir` and the directories in it, in path order, each on its own
+    /// JIT with no input. Each line a program writes is prefixed with its path relative to `dir`,
+    /// and the programs that failed to compile, panicked or exited with a nonzero status are
+    /// listed once all have run. Returns 1 if any failed, and 0 if none did.
+    pub fn run_dir(
+        &self,
+        dir: &Path,
+        mut std_out: impl Write,
+        mut std_err: impl Write,
+    ) -> io::Result<i32> {
+        let files = sculpt_files(dir)?;
+        let mut failed = Vec::new();
+        for file in &files {
+            let name = file.strip_prefix(dir).unwrap_or(file).display().to_string();
+            let source_code = self.read(file)?;
+            let mut out = Vec::new();
+            let mut err = Vec::new();
+            let result = self.run_in(Some(file), &source_code, io::empty(), &mut out, &mut err);
+            write_prefixed(&mut std_out, &name, &out)?;
+            write_prefixed(&mut std_err, &name, &err)?;
+            match result {
+                Ok(()) => continue,
+                Err(Error::Exited(status)) => {
+                    writeln!(std_err, "{}: exited with status {}", name, status)?;
+                }
+                Err(error) => self.report(file, &source_code, error),
+            }
+            failed.push(name);
+        }
+
+        writeln!(
+            std_err,
+            "\nran {} programs: {} succeeded; {} failed",
+            files.len(),
+            files.len() - failed.len(),
+            failed.len()
+        )?;
+        for name in &failed {
+            writeln!(std_err, "    {}", name)?;
+        }
+        Ok(i32::from(!failed.is_empty()))
+    }
+
+    /// Compiles `source_code` and times every `#[bench]` function in it. Output the benchmarks
+    /// print is discarded. A benchmark that panics or exits fails the whole run with its error.
+    /// `include_str!` paths are relative to the current directory.
+    pub fn bench<'src>(
+        &self,
+        source_code: &'src str,
+        options: &BenchOptions,
+    ) -> Result<Vec<BenchResult>, Error<'src>> {
+        self.bench_in(None, source_code, options)
+    }
+
+    fn bench_in<'src>(
+        &self,
+        file: Option<&Path>,
+        source_code: &'src str,
+        options: &BenchOptions,
+    ) -> Result<Vec<BenchResult>, Error<'src>> {
+        let program = self
+            .profiler
+            .time(Phase::Parse, || self.parse(file, source_code))?;
+        let names: Vec<_> = program
+            .functions
+            .iter()
+            .filter(|function| function.has_attr(sym::BENCH))
+            .map(|function| function.name.name.to_string())
+            .collect();
+
+        let compiler = self.compiler();
+        let mut streams =
+            Streams::new(io::empty(), io::sink(), io::sink()).with_options(self.run_options());
+        compiler.build_program(program, &mut streams)?;
+        names
+            .into_iter()
+            .map(|name| {
+                let function = compiler.function(&name).unwrap();
+                let stats = self.profiler.time(Phase::Execute, || {
+                    measure(|| unsafe { call(&function) }, options)
+                })?;
+                Ok(BenchResult { name, stats })
+            })
+            .collect()
+    }
+
+    /// Reads `file`, benchmarks it, and writes a results table to `writer`. Returns whether the
+    /// program compiled and every benchmark ran to completion.
+    pub fn bench_file(
+        &self,
+        file: &Path,
+        options: &BenchOptions,
+        writer: impl Write,
+    ) -> io::Result<bool> {
+        let source_code = self.read(file)?;
+        match self.bench_in(Some(file), &source_code, options) {
+            Ok(results) => {
+                write_results(&results, writer)?;
+                Ok(true)
+            }
+            Err(error) => {
+                self.report(file, &source_code, error);
+                Ok(false)
+            }
+        }
+    }
+
+    /// Compiles `source_code`, read from `file`, and runs every `#[test]` function in it, then
+    /// every code block in its doc comments as a program of its own. Tests fail by panicking or,
+    /// for code blocks, by not compiling, and each failure is reported to the diagnostics sink.
+    /// Output the tests print is discarded.
+    fn test_in<'src>(
+        &self,
+        file: &Path,
+        source_code: &'src str,
+    ) -> Result<Vec<TestResult>, Error<'src>> {
+        let program = self
+            .profiler
+            .time(Phase::Parse, || self.parse(Some(file), source_code))?;
+        let names: Vec<_> = program
+            .functions
+            .iter()
+            .filter(|function| function.has_attr(sym::TEST))
+            .map(|function| function.name.name.to_string())
+            .collect();
+        let doctests = doctests(&program, source_code);
+
+        let compiler = self.compiler();
+        let mut streams =
+            Streams::new(io::empty(), io::sink(), io::sink()).with_options(self.run_options());
+        compiler.build_program(program, &mut streams)?;
+        let mut results = Vec::new();
+        for name in names {
+            let function = compiler.function(&name).unwrap();
+            let result = self
+                .profiler
+                .time(Phase::Execute, || unsafe { call(&function) })
+                .map_err(Error::from);
+            results.push(self.test_result(file, source_code, name, result));
+        }
+        for test in &doctests {
+            let result = self.doctest(file, test);
+            results.push(self.test_result(file, source_code, test.name.clone(), result));
+        }
+        Ok(results)
+    }
+
+    /// Compiles and runs the code block of `test`, which is in `file`.
+    fn doctest<'a>(&self, file: &Path, test: &'a DocTest) -> Result<(), Error<'a>> {
+        let program = match test.is_statements() {
+            true => parse_statements(&test.source_code)?,
+            false => parse(&test.source_code)?,
+        };
+        let program = expand_with(program, self.expand_context(Some(file)))?;
+        self.jit().run_parsed(
+            program,
+            io::empty(),
+            io::sink(),
+            io::sink(),
+            &self.run_options(),
+        )
+    }
+
+    /// Whether the test named `name` passed, reporting how it failed if it didn't. Tests that
+    /// exit with 0 pass.
+    fn test_result(
+        &self,
+        file: &Path,
+        source_code: &str,
+        name: String,
+        result: Result<(), Error>,
+    ) -> TestResult {
+        let passed = matches!(result, Ok(()) | Err(Error::Exited(0)));
+        if let (false, Err(error)) = (passed, result) {
+            self.report(file, source_code, error);
+        }
+        TestResult { name, passed }
+    }
+
+    /// Reads `file`, runs its tests, and writes a line per test and a summary to `writer`.
+    /// Returns whether every test passed.
+    pub fn test_file(&self, file: &Path, writer: impl Write) -> io::Result<bool> {
+        let source_code = self.read(file)?;
+        match self.test_in(file, &source_code) {
+            Ok(results) => {
+                write_test_results(&results, writer)?;
+                Ok(results.iter().all(|result| result.passed))
+            }
+            Err(error) => {
+                self.report(file, &source_code, error);
+                Ok(false)
+            }
+        }
+    }
+
+    /// Runs every `.sculpt` file in `dir`, in name order, and compares what it prints to the
+    /// `.expected` file beside it, like [`Session::check_grammar`] compares trees. Returns whether
+    /// every test passed.
+    pub fn test_ui(&self, dir: &Path, bless: bool, writer: impl Write) -> io::Result<bool> {
+        self.test_golden(dir, "expected", bless, writer, |file, source_code| {
+            self.ui_output(file, source_code).expected()
+        })
+    }
+
+    /// Checks every `.sculpt` file in `dir` like `sculpt check` does, without running it, and
+    /// compares the errors it raises to the `.expected` file beside it, like [`Session::test_ui`].
+    pub fn test_check_ui(&self, dir: &Path, bless: bool, writer: impl Write) -> io::Result<bool> {
+        self.test_golden(dir, "expected", bless, writer, |file, source_code| {
+            let name = Path::new(file.file_name().unwrap_or_default());
+            let mut std_err = Vec::new();
+            if let Err(error) = self.check_in(Some(file), source_code, false) {
+                report_error(name, source_code, error, false, &mut std_err);
+            }
+            UiOutput {
+                stdout: String::new(),
+                stderr: String::from_utf8_lossy(&std_err).into_owned(),
+            }
+            .expected()
+        })
+    }
+
+    /// Runs `source_code`, read from `file`, with no input. Diagnostics are rendered without color
+    /// and with only the file's name, so they don't depend on where the tests are run from.
+    fn ui_output(&self, file: &Path, source_code: &str) -> UiOutput {
+        let name = Path::new(file.file_name().unwrap_or_default());
+        let mut std_out = Vec::new();
+        let mut std_err = Vec::new();
+        let result = parse_and_expand(source_code, self.expand_context(Some(file)))
+            .and_then(|program| {
+                check_entry_point(&program, source_code, &self.run_options())?;
+                Ok((lint::check(&program)?, program))
+            })
+            .and_then(|(warnings, program)| {
+                for warning in warnings {
+                    report_warning(name, source_code, warning, false, &mut std_err);
+                }
+                self.jit().run_parsed(
+                    program,
+                    io::empty(),
+                    &mut std_out,
+                    &mut std_err,
+                    &self.run_options(),
+                )
+            });
+        match result {
+            Ok(()) | Err(Error::Exited(0)) => {}
+            Err(Error::Exited(status)) => {
+                let _ = writeln!(std_err, "exited with status {}", status);
+            }
+            Err(error) => report_error(name, source_code, error, false, &mut std_err),
+        }
+        UiOutput {
+            stdout: String::from_utf8_lossy(&std_out).into_owned(),
+            stderr: String::from_utf8_lossy(&std_err).into_owned(),
+        }
+    }
+
+    /// Reads `file` and builds it into a native executable at `output`. Returns whether the
+    /// program compiled.
+    pub fn build_file(&self, file: &Path, output: &Path) -> io::Result<bool> {
+        self.build_aot(file, false, |aot| aot.link(output))
+    }
+
+    /// Reads `file` and builds it into a static library at `output`, with a C header declaring
+    /// its exports written alongside. Returns whether the program compiled, which it doesn't if
+    /// any `pub fn` can't be exported.
+    pub fn build_library_file(&self, file: &Path, output: &Path) -> io::Result<bool> {
+        let name = crate_name(file);
+        self.build_aot(file, true, |aot| {
+            aot.archive(&name, output)?;
+            fs::write(
+                output.with_file_name(format!("{}.h", name)),
+                aot.header(&name),
+            )
+        })
+    }
+
+    fn build_aot(
+        &self,
+        file: &Path,
+        library: bool,
+        build: impl FnOnce(&Aot) -> io::Result<()>,
+    ) -> io::Result<bool> {
+        let source_code = self.read(file)?;
+        let program = self
+            .profiler
+            .time(Phase::Parse, || self.parse(Some(file), &source_code))
+            .and_then(|program| {
+                // The names of the functions a library exports, to report any it can't.
+                let exported: HashMap<_, _> = program
+                    .functions
+                    .iter()
+                    .filter(|function| library && function.public)
+                    .map(|function| (function.name.name, function.name.span.clone()))
+                    .collect();
+                let program = self.profiler.time(Phase::Lower, || hir::lower(program))?;
+                check_exports(&program, &exported)?;
+                Ok(program)
+            });
+        match program {
+            Ok(mut program) => {
+                if self.deterministic {
+                    program.sort_by_name();
+                }
+                let mut aot = self.aot();
+                aot.lower_module(program);
+                build(&aot)?;
+                Ok(true)
+            }
+            Err(error) => {
+                self.report(file, &source_code, error);
+                Ok(false)
+            }
+        }
+    }
+
+    /// Compiles `source_code` without running it, optimizing the module at the session's opt level
+    /// if `optimized` is set. `include_str!` paths are relative to the current directory.
+    pub fn compile<'src>(
+        &self,
+        source_code: &'src str,
+        optimized: bool,
+    ) -> Result<Compiler<'_>, Error<'src>> {
+        self.compile_in(None, source_code, optimized)
+    }
+
+    fn compile_in<'src>(
+        &self,
+        file: Option<&Path>,
+        source_code: &'src str,
+        optimized: bool,
+    ) -> Result<Compiler<'_>, Error<'src>> {
+        let program = self
+            .profiler
+            .time(Phase::Parse, || self.parse(file, source_code))?;
+        let mut program = self.profiler.time(Phase::Lower, || hir::lower(program))?;
+        if self.deterministic {
+            program.sort_by_name();
+        }
+        let compiler = self.compiler();
+        compiler.build_module(program);
+        if optimized {
+            compiler.optimize(self.opt_level);
+        }
+        Ok(compiler)
+    }
+
+    /// Reads and compiles `file`, then writes its LLVM IR to `writer`, or its bitcode to `bitcode`
+    /// if given. Returns whether the program compiled.
+    pub fn ir_file(
+        &self,
+        file: &Path,
+        optimized: bool,
+        bitcode: Option<&Path>,
+        mut writer: impl Write,
+    ) -> io::Result<bool> {
+        let source_code = self.read(file)?;
+        match self.compile_in(Some(file), &source_code, optimized) {
+            Ok(compiler) => {
+                match bitcode {
+                    Some(path) => compiler.write_bitcode(path)?,
+                    None => write!(writer, "{}", compiler.ir())?,
+                }
+                Ok(true)
+            }
+            Err(error) => {
+                self.report(file, &source_code, error);
+                Ok(false)
+            }
+        }
+    }
+
+    fn limit<W: Write>(&self, stream: W) -> Limited<W> {
+        let limit = self.max_output_bytes.or(self
+            .sandbox
+            .as_ref()
+            .map(|sandbox| sandbox.max_output_bytes));
+        Limited::new(stream, limit)
+    }
+}
+
+fn parse_and_expand<'src>(
+    source_code: &'src str,
+    context: ExpandContext,
+) -> Result<Program, Error<'src>> {
+    expand_with(parse(source_code)?, context)
+}
+
+/// Every `.sculpt` file in `dir` and the directories in it, in path order.
+#[cfg(feature = "llvm")]
+fn sculpt_files(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            files.extend(sculpt_files(&path)?);
+        } else if path
+            .extension()
+            .is_some_and(|extension| extension == "sculpt")
+        {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Writes `output` to `writer` a line at a time, each prefixed with `name`.
+#[cfg(feature = "llvm")]
+fn write_prefixed(mut writer: impl Write, name: &str, output: &[u8]) -> io::Result<()> {
+    for line in String::from_utf8_lossy(output).lines() {
+        writeln!(writer, "{}: {}", name, line)?;
+    }
+    Ok(())
+}
+
+/// Checks that `program`, parsed from `source_code`, defines the function it starts in with
+/// `options`, and that the host can call it.
+fn check_entry_point<'src>(
+    program: &Program,
+    source_code: &str,
+    options: &RunOptions,
+) -> Result<(), Error<'src>> {
+    let entry = options.entry();
+    match program
+        .functions
+        .iter()
+        .find(|function| function.name.name.as_str() == entry)
+    {
+        Some(function) if function.params.is_empty() && function.generics.is_empty() => Ok(()),
+        Some(function) => Err(Error::EntryPointParameters(function.name.span.clone())),
+        None => Err(Error::MissingEntryPoint(
+            source_code.len()..source_code.len(),
+            entry.to_string(),
+        )),
+    }
+}
+
+/// Checks that each function of `program` in `exported`, which maps the names of the functions
+/// a library exports to their spans, can be exported.
+#[cfg(feature = "llvm")]
+fn check_exports<'src>(
+    program: &hir::Program,
+    exported: &HashMap<Symbol, Range<usize>>,
+) -> Result<(), Error<'src>> {
+    for function in &program.functions {
+        if let Some(name) = exported.get(&function.name) {
+            aot::export(function).map_err(|ty| Error::UnexportableFunction(name.clone(), ty))?;
+        }
+    }
+    Ok(())
+}
+
+/// Replaces the error a program was aborted with by `OutputLimitExceeded` if it was aborted for
+/// writing past the output limit.
+#[cfg(feature = "llvm")]
+fn check_output_limit<'src, O: Write, E: Write>(
+    result: Result<(), Error<'src>>,
+    std_out: &Limited<O>,
+    std_err: &Limited<E>,
+) -> Result<(), Error<'src>> {
+    let truncated = std_out.truncated() + std_err.truncated();
+    match std_out.limit() {
+        Some(limit) if truncated > 0 => Err(Error::OutputLimitExceeded(limit, truncated)),
+        _ => result,
+    }
+}
+
+pub struct SessionBuilder {
+    #[cfg(feature = "llvm")]
+    opt_level: OptimizationLevel,
+    #[cfg(feature = "llvm")]
+    backend: BackendKind,
+    colored: bool,
+    max_labels: usize,
+    quiet: bool,
+    sandbox: Option<Sandbox>,
+    #[cfg(feature = "llvm")]
+    max_output_bytes: Option<usize>,
+    #[cfg(feature = "llvm")]
+    deterministic: bool,
+    seed: Option<u64>,
+    entry: Option<String>,
+    plugins: Vec<Plugin>,
+    sources: Box<dyn SourceProvider>,
+    diagnostics: Box<dyn Write>,
+    verbose_codegen: bool,
+}
+
+impl Default for SessionBuilder {
+    fn default() -> Self {
+        Self {
+            #[cfg(feature = "llvm")]
+            opt_level: OptimizationLevel::None,
+            #[cfg(feature = "llvm")]
+            backend: BackendKind::default(),
+            colored: false,
+            max_labels: MAX_LABELS,
+            quiet: false,
+            sandbox: None,
+            #[cfg(feature = "llvm")]
+            max_output_bytes: None,
+            #[cfg(feature = "llvm")]
+            deterministic: false,
+            seed: None,
+            entry: None,
+            plugins: Vec::new(),
+            sources: Box::new(FileSystem),
+            diagnostics: Box::new(io::stderr()),
+            verbose_codegen: false,
+        }
+    }
+}
+
+impl SessionBuilder {
+    #[cfg(feature = "llvm")]
+    pub fn opt_level(mut self, opt_level: OptimizationLevel) -> Self {
+        self.opt_level = opt_level;
+        self
+    }
+
+    #[cfg(feature = "llvm")]
+    pub fn backend(mut self, backend: BackendKind) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    pub fn colored(mut self, colored: bool) -> Self {
+        self.colored = colored;
+        self
+    }
+
+    /// Renders at most `max_labels` labels for each list of spans an error points at, like the
+    /// unused arguments of a macro call, summing up the rest in a note.
+    pub fn max_labels(mut self, max_labels: usize) -> Self {
+        self.max_labels = max_labels;
+        self
+    }
+
+    /// Reports only errors: no warnings or notes about what was done, like fixes applied.
+    pub fn quiet(mut self, quiet: bool) -> Self {
+        self.quiet = quiet;
+        self
+    }
+
+    pub fn sandbox(mut self, sandbox: Sandbox) -> Self {
+        self.sandbox = Some(sandbox);
+        self
+    }
+
+    /// Aborts programs that write more than `max_output_bytes` to stdout or stderr. Overrides the
+    /// sandbox's limit.
+    #[cfg(feature = "llvm")]
+    pub fn max_output_bytes(mut self, max_output_bytes: usize) -> Self {
+        self.max_output_bytes = Some(max_output_bytes);
+        self
+    }
+
+    /// Emits the same IR, objects and archives for the same program on any machine: functions
+    /// and globals sorted by name, and no timestamps or host paths.
+    #[cfg(feature = "llvm")]
+    pub fn deterministic(mut self, deterministic: bool) -> Self {
+        self.deterministic = deterministic;
+        self
+    }
+
+    /// Seeds `random_int!` in every program the session runs, making them reproducible.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Starts every program the session runs in the function `entry`, instead of `main`.
+    pub fn entry(mut self, entry: impl Into<String>) -> Self {
+        self.entry = Some(entry.into());
+        self
+    }
+
+    /// Expands the macros `plugin` registers in every program the session compiles. Plugins added
+    /// earlier take precedence.
+    pub fn plugin(mut self, plugin: Plugin) -> Self {
+        self.plugins.push(plugin);
+        self
+    }
+
+    pub fn sources(mut self, sources: impl SourceProvider + 'static) -> Self {
+        self.sources = Box::new(sources);
+        self
+    }
+
+    pub fn diagnostics(mut self, diagnostics: impl Write + 'static) -> Self {
+        self.diagnostics = Box::new(diagnostics);
+        self
+    }
+
+    /// Logs the IR of each function as it's built, before the module is verified.
+    pub fn verbose_codegen(mut self, verbose_codegen: bool) -> Self {
+        self.verbose_codegen = verbose_codegen;
+        self
+    }
+
+    pub fn build(self) -> Session {
+        Session {
+            #[cfg(feature = "llvm")]
+            context: Context::create(),
+            #[cfg(feature = "llvm")]
+            opt_level: self.opt_level,
+            #[cfg(feature = "llvm")]
+            backend: self.backend,
+            colored: self.colored,
+            max_labels: self.max_labels,
+            quiet: self.quiet,
+            sandbox: self.sandbox,
+            #[cfg(feature = "llvm")]
+            max_output_bytes: self.max_output_bytes,
+            #[cfg(feature = "llvm")]
+            deterministic: self.deterministic,
+            seed: self.seed,
+            entry: self.entry,
+            plugins: self.plugins,
+            sources: self.sources,
+            diagnostics: RefCell::new(self.diagnostics),
+            counts: Cell::default(),
+            profiler: match self.verbose_codegen {
+                true => Profiler::default().with_verbose_codegen(io::stderr()),
+                false => Profiler::default(),
+            },
+            names: RefCell::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::InMemory;
+
+    fn session(file: &str, source_code: &str) -> Session {
+        Session::builder()
+            .sources(InMemory::new().with_file(file, source_code))
+            .diagnostics(io::sink())
+            .build()
+    }
+
+    /// Diagnostics that can still be read once the session writing to them has them.
+    #[derive(Clone, Default)]
+    struct Shared(std::rc::Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn quiet_sessions_count_the_warnings_they_dont_report() {
+        let diagnostics = Shared::default();
+        let session = Session::builder()
+            .sources(
+                InMemory::new()
+                    .with_file("a.sculpt", "fn main() { let x = 1; }")
+                    .with_file("b.sculpt", "fn main() { log!(); }"),
+            )
+            .diagnostics(diagnostics.clone())
+            .quiet(true)
+            .build();
+        let files = ["a.sculpt", "b.sculpt"].map(PathBuf::from);
+        assert!(!session.check_files(&files, false).unwrap());
+        let reported = String::from_utf8(diagnostics.0.take()).unwrap();
+        assert!(reported.contains("UnknownMacro"));
+        assert!(!reported.contains("UnusedVariable"));
+        assert_eq!(
+            session.counts(),
+            Counts {
+                errors: 1,
+                warnings: 1
+            }
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "llvm")]
+    fn in_memory_file_runs() {
+        let session = session("main.sculpt", r#"fn main() { print!("hi"); }"#);
+        let mut output = Vec::new();
+        assert!(session
+            .run_file(Path::new("main.sculpt"), &mut output)
+            .unwrap());
+        assert_eq!(output, b"hi");
+    }
+
+    #[test]
+    #[cfg(feature = "llvm")]
+    fn multiple_files_run_against_one_jit() {
+        let session = Session::builder()
+            .sources(
+                InMemory::new()
+                    .with_file("a.sculpt", r#"fn main() { print!("a"); }"#)
+                    .with_file("b.sculpt", r#"fn main() { print!(); }"#)
+                    .with_file("c.sculpt", r#"fn main() { println!("c"); }"#),
+            )
+            .diagnostics(io::sink())
+            .build();
+        let mut output = Vec::new();
+        let files = ["a.sculpt", "b.sculpt", "c.sculpt"].map(PathBuf::from);
+        assert_eq!(
+            session
+                .run_files(&files, io::empty(), &mut output, io::sink())
+                .unwrap(),
+            1
+        );
+        assert_eq!(output, b"ac\n");
+    }
+
+    #[test]
+    #[cfg(feature = "llvm")]
+    fn type_errors_are_reported_from_every_file() {
+        let diagnostics = Shared::default();
+        let session = Session::builder()
+            .sources(
+                InMemory::new()
+                    .with_file("a.sculpt", r#"fn main() { let x: i64 = "a"; }"#)
+                    .with_file("b.sculpt", r#"fn main() { print!("b"); }"#)
+                    .with_file("c.sculpt", "fn main() { let y: str = 1; }"),
+            )
+            .diagnostics(diagnostics.clone())
+            .build();
+        let mut output = Vec::new();
+        let files = ["a.sculpt", "b.sculpt", "c.sculpt"].map(PathBuf::from);
+        assert_eq!(
+            session
+                .run_files(&files, io::empty(), &mut output, io::sink())
+                .unwrap(),
+            1
+        );
+        assert_eq!(output, b"b");
+        let reported = String::from_utf8(diagnostics.0.take()).unwrap();
+        assert!(reported.contains("a.sculpt"), "{reported}");
+        assert!(reported.contains("c.sculpt"), "{reported}");
+        assert_eq!(session.counts().errors, 2);
+    }
+
+    #[test]
+    #[cfg(feature = "llvm")]
+    fn exit_skips_the_remaining_files() {
+        let session = Session::builder()
+            .sources(
+                InMemory::new()
+                    .with_file(
+                        "a.sculpt",
+                        r#"fn main() { print!("a"); exit!(3); print!("b"); }"#,
+                    )
+                    .with_file("b.sculpt", r#"fn main() { print!("c"); }"#),
+            )
+            .diagnostics(io::sink())
+            .build();
+        let mut output = Vec::new();
+        let files = ["a.sculpt", "b.sculpt"].map(PathBuf::from);
+        assert_eq!(
+            session
+                .run_files(&files, io::empty(), &mut output, io::sink())
+                .unwrap(),
+            3
+        );
+        assert_eq!(output, b"a");
+    }
+
+    #[test]
+    #[cfg(feature = "llvm")]
+    fn exit_status_is_returned_from_every_backend() {
+        for backend in [BackendKind::Jit, BackendKind::Aot] {
+            let session = Session::builder().backend(backend).build();
+            let run = |src| session.run_with_streams(src, io::empty(), io::sink(), io::sink());
+            assert_eq!(run(r#"fn main() { exit!(0); print!("a"); }"#), Ok(()));
+            assert_eq!(
+                run(r#"fn main() { exit!(40 + 2); }"#),
+                Err(Error::Exited(42))
+            );
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "llvm")]
+    fn only_bench_functions_are_benchmarked() {
+        let session = Session::builder().build();
+        let src = r#"
+            fn main() {}
+            #[bench]
+            fn hello() { print!("hello"); }
+            #[bench]
+            fn empty() {}
+        "#;
+        let options = BenchOptions {
+            warmup: 1,
+            samples: 2,
+            iterations: 1,
+        };
+        let names: Vec<_> = session
+            .bench(src, &options)
+            .unwrap()
+            .into_iter()
+            .map(|result| result.name)
+            .collect();
+        assert_eq!(names, ["hello", "empty"]);
+    }
+
+    #[test]
+    #[cfg(feature = "llvm")]
+    fn failing_benchmarks_fail_the_run() {
+        let session = Session::builder().build();
+        let src = r#"
+            fn main() {}
+            #[bench]
+            fn fine() {}
+            #[bench]
+            fn panics() { let n: Option<i64> = None; print!("{}", n.unwrap()); }
+            #[bench]
+            fn exits() { exit!(3); }
+        "#;
+        let options = BenchOptions {
+            warmup: 0,
+            samples: 2,
+            iterations: 1,
+        };
+        let error = session.bench(src, &options).unwrap_err();
+        assert!(matches!(error, Error::PanickedAt(..)), "{:?}", error);
+        let src = r#"
+            fn main() {}
+            #[bench]
+            fn exits() { exit!(3); }
+        "#;
+        assert_eq!(session.bench(src, &options).unwrap_err(), Error::Exited(3));
+    }
+
+    #[test]
+    #[cfg(feature = "llvm")]
+    fn test_functions_and_doc_code_blocks_are_tests() {
+        let src = r#"
+            /// ```
+            /// println!("{}", 1);
+            /// ```
+            /// ```
+            /// fn main() { exit!(1); }
+            /// ```
+            #[test]
+            fn passes() {}
+            #[test]
+            fn exits() { exit!(2); }
+        "#;
+        let session = session("lib.sculpt", src);
+        let mut output = Vec::new();
+        assert!(!session
+            .test_file(Path::new("lib.sculpt"), &mut output)
+            .unwrap());
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "test passes ... ok\n\
+             test exits ... FAILED\n\
+             test passes (line 2) ... ok\n\
+             test passes (line 5) ... FAILED\n\
+             \n\
+             test result: FAILED. 2 passed; 2 failed\n"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "llvm")]
+    fn sandboxed_output_is_capped() {
+        let session = Session::builder()
+            .sandbox(Sandbox {
+                max_output_bytes: 8,
+                ..Sandbox::default()
+            })
+            .build();
+        let mut output = Vec::new();
+        assert_eq!(
+            session.run(
+                r#"fn main() { println!("Hello {}!", "world"); }"#,
+                &mut output,
+            ),
+            Err(Error::OutputLimitExceeded(8, 3))
+        );
+        assert_eq!(output, b"Hello wo");
+    }
+
+    #[test]
+    #[cfg(feature = "llvm")]
+    fn sandboxed_programs_run_out_of_fuel() {
+        let loops = r#"fn main() { print!("spinning"); while let true = true {} }"#;
+        let recurses = r#"
+            fn spin(n: i64) -> i64 { spin(n + 1) }
+            fn main() { print!("spinning"); let n = spin(0); print!("{}", n); }
+        "#;
+        for backend in [BackendKind::Jit, BackendKind::Aot] {
+            for src in [loops, recurses] {
+                let session = Session::builder()
+                    .backend(backend)
+                    .sandbox(Sandbox {
+                        fuel: 1000,
+                        ..Sandbox::default()
+                    })
+                    .build();
+                let mut output = Vec::new();
+                let mut errors = Vec::new();
+                let result = session.run_with_streams(src, io::empty(), &mut output, &mut errors);
+                assert_eq!(output, b"spinning");
+                match backend {
+                    BackendKind::Jit => assert_eq!(
+                        result,
+                        Err(Error::RuntimeIoError(
+                            "ran out of fuel after 1000 steps".into()
+                        ))
+                    ),
+                    BackendKind::Aot => {
+                        assert_eq!(result, Err(Error::Exited(101)));
+                        assert_eq!(
+                            errors,
+                            b"program aborted: ran out of fuel after 1000 steps\n"
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "llvm")]
+    fn sandboxed_programs_run_out_of_time() {
+        let src = r#"fn main() { print!("spinning"); while let true = true {} }"#;
+        for backend in [BackendKind::Jit, BackendKind::Aot] {
+            let session = Session::builder()
+                .backend(backend)
+                .sandbox(Sandbox {
+                    fuel: u64::MAX,
+                    time_limit: std::time::Duration::from_millis(50),
+                    ..Sandbox::default()
+                })
+                .build();
+            let mut output = Vec::new();
+            let result = session.run_with_streams(src, io::empty(), &mut output, io::sink());
+            assert_eq!(output, b"spinning");
+            assert_eq!(
+                result,
+                Err(Error::RuntimeIoError("ran out of time after 50 ms".into()))
+            );
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "llvm")]
+    fn sandboxed_heaps_are_capped() {
+        let src = r#"
+            fn grow(s: str) -> i64 { grow(format!("{}{}", s, s)) }
+            fn main() { let n = grow("sculpt"); print!("{}", n); }
+        "#;
+        for backend in [BackendKind::Jit, BackendKind::Aot] {
+            let session = Session::builder()
+                .backend(backend)
+                .sandbox(Sandbox {
+                    max_heap_bytes: 1024 * 1024,
+                    ..Sandbox::default()
+                })
+                .build();
+            let mut errors = Vec::new();
+            let result = session.run_with_streams(src, io::empty(), io::sink(), &mut errors);
+            match backend {
+                BackendKind::Jit => assert_eq!(
+                    result,
+                    Err(Error::RuntimeIoError(
+                        "heap limit of 1048576 bytes exceeded".into()
+                    ))
+                ),
+                BackendKind::Aot => {
+                    assert_eq!(result, Err(Error::Exited(101)));
+                    assert_eq!(
+                        errors,
+                        b"program aborted: heap limit of 1048576 bytes exceeded\n"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "llvm")]
+    fn output_limit_applies_without_sandbox() {
+        let session = Session::builder().max_output_bytes(2).build();
+        let mut output = Vec::new();
+        let mut errors = Vec::new();
+        assert_eq!(
+            session.run_with_streams(
+                r#"fn main() { print!("ab"); eprint!("cd"); }"#,
+                io::empty(),
+                &mut output,
+                &mut errors,
+            ),
+            Ok(())
+        );
+        assert_eq!(
+            session.run_with_streams(
+                r#"fn main() { print!("abc"); eprint!("d"); }"#,
+                io::empty(),
+                &mut output,
+                &mut errors,
+            ),
+            Err(Error::OutputLimitExceeded(2, 1))
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "llvm")]
+    fn deterministic_ir_sorts_functions_and_statics_by_name() {
+        let session = Session::builder().deterministic(true).build();
+        let src = r#"
+            static Z: i64 = 1;
+            static A: i64 = 2;
+            fn main() { print!("{}", b(Z) + a(A)); }
+            fn b(x: i64) -> i64 { x }
+            fn a(x: i64) -> i64 { x }
+        "#;
+        let ir = session.compile(src, false).unwrap().ir();
+        let position = |name: &str| ir.find(name).unwrap();
+        assert!(position("@static.A =") < position("@static.Z ="));
+        assert!(position("@a.body(") < position("@b.body("));
+        assert!(position("@b.body(") < position("@main.body("));
+    }
+
+    #[test]
+    #[cfg(feature = "llvm")]
+    fn optimized_ir_runs_the_pass_pipeline() {
+        let session = Session::builder()
+            .opt_level(OptimizationLevel::Default)
+            .build();
+        let src = r#"fn main() { read_line!(); }"#;
+        let branches = |optimized| {
+            let ir = session.compile(src, optimized).unwrap().ir();
+            ir.matches("br i1").count()
+        };
+        // The branches to the abort block after the read and the call are folded away since both
+        // targets just return, leaving the ones taking a step of fuel and refueling.
+        assert_eq!((branches(false), branches(true)), (4, 2));
+    }
+
+    #[test]
+    #[cfg(feature = "llvm")]
+    fn ir_file_writes_bitcode() {
+        let session = session("main.sculpt", r#"fn main() { print!("hi"); }"#);
+        let path = std::env::temp_dir().join(format!("sculpt-{}.bc", std::process::id()));
+        assert!(session
+            .ir_file(Path::new("main.sculpt"), true, Some(&path), io::sink())
+            .unwrap());
+        let bitcode = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(bitcode.starts_with(b"BC\xc0\xde"));
+    }
+
+    #[test]
+    #[cfg(feature = "llvm")]
+    fn library_reports_functions_it_cant_export() {
+        let diagnostics = Shared::default();
+        let session = Session::builder()
+            .sources(InMemory::new().with_file(
+                "lib.sculpt",
+                "struct Point(i64); pub fn origin(p: Point) -> i64 { 0 }",
+            ))
+            .diagnostics(diagnostics.clone())
+            .build();
+        let path = std::env::temp_dir().join(format!("sculpt-lib-{}.a", std::process::id()));
+        assert!(!session
+            .build_library_file(Path::new("lib.sculpt"), &path)
+            .unwrap());
+        assert!(!path.exists());
+        let reported = String::from_utf8(diagnostics.0.take()).unwrap();
+        assert!(
+            reported.contains("`origin` can't be exported to C"),
+            "{reported}"
+        );
+        assert!(
+            reported.contains("`Point` has no C equivalent"),
+            "{reported}"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "llvm")]
+    fn failed_compile_is_reported_as_unsuccessful() {
+        let session = session("main.sculpt", r#"fn main() { print!(); }"#);
+        assert!(!session
+            .run_file(Path::new("main.sculpt"), io::sink())
+            .unwrap());
+    }
+
+    #[test]
+    #[cfg(feature = "llvm")]
+    fn programs_start_in_the_entry_point_they_are_given() {
+        let source_code = r#"fn main() { print!("main"); } fn start() { print!("start"); }"#;
+        let session = Session::builder().entry("start").build();
+        let mut output = Vec::new();
+        session
+            .run_with_streams(source_code, io::empty(), &mut output, io::sink())
+            .unwrap();
+        assert_eq!(output, b"start");
+
+        let session = Session::builder().entry("begin").build();
+        assert!(matches!(
+            session.run_with_streams(source_code, io::empty(), io::sink(), io::sink()),
+            Err(Error::MissingEntryPoint(span, name)) if span == (61..61) && name == "begin"
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "llvm")]
+    fn run_dir_prefixes_output_and_lists_failures() {
+        let dir = std::env::temp_dir().join(format!("sculpt-run-dir-{}", std::process::id()));
+        fs::create_dir_all(dir.join("nested")).unwrap();
+        fs::write(dir.join("a.sculpt"), r#"fn main() { println!("a"); }"#).unwrap();
+        fs::write(dir.join("b.sculpt"), "fn main() { exit!(2); }").unwrap();
+        fs::write(dir.join("notes.txt"), "not a program").unwrap();
+        fs::write(
+            dir.join("nested/c.sculpt"),
+            r#"fn main() { print!("c\nc"); }"#,
+        )
+        .unwrap();
+        let mut output = Vec::new();
+        let mut errors = Vec::new();
+        let status = Session::builder()
+            .build()
+            .run_dir(&dir, &mut output, &mut errors)
+            .unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(status, 1);
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "a.sculpt: a\nnested/c.sculpt: c\nnested/c.sculpt: c\n"
+        );
+        assert_eq!(
+            String::from_utf8(errors).unwrap(),
+            "b.sculpt: exited with status 2\n\nran 3 programs: 2 succeeded; 1 failed\n    b.sculpt\n"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "llvm")]
+    fn includes_are_relative_to_the_including_file() {
+        let session = Session::builder()
+            .sources(
+                InMemory::new()
+                    .with_file(
+                        "src/main.sculpt",
+                        r#"fn main() { print!(include_str!("fmt.txt"), include_str!("name.txt")); }"#,
+                    )
+                    .with_file("src/fmt.txt", "Hello {}!")
+                    .with_file("src/name.txt", "world"),
+            )
+            .build();
+        let mut output = Vec::new();
+        assert!(session
+            .run_file(Path::new("src/main.sculpt"), &mut output)
+            .unwrap());
+        assert_eq!(output, b"Hello world!");
+    }
+
+    #[test]
+    fn missing_includes_are_errors() {
+        let session = session("main.sculpt", "");
+        let src = r#"fn main() { include_str!("missing.txt"); }"#;
+        assert_eq!(
+            session.parse(Some(Path::new("main.sculpt")), src),
+            Err(Error::IncludeFailed(
+                12..39,
+                "missing.txt".into(),
+                "no in-memory source for missing.txt".into()
+            ))
+        );
+        assert_eq!(
+            session.check(r#"fn main() { include_str!(read_line!()); }"#),
+            Err(Error::ExpectedStrLit(25..37))
+        );
+    }
+
+    #[test]
+    fn sandboxed_programs_cannot_read_the_environment() {
+        let session = Session::builder().sandbox(Sandbox::default()).build();
+        assert_eq!(
+            session.check(r#"fn main() { print!("{}", env!("CARGO_PKG_NAME")); }"#),
+            Err(Error::EnvVarUnset(
+                25..47,
+                "environment variable `CARGO_PKG_NAME` not defined at compile time".into()
+            ))
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "llvm")]
+    fn sandboxed_programs_see_an_empty_environment() {
+        let session = Session::builder().sandbox(Sandbox::default()).build();
+        let mut output = Vec::new();
+        session
+            .run(
+                r#"fn main() { print!("{:?}", getenv!("CARGO_PKG_NAME")); }"#,
+                &mut output,
+            )
+            .unwrap();
+        assert_eq!(output, b"None");
+    }
+
+    #[test]
+    #[cfg(feature = "llvm")]
+    fn seeded_runs_are_reproducible() {
+        let src = r#"fn main() { print!("{} {} {}", random_int!(0, 1000000), random_int!(-5, 5), random_int!(7, 8)); }"#;
+        let run = |backend| {
+            let session = Session::builder().backend(backend).seed(42).build();
+            let mut output = Vec::new();
+            session
+                .run_with_streams(src, io::empty(), &mut output, io::sink())
+                .unwrap();
+            String::from_utf8(output).unwrap()
+        };
+        let jit = run(BackendKind::Jit);
+        assert_eq!(jit, run(BackendKind::Jit));
+        assert_eq!(jit, run(BackendKind::Aot));
+        assert!(jit.ends_with(" 7"));
+    }
+
+    #[test]
+    #[cfg(feature = "llvm")]
+    fn sandboxed_clocks_are_virtual_or_denied() {
+        let src = r#"
+            fn main() {
+                print!("{} ", now_millis!());
+                sleep_millis!(60000);
+                sleep_millis!(1500);
+                print!("{}", now_millis!());
+            }
+        "#;
+        for backend in [BackendKind::Jit, BackendKind::Aot] {
+            let session = Session::builder()
+                .backend(backend)
+                .sandbox(Sandbox::default())
+                .build();
+            let mut output = Vec::new();
+            session
+                .run_with_streams(src, io::empty(), &mut output, io::sink())
+                .unwrap();
+            assert_eq!(output, b"0 61500");
+
+            let session = Session::builder()
+                .backend(backend)
+                .sandbox(Sandbox {
+                    clock: Clock::Denied,
+                    ..Sandbox::default()
+                })
+                .build();
+            let mut output = Vec::new();
+            let result = session.run_with_streams(src, io::empty(), &mut output, io::sink());
+            assert_eq!(output, b"");
+            match backend {
+                BackendKind::Jit => assert_eq!(
+                    result,
+                    Err(Error::Panicked("this program may not use the clock".into()))
+                ),
+                // Executables report the panic on their stderr, and exit with status 101.
+                BackendKind::Aot => assert!(result.is_err()),
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "llvm")]
+    fn sandboxed_files_are_virtual_or_denied() {
+        let src = r#"
+            fn main() {
+                write_file!("Cargo.toml", "virtual");
+                write_file!("out.txt", format!("{} ", read_file!("Cargo.toml")));
+                write_file!("out.txt", format!("{}{}", read_file!("out.txt"), 42));
+                print!("{}", read_file!("out.txt"));
+            }
+        "#;
+        for backend in [BackendKind::Jit, BackendKind::Aot] {
+            let session = Session::builder()
+                .backend(backend)
+                .sandbox(Sandbox::default())
+                .build();
+            let mut output = Vec::new();
+            session
+                .run_with_streams(src, io::empty(), &mut output, io::sink())
+                .unwrap();
+            assert_eq!(output, b"virtual 42");
+
+            let session = Session::builder()
+                .backend(backend)
+                .sandbox(Sandbox {
+                    files: Files::Denied,
+                    ..Sandbox::default()
+                })
+                .build();
+            let result = session.run_with_streams(src, io::empty(), io::sink(), io::sink());
+            match backend {
+                BackendKind::Jit => assert_eq!(
+                    result,
+                    Err(Error::Panicked("this program may not use files".into()))
+                ),
+                BackendKind::Aot => assert!(result.is_err()),
+            }
+        }
+        assert!(!Path::new("out.txt").exists());
+    }
+
+    #[test]
+    fn sandboxed_programs_cannot_include_files() {
+        let session = Session::builder().sandbox(Sandbox::default()).build();
+        assert_eq!(
+            session.check(r#"fn main() { print!("{}", include_str!("Cargo.toml")); }"#),
+            Err(Error::IncludeFailed(
+                25..51,
+                "Cargo.toml".into(),
+                "this program may not read files".into()
+            ))
+        );
+    }
+
+    #[test]
+    fn check_reports_errors_without_running() {
+        let files = [PathBuf::from("main.sculpt")];
+        let valid = session("main.sculpt", r#"fn main() { print!("a"); }"#);
+        assert!(valid.check_files(&files, false).unwrap());
+        let invalid = session("main.sculpt", r#"fn main() { print!(); }"#);
+        assert!(!invalid.check_files(&files, false).unwrap());
+    }
+
+    #[test]
+    fn only_programs_are_checked_for_main() {
+        let files = [PathBuf::from("lib.sculpt")];
+        let session = session("lib.sculpt", "pub fn f() {}");
+        assert!(!session.check_files(&files, false).unwrap());
+        assert!(session.check_files(&files, true).unwrap());
+    }
+}
@@ -0,0 +1,183 @@
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::str;
+
+/// Supplies source text to the compiler. Embedders that don't want compilation to touch the
+/// real file system (editors, playgrounds, tests) can provide their own implementation. Files are
+/// read from several threads when compiling in parallel.
+pub trait SourceProvider: Sync {
+    /// Reads the source at `path`, without any byte order mark it starts with, so spans are
+    /// offsets into the text that follows it.
+    fn read(&self, path: &Path) -> io::Result<String>;
+}
+
+pub struct FileSystem;
+
+impl SourceProvider for FileSystem {
+    fn read(&self, path: &Path) -> io::Result<String> {
+        let file = File::open(path)?;
+        let len = file.metadata().map_or(0, |metadata| metadata.len());
+        read_chunked(path, file, len.try_into().unwrap_or(0))
+    }
+}
+
+/// Error reading a source file that isn't valid UTF-8, wrapped in an [`io::Error`].
+#[derive(Debug)]
+pub struct InvalidUtf8 {
+    pub path: PathBuf,
+    /// Offset of the first invalid byte in the file.
+    pub offset: usize,
+}
+
+impl fmt::Display for InvalidUtf8 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: invalid UTF-8 at byte offset {}",
+            self.path.display(),
+            self.offset
+        )
+    }
+}
+
+impl error::Error for InvalidUtf8 {}
+
+const BOM: &str = "\u{feff}";
+
+/// How much [`read_chunked`] reads at a time.
+const CHUNK_BYTES: u64 = 64 * 1024;
+
+/// Reads the source at `path` from `reader` in chunks, stripping a byte order mark. The text is
+/// read straight into a buffer with room for `size_hint` bytes and validated as UTF-8 a chunk at
+/// a time, so even sources tens of megabytes long are neither copied nor regrown as they're read.
+pub fn read_chunked(path: &Path, mut reader: impl Read, size_hint: usize) -> io::Result<String> {
+    let mut bytes = Vec::with_capacity(size_hint);
+    (&mut reader)
+        .take(BOM.len() as u64)
+        .read_to_end(&mut bytes)?;
+    if bytes == BOM.as_bytes() {
+        bytes.clear();
+    }
+    let invalid = |offset| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            InvalidUtf8 {
+                path: path.to_path_buf(),
+                offset,
+            },
+        )
+    };
+    let mut valid = 0;
+    loop {
+        let read = (&mut reader).take(CHUNK_BYTES).read_to_end(&mut bytes)?;
+        match str::from_utf8(&bytes[valid..]) {
+            Ok(_) => valid = bytes.len(),
+            // A character split between this chunk and the next is finished by the next.
+            Err(error) if error.error_len().is_none() && read > 0 => valid += error.valid_up_to(),
+            Err(error) => return Err(invalid(valid + error.valid_up_to())),
+        }
+        if read == 0 {
+            break;
+        }
+    }
+    // Every byte was validated above, chunk by chunk.
+    Ok(unsafe { String::from_utf8_unchecked(bytes) })
+}
+
+#[derive(Default)]
+pub struct InMemory {
+    files: HashMap<PathBuf, String>,
+}
+
+impl InMemory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_file(mut self, path: impl Into<PathBuf>, source_code: impl Into<String>) -> Self {
+        self.insert(path, source_code);
+        self
+    }
+
+    pub fn insert(&mut self, path: impl Into<PathBuf>, source_code: impl Into<String>) {
+        self.files.insert(path.into(), source_code.into());
+    }
+}
+
+impl SourceProvider for InMemory {
+    fn read(&self, path: &Path) -> io::Result<String> {
+        let source_code = self.files.get(path).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no in-memory source for {}", path.display()),
+            )
+        })?;
+        Ok(source_code
+            .strip_prefix(BOM)
+            .unwrap_or(source_code)
+            .to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn in_memory_files_are_read_back() {
+        let sources = InMemory::new().with_file("main.sculpt", "fn main() {}");
+        assert_eq!(
+            sources.read(Path::new("main.sculpt")).unwrap(),
+            "fn main() {}"
+        );
+    }
+
+    #[test]
+    fn missing_in_memory_file_is_not_found() {
+        let sources = InMemory::new();
+        assert_eq!(
+            sources.read(Path::new("main.sculpt")).unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+    }
+
+    #[test]
+    fn byte_order_marks_are_stripped() {
+        let path = Path::new("main.sculpt");
+        let source_code = "\u{feff}fn main() {}";
+        let read = read_chunked(path, source_code.as_bytes(), 0).unwrap();
+        assert_eq!(read, "fn main() {}");
+        let sources = InMemory::new().with_file(path, source_code);
+        assert_eq!(sources.read(path).unwrap(), "fn main() {}");
+    }
+
+    #[test]
+    fn invalid_utf8_is_reported_with_its_offset() {
+        let error = read_chunked(Path::new("main.sculpt"), &b"fn \xff() {}"[..], 0).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(
+            error.to_string(),
+            "main.sculpt: invalid UTF-8 at byte offset 3"
+        );
+    }
+
+    #[test]
+    fn characters_split_between_chunks_are_read_whole() {
+        // The first chunk ends after the first byte of `é`, as the first three bytes are read
+        // to look for a byte order mark.
+        let mut source_code = "x".repeat(CHUNK_BYTES as usize + 2);
+        source_code.push('é');
+        let path = Path::new("main.sculpt");
+        assert_eq!(
+            read_chunked(path, source_code.as_bytes(), 0).unwrap(),
+            source_code
+        );
+        let truncated = &source_code.as_bytes()[..source_code.len() - 1];
+        let error = read_chunked(path, truncated, 0).unwrap_err();
+        assert!(error.to_string().ends_with("offset 65538"));
+    }
+}
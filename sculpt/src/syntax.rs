@@ -1,24 +1,1000 @@
+use serde::{Deserialize, Serialize};
+
+use std::cell::Cell;
 use std::ops::Range;
 
-#[derive(Debug)]
-pub struct Main<'s> {
-    pub statements: Vec<Macro<'s>>,
+use crate::intern::Symbol;
+
+/// Identifies a node within the tree it was parsed into. Ids are handed out in the order the
+/// parser builds nodes, so parsing the same source always yields the same ids.
+///
+/// Nodes deserialized without ids and spans, like the ones plugins build, get id 0 and an empty
+/// span.
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize,
+)]
+pub struct NodeId(pub u32);
+
+#[derive(Default)]
+pub struct NodeIds {
+    next: Cell<u32>,
+}
+
+impl NodeIds {
+    pub fn next(&self) -> NodeId {
+        let id = self.next.get();
+        self.next.set(id + 1);
+        NodeId(id)
+    }
+}
+
+/// Words reserved for the language, which can't be used as names even where the grammar doesn't
+/// use them yet.
+pub const KEYWORDS: [&str; 38] = [
+    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern",
+    "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub",
+    "ref", "return", "self", "Self", "static", "struct", "super", "trait", "true", "type",
+    "unsafe", "use", "where", "while",
+];
+
+pub fn is_keyword(name: &str) -> bool {
+    KEYWORDS.contains(&name)
+}
+
+/// Errors the grammar raises itself, on top of the ones lalrpop's parser does.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SyntaxError {
+    /// A keyword was used where a name was expected.
+    KeywordAsName(Range<usize>),
+    /// A macro parameter's fragment specifier was something other than `expr`.
+    UnknownFragment(Range<usize>),
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Program {
+    pub id: NodeId,
+    /// `#![name(args, ...)]` attributes at the top of the file, configuring all of it.
+    #[serde(default)]
+    pub attrs: Vec<Attr>,
+    pub functions: Vec<Function>,
+    #[serde(default)]
+    pub structs: Vec<Struct>,
+    #[serde(default)]
+    pub impls: Vec<Impl>,
+    #[serde(default)]
+    pub traits: Vec<Trait>,
+    pub macros: Vec<MacroRules>,
+    #[serde(default)]
+    pub statics: Vec<Static>,
+}
+
+impl Program {
+    /// Every function the program defines, along with the type of the `impl` it's in, if any.
+    pub fn all_functions(&self) -> impl Iterator<Item = (Option<&Name>, &Function)> {
+        let methods = self.impls.iter().flat_map(|i| {
+            i.functions
+                .iter()
+                .map(move |function| (Some(&i.ty), function))
+        });
+        self.functions
+            .iter()
+            .map(|function| (None, function))
+            .chain(methods)
+    }
+
+    /// Like [`Program::all_functions`], taking them out of the program.
+    pub fn into_all_functions(self) -> impl Iterator<Item = (Option<Name>, Function)> {
+        let methods = self.impls.into_iter().flat_map(|i| {
+            let ty = i.ty;
+            i.functions
+                .into_iter()
+                .map(move |function| (Some(ty.clone()), function))
+        });
+        self.functions
+            .into_iter()
+            .map(|function| (None, function))
+            .chain(methods)
+    }
+}
+
+/// Anything that can appear at the top level of a program, which the parser sorts into
+/// [`Program`]'s lists. Functions are boxed, as they're much larger than the rest.
+pub enum Item {
+    Function(Box<Function>),
+    Struct(Struct),
+    Impl(Impl),
+    Trait(Trait),
+    MacroRules(MacroRules),
+    Static(Static),
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Function {
+    pub id: NodeId,
+    pub span: Range<usize>,
+    #[serde(default)]
+    pub docs: Vec<Doc>,
+    pub attrs: Vec<Attr>,
+    /// Declared `pub`, so libraries export it.
+    pub public: bool,
+    pub name: Name,
+    /// Type parameters, like the `T` in `fn show<T: Display>(x: T)`.
+    #[serde(default)]
+    pub generics: Vec<GenericParam>,
+    #[serde(default)]
+    pub params: Vec<Param>,
+    /// The type after `->`, if there is one.
+    pub ret: Option<Ty>,
+    pub statements: Vec<Stmt>,
+    /// The expression after the last statement, which the function returns.
+    #[serde(default)]
+    pub tail: Option<Expr>,
+}
+
+impl Function {
+    pub fn has_attr(&self, attr: Symbol) -> bool {
+        self.attrs.iter().any(|a| a.name.name == attr)
+    }
+}
+
+/// A `/// text` comment documenting the item after it, one per line.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Doc {
+    /// The span of the text, which excludes the `///` and the space after it.
+    #[serde(default)]
+    pub span: Range<usize>,
+    pub text: String,
+}
+
+/// `#[name]` or `#[name(args, ...)]`, configuring the item it's on, like `#[allow(lint)]`.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Attr {
+    #[serde(default)]
+    pub id: NodeId,
+    #[serde(default)]
+    pub span: Range<usize>,
+    pub name: Name,
+    #[serde(default)]
+    pub args: Vec<Name>,
+}
+
+/// `name: ty`, or the `self` or `&self` a method takes its receiver as.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Param {
+    #[serde(default)]
+    pub id: NodeId,
+    #[serde(default)]
+    pub span: Range<usize>,
+    pub name: Name,
+    /// `None` for `self`, whose type is the one the method is implemented on.
+    pub ty: Option<Ty>,
+}
+
+/// `T: Bound + ...`, a type parameter and the bounds its types must satisfy.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct GenericParam {
+    #[serde(default)]
+    pub id: NodeId,
+    #[serde(default)]
+    pub span: Range<usize>,
+    pub name: Name,
+    #[serde(default)]
+    pub bounds: Vec<Bound>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub enum Bound {
+    /// A trait the type must implement.
+    Trait(Name),
+    /// `Fn(params) -> ret`, for closures that can be called with the parameters.
+    Fn(FnBound),
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct FnBound {
+    #[serde(default)]
+    pub id: NodeId,
+    #[serde(default)]
+    pub span: Range<usize>,
+    pub params: Vec<Ty>,
+    pub ret: Option<Ty>,
+}
+
+/// `static NAME: Ty = value;`, a value any function can read by name. Its value is worked out
+/// when the program is compiled.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Static {
+    pub id: NodeId,
+    pub span: Range<usize>,
+    /// Declared `static mut`.
+    pub mutable: bool,
+    pub name: Name,
+    pub ty: Ty,
+    pub value: Expr,
+}
+
+/// `struct Name(fields);`, a tuple struct.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Struct {
+    pub id: NodeId,
+    pub span: Range<usize>,
+    #[serde(default)]
+    pub docs: Vec<Doc>,
+    pub name: Name,
+    pub fields: Vec<Ty>,
+}
+
+/// `impl Name { functions }`, the methods and associated functions of a struct, or
+/// `impl Trait for Name { functions }`, its implementation of a trait.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Impl {
+    pub id: NodeId,
+    pub span: Range<usize>,
+    #[serde(default)]
+    pub trait_name: Option<Name>,
+    pub ty: Name,
+    pub functions: Vec<Function>,
+}
+
+/// `trait Name { methods }`, functions a type can implement.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Trait {
+    pub id: NodeId,
+    pub span: Range<usize>,
+    pub name: Name,
+    pub methods: Vec<TraitMethod>,
+}
+
+/// `fn name(params) -> ret;`, a function a trait's implementations define.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct TraitMethod {
+    pub id: NodeId,
+    pub span: Range<usize>,
+    pub name: Name,
+    pub params: Vec<Param>,
+    pub ret: Option<Ty>,
 }
 
-#[derive(Debug)]
-pub struct Name<'s> {
+/// A user-defined macro, `macro_rules! name { ($a:expr, ...) => { ... }; ... }`.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct MacroRules {
+    pub id: NodeId,
     pub span: Range<usize>,
-    pub name: &'s str,
+    /// The name without the `!` it's invoked with.
+    pub name: Name,
+    pub rules: Vec<MacroRule>,
 }
 
-#[derive(Debug)]
-pub struct Macro<'s> {
-    pub name: Name<'s>,
-    pub args: Vec<StrLit<'s>>,
+/// One `(params) => { body }` arm of a [`MacroRules`]. Invocations expand to the body of the
+/// first rule taking as many arguments as they pass, with each `$param` replaced by its argument.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct MacroRule {
+    pub id: NodeId,
+    pub span: Range<usize>,
+    /// Parameter names, without their `$`.
+    pub params: Vec<Name>,
+    pub body: Vec<Stmt>,
 }
 
-#[derive(Debug)]
-pub struct StrLit<'s> {
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Name {
+    #[serde(default)]
+    pub id: NodeId,
+    #[serde(default)]
     pub span: Range<usize>,
-    pub val: &'s str,
+    pub name: Symbol,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Stmt {
+    /// `name!(args);`, invoking a macro for what it does.
+    Macro(Macro),
+    For(For),
+    /// `while let pattern = value { body }`.
+    WhileLet(WhileLet),
+    /// An `if let` run for what it does, which needs no `;` after it.
+    IfLet(IfLet),
+    Block(Block),
+    Let(Let),
+}
+
+/// `let pattern: ty = value;`, which binds the names in the pattern for the rest of the block. The
+/// type is inferred from the value if it isn't given.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Let {
+    #[serde(default)]
+    pub id: NodeId,
+    #[serde(default)]
+    pub span: Range<usize>,
+    pub pattern: Pattern,
+    pub ty: Option<Ty>,
+    pub value: Expr,
+}
+
+/// `{ statements tail }`, whose statements run in a scope of their own. As a statement, it has no
+/// tail. As an expression, it evaluates to its tail.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Block {
+    #[serde(default)]
+    pub id: NodeId,
+    #[serde(default)]
+    pub span: Range<usize>,
+    pub statements: Vec<Stmt>,
+    pub tail: Option<Box<Expr>>,
+}
+
+/// `for pattern in iter { body }`, which runs the body for each item the iterator yields.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct For {
+    #[serde(default)]
+    pub id: NodeId,
+    #[serde(default)]
+    pub span: Range<usize>,
+    pub pattern: Pattern,
+    pub iter: Expr,
+    pub body: Vec<Stmt>,
+}
+
+/// `while let pattern = value { body }`, which evaluates the value before each run of the body,
+/// and stops once it doesn't match the pattern.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct WhileLet {
+    #[serde(default)]
+    pub id: NodeId,
+    #[serde(default)]
+    pub span: Range<usize>,
+    pub pattern: Pattern,
+    pub value: Expr,
+    pub body: Vec<Stmt>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Macro {
+    #[serde(default)]
+    pub id: NodeId,
+    #[serde(default)]
+    pub span: Range<usize>,
+    pub name: Name,
+    pub args: Vec<Expr>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Expr {
+    Str(StrLit),
+    Int(IntLit),
+    Name(Name),
+    Neg(Neg),
+    Binary(Binary),
+    Macro(Macro),
+    /// A `$param` in the body of a [`MacroRule`]. The name doesn't include the `$`, but the span
+    /// does.
+    MetaVar(Name),
+    Call(Call),
+    MethodCall(MethodCall),
+    Match(Match),
+    Try(Try),
+    Field(Field),
+    Closure(Closure),
+    Borrow(Borrow),
+    Deref(Deref),
+    Slice(Slice),
+    Range(RangeExpr),
+    Block(Block),
+    IfLet(Box<IfLet>),
+}
+
+impl Expr {
+    pub fn id(&self) -> NodeId {
+        match self {
+            Expr::Str(lit) => lit.id,
+            Expr::Int(lit) => lit.id,
+            Expr::Name(name) => name.id,
+            Expr::Neg(neg) => neg.id,
+            Expr::Binary(binary) => binary.id,
+            Expr::Macro(m) => m.id,
+            Expr::MetaVar(name) => name.id,
+            Expr::Call(call) => call.id,
+            Expr::MethodCall(call) => call.id,
+            Expr::Match(m) => m.id,
+            Expr::Try(t) => t.id,
+            Expr::Field(field) => field.id,
+            Expr::Closure(closure) => closure.id,
+            Expr::Borrow(borrow) => borrow.id,
+            Expr::Deref(deref) => deref.id,
+            Expr::Slice(slice) => slice.id,
+            Expr::Range(range) => range.id,
+            Expr::Block(block) => block.id,
+            Expr::IfLet(i) => i.id,
+        }
+    }
+
+    pub fn span(&self) -> Range<usize> {
+        match self {
+            Expr::Str(lit) => lit.span.clone(),
+            Expr::Int(lit) => lit.span.clone(),
+            Expr::Name(name) => name.span.clone(),
+            Expr::Neg(neg) => neg.span.clone(),
+            Expr::Binary(binary) => binary.span.clone(),
+            Expr::Macro(m) => m.span.clone(),
+            Expr::MetaVar(name) => name.span.clone(),
+            Expr::Call(call) => call.span.clone(),
+            Expr::MethodCall(call) => call.span.clone(),
+            Expr::Match(m) => m.span.clone(),
+            Expr::Try(t) => t.span.clone(),
+            Expr::Field(field) => field.span.clone(),
+            Expr::Closure(closure) => closure.span.clone(),
+            Expr::Borrow(borrow) => borrow.span.clone(),
+            Expr::Deref(deref) => deref.span.clone(),
+            Expr::Slice(slice) => slice.span.clone(),
+            Expr::Range(range) => range.span.clone(),
+            Expr::Block(block) => block.span.clone(),
+            Expr::IfLet(i) => i.span.clone(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct StrLit {
+    #[serde(default)]
+    pub id: NodeId,
+    #[serde(default)]
+    pub span: Range<usize>,
+    pub val: Symbol,
+    /// Where `val` differs from the source, in order.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub escapes: Vec<Escape>,
+}
+
+impl StrLit {
+    /// Whether the literal is written out in the source, rather than expanded from a macro like
+    /// `include_str!`, whose invocation `span` covers instead.
+    pub fn is_in_source(&self) -> bool {
+        self.span.len() == self.source_offset(self.val.as_str().len()) - self.span.start + 1
+    }
+
+    /// The offset in the source of the byte `offset` into `val`, past its opening quote and any
+    /// escape sequences before it.
+    pub fn source_offset(&self, offset: usize) -> usize {
+        let escaped: usize = self
+            .escapes
+            .iter()
+            .take_while(|escape| escape.offset < offset)
+            .map(|escape| escape.len - 1)
+            .sum();
+        self.span.start + 1 + offset + escaped
+    }
+}
+
+/// An escape sequence like `\n`, written with `len` bytes of source for the one byte at `offset`
+/// into a string literal's value.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Escape {
+    pub offset: usize,
+    pub len: usize,
+}
+
+/// The statements and tail of a block parsed as `statements` followed by `tail`. An `if let`
+/// ending a block is parsed as a statement, but is the tail of a block that has no other.
+pub fn with_tail(mut statements: Vec<Stmt>, tail: Option<Expr>) -> (Vec<Stmt>, Option<Box<Expr>>) {
+    let tail = tail.or_else(|| match statements.pop() {
+        Some(Stmt::IfLet(i)) => Some(Expr::IfLet(Box::new(i))),
+        Some(statement) => {
+            statements.push(statement);
+            None
+        }
+        None => None,
+    });
+    (statements, tail.map(Box::new))
+}
+
+/// Replaces the escape sequences in `contents`, the source between a string literal's quotes.
+/// A backslash before anything but `n`, `t`, `r`, `0`, `\\` or `"` is kept as it is.
+pub fn unescape(contents: &str) -> (String, Vec<Escape>) {
+    let mut val = String::with_capacity(contents.len());
+    let mut escapes = Vec::new();
+    let mut rest = contents.chars();
+    while let Some(c) = rest.next() {
+        let escaped = match rest.clone().next().filter(|_| c == '\\') {
+            Some('n') => '\n',
+            Some('t') => '\t',
+            Some('r') => '\r',
+            Some('0') => '\0',
+            Some(c @ ('\\' | '"')) => c,
+            _ => {
+                val.push(c);
+                continue;
+            }
+        };
+        rest.next();
+        escapes.push(Escape {
+            offset: val.len(),
+            len: 2,
+        });
+        val.push(escaped);
+    }
+    (val, escapes)
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct IntLit {
+    #[serde(default)]
+    pub id: NodeId,
+    #[serde(default)]
+    pub span: Range<usize>,
+    /// The literal's digits, which may not fit in any integer type.
+    pub digits: Symbol,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Neg {
+    #[serde(default)]
+    pub id: NodeId,
+    #[serde(default)]
+    pub span: Range<usize>,
+    pub expr: Box<Expr>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Binary {
+    #[serde(default)]
+    pub id: NodeId,
+    #[serde(default)]
+    pub span: Range<usize>,
+    pub op: BinOp,
+    #[serde(default)]
+    pub op_span: Range<usize>,
+    pub lhs: Box<Expr>,
+    pub rhs: Box<Expr>,
+}
+
+/// `name(args)`, like `Some(1)`, or `Type::name(args)`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Call {
+    #[serde(default)]
+    pub id: NodeId,
+    #[serde(default)]
+    pub span: Range<usize>,
+    /// The type before the `::`, if there is one.
+    #[serde(default)]
+    pub ty: Option<Name>,
+    pub name: Name,
+    /// Type arguments given with `::<...>`, like the `i64` in `id::<i64>(x)`.
+    #[serde(default)]
+    pub generics: Vec<Ty>,
+    pub args: Vec<Expr>,
+}
+
+/// `receiver.name(args)`, like `x.unwrap()`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MethodCall {
+    #[serde(default)]
+    pub id: NodeId,
+    #[serde(default)]
+    pub span: Range<usize>,
+    pub receiver: Box<Expr>,
+    pub name: Name,
+    pub args: Vec<Expr>,
+}
+
+/// `expr.0`, a field of a tuple struct.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Field {
+    #[serde(default)]
+    pub id: NodeId,
+    #[serde(default)]
+    pub span: Range<usize>,
+    pub expr: Box<Expr>,
+    pub index: IntLit,
+}
+
+/// `|params| body`, a function that captures the values of the names its body uses.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Closure {
+    #[serde(default)]
+    pub id: NodeId,
+    #[serde(default)]
+    pub span: Range<usize>,
+    pub params: Vec<ClosureParam>,
+    pub body: Box<Expr>,
+}
+
+/// `name` or `name: ty`, a parameter of a closure.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ClosureParam {
+    #[serde(default)]
+    pub id: NodeId,
+    #[serde(default)]
+    pub span: Range<usize>,
+    pub name: Name,
+    /// `None` if it's left to be inferred from where the closure is passed.
+    pub ty: Option<Ty>,
+}
+
+/// `&expr`, or `&mut expr`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Borrow {
+    #[serde(default)]
+    pub id: NodeId,
+    #[serde(default)]
+    pub span: Range<usize>,
+    #[serde(default)]
+    pub mutable: bool,
+    pub expr: Box<Expr>,
+}
+
+/// `*expr`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Deref {
+    #[serde(default)]
+    pub id: NodeId,
+    #[serde(default)]
+    pub span: Range<usize>,
+    pub expr: Box<Expr>,
+}
+
+/// `expr[start..end]`, where either bound can be left out.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Slice {
+    #[serde(default)]
+    pub id: NodeId,
+    #[serde(default)]
+    pub span: Range<usize>,
+    pub expr: Box<Expr>,
+    pub start: Option<Box<Expr>>,
+    pub end: Option<Box<Expr>>,
+}
+
+/// `start..end`, the integers from `start` up to but not including `end`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RangeExpr {
+    #[serde(default)]
+    pub id: NodeId,
+    #[serde(default)]
+    pub span: Range<usize>,
+    pub start: Box<Expr>,
+    pub end: Box<Expr>,
+}
+
+/// `expr?`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Try {
+    #[serde(default)]
+    pub id: NodeId,
+    #[serde(default)]
+    pub span: Range<usize>,
+    pub expr: Box<Expr>,
+}
+
+/// A type as written, like `Result<(), str>`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Ty {
+    /// `()`. Holds its span.
+    Unit(#[serde(default)] Range<usize>),
+    /// A name and its generic arguments, if any, like `Option<i64>`.
+    Path(TyPath),
+    /// `&ty`, or `&mut ty`.
+    Ref(RefTy),
+}
+
+impl Ty {
+    pub fn span(&self) -> Range<usize> {
+        match self {
+            Ty::Unit(span) => span.clone(),
+            Ty::Path(path) => path.span.clone(),
+            Ty::Ref(r) => r.span.clone(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RefTy {
+    #[serde(default)]
+    pub id: NodeId,
+    #[serde(default)]
+    pub span: Range<usize>,
+    #[serde(default)]
+    pub mutable: bool,
+    pub ty: Box<Ty>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TyPath {
+    #[serde(default)]
+    pub id: NodeId,
+    #[serde(default)]
+    pub span: Range<usize>,
+    pub name: Name,
+    pub args: Vec<Ty>,
+}
+
+/// `match scrutinee { pattern => body, ... }`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Match {
+    #[serde(default)]
+    pub id: NodeId,
+    #[serde(default)]
+    pub span: Range<usize>,
+    pub scrutinee: Box<Expr>,
+    pub arms: Vec<MatchArm>,
+}
+
+/// `if let pattern = value { then } else { otherwise }`, which evaluates to `then`, with the names
+/// in the pattern bound, if the value matches the pattern, and to `otherwise` if it doesn't. An
+/// `else if let` is an `otherwise` holding just another `if let`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct IfLet {
+    #[serde(default)]
+    pub id: NodeId,
+    #[serde(default)]
+    pub span: Range<usize>,
+    pub pattern: Pattern,
+    pub value: Box<Expr>,
+    pub then: Block,
+    #[serde(default)]
+    pub otherwise: Option<Block>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MatchArm {
+    #[serde(default)]
+    pub id: NodeId,
+    #[serde(default)]
+    pub span: Range<usize>,
+    pub pattern: Pattern,
+    pub body: Expr,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Pattern {
+    /// `_`, which matches anything. Holds its span.
+    Wildcard(#[serde(default)] Range<usize>),
+    /// A name, which either binds whatever it matches or, like `None`, names what it matches.
+    Name(Name),
+    /// A string literal, which matches strings equal to it.
+    Str(StrLit),
+    /// `name(fields)`, like `Some(x)`.
+    TupleStruct(TupleStructPattern),
+}
+
+impl Pattern {
+    pub fn span(&self) -> Range<usize> {
+        match self {
+            Pattern::Wildcard(span) => span.clone(),
+            Pattern::Name(name) => name.span.clone(),
+            Pattern::Str(lit) => lit.span.clone(),
+            Pattern::TupleStruct(pattern) => pattern.span.clone(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TupleStructPattern {
+    #[serde(default)]
+    pub id: NodeId,
+    #[serde(default)]
+    pub span: Range<usize>,
+    pub name: Name,
+    pub fields: Vec<Pattern>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+}
+
+impl BinOp {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            BinOp::Add => "+",
+            BinOp::Sub => "-",
+            BinOp::Mul => "*",
+            BinOp::Div => "/",
+            BinOp::Rem => "%",
+        }
+    }
+}
+
+/// The tree as an indented outline, for golden tests of the grammar. Node ids and empty fields
+/// are left out, spans are written `start..end`, and nodes that are only a span and a value,
+/// like names, are written on one line as `"value" start..end`.
+pub fn dump(program: &Program) -> String {
+    let mut lines = Vec::new();
+    for field in outline(&serde_json::to_value(program).unwrap()).children {
+        field.write(0, &mut lines);
+    }
+    lines.join("\n") + "\n"
+}
+
+/// A line of a dump, and the lines indented under it.
+struct Outline {
+    line: String,
+    children: Vec<Outline>,
+}
+
+impl Outline {
+    fn leaf(line: String) -> Self {
+        Outline {
+            line,
+            children: Vec::new(),
+        }
+    }
+
+    /// The outline with `prefix` put before its line.
+    fn prefixed(self, prefix: &str) -> Self {
+        Outline {
+            line: format!("{} {}", prefix, self.line).trim_end().to_string(),
+            ..self
+        }
+    }
+
+    fn write(self, indent: usize, lines: &mut Vec<String>) {
+        lines.push(format!("{:indent$}{}", "", self.line, indent = indent * 2));
+        for child in self.children {
+            child.write(indent + 1, lines);
+        }
+    }
+}
+
+fn outline(value: &serde_json::Value) -> Outline {
+    use serde_json::Value;
+    match value {
+        Value::Array(items) => Outline {
+            line: String::new(),
+            children: items
+                .iter()
+                .map(|item| outline(item).prefixed("-"))
+                .collect(),
+        },
+        Value::Object(fields) => {
+            if let (Some(start), Some(end), 2) =
+                (fields.get("start"), fields.get("end"), fields.len())
+            {
+                return Outline::leaf(format!("{}..{}", start, end));
+            }
+            // An enum variant.
+            if let Some((variant, value)) = fields.iter().next().filter(|_| fields.len() == 1) {
+                if variant.starts_with(char::is_uppercase) {
+                    return outline(value).prefixed(variant);
+                }
+            }
+            let span = fields
+                .get("span")
+                .map_or(String::new(), |span| outline(span).line);
+            let fields: Vec<_> = fields
+                .iter()
+                .filter(|(name, value)| {
+                    !matches!(name.as_str(), "id" | "span")
+                        && !matches!(value, Value::Null | Value::Bool(false))
+                        && value.as_array().is_none_or(|items| !items.is_empty())
+                })
+                .collect();
+            match fields[..] {
+                [(_, value)] if !value.is_array() && !value.is_object() => {
+                    Outline::leaf(span).prefixed(&value.to_string())
+                }
+                _ => Outline {
+                    line: span,
+                    children: fields
+                        .into_iter()
+                        .map(|(name, value)| outline(value).prefixed(&format!("{}:", name)))
+                        .collect(),
+                },
+            }
+        }
+        value => Outline::leaf(value.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::run::parse;
+
+    const SRC: &str = r#"
+        #[bench]
+        fn main() {
+            println!("{} {}", "a", 1 + 2);
+        }
+    "#;
+
+    #[test]
+    fn tree_round_trips_through_json() {
+        let program = parse(SRC).unwrap();
+        let json = serde_json::to_string(&program).unwrap();
+        assert_eq!(serde_json::from_str::<Program>(&json).unwrap(), program);
+    }
+
+    #[test]
+    fn dumps_leave_out_ids_and_empty_fields() {
+        assert_eq!(
+            dump(&parse("fn main() { let x = -1; }").unwrap()),
+            [
+                "functions:",
+                "  - 0..25",
+                "    name: \"main\" 3..7",
+                "    statements:",
+                "      - Let 12..23",
+                "        pattern: Name \"x\" 16..17",
+                "        value: Neg 20..22",
+                "          expr: Int \"1\" 21..22",
+                "",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn node_ids_are_stable_across_parses() {
+        assert_eq!(parse(SRC).unwrap(), parse(SRC).unwrap());
+    }
+
+    #[test]
+    fn node_ids_are_unique() {
+        let program = parse(SRC).unwrap();
+        let function = &program.functions[0];
+        let Stmt::Macro(statement) = &function.statements[0] else {
+            panic!("expected a macro statement");
+        };
+        let mut ids = vec![program.id, function.id, function.name.id, statement.id];
+        ids.push(statement.name.id);
+        ids.extend(function.attrs.iter().map(|attr| attr.id));
+        ids.extend(statement.args.iter().map(Expr::id));
+        let count = ids.len();
+        ids.sort();
+        ids.dedup();
+        assert_eq!(ids.len(), count);
+    }
+
+    #[test]
+    fn binary_expressions_span_their_operands() {
+        let program = parse(r#"fn main() { print!("{}", (1) - -x * 2); }"#).unwrap();
+        let Stmt::Macro(statement) = &program.functions[0].statements[0] else {
+            panic!("expected a macro statement");
+        };
+        let Expr::Binary(sub) = &statement.args[1] else {
+            panic!("expected a binary expression");
+        };
+        assert_eq!(
+            (sub.op, sub.span.clone(), sub.op_span.clone()),
+            (BinOp::Sub, 25..37, 29..30)
+        );
+        let Expr::Binary(mul) = &*sub.rhs else {
+            panic!("`*` binds tighter than `-`");
+        };
+        assert_eq!((mul.op, mul.span.clone()), (BinOp::Mul, 31..37));
+        assert!(matches!(&*mul.lhs, Expr::Neg(neg) if neg.span == (31..33)));
+    }
+
+    #[test]
+    fn trailing_commas_need_an_argument() {
+        assert!(parse(r#"fn main() { print!("a",); }"#).is_ok());
+        assert!(parse(r#"fn main() { print!(,); }"#).is_err());
+        assert!(parse(r#"fn main() { print!("a",,); }"#).is_err());
+    }
+
+    #[test]
+    fn attributes_take_optional_arguments() {
+        let program = parse("#[test] #[allow(a, b)] fn main() {}").unwrap();
+        let attrs = &program.functions[0].attrs;
+        let names: Vec<_> = attrs.iter().map(|attr| attr.name.name.as_str()).collect();
+        assert_eq!(names, ["test", "allow"]);
+        assert!(attrs[0].args.is_empty());
+        let args: Vec<_> = attrs[1].args.iter().map(|arg| arg.name.as_str()).collect();
+        assert_eq!(args, ["a", "b"]);
+        assert_eq!(attrs[1].span, 8..22);
+    }
+
+    #[test]
+    fn doc_comments_document_the_item_after_them() {
+        let program = parse("// skipped\n/// Runs.\n///\n///x\nfn main() { // skipped\n}").unwrap();
+        let docs = &program.functions[0].docs;
+        let texts: Vec<_> = docs.iter().map(|doc| doc.text.as_str()).collect();
+        assert_eq!(texts, ["Runs.", "", "x"]);
+        assert_eq!(docs[0].span, 15..20);
+        assert_eq!(docs[2].span, 28..29);
+    }
 }
@@ -0,0 +1,147 @@
+//! Helpers for testing programs and the diagnostics they raise in a line or two, the way the
+//! compiler's own tests do, for code embedding sculpt.
+//!
+//! ```ignore
+//! sculpt::assert_program_output!(r#"fn main() { print!("hi"); }"#, "hi");
+//! sculpt::assert_exit_status!("fn main() { exit!(3); }", 3);
+//! sculpt::assert_diagnostic!("fn main() { log!(); }", DiagnosticCode::UnknownMacro, 12..16);
+//! ```
+
+use std::ops::Range;
+use std::path::Path;
+
+use crate::codes::DiagnosticCode;
+use crate::report::report_error;
+use crate::run::Error;
+use crate::session::Session;
+
+/// What a program that ran to completion, or to an `exit!`, wrote and exited with.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Output {
+    pub stdout: String,
+    pub stderr: String,
+    pub status: i32,
+}
+
+/// Runs `source_code` with an empty stdin, returning what it wrote and the status it exited with,
+/// or the error that stopped it compiling or running.
+#[cfg(feature = "llvm")]
+pub fn run(source_code: &str) -> Result<Output, Error<'_>> {
+    let mut stdout = Vec::new();
+    let mut stderr = Vec::new();
+    let status = match Session::builder().build().run_with_streams(
+        source_code,
+        std::io::empty(),
+        &mut stdout,
+        &mut stderr,
+    ) {
+        Ok(()) => 0,
+        Err(Error::Exited(status)) => status,
+        Err(error) => return Err(error),
+    };
+    Ok(Output {
+        stdout: String::from_utf8_lossy(&stdout).into_owned(),
+        stderr: String::from_utf8_lossy(&stderr).into_owned(),
+        status,
+    })
+}
+
+/// Checks `source_code` without running it, returning the first error it raises.
+pub fn check(source_code: &str) -> Result<(), Error<'_>> {
+    Session::builder().build().check(source_code)
+}
+
+/// `error`, raised in `source_code`, rendered without colors as if read from `file.sculpt`, with
+/// the whitespace ending each line trimmed.
+pub fn render(source_code: &str, error: Error) -> String {
+    let mut rendered = Vec::new();
+    report_error(
+        Path::new("file.sculpt"),
+        source_code,
+        error,
+        false,
+        &mut rendered,
+    );
+    String::from_utf8_lossy(&rendered)
+        .lines()
+        .map(str::trim_end)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// The code `error` is reported with, and the source it points at.
+pub fn diagnostic(error: &Error) -> (DiagnosticCode, Range<usize>) {
+    (error.code(), error.span())
+}
+
+/// Asserts that the program `src` runs to completion, writing `expected` to stdout.
+#[cfg(feature = "llvm")]
+#[macro_export]
+macro_rules! assert_program_output {
+    ($src:expr, $expected:expr $(,)?) => {{
+        let src: &str = $src;
+        match $crate::testing::run(src) {
+            Ok(output) => assert_eq!(output.stdout, $expected),
+            Err(error) => panic!(
+                "the program failed:\n{}",
+                $crate::testing::render(src, error)
+            ),
+        }
+    }};
+}
+
+/// Asserts that the program `src` runs, exiting with `status`: 0 unless it calls `exit!`.
+#[cfg(feature = "llvm")]
+#[macro_export]
+macro_rules! assert_exit_status {
+    ($src:expr, $status:expr $(,)?) => {{
+        let src: &str = $src;
+        match $crate::testing::run(src) {
+            Ok(output) => assert_eq!(output.status, $status),
+            Err(error) => panic!(
+                "the program failed:\n{}",
+                $crate::testing::render(src, error)
+            ),
+        }
+    }};
+}
+
+/// Asserts that checking the program `src` raises an error reported with `code`, a
+/// [`DiagnosticCode`], at `span`, a range of offsets into `src`.
+#[macro_export]
+macro_rules! assert_diagnostic {
+    ($src:expr, $code:expr, $span:expr $(,)?) => {{
+        let src: &str = $src;
+        match $crate::testing::check(src) {
+            Ok(()) => panic!("the program checked without errors"),
+            Err(error) => assert_eq!($crate::testing::diagnostic(&error), ($code, $span)),
+        }
+    }};
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::codes::DiagnosticCode;
+
+    #[test]
+    #[cfg(feature = "llvm")]
+    fn programs_are_asserted_on_by_output_and_status() {
+        crate::assert_program_output!(r#"fn main() { println!("{}", 1 + 2); }"#, "3\n");
+        crate::assert_exit_status!(r#"fn main() { print!("a"); exit!(3); }"#, 3);
+        crate::assert_exit_status!("fn main() {}", 0);
+    }
+
+    #[test]
+    fn diagnostics_are_asserted_on_by_code_and_span() {
+        crate::assert_diagnostic!(
+            "fn main() { log!(); }",
+            DiagnosticCode::UnknownMacro,
+            12..16
+        );
+        crate::assert_diagnostic!(
+            "fn main() { 1 }",
+            DiagnosticCode::MismatchedReturnType,
+            12..13
+        );
+    }
+}
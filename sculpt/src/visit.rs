@@ -0,0 +1,728 @@
+//! Traversal of the syntax tree. Passes override the methods for the nodes they care about and
+//! call the matching `walk_*`/`fold_*` function to keep descending into children.
+
+use crate::syntax::{
+    Attr, Binary, Block, Borrow, Bound, Call, Closure, ClosureParam, Deref, Expr, Field, For,
+    Function, IfLet, Impl, IntLit, Let, Macro, MacroRule, MacroRules, Match, MatchArm, MethodCall,
+    Name, Neg, Param, Pattern, Program, RangeExpr, Slice, Static, Stmt, StrLit, Try,
+    TupleStructPattern, WhileLet,
+};
+
+/// Read-only traversal. Every method defaults to visiting the node's children.
+pub trait Visit<'ast> {
+    fn visit_program(&mut self, program: &'ast Program) {
+        walk_program(self, program)
+    }
+
+    fn visit_function(&mut self, function: &'ast Function) {
+        walk_function(self, function)
+    }
+
+    fn visit_macro_rules(&mut self, m: &'ast MacroRules) {
+        walk_macro_rules(self, m)
+    }
+
+    fn visit_macro_rule(&mut self, rule: &'ast MacroRule) {
+        walk_macro_rule(self, rule)
+    }
+
+    fn visit_stmt(&mut self, stmt: &'ast Stmt) {
+        walk_stmt(self, stmt)
+    }
+
+    fn visit_macro(&mut self, m: &'ast Macro) {
+        walk_macro(self, m)
+    }
+
+    fn visit_expr(&mut self, expr: &'ast Expr) {
+        walk_expr(self, expr)
+    }
+
+    fn visit_match_arm(&mut self, arm: &'ast MatchArm) {
+        walk_match_arm(self, arm)
+    }
+
+    fn visit_if_let(&mut self, i: &'ast IfLet) {
+        walk_if_let(self, i)
+    }
+
+    fn visit_block(&mut self, block: &'ast Block) {
+        walk_block(self, block)
+    }
+
+    fn visit_pattern(&mut self, pattern: &'ast Pattern) {
+        walk_pattern(self, pattern)
+    }
+
+    fn visit_name(&mut self, _name: &'ast Name) {}
+
+    fn visit_str_lit(&mut self, _lit: &'ast StrLit) {}
+
+    fn visit_int_lit(&mut self, _lit: &'ast IntLit) {}
+}
+
+pub fn walk_program<'ast, V: Visit<'ast> + ?Sized>(visitor: &mut V, program: &'ast Program) {
+    for function in &program.functions {
+        visitor.visit_function(function);
+    }
+    for s in &program.structs {
+        visitor.visit_name(&s.name);
+    }
+    for i in &program.impls {
+        if let Some(trait_name) = &i.trait_name {
+            visitor.visit_name(trait_name);
+        }
+        visitor.visit_name(&i.ty);
+        for function in &i.functions {
+            visitor.visit_function(function);
+        }
+    }
+    for t in &program.traits {
+        visitor.visit_name(&t.name);
+        for method in &t.methods {
+            visitor.visit_name(&method.name);
+            for param in &method.params {
+                visitor.visit_name(&param.name);
+            }
+        }
+    }
+    for m in &program.macros {
+        visitor.visit_macro_rules(m);
+    }
+    for s in &program.statics {
+        visitor.visit_name(&s.name);
+        visitor.visit_expr(&s.value);
+    }
+}
+
+pub fn walk_function<'ast, V: Visit<'ast> + ?Sized>(visitor: &mut V, function: &'ast Function) {
+    for attr in &function.attrs {
+        visitor.visit_name(&attr.name);
+        for arg in &attr.args {
+            visitor.visit_name(arg);
+        }
+    }
+    visitor.visit_name(&function.name);
+    for generic in &function.generics {
+        visitor.visit_name(&generic.name);
+        for bound in &generic.bounds {
+            if let Bound::Trait(name) = bound {
+                visitor.visit_name(name);
+            }
+        }
+    }
+    for param in &function.params {
+        visitor.visit_name(&param.name);
+    }
+    for statement in &function.statements {
+        visitor.visit_stmt(statement);
+    }
+    if let Some(tail) = &function.tail {
+        visitor.visit_expr(tail);
+    }
+}
+
+pub fn walk_macro_rules<'ast, V: Visit<'ast> + ?Sized>(visitor: &mut V, m: &'ast MacroRules) {
+    visitor.visit_name(&m.name);
+    for rule in &m.rules {
+        visitor.visit_macro_rule(rule);
+    }
+}
+
+pub fn walk_macro_rule<'ast, V: Visit<'ast> + ?Sized>(visitor: &mut V, rule: &'ast MacroRule) {
+    for param in &rule.params {
+        visitor.visit_name(param);
+    }
+    for statement in &rule.body {
+        visitor.visit_stmt(statement);
+    }
+}
+
+pub fn walk_stmt<'ast, V: Visit<'ast> + ?Sized>(visitor: &mut V, stmt: &'ast Stmt) {
+    match stmt {
+        Stmt::Macro(m) => visitor.visit_macro(m),
+        Stmt::For(f) => {
+            visitor.visit_pattern(&f.pattern);
+            visitor.visit_expr(&f.iter);
+            for statement in &f.body {
+                visitor.visit_stmt(statement);
+            }
+        }
+        Stmt::WhileLet(w) => {
+            visitor.visit_pattern(&w.pattern);
+            visitor.visit_expr(&w.value);
+            for statement in &w.body {
+                visitor.visit_stmt(statement);
+            }
+        }
+        Stmt::IfLet(i) => visitor.visit_if_let(i),
+        Stmt::Block(block) => visitor.visit_block(block),
+        Stmt::Let(l) => {
+            visitor.visit_pattern(&l.pattern);
+            visitor.visit_expr(&l.value);
+        }
+    }
+}
+
+pub fn walk_block<'ast, V: Visit<'ast> + ?Sized>(visitor: &mut V, block: &'ast Block) {
+    for statement in &block.statements {
+        visitor.visit_stmt(statement);
+    }
+    if let Some(tail) = &block.tail {
+        visitor.visit_expr(tail);
+    }
+}
+
+pub fn walk_macro<'ast, V: Visit<'ast> + ?Sized>(visitor: &mut V, m: &'ast Macro) {
+    visitor.visit_name(&m.name);
+    for arg in &m.args {
+        visitor.visit_expr(arg);
+    }
+}
+
+pub fn walk_expr<'ast, V: Visit<'ast> + ?Sized>(visitor: &mut V, expr: &'ast Expr) {
+    match expr {
+        Expr::Str(lit) => visitor.visit_str_lit(lit),
+        Expr::Int(lit) => visitor.visit_int_lit(lit),
+        Expr::Name(name) => visitor.visit_name(name),
+        Expr::Neg(neg) => visitor.visit_expr(&neg.expr),
+        Expr::Binary(binary) => {
+            visitor.visit_expr(&binary.lhs);
+            visitor.visit_expr(&binary.rhs);
+        }
+        Expr::Macro(m) => visitor.visit_macro(m),
+        Expr::MetaVar(name) => visitor.visit_name(name),
+        Expr::Call(call) => {
+            if let Some(ty) = &call.ty {
+                visitor.visit_name(ty);
+            }
+            visitor.visit_name(&call.name);
+            for arg in &call.args {
+                visitor.visit_expr(arg);
+            }
+        }
+        Expr::MethodCall(call) => {
+            visitor.visit_expr(&call.receiver);
+            visitor.visit_name(&call.name);
+            for arg in &call.args {
+                visitor.visit_expr(arg);
+            }
+        }
+        Expr::Match(m) => {
+            visitor.visit_expr(&m.scrutinee);
+            for arm in &m.arms {
+                visitor.visit_match_arm(arm);
+            }
+        }
+        Expr::Try(t) => visitor.visit_expr(&t.expr),
+        Expr::Field(field) => {
+            visitor.visit_expr(&field.expr);
+            visitor.visit_int_lit(&field.index);
+        }
+        Expr::Closure(closure) => {
+            for param in &closure.params {
+                visitor.visit_name(&param.name);
+            }
+            visitor.visit_expr(&closure.body);
+        }
+        Expr::Borrow(borrow) => visitor.visit_expr(&borrow.expr),
+        Expr::Deref(deref) => visitor.visit_expr(&deref.expr),
+        Expr::Slice(slice) => {
+            visitor.visit_expr(&slice.expr);
+            for bound in slice.start.iter().chain(&slice.end) {
+                visitor.visit_expr(bound);
+            }
+        }
+        Expr::Range(range) => {
+            visitor.visit_expr(&range.start);
+            visitor.visit_expr(&range.end);
+        }
+        Expr::Block(block) => visitor.visit_block(block),
+        Expr::IfLet(i) => visitor.visit_if_let(i),
+    }
+}
+
+pub fn walk_match_arm<'ast, V: Visit<'ast> + ?Sized>(visitor: &mut V, arm: &'ast MatchArm) {
+    visitor.visit_pattern(&arm.pattern);
+    visitor.visit_expr(&arm.body);
+}
+
+pub fn walk_if_let<'ast, V: Visit<'ast> + ?Sized>(visitor: &mut V, i: &'ast IfLet) {
+    visitor.visit_pattern(&i.pattern);
+    visitor.visit_expr(&i.value);
+    visitor.visit_block(&i.then);
+    if let Some(otherwise) = &i.otherwise {
+        visitor.visit_block(otherwise);
+    }
+}
+
+pub fn walk_pattern<'ast, V: Visit<'ast> + ?Sized>(visitor: &mut V, pattern: &'ast Pattern) {
+    match pattern {
+        Pattern::Wildcard(_) => {}
+        Pattern::Name(name) => visitor.visit_name(name),
+        Pattern::Str(lit) => visitor.visit_str_lit(lit),
+        Pattern::TupleStruct(pattern) => {
+            visitor.visit_name(&pattern.name);
+            for field in &pattern.fields {
+                visitor.visit_pattern(field);
+            }
+        }
+    }
+}
+
+/// Owning traversal that rebuilds the tree. Every method defaults to folding the node's children
+/// and reassembling it unchanged.
+pub trait Fold {
+    fn fold_program(&mut self, program: Program) -> Program {
+        fold_program(self, program)
+    }
+
+    fn fold_function(&mut self, function: Function) -> Function {
+        fold_function(self, function)
+    }
+
+    fn fold_macro_rules(&mut self, m: MacroRules) -> MacroRules {
+        fold_macro_rules(self, m)
+    }
+
+    fn fold_macro_rule(&mut self, rule: MacroRule) -> MacroRule {
+        fold_macro_rule(self, rule)
+    }
+
+    fn fold_stmt(&mut self, stmt: Stmt) -> Stmt {
+        fold_stmt(self, stmt)
+    }
+
+    fn fold_macro(&mut self, m: Macro) -> Macro {
+        fold_macro(self, m)
+    }
+
+    fn fold_expr(&mut self, expr: Expr) -> Expr {
+        fold_expr(self, expr)
+    }
+
+    fn fold_match_arm(&mut self, arm: MatchArm) -> MatchArm {
+        fold_match_arm(self, arm)
+    }
+
+    fn fold_if_let(&mut self, i: IfLet) -> IfLet {
+        fold_if_let(self, i)
+    }
+
+    fn fold_block(&mut self, block: Block) -> Block {
+        fold_block(self, block)
+    }
+
+    fn fold_pattern(&mut self, pattern: Pattern) -> Pattern {
+        fold_pattern(self, pattern)
+    }
+
+    fn fold_name(&mut self, name: Name) -> Name {
+        name
+    }
+
+    fn fold_str_lit(&mut self, lit: StrLit) -> StrLit {
+        lit
+    }
+
+    fn fold_int_lit(&mut self, lit: IntLit) -> IntLit {
+        lit
+    }
+}
+
+pub fn fold_program<F: Fold + ?Sized>(folder: &mut F, program: Program) -> Program {
+    Program {
+        id: program.id,
+        attrs: program.attrs,
+        functions: program
+            .functions
+            .into_iter()
+            .map(|function| folder.fold_function(function))
+            .collect(),
+        structs: program.structs,
+        impls: program
+            .impls
+            .into_iter()
+            .map(|i| Impl {
+                functions: i
+                    .functions
+                    .into_iter()
+                    .map(|function| folder.fold_function(function))
+                    .collect(),
+                ..i
+            })
+            .collect(),
+        traits: program.traits,
+        macros: program
+            .macros
+            .into_iter()
+            .map(|m| folder.fold_macro_rules(m))
+            .collect(),
+        statics: program
+            .statics
+            .into_iter()
+            .map(|s| Static {
+                name: folder.fold_name(s.name),
+                value: folder.fold_expr(s.value),
+                ..s
+            })
+            .collect(),
+    }
+}
+
+pub fn fold_function<F: Fold + ?Sized>(folder: &mut F, function: Function) -> Function {
+    Function {
+        id: function.id,
+        span: function.span,
+        docs: function.docs,
+        attrs: function
+            .attrs
+            .into_iter()
+            .map(|attr| Attr {
+                id: attr.id,
+                span: attr.span,
+                name: folder.fold_name(attr.name),
+                args: attr
+                    .args
+                    .into_iter()
+                    .map(|arg| folder.fold_name(arg))
+                    .collect(),
+            })
+            .collect(),
+        public: function.public,
+        name: folder.fold_name(function.name),
+        generics: function.generics,
+        params: function
+            .params
+            .into_iter()
+            .map(|param| Param {
+                name: folder.fold_name(param.name),
+                ..param
+            })
+            .collect(),
+        ret: function.ret,
+        statements: function
+            .statements
+            .into_iter()
+            .map(|statement| folder.fold_stmt(statement))
+            .collect(),
+        tail: function.tail.map(|tail| folder.fold_expr(tail)),
+    }
+}
+
+pub fn fold_macro_rules<F: Fold + ?Sized>(folder: &mut F, m: MacroRules) -> MacroRules {
+    MacroRules {
+        id: m.id,
+        span: m.span,
+        name: folder.fold_name(m.name),
+        rules: m
+            .rules
+            .into_iter()
+            .map(|rule| folder.fold_macro_rule(rule))
+            .collect(),
+    }
+}
+
+pub fn fold_macro_rule<F: Fold + ?Sized>(folder: &mut F, rule: MacroRule) -> MacroRule {
+    MacroRule {
+        id: rule.id,
+        span: rule.span,
+        params: rule
+            .params
+            .into_iter()
+            .map(|param| folder.fold_name(param))
+            .collect(),
+        body: rule
+            .body
+            .into_iter()
+            .map(|statement| folder.fold_stmt(statement))
+            .collect(),
+    }
+}
+
+pub fn fold_stmt<F: Fold + ?Sized>(folder: &mut F, stmt: Stmt) -> Stmt {
+    match stmt {
+        Stmt::Macro(m) => Stmt::Macro(folder.fold_macro(m)),
+        Stmt::For(f) => Stmt::For(For {
+            id: f.id,
+            span: f.span,
+            pattern: folder.fold_pattern(f.pattern),
+            iter: folder.fold_expr(f.iter),
+            body: f
+                .body
+                .into_iter()
+                .map(|statement| folder.fold_stmt(statement))
+                .collect(),
+        }),
+        Stmt::WhileLet(w) => Stmt::WhileLet(WhileLet {
+            id: w.id,
+            span: w.span,
+            pattern: folder.fold_pattern(w.pattern),
+            value: folder.fold_expr(w.value),
+            body: w
+                .body
+                .into_iter()
+                .map(|statement| folder.fold_stmt(statement))
+                .collect(),
+        }),
+        Stmt::IfLet(i) => Stmt::IfLet(folder.fold_if_let(i)),
+        Stmt::Block(block) => Stmt::Block(folder.fold_block(block)),
+        Stmt::Let(l) => Stmt::Let(Let {
+            id: l.id,
+            span: l.span,
+            pattern: folder.fold_pattern(l.pattern),
+            ty: l.ty,
+            value: folder.fold_expr(l.value),
+        }),
+    }
+}
+
+pub fn fold_block<F: Fold + ?Sized>(folder: &mut F, block: Block) -> Block {
+    Block {
+        id: block.id,
+        span: block.span,
+        statements: block
+            .statements
+            .into_iter()
+            .map(|statement| folder.fold_stmt(statement))
+            .collect(),
+        tail: block.tail.map(|tail| Box::new(folder.fold_expr(*tail))),
+    }
+}
+
+pub fn fold_macro<F: Fold + ?Sized>(folder: &mut F, m: Macro) -> Macro {
+    Macro {
+        id: m.id,
+        span: m.span,
+        name: folder.fold_name(m.name),
+        args: m
+            .args
+            .into_iter()
+            .map(|arg| folder.fold_expr(arg))
+            .collect(),
+    }
+}
+
+pub fn fold_expr<F: Fold + ?Sized>(folder: &mut F, expr: Expr) -> Expr {
+    match expr {
+        Expr::Str(lit) => Expr::Str(folder.fold_str_lit(lit)),
+        Expr::Int(lit) => Expr::Int(folder.fold_int_lit(lit)),
+        Expr::Name(name) => Expr::Name(folder.fold_name(name)),
+        Expr::Neg(neg) => Expr::Neg(Neg {
+            id: neg.id,
+            span: neg.span,
+            expr: Box::new(folder.fold_expr(*neg.expr)),
+        }),
+        Expr::Binary(binary) => Expr::Binary(Binary {
+            id: binary.id,
+            span: binary.span,
+            op: binary.op,
+            op_span: binary.op_span,
+            lhs: Box::new(folder.fold_expr(*binary.lhs)),
+            rhs: Box::new(folder.fold_expr(*binary.rhs)),
+        }),
+        Expr::Macro(m) => Expr::Macro(folder.fold_macro(m)),
+        Expr::MetaVar(name) => Expr::MetaVar(folder.fold_name(name)),
+        Expr::Call(call) => Expr::Call(Call {
+            id: call.id,
+            span: call.span,
+            ty: call.ty.map(|ty| folder.fold_name(ty)),
+            name: folder.fold_name(call.name),
+            generics: call.generics,
+            args: call
+                .args
+                .into_iter()
+                .map(|arg| folder.fold_expr(arg))
+                .collect(),
+        }),
+        Expr::MethodCall(call) => Expr::MethodCall(MethodCall {
+            id: call.id,
+            span: call.span,
+            receiver: Box::new(folder.fold_expr(*call.receiver)),
+            name: folder.fold_name(call.name),
+            args: call
+                .args
+                .into_iter()
+                .map(|arg| folder.fold_expr(arg))
+                .collect(),
+        }),
+        Expr::Match(m) => Expr::Match(Match {
+            id: m.id,
+            span: m.span,
+            scrutinee: Box::new(folder.fold_expr(*m.scrutinee)),
+            arms: m
+                .arms
+                .into_iter()
+                .map(|arm| folder.fold_match_arm(arm))
+                .collect(),
+        }),
+        Expr::Try(t) => Expr::Try(Try {
+            id: t.id,
+            span: t.span,
+            expr: Box::new(folder.fold_expr(*t.expr)),
+        }),
+        Expr::Field(field) => Expr::Field(Field {
+            id: field.id,
+            span: field.span,
+            expr: Box::new(folder.fold_expr(*field.expr)),
+            index: folder.fold_int_lit(field.index),
+        }),
+        Expr::Closure(closure) => Expr::Closure(Closure {
+            id: closure.id,
+            span: closure.span,
+            params: closure
+                .params
+                .into_iter()
+                .map(|param| ClosureParam {
+                    name: folder.fold_name(param.name),
+                    ..param
+                })
+                .collect(),
+            body: Box::new(folder.fold_expr(*closure.body)),
+        }),
+        Expr::Borrow(borrow) => Expr::Borrow(Borrow {
+            id: borrow.id,
+            span: borrow.span,
+            mutable: borrow.mutable,
+            expr: Box::new(folder.fold_expr(*borrow.expr)),
+        }),
+        Expr::Deref(deref) => Expr::Deref(Deref {
+            id: deref.id,
+            span: deref.span,
+            expr: Box::new(folder.fold_expr(*deref.expr)),
+        }),
+        Expr::Slice(slice) => Expr::Slice(Slice {
+            id: slice.id,
+            span: slice.span,
+            expr: Box::new(folder.fold_expr(*slice.expr)),
+            start: slice.start.map(|start| Box::new(folder.fold_expr(*start))),
+            end: slice.end.map(|end| Box::new(folder.fold_expr(*end))),
+        }),
+        Expr::Range(range) => Expr::Range(RangeExpr {
+            id: range.id,
+            span: range.span,
+            start: Box::new(folder.fold_expr(*range.start)),
+            end: Box::new(folder.fold_expr(*range.end)),
+        }),
+        Expr::Block(block) => Expr::Block(folder.fold_block(block)),
+        Expr::IfLet(i) => Expr::IfLet(Box::new(folder.fold_if_let(*i))),
+    }
+}
+
+pub fn fold_match_arm<F: Fold + ?Sized>(folder: &mut F, arm: MatchArm) -> MatchArm {
+    MatchArm {
+        id: arm.id,
+        span: arm.span,
+        pattern: folder.fold_pattern(arm.pattern),
+        body: folder.fold_expr(arm.body),
+    }
+}
+
+pub fn fold_if_let<F: Fold + ?Sized>(folder: &mut F, i: IfLet) -> IfLet {
+    IfLet {
+        id: i.id,
+        span: i.span,
+        pattern: folder.fold_pattern(i.pattern),
+        value: Box::new(folder.fold_expr(*i.value)),
+        then: folder.fold_block(i.then),
+        otherwise: i.otherwise.map(|otherwise| folder.fold_block(otherwise)),
+    }
+}
+
+pub fn fold_pattern<F: Fold + ?Sized>(folder: &mut F, pattern: Pattern) -> Pattern {
+    match pattern {
+        Pattern::Wildcard(span) => Pattern::Wildcard(span),
+        Pattern::Name(name) => Pattern::Name(folder.fold_name(name)),
+        Pattern::Str(lit) => Pattern::Str(folder.fold_str_lit(lit)),
+        Pattern::TupleStruct(pattern) => Pattern::TupleStruct(TupleStructPattern {
+            id: pattern.id,
+            span: pattern.span,
+            name: folder.fold_name(pattern.name),
+            fields: pattern
+                .fields
+                .into_iter()
+                .map(|field| folder.fold_pattern(field))
+                .collect(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::run::parse;
+
+    const SRC: &str = r#"
+        #[bench]
+        fn main() {
+            println!("{} {}", "a", "b");
+        }
+        fn other() { print!("c"); }
+    "#;
+
+    #[test]
+    fn visit_reaches_every_literal() {
+        struct Lits(Vec<&'static str>);
+        impl<'ast> Visit<'ast> for Lits {
+            fn visit_str_lit(&mut self, lit: &'ast StrLit) {
+                self.0.push(lit.val.as_str());
+            }
+        }
+
+        let program = parse(SRC).unwrap();
+        let mut lits = Lits(Vec::new());
+        lits.visit_program(&program);
+        assert_eq!(lits.0, ["{} {}", "a", "b", "c"]);
+    }
+
+    #[test]
+    fn visit_reaches_names_in_source_order() {
+        struct Names(Vec<String>);
+        impl<'ast> Visit<'ast> for Names {
+            fn visit_name(&mut self, name: &'ast Name) {
+                self.0.push(name.name.to_string());
+            }
+        }
+
+        let program = parse(SRC).unwrap();
+        let mut names = Names(Vec::new());
+        names.visit_program(&program);
+        assert_eq!(names.0, ["bench", "main", "println!", "other", "print!"]);
+    }
+
+    #[test]
+    fn default_fold_is_identity() {
+        struct Identity;
+        impl Fold for Identity {}
+
+        assert_eq!(
+            Identity.fold_program(parse(SRC).unwrap()),
+            parse(SRC).unwrap()
+        );
+    }
+
+    #[test]
+    fn fold_can_rewrite_nodes() {
+        struct Quiet;
+        impl Fold for Quiet {
+            fn fold_macro(&mut self, mut m: Macro) -> Macro {
+                m.args.clear();
+                fold_macro(self, m)
+            }
+        }
+
+        let program = Quiet.fold_program(parse(SRC).unwrap());
+        assert!(program
+            .functions
+            .iter()
+            .flat_map(|function| &function.statements)
+            .all(|statement| match statement {
+                Stmt::Macro(m) => m.args.is_empty(),
+                Stmt::For(_)
+                | Stmt::WhileLet(_)
+                | Stmt::IfLet(_)
+                | Stmt::Block(_)
+                | Stmt::Let(_) => false,
+            }));
+    }
+}
@@ -0,0 +1,19 @@
+//! Parses the snippets in `tests/grammar` and compares the trees they parse to against the `.ast`
+//! files beside them. Set `SCULPT_BLESS` to write the `.ast` files instead.
+
+use std::env;
+use std::path::Path;
+
+use sculpt::session::Session;
+
+#[test]
+fn grammar() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/grammar");
+    let bless = env::var_os("SCULPT_BLESS").is_some();
+    let mut output = Vec::new();
+    let passed = Session::builder()
+        .build()
+        .check_grammar(&dir, bless, &mut output)
+        .unwrap();
+    assert!(passed, "{}", String::from_utf8_lossy(&output));
+}
@@ -0,0 +1,34 @@
+//! Runs the programs in `tests/ui`, and checks those in `tests/ui/check` without running them,
+//! and compares their output to the `.expected` files beside them. Set `SCULPT_BLESS` to write
+//! the `.expected` files instead.
+
+#![cfg(feature = "llvm")]
+
+use std::env;
+use std::path::Path;
+
+use sculpt::session::Session;
+
+#[test]
+fn ui() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/ui");
+    let bless = env::var_os("SCULPT_BLESS").is_some();
+    let mut output = Vec::new();
+    let passed = Session::builder()
+        .build()
+        .test_ui(&dir, bless, &mut output)
+        .unwrap();
+    assert!(passed, "{}", String::from_utf8_lossy(&output));
+}
+
+#[test]
+fn check() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/ui/check");
+    let bless = env::var_os("SCULPT_BLESS").is_some();
+    let mut output = Vec::new();
+    let passed = Session::builder()
+        .build()
+        .test_check_ui(&dir, bless, &mut output)
+        .unwrap();
+    assert!(passed, "{}", String::from_utf8_lossy(&output));
+}